@@ -0,0 +1,125 @@
+use crate::index::SearchHit;
+use crate::storage::{SavedSearch, WebhookFormat};
+use crate::{AppState, storage};
+use std::time::Duration;
+
+// Saved searches with webhook notifications.
+//
+// Every SERMA_ALERTS_EVERY_SECS each saved search is run against the index, and torrents
+// that show up in its top results for the first time (newly indexed, or ranked higher
+// after a seeder update) are POSTed to the search's webhook. Matches are remembered per
+// search in storage, so each torrent is announced once. A new search's existing matches
+// are recorded silently on its first run.
+
+/// Results per saved search considered on each run.
+const TOP_HITS: usize = 50;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Discord rejects messages over 2000 characters.
+const CHAT_MESSAGE_MAX: usize = 1900;
+
+pub async fn run(state: AppState) {
+    if state.config.alerts_every_secs == 0 {
+        tracing::info!("alerts: disabled (SERMA_ALERTS_EVERY_SECS=0)");
+        return;
+    }
+    let client = match reqwest::Client::builder()
+        .user_agent(concat!("serma/", env!("CARGO_PKG_VERSION")))
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%err, "alerts: failed creating HTTP client; disabled");
+            return;
+        }
+    };
+
+    let mut tick = tokio::time::interval(Duration::from_secs(state.config.alerts_every_secs));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = state.shutdown.wait() => return,
+        }
+        let searches = match storage::list_saved_searches(&state.db) {
+            Ok(searches) => searches,
+            Err(err) => {
+                tracing::warn!(%err, "alerts: failed reading saved searches");
+                continue;
+            }
+        };
+        for search in searches {
+            if state.shutdown.is_triggered() {
+                return;
+            }
+            if let Err(err) = check(&state, &client, &search).await {
+                tracing::warn!(name = %search.name, err = %format!("{err:#}"), "alerts: check failed");
+            }
+        }
+    }
+}
+
+async fn check(state: &AppState, client: &reqwest::Client, search: &SavedSearch) -> anyhow::Result<()> {
+    let primed = storage::saved_search_primed(&state.db, &search.name)?;
+    let hits = state.index.search(&search.query, TOP_HITS, state.config.query_options())?;
+
+    let mut notified = 0usize;
+    for hit in hits {
+        let Some(hash) = hit.info_hash.as_deref() else {
+            continue;
+        };
+        if !primed {
+            storage::mark_saved_search_hit(&state.db, &search.name, hash)?;
+        } else if !storage::saved_search_hit_known(&state.db, &search.name, hash)? {
+            // Recorded only once delivered, so a failed webhook is retried next run.
+            notify(client, search, &hit).await?;
+            storage::mark_saved_search_hit(&state.db, &search.name, hash)?;
+            notified += 1;
+        }
+    }
+    if notified > 0 {
+        tracing::info!(name = %search.name, matches = notified, "alerts: notified");
+    }
+    Ok(())
+}
+
+async fn notify(client: &reqwest::Client, search: &SavedSearch, hit: &SearchHit) -> anyhow::Result<()> {
+    client
+        .post(&search.webhook_url)
+        .json(&payload(search, hit))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+fn payload(search: &SavedSearch, hit: &SearchHit) -> serde_json::Value {
+    let title = hit.title.as_deref().unwrap_or("(untitled)");
+    let hash = hit.info_hash.as_deref().unwrap_or_default();
+    let chat_message = || {
+        let mut message = format!(
+            "New match for \"{}\": {} ({} seeders)\n{}",
+            search.name,
+            title,
+            hit.seeders,
+            hit.magnet.as_deref().unwrap_or(hash)
+        );
+        if message.len() > CHAT_MESSAGE_MAX {
+            let mut end = CHAT_MESSAGE_MAX;
+            while !message.is_char_boundary(end) {
+                end -= 1;
+            }
+            message.truncate(end);
+        }
+        message
+    };
+    match search.webhook_format {
+        WebhookFormat::Discord => serde_json::json!({ "content": chat_message() }),
+        WebhookFormat::Slack => serde_json::json!({ "text": chat_message() }),
+        WebhookFormat::Json => serde_json::json!({
+            "search": search.name,
+            "query": search.query,
+            "torrent": hit,
+        }),
+    }
+}
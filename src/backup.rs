@@ -0,0 +1,548 @@
+use crate::kv::{Backend, Batch, Db};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Backups: one .tar.zst holding the whole database and the search index, written by
+// `POST /api/admin/backup` while the server runs.
+//
+// The database goes in as a dump of every tree (`kv/*.bin`), not as sled or SQLite files,
+// so it is consistent per tree and restores into either backend. The index goes in as a
+// hard-linked snapshot of its committed segments. `manifest.json` comes first and says which
+// serma and record layout wrote the archive; `summary.json` comes last with the entry
+// counts a restore checks.
+//
+// Restoring is two steps: `serma restore <file>` or `POST /api/admin/restore` checks the
+// archive and unpacks it into `data/restore/`, and the next start builds a database from
+// it and swaps it in, moving the old database and index to `data/pre-restore/`.
+
+/// Archive layout version; restore refuses archives newer than this.
+const FORMAT: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+const SUMMARY: &str = "summary.json";
+/// Bytes of dumped entries per `kv/` file.
+const KV_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+/// Largest tree name, key or value a backup holds.
+const MAX_FRAME_BYTES: usize = 64 * 1024 * 1024;
+/// Largest `kv/` file a restore reads: a full chunk plus the entry that overflowed it.
+const MAX_KV_FILE_BYTES: u64 = (KV_CHUNK_BYTES + 3 * (4 + MAX_FRAME_BYTES)) as u64;
+/// Largest `manifest.json` / `summary.json` a restore reads.
+const MAX_JSON_BYTES: u64 = 1024 * 1024;
+/// How far `next_id` may run ahead of the entry count. Ids are only taken for block
+/// rules and backups, so a real archive stays far below; restoring advances sled's ids
+/// one at a time.
+const MAX_ID_SLACK: u64 = 10_000_000;
+/// Entries written per batch when a restore rebuilds the database.
+const RESTORE_BATCH: usize = 10_000;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Set while a backup runs, so only one runs at a time.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub serma_version: String,
+    /// Unix seconds.
+    pub created_at: i64,
+    /// Backend the data came from; a restore writes into whichever one is configured.
+    pub storage: String,
+    /// `storage::RECORD_FORMAT` of the writer. Older record layouts are upgraded on read,
+    /// newer ones can't be read.
+    pub record_format: u32,
+}
+
+impl Manifest {
+    fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.format <= FORMAT,
+            "backup format {} is newer than this serma reads ({FORMAT}); it was written by serma {}",
+            self.format,
+            self.serma_version
+        );
+        anyhow::ensure!(
+            self.record_format <= crate::storage::RECORD_FORMAT,
+            "backup records are format {}, newer than this serma reads ({}); it was written by serma {}",
+            self.record_format,
+            crate::storage::RECORD_FORMAT,
+            self.serma_version
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Summary {
+    /// Entries per tree.
+    pub trees: BTreeMap<String, u64>,
+    pub entries: u64,
+    /// Next `generate_id` value, so restored block rules keep unique ids.
+    pub next_id: u64,
+    pub index_docs: u64,
+}
+
+impl Summary {
+    fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.next_id <= self.entries.saturating_add(MAX_ID_SLACK),
+            "backup summary is damaged: next id {} for {} entries",
+            self.next_id,
+            self.entries
+        );
+        Ok(())
+    }
+}
+
+/// What a finished backup holds.
+#[derive(Debug, Serialize)]
+pub struct Backup {
+    pub entries: u64,
+    pub index_docs: u64,
+}
+
+/// Claims the single backup slot; `None` while another backup runs.
+pub fn try_start() -> Option<RunningGuard> {
+    (!RUNNING.swap(true, Ordering::SeqCst)).then_some(RunningGuard)
+}
+
+pub struct RunningGuard;
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Default file name for a backup taken now.
+pub fn file_name() -> String {
+    format!("serma-backup-{}.tar.zst", now_unix_ms() / 1000)
+}
+
+/// Writes a backup into `dir` under `file_name()`; returns its path and size. The file
+/// only gets its name once complete.
+pub fn write_file(state: &crate::AppState, dir: &Path) -> anyhow::Result<(PathBuf, u64, Backup)> {
+    std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    let path = dir.join(file_name());
+    let mut partial = path.clone().into_os_string();
+    partial.push(".partial");
+    let file = std::fs::File::create(&partial).with_context(|| format!("create {}", path.display()))?;
+    let done = match write(state, std::io::BufWriter::new(file)) {
+        Ok(done) => done,
+        Err(err) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(err);
+        }
+    };
+    std::fs::rename(&partial, &path)?;
+    let bytes = std::fs::metadata(&path)?.len();
+    Ok((path, bytes, done))
+}
+
+/// Writes a backup of `state`'s database and index to `out` (blocking).
+pub fn write(state: &crate::AppState, out: impl Write) -> anyhow::Result<Backup> {
+    let snapshot = state.data_dir.join("backup.tmp");
+    let result = write_with_snapshot(state, &snapshot, out);
+    let _ = std::fs::remove_dir_all(&snapshot);
+    result
+}
+
+fn write_with_snapshot(state: &crate::AppState, snapshot: &Path, out: impl Write) -> anyhow::Result<Backup> {
+    // Index first: records written during the dump are then in the database but possibly
+    // not in the index, which `serma reindex` fixes, rather than the other way around.
+    let index_docs = state
+        .index
+        .snapshot_files(&state.data_dir.join("tantivy"), snapshot)
+        .context("snapshot search index")?;
+
+    let encoder = zstd::Encoder::new(out, ZSTD_LEVEL)?;
+    let mut tar = tar::Builder::new(encoder);
+    let manifest = Manifest {
+        format: FORMAT,
+        serma_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: now_unix_ms() / 1000,
+        storage: state.config.storage_backend.as_str().to_string(),
+        record_format: crate::storage::RECORD_FORMAT,
+    };
+    append(&mut tar, MANIFEST, &serde_json::to_vec_pretty(&manifest)?)?;
+
+    let mut summary = dump(&state.db, &mut tar)?;
+    summary.next_id = state.db.generate_id()?;
+    summary.index_docs = index_docs;
+    tar.append_dir_all("tantivy", snapshot).context("add search index")?;
+    append(&mut tar, SUMMARY, &serde_json::to_vec_pretty(&summary)?)?;
+    tar.into_inner()?.finish()?.flush()?;
+
+    Ok(Backup {
+        entries: summary.entries,
+        index_docs,
+    })
+}
+
+/// Writes every tree as `kv/NNNNNN.bin` files of frames (see `write_frame`).
+fn dump(db: &Db, tar: &mut tar::Builder<impl Write>) -> anyhow::Result<Summary> {
+    let mut summary = Summary::default();
+    let mut chunk = Vec::with_capacity(KV_CHUNK_BYTES);
+    let mut chunks = 0usize;
+    for name in db.tree_names()? {
+        let mut entries = 0u64;
+        for entry in db.open_tree(&name)?.iter() {
+            let (key, value) = entry?;
+            anyhow::ensure!(
+                key.len().max(value.len()) <= MAX_FRAME_BYTES,
+                "entry in tree {} is too large to back up ({} bytes)",
+                tree_label(&name),
+                key.len().max(value.len())
+            );
+            write_frame(&mut chunk, &name, &key, &value);
+            entries += 1;
+            if chunk.len() >= KV_CHUNK_BYTES {
+                append(tar, &format!("kv/{chunks:06}.bin"), &chunk)?;
+                chunks += 1;
+                chunk.clear();
+            }
+        }
+        if entries > 0 {
+            summary.trees.insert(tree_label(&name), entries);
+            summary.entries += entries;
+        }
+    }
+    if !chunk.is_empty() {
+        append(tar, &format!("kv/{chunks:06}.bin"), &chunk)?;
+    }
+    Ok(summary)
+}
+
+fn append(tar: &mut tar::Builder<impl Write>, path: &str, data: &[u8]) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime((now_unix_ms() / 1000).max(0) as u64);
+    header.set_cksum();
+    tar.append_data(&mut header, path, data)
+        .with_context(|| format!("add {path} to backup"))
+}
+
+/// One entry: tree, key and value, each as a little-endian u32 length and the bytes.
+fn write_frame(out: &mut Vec<u8>, tree: &[u8], key: &[u8], value: &[u8]) {
+    for part in [tree, key, value] {
+        out.extend_from_slice(&(part.len() as u32).to_le_bytes());
+        out.extend_from_slice(part);
+    }
+}
+
+/// Splits a `kv/` file back into (tree, key, value) entries.
+fn read_frames(mut data: &[u8]) -> anyhow::Result<Vec<[&[u8]; 3]>> {
+    let mut frames = Vec::new();
+    while !data.is_empty() {
+        let mut frame: [&[u8]; 3] = [&[]; 3];
+        for part in &mut frame {
+            anyhow::ensure!(data.len() >= 4, "truncated entry");
+            let len = u32::from_le_bytes(data[..4].try_into()?) as usize;
+            anyhow::ensure!(len <= MAX_FRAME_BYTES, "entry of {len} bytes is too large");
+            anyhow::ensure!(data.len() >= 4 + len, "truncated entry");
+            *part = &data[4..4 + len];
+            data = &data[4 + len..];
+        }
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+fn tree_label(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).into_owned()
+}
+
+/// Where a checked archive waits for the next start.
+fn staged_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("restore")
+}
+
+/// Checks the archive in `input` and unpacks it into `data/restore/`, replacing any restore
+/// already waiting there; the next start applies it. Refuses archives from a newer serma,
+/// and archives whose entry counts don't match their summary.
+pub fn stage_restore(data_dir: &Path, input: impl Read) -> anyhow::Result<(Manifest, Summary)> {
+    let partial = data_dir.join("restore.partial");
+    let _ = std::fs::remove_dir_all(&partial);
+    std::fs::create_dir_all(partial.join("kv")).context("create restore directory")?;
+
+    let result = unpack(&partial, input);
+    let (manifest, summary) = match result {
+        Ok(unpacked) => unpacked,
+        Err(err) => {
+            let _ = std::fs::remove_dir_all(&partial);
+            return Err(err);
+        }
+    };
+    let staged = staged_dir(data_dir);
+    if staged.exists() {
+        std::fs::remove_dir_all(&staged).context("remove previously staged restore")?;
+    }
+    std::fs::rename(&partial, &staged).context("stage restore")?;
+    Ok((manifest, summary))
+}
+
+fn unpack(dest: &Path, input: impl Read) -> anyhow::Result<(Manifest, Summary)> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(input).context("not a zstd file")?);
+    let mut manifest: Option<Manifest> = None;
+    let mut summary: Option<Summary> = None;
+    let mut counted: BTreeMap<String, u64> = BTreeMap::new();
+    for entry in archive.entries().context("read backup")? {
+        let mut entry = entry.context("read backup")?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        if manifest.is_none() {
+            // Checked before anything is unpacked.
+            anyhow::ensure!(path == MANIFEST, "not a serma backup (no {MANIFEST} at the start)");
+            let data = read_limited(&mut entry, MAX_JSON_BYTES, &path)?;
+            let parsed: Manifest = serde_json::from_slice(&data).context("parse backup manifest")?;
+            parsed.check()?;
+            std::fs::write(dest.join(MANIFEST), serde_json::to_vec_pretty(&parsed)?)?;
+            manifest = Some(parsed);
+            continue;
+        }
+        if path == SUMMARY {
+            let data = read_limited(&mut entry, MAX_JSON_BYTES, &path)?;
+            let parsed: Summary = serde_json::from_slice(&data).context("parse backup summary")?;
+            parsed.check()?;
+            std::fs::write(dest.join(SUMMARY), &data)?;
+            summary = Some(parsed);
+        } else if let Some(name) = path.strip_prefix("kv/") {
+            let valid = name.strip_suffix(".bin").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+            anyhow::ensure!(valid, "unexpected file in backup: {path}");
+            let data = read_limited(&mut entry, MAX_KV_FILE_BYTES, &path)?;
+            for [tree, _, _] in read_frames(&data).with_context(|| format!("read {path}"))? {
+                *counted.entry(tree_label(tree)).or_default() += 1;
+            }
+            std::fs::write(dest.join(&path), &data)?;
+        } else if path.trim_end_matches('/') == "tantivy" || path.starts_with("tantivy/") {
+            anyhow::ensure!(entry.unpack_in(dest)?, "refusing to unpack {path} outside the restore directory");
+        } else {
+            anyhow::bail!("unexpected file in backup: {path}");
+        }
+    }
+    let manifest = manifest.context("empty backup")?;
+    let summary = summary.context("backup is incomplete (no summary at the end)")?;
+    anyhow::ensure!(
+        counted == summary.trees,
+        "backup entries don't match its summary; the file is damaged"
+    );
+    Ok((manifest, summary))
+}
+
+/// Reads a whole archive entry of at most `limit` bytes; a larger one is refused.
+fn read_limited(entry: &mut impl Read, limit: u64, path: &str) -> anyhow::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    entry.take(limit + 1).read_to_end(&mut data)?;
+    anyhow::ensure!(data.len() as u64 <= limit, "{path} in backup is larger than {limit} bytes");
+    Ok(data)
+}
+
+/// What `apply_staged` restored.
+pub struct Restored {
+    pub manifest: Manifest,
+    pub entries: u64,
+}
+
+/// Applies a restore staged by `stage_restore`, if any: builds a `backend` database from it
+/// next to the live one, checks the entry counts, then moves the live database and index
+/// to `data/pre-restore/` and the restored ones into place. Must run before the database
+/// is opened. An interrupted run leaves the staged restore in place and is redone by the
+/// next start.
+pub fn apply_staged(backend: Backend, data_dir: &Path) -> anyhow::Result<Option<Restored>> {
+    let staged = staged_dir(data_dir);
+    if !staged.join(SUMMARY).exists() {
+        return Ok(None);
+    }
+    let manifest: Manifest = serde_json::from_slice(&std::fs::read(staged.join(MANIFEST))?)
+        .context("parse staged backup manifest")?;
+    // Again, in case serma was downgraded since the restore was staged.
+    manifest.check()?;
+    let summary: Summary = serde_json::from_slice(&std::fs::read(staged.join(SUMMARY))?)
+        .context("parse staged backup summary")?;
+    summary.check()?;
+
+    let live = backend.path(data_dir);
+    let mut building = live.clone().into_os_string();
+    building.push(".restoring");
+    let building = PathBuf::from(building);
+    remove_db(backend, &building)?;
+    load(backend, &building, &staged, &summary)?;
+
+    // Keep the old data until the next restore, unless an interrupted run already moved it.
+    let previous = data_dir.join("pre-restore");
+    let index = data_dir.join("tantivy");
+    if live.exists() || index.exists() {
+        if previous.exists() {
+            std::fs::remove_dir_all(&previous).context("remove old pre-restore data")?;
+        }
+        std::fs::create_dir_all(&previous)?;
+        move_db(backend, &live, &previous.join(live.file_name().context("database path")?))?;
+        if index.exists() {
+            std::fs::rename(&index, previous.join("tantivy")).context("move old search index")?;
+        }
+    }
+    move_db(backend, &building, &live)?;
+    if staged.join("tantivy").exists() {
+        std::fs::rename(staged.join("tantivy"), &index).context("move restored search index in place")?;
+    }
+    std::fs::remove_dir_all(&staged).context("remove staged restore")?;
+    Ok(Some(Restored {
+        manifest,
+        entries: summary.entries,
+    }))
+}
+
+/// Builds a database at `path` from the staged `kv/` files.
+fn load(backend: Backend, path: &Path, staged: &Path, summary: &Summary) -> anyhow::Result<()> {
+    let db = match backend {
+        Backend::Sled => {
+            let sled = crate::kv::SledStore::open(path)?;
+            // sled ids can't be set, only advanced.
+            let db = Db::new(sled);
+            while db.generate_id()? + 1 < summary.next_id {}
+            db
+        }
+        Backend::Sqlite => {
+            let sqlite = crate::sqlite::SqliteStore::open(path)?;
+            sqlite.set_next_id(summary.next_id)?;
+            Db::new(sqlite)
+        }
+    };
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(staged.join("kv"))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    files.sort();
+    let mut batch = Batch::default();
+    let mut batch_tree: Vec<u8> = Vec::new();
+    let mut batched = 0usize;
+    for file in files {
+        let data = std::fs::read(&file)?;
+        for [tree, key, value] in read_frames(&data).with_context(|| format!("read {}", file.display()))? {
+            if tree != batch_tree.as_slice() || batched >= RESTORE_BATCH {
+                if batched > 0 {
+                    db.open_tree(&batch_tree)?.apply_batch(std::mem::take(&mut batch))?;
+                }
+                batch_tree = tree.to_vec();
+                batched = 0;
+            }
+            batch.insert(key, value);
+            batched += 1;
+        }
+    }
+    if batched > 0 {
+        db.open_tree(&batch_tree)?.apply_batch(batch)?;
+    }
+
+    for (name, expected) in &summary.trees {
+        let loaded = db.open_tree(name.as_bytes())?.len()? as u64;
+        anyhow::ensure!(
+            loaded == *expected,
+            "restored tree {name} has {loaded} entries, the backup {expected}"
+        );
+    }
+    db.flush()?;
+    Ok(())
+}
+
+/// Database paths, including SQLite's side files.
+fn db_files(backend: Backend, path: &Path) -> Vec<PathBuf> {
+    match backend {
+        Backend::Sled => vec![path.to_path_buf()],
+        Backend::Sqlite => ["", "-wal", "-shm"]
+            .iter()
+            .map(|suffix| {
+                let mut p = path.as_os_str().to_owned();
+                p.push(suffix);
+                PathBuf::from(p)
+            })
+            .collect(),
+    }
+}
+
+fn remove_db(backend: Backend, path: &Path) -> anyhow::Result<()> {
+    for p in db_files(backend, path) {
+        if p.is_dir() {
+            std::fs::remove_dir_all(&p)?;
+        } else if p.exists() {
+            std::fs::remove_file(&p)?;
+        }
+    }
+    Ok(())
+}
+
+fn move_db(backend: Backend, from: &Path, to: &Path) -> anyhow::Result<()> {
+    for (from, to) in db_files(backend, from).into_iter().zip(db_files(backend, to)) {
+        if from.exists() {
+            std::fs::rename(&from, &to).with_context(|| format!("move {} to {}", from.display(), to.display()))?;
+        }
+    }
+    Ok(())
+}
+
+fn now_unix_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive(summary: &Summary, kv: &[u8]) -> Vec<u8> {
+        let mut tar = tar::Builder::new(zstd::Encoder::new(Vec::new(), ZSTD_LEVEL).unwrap());
+        let manifest = Manifest {
+            format: FORMAT,
+            serma_version: "test".to_string(),
+            created_at: 0,
+            storage: "sled".to_string(),
+            record_format: crate::storage::RECORD_FORMAT,
+        };
+        append(&mut tar, MANIFEST, &serde_json::to_vec(&manifest).unwrap()).unwrap();
+        append(&mut tar, "kv/000000.bin", kv).unwrap();
+        append(&mut tar, SUMMARY, &serde_json::to_vec(summary).unwrap()).unwrap();
+        tar.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn restore_checks_sizes_and_ids() {
+        let mut kv = Vec::new();
+        write_frame(&mut kv, b"meta", b"key", b"value");
+        let summary = Summary {
+            trees: BTreeMap::from([("meta".to_string(), 1)]),
+            entries: 1,
+            next_id: 5,
+            index_docs: 0,
+        };
+
+        let dir = crate::test_dir("backup");
+        let (_, staged) = stage_restore(&dir, archive(&summary, &kv).as_slice()).unwrap();
+        assert_eq!(staged.entries, 1);
+        let restored = apply_staged(Backend::Sled, &dir).unwrap().unwrap();
+        assert_eq!(restored.entries, 1);
+
+        // An id far past anything the entries could have used would spin the restore.
+        let runaway = Summary {
+            trees: summary.trees.clone(),
+            next_id: u64::MAX,
+            ..summary
+        };
+        let err = stage_restore(&dir, archive(&runaway, &kv).as_slice()).unwrap_err();
+        assert!(err.to_string().contains("next id"), "{err:#}");
+
+        // A frame claiming more than an entry may hold is refused before it is read.
+        let mut huge = Vec::new();
+        huge.extend_from_slice(&((MAX_FRAME_BYTES + 1) as u32).to_le_bytes());
+        let err = stage_restore(&dir, archive(&summary, &huge).as_slice()).unwrap_err();
+        assert!(format!("{err:#}").contains("too large"), "{err:#}");
+
+        let mut entry = std::io::repeat(0).take(MAX_JSON_BYTES + 10);
+        let err = read_limited(&mut entry, MAX_JSON_BYTES, SUMMARY).unwrap_err();
+        assert!(err.to_string().contains("larger than"), "{err:#}");
+    }
+}
@@ -0,0 +1,156 @@
+use crate::storage::{self, BlockRuleKind, TorrentRecord};
+use regex::{Regex, RegexBuilder};
+use std::sync::{Arc, PoisonError, RwLock};
+
+// User-managed rules that keep unwanted torrents (spam, malware names) out of the index.
+//
+// Rules live in sled (`storage::list_block_rules`); this is the compiled form checked
+// wherever torrents come in: the spider and enrichment, and every ingest path (uploads,
+// hash lists, feeds, imports and sync) before a record is stored. The web API edits the
+// rules and calls `reload`.
+
+#[derive(Clone, Default)]
+pub struct Blocklist {
+    inner: Arc<RwLock<Compiled>>,
+}
+
+#[derive(Default)]
+struct Compiled {
+    /// Lowercased.
+    keywords: Vec<String>,
+    regexes: Vec<(Regex, String)>,
+    /// Lowercased, without the leading dot.
+    extensions: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn load(db: &crate::kv::Db) -> anyhow::Result<Self> {
+        let blocklist = Self::default();
+        blocklist.reload(db)?;
+        Ok(blocklist)
+    }
+
+    /// Recompiles the rules from storage. Rules that no longer compile are skipped.
+    pub fn reload(&self, db: &crate::kv::Db) -> anyhow::Result<()> {
+        let mut compiled = Compiled::default();
+        for rule in storage::list_block_rules(db)? {
+            match rule.kind {
+                BlockRuleKind::Keyword => compiled.keywords.push(rule.pattern.to_lowercase()),
+                BlockRuleKind::Regex => match compile_regex(&rule.pattern) {
+                    Ok(regex) => compiled.regexes.push((regex, rule.pattern)),
+                    Err(err) => tracing::warn!(id = rule.id, %err, "blocklist: skipping invalid regex"),
+                },
+                BlockRuleKind::Extension => compiled.extensions.push(normalize_extension(&rule.pattern)),
+            }
+        }
+        *self.inner.write().unwrap_or_else(PoisonError::into_inner) = compiled;
+        Ok(())
+    }
+
+    /// Returns a description of the first rule the record matches, if any. A record with
+    /// an info dict but no file list yet is checked against the dict's files.
+    pub fn matches(&self, record: &TorrentRecord) -> Option<String> {
+        if record.files.is_empty() && record.info_bencode_base64.is_some() {
+            let mut record = record.clone();
+            storage::fill_from_info(&mut record);
+            if !record.files.is_empty() {
+                return self.matches(&record);
+            }
+        }
+        let compiled = self.inner.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(title) = record.title.as_deref() {
+            let lower = title.to_lowercase();
+            if let Some(keyword) = compiled.keywords.iter().find(|k| lower.contains(k.as_str())) {
+                return Some(format!("keyword {keyword:?}"));
+            }
+            if let Some((_, pattern)) = compiled.regexes.iter().find(|(r, _)| r.is_match(title)) {
+                return Some(format!("regex {pattern:?}"));
+            }
+        }
+        if !compiled.extensions.is_empty() {
+            let paths = record.files.iter().map(|f| f.path.as_str()).chain(record.title.as_deref());
+            for path in paths {
+                if let Some((_, ext)) = path.rsplit_once('.') {
+                    let ext = ext.to_lowercase();
+                    if compiled.extensions.contains(&ext) {
+                        return Some(format!("extension .{ext}"));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Validates a rule before it is stored.
+pub fn validate(kind: BlockRuleKind, pattern: &str) -> anyhow::Result<()> {
+    match kind {
+        BlockRuleKind::Keyword if pattern.trim().is_empty() => anyhow::bail!("keyword must be non-empty"),
+        BlockRuleKind::Extension if normalize_extension(pattern).is_empty() => {
+            anyhow::bail!("extension must be non-empty")
+        }
+        BlockRuleKind::Regex => {
+            compile_regex(pattern)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn compile_regex(pattern: &str) -> anyhow::Result<Regex> {
+    Ok(RegexBuilder::new(pattern).case_insensitive(true).size_limit(1 << 20).build()?)
+}
+
+fn normalize_extension(pattern: &str) -> String {
+    pattern.trim().trim_start_matches('.').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocklist(kind: BlockRuleKind, pattern: &str) -> Blocklist {
+        let dir = crate::test_dir("blocklist");
+        let db = crate::kv::open(crate::kv::Backend::Sled, &dir).unwrap();
+        storage::add_block_rule(&db, kind, pattern).unwrap();
+        Blocklist::load(&db).unwrap()
+    }
+
+    fn titled(title: &str) -> TorrentRecord {
+        let mut record = TorrentRecord::new(&"a".repeat(40), 0);
+        record.title = Some(title.to_string());
+        record
+    }
+
+    #[test]
+    fn keyword_rules_match_titles_ignoring_case() {
+        let blocklist = blocklist(BlockRuleKind::Keyword, "Spam");
+        assert_eq!(blocklist.matches(&titled("Free SPAM pack")).as_deref(), Some(r#"keyword "spam""#));
+        assert!(blocklist.matches(&titled("Debian 12")).is_none());
+        // Nothing to match until the name is known.
+        assert!(blocklist.matches(&TorrentRecord::new(&"a".repeat(40), 0)).is_none());
+    }
+
+    #[test]
+    fn regex_rules_match_titles() {
+        let blocklist = blocklist(BlockRuleKind::Regex, r"^crack\d+$");
+        assert!(blocklist.matches(&titled("CRACK2024")).is_some());
+        assert!(blocklist.matches(&titled("no crack2024 here")).is_none());
+    }
+
+    #[test]
+    fn extension_rules_match_files_of_an_info_dict() {
+        use base64::Engine as _;
+
+        let blocklist = blocklist(BlockRuleKind::Extension, ".EXE");
+        let info = b"d5:filesld6:lengthi5e4:pathl9:setup.exeeed6:lengthi7e4:pathl10:readme.txteee\
+4:name5:tools12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        // An uploaded .torrent or synced record arrives with its info dict, before the file
+        // list is stored.
+        let mut record = titled("tools");
+        record.info_bencode_base64 = Some(base64::engine::general_purpose::STANDARD.encode(info));
+        assert_eq!(blocklist.matches(&record).as_deref(), Some("extension .exe"));
+        assert!(blocklist.matches(&titled("tools")).is_none());
+        assert!(blocklist.matches(&titled("installer.exe")).is_some());
+    }
+}
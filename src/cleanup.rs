@@ -1,9 +1,13 @@
-use crate::{AppState, storage};
+use crate::stats::{CleanupReasons, CleanupSweep};
+use crate::{AppState, control::Subsystem, storage};
+use std::collections::HashSet;
 use std::ops::Bound;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::time::interval;
 
 const TORRENT_PREFIX: &[u8] = b"torrent:";
+/// Sweep reports kept for `/api/admin/cleanup/history`.
+pub const HISTORY_KEEP: usize = 1000;
 
 fn now_unix_ms() -> i64 {
     SystemTime::now()
@@ -27,9 +31,6 @@ pub async fn run(state: AppState) {
     // Wall-clock budget per tick.
     let max_ms = state.config.cleanup_max_ms;
 
-    // Records not seen for this long are considered inactive.
-    let ttl_secs = state.config.torrent_ttl_secs;
-
     // Give newly discovered hashes time to be enriched before pruning low-seed entries.
     let low_seed_grace_secs = state.config.low_seed_grace_secs;
 
@@ -37,10 +38,29 @@ pub async fn run(state: AppState) {
     // If set (> 0), we evict oldest-by-last_seen until we're under the limit.
     let max_records = state.config.max_torrents;
 
+    // Torrents found these ways are kept like pinned ones.
+    let keep_sources = state.config.cleanup_keep_sources.clone();
+    let kept = |record: &storage::TorrentRecord| {
+        record.pinned || record.discovered_via.is_some_and(|via| keep_sources.contains(&via))
+    };
+
+    // Count what would go without removing anything, to tune the values above.
+    let dry_run = state.config.cleanup_dry_run;
+    if dry_run {
+        tracing::info!("cleanup: dry run, nothing will be removed (SERMA_CLEANUP_DRY_RUN)");
+    }
+
     let mut tick = interval(Duration::from_secs(every_secs.max(1)));
 
     loop {
-        tick.tick().await;
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = state.shutdown.wait() => return,
+        }
+
+        if state.control.is_paused(Subsystem::Cleanup) {
+            continue;
+        }
 
         let last_seen = match storage::cleanup_last_seen_tree(&state.db) {
             Ok(t) => t,
@@ -58,8 +78,13 @@ pub async fn run(state: AppState) {
             }
         };
 
+        // Records not seen for `torrent_ttl_secs` are inactive; both values may be changed
+        // at runtime (see settings.rs) and apply from the next sweep.
+        let settings = state.settings.get();
+        let min_seeders = settings.cleanup_min_seeders as i64;
+
         let now = now_unix_ms();
-        let ttl_ms = (ttl_secs as i64) * 1000;
+        let ttl_ms = (settings.torrent_ttl_secs as i64).saturating_mul(1000);
         let grace_ms = (low_seed_grace_secs as i64) * 1000;
 
         let cutoff_last_seen = now.saturating_sub(ttl_ms);
@@ -68,6 +93,10 @@ pub async fn run(state: AppState) {
         let mut scanned: usize = 0;
         let mut deleted: usize = 0;
         let mut stale_fixed: usize = 0;
+        let mut spared_by_trend: usize = 0;
+        let mut candidates = CleanupReasons::default();
+        // Dry run only: TTL candidates, so phase 2 doesn't count them again.
+        let mut expired = HashSet::new();
 
         let start = Instant::now();
 
@@ -103,10 +132,17 @@ pub async fn run(state: AppState) {
                 Err(_) => continue,
             };
 
-            if record.last_seen_unix_ms <= cutoff_last_seen {
-                let _ = storage::delete(&state.db, &record.info_hash_hex);
-                let _ = state.index.delete(&record.info_hash_hex);
-                deleted += 1;
+            if record.last_seen_unix_ms <= cutoff_last_seen && kept(&record) {
+                // Kept: drop the entry so the sweep doesn't stall on it (unpinning restores it).
+                let _ = last_seen.remove(idx_key);
+            } else if record.last_seen_unix_ms <= cutoff_last_seen {
+                candidates.ttl += 1;
+                if dry_run {
+                    expired.insert(record.info_hash_hex);
+                } else {
+                    remove(&state, &record);
+                    deleted += 1;
+                }
             } else {
                 // Index entry is stale; fix it so we don't keep revisiting.
                 if storage::fix_last_seen_index_entry(&state.db, indexed_last_seen, &record).is_ok() {
@@ -114,7 +150,7 @@ pub async fn run(state: AppState) {
                 }
             }
 
-            if scanned % 250 == 0 {
+            if scanned.is_multiple_of(250) {
                 tokio::task::yield_now().await;
             }
 
@@ -125,7 +161,7 @@ pub async fn run(state: AppState) {
 
         // Phase 2: low-seed cleanup driven by first_seen index.
         // We scan low-seed candidates older than grace.
-        if start.elapsed() < Duration::from_millis(max_ms) {
+        if min_seeders > 0 && start.elapsed() < Duration::from_millis(max_ms) {
             let remaining = Duration::from_millis(max_ms).saturating_sub(start.elapsed());
             let end_key = storage::end_key_for_ts(cutoff_first_seen);
             for item in low_seed
@@ -161,12 +197,38 @@ pub async fn run(state: AppState) {
                     if storage::fix_low_seed_index_entry(&state.db, indexed_first_seen, &record).is_ok() {
                         stale_fixed += 1;
                     }
+                } else if record.seeders >= min_seeders {
+                    // Enough with a lowered `cleanup_min_seeders`. Like a spared entry, it
+                    // comes back with the next history sample.
+                    if !dry_run {
+                        let _ = low_seed.remove(idx_key);
+                    }
                 } else {
                     let old_enough = now.saturating_sub(record.first_seen_unix_ms) > grace_ms;
-                    if old_enough {
-                        let _ = storage::delete(&state.db, &record.info_hash_hex);
-                        let _ = state.index.delete(&record.info_hash_hex);
-                        deleted += 1;
+                    if old_enough && kept(&record) {
+                        let _ = low_seed.remove(idx_key);
+                    } else if old_enough
+                        && holding_up(
+                            &storage::seeder_history(&state.db, &record.info_hash_hex).unwrap_or_default(),
+                            min_seeders,
+                        )
+                    {
+                        // One low reading in a swarm that has been steady. The entry comes
+                        // back with the next history sample, which decides again.
+                        spared_by_trend += 1;
+                        if !dry_run {
+                            let _ = low_seed.remove(idx_key);
+                        }
+                    } else if old_enough {
+                        if dry_run {
+                            if !expired.contains(&record.info_hash_hex) {
+                                candidates.low_seed += 1;
+                            }
+                        } else {
+                            remove(&state, &record);
+                            candidates.low_seed += 1;
+                            deleted += 1;
+                        }
                     } else {
                         // Still in grace; ensure key is consistent.
                         if storage::fix_low_seed_index_entry(&state.db, indexed_first_seen, &record).is_ok() {
@@ -175,7 +237,7 @@ pub async fn run(state: AppState) {
                     }
                 }
 
-                if scanned % 250 == 0 {
+                if scanned.is_multiple_of(250) {
                     tokio::task::yield_now().await;
                 }
 
@@ -190,10 +252,17 @@ pub async fn run(state: AppState) {
 
         // Phase 3 (optional): enforce max-record cap by evicting oldest by last_seen.
         // This prevents unbounded growth even if TTL is long and ingestion is massive.
-        if max_records > 0 {
+        if max_records > 0 && dry_run {
+            // Evicting walks the same oldest entries every time, so estimate from the count.
+            candidates.max_records = last_seen
+                .len()
+                .unwrap_or(0)
+                .saturating_sub(candidates.ttl + candidates.low_seed)
+                .saturating_sub(max_records);
+        } else if max_records > 0 {
             // Safety: we only do eviction if we still have budget.
             while start.elapsed() < Duration::from_millis(max_ms) {
-                let len = last_seen.len();
+                let len = last_seen.len().unwrap_or(0);
                 if len <= max_records {
                     break;
                 }
@@ -215,8 +284,13 @@ pub async fn run(state: AppState) {
                     db_key.extend_from_slice(hash_hex.as_bytes());
                     if let Some(bytes) = state.db.get(&db_key).ok().flatten() {
                         if let Ok(record) = storage::decode_torrent_record_maybe_migrate(&state.db, &db_key, &bytes) {
-                            let _ = storage::delete(&state.db, &record.info_hash_hex);
-                            let _ = state.index.delete(&record.info_hash_hex);
+                            if kept(&record) {
+                                let _ = last_seen.remove(idx_key);
+                                evicted_one = true;
+                                continue;
+                            }
+                            remove(&state, &record);
+                            candidates.max_records += 1;
                             deleted += 1;
                             evicted_one = true;
                         }
@@ -232,10 +306,53 @@ pub async fn run(state: AppState) {
             }
         }
 
+        // Phase 4: forget old tombstones (they only guard imports against resurrecting
+        // recently deleted hashes).
+        let tombstone_cutoff = now.saturating_sub((state.config.tombstone_ttl_secs as i64) * 1000);
+        let tombstones_pruned = if dry_run {
+            0
+        } else {
+            storage::prune_tombstones(&state.db, tombstone_cutoff, batch).unwrap_or(0)
+        };
+
         if deleted > 0 {
             let _ = state.index.maybe_commit();
         }
 
-        tracing::debug!(scanned, deleted, stale_fixed, budget_ms = max_ms, cutoff_last_seen, cutoff_first_seen, max_records, "cleanup: sweep");
+        let sweep = CleanupSweep {
+            at_unix_ms: now,
+            dry_run,
+            scanned,
+            candidates,
+            deleted,
+            tombstones_pruned,
+            spared_by_trend,
+        };
+        // Idle sweeps would push the useful ones out of the history.
+        if (scanned > 0 || tombstones_pruned > 0 || candidates.total() > 0)
+            && let Err(err) = storage::push_cleanup_sweep(&state.db, now, &sweep, HISTORY_KEEP)
+        {
+            tracing::warn!(%err, "cleanup: failed saving sweep report");
+        }
+        state.stats.record_cleanup(sweep);
+        tracing::debug!(scanned, candidates = candidates.total(), deleted, stale_fixed, tombstones_pruned, spared_by_trend, budget_ms = max_ms, cutoff_last_seen, cutoff_first_seen, max_records, min_seeders, "cleanup: sweep");
     }
 }
+
+/// Whether a low-seed torrent's history says its swarm is holding up: not declining, and
+/// averaging `min_seeders` or more over the newer half of the samples.
+fn holding_up(samples: &[storage::SeederSample], min_seeders: i64) -> bool {
+    let trend = storage::SeederTrend::of(samples);
+    matches!(trend, storage::SeederTrend::Stable | storage::SeederTrend::Rising)
+        && storage::mean_seeders(&samples[samples.len() / 2..]) >= min_seeders as f64
+}
+
+/// Deletes an expired record, or archives it with `SERMA_CLEANUP_ARCHIVE`.
+fn remove(state: &AppState, record: &storage::TorrentRecord) {
+    let _ = if state.config.cleanup_archive {
+        storage::archive(&state.db, record)
+    } else {
+        storage::delete(&state.db, &record.info_hash_hex)
+    };
+    let _ = state.index.delete(&record.info_hash_hex);
+}
@@ -0,0 +1,104 @@
+use serma::import::{FieldMap, ImportFormat};
+use serma::index::SearchSort;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+// Command line. Everything but `serve` works on the data dir directly: no web server,
+// spider or other background tasks, so they can't run next to a running server (the
+// data dir lock refuses that).
+
+#[derive(Parser)]
+#[command(name = "serma", version, about = "Self-hosted BitTorrent DHT search engine")]
+pub struct Cli {
+    /// TOML config file (default: ./serma.toml if it exists).
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web server, spider and background tasks (the default).
+    Serve,
+    /// Import info hashes, magnet links or .torrent files ("-" or nothing reads stdin).
+    Ingest {
+        #[arg(value_name = "PATH")]
+        paths: Vec<String>,
+    },
+    /// Import a magnetico / bitmagnet database, a CSV / NDJSON dump or a serma export.
+    Import {
+        /// magnetico, bitmagnet, csv, ndjson or serma.
+        #[arg(long, value_parser = parse_import_format)]
+        format: ImportFormat,
+        /// Column mapping for csv / ndjson, e.g. hash=infohash,title=name; fields left out
+        /// come from SERMA_IMPORT_MAP.
+        #[arg(long, value_name = "FIELD=COLUMN,...")]
+        map: Option<FieldMap>,
+        /// CSV delimiter: one character, or "tab".
+        #[arg(long, value_parser = parse_delimiter)]
+        delimiter: Option<u8>,
+        /// The CSV has no header row (map columns by number).
+        #[arg(long)]
+        no_header: bool,
+        /// Also import torrents that were deleted here recently.
+        #[arg(long)]
+        include_deleted: bool,
+        /// File, or connection string for bitmagnet.
+        source: String,
+    },
+    /// Search the index and print the results.
+    Search {
+        query: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// relevance, seeders, added or size.
+        #[arg(long, default_value = "relevance", value_parser = parse_sort)]
+        sort: SearchSort,
+        /// Print the results as JSON lines.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write every record as JSONL, for `serma import --format serma`.
+    Export {
+        #[arg(long)]
+        gzip: bool,
+        /// Output file ("-" or nothing for stdout).
+        path: Option<String>,
+    },
+    /// Delete the search index and rebuild it from the database.
+    Reindex,
+    /// Rewrite the database and merge the index segments to reclaim disk space.
+    Compact,
+    /// Check that every record reads, every index entry points at a live record and every
+    /// search hit has one.
+    Fsck {
+        /// Fix what the check finds.
+        #[arg(long)]
+        repair: bool,
+    },
+    /// Copy the sled database into a new SQLite database (for SERMA_STORAGE=sqlite).
+    MigrateSqlite,
+    /// Check a backup (.tar.zst from /api/admin/backup) and restore it on the next start.
+    Restore {
+        #[arg(value_name = "FILE")]
+        path: PathBuf,
+    },
+}
+
+fn parse_import_format(value: &str) -> Result<ImportFormat, String> {
+    ImportFormat::parse(value).ok_or_else(|| "expected magnetico, bitmagnet, csv, ndjson or serma".to_string())
+}
+
+fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value {
+        "\\t" | "tab" => Ok(b'\t'),
+        v if v.len() == 1 => Ok(v.as_bytes()[0]),
+        _ => Err("must be a single character".to_string()),
+    }
+}
+
+fn parse_sort(value: &str) -> Result<SearchSort, String> {
+    SearchSort::parse(value).ok_or_else(|| "expected relevance, seeders, added or size".to_string())
+}
@@ -0,0 +1,105 @@
+use crate::{AppState, storage};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Periodic check for reclaimable database space.
+//
+// Cleanup deletes records, but sled never shrinks its file while it is open and SQLite
+// keeps freed pages; only copying the live data into a fresh database or vacuuming
+// (`storage::compact`) gives the space back. Every SERMA_COMPACT_EVERY_SECS this task adds
+// up the live keys and values, in slices of SERMA_COMPACT_MAX_MS with equal pauses between
+// them, and compares that with the file size. When most of the file is garbage the database is marked for compaction, which
+// then runs on the next start (SERMA_COMPACT_ON_START) or with `serma compact`.
+
+/// Smaller databases are never marked.
+const MIN_DB_BYTES: u64 = 64 * 1024 * 1024;
+/// A freshly compacted database takes about this many times its live data.
+const COMPACTED_FACTOR: u64 = 4;
+/// Marked once the file is this many times the live data, so that compacting frees at
+/// least a third of it.
+const GARBAGE_FACTOR: u64 = 6;
+
+fn now_unix_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+pub async fn run(state: AppState) {
+    if state.config.compact_every_secs == 0 {
+        tracing::info!("compact: disabled (SERMA_COMPACT_EVERY_SECS=0)");
+        return;
+    }
+    let slice = Duration::from_millis(state.config.compact_max_ms);
+    let every = Duration::from_secs(state.config.compact_every_secs);
+    // First check one interval after startup, not during it.
+    let mut tick = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = state.shutdown.wait() => return,
+        }
+
+        let live_bytes = match measure(&state, slice).await {
+            Ok(bytes) => bytes,
+            Err(_) if state.shutdown.is_triggered() => return,
+            Err(err) => {
+                tracing::warn!(%err, "compact: measuring failed");
+                continue;
+            }
+        };
+        let db_bytes = state.db.size_on_disk().unwrap_or(0);
+        let due = db_bytes >= MIN_DB_BYTES && db_bytes >= live_bytes.saturating_mul(GARBAGE_FACTOR);
+
+        let mut status = match storage::compaction_status(&state.db) {
+            Ok(status) => status,
+            Err(err) => {
+                tracing::warn!(%err, "compact: failed reading status");
+                continue;
+            }
+        };
+        if due && status.due_bytes.is_none() {
+            tracing::info!(
+                db_bytes,
+                live_bytes,
+                "compact: database is mostly free space; it will be compacted on the next start"
+            );
+        }
+        status.checked_unix_ms = Some(now_unix_ms());
+        status.live_bytes = Some(live_bytes);
+        status.due_bytes = due.then(|| db_bytes.saturating_sub(live_bytes.saturating_mul(COMPACTED_FACTOR)));
+        if let Err(err) = storage::set_compaction_status(&state.db, &status) {
+            tracing::warn!(%err, "compact: failed saving status");
+        }
+        tracing::debug!(db_bytes, live_bytes, due, "compact: checked");
+    }
+}
+
+/// Live key and value bytes in every tree.
+async fn measure(state: &AppState, slice: Duration) -> anyhow::Result<u64> {
+    let mut total = 0u64;
+    for name in state.db.tree_names()? {
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let db = state.db.clone();
+            let tree = name.clone();
+            let resume = after.take();
+            let progress = tokio::task::spawn_blocking(move || {
+                storage::measure_tree(&db, &tree, resume.as_deref(), Instant::now() + slice)
+            })
+            .await??;
+            total += progress.bytes;
+            after = progress.next;
+            if after.is_none() {
+                break;
+            }
+            state.shutdown.sleep(slice).await;
+            if state.shutdown.is_triggered() {
+                anyhow::bail!("interrupted by shutdown");
+            }
+        }
+    }
+    Ok(total)
+}
@@ -1,33 +1,79 @@
+use anyhow::Context;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 #[derive(Clone, Debug)]
 pub struct Config {
     pub data_dir: PathBuf,
+    pub storage_backend: crate::kv::Backend,
 
     // Web
-    pub http_addr: Option<SocketAddr>,
+    /// Addresses the web server listens on; empty means loopback on `web_port`.
+    pub http_addrs: Vec<SocketAddr>,
     pub web_port: u16,
+    pub torznab_apikey: Option<String>,
+    pub api_token: Option<String>,
+    pub public_search: bool,
+    pub read_only: bool,
+    pub rate_limit_search_per_min: u32,
+    pub rate_limit_search_burst: u32,
+    pub rate_limit_ingest_per_min: u32,
+    pub rate_limit_ingest_burst: u32,
+    pub trust_proxy_headers: bool,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub public_url: Option<String>,
+
+    // Search
+    pub search_fuzzy_distance: u8,
+    pub search_match_all: bool,
+    pub search_seeder_boost: f32,
+    pub search_tokenizer: crate::index::TitleTokenizer,
+
+    // Index writer
+    pub index_writer_heap_mb: usize,
+    pub index_merge_policy: crate::index::MergePolicy,
+    pub index_merge_min_segments: usize,
+    pub index_commit_ops: usize,
+    pub index_commit_interval_ms: u64,
+    pub index_reindex_batch: usize,
+    pub index_reindex_max_per_sec: u64,
+    pub index_optimize_every_secs: u64,
+    pub index_optimize_max_docs: u64,
 
     // Spider
     pub spider_enabled: bool,
     pub spider_bind: String,
+    pub spider_nodes: usize,
     pub spider_bootstrap: Vec<String>,
     pub spider_max_known_nodes: usize,
+    pub spider_peer_store_hashes: usize,
+    pub spider_ip_rate: u32,
+    pub spider_ban_secs: u64,
+    pub spider_hot_hashes: usize,
     pub spider_seen_rotate_every_secs: u64,
     pub spider_seen_bits_pow2: u32,
     pub spider_seen_k: u8,
     pub spider_sample_every_secs: u64,
     pub spider_sample_per_tick: usize,
     pub spider_max_samples_per_msg: usize,
+    pub spider_queue_target: u64,
+    pub spider_max_disk_growth_mb: u64,
     pub spider_bootstrap_every_secs: u64,
     pub spider_gc_every_secs: u64,
 
     // Enrich
     pub enrich_missing_scan_limit: usize,
     pub enrich_max_concurrent: usize,
+    pub enrich_max_lookups: usize,
+    pub enrich_max_fetches: usize,
     pub enrich_peers_per_hash: usize,
+    pub enrich_dead_peers: usize,
+    pub enrich_peer_family: crate::dht::PeerFamily,
+    pub enrich_listen: Option<SocketAddr>,
     pub enrich_dht_bootstrap: Vec<String>,
     pub enrich_dht_query_timeout_ms: u64,
     pub enrich_dht_max_queries_per_hash: usize,
@@ -37,37 +83,164 @@ pub struct Config {
     pub enrich_dht_recv_timeout_ms: u64,
     pub enrich_metadata_inflight: usize,
     pub enrich_metadata_overall_timeout_secs: u64,
+    pub enrich_scrape_every_secs: u64,
+    pub tracker_max_per_sec: u32,
+    pub scrape_open_trackers: bool,
+    pub open_trackers: Vec<String>,
+    pub tracker_backoff_secs: u64,
+    pub enrich_recheck_every_hours: u64,
+    pub enrich_retry_base_secs: u64,
+    pub enrich_retry_max_secs: u64,
+    pub enrich_max_attempts: u32,
 
     // Cleanup
     pub cleanup_enabled: bool,
     pub cleanup_every_secs: u64,
     pub cleanup_batch: usize,
     pub cleanup_max_ms: u64,
+    pub cleanup_archive: bool,
+    pub cleanup_dry_run: bool,
+    /// Discovery sources whose torrents cleanup keeps, like pinned ones.
+    pub cleanup_keep_sources: Vec<crate::storage::Discovery>,
     pub torrent_ttl_secs: u64,
     pub low_seed_grace_secs: u64,
+    /// Torrents with fewer seeders count as low-seed (0 to 2; 0 keeps them all).
+    pub cleanup_min_seeders: u32,
     pub max_torrents: usize,
+    pub tombstone_ttl_secs: u64,
+    pub seeder_history_samples: usize,
+    pub seeder_history_every_secs: u64,
+
+    // Watch folder
+    pub watch_enabled: bool,
+    pub watch_dir: PathBuf,
+
+    // Ingest
+    pub ingest_max_per_sec: u64,
+    pub ingest: Option<crate::ingest::IngestSource>,
+    pub import_map: Option<crate::import::FieldMap>,
+
+    // Remote sources
+    pub sources: Vec<String>,
+    pub sources_every_secs: u64,
+
+    // Federation
+    pub export_token: Option<String>,
+    /// Instances to pull records from.
+    pub sync_from: Vec<String>,
+    /// Instances to push records to (from behind NAT).
+    pub sync_push_to: Vec<String>,
+    pub sync_token: Option<String>,
+    pub sync_every_secs: u64,
+
+    // Saved search alerts
+    pub alerts_every_secs: u64,
+    pub compact_every_secs: u64,
+    pub compact_max_ms: u64,
+    pub compact_on_start: bool,
+    pub fsck_on_start: bool,
+    pub backup_dir: PathBuf,
+
+    // Telemetry
+    /// OTLP/HTTP collector (`http://localhost:4318`) that receives trace spans; unset disables export.
+    pub otlp_endpoint: Option<String>,
+    pub otlp_service_name: String,
+    /// Fraction of new traces exported, 0 to 1.
+    pub otlp_sample_ratio: f32,
 }
 
 impl Config {
-    pub fn load() -> anyhow::Result<Self> {
+    /// Reads the config file (`--config PATH`, else `SERMA_CONFIG`, else `./serma.toml` if it
+    /// exists) and applies environment overrides. Every problem is reported at once.
+    pub fn load(config_path: Option<&Path>) -> anyhow::Result<Self> {
         // If a .env file exists, load it. If not, keep going.
-        // Precedence: process env > .env > code defaults.
+        // Precedence: process env > .env > config file > code defaults.
         let _ = dotenvy::dotenv();
-        Self::from_env()
+
+        let path = config_path
+            .map(Path::to_path_buf)
+            .or_else(|| env_opt_string("SERMA_CONFIG").map(PathBuf::from))
+            .or_else(|| Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|p| p.exists()));
+        let file = match path.as_deref() {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("read config {}", path.display()))?;
+                // toml's errors point at the line and column.
+                toml::from_str(&text).with_context(|| format!("parse config {}", path.display()))?
+            }
+            None => toml::Table::new(),
+        };
+
+        Self::resolve(file, &|name| std::env::var(name).ok()).map_err(|errors| {
+            let origin = path
+                .map(|p| format!(" (config file {})", p.display()))
+                .unwrap_or_default();
+            anyhow::anyhow!("invalid configuration{origin}:\n  {}", errors.join("\n  "))
+        })
+    }
+
+    /// The config from a parsed config file and environment variables (looked up with
+    /// `env`), or every problem found in them.
+    pub(crate) fn resolve(file: toml::Table, env: &dyn Fn(&str) -> Option<String>) -> Result<Self, Vec<String>> {
+        let sources = Sources {
+            file,
+            env,
+            read: RefCell::default(),
+            errors: RefCell::default(),
+        };
+        let config = Self::from_env(&sources);
+        let mut errors = sources.errors.take();
+        errors.extend(sources.unknown_keys().map(|key| {
+            if key.starts_with("socks5_") {
+                let name = key.to_ascii_uppercase();
+                format!("{key}: proxy settings are only read from the environment (SERMA_{name})")
+            } else {
+                format!("{key}: unknown setting")
+            }
+        }));
+        errors.extend(config.validate());
+        if errors.is_empty() { Ok(config) } else { Err(errors) }
     }
 
-    fn from_env() -> anyhow::Result<Self> {
-        let data_dir = env_pathbuf("SERMA_DATA_DIR", "data");
+    fn from_env(src: &Sources) -> Self {
+        let data_dir = src.pathbuf("SERMA_DATA_DIR", "data");
+        let storage_backend = src.parse_opt("SERMA_STORAGE").unwrap_or_default();
+
+        let http_addrs = src.csv_parse("SERMA_ADDR");
+
+        let web_port = src.u16("SERMA_WEB_PORT", 3000);
+        let torznab_apikey = src.opt_string("SERMA_TORZNAB_APIKEY");
+        let api_token = src.opt_string("SERMA_API_TOKEN");
+        let public_search = src.enabled("SERMA_PUBLIC_SEARCH", true);
+        let read_only = src.enabled("SERMA_READ_ONLY", false);
+        let rate_limit_search_per_min = src.u32("SERMA_RATE_LIMIT_SEARCH_PER_MIN", 120);
+        let rate_limit_search_burst = src.u32("SERMA_RATE_LIMIT_SEARCH_BURST", 30);
+        let rate_limit_ingest_per_min = src.u32("SERMA_RATE_LIMIT_INGEST_PER_MIN", 20);
+        let rate_limit_ingest_burst = src.u32("SERMA_RATE_LIMIT_INGEST_BURST", 10);
+        let trust_proxy_headers = src.enabled("SERMA_TRUST_PROXY_HEADERS", false);
+        let tls_cert = src.opt_string("SERMA_TLS_CERT").map(PathBuf::from);
+        let tls_key = src.opt_string("SERMA_TLS_KEY").map(PathBuf::from);
+        let public_url = src.opt_string("SERMA_PUBLIC_URL").map(|url| url.trim_end_matches('/').to_string());
 
-        let http_addr = env_opt_string("SERMA_ADDR")
-            .map(|s| SocketAddr::from_str(&s).map_err(|e| anyhow::anyhow!("parse SERMA_ADDR: {e}")))
-            .transpose()?;
+        let search_fuzzy_distance = src.u8("SERMA_SEARCH_FUZZY_DISTANCE", 1);
+        let search_match_all = src.enabled("SERMA_SEARCH_MATCH_ALL", true);
+        let search_seeder_boost = src.f32("SERMA_SEARCH_SEEDER_BOOST", 1.0);
+        let search_tokenizer = src.parse_opt("SERMA_SEARCH_TOKENIZER").unwrap_or_default();
 
-        let web_port = env_u16("SERMA_WEB_PORT", 3000);
+        let index_writer_heap_mb = src.usize("SERMA_INDEX_WRITER_HEAP_MB", 200);
+        let index_merge_policy = src.parse_opt("SERMA_INDEX_MERGE_POLICY").unwrap_or_default();
+        let index_merge_min_segments = src.usize("SERMA_INDEX_MERGE_MIN_SEGMENTS", 8);
+        let index_commit_ops = src.usize("SERMA_INDEX_COMMIT_OPS", 100);
+        let index_commit_interval_ms = src.u64("SERMA_INDEX_COMMIT_INTERVAL_MS", 2_000);
+        let index_reindex_batch = src.usize("SERMA_INDEX_REINDEX_BATCH", 100_000);
+        let index_reindex_max_per_sec = src.u64("SERMA_INDEX_REINDEX_MAX_PER_SEC", 0);
+        let index_optimize_every_secs = src.u64("SERMA_INDEX_OPTIMIZE_EVERY_SECS", 60 * 60);
+        let index_optimize_max_docs = src.u64("SERMA_INDEX_OPTIMIZE_MAX_DOCS", 1_000_000);
 
-        let spider_enabled = env_enabled("SERMA_SPIDER", true);
-        let spider_bind = env_string("SERMA_SPIDER_BIND", "0.0.0.0:0");
-        let spider_bootstrap = env_csv_strings(
+        let spider_enabled = src.enabled("SERMA_SPIDER", true);
+        let spider_bind = src.string("SERMA_SPIDER_BIND", "0.0.0.0:0");
+        let spider_nodes = src.usize("SERMA_SPIDER_NODES", 1);
+        let spider_bootstrap = src.csv_strings(
             "SERMA_SPIDER_BOOTSTRAP",
             &[
                 "router.bittorrent.com:6881",
@@ -75,20 +248,31 @@ impl Config {
                 "router.utorrent.com:6881",
             ],
         );
-        let spider_max_known_nodes = env_usize("SERMA_SPIDER_MAX_KNOWN_NODES", 10_000);
-        let spider_seen_rotate_every_secs = env_u64("SERMA_SPIDER_SEEN_ROTATE_EVERY_SECS", 15 * 60);
-        let spider_seen_bits_pow2 = env_u32("SERMA_SPIDER_SEEN_BITS_POW2", 26);
-        let spider_seen_k = env_u8("SERMA_SPIDER_SEEN_K", 12);
-        let spider_sample_every_secs = env_u64("SERMA_SPIDER_SAMPLE_EVERY_SECS", 5);
-        let spider_sample_per_tick = env_usize("SERMA_SPIDER_SAMPLE_PER_TICK", 12);
-        let spider_max_samples_per_msg = env_usize("SERMA_SPIDER_MAX_SAMPLES_PER_MSG", 256);
-        let spider_bootstrap_every_secs = env_u64("SERMA_SPIDER_BOOTSTRAP_EVERY_SECS", 15);
-        let spider_gc_every_secs = env_u64("SERMA_SPIDER_GC_EVERY_SECS", 30);
-
-        let enrich_missing_scan_limit = env_usize("SERMA_ENRICH_MISSING_SCAN_LIMIT", 200);
-        let enrich_max_concurrent = env_usize("SERMA_ENRICH_MAX_CONCURRENT", 64);
-        let enrich_peers_per_hash = env_usize("SERMA_ENRICH_PEERS_PER_HASH", 64);
-        let enrich_dht_bootstrap = env_csv_strings(
+        let spider_max_known_nodes = src.usize("SERMA_SPIDER_MAX_KNOWN_NODES", 10_000);
+        let spider_peer_store_hashes = src.usize("SERMA_SPIDER_PEER_STORE_HASHES", 20_000);
+        let spider_ip_rate = src.u32("SERMA_SPIDER_IP_RATE", 20);
+        let spider_ban_secs = src.u64("SERMA_SPIDER_BAN_SECS", 600);
+        let spider_hot_hashes = src.usize("SERMA_SPIDER_HOT_HASHES", 32);
+        let spider_seen_rotate_every_secs = src.u64("SERMA_SPIDER_SEEN_ROTATE_EVERY_SECS", 15 * 60);
+        let spider_seen_bits_pow2 = src.u32("SERMA_SPIDER_SEEN_BITS_POW2", 26);
+        let spider_seen_k = src.u8("SERMA_SPIDER_SEEN_K", 12);
+        let spider_sample_every_secs = src.u64("SERMA_SPIDER_SAMPLE_EVERY_SECS", 5);
+        let spider_sample_per_tick = src.usize("SERMA_SPIDER_SAMPLE_PER_TICK", 12);
+        let spider_max_samples_per_msg = src.usize("SERMA_SPIDER_MAX_SAMPLES_PER_MSG", 256);
+        let spider_queue_target = src.u64("SERMA_SPIDER_QUEUE_TARGET", 100_000);
+        let spider_max_disk_growth_mb = src.u64("SERMA_SPIDER_MAX_DISK_GROWTH_MB", 128);
+        let spider_bootstrap_every_secs = src.u64("SERMA_SPIDER_BOOTSTRAP_EVERY_SECS", 15);
+        let spider_gc_every_secs = src.u64("SERMA_SPIDER_GC_EVERY_SECS", 30);
+
+        let enrich_missing_scan_limit = src.usize("SERMA_ENRICH_MISSING_SCAN_LIMIT", 200);
+        let enrich_max_concurrent = src.usize("SERMA_ENRICH_MAX_CONCURRENT", 64);
+        let enrich_max_lookups = src.usize("SERMA_ENRICH_MAX_LOOKUPS", 64);
+        let enrich_max_fetches = src.usize("SERMA_ENRICH_MAX_FETCHES", 64);
+        let enrich_peers_per_hash = src.usize("SERMA_ENRICH_PEERS_PER_HASH", 64);
+        let enrich_dead_peers = src.usize("SERMA_ENRICH_DEAD_PEERS", 50_000);
+        let enrich_peer_family = src.parse_opt("SERMA_ENRICH_PEER_FAMILY").unwrap_or_default();
+        let enrich_listen = src.parse_opt("SERMA_ENRICH_LISTEN");
+        let enrich_dht_bootstrap = src.csv_strings(
             "SERMA_ENRICH_DHT_BOOTSTRAP",
             &[
                 "router.bittorrent.com:6881",
@@ -96,45 +280,138 @@ impl Config {
                 "router.utorrent.com:6881",
             ],
         );
-        let enrich_dht_query_timeout_ms = env_u64("SERMA_ENRICH_DHT_QUERY_TIMEOUT_MS", 900);
-        let enrich_dht_max_queries_per_hash = env_usize("SERMA_ENRICH_DHT_MAX_QUERIES_PER_HASH", 32);
-        let enrich_dht_get_peers_timeout_secs = env_u64("SERMA_ENRICH_DHT_GET_PEERS_TIMEOUT_SECS", 12);
-        let enrich_dht_overall_deadline_secs = env_u64("SERMA_ENRICH_DHT_OVERALL_DEADLINE_SECS", 10);
-        let enrich_dht_inflight = env_usize("SERMA_ENRICH_DHT_INFLIGHT", 8);
-        let enrich_dht_recv_timeout_ms = env_u64("SERMA_ENRICH_DHT_RECV_TIMEOUT_MS", 250);
-        let enrich_metadata_inflight = env_usize("SERMA_ENRICH_METADATA_INFLIGHT", 8);
+        let enrich_dht_query_timeout_ms = src.u64("SERMA_ENRICH_DHT_QUERY_TIMEOUT_MS", 900);
+        let enrich_dht_max_queries_per_hash = src.usize("SERMA_ENRICH_DHT_MAX_QUERIES_PER_HASH", 32);
+        let enrich_dht_get_peers_timeout_secs = src.u64("SERMA_ENRICH_DHT_GET_PEERS_TIMEOUT_SECS", 12);
+        let enrich_dht_overall_deadline_secs = src.u64("SERMA_ENRICH_DHT_OVERALL_DEADLINE_SECS", 10);
+        let enrich_dht_inflight = src.usize("SERMA_ENRICH_DHT_INFLIGHT", 8);
+        let enrich_dht_recv_timeout_ms = src.u64("SERMA_ENRICH_DHT_RECV_TIMEOUT_MS", 250);
+        let enrich_metadata_inflight = src.usize("SERMA_ENRICH_METADATA_INFLIGHT", 8);
         let enrich_metadata_overall_timeout_secs =
-            env_u64("SERMA_ENRICH_METADATA_OVERALL_TIMEOUT_SECS", 16);
+            src.u64("SERMA_ENRICH_METADATA_OVERALL_TIMEOUT_SECS", 16);
+        let enrich_scrape_every_secs = src.u64("SERMA_ENRICH_SCRAPE_EVERY_SECS", 30 * 60);
+        let tracker_max_per_sec = src.u32("SERMA_TRACKER_MAX_PER_SEC", 5);
+        let scrape_open_trackers = src.enabled("SERMA_SCRAPE_OPEN_TRACKERS", false);
+        let open_trackers = src.csv_strings("SERMA_OPEN_TRACKERS", crate::scrape::OPEN_TRACKERS);
+        let tracker_backoff_secs = src.u64("SERMA_TRACKER_BACKOFF_SECS", 60);
+        let enrich_recheck_every_hours = src.u64("SERMA_ENRICH_RECHECK_EVERY_HOURS", 24);
+        let enrich_retry_base_secs = src.u64("SERMA_ENRICH_RETRY_BASE_SECS", 15 * 60);
+        let enrich_retry_max_secs = src.u64("SERMA_ENRICH_RETRY_MAX_SECS", 24 * 60 * 60);
+        let enrich_max_attempts = src.u32("SERMA_ENRICH_MAX_ATTEMPTS", 10);
+
+        let cleanup_enabled = src.enabled("SERMA_CLEANUP", true);
+        let cleanup_every_secs = src.u64("SERMA_CLEANUP_EVERY_SECS", 10);
+        let cleanup_batch = src.usize("SERMA_CLEANUP_BATCH", 5_000);
+        let cleanup_max_ms = src.u64("SERMA_CLEANUP_MAX_MS", 1_000);
+        let cleanup_archive = src.enabled("SERMA_CLEANUP_ARCHIVE", false);
+        let cleanup_dry_run = src.enabled("SERMA_CLEANUP_DRY_RUN", false);
+        let cleanup_keep_sources = src.csv_parse("SERMA_CLEANUP_KEEP_SOURCES");
+        let torrent_ttl_secs = src.u64("SERMA_TORRENT_TTL_SECS", 24 * 60 * 60);
+        let low_seed_grace_secs = src.u64("SERMA_LOW_SEED_GRACE_SECS", 20 * 60);
+        let cleanup_min_seeders = src.u32("SERMA_CLEANUP_MIN_SEEDERS", 2);
+        let max_torrents = src.usize("SERMA_MAX_TORRENTS", 0);
+        let tombstone_ttl_secs = src.u64("SERMA_TOMBSTONE_TTL_SECS", 7 * 24 * 60 * 60);
+        let seeder_history_samples = src.usize("SERMA_SEEDER_HISTORY_SAMPLES", 28);
+        let seeder_history_every_secs = src.u64("SERMA_SEEDER_HISTORY_EVERY_SECS", 6 * 60 * 60);
+
+        let ingest_max_per_sec = src.u64("SERMA_INGEST_MAX_PER_SEC", 0);
+        let ingest = src.parse_opt("SERMA_INGEST");
+        let import_map = src.parse_opt("SERMA_IMPORT_MAP");
+
+        let sources = src.csv_strings("SERMA_SOURCES", &[]);
+        let sources_every_secs = src.u64("SERMA_SOURCES_EVERY_SECS", 30 * 60).max(60);
+
+        let export_token = src.opt_string("SERMA_EXPORT_TOKEN");
+        let peer_urls = |name| -> Vec<String> {
+            src.csv_strings(name, &[])
+                .into_iter()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .collect()
+        };
+        let sync_from = peer_urls("SERMA_SYNC_FROM");
+        let sync_push_to = peer_urls("SERMA_SYNC_PUSH_TO");
+        let sync_token = src.opt_string("SERMA_SYNC_TOKEN");
+        let sync_every_secs = src.u64("SERMA_SYNC_EVERY_SECS", 5 * 60).max(10);
 
-        let cleanup_enabled = env_enabled("SERMA_CLEANUP", true);
-        let cleanup_every_secs = env_u64("SERMA_CLEANUP_EVERY_SECS", 10);
-        let cleanup_batch = env_usize("SERMA_CLEANUP_BATCH", 5_000);
-        let cleanup_max_ms = env_u64("SERMA_CLEANUP_MAX_MS", 1_000);
-        let torrent_ttl_secs = env_u64("SERMA_TORRENT_TTL_SECS", 24 * 60 * 60);
-        let low_seed_grace_secs = env_u64("SERMA_LOW_SEED_GRACE_SECS", 20 * 60);
-        let max_torrents = env_usize("SERMA_MAX_TORRENTS", 0);
+        let alerts_every_secs = src.u64("SERMA_ALERTS_EVERY_SECS", 60);
+        let compact_every_secs = src.u64("SERMA_COMPACT_EVERY_SECS", 60 * 60);
+        let compact_max_ms = src.u64("SERMA_COMPACT_MAX_MS", 500);
+        let compact_on_start = src.enabled("SERMA_COMPACT_ON_START", true);
+        let fsck_on_start = src.enabled("SERMA_FSCK_ON_START", false);
+        let backup_dir = src.opt_string("SERMA_BACKUP_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| data_dir.join("backups"));
 
-        Ok(Self {
+        let watch_enabled = src.enabled("SERMA_WATCH", true);
+        let watch_dir = src.opt_string("SERMA_WATCH_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| data_dir.join("watch"));
+
+        let otlp_endpoint = src.opt_string("SERMA_OTLP_ENDPOINT").map(|s| s.trim_end_matches('/').to_string());
+        let otlp_service_name = src.string("SERMA_OTLP_SERVICE_NAME", "serma");
+        let otlp_sample_ratio = src.f32("SERMA_OTLP_SAMPLE_RATIO", 1.0);
+
+        Self {
             data_dir,
-            http_addr,
+            storage_backend,
+            http_addrs,
             web_port,
+            torznab_apikey,
+            api_token,
+            public_search,
+            read_only,
+            rate_limit_search_per_min,
+            rate_limit_search_burst,
+            rate_limit_ingest_per_min,
+            rate_limit_ingest_burst,
+            trust_proxy_headers,
+            tls_cert,
+            tls_key,
+            public_url,
+
+            search_fuzzy_distance,
+            search_match_all,
+            search_seeder_boost,
+            search_tokenizer,
+
+            index_writer_heap_mb,
+            index_merge_policy,
+            index_merge_min_segments,
+            index_commit_ops,
+            index_commit_interval_ms,
+            index_reindex_batch,
+            index_reindex_max_per_sec,
+            index_optimize_every_secs,
+            index_optimize_max_docs,
 
             spider_enabled,
             spider_bind,
+            spider_nodes,
             spider_bootstrap,
             spider_max_known_nodes,
+            spider_peer_store_hashes,
+            spider_ip_rate,
+            spider_ban_secs,
+            spider_hot_hashes,
             spider_seen_rotate_every_secs,
             spider_seen_bits_pow2,
             spider_seen_k,
             spider_sample_every_secs,
             spider_sample_per_tick,
             spider_max_samples_per_msg,
+            spider_queue_target,
+            spider_max_disk_growth_mb,
             spider_bootstrap_every_secs,
             spider_gc_every_secs,
 
             enrich_missing_scan_limit,
             enrich_max_concurrent,
+            enrich_max_lookups,
+            enrich_max_fetches,
             enrich_peers_per_hash,
+            enrich_dead_peers,
+            enrich_peer_family,
+            enrich_listen,
             enrich_dht_bootstrap,
             enrich_dht_query_timeout_ms,
             enrich_dht_max_queries_per_hash,
@@ -144,18 +421,268 @@ impl Config {
             enrich_dht_recv_timeout_ms,
             enrich_metadata_inflight,
             enrich_metadata_overall_timeout_secs,
+            enrich_scrape_every_secs,
+            tracker_max_per_sec,
+            scrape_open_trackers,
+            open_trackers,
+            tracker_backoff_secs,
+            enrich_recheck_every_hours,
+            enrich_retry_base_secs,
+            enrich_retry_max_secs,
+            enrich_max_attempts,
 
             cleanup_enabled,
             cleanup_every_secs,
             cleanup_batch,
             cleanup_max_ms,
+            cleanup_archive,
+            cleanup_dry_run,
+            cleanup_keep_sources,
             torrent_ttl_secs,
             low_seed_grace_secs,
+            cleanup_min_seeders,
             max_torrents,
-        })
+            tombstone_ttl_secs,
+            seeder_history_samples,
+            seeder_history_every_secs,
+
+            watch_enabled,
+            watch_dir,
+
+            ingest_max_per_sec,
+            ingest,
+            import_map,
+
+            sources,
+            sources_every_secs,
+
+            export_token,
+            sync_from,
+            sync_push_to,
+            sync_token,
+            sync_every_secs,
+
+            alerts_every_secs,
+            compact_every_secs,
+            compact_max_ms,
+            compact_on_start,
+            fsck_on_start,
+            backup_dir,
+
+            otlp_endpoint,
+            otlp_service_name,
+            otlp_sample_ratio,
+        }
+    }
+
+    /// Range checks, whichever source a value came from.
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut check = |ok: bool, name: &str, value: &dyn std::fmt::Display, rule: &str| {
+            if !ok {
+                errors.push(format!("{name}={value}: {rule}"));
+            }
+        };
+
+        check(
+            self.tls_cert.is_some() == self.tls_key.is_some(),
+            "SERMA_TLS_CERT",
+            &self.tls_cert.as_deref().map_or("(unset)".into(), |p| p.display().to_string()),
+            "must be set together with SERMA_TLS_KEY",
+        );
+        check(
+            self.public_url.as_deref().is_none_or(|url| {
+                url.strip_prefix("https://")
+                    .or_else(|| url.strip_prefix("http://"))
+                    .is_some_and(|rest| !rest.is_empty() && !rest.contains(['?', '#']) && !rest.contains(char::is_whitespace))
+            }),
+            "SERMA_PUBLIC_URL",
+            &self.public_url.as_deref().unwrap_or_default(),
+            "must be an http:// or https:// URL without a query",
+        );
+        check(
+            !self.read_only || self.api_token.is_some(),
+            "SERMA_READ_ONLY",
+            &self.read_only,
+            "requires SERMA_API_TOKEN, or the admin pages would be open to everyone",
+        );
+        check(
+            self.spider_bind.parse::<SocketAddr>().is_ok(),
+            "SERMA_SPIDER_BIND",
+            &self.spider_bind,
+            "must be an ip:port address",
+        );
+        check(
+            self.search_fuzzy_distance <= crate::index::MAX_FUZZY_DISTANCE,
+            "SERMA_SEARCH_FUZZY_DISTANCE",
+            &self.search_fuzzy_distance,
+            "must be 0, 1 or 2",
+        );
+        check(
+            (0.0..=100.0).contains(&self.search_seeder_boost),
+            "SERMA_SEARCH_SEEDER_BOOST",
+            &self.search_seeder_boost,
+            "must be between 0 and 100",
+        );
+        // Tantivy wants at least 15 MB per writer thread.
+        check(
+            (16..=16_384).contains(&self.index_writer_heap_mb),
+            "SERMA_INDEX_WRITER_HEAP_MB",
+            &self.index_writer_heap_mb,
+            "must be between 16 and 16384",
+        );
+        check(
+            self.index_merge_min_segments >= 2,
+            "SERMA_INDEX_MERGE_MIN_SEGMENTS",
+            &self.index_merge_min_segments,
+            "must be at least 2",
+        );
+        check(
+            (1..=64).contains(&self.spider_nodes),
+            "SERMA_SPIDER_NODES",
+            &self.spider_nodes,
+            "must be between 1 and 64",
+        );
+        check(
+            (10..=32).contains(&self.spider_seen_bits_pow2),
+            "SERMA_SPIDER_SEEN_BITS_POW2",
+            &self.spider_seen_bits_pow2,
+            "must be between 10 and 32",
+        );
+        check(
+            (1..=32).contains(&self.spider_seen_k),
+            "SERMA_SPIDER_SEEN_K",
+            &self.spider_seen_k,
+            "must be between 1 and 32",
+        );
+        for (name, value) in [
+            ("SERMA_ENRICH_MAX_CONCURRENT", self.enrich_max_concurrent),
+            ("SERMA_ENRICH_MAX_LOOKUPS", self.enrich_max_lookups),
+            ("SERMA_ENRICH_MAX_FETCHES", self.enrich_max_fetches),
+            ("SERMA_ENRICH_PEERS_PER_HASH", self.enrich_peers_per_hash),
+            ("SERMA_ENRICH_DHT_INFLIGHT", self.enrich_dht_inflight),
+            ("SERMA_ENRICH_METADATA_INFLIGHT", self.enrich_metadata_inflight),
+            ("SERMA_CLEANUP_BATCH", self.cleanup_batch),
+            ("SERMA_INDEX_COMMIT_OPS", self.index_commit_ops),
+            ("SERMA_INDEX_REINDEX_BATCH", self.index_reindex_batch),
+        ] {
+            check(value >= 1, name, &value, "must be at least 1");
+        }
+        for (name, value) in [
+            ("SERMA_SPIDER_SEEN_ROTATE_EVERY_SECS", self.spider_seen_rotate_every_secs),
+            ("SERMA_ENRICH_SCRAPE_EVERY_SECS", self.enrich_scrape_every_secs),
+            ("SERMA_ENRICH_RECHECK_EVERY_HOURS", self.enrich_recheck_every_hours),
+            ("SERMA_ENRICH_RETRY_BASE_SECS", self.enrich_retry_base_secs),
+            ("SERMA_ALERTS_EVERY_SECS", self.alerts_every_secs),
+            ("SERMA_COMPACT_MAX_MS", self.compact_max_ms),
+            ("SERMA_SEEDER_HISTORY_EVERY_SECS", self.seeder_history_every_secs),
+            ("SERMA_INDEX_OPTIMIZE_MAX_DOCS", self.index_optimize_max_docs),
+        ] {
+            check(value >= 1, name, &value, "must be at least 1");
+        }
+        for (name, value) in [
+            ("SERMA_RATE_LIMIT_SEARCH_BURST", self.rate_limit_search_burst),
+            ("SERMA_RATE_LIMIT_INGEST_BURST", self.rate_limit_ingest_burst),
+        ] {
+            check(value >= 1, name, &value, "must be at least 1");
+        }
+        check(
+            self.cleanup_min_seeders <= 2,
+            "SERMA_CLEANUP_MIN_SEEDERS",
+            &self.cleanup_min_seeders,
+            "must be between 0 and 2",
+        );
+        check(
+            self.enrich_max_attempts >= 1,
+            "SERMA_ENRICH_MAX_ATTEMPTS",
+            &self.enrich_max_attempts,
+            "must be at least 1",
+        );
+        for (name, urls) in [("SERMA_SYNC_FROM", &self.sync_from), ("SERMA_SYNC_PUSH_TO", &self.sync_push_to)] {
+            for url in urls {
+                check(
+                    url.starts_with("http://") || url.starts_with("https://"),
+                    name,
+                    url,
+                    "must be a list of http(s) URLs",
+                );
+            }
+        }
+        for url in &self.open_trackers {
+            check(
+                crate::scrape::is_scrapable(url),
+                "SERMA_OPEN_TRACKERS",
+                url,
+                "must be a list of udp:// or http(s) announce URLs",
+            );
+        }
+        if let Some(url) = self.otlp_endpoint.as_deref() {
+            check(
+                url.starts_with("http://") || url.starts_with("https://"),
+                "SERMA_OTLP_ENDPOINT",
+                &url,
+                "must be an http(s) URL",
+            );
+        }
+        check(
+            (0.0..=1.0).contains(&self.otlp_sample_ratio),
+            "SERMA_OTLP_SAMPLE_RATIO",
+            &self.otlp_sample_ratio,
+            "must be between 0 and 1",
+        );
+        errors
+    }
+
+    /// How swarm count updates are sampled into seeder history.
+    pub fn seeder_history(&self) -> crate::storage::HistoryPolicy {
+        crate::storage::HistoryPolicy {
+            every_ms: (self.seeder_history_every_secs as i64).saturating_mul(1000),
+            keep: self.seeder_history_samples,
+        }
+    }
+
+    /// How the search index writes, merges and commits.
+    pub fn index_writer_options(&self) -> crate::index::WriterOptions {
+        crate::index::WriterOptions {
+            heap_bytes: self.index_writer_heap_mb << 20,
+            merge_policy: self.index_merge_policy,
+            merge_min_segments: self.index_merge_min_segments,
+            commit_every_ops: self.index_commit_ops,
+            commit_interval: std::time::Duration::from_millis(self.index_commit_interval_ms),
+            reindex_batch: self.index_reindex_batch,
+            reindex_max_per_sec: self.index_reindex_max_per_sec,
+        }
+    }
+
+    /// Instance-wide query behaviour; search requests may override it.
+    pub fn query_options(&self) -> crate::index::QueryOptions {
+        crate::index::QueryOptions {
+            fuzzy_distance: self.search_fuzzy_distance,
+            match_all: self.search_match_all,
+            seeder_boost: self.search_seeder_boost,
+            // Only per request (`group=title`): bulk jobs and alerts want every torrent.
+            group_titles: false,
+        }
     }
 }
 
+const DEFAULT_CONFIG_FILE: &str = "serma.toml";
+
+// `serma.toml` uses the variable names without the `SERMA_` prefix, in lowercase
+// (`web_port = 3000` for `SERMA_WEB_PORT`); lists may be arrays. Env vars win; values
+// that don't parse are collected in `errors` instead of silently falling back.
+struct Sources<'a> {
+    file: toml::Table,
+    env: &'a dyn Fn(&str) -> Option<String>,
+    /// File keys looked up so far; anything else in the file is a typo.
+    read: RefCell<BTreeSet<String>>,
+    errors: RefCell<Vec<String>>,
+}
+
+fn file_key(name: &str) -> String {
+    name.strip_prefix("SERMA_").unwrap_or(name).to_ascii_lowercase()
+}
+
 fn env_opt_string(name: &str) -> Option<String> {
     std::env::var(name)
         .ok()
@@ -163,75 +690,220 @@ fn env_opt_string(name: &str) -> Option<String> {
         .filter(|s| !s.is_empty())
 }
 
-fn env_string(name: &str, default: &str) -> String {
-    env_opt_string(name).unwrap_or_else(|| default.to_string())
-}
+impl Sources<'_> {
+    fn unknown_keys(&self) -> impl Iterator<Item = &String> {
+        let read = self.read.borrow().clone();
+        self.file.keys().filter(move |key| !read.contains(*key))
+    }
 
-fn env_pathbuf(name: &str, default: &str) -> PathBuf {
-    PathBuf::from(env_string(name, default))
-}
+    /// The file's value for `name` as text (arrays joined with commas), like an env var.
+    fn file_value(&self, name: &str) -> Option<String> {
+        let key = file_key(name);
+        let value = match self.file.get(&key)? {
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Integer(i) => i.to_string(),
+            toml::Value::Float(f) => f.to_string(),
+            toml::Value::Boolean(b) => b.to_string(),
+            toml::Value::Array(items) => match items
+                .iter()
+                .map(toml::Value::as_str)
+                .collect::<Option<Vec<_>>>()
+            {
+                Some(items) => items.join(","),
+                None => {
+                    self.errors.borrow_mut().push(format!("{key}: expected a list of strings"));
+                    return None;
+                }
+            },
+            other => {
+                self.errors.borrow_mut().push(format!("{key}: unsupported value {other}"));
+                return None;
+            }
+        };
+        Some(value)
+    }
 
-fn env_u64(name: &str, default: u64) -> u64 {
-    std::env::var(name)
-        .ok()
-        .and_then(|v| v.trim().parse::<u64>().ok())
-        .unwrap_or(default)
-}
+    /// Env var, else file value, with where it came from; blank counts as unset.
+    fn lookup(&self, name: &str) -> Option<(String, bool)> {
+        self.read.borrow_mut().insert(file_key(name));
+        let env = (self.env)(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        env.map(|v| (v, true)).or_else(|| {
+            self.file_value(name)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .map(|v| (v, false))
+        })
+    }
 
-fn env_u32(name: &str, default: u32) -> u32 {
-    std::env::var(name)
-        .ok()
-        .and_then(|v| v.trim().parse::<u32>().ok())
-        .unwrap_or(default)
-}
+    fn report(&self, name: &str, from_env: bool, value: &str, err: impl std::fmt::Display) {
+        let source = if from_env { name.to_string() } else { file_key(name) };
+        self.errors.borrow_mut().push(format!("{source}={value:?}: {err}"));
+    }
 
-fn env_u16(name: &str, default: u16) -> u16 {
-    std::env::var(name)
-        .ok()
-        .and_then(|v| v.trim().parse::<u16>().ok())
-        .unwrap_or(default)
-}
+    fn opt_string(&self, name: &str) -> Option<String> {
+        self.lookup(name).map(|(v, _)| v)
+    }
 
-fn env_u8(name: &str, default: u8) -> u8 {
-    std::env::var(name)
-        .ok()
-        .and_then(|v| v.trim().parse::<u8>().ok())
-        .unwrap_or(default)
-}
+    fn string(&self, name: &str, default: &str) -> String {
+        self.opt_string(name).unwrap_or_else(|| default.to_string())
+    }
 
-fn env_usize(name: &str, default: usize) -> usize {
-    std::env::var(name)
-        .ok()
-        .and_then(|v| v.trim().parse::<usize>().ok())
-        .unwrap_or(default)
-}
+    fn pathbuf(&self, name: &str, default: &str) -> PathBuf {
+        PathBuf::from(self.string(name, default))
+    }
+
+    fn parse_opt<T>(&self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let (value, from_env) = self.lookup(name)?;
+        value
+            .parse()
+            .map_err(|err| self.report(name, from_env, &value, err))
+            .ok()
+    }
 
-fn env_csv_strings(name: &str, defaults: &[&str]) -> Vec<String> {
-    if let Some(s) = env_opt_string(name) {
-        let v: Vec<String> = s
+    fn u64(&self, name: &str, default: u64) -> u64 {
+        self.parse_opt(name).unwrap_or(default)
+    }
+
+    fn u32(&self, name: &str, default: u32) -> u32 {
+        self.parse_opt(name).unwrap_or(default)
+    }
+
+    fn u16(&self, name: &str, default: u16) -> u16 {
+        self.parse_opt(name).unwrap_or(default)
+    }
+
+    fn u8(&self, name: &str, default: u8) -> u8 {
+        self.parse_opt(name).unwrap_or(default)
+    }
+
+    fn f32(&self, name: &str, default: f32) -> f32 {
+        self.parse_opt(name).unwrap_or(default)
+    }
+
+    fn usize(&self, name: &str, default: usize) -> usize {
+        self.parse_opt(name).unwrap_or(default)
+    }
+
+    /// A comma-separated list, each item parsed like `parse_opt`; bad items are reported.
+    fn csv_parse<T>(&self, name: &str) -> Vec<T>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let Some((value, from_env)) = self.lookup(name) else {
+            return Vec::new();
+        };
+        value
             .split(',')
-            .map(|x| x.trim().to_string())
-            .filter(|x| !x.is_empty())
-            .collect();
-        if !v.is_empty() {
-            return v;
-        }
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .filter_map(|item| item.parse().map_err(|err| self.report(name, from_env, item, err)).ok())
+            .collect()
     }
-    defaults.iter().map(|s| s.to_string()).collect()
-}
 
-fn env_enabled(name: &str, default: bool) -> bool {
-    match env_opt_string(name) {
-        None => default,
-        Some(v) => {
-            let v = v.to_ascii_lowercase();
-            if matches!(v.as_str(), "0" | "false" | "off" | "no") {
-                return false;
+    fn csv_strings(&self, name: &str, defaults: &[&str]) -> Vec<String> {
+        if let Some(s) = self.opt_string(name) {
+            let v: Vec<String> = s
+                .split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect();
+            if !v.is_empty() {
+                return v;
             }
-            if matches!(v.as_str(), "1" | "true" | "on" | "yes") {
-                return true;
+        }
+        defaults.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn enabled(&self, name: &str, default: bool) -> bool {
+        match self.lookup(name) {
+            None => default,
+            Some((v, from_env)) => {
+                let lower = v.to_ascii_lowercase();
+                if matches!(lower.as_str(), "0" | "false" | "off" | "no") {
+                    return false;
+                }
+                if matches!(lower.as_str(), "1" | "true" | "on" | "yes") {
+                    return true;
+                }
+                self.report(name, from_env, &v, "expected 1/0, true/false, on/off or yes/no");
+                default
             }
-            default
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(file: &str, env: &[(&str, &str)]) -> Result<Config, Vec<String>> {
+        let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::resolve(toml::from_str(file).unwrap(), &|name| env.get(name).cloned())
+    }
+
+    #[test]
+    fn env_wins_over_the_file_and_the_file_over_defaults() {
+        let config = resolve("", &[]).unwrap();
+        assert_eq!(config.web_port, 3000);
+        assert!(config.public_search);
+
+        let file = "web_port = 4000\npublic_search = false\nspider_bootstrap = [\"a:1\", \"b:2\"]";
+        let config = resolve(file, &[]).unwrap();
+        assert_eq!(config.web_port, 4000);
+        assert!(!config.public_search);
+        assert_eq!(config.spider_bootstrap, ["a:1", "b:2"]);
+
+        let config = resolve(file, &[("SERMA_WEB_PORT", "5000"), ("SERMA_SPIDER_BOOTSTRAP", "c:3")]).unwrap();
+        assert_eq!(config.web_port, 5000);
+        assert_eq!(config.spider_bootstrap, ["c:3"]);
+        // A blank variable counts as unset.
+        let config = resolve(file, &[("SERMA_WEB_PORT", "  ")]).unwrap();
+        assert_eq!(config.web_port, 4000);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_bad_values() {
+        let errors = resolve("web_prot = 4000\nsocks5_proxy = \"x\"", &[]).unwrap_err();
+        assert!(errors.contains(&"web_prot: unknown setting".to_string()), "{errors:?}");
+        assert!(errors.iter().any(|e| e.starts_with("socks5_proxy: proxy settings are only read from the environment")));
+
+        // Each problem names where the value came from.
+        let errors = resolve("web_port = \"high\"", &[("SERMA_SPIDER", "maybe")]).unwrap_err();
+        assert_eq!(errors.len(), 2, "{errors:?}");
+        assert!(errors[0].starts_with("web_port=\"high\""), "{errors:?}");
+        assert!(errors[1].starts_with("SERMA_SPIDER=\"maybe\""), "{errors:?}");
+        assert!(resolve("spider_bootstrap = [1, 2]", &[]).unwrap_err()[0].contains("expected a list of strings"));
+    }
+
+    #[test]
+    fn validates_ranges_and_combinations() {
+        let errors = resolve(
+            "search_fuzzy_distance = 3\nspider_nodes = 0\ntls_cert = \"cert.pem\"\nread_only = true",
+            &[("SERMA_SYNC_FROM", "ftp://peer"), ("SERMA_OTLP_SAMPLE_RATIO", "2")],
+        )
+        .unwrap_err();
+        for name in [
+            "SERMA_SEARCH_FUZZY_DISTANCE=3",
+            "SERMA_SPIDER_NODES=0",
+            "SERMA_TLS_CERT=cert.pem",
+            "SERMA_READ_ONLY=true",
+            "SERMA_SYNC_FROM=ftp://peer",
+            "SERMA_OTLP_SAMPLE_RATIO=2",
+        ] {
+            assert!(errors.iter().any(|e| e.starts_with(name)), "{name} missing from {errors:?}");
+        }
+        assert_eq!(errors.len(), 6, "{errors:?}");
+
+        let config = resolve(
+            "tls_cert = \"cert.pem\"\ntls_key = \"key.pem\"\nread_only = true\napi_token = \"t\"",
+            &[("SERMA_SEARCH_FUZZY_DISTANCE", "2"), ("SERMA_SPIDER_NODES", "64")],
+        );
+        assert!(config.is_ok(), "{:?}", config.err());
+    }
+}
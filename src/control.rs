@@ -0,0 +1,98 @@
+use crate::config::Config;
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Runtime pause/resume switches for background subsystems.
+//
+// `SERMA_SPIDER` / `SERMA_CLEANUP` still decide whether a task is started at all;
+// these switches only pause/resume tasks that are running, without a restart.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Spider,
+    Enrich,
+    Cleanup,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 3] = [Subsystem::Spider, Subsystem::Enrich, Subsystem::Cleanup];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Subsystem::Spider => "spider",
+            Subsystem::Enrich => "enrich",
+            Subsystem::Cleanup => "cleanup",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "spider" => Some(Subsystem::Spider),
+            "enrich" => Some(Subsystem::Enrich),
+            "cleanup" => Some(Subsystem::Cleanup),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemStatus {
+    pub name: &'static str,
+    /// False if the task was disabled at startup (it cannot be resumed at runtime).
+    pub enabled: bool,
+    pub paused: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct Controls {
+    inner: Arc<ControlsInner>,
+}
+
+#[derive(Default)]
+struct ControlsInner {
+    spider_paused: AtomicBool,
+    enrich_paused: AtomicBool,
+    cleanup_paused: AtomicBool,
+}
+
+impl Controls {
+    fn flag(&self, subsystem: Subsystem) -> &AtomicBool {
+        match subsystem {
+            Subsystem::Spider => &self.inner.spider_paused,
+            Subsystem::Enrich => &self.inner.enrich_paused,
+            Subsystem::Cleanup => &self.inner.cleanup_paused,
+        }
+    }
+
+    pub fn is_paused(&self, subsystem: Subsystem) -> bool {
+        self.flag(subsystem).load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, subsystem: Subsystem, paused: bool) {
+        let was = self.flag(subsystem).swap(paused, Ordering::Relaxed);
+        if was != paused {
+            tracing::info!(subsystem = subsystem.as_str(), paused, "control: subsystem state changed");
+        }
+    }
+
+    pub fn status(&self, config: &Config, subsystem: Subsystem) -> SubsystemStatus {
+        let enabled = match subsystem {
+            Subsystem::Spider => config.spider_enabled,
+            Subsystem::Enrich => true,
+            Subsystem::Cleanup => config.cleanup_enabled,
+        };
+        SubsystemStatus {
+            name: subsystem.as_str(),
+            enabled,
+            paused: self.is_paused(subsystem),
+        }
+    }
+
+    pub fn snapshot(&self, config: &Config) -> Vec<SubsystemStatus> {
+        Subsystem::ALL
+            .iter()
+            .map(|s| self.status(config, *s))
+            .collect()
+    }
+}
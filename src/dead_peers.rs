@@ -0,0 +1,90 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+// Peers the metadata fetcher recently found useless.
+//
+// The same peers show up in the swarms of many hashes (often one client seeding thousands of
+// torrents), and a dead one costs every lookup a connect timeout. Peers that refused or
+// timed out the connection, or that don't speak ut_metadata, are remembered for `DEAD_FOR`
+// and skipped by all enrichment tasks. At most `capacity` are kept; the least recently
+// failed go first.
+
+/// How long a failed peer is skipped.
+const DEAD_FOR: Duration = Duration::from_secs(10 * 60);
+
+pub struct DeadPeers {
+    capacity: usize,
+    failed: HashMap<SocketAddr, Instant>,
+    /// Failures, oldest first; entries whose peer failed again since are skipped.
+    order: VecDeque<(SocketAddr, Instant)>,
+}
+
+impl DeadPeers {
+    /// Remembers up to `capacity` peers; 0 remembers none.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            failed: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Notes that `peer` failed.
+    pub fn mark(&mut self, peer: SocketAddr, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.failed.insert(peer, now);
+        self.order.push_back((peer, now));
+        while self.failed.len() > self.capacity {
+            let Some((oldest, at)) = self.order.pop_front() else {
+                break;
+            };
+            if self.failed.get(&oldest) == Some(&at) {
+                self.failed.remove(&oldest);
+            }
+        }
+        // Peers that keep failing leave stale entries behind; don't let them pile up.
+        if self.order.len() > self.capacity * 2 {
+            let failed = &self.failed;
+            self.order.retain(|(peer, at)| failed.get(peer) == Some(at));
+        }
+    }
+
+    /// True if `peer` failed within `DEAD_FOR`.
+    pub fn is_dead(&self, peer: &SocketAddr, now: Instant) -> bool {
+        self.failed
+            .get(peer)
+            .is_some_and(|at| now.saturating_duration_since(*at) < DEAD_FOR)
+    }
+
+    /// Peers remembered, expired ones included until they are pushed out.
+    pub fn count(&self) -> usize {
+        self.failed.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_least_recently_failed_and_expired() {
+        let mut dead = DeadPeers::new(2);
+        let start = Instant::now();
+        let a: SocketAddr = "192.0.2.1:6881".parse().unwrap();
+        let b: SocketAddr = "192.0.2.2:6881".parse().unwrap();
+        let c: SocketAddr = "192.0.2.3:6881".parse().unwrap();
+        dead.mark(a, start);
+        dead.mark(b, start);
+        // `a` failing again makes `b` the least recent.
+        dead.mark(a, start + Duration::from_secs(1));
+        dead.mark(c, start + Duration::from_secs(2));
+        assert!(dead.is_dead(&a, start + Duration::from_secs(2)));
+        assert!(!dead.is_dead(&b, start + Duration::from_secs(2)));
+        assert!(dead.is_dead(&c, start + Duration::from_secs(2)));
+        assert_eq!(dead.count(), 2);
+        assert!(!dead.is_dead(&a, start + Duration::from_secs(1) + DEAD_FOR));
+    }
+}
@@ -0,0 +1,1075 @@
+use crate::routing::RoutingTable;
+use crate::{config::Config, AppState};
+use anyhow::Context;
+use rbit::peer::PeerId;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant, interval, timeout};
+
+use crate::socks5::{Socks5Config, Socks5UdpAssociate};
+
+// Shared DHT client for peer lookups (enrichment and seeder re-checks).
+//
+// One long-lived node: a pair of UDP sockets (or one SOCKS5 UDP association), a receive
+// task that hands each response to the lookup whose transaction id it carries, and a
+// routing table filled from every response. Lookups start from the known nodes closest to
+// the info hash instead of re-bootstrapping from the routers, so most finish in a few
+// round trips. The spider feeds the nodes that answer it into the same table, and the
+// table is saved to `dht_nodes.dat` in the data dir, so lookups after a restart don't
+// start from the routers either.
+//
+// The spider's own queries (`find_node`, `sample_infohashes`) don't go through here: they
+// have to come from its listening sockets and node ids, so the nodes it asks add it to
+// their routing tables and send it the `get_peers`/`announce_peer` traffic it harvests.
+
+/// Nodes kept in the shared table.
+const MAX_KNOWN_NODES: usize = 5000;
+/// Closest known nodes (per address family) a lookup starts from.
+const LOOKUP_SEEDS: usize = 16;
+/// Closest nodes that answered a lookup which we then announce ourselves to.
+const ANNOUNCE_TO: usize = 8;
+const MAINTAIN_EVERY: Duration = Duration::from_secs(60);
+/// How often the routing table is written to disk.
+const SAVE_EVERY: Duration = Duration::from_secs(5 * 60);
+pub const FILE_NAME: &str = "dht_nodes.dat";
+
+#[derive(Clone)]
+pub struct Dht {
+    inner: Arc<DhtInner>,
+}
+
+struct DhtInner {
+    node_id: [u8; 20],
+    /// Set once `run` has its sockets; lookups fail until then.
+    transport: OnceLock<DhtTransport>,
+    table: Mutex<RoutingTable>,
+    /// Lookups waiting for responses, by transaction id.
+    pending: Mutex<HashMap<[u8; 2], mpsc::UnboundedSender<Vec<u8>>>>,
+    next_tx: AtomicU16,
+}
+
+impl Default for Dht {
+    fn default() -> Self {
+        Self::with_table(RoutingTable::new(*PeerId::generate().as_bytes(), MAX_KNOWN_NODES))
+    }
+}
+
+impl Dht {
+    /// The shared node, with the routing table (and node id) saved at `path` if there is one.
+    pub fn load(path: &Path) -> Self {
+        match RoutingTable::load(path, MAX_KNOWN_NODES) {
+            Ok(table) => {
+                tracing::info!(nodes = table.len(), live = table.live_len(), "dht: loaded routing table");
+                Self::with_table(table)
+            }
+            Err(err) => {
+                if path.exists() {
+                    tracing::warn!(%err, "dht: failed loading routing table; starting fresh");
+                }
+                Self::default()
+            }
+        }
+    }
+
+    fn with_table(table: RoutingTable) -> Self {
+        Self {
+            inner: Arc::new(DhtInner {
+                node_id: *table.own_id(),
+                transport: OnceLock::new(),
+                table: Mutex::new(table),
+                pending: Mutex::new(HashMap::new()),
+                next_tx: AtomicU16::new(0),
+            }),
+        }
+    }
+
+    fn save(&self, path: &Path) {
+        let table = self.table();
+        match table.save(path) {
+            Ok(()) => tracing::debug!(nodes = table.len(), "dht: saved routing table"),
+            Err(err) => tracing::warn!(%err, "dht: failed saving routing table"),
+        }
+    }
+}
+
+/// Opens the shared node's sockets and dispatches responses until shutdown.
+pub async fn run(state: AppState) {
+    let dht = &state.dht;
+    let transport = match Socks5Config::from_env() {
+        Some(Ok(cfg)) => {
+            let Some(sock) = Socks5UdpAssociate::connect_with_backoff(&cfg, &state.shutdown, "dht").await else {
+                return;
+            };
+            DhtTransport::Socks { sock }
+        }
+        Some(Err(err)) => {
+            tracing::warn!(%err, "dht: invalid SERMA_SOCKS5_PROXY; peer lookups disabled");
+            return;
+        }
+        None => {
+            // Use separate IPv4 + (optional) IPv6 UDP sockets so we can talk to both
+            // families regardless of OS IPv6 dual-stack settings.
+            let socket_v4 = match UdpSocket::bind("0.0.0.0:0").await {
+                Ok(s) => s,
+                Err(err) => {
+                    tracing::warn!(%err, "dht: failed to bind UDP socket; peer lookups disabled");
+                    return;
+                }
+            };
+            let socket_v6 = match UdpSocket::bind("[::]:0").await {
+                Ok(s) => Some(s),
+                Err(err) => {
+                    tracing::debug!(%err, "dht: ipv6 udp bind failed; continuing with ipv4 only");
+                    None
+                }
+            };
+            DhtTransport::Direct { socket_v4, socket_v6 }
+        }
+    };
+    let transport = dht.inner.transport.get_or_init(|| transport);
+
+    let mut buf4 = vec![0u8; 4096];
+    let mut buf6 = vec![0u8; 4096];
+    let mut maintain = interval(MAINTAIN_EVERY);
+    let table_path = state.data_dir.join(FILE_NAME);
+    let mut last_save = Instant::now();
+    loop {
+        tokio::select! {
+            _ = state.shutdown.wait() => {
+                dht.save(&table_path);
+                return;
+            }
+            _ = maintain.tick() => {
+                {
+                    let mut table = dht.table();
+                    table.maintain();
+                    tracing::debug!(nodes = table.len(), live = table.live_len(), "dht: routing table");
+                }
+                if last_save.elapsed() >= SAVE_EVERY {
+                    last_save = Instant::now();
+                    dht.save(&table_path);
+                }
+            }
+            recv = dht_recv(transport, &mut buf4, &mut buf6) => {
+                let Some((n, from, fam)) = recv else {
+                    continue;
+                };
+                let raw = if fam == 4 { &buf4[..n] } else { &buf6[..n] };
+                dht.handle_response(raw, from);
+            }
+        }
+    }
+}
+
+impl Dht {
+    fn table(&self) -> MutexGuard<'_, RoutingTable> {
+        self.inner.table.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashMap<[u8; 2], mpsc::UnboundedSender<Vec<u8>>>> {
+        self.inner.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A node that answered someone else's query (the spider's); lookups may start from it.
+    pub fn add_node(&self, id: [u8; 20], addr: SocketAddr) {
+        if filter_addr(addr) {
+            self.table().heard_from(id, addr, true);
+        }
+    }
+
+    /// Learns from a response, then passes it to the lookup waiting for it.
+    fn handle_response(&self, raw: &[u8], from: SocketAddr) {
+        let Some(resp) = KrpcResponse::decode(raw) else {
+            return;
+        };
+        {
+            let mut table = self.table();
+            if let Some(id) = resp.id
+                && filter_addr(from)
+            {
+                table.heard_from(id, from, true);
+            }
+            let nodes = resp.nodes.map(parse_compact_nodes_v4).unwrap_or_default();
+            let nodes6 = resp.nodes6.map(parse_compact_nodes_v6).unwrap_or_default();
+            for node in nodes.into_iter().chain(nodes6) {
+                if filter_addr(node.addr) {
+                    table.add_candidate(node.id, node.addr);
+                }
+            }
+        }
+        if let Some(waiting) = self.pending().get(&resp.tx) {
+            let _ = waiting.send(raw.to_vec());
+        }
+    }
+
+    /// A transaction id no running lookup is using.
+    fn register(&self, sender: &mpsc::UnboundedSender<Vec<u8>>) -> [u8; 2] {
+        let mut pending = self.pending();
+        loop {
+            let tx = self.inner.next_tx.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+            if let std::collections::hash_map::Entry::Vacant(slot) = pending.entry(tx) {
+                slot.insert(sender.clone());
+                return tx;
+            }
+        }
+    }
+
+    /// Iterative `get_peers` lookup for `info_hash`, with BEP-33 scrape filters. With an
+    /// `announce_port`, we then announce ourselves as a peer on that TCP port to the closest
+    /// nodes that answered (BEP-5 `announce_peer`), so peers looking for it connect to us.
+    #[tracing::instrument(name = "dht_get_peers", skip(self, cfg, info_hash), fields(info_hash = %hex::encode(info_hash)))]
+    pub async fn get_peers(&self, cfg: &Config, info_hash: [u8; 20], announce_port: Option<u16>) -> anyhow::Result<DhtLookup> {
+        let transport = self.inner.transport.get().context("dht: not connected yet")?;
+
+        // Prefer nodes closer (XOR-distance) to the target infohash.
+        // Store a min-heap by using Reverse(distance).
+        let mut q: BinaryHeap<(Reverse<[u8; 20]>, SocketAddr)> = BinaryHeap::new();
+        let mut seen_nodes: HashSet<SocketAddr> = HashSet::new();
+        let (known, live) = {
+            let table = self.table();
+            let mut known = table.closest(&info_hash, LOOKUP_SEEDS, SocketAddr::from(([0, 0, 0, 0], 0)));
+            known.extend(table.closest(&info_hash, LOOKUP_SEEDS, SocketAddr::from(([0u16; 8], 0))));
+            (known, table.live_len())
+        };
+        for (id, addr) in known {
+            push_node(DhtNode { id, addr }, &info_hash, &mut q, &mut seen_nodes);
+        }
+        // Until the table has filled up, ask the routers too.
+        if live < crate::routing::K {
+            for addr in resolve_bootstrap(cfg).await {
+                push_node_seed(addr, &mut q, &mut seen_nodes);
+            }
+        }
+        if q.is_empty() {
+            anyhow::bail!("no DHT nodes known and no bootstrap nodes resolved");
+        }
+
+        let mut peers: Vec<SocketAddr> = Vec::new();
+        let mut seen_peers: HashSet<SocketAddr> = HashSet::new();
+        let mut scrape = DhtScrape::default();
+        let mut queries = 0usize;
+        // (distance, node, token) of nodes that answered with a write token.
+        let mut tokens: Vec<([u8; 20], SocketAddr, Vec<u8>)> = Vec::new();
+
+        // Responses for this lookup arrive on `rx`; the guard unregisters its transaction
+        // ids however the lookup ends (including the caller's timeout dropping it).
+        let (sender, mut rx) = mpsc::unbounded_channel();
+        let mut inflight = Inflight {
+            dht: self,
+            sent: HashMap::new(),
+        };
+        let max_inflight: usize = cfg.enrich_dht_inflight;
+
+        // Bound total time spent per hash lookup (outer timeout still applies too).
+        let overall_deadline = Instant::now() + Duration::from_secs(cfg.enrich_dht_overall_deadline_secs);
+
+        while Instant::now() < overall_deadline {
+            if peers.len() >= cfg.enrich_peers_per_hash {
+                break;
+            }
+            if queries >= cfg.enrich_dht_max_queries_per_hash {
+                break;
+            }
+
+            // Reap timed-out inflight requests.
+            let query_timeout = Duration::from_millis(cfg.enrich_dht_query_timeout_ms);
+            inflight.reap(query_timeout);
+
+            // Fill the inflight window.
+            while inflight.sent.len() < max_inflight
+                && queries < cfg.enrich_dht_max_queries_per_hash
+                && peers.len() < cfg.enrich_peers_per_hash
+            {
+                let Some((_, addr)) = q.pop() else { break };
+                let txid = self.register(&sender);
+                let msg = make_get_peers(txid, &self.inner.node_id, &info_hash);
+                self.table().note_queried(&addr);
+                let _ = dht_send(transport, &msg, addr).await;
+                inflight.sent.insert(txid, (Instant::now(), addr));
+                queries += 1;
+            }
+
+            if inflight.sent.is_empty() && q.is_empty() {
+                break;
+            }
+
+            // Process responses; use a short receive timeout to keep the loop responsive.
+            let Ok(Some(raw)) = timeout(Duration::from_millis(cfg.enrich_dht_recv_timeout_ms), rx.recv()).await
+            else {
+                continue;
+            };
+            let Some(resp) = KrpcResponse::decode(&raw) else {
+                continue;
+            };
+            let Some(from) = inflight.finish(&resp.tx) else {
+                continue;
+            };
+            if let (Some(id), Some(token)) = (resp.id, resp.token) {
+                tokens.push((xor_distance(&id, &info_hash), from, token.to_vec()));
+            }
+
+            scrape.merge(resp.seeds_filter, resp.peers_filter);
+
+            if let Some(nodes) = resp.nodes {
+                for node in parse_compact_nodes_v4(nodes) {
+                    push_node(node, &info_hash, &mut q, &mut seen_nodes);
+                }
+            }
+            if let Some(nodes6) = resp.nodes6 {
+                for node in parse_compact_nodes_v6(nodes6) {
+                    push_node(node, &info_hash, &mut q, &mut seen_nodes);
+                }
+            }
+            // Peers of a family the fetch won't use don't count towards the limit.
+            let family = cfg.enrich_peer_family;
+            if let Some(values) = resp.values {
+                for v in values {
+                    if let Some(peer) = parse_compact_peer_v4(&v)
+                        && family.allows(&peer)
+                        && seen_peers.insert(peer)
+                    {
+                        peers.push(peer);
+                        if peers.len() >= cfg.enrich_peers_per_hash {
+                            break;
+                        }
+                    }
+                }
+            }
+            if let Some(values6) = resp.values6 {
+                for v in values6 {
+                    if let Some(peer) = parse_compact_peer_v6(&v)
+                        && family.allows(&peer)
+                        && seen_peers.insert(peer)
+                    {
+                        peers.push(peer);
+                        if peers.len() >= cfg.enrich_peers_per_hash {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(port) = announce_port {
+            tokens.sort_by_key(|(distance, _, _)| *distance);
+            for (_, addr, token) in tokens.into_iter().take(ANNOUNCE_TO) {
+                // Fire and forget: nobody waits for the answer.
+                let txid = self.inner.next_tx.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+                let msg = make_announce_peer(txid, &self.inner.node_id, &info_hash, port, &token);
+                let _ = dht_send(transport, &msg, addr).await;
+            }
+        }
+
+        Ok(DhtLookup { peers, scrape })
+    }
+}
+
+/// A lookup's outstanding queries: transaction id -> (sent at, node).
+struct Inflight<'a> {
+    dht: &'a Dht,
+    sent: HashMap<[u8; 2], (Instant, SocketAddr)>,
+}
+
+impl Inflight<'_> {
+    fn reap(&mut self, query_timeout: Duration) {
+        let now = Instant::now();
+        let mut pending = self.dht.pending();
+        self.sent.retain(|tx, (sent_at, _)| {
+            let alive = now.saturating_duration_since(*sent_at) <= query_timeout;
+            if !alive {
+                pending.remove(tx);
+            }
+            alive
+        });
+    }
+
+    /// Only responses for txids we sent count, and only once. Returns the node asked.
+    fn finish(&mut self, tx: &[u8; 2]) -> Option<SocketAddr> {
+        let (_, addr) = self.sent.remove(tx)?;
+        self.dht.pending().remove(tx);
+        Some(addr)
+    }
+}
+
+impl Drop for Inflight<'_> {
+    fn drop(&mut self) {
+        let mut pending = self.dht.pending();
+        for tx in self.sent.keys() {
+            pending.remove(tx);
+        }
+    }
+}
+
+/// Which peers the metadata fetch uses (SERMA_ENRICH_PEER_FAMILY).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PeerFamily {
+    #[default]
+    Any,
+    /// Both, IPv4 peers tried first.
+    PreferV4,
+    /// Both, IPv6 peers tried first.
+    PreferV6,
+    V4Only,
+    V6Only,
+}
+
+impl PeerFamily {
+    pub fn allows(self, peer: &SocketAddr) -> bool {
+        match self {
+            PeerFamily::V4Only => peer.is_ipv4(),
+            PeerFamily::V6Only => peer.is_ipv6(),
+            _ => true,
+        }
+    }
+
+    /// Sort key: peers of the preferred family first.
+    pub fn rank(self, peer: &SocketAddr) -> u8 {
+        match self {
+            PeerFamily::PreferV4 => u8::from(!peer.is_ipv4()),
+            PeerFamily::PreferV6 => u8::from(!peer.is_ipv6()),
+            _ => 0,
+        }
+    }
+}
+
+impl std::str::FromStr for PeerFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "any" => Ok(PeerFamily::Any),
+            "prefer-v4" | "prefer-ipv4" => Ok(PeerFamily::PreferV4),
+            "prefer-v6" | "prefer-ipv6" => Ok(PeerFamily::PreferV6),
+            "v4" | "ipv4" => Ok(PeerFamily::V4Only),
+            "v6" | "ipv6" => Ok(PeerFamily::V6Only),
+            _ => Err("expected any, prefer-v4, prefer-v6, v4 or v6".to_string()),
+        }
+    }
+}
+
+/// What a DHT `get_peers` lookup found.
+pub struct DhtLookup {
+    pub peers: Vec<SocketAddr>,
+    pub scrape: DhtScrape,
+}
+
+/// BEP-33 scrape: every node that stores peers for the torrent returns bloom filters of
+/// the seeds and downloaders it knows; OR-ing them over all answers and counting set bits
+/// estimates the swarm without contacting a single peer.
+#[derive(Default)]
+pub struct DhtScrape {
+    seeds: Option<[u8; BEP33_FILTER_BYTES]>,
+    peers: Option<[u8; BEP33_FILTER_BYTES]>,
+}
+
+const BEP33_FILTER_BYTES: usize = 256;
+
+impl DhtScrape {
+    fn merge(&mut self, seeds: Option<&[u8]>, peers: Option<&[u8]>) {
+        for (acc, filter) in [(&mut self.seeds, seeds), (&mut self.peers, peers)] {
+            if let Some(filter) = filter
+                && filter.len() == BEP33_FILTER_BYTES
+            {
+                let acc = acc.get_or_insert([0; BEP33_FILTER_BYTES]);
+                for (a, b) in acc.iter_mut().zip(filter) {
+                    *a |= b;
+                }
+            }
+        }
+    }
+
+    /// Estimated seeders and leechers, if any node sent filters.
+    pub fn estimate(&self) -> Option<crate::tracker_udp::SwarmStats> {
+        if self.seeds.is_none() && self.peers.is_none() {
+            return None;
+        }
+        Some(crate::tracker_udp::SwarmStats {
+            seeders: self.seeds.as_ref().map_or(0, bloom_size_estimate),
+            leechers: self.peers.as_ref().map_or(0, bloom_size_estimate),
+            completed: 0,
+        })
+    }
+}
+
+/// BEP-33 size estimate for a 2048-bit filter with two hash functions:
+/// ln(c / m) / (k * ln(1 - 1 / m)), c being the number of zero bits.
+fn bloom_size_estimate(filter: &[u8; BEP33_FILTER_BYTES]) -> u32 {
+    let m = (BEP33_FILTER_BYTES * 8) as f64;
+    let ones: u32 = filter.iter().map(|b| b.count_ones()).sum();
+    // A full filter would be ln(0); treat it as one zero bit left (about 7,800 peers).
+    let zeros = (m - f64::from(ones)).max(1.0);
+    ((zeros / m).ln() / (2.0 * (1.0 - 1.0 / m).ln())).round() as u32
+}
+
+enum DhtTransport {
+    Direct {
+        socket_v4: UdpSocket,
+        socket_v6: Option<UdpSocket>,
+    },
+    Socks {
+        sock: Socks5UdpAssociate,
+    },
+}
+
+async fn dht_send(
+    transport: &DhtTransport,
+    msg: &[u8],
+    addr: SocketAddr,
+) -> std::io::Result<usize> {
+    match transport {
+        DhtTransport::Direct { socket_v4, socket_v6 } => match addr.ip() {
+            IpAddr::V4(_) => socket_v4.send_to(msg, addr).await,
+            IpAddr::V6(_) => {
+                if let Some(sock6) = socket_v6.as_ref() {
+                    sock6.send_to(msg, addr).await
+                } else {
+                    Ok(0)
+                }
+            }
+        },
+        DhtTransport::Socks { sock } => sock.send_to(msg, addr).await,
+    }
+}
+
+async fn dht_recv(transport: &DhtTransport, buf4: &mut [u8], buf6: &mut [u8]) -> Option<(usize, SocketAddr, u8)> {
+    let (result, fam) = match transport {
+        DhtTransport::Direct { socket_v4, socket_v6 } => {
+            tokio::select! {
+                r = socket_v4.recv_from(buf4) => (r, 4u8),
+                r = async {
+                    if let Some(sock6) = socket_v6.as_ref() {
+                        sock6.recv_from(buf6).await
+                    } else {
+                        // Never resolves when IPv6 socket is unavailable.
+                        std::future::pending::<std::io::Result<(usize, SocketAddr)>>().await
+                    }
+                } => (r, 6u8),
+            }
+        }
+        // Bytes land in buf4; the family tag tells the caller which buffer to read.
+        DhtTransport::Socks { sock } => (sock.recv_from(buf4).await, 4u8),
+    };
+    match result {
+        Ok((n, from)) if n > 0 => Some((n, from, fam)),
+        Ok(_) => None,
+        Err(err) => {
+            tracing::trace!(%err, "dht: recv failed");
+            None
+        }
+    }
+}
+
+async fn resolve_bootstrap(cfg: &Config) -> Vec<SocketAddr> {
+    let mut out = Vec::new();
+    for host in cfg.enrich_dht_bootstrap.iter() {
+        match tokio::net::lookup_host(host).await {
+            Ok(iter) => {
+                for addr in iter {
+                    out.push(addr);
+                }
+            }
+            Err(err) => {
+                tracing::debug!(%err, host=%host, "dht: bootstrap resolve failed");
+            }
+        }
+    }
+    out
+}
+
+#[derive(Clone, Copy)]
+struct DhtNode {
+    id: [u8; 20],
+    addr: SocketAddr,
+}
+
+fn xor_distance(a: &[u8; 20], b: &[u8; 20]) -> [u8; 20] {
+    let mut out = [0u8; 20];
+    for i in 0..20 {
+        out[i] = a[i] ^ b[i];
+    }
+    out
+}
+
+fn push_node_seed(
+    addr: SocketAddr,
+    q: &mut BinaryHeap<(Reverse<[u8; 20]>, SocketAddr)>,
+    set: &mut HashSet<SocketAddr>,
+) {
+    // Seed nodes don't come with an ID, so just give them highest priority.
+    if !filter_addr(addr) {
+        return;
+    }
+    if set.insert(addr) {
+        q.push((Reverse([0u8; 20]), addr));
+    }
+}
+
+fn push_node(
+    node: DhtNode,
+    target: &[u8; 20],
+    q: &mut BinaryHeap<(Reverse<[u8; 20]>, SocketAddr)>,
+    set: &mut HashSet<SocketAddr>,
+) {
+    if !filter_addr(node.addr) {
+        return;
+    }
+    if set.insert(node.addr) {
+        let dist = xor_distance(&node.id, target);
+        q.push((Reverse(dist), node.addr));
+    }
+}
+
+fn filter_addr(addr: SocketAddr) -> bool {
+    if addr.ip().is_loopback() { return true; }
+    if addr.port() == 0 {
+        return false;
+    }
+    match addr.ip() {
+        IpAddr::V4(v4) => {
+            if v4.is_private() || v4.is_loopback() || v4.is_unspecified() {
+                return false;
+            }
+        }
+        IpAddr::V6(v6) => {
+            if v6.is_loopback() || v6.is_unspecified() || v6.is_unique_local() {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn parse_compact_peer_v4(bytes: &[u8]) -> Option<SocketAddr> {
+    if bytes.len() != 6 {
+        return None;
+    }
+    let ip = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+    let port = u16::from_be_bytes([bytes[4], bytes[5]]);
+    Some(SocketAddr::new(IpAddr::V4(ip), port))
+}
+
+fn parse_compact_peer_v6(bytes: &[u8]) -> Option<SocketAddr> {
+    if bytes.len() != 18 {
+        return None;
+    }
+    let ip = Ipv6Addr::from([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+        bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15],
+    ]);
+    let port = u16::from_be_bytes([bytes[16], bytes[17]]);
+    Some(SocketAddr::new(IpAddr::V6(ip), port))
+}
+
+fn parse_compact_nodes_v4(nodes: &[u8]) -> Vec<DhtNode> {
+    // Compact node info: 26 bytes per node: 20-byte node id + 4-byte IPv4 + 2-byte port.
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i + 26 <= nodes.len() {
+        let mut id = [0u8; 20];
+        id.copy_from_slice(&nodes[i..i + 20]);
+        let ip = Ipv4Addr::new(nodes[i + 20], nodes[i + 21], nodes[i + 22], nodes[i + 23]);
+        let port = u16::from_be_bytes([nodes[i + 24], nodes[i + 25]]);
+        out.push(DhtNode {
+            id,
+            addr: SocketAddr::new(IpAddr::V4(ip), port),
+        });
+        i += 26;
+    }
+    out
+}
+
+fn parse_compact_nodes_v6(nodes: &[u8]) -> Vec<DhtNode> {
+    // nodes6: 38 bytes per node: 20-byte node id + 16-byte IPv6 + 2-byte port.
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i + 38 <= nodes.len() {
+        let mut id = [0u8; 20];
+        id.copy_from_slice(&nodes[i..i + 20]);
+        let ip = Ipv6Addr::from([
+            nodes[i + 20],
+            nodes[i + 21],
+            nodes[i + 22],
+            nodes[i + 23],
+            nodes[i + 24],
+            nodes[i + 25],
+            nodes[i + 26],
+            nodes[i + 27],
+            nodes[i + 28],
+            nodes[i + 29],
+            nodes[i + 30],
+            nodes[i + 31],
+            nodes[i + 32],
+            nodes[i + 33],
+            nodes[i + 34],
+            nodes[i + 35],
+        ]);
+        let port = u16::from_be_bytes([nodes[i + 36], nodes[i + 37]]);
+        out.push(DhtNode {
+            id,
+            addr: SocketAddr::new(IpAddr::V6(ip), port),
+        });
+        i += 38;
+    }
+    out
+}
+
+fn make_get_peers(tx: [u8; 2], id: &[u8; 20], info_hash: &[u8; 20]) -> Vec<u8> {
+    // d1:ad2:id20:<id>9:info_hash20:<info>6:scrapei1e4:wantl2:n42:n6ee1:q9:get_peers1:t2:<tx>1:y1:qe
+    // scrape=1 asks nodes holding peers for the BEP-33 seed/peer bloom filters too; want
+    // (BEP-32) asks for closer nodes of both families, so IPv6 nodes (and their IPv6
+    // peers) are found over IPv4 too.
+    let mut out = Vec::with_capacity(150);
+    out.push(b'd');
+
+    benc_key(&mut out, b"a");
+    out.push(b'd');
+    benc_key(&mut out, b"id");
+    benc_bytes(&mut out, id);
+    benc_key(&mut out, b"info_hash");
+    benc_bytes(&mut out, info_hash);
+    benc_key(&mut out, b"scrape");
+    out.extend_from_slice(b"i1e");
+    benc_key(&mut out, b"want");
+    out.push(b'l');
+    benc_bytes(&mut out, b"n4");
+    benc_bytes(&mut out, b"n6");
+    out.push(b'e');
+    out.push(b'e');
+
+    benc_key(&mut out, b"q");
+    benc_bytes(&mut out, b"get_peers");
+
+    benc_key(&mut out, b"t");
+    benc_bytes(&mut out, &tx);
+
+    benc_key(&mut out, b"y");
+    benc_bytes(&mut out, b"q");
+
+    out.push(b'e');
+    out
+}
+
+fn make_announce_peer(tx: [u8; 2], id: &[u8; 20], info_hash: &[u8; 20], port: u16, token: &[u8]) -> Vec<u8> {
+    // d1:ad2:id20:<id>12:implied_porti0e9:info_hash20:<info>4:porti<port>e5:token<len>:<token>e1:q13:announce_peer1:t2:<tx>1:y1:qe
+    let mut out = Vec::with_capacity(160 + token.len());
+    out.push(b'd');
+
+    benc_key(&mut out, b"a");
+    out.push(b'd');
+    benc_key(&mut out, b"id");
+    benc_bytes(&mut out, id);
+    benc_key(&mut out, b"implied_port");
+    out.extend_from_slice(b"i0e");
+    benc_key(&mut out, b"info_hash");
+    benc_bytes(&mut out, info_hash);
+    benc_key(&mut out, b"port");
+    out.extend_from_slice(format!("i{port}e").as_bytes());
+    benc_key(&mut out, b"token");
+    benc_bytes(&mut out, token);
+    out.push(b'e');
+
+    benc_key(&mut out, b"q");
+    benc_bytes(&mut out, b"announce_peer");
+
+    benc_key(&mut out, b"t");
+    benc_bytes(&mut out, &tx);
+
+    benc_key(&mut out, b"y");
+    benc_bytes(&mut out, b"q");
+
+    out.push(b'e');
+    out
+}
+
+fn benc_key(out: &mut Vec<u8>, key: &[u8]) {
+    benc_bytes(out, key);
+}
+
+fn benc_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    itoa_len(out, bytes.len());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+fn itoa_len(out: &mut Vec<u8>, n: usize) {
+    let mut buf = [0u8; 20];
+    let mut i = buf.len();
+    let mut x = n;
+    if x == 0 {
+        out.push(b'0');
+        return;
+    }
+    while x > 0 {
+        i -= 1;
+        buf[i] = b'0' + (x % 10) as u8;
+        x /= 10;
+    }
+    out.extend_from_slice(&buf[i..]);
+}
+
+struct KrpcResponse<'a> {
+    tx: [u8; 2],
+    /// The responding node's id.
+    id: Option<[u8; 20]>,
+    nodes: Option<&'a [u8]>,
+    nodes6: Option<&'a [u8]>,
+    values: Option<Vec<Vec<u8>>>,
+    values6: Option<Vec<Vec<u8>>>,
+    /// BEP-33 bloom filters of seeds and of downloading peers.
+    seeds_filter: Option<&'a [u8]>,
+    peers_filter: Option<&'a [u8]>,
+    /// Write token for a later `announce_peer`.
+    token: Option<&'a [u8]>,
+}
+
+impl<'a> KrpcResponse<'a> {
+    fn decode(raw: &'a [u8]) -> Option<Self> {
+        if raw.first().copied()? != b'd' {
+            return None;
+        }
+        let y = benc_get_bytes(raw, b"y")?;
+        if y != b"r" {
+            return None;
+        }
+        let t = benc_get_bytes(raw, b"t")?;
+        if t.len() != 2 {
+            return None;
+        }
+        let mut tx = [0u8; 2];
+        tx.copy_from_slice(t);
+
+        let r = benc_get_dict(raw, b"r")?;
+        let id = benc_get_bytes(r, b"id").and_then(|id| id.try_into().ok());
+        let nodes = benc_get_bytes(r, b"nodes");
+        let nodes6 = benc_get_bytes(r, b"nodes6");
+        let values = benc_get_list_bytes(r, b"values");
+        let values6 = benc_get_list_bytes(r, b"values6");
+        let seeds_filter = benc_get_bytes(r, b"BFsd");
+        let peers_filter = benc_get_bytes(r, b"BFpe");
+        let token = benc_get_bytes(r, b"token");
+
+        Some(Self {
+            tx,
+            id,
+            nodes,
+            nodes6,
+            values,
+            values6,
+            seeds_filter,
+            peers_filter,
+            token,
+        })
+    }
+}
+
+// ------------------------------
+// Minimal bencode “dict-getter”
+// ------------------------------
+
+fn benc_get_bytes<'a>(raw: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let dict = BencParser::new(raw).parse_dict()?;
+    dict.get_bytes(key)
+}
+
+fn benc_get_dict<'a>(raw: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let dict = BencParser::new(raw).parse_dict()?;
+    dict.get_dict_slice(key)
+}
+
+fn benc_get_list_bytes(raw: &[u8], key: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let dict = BencParser::new(raw).parse_dict()?;
+    dict.get_list_bytes(key)
+}
+
+struct BencDict<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> BencDict<'a> {
+    fn get_bytes(&self, key: &[u8]) -> Option<&'a [u8]> {
+        let mut p = BencParser::new(self.raw);
+        p.expect_byte(b'd')?;
+        loop {
+            if p.peek()? == b'e' {
+                return None;
+            }
+            let k = p.parse_bytes()?;
+            match p.peek()? {
+                b'0'..=b'9' => {
+                    let bytes = p.parse_bytes()?;
+                    if k == key {
+                        return Some(bytes);
+                    }
+                }
+                b'd' | b'l' | b'i' => {
+                    p.skip_value()?;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn get_dict_slice(&self, key: &[u8]) -> Option<&'a [u8]> {
+        let mut p = BencParser::new(self.raw);
+        p.expect_byte(b'd')?;
+        loop {
+            if p.peek()? == b'e' {
+                return None;
+            }
+            let k = p.parse_bytes()?;
+            let v_start = p.pos;
+            if p.peek()? != b'd' {
+                p.skip_value()?;
+                continue;
+            }
+            p.skip_value()?;
+            let v_end = p.pos;
+            if k == key {
+                return self.raw.get(v_start..v_end);
+            }
+        }
+    }
+
+    fn get_list_bytes(&self, key: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let mut p = BencParser::new(self.raw);
+        p.expect_byte(b'd')?;
+        loop {
+            if p.peek()? == b'e' {
+                return None;
+            }
+            let k = p.parse_bytes()?;
+            if p.peek()? != b'l' {
+                p.skip_value()?;
+                continue;
+            }
+
+            // List value.
+            p.expect_byte(b'l')?;
+            let mut out: Vec<Vec<u8>> = Vec::new();
+            while p.peek()? != b'e' {
+                match p.peek()? {
+                    b'0'..=b'9' => {
+                        let b = p.parse_bytes()?;
+                        out.push(b.to_vec());
+                    }
+                    _ => {
+                        p.skip_value()?;
+                    }
+                }
+            }
+            p.expect_byte(b'e')?;
+            if k == key {
+                return Some(out);
+            }
+        }
+    }
+}
+
+struct BencParser<'a> {
+    raw: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BencParser<'a> {
+    fn new(raw: &'a [u8]) -> Self {
+        Self { raw, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.raw.get(self.pos).copied()
+    }
+
+    fn expect_byte(&mut self, b: u8) -> Option<()> {
+        if self.peek()? != b {
+            return None;
+        }
+        self.pos += 1;
+        Some(())
+    }
+
+    fn parse_dict(mut self) -> Option<BencDict<'a>> {
+        if self.peek()? != b'd' {
+            return None;
+        }
+        let start = self.pos;
+        self.skip_value()?;
+        let end = self.pos;
+        Some(BencDict {
+            raw: self.raw.get(start..end)?,
+        })
+    }
+
+    fn parse_bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.parse_usize()?;
+        self.expect_byte(b':')?;
+        let start = self.pos;
+        let end = self.pos.checked_add(len)?;
+        let out = self.raw.get(start..end)?;
+        self.pos = end;
+        Some(out)
+    }
+
+    fn parse_usize(&mut self) -> Option<usize> {
+        let mut n: usize = 0;
+        let mut saw = false;
+        while let Some(b) = self.peek() {
+            if !b.is_ascii_digit() {
+                break;
+            }
+            saw = true;
+            n = n.checked_mul(10)? + (b - b'0') as usize;
+            self.pos += 1;
+        }
+        if !saw { None } else { Some(n) }
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'i' => {
+                self.pos += 1;
+                while self.peek()? != b'e' {
+                    self.pos += 1;
+                    if self.pos >= self.raw.len() {
+                        return None;
+                    }
+                }
+                self.pos += 1;
+                Some(())
+            }
+            b'l' => {
+                self.pos += 1;
+                while self.peek()? != b'e' {
+                    self.skip_value()?;
+                }
+                self.pos += 1;
+                Some(())
+            }
+            b'd' => {
+                self.pos += 1;
+                while self.peek()? != b'e' {
+                    self.parse_bytes()?;
+                    self.skip_value()?;
+                }
+                self.pos += 1;
+                Some(())
+            }
+            b'0'..=b'9' => {
+                let len = self.parse_usize()?;
+                self.expect_byte(b':')?;
+                self.pos = self.pos.checked_add(len)?;
+                if self.pos > self.raw.len() {
+                    return None;
+                }
+                Some(())
+            }
+            _ => None,
+        }
+    }
+}
+
@@ -1,111 +1,361 @@
-use crate::{config::Config, AppState, storage};
+use crate::dht::DhtLookup;
+use crate::events::Event;
+use crate::{control::Subsystem, AppState, storage};
 use anyhow::Context;
 use base64::Engine as _;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt as _;
 use rbit::bencode;
-use rbit::metainfo::{InfoHash, MagnetLink};
+use rbit::metainfo::{InfoHashV1, InfoHashV2};
 use rbit::peer::{
-    ExtensionHandshake, ExtensionMessage, METADATA_PIECE_SIZE, Message, MetadataMessage,
-    MetadataMessageType, PeerConnection, PeerId, metadata_piece_size,
+    ExtensionHandshake, ExtensionMessage, Handshake, METADATA_PIECE_SIZE, Message, MetadataMessage,
+    MetadataMessageType, PeerError, PeerId, metadata_piece_size,
 };
-use rbit::tracker::{AnnounceParams, TrackerClient, TrackerEvent};
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap, HashSet};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
-use std::sync::Arc;
-use tokio::net::UdpSocket;
-use tokio::sync::Semaphore;
+use crate::dead_peers::DeadPeers;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::net::TcpStream;
+use tokio::sync::{Semaphore, SemaphorePermit};
 use tokio::time::{Duration, timeout};
 
-use crate::socks5::{Socks5Config, Socks5UdpAssociate};
+use crate::socks5::Socks5Config;
+use crate::stats::{EnrichStage, Stats};
+
+/// Metadata fetching with everything it depends on, for embedders: the shared DHT node
+/// that lookups go through, the listener for inbound peers (SERMA_ENRICH_LISTEN), the
+/// enrich queue workers, tracker scrapes and seeder re-checks.
+pub struct Enricher {
+    state: AppState,
+}
+
+impl Enricher {
+    pub fn new(state: &AppState) -> Self {
+        Self { state: state.clone() }
+    }
+
+    /// Runs until `state.shutdown` is triggered.
+    pub async fn run(self) {
+        // Own tasks, so the DHT node's receive loop never waits on a busy worker.
+        let dht = tokio::spawn(crate::dht::run(self.state.clone()));
+        let inbound = tokio::spawn(crate::inbound::run(self.state.clone()));
+        run(self.state).await;
+        let _ = tokio::join!(dht, inbound);
+    }
+}
 
 pub async fn run(state: AppState) {
-    let tracker = Arc::new(TrackerClient::new());
-    let sem = Arc::new(Semaphore::new(state.config.enrich_max_concurrent));
+    let tracker = match crate::scrape::http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%err, "enrich: failed creating HTTP client; disabled");
+            return;
+        }
+    };
+    let scrape = tokio::spawn(crate::scrape::run(state.clone()));
+    let recheck = tokio::spawn(recheck_loop(state.clone(), tracker.clone()));
+    // Resized between batches when `enrich_max_concurrent` is changed at runtime.
+    let mut settings = state.settings.subscribe();
+    let mut capacity = settings.borrow_and_update().enrich_max_concurrent;
+    let sem = Arc::new(Semaphore::new(capacity));
+    let stages = Arc::new(Stages::new(&state.config));
+    // Shared by all lookups: a dead peer found by one is skipped by the others.
+    let dead_peers = Arc::new(Mutex::new(DeadPeers::new(state.config.enrich_dead_peers)));
+    let retry = storage::RetryPolicy {
+        base_ms: (state.config.enrich_retry_base_secs * 1000) as i64,
+        max_ms: (state.config.enrich_retry_max_secs * 1000) as i64,
+        max_attempts: state.config.enrich_max_attempts,
+    };
 
-    loop {
-        let missing = match storage::list_missing_info(&state.db, state.config.enrich_missing_scan_limit) {
+    let shutdown = &state.shutdown;
+    while !shutdown.is_triggered() {
+        if settings.has_changed().unwrap_or(false) {
+            let wanted = settings.borrow_and_update().enrich_max_concurrent;
+            resize(&sem, capacity, wanted);
+            capacity = wanted;
+        }
+        if state.control.is_paused(Subsystem::Enrich) {
+            shutdown.sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+
+        let limit = state.config.enrich_missing_scan_limit;
+        if let Err(err) = storage::release_due_enrich_retries(&state.db, limit) {
+            tracing::warn!(%err, "enrich: failed reading retry queue");
+        }
+        let missing = match storage::take_missing_info(&state.db, limit, &retry) {
             Ok(v) => v,
             Err(err) => {
                 tracing::warn!(%err, "enrich: failed scanning sled");
-                tokio::time::sleep(Duration::from_secs(5)).await;
+                shutdown.sleep(Duration::from_secs(5)).await;
                 continue;
             }
         };
 
         if missing.is_empty() {
-            tokio::time::sleep(Duration::from_secs(5)).await;
+            shutdown.sleep(Duration::from_secs(5)).await;
             continue;
         }
 
         for record in missing {
-            let permit = match sem.clone().acquire_owned().await {
-                Ok(p) => p,
-                Err(_) => break,
+            let permit = tokio::select! {
+                permit = sem.clone().acquire_owned() => match permit {
+                    Ok(p) => p,
+                    Err(_) => break,
+                },
+                _ = shutdown.wait() => break,
             };
 
             let state = state.clone();
             let tracker = tracker.clone();
+            let dead_peers = dead_peers.clone();
+            let stages = stages.clone();
 
             tokio::spawn(async move {
                 let _permit = permit;
-                if let Err(err) = enrich_one(&state, &tracker, record).await {
-                    tracing::debug!(%err, "enrich: failed");
-                }
+                state.stats.enrich_started();
+                let fetched = match enrich_one(&state, &tracker, &dead_peers, &stages, record).await {
+                    Ok(fetched) => fetched,
+                    Err(err) => {
+                        tracing::debug!(%err, "enrich: failed");
+                        false
+                    }
+                };
+                state.stats.enrich_finished(fetched);
             });
         }
 
-        tokio::time::sleep(Duration::from_secs(2)).await;
+        shutdown.sleep(Duration::from_secs(2)).await;
     }
+
+    // Holding every permit means all in-flight lookups have finished and persisted.
+    let _ = sem.acquire_many(capacity as u32).await;
+    let _ = tokio::join!(scrape, recheck);
+    tracing::info!("enrich: stopped");
 }
 
+/// Grows or shrinks the lookup pool. Shrinking takes effect as lookups in flight finish.
+fn resize(sem: &Arc<Semaphore>, from: usize, to: usize) {
+    if to > from {
+        sem.add_permits(to - from);
+    } else if to < from {
+        let sem = sem.clone();
+        let surplus = (from - to) as u32;
+        tokio::spawn(async move {
+            if let Ok(permits) = sem.acquire_many_owned(surplus).await {
+                permits.forget();
+            }
+        });
+    }
+    tracing::info!(from, to, "enrich: concurrency changed");
+}
+
+/// Limits on the two steps of a lookup, within the pool of SERMA_ENRICH_MAX_CONCURRENT.
+/// A DHT lookup mostly waits on UDP answers through the shared DHT node, while a metadata
+/// fetch holds up to SERMA_ENRICH_METADATA_INFLIGHT TCP connections, so each gets its own.
+struct Stages {
+    lookups: Semaphore,
+    fetches: Semaphore,
+}
+
+impl Stages {
+    fn new(config: &crate::config::Config) -> Self {
+        Self {
+            lookups: Semaphore::new(config.enrich_max_lookups),
+            fetches: Semaphore::new(config.enrich_max_fetches),
+        }
+    }
+
+    /// Waits for a slot in `stage`, counted as waiting and then running in the stats.
+    async fn enter<'a>(&'a self, stats: &'a Stats, stage: EnrichStage) -> StagePermit<'a> {
+        let sem = match stage {
+            EnrichStage::Lookup => &self.lookups,
+            EnrichStage::Fetch => &self.fetches,
+        };
+        stats.enrich_stage_waiting(stage);
+        let permit = sem.acquire().await.expect("stage semaphores are never closed");
+        stats.enrich_stage_entered(stage);
+        StagePermit { stats, stage, _permit: permit }
+    }
+}
+
+/// A slot in one stage, given back on drop.
+struct StagePermit<'a> {
+    stats: &'a Stats,
+    stage: EnrichStage,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Drop for StagePermit<'_> {
+    fn drop(&mut self) {
+        self.stats.enrich_stage_left(self.stage);
+    }
+}
+
+/// Torrents re-checked per batch, and concurrently.
+const RECHECK_BATCH: usize = 64;
+const RECHECK_CONCURRENCY: usize = 8;
+
+/// Refreshes seeder counts of torrents with metadata every `enrich_recheck_every_hours`,
+/// oldest first (`idx_next_recheck`): a DHT peer lookup plus a tracker scrape, then the
+/// torrent is reindexed (or dropped from the index) with the fresh count.
+async fn recheck_loop(state: AppState, tracker: reqwest::Client) {
+    let max_age_ms = state.config.enrich_recheck_every_hours as i64 * 60 * 60 * 1000;
+    if max_age_ms == 0 {
+        tracing::info!("enrich: seeder re-checks disabled (SERMA_ENRICH_RECHECK_EVERY_HOURS=0)");
+        return;
+    }
+
+    while !state.shutdown.is_triggered() {
+        if state.control.is_paused(Subsystem::Enrich) {
+            state.shutdown.sleep(Duration::from_secs(5)).await;
+            continue;
+        }
+
+        let due = match storage::take_due_rechecks(&state.db, max_age_ms, RECHECK_BATCH) {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::warn!(%err, "enrich: failed reading re-check queue");
+                state.shutdown.sleep(Duration::from_secs(60)).await;
+                continue;
+            }
+        };
+        if due.is_empty() {
+            state.shutdown.sleep(Duration::from_secs(60)).await;
+            continue;
+        }
+
+        let count = due.len();
+        futures_util::stream::iter(due)
+            .for_each_concurrent(RECHECK_CONCURRENCY, |record| {
+                let state = &state;
+                let tracker = &tracker;
+                async move {
+                    if let Err(err) = recheck_one(state, tracker, &record).await {
+                        tracing::debug!(%err, hash = %record.info_hash_hex, "enrich: re-check failed");
+                    }
+                    let _ = storage::mark_rechecked(&state.db, &record.info_hash_hex);
+                }
+            })
+            .await;
+        let _ = state.index.maybe_commit();
+        tracing::debug!(count, "enrich: re-checked seeders");
+    }
+}
+
+/// Fresh seeder counts for one torrent; also run by bulk `re-enrich` jobs.
+#[tracing::instrument(name = "recheck", skip_all, fields(info_hash = %record.info_hash_hex))]
+pub async fn recheck_one(state: &AppState, tracker: &reqwest::Client, record: &storage::TorrentRecord) -> anyhow::Result<()> {
+    let info_hash_bytes = parse_info_hash_hex(&record.info_hash_hex)?;
+    let lookup = timeout(
+        Duration::from_secs(state.config.enrich_dht_get_peers_timeout_secs),
+        state.dht.get_peers(&state.config, info_hash_bytes, None),
+    )
+    .await
+    .context("dht get_peers timed out")??;
+
+    // Same DHT numbers as first enrichment; trackers can only raise them.
+    let mut stats = dht_swarm_stats(&lookup);
+    let trackers = crate::scrape::trackers_for(&state.config, record.magnet.as_deref());
+    if let Some(scraped) = crate::scrape::scrape_one(tracker, &state.trackers, info_hash_bytes, &trackers).await {
+        let scraped = scraped.stats;
+        stats.seeders = stats.seeders.max(scraped.seeders);
+        stats.leechers = stats.leechers.max(scraped.leechers);
+        stats.completed = scraped.completed;
+    }
+    crate::scrape::apply(state, &record.info_hash_hex, stats)?;
+    Ok(())
+}
+
+/// Returns whether the metadata was fetched.
+#[tracing::instrument(name = "enrich", skip_all, fields(info_hash = %record.info_hash_hex))]
 async fn enrich_one(
     state: &AppState,
-    tracker: &TrackerClient,
+    tracker: &reqwest::Client,
+    dead_peers: &Mutex<DeadPeers>,
+    stages: &Stages,
     record: storage::TorrentRecord,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<bool> {
     let info_hash_bytes = parse_info_hash_hex(&record.info_hash_hex)
         .with_context(|| format!("invalid info hash: {}", record.info_hash_hex))?;
 
     tracing::debug!(hash = %record.info_hash_hex, "enrich: start");
 
-    let peers = timeout(
-        Duration::from_secs(state.config.enrich_dht_get_peers_timeout_secs),
-        dht_get_peers_krpc(&state.config, info_hash_bytes),
-    )
+    let lookup = {
+        let _slot = stages.enter(&state.stats, EnrichStage::Lookup).await;
+        timeout(
+            Duration::from_secs(state.config.enrich_dht_get_peers_timeout_secs),
+            state.dht.get_peers(&state.config, info_hash_bytes, crate::inbound::announce_port(&state.config)),
+        )
         .await
-        .context("dht get_peers timed out")??;
-
-    tracing::debug!(hash = %record.info_hash_hex, peers = peers.len(), "enrich: dht peers");
+        .context("dht get_peers timed out")??
+    };
 
-    if peers.is_empty() {
-        return Ok(());
+    let dht = dht_swarm_stats(&lookup);
+    tracing::debug!(
+        hash = %record.info_hash_hex,
+        peers = lookup.peers.len(),
+        seeders = dht.seeders,
+        leechers = dht.leechers,
+        "enrich: dht peers"
+    );
+
+    // Best-effort DHT counts; trackers provide real ones when available. Stored even when
+    // no peer is reachable, so trackerless torrents still get numbers.
+    let seeders = i64::from(dht.seeders);
+    let leechers = i64::from(dht.leechers);
+    if seeders > record.seeders || leechers > record.leechers {
+        let _ = storage::set_dht_swarm_counts(
+            &state.db,
+            &record.info_hash_hex,
+            seeders.max(record.seeders),
+            leechers.max(record.leechers),
+            &state.config.seeder_history(),
+        );
     }
 
-    // Best-effort: use DHT peer count as a lower-bound popularity signal.
-    // Cap it to avoid writing unrealistic values.
-    // (Trackers provide real seeder counts when available.)
-    let dht_peers_lb = (peers.len().min(50)) as i64;
-    if dht_peers_lb > record.seeders {
-        let _ = storage::set_seeders(&state.db, &record.info_hash_hex, dht_peers_lb);
+    if lookup.peers.is_empty() {
+        return Ok(false);
+    }
+    let (mut peers, remembered) = {
+        let dead = lock(dead_peers);
+        let now = std::time::Instant::now();
+        let peers: Vec<SocketAddr> = lookup.peers.iter().copied().filter(|peer| !dead.is_dead(peer, now)).collect();
+        (peers, dead.count())
+    };
+    // Stable, so each family keeps the lookup's order.
+    let family = state.config.enrich_peer_family;
+    peers.sort_by_key(|peer| family.rank(peer));
+    if peers.len() < lookup.peers.len() {
+        tracing::debug!(
+            hash = %record.info_hash_hex,
+            skipped = lookup.peers.len() - peers.len(),
+            remembered,
+            "enrich: skipped recently failed peers"
+        );
     }
 
     // Try multiple peers concurrently; many peers will refuse connections or lack ut_metadata.
-    // Concurrency keeps enrichment from stalling on slow/blocked peers.
+    // Concurrency keeps enrichment from stalling on slow/blocked peers, and the peers that
+    // do connect download different pieces of the same metadata.
     let max_metadata_inflight = state.config.enrich_metadata_inflight;
     let metadata_overall_timeout = Duration::from_secs(state.config.enrich_metadata_overall_timeout_secs);
 
+    let fetching = stages.enter(&state.stats, EnrichStage::Fetch).await;
     let mut tried: usize = 0;
     let mut failures_logged: usize = 0;
     let mut last_err: Option<anyhow::Error> = None;
 
+    let assembly = Arc::new(Mutex::new(MetadataAssembly::default()));
     let mut join_set = tokio::task::JoinSet::new();
     let mut peer_iter = peers.into_iter().take(state.config.enrich_peers_per_hash);
     for _ in 0..max_metadata_inflight {
         if let Some(peer) = peer_iter.next() {
             tried += 1;
+            let assembly = assembly.clone();
             join_set.spawn(async move {
-                let r = timeout(metadata_overall_timeout, fetch_ut_metadata(peer, info_hash_bytes)).await;
+                let r = timeout(metadata_overall_timeout, fetch_ut_metadata(peer, info_hash_bytes, assembly)).await;
                 (peer, r)
             });
         }
@@ -129,6 +379,9 @@ async fn enrich_one(
                 break;
             }
             Ok(Err(err)) => {
+                if err.downcast_ref::<UnusablePeer>().is_some() {
+                    lock(dead_peers).mark(peer, std::time::Instant::now());
+                }
                 last_err = Some(err);
                 if failures_logged < 2 {
                     if let Some(err) = last_err.as_ref() {
@@ -154,14 +407,17 @@ async fn enrich_one(
 
         if let Some(next_peer) = peer_iter.next() {
             tried += 1;
+            let assembly = assembly.clone();
             join_set.spawn(async move {
-                let r = timeout(metadata_overall_timeout, fetch_ut_metadata(next_peer, info_hash_bytes)).await;
+                let r = timeout(metadata_overall_timeout, fetch_ut_metadata(next_peer, info_hash_bytes, assembly)).await;
                 (next_peer, r)
             });
         } else if join_set.is_empty() {
             break;
         }
     }
+    drop(join_set);
+    drop(fetching);
 
     let Some(info_bytes) = metadata else {
         if let Some(err) = last_err {
@@ -169,813 +425,626 @@ async fn enrich_one(
         } else {
             tracing::debug!(hash = %record.info_hash_hex, tried, "enrich: metadata unavailable");
         }
-        return Ok(());
+        return Ok(false);
     };
 
-    let title = extract_name_from_info(&info_bytes).ok();
-    let info_b64 = base64::engine::general_purpose::STANDARD.encode(&info_bytes);
+    store_metadata(state, tracker, &record.info_hash_hex, info_hash_bytes, &info_bytes).await
+}
+
+/// Fetches the metadata from a peer that connected to us (see inbound.rs), if it offers a
+/// hash we still lack it for. Returns whether the metadata was fetched.
+#[tracing::instrument(name = "enrich_inbound", skip_all, fields(%peer, info_hash = tracing::field::Empty))]
+pub async fn enrich_inbound(state: &AppState, tracker: &reqwest::Client, stream: TcpStream, peer: SocketAddr) -> anyhow::Result<bool> {
+    let mut conn = PeerConn::plain(Box::new(stream));
+    let theirs = timeout(PEER_HANDSHAKE_TIMEOUT, conn.receive_handshake())
+        .await
+        .context("peer handshake timed out")??;
+    if !theirs.supports_extension_protocol() {
+        anyhow::bail!("peer does not support BEP-10");
+    }
+    let info_hash_hex = hex::encode(theirs.info_hash);
+    tracing::Span::current().record("info_hash", info_hash_hex.as_str());
+    let wanted = storage::get(&state.db, &info_hash_hex)?
+        .is_some_and(|record| !storage::has_info(&record) && !state.control.is_paused(Subsystem::Enrich));
+    if !wanted {
+        // Dropping the connection tells the peer we don't have this torrent.
+        return Ok(false);
+    }
+
+    let peer_id = *PeerId::generate().as_bytes();
+    conn.send_handshake(&Handshake::new(theirs.info_hash, peer_id)).await?;
+    let assembly = Arc::new(Mutex::new(MetadataAssembly::default()));
+    let info_bytes = fetch_metadata(conn, theirs.info_hash, assembly).await?;
+    tracing::debug!(hash = %info_hash_hex, peer = %peer, bytes = info_bytes.len(), "enrich: got metadata from inbound peer");
+    store_metadata(state, tracker, &info_hash_hex, theirs.info_hash, &info_bytes).await
+}
+
+/// Persists fetched metadata, scrapes the magnet's trackers and (re)indexes the torrent.
+async fn store_metadata(
+    state: &AppState,
+    tracker: &reqwest::Client,
+    info_hash_hex: &str,
+    info_hash_bytes: [u8; 20],
+    info_bytes: &[u8],
+) -> anyhow::Result<bool> {
+    // Blacklisted while the lookup was running: don't bring the record back.
+    if storage::is_blacklisted(&state.db, info_hash_hex)? {
+        return Ok(false);
+    }
+
+    let title = extract_name_from_info(info_bytes).ok();
+    let info_b64 = base64::engine::general_purpose::STANDARD.encode(info_bytes);
     let mut updated = storage::set_metadata(
         &state.db,
-        &record.info_hash_hex,
+        info_hash_hex,
         title.as_deref(),
         &info_b64,
     )?;
 
-    // If the record contains trackers in its magnet, use them to get a real seeder count.
-    if let Some(magnet) = updated.magnet.clone() {
-        if let Ok(m) = MagnetLink::parse(&magnet) {
-            if !m.trackers.is_empty() {
-                if let Ok(hash) = InfoHash::from_hex(&updated.info_hash_hex) {
-                    let peer_id = *PeerId::generate().as_bytes();
-                    if let Some(seeders) =
-                        announce_seeders(tracker, &hash, &peer_id, &m.trackers).await
-                    {
-                        if seeders > updated.seeders {
-                            updated =
-                                storage::set_seeders(&state.db, &updated.info_hash_hex, seeders)?;
-                        }
-                    }
-                }
-            }
+    if let Some(rule) = state.blocklist.matches(&updated) {
+        tracing::info!(hash = %updated.info_hash_hex, %rule, "enrich: dropped by blocklist");
+        storage::delete(&state.db, &updated.info_hash_hex)?;
+        let _ = state.index.delete(&updated.info_hash_hex);
+        let _ = state.index.maybe_commit();
+        return Ok(true);
+    }
+
+    // Scrape the magnet's trackers (or the open ones) for real counts.
+    let trackers = crate::scrape::trackers_for(&state.config, updated.magnet.as_deref());
+    let mut tracking = Vec::new();
+    if let Some(scraped) = crate::scrape::scrape_one(tracker, &state.trackers, info_hash_bytes, &trackers).await {
+        // Keep the DHT counts if they're higher; later scrape rounds replace them.
+        let seeders = updated.seeders.max(i64::from(scraped.stats.seeders));
+        let leechers = updated.leechers.max(i64::from(scraped.stats.leechers));
+        if let Some(record) = storage::set_swarm_counts(&state.db, &updated.info_hash_hex, seeders, leechers, &state.config.seeder_history())? {
+            updated = record;
         }
+        tracking = scraped.trackers;
     }
 
-    let title_for_index = updated
-        .title
-        .clone()
-        .unwrap_or_else(|| format!("Torrent {}", &updated.info_hash_hex));
-    let magnet_for_index = updated.magnet.clone().unwrap_or_default();
+    // Spidered hashes start as bare `xt` magnets. Now the magnet gets the torrent's name, the
+    // v2 hash (btmh) of a v2 or hybrid torrent so v2 clients can use it, and the trackers that
+    // just reported the swarm, so a client opening it finds peers without waiting on the DHT.
+    if let Some(magnet) = crate::ingest::enriched_magnet(&updated, &tracking) {
+        updated = storage::set_magnet(&state.db, &updated.info_hash_hex, &magnet)?;
+    }
 
     // Only index torrents that meet the minimum activity threshold.
     if updated.seeders >= 2 {
-        let _ = state.index.upsert(
-            &updated.info_hash_hex,
-            &title_for_index,
-            &magnet_for_index,
-            updated.seeders,
-        );
+        let _ = state.index.upsert_record(&updated);
     } else {
         // If it was previously indexed, remove it.
         let _ = state.index.delete(&updated.info_hash_hex);
     }
     let _ = state.index.maybe_commit();
-    Ok(())
+    state.events.publish(Event::Enriched {
+        info_hash: updated.info_hash_hex,
+        title: updated.title,
+        size_bytes: updated.size_bytes,
+        file_count: updated.file_count,
+        seeders: updated.seeders,
+        leechers: updated.leechers,
+    });
+    Ok(true)
 }
 
-async fn dht_get_peers_krpc(cfg: &Config, info_hash: [u8; 20]) -> anyhow::Result<Vec<SocketAddr>> {
-    let transport = match Socks5Config::from_env() {
-        Some(Ok(cfg)) => {
-            let sock = Socks5UdpAssociate::connect(&cfg)
-                .await
-                .with_context(|| format!("enrich: connect SOCKS5 proxy {}", cfg.proxy))?;
-            DhtTransport::Socks { sock }
-        }
-        Some(Err(err)) => {
-            anyhow::bail!("enrich: invalid SERMA_SOCKS5_PROXY: {err}");
-        }
-        None => {
-            // Use separate IPv4 + (optional) IPv6 UDP sockets so we can talk to both
-            // families regardless of OS IPv6 dual-stack settings.
-            let socket_v4 = UdpSocket::bind("0.0.0.0:0").await?;
-            let socket_v6 = match UdpSocket::bind("[::]:0").await {
-                Ok(s) => Some(s),
-                Err(err) => {
-                    tracing::debug!(%err, "enrich: ipv6 udp bind failed; continuing with ipv4 only");
-                    None
-                }
-            };
-            DhtTransport::Direct { socket_v4, socket_v6 }
-        }
-    };
-    let node_id = *PeerId::generate().as_bytes();
-
-    let bootstrap = resolve_bootstrap(cfg).await;
-    if bootstrap.is_empty() {
-        anyhow::bail!("no DHT bootstrap nodes resolved");
-    }
-
-    // Prefer nodes closer (XOR-distance) to the target infohash.
-    // Store a min-heap by using Reverse(distance).
-    let mut q: BinaryHeap<(Reverse<[u8; 20]>, SocketAddr)> = BinaryHeap::new();
-    let mut seen_nodes: HashSet<SocketAddr> = HashSet::new();
-    for addr in bootstrap {
-        push_node_seed(addr, &mut q, &mut seen_nodes);
-    }
-
-    let mut peers: Vec<SocketAddr> = Vec::new();
-    let mut seen_peers: HashSet<SocketAddr> = HashSet::new();
-    let mut tx: u16 = 0;
-    let mut buf4 = vec![0u8; 4096];
-    let mut buf6 = vec![0u8; 4096];
-    let mut queries = 0usize;
-
-    // Track a small window of in-flight queries so we don't miss responses due to timing.
-    // key=txid, value=(addr, sent_at)
-    let mut inflight: HashMap<[u8; 2], (SocketAddr, tokio::time::Instant)> = HashMap::new();
-    let max_inflight: usize = cfg.enrich_dht_inflight;
-
-    // Bound total time spent per hash lookup (outer timeout still applies too).
-    let overall_deadline = tokio::time::Instant::now()
-        + Duration::from_secs(cfg.enrich_dht_overall_deadline_secs);
-
-    while tokio::time::Instant::now() < overall_deadline {
-        if peers.len() >= cfg.enrich_peers_per_hash {
-            break;
-        }
-        if queries >= cfg.enrich_dht_max_queries_per_hash {
-            break;
-        }
-
-        // Reap timed-out inflight requests.
-        let now = tokio::time::Instant::now();
-        let query_timeout = Duration::from_millis(cfg.enrich_dht_query_timeout_ms);
-        inflight.retain(|_, (_, sent_at)| now.saturating_duration_since(*sent_at) <= query_timeout);
-
-        // Fill the inflight window.
-        while inflight.len() < max_inflight
-            && queries < cfg.enrich_dht_max_queries_per_hash
-            && peers.len() < cfg.enrich_peers_per_hash
-        {
-            let Some((_, addr)) = q.pop() else { break };
-            tx = tx.wrapping_add(1);
-            let txid = tx.to_be_bytes();
-            let msg = make_get_peers(txid, &node_id, &info_hash);
-            let _ = dht_send(&transport, &msg, addr).await;
-            inflight.insert(txid, (addr, tokio::time::Instant::now()));
-            queries += 1;
-        }
-
-        if inflight.is_empty() && q.is_empty() {
-            break;
-        }
-
-        // Process responses; use a short receive timeout to keep the loop responsive.
-        let recv = dht_recv(
-            &transport,
-            &mut buf4,
-            &mut buf6,
-            Duration::from_millis(cfg.enrich_dht_recv_timeout_ms),
-        );
-        let Some((n_res, fam_tag)) = recv.await else {
-            continue;
-        };
-        let Ok(n) = n_res else {
-            continue;
-        };
-        if n == 0 {
-            continue;
-        }
-
-        let raw = if fam_tag == 4 { &buf4[..n] } else { &buf6[..n] };
-        let Some(resp) = KrpcResponse::decode(raw) else {
-            continue;
-        };
-
-        // Only accept responses for txids we sent.
-        if inflight.remove(&resp.tx).is_none() {
-            continue;
-        }
+/// Seeders and leechers from a lookup: the BEP-33 estimate, with the number of peers
+/// found (capped, to avoid writing unrealistic values) as a lower bound for seeders.
+fn dht_swarm_stats(lookup: &DhtLookup) -> crate::tracker_udp::SwarmStats {
+    let mut stats = lookup.scrape.estimate().unwrap_or_default();
+    stats.seeders = stats.seeders.max(lookup.peers.len().min(50) as u32);
+    stats
+}
 
-        if let Some(nodes) = resp.nodes {
-            for node in parse_compact_nodes_v4(nodes) {
-                push_node(node, &info_hash, &mut q, &mut seen_nodes);
-            }
-        }
-        if let Some(nodes6) = resp.nodes6 {
-            for node in parse_compact_nodes_v6(nodes6) {
-                push_node(node, &info_hash, &mut q, &mut seen_nodes);
-            }
-        }
-        if let Some(values) = resp.values {
-            for v in values {
-                if let Some(peer) = parse_compact_peer_v4(&v) {
-                    if seen_peers.insert(peer) {
-                        peers.push(peer);
-                        if peers.len() >= cfg.enrich_peers_per_hash {
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-        if let Some(values6) = resp.values6 {
-            for v in values6 {
-                if let Some(peer) = parse_compact_peer_v6(&v) {
-                    if seen_peers.insert(peer) {
-                        peers.push(peer);
-                        if peers.len() >= cfg.enrich_peers_per_hash {
-                            break;
-                        }
-                    }
-                }
-            }
-        }
+/// Opens the peer's TCP connection, through the SOCKS5 proxy when one is configured so
+/// peers never see our own address.
+async fn connect_peer(addr: SocketAddr) -> anyhow::Result<TcpStream> {
+    match Socks5Config::from_env() {
+        Some(Ok(cfg)) => crate::socks5::connect_tcp(&cfg, addr).await,
+        Some(Err(err)) => anyhow::bail!("enrich: invalid SERMA_SOCKS5_PROXY: {err}"),
+        None => Ok(TcpStream::connect(addr).await?),
     }
-
-    Ok(peers)
 }
 
-enum DhtTransport {
-    Direct {
-        socket_v4: UdpSocket,
-        socket_v6: Option<UdpSocket>,
-    },
-    Socks {
-        sock: Socks5UdpAssociate,
-    },
-}
+/// An error that makes the peer useless for any torrent for now: it can't be reached, or
+/// doesn't support ut_metadata. Such peers are skipped for a while (see dead_peers.rs).
+#[derive(Debug)]
+struct UnusablePeer(&'static str);
 
-async fn dht_send(
-    transport: &DhtTransport,
-    msg: &[u8],
-    addr: SocketAddr,
-) -> std::io::Result<usize> {
-    match transport {
-        DhtTransport::Direct { socket_v4, socket_v6 } => match addr.ip() {
-            IpAddr::V4(_) => socket_v4.send_to(msg, addr).await,
-            IpAddr::V6(_) => {
-                if let Some(sock6) = socket_v6.as_ref() {
-                    sock6.send_to(msg, addr).await
-                } else {
-                    Ok(0)
-                }
-            }
-        },
-        DhtTransport::Socks { sock } => sock.send_to(msg, addr).await,
+impl std::fmt::Display for UnusablePeer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
     }
 }
 
-async fn dht_recv(
-    transport: &DhtTransport,
-    buf4: &mut [u8],
-    buf6: &mut [u8],
-    per_recv_timeout: Duration,
-) -> Option<(std::io::Result<usize>, u8)> {
-    let sleep = tokio::time::sleep(per_recv_timeout);
-    tokio::pin!(sleep);
-
-    match transport {
-        DhtTransport::Direct { socket_v4, socket_v6 } => {
-            tokio::select! {
-                _ = &mut sleep => None,
-                r = socket_v4.recv_from(buf4) => Some((r.map(|(n, _)| n), 4u8)),
-                r = async {
-                    if let Some(sock6) = socket_v6.as_ref() {
-                        sock6.recv_from(buf6).await.map(|(n, _)| n)
-                    } else {
-                        // Never resolves when IPv6 socket is unavailable.
-                        std::future::pending::<std::io::Result<usize>>().await
-                    }
-                } => Some((r, 6u8)),
-            }
-        }
-        DhtTransport::Socks { sock } => {
-            tokio::select! {
-                _ = &mut sleep => None,
-                r = sock.recv_from(buf4) => {
-                    Some((r.map(|(n, from)| {
-                        // Place bytes in buf4; caller uses fam_tag to choose buf.
-                        let _ = from;
-                        n
-                    }), 4u8))
-                }
-            }
+impl std::error::Error for UnusablePeer {}
+
+/// Connect and handshake budget, per attempt.
+const PEER_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(6);
+/// TCP connect budget before trying uTP.
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A peer connection's byte stream: TCP, or the pipe to a uTP connection.
+trait PeerIo: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> PeerIo for T {}
+
+/// Opens a byte stream to the peer over TCP, or over uTP when the TCP connect fails (many
+/// home peers only accept uTP). Never uTP behind a SOCKS5 proxy: it would go around it.
+/// `utp` pins the transport when reconnecting. Returns the stream and whether it is uTP.
+async fn dial(addr: SocketAddr, utp: Option<bool>) -> anyhow::Result<(Box<dyn PeerIo>, bool)> {
+    if utp != Some(true) {
+        let tcp = timeout(TCP_CONNECT_TIMEOUT, connect_peer(addr))
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("peer connect timed out")));
+        match tcp {
+            Ok(stream) => return Ok((Box::new(stream), false)),
+            Err(err) if utp == Some(false) || Socks5Config::from_env().is_some() => return Err(err),
+            Err(_) => {}
         }
     }
+    Ok((Box::new(crate::utp::connect(addr).await?), true))
 }
 
-async fn resolve_bootstrap(cfg: &Config) -> Vec<SocketAddr> {
-    let mut out = Vec::new();
-    for host in cfg.enrich_dht_bootstrap.iter().cloned() {
-        match tokio::net::lookup_host(&host).await {
-            Ok(iter) => {
-                for addr in iter {
-                    out.push(addr);
-                }
-            }
-            Err(err) => {
-                tracing::debug!(%err, host=%host, "enrich: bootstrap resolve failed");
-            }
-        }
+/// Connects and exchanges BitTorrent handshakes. A peer that accepts the connection but
+/// drops a plaintext handshake (many require encryption) gets a second connection that
+/// starts with an MSE handshake.
+async fn open_peer(addr: SocketAddr, info_hash: [u8; 20], peer_id: [u8; 20]) -> anyhow::Result<PeerConn> {
+    let (stream, utp) = timeout(PEER_HANDSHAKE_TIMEOUT, dial(addr, None))
+        .await
+        .context("peer connect timed out")
+        .and_then(|dialed| dialed)
+        .context(UnusablePeer("peer unreachable"))?;
+    match timeout(PEER_HANDSHAKE_TIMEOUT, bt_handshake(PeerConn::plain(stream), info_hash, peer_id)).await {
+        Ok(Ok(conn)) => return Ok(conn),
+        Ok(Err(err)) if !refused_plaintext(&err) => return Err(err),
+        _ => {}
     }
-    out
-}
 
-#[derive(Clone, Copy)]
-struct DhtNode {
-    id: [u8; 20],
-    addr: SocketAddr,
+    timeout(PEER_HANDSHAKE_TIMEOUT, async {
+        let (mut stream, _) = dial(addr, Some(utp)).await?;
+        let negotiated = crate::mse::initiate(&mut stream, &info_hash).await?;
+        bt_handshake(PeerConn::encrypted(stream, negotiated), info_hash, peer_id).await
+    })
+    .await
+    .context("encrypted peer handshake timed out")?
 }
 
-fn xor_distance(a: &[u8; 20], b: &[u8; 20]) -> [u8; 20] {
-    let mut out = [0u8; 20];
-    for i in 0..20 {
-        out[i] = a[i] ^ b[i];
-    }
-    out
+/// The peer hung up or reset the connection mid-handshake.
+fn refused_plaintext(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<PeerError>(), Some(PeerError::ConnectionClosed | PeerError::Io(_)))
 }
 
-fn push_node_seed(
-    addr: SocketAddr,
-    q: &mut BinaryHeap<(Reverse<[u8; 20]>, SocketAddr)>,
-    set: &mut HashSet<SocketAddr>,
-) {
-    // Seed nodes don't come with an ID, so just give them highest priority.
-    if !filter_addr(addr) {
-        return;
+async fn bt_handshake(mut conn: PeerConn, info_hash: [u8; 20], peer_id: [u8; 20]) -> anyhow::Result<PeerConn> {
+    conn.send_handshake(&Handshake::new(info_hash, peer_id)).await?;
+    let theirs = conn.receive_handshake().await?;
+    if theirs.info_hash != info_hash {
+        anyhow::bail!("peer answered for a different info hash");
     }
-    if set.insert(addr) {
-        q.push((Reverse([0u8; 20]), addr));
+    if !theirs.supports_extension_protocol() {
+        anyhow::bail!(UnusablePeer("peer does not support BEP-10"));
     }
+    Ok(conn)
 }
 
-fn push_node(
-    node: DhtNode,
-    target: &[u8; 20],
-    q: &mut BinaryHeap<(Reverse<[u8; 20]>, SocketAddr)>,
-    set: &mut HashSet<SocketAddr>,
-) {
-    if !filter_addr(node.addr) {
-        return;
-    }
-    if set.insert(node.addr) {
-        let dist = xor_distance(&node.id, target);
-        q.push((Reverse(dist), node.addr));
-    }
+/// Peer wire framing over a plaintext or MSE-encrypted connection, TCP or uTP (rbit's
+/// `PeerTransport` only does plaintext TCP). Reads and writes are bounded by the callers' timeouts.
+struct PeerConn {
+    stream: Box<dyn PeerIo>,
+    /// `(outgoing, incoming)` RC4 state.
+    cipher: Option<(crate::mse::Rc4, crate::mse::Rc4)>,
+    /// Received bytes, already decrypted.
+    read_buf: BytesMut,
 }
 
-fn filter_addr(addr: SocketAddr) -> bool {
-    if addr.port() == 0 {
-        return false;
-    }
-    match addr.ip() {
-        IpAddr::V4(v4) => {
-            if v4.is_private() || v4.is_loopback() || v4.is_unspecified() {
-                return false;
-            }
-        }
-        IpAddr::V6(v6) => {
-            if v6.is_loopback() || v6.is_unspecified() || v6.is_unique_local() {
-                return false;
-            }
+impl PeerConn {
+    fn plain(stream: Box<dyn PeerIo>) -> Self {
+        Self {
+            stream,
+            cipher: None,
+            read_buf: BytesMut::with_capacity(32 * 1024),
         }
     }
-    true
-}
 
-fn parse_compact_peer_v4(bytes: &[u8]) -> Option<SocketAddr> {
-    if bytes.len() != 6 {
-        return None;
+    fn encrypted(stream: Box<dyn PeerIo>, negotiated: crate::mse::Negotiated) -> Self {
+        let mut read_buf = BytesMut::with_capacity(32 * 1024);
+        read_buf.extend_from_slice(&negotiated.leftover);
+        Self {
+            stream,
+            cipher: negotiated.cipher,
+            read_buf,
+        }
     }
-    let ip = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
-    let port = u16::from_be_bytes([bytes[4], bytes[5]]);
-    Some(SocketAddr::new(IpAddr::V4(ip), port))
-}
 
-fn parse_compact_peer_v6(bytes: &[u8]) -> Option<SocketAddr> {
-    if bytes.len() != 18 {
-        return None;
+    async fn send_handshake(&mut self, handshake: &Handshake) -> Result<(), PeerError> {
+        self.write(&handshake.encode()).await
     }
-    let ip = Ipv6Addr::from([
-        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-        bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15],
-    ]);
-    let port = u16::from_be_bytes([bytes[16], bytes[17]]);
-    Some(SocketAddr::new(IpAddr::V6(ip), port))
-}
 
-fn parse_compact_nodes_v4(nodes: &[u8]) -> Vec<DhtNode> {
-    // Compact node info: 26 bytes per node: 20-byte node id + 4-byte IPv4 + 2-byte port.
-    let mut out = Vec::new();
-    let mut i = 0;
-    while i + 26 <= nodes.len() {
-        let mut id = [0u8; 20];
-        id.copy_from_slice(&nodes[i..i + 20]);
-        let ip = Ipv4Addr::new(nodes[i + 20], nodes[i + 21], nodes[i + 22], nodes[i + 23]);
-        let port = u16::from_be_bytes([nodes[i + 24], nodes[i + 25]]);
-        out.push(DhtNode {
-            id,
-            addr: SocketAddr::new(IpAddr::V4(ip), port),
-        });
-        i += 26;
+    async fn receive_handshake(&mut self) -> Result<Handshake, PeerError> {
+        self.fill(HANDSHAKE_LEN).await?;
+        Handshake::decode(&self.read_buf.split_to(HANDSHAKE_LEN))
     }
-    out
-}
 
-fn parse_compact_nodes_v6(nodes: &[u8]) -> Vec<DhtNode> {
-    // nodes6: 38 bytes per node: 20-byte node id + 16-byte IPv6 + 2-byte port.
-    let mut out = Vec::new();
-    let mut i = 0;
-    while i + 38 <= nodes.len() {
-        let mut id = [0u8; 20];
-        id.copy_from_slice(&nodes[i..i + 20]);
-        let ip = Ipv6Addr::from([
-            nodes[i + 20],
-            nodes[i + 21],
-            nodes[i + 22],
-            nodes[i + 23],
-            nodes[i + 24],
-            nodes[i + 25],
-            nodes[i + 26],
-            nodes[i + 27],
-            nodes[i + 28],
-            nodes[i + 29],
-            nodes[i + 30],
-            nodes[i + 31],
-            nodes[i + 32],
-            nodes[i + 33],
-            nodes[i + 34],
-            nodes[i + 35],
-        ]);
-        let port = u16::from_be_bytes([nodes[i + 36], nodes[i + 37]]);
-        out.push(DhtNode {
-            id,
-            addr: SocketAddr::new(IpAddr::V6(ip), port),
-        });
-        i += 38;
+    async fn send_message(&mut self, message: &Message) -> Result<(), PeerError> {
+        self.write(&message.encode()).await
     }
-    out
-}
-
-fn make_get_peers(tx: [u8; 2], id: &[u8; 20], info_hash: &[u8; 20]) -> Vec<u8> {
-    // d1:ad2:id20:<id>9:info_hash20:<info>e1:q9:get_peers1:t2:<tx>1:y1:qe
-    let mut out = Vec::with_capacity(120);
-    out.push(b'd');
 
-    benc_key(&mut out, b"a");
-    out.push(b'd');
-    benc_key(&mut out, b"id");
-    benc_bytes(&mut out, id);
-    benc_key(&mut out, b"info_hash");
-    benc_bytes(&mut out, info_hash);
-    out.push(b'e');
+    async fn receive_message(&mut self) -> Result<Message, PeerError> {
+        self.fill(4).await?;
+        let length = u32::from_be_bytes([self.read_buf[0], self.read_buf[1], self.read_buf[2], self.read_buf[3]]) as usize;
+        if length > MAX_MESSAGE_SIZE {
+            return Err(PeerError::InvalidMessage(format!("message too large: {length}")));
+        }
+        self.fill(4 + length).await?;
+        Message::decode(self.read_buf.split_to(4 + length).freeze())
+    }
 
-    benc_key(&mut out, b"q");
-    benc_bytes(&mut out, b"get_peers");
+    async fn write(&mut self, data: &[u8]) -> Result<(), PeerError> {
+        let mut data = data.to_vec();
+        if let Some((outgoing, _)) = self.cipher.as_mut() {
+            outgoing.apply(&mut data);
+        }
+        self.stream.write_all(&data).await?;
+        Ok(())
+    }
 
-    benc_key(&mut out, b"t");
-    benc_bytes(&mut out, &tx);
+    async fn fill(&mut self, len: usize) -> Result<(), PeerError> {
+        while self.read_buf.len() < len {
+            let start = self.read_buf.len();
+            if self.stream.read_buf(&mut self.read_buf).await? == 0 {
+                return Err(PeerError::ConnectionClosed);
+            }
+            if let Some((_, incoming)) = self.cipher.as_mut() {
+                incoming.apply(&mut self.read_buf[start..]);
+            }
+        }
+        Ok(())
+    }
+}
 
-    benc_key(&mut out, b"y");
-    benc_bytes(&mut out, b"q");
+/// BitTorrent handshake length, and the largest peer message accepted (rbit's limit).
+const HANDSHAKE_LEN: usize = 68;
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
 
-    out.push(b'e');
-    out
+/// Fetches the metadata from one peer into the lookup's shared `assembly`, which other
+/// peers fill at the same time. Returns the whole verified info dict once it is complete.
+#[tracing::instrument(name = "ut_metadata", skip_all, fields(peer = %addr, info_hash = %hex::encode(info_hash)))]
+async fn fetch_ut_metadata(
+    addr: SocketAddr,
+    info_hash: [u8; 20],
+    assembly: Arc<Mutex<MetadataAssembly>>,
+) -> anyhow::Result<Vec<u8>> {
+    let peer_id = *PeerId::generate().as_bytes();
+    let conn = open_peer(addr, info_hash, peer_id).await?;
+    fetch_metadata(conn, info_hash, assembly).await
 }
 
-fn benc_key(out: &mut Vec<u8>, key: &[u8]) {
-    benc_bytes(out, key);
-}
+/// The ut_metadata exchange on a connection whose BitTorrent handshakes are done.
+async fn fetch_metadata(
+    mut conn: PeerConn,
+    info_hash: [u8; 20],
+    assembly: Arc<Mutex<MetadataAssembly>>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut hs = ExtensionHandshake::with_extensions(&[("ut_metadata", 1)]);
+    hs.client = Some("serma".to_string());
 
-fn benc_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
-    itoa_len(out, bytes.len());
-    out.push(b':');
-    out.extend_from_slice(bytes);
-}
+    let payload = hs.encode()?;
+    conn.send_message(&Message::Extended { id: 0, payload }).await?;
 
-fn itoa_len(out: &mut Vec<u8>, n: usize) {
-    let mut buf = [0u8; 20];
-    let mut i = buf.len();
-    let mut x = n;
-    if x == 0 {
-        out.push(b'0');
-        return;
+    let (ut_metadata_id, mut total_size) = wait_for_peer_handshake(&mut conn).await?;
+
+    // If peer didn't advertise metadata_size, another peer may have, or we request piece 0
+    // to learn total_size.
+    let mut first_piece = None;
+    if total_size.is_none() {
+        total_size = lock(&assembly).total_size().and_then(|size| u32::try_from(size).ok());
     }
-    while x > 0 {
-        i -= 1;
-        buf[i] = b'0' + (x % 10) as u8;
-        x /= 10;
+    if total_size.is_none() {
+        request_piece(&mut conn, ut_metadata_id, 0).await?;
+        let msg = recv_metadata_msg(&mut conn, ut_metadata_id, Duration::from_secs(6)).await?;
+        if msg.msg_type != MetadataMessageType::Data || msg.piece != 0 {
+            anyhow::bail!("peer did not send metadata data for piece 0");
+        }
+        total_size = msg.total_size;
+        first_piece = msg.data;
     }
-    out.extend_from_slice(&buf[i..]);
-}
 
-struct KrpcResponse<'a> {
-    tx: [u8; 2],
-    nodes: Option<&'a [u8]>,
-    nodes6: Option<&'a [u8]>,
-    values: Option<Vec<Vec<u8>>>,
-    values6: Option<Vec<Vec<u8>>>,
-}
+    let total_size = total_size.context("missing metadata total_size")? as usize;
+    if total_size == 0 {
+        anyhow::bail!("metadata has zero pieces");
+    }
+    {
+        let mut assembly = lock(&assembly);
+        if !assembly.start(total_size) {
+            anyhow::bail!("peer disagrees on the metadata size");
+        }
+        if let Some(data) = first_piece {
+            assembly.received(0, data);
+        }
+    }
 
-impl<'a> KrpcResponse<'a> {
-    fn decode(raw: &'a [u8]) -> Option<Self> {
-        if raw.first().copied()? != b'd' {
-            return None;
+    let mut requests = PeerRequests::default();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(12);
+    loop {
+        let (due, wake) = {
+            let mut assembly = lock(&assembly);
+            if assembly.complete() {
+                break;
+            }
+            let now = tokio::time::Instant::now();
+            let due = requests.due(&mut assembly, now)?;
+            (due, requests.next_due(&assembly))
+        };
+        for piece in due {
+            request_piece(&mut conn, ut_metadata_id, piece).await?;
         }
-        let y = benc_get_bytes(raw, b"y")?;
-        if y != b"r" {
-            return None;
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            anyhow::bail!("timed out waiting for metadata pieces");
         }
-        let t = benc_get_bytes(raw, b"t")?;
-        if t.len() != 2 {
-            return None;
+        // Wake up when a request is due again, here or at another peer, to re-request it.
+        // Reads are cancel-safe: a partly received message stays in `read_buf`.
+        let wait = wake.map_or(deadline, |due| due.min(deadline)).saturating_duration_since(now);
+        let msg = match timeout(wait, recv_metadata_msg(&mut conn, ut_metadata_id, deadline - now)).await {
+            Ok(msg) => msg?,
+            Err(_) => continue,
+        };
+        match msg.msg_type {
+            MetadataMessageType::Data => {
+                if let Some(data) = msg.data {
+                    requests.received(msg.piece);
+                    lock(&assembly).received(msg.piece, data);
+                }
+            }
+            MetadataMessageType::Reject => requests.rejected(&mut lock(&assembly), msg.piece),
+            _ => {}
         }
-        let mut tx = [0u8; 2];
-        tx.copy_from_slice(t);
-
-        let r = benc_get_dict(raw, b"r")?;
-        let nodes = benc_get_bytes(r, b"nodes");
-        let nodes6 = benc_get_bytes(r, b"nodes6");
-        let values = benc_get_list_bytes(r, b"values");
-        let values6 = benc_get_list_bytes(r, b"values6");
-
-        Some(Self {
-            tx,
-            nodes,
-            nodes6,
-            values,
-            values6,
-        })
     }
-}
-
-// ------------------------------
-// Minimal bencode “dict-getter”
-// ------------------------------
 
-fn benc_get_bytes<'a>(raw: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
-    let dict = BencParser::new(raw).parse_dict()?;
-    dict.get_bytes(key)
+    let out = lock(&assembly).assemble()?;
+    if let Err(err) = verify_metadata(&info_hash, &out) {
+        // Some peer sent bad data; start over with whoever is still connected.
+        lock(&assembly).reset();
+        return Err(err);
+    }
+    Ok(out)
 }
 
-fn benc_get_dict<'a>(raw: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
-    let dict = BencParser::new(raw).parse_dict()?;
-    dict.get_dict_slice(key)
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
-fn benc_get_list_bytes(raw: &[u8], key: &[u8]) -> Option<Vec<Vec<u8>>> {
-    let dict = BencParser::new(raw).parse_dict()?;
-    dict.get_list_bytes(key)
+/// Metadata pieces requested from one peer at a time; more are asked for as they arrive.
+const METADATA_IN_FLIGHT: usize = 4;
+/// A piece not answered this long ago is requested again (or by another peer).
+const METADATA_PIECE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Requests of one piece from one peer before that peer isn't asked for it any more.
+const METADATA_PIECE_TRIES: u8 = 3;
+
+enum PieceState {
+    Wanted,
+    /// Requested from some peer; up for grabs again at `retry_at`.
+    Requested { retry_at: tokio::time::Instant },
+    Received(Bytes),
 }
 
-struct BencDict<'a> {
-    raw: &'a [u8],
+/// One torrent's metadata, put together from all the peers its lookup talks to at once.
+/// Each peer asks for pieces nobody has asked for yet, then for ones another peer is late
+/// with, so a large info dict downloads from several peers in parallel and a peer that
+/// rejects some pieces just leaves them to the others. The result is verified against the
+/// info hash as a whole; if it doesn't match, the pieces are fetched again.
+#[derive(Default)]
+struct MetadataAssembly {
+    total_size: Option<usize>,
+    pieces: Vec<PieceState>,
 }
 
-impl<'a> BencDict<'a> {
-    fn get_bytes(&self, key: &[u8]) -> Option<&'a [u8]> {
-        let mut p = BencParser::new(self.raw);
-        p.expect_byte(b'd')?;
-        loop {
-            if p.peek()? == b'e' {
-                return None;
-            }
-            let k = p.parse_bytes()?;
-            match p.peek()? {
-                b'0'..=b'9' => {
-                    let bytes = p.parse_bytes()?;
-                    if k == key {
-                        return Some(bytes);
-                    }
-                }
-                b'd' | b'l' | b'i' => {
-                    p.skip_value()?;
-                }
-                _ => return None,
+impl MetadataAssembly {
+    /// Sets the metadata size on first use; false if the peer's `total_size` differs.
+    fn start(&mut self, total_size: usize) -> bool {
+        match self.total_size {
+            Some(size) => size == total_size,
+            None => {
+                self.total_size = Some(total_size);
+                self.pieces = (0..total_size.div_ceil(METADATA_PIECE_SIZE)).map(|_| PieceState::Wanted).collect();
+                true
             }
         }
     }
 
-    fn get_dict_slice(&self, key: &[u8]) -> Option<&'a [u8]> {
-        let mut p = BencParser::new(self.raw);
-        p.expect_byte(b'd')?;
-        loop {
-            if p.peek()? == b'e' {
-                return None;
-            }
-            let k = p.parse_bytes()?;
-            let v_start = p.pos;
-            if p.peek()? != b'd' {
-                p.skip_value()?;
-                continue;
-            }
-            p.skip_value()?;
-            let v_end = p.pos;
-            if k == key {
-                return self.raw.get(v_start..v_end);
-            }
-        }
+    fn total_size(&self) -> Option<usize> {
+        self.total_size
     }
 
-    fn get_list_bytes(&self, key: &[u8]) -> Option<Vec<Vec<u8>>> {
-        let mut p = BencParser::new(self.raw);
-        p.expect_byte(b'd')?;
-        loop {
-            if p.peek()? == b'e' {
-                return None;
-            }
-            let k = p.parse_bytes()?;
-            if p.peek()? != b'l' {
-                p.skip_value()?;
-                continue;
-            }
-
-            // List value.
-            p.expect_byte(b'l')?;
-            let mut out: Vec<Vec<u8>> = Vec::new();
-            while p.peek()? != b'e' {
-                match p.peek()? {
-                    b'0'..=b'9' => {
-                        let b = p.parse_bytes()?;
-                        out.push(b.to_vec());
-                    }
-                    _ => {
-                        p.skip_value()?;
-                    }
-                }
-            }
-            p.expect_byte(b'e')?;
-            if k == key {
-                return Some(out);
-            }
-        }
+    fn complete(&self) -> bool {
+        self.total_size.is_some() && self.pieces.iter().all(|p| matches!(p, PieceState::Received(_)))
     }
-}
 
-struct BencParser<'a> {
-    raw: &'a [u8],
-    pos: usize,
-}
+    fn has(&self, piece: u32) -> bool {
+        matches!(self.pieces.get(piece as usize), Some(PieceState::Received(_)))
+    }
 
-impl<'a> BencParser<'a> {
-    fn new(raw: &'a [u8]) -> Self {
-        Self { raw, pos: 0 }
+    /// Claims the first piece nobody asked for, else the first one whose request is overdue,
+    /// skipping pieces `skip` says this peer can't be asked for.
+    fn claim(&mut self, skip: impl Fn(u32) -> bool, now: tokio::time::Instant) -> Option<u32> {
+        let unasked = (0..self.pieces.len()).find(|&i| matches!(self.pieces[i], PieceState::Wanted) && !skip(i as u32));
+        let piece = unasked.or_else(|| {
+            (0..self.pieces.len()).find(|&i| {
+                matches!(self.pieces[i], PieceState::Requested { retry_at } if now >= retry_at) && !skip(i as u32)
+            })
+        })?;
+        self.pieces[piece] = PieceState::Requested {
+            retry_at: now + METADATA_PIECE_TIMEOUT,
+        };
+        Some(piece as u32)
     }
 
-    fn peek(&self) -> Option<u8> {
-        self.raw.get(self.pos).copied()
+    /// Notes that `piece` was asked for again.
+    fn requested(&mut self, piece: u32, now: tokio::time::Instant) {
+        if let Some(state @ PieceState::Requested { .. }) = self.pieces.get_mut(piece as usize) {
+            *state = PieceState::Requested {
+                retry_at: now + METADATA_PIECE_TIMEOUT,
+            };
+        }
     }
 
-    fn expect_byte(&mut self, b: u8) -> Option<()> {
-        if self.peek()? != b {
-            return None;
+    /// Gives up on a request; another peer may take the piece right away.
+    fn release(&mut self, piece: u32) {
+        if let Some(state @ PieceState::Requested { .. }) = self.pieces.get_mut(piece as usize) {
+            *state = PieceState::Wanted;
         }
-        self.pos += 1;
-        Some(())
     }
 
-    fn parse_dict(mut self) -> Option<BencDict<'a>> {
-        if self.peek()? != b'd' {
-            return None;
+    /// Stores a piece's data; data of the wrong length is ignored.
+    fn received(&mut self, piece: u32, data: Bytes) {
+        let Some(total_size) = self.total_size else {
+            return;
+        };
+        if data.len() != metadata_piece_size(piece, total_size) {
+            return;
         }
-        let start = self.pos;
-        self.skip_value()?;
-        let end = self.pos;
-        Some(BencDict {
-            raw: self.raw.get(start..end)?,
-        })
-    }
-
-    fn parse_bytes(&mut self) -> Option<&'a [u8]> {
-        let len = self.parse_usize()?;
-        self.expect_byte(b':')?;
-        let start = self.pos;
-        let end = self.pos.checked_add(len)?;
-        let out = self.raw.get(start..end)?;
-        self.pos = end;
-        Some(out)
-    }
-
-    fn parse_usize(&mut self) -> Option<usize> {
-        let mut n: usize = 0;
-        let mut saw = false;
-        while let Some(b) = self.peek() {
-            if !b.is_ascii_digit() {
-                break;
-            }
-            saw = true;
-            n = n.checked_mul(10)? + (b - b'0') as usize;
-            self.pos += 1;
+        if let Some(state) = self.pieces.get_mut(piece as usize) {
+            *state = PieceState::Received(data);
         }
-        if !saw { None } else { Some(n) }
     }
 
-    fn skip_value(&mut self) -> Option<()> {
-        match self.peek()? {
-            b'i' => {
-                self.pos += 1;
-                while self.peek()? != b'e' {
-                    self.pos += 1;
-                    if self.pos >= self.raw.len() {
-                        return None;
-                    }
-                }
-                self.pos += 1;
-                Some(())
-            }
-            b'l' => {
-                self.pos += 1;
-                while self.peek()? != b'e' {
-                    self.skip_value()?;
-                }
-                self.pos += 1;
-                Some(())
-            }
-            b'd' => {
-                self.pos += 1;
-                while self.peek()? != b'e' {
-                    self.parse_bytes()?;
-                    self.skip_value()?;
-                }
-                self.pos += 1;
-                Some(())
-            }
-            b'0'..=b'9' => {
-                let len = self.parse_usize()?;
-                self.expect_byte(b':')?;
-                self.pos = self.pos.checked_add(len)?;
-                if self.pos > self.raw.len() {
-                    return None;
-                }
-                Some(())
-            }
-            _ => None,
-        }
+    /// Pieces not received yet.
+    fn missing(&self) -> impl Iterator<Item = u32> + '_ {
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| !matches!(p, PieceState::Received(_)))
+            .map(|(i, _)| i as u32)
     }
-}
 
-async fn fetch_ut_metadata(addr: SocketAddr, info_hash: [u8; 20]) -> anyhow::Result<Vec<u8>> {
-    let peer_id = *PeerId::generate().as_bytes();
-    let mut conn = timeout(
-        Duration::from_secs(6),
-        PeerConnection::connect(addr, info_hash, peer_id),
-    )
-    .await
-    .context("peer connect timed out")??;
+    /// When the first outstanding request that `skip` doesn't rule out is overdue.
+    fn next_due(&self, skip: impl Fn(u32) -> bool) -> Option<tokio::time::Instant> {
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| match p {
+                PieceState::Requested { retry_at } if !skip(i as u32) => Some(*retry_at),
+                _ => None,
+            })
+            .min()
+    }
 
-    if !conn.supports_extension {
-        anyhow::bail!("peer does not support BEP-10");
+    /// The pieces as one contiguous buffer.
+    fn assemble(&self) -> anyhow::Result<Vec<u8>> {
+        let total_size = self.total_size.context("missing metadata total_size")?;
+        let mut out = Vec::with_capacity(total_size);
+        for state in &self.pieces {
+            let PieceState::Received(data) = state else {
+                anyhow::bail!("missing piece data");
+            };
+            out.extend_from_slice(data);
+        }
+        Ok(out)
     }
 
-    let mut hs = ExtensionHandshake::with_extensions(&[("ut_metadata", 1)]);
-    hs.client = Some("serma".to_string());
+    /// Drops all pieces, keeping the size.
+    fn reset(&mut self) {
+        for state in &mut self.pieces {
+            *state = PieceState::Wanted;
+        }
+    }
+}
 
-    let payload = hs.encode()?;
-    conn.send(Message::Extended { id: 0, payload }).await?;
+/// One peer's part in a `MetadataAssembly`: its outstanding requests, and the pieces it
+/// rejected or never answered.
+#[derive(Default)]
+struct PeerRequests {
+    /// Piece -> (asked for again at, requests so far).
+    outstanding: HashMap<u32, (tokio::time::Instant, u8)>,
+    given_up: HashSet<u32>,
+}
 
-    let (ut_metadata_id, mut total_size) = wait_for_peer_handshake(&mut conn).await?;
+impl PeerRequests {
+    /// Pieces to request from this peer now: its own overdue ones again, then new ones up
+    /// to the in-flight cap. Fails once every missing piece is one this peer gave up on.
+    fn due(&mut self, assembly: &mut MetadataAssembly, now: tokio::time::Instant) -> anyhow::Result<Vec<u32>> {
+        self.outstanding.retain(|&piece, _| !assembly.has(piece));
+        let mut due = Vec::new();
+        let mut exhausted = Vec::new();
+        for (&piece, (retry_at, tries)) in &mut self.outstanding {
+            if now < *retry_at {
+                continue;
+            }
+            if *tries >= METADATA_PIECE_TRIES {
+                exhausted.push(piece);
+                continue;
+            }
+            *retry_at = now + METADATA_PIECE_TIMEOUT;
+            *tries += 1;
+            assembly.requested(piece, now);
+            due.push(piece);
+        }
+        for piece in exhausted {
+            self.give_up(assembly, piece);
+        }
 
-    // If peer didn't advertise metadata_size, we still can request piece 0 to learn total_size.
-    if total_size.is_none() {
-        request_piece(&mut conn, ut_metadata_id, 0).await?;
-        let msg = recv_metadata_msg(&mut conn, ut_metadata_id, Duration::from_secs(6)).await?;
-        if msg.msg_type != MetadataMessageType::Data {
-            anyhow::bail!("peer did not send metadata data for piece 0");
+        while self.outstanding.len() < METADATA_IN_FLIGHT {
+            let Some(piece) = assembly.claim(|p| self.outstanding.contains_key(&p) || self.given_up.contains(&p), now) else {
+                break;
+            };
+            self.outstanding.insert(piece, (now + METADATA_PIECE_TIMEOUT, 1));
+            due.push(piece);
         }
-        total_size = msg.total_size;
+
+        if self.outstanding.is_empty() && assembly.missing().all(|p| self.given_up.contains(&p)) && !assembly.complete() {
+            anyhow::bail!("peer can't provide the remaining metadata pieces");
+        }
+        Ok(due)
     }
 
-    let total_size = total_size.context("missing metadata total_size")? as usize;
-    let piece_count = (total_size + (METADATA_PIECE_SIZE - 1)) / METADATA_PIECE_SIZE;
-    if piece_count == 0 {
-        anyhow::bail!("metadata has zero pieces");
+    /// When this peer should next look for work: one of its requests is overdue, or (with
+    /// room for more) one it could take over from another peer.
+    fn next_due(&self, assembly: &MetadataAssembly) -> Option<tokio::time::Instant> {
+        let own = self.outstanding.values().map(|(retry_at, _)| *retry_at).min();
+        let others = (self.outstanding.len() < METADATA_IN_FLIGHT)
+            .then(|| assembly.next_due(|p| self.outstanding.contains_key(&p) || self.given_up.contains(&p)))
+            .flatten();
+        own.into_iter().chain(others).min()
     }
 
-    // Request all pieces.
-    for piece in 0..piece_count {
-        request_piece(&mut conn, ut_metadata_id, piece as u32).await?;
+    fn received(&mut self, piece: u32) {
+        self.outstanding.remove(&piece);
     }
 
-    let mut pieces: Vec<Option<Bytes>> = vec![None; piece_count];
-    let deadline = tokio::time::Instant::now() + Duration::from_secs(12);
-    while pieces.iter().any(|p| p.is_none()) {
-        let now = tokio::time::Instant::now();
-        if now >= deadline {
-            anyhow::bail!("timed out waiting for metadata pieces");
-        }
-        let remaining = deadline - now;
-        let msg = recv_metadata_msg(&mut conn, ut_metadata_id, remaining).await?;
-        if msg.msg_type == MetadataMessageType::Reject {
-            anyhow::bail!("peer rejected metadata piece {}", msg.piece);
-        }
-        if msg.msg_type != MetadataMessageType::Data {
-            continue;
-        }
-        let Some(data) = msg.data else {
-            continue;
-        };
-        let idx = msg.piece as usize;
-        if idx < pieces.len() {
-            pieces[idx] = Some(data);
-        }
+    fn rejected(&mut self, assembly: &mut MetadataAssembly, piece: u32) {
+        self.give_up(assembly, piece);
     }
 
-    // Assemble into contiguous buffer.
-    let mut out = vec![0u8; total_size];
-    for (piece, maybe_data) in pieces.into_iter().enumerate() {
-        let data = maybe_data.context("missing piece data")?;
-        let expected = metadata_piece_size(piece as u32, total_size);
-        let offset = piece * METADATA_PIECE_SIZE;
-        let to_copy = expected
-            .min(data.len())
-            .min(out.len().saturating_sub(offset));
-        out[offset..offset + to_copy].copy_from_slice(&data[..to_copy]);
+    fn give_up(&mut self, assembly: &mut MetadataAssembly, piece: u32) {
+        if self.outstanding.remove(&piece).is_some() {
+            assembly.release(piece);
+        }
+        self.given_up.insert(piece);
     }
+}
 
-    Ok(out)
+/// Checks that metadata from a peer hashes to the torrent we asked for.
+///
+/// v1 and hybrid torrents are looked up by their SHA-1 hash; v2-only torrents by the
+/// first 20 bytes of their SHA-256 hash (BEP 52), and must carry a v2 file tree.
+pub(crate) fn verify_metadata(info_hash: &[u8; 20], info_bytes: &[u8]) -> anyhow::Result<()> {
+    if InfoHashV1::from_info_bytes(info_bytes).as_bytes() == info_hash {
+        return Ok(());
+    }
+    let v2 = InfoHashV2::from_info_bytes(info_bytes);
+    if &v2.as_bytes()[..20] != info_hash {
+        anyhow::bail!("metadata does not match info hash");
+    }
+    let info = bencode::decode(info_bytes)?;
+    if info.get(b"meta version").and_then(|v| v.as_integer()) != Some(2) {
+        anyhow::bail!("metadata matches truncated v2 hash but is not v2");
+    }
+    if info.get(b"file tree").and_then(|v| v.as_dict()).is_none_or(|tree| tree.is_empty()) {
+        anyhow::bail!("v2 metadata has no file tree");
+    }
+    Ok(())
 }
 
-async fn wait_for_peer_handshake(conn: &mut PeerConnection) -> anyhow::Result<(u8, Option<u32>)> {
+async fn wait_for_peer_handshake(conn: &mut PeerConn) -> anyhow::Result<(u8, Option<u32>)> {
     let deadline = tokio::time::Instant::now() + Duration::from_secs(6);
     loop {
         let now = tokio::time::Instant::now();
@@ -983,7 +1052,7 @@ async fn wait_for_peer_handshake(conn: &mut PeerConnection) -> anyhow::Result<(u
             anyhow::bail!("timed out waiting for extension handshake");
         }
         let remaining = deadline - now;
-        let msg = timeout(remaining, conn.receive()).await??;
+        let msg = timeout(remaining, conn.receive_message()).await??;
         let Message::Extended { id, payload } = msg else {
             continue;
         };
@@ -993,7 +1062,7 @@ async fn wait_for_peer_handshake(conn: &mut PeerConnection) -> anyhow::Result<(u
         };
 
         let Some(ut_id) = peer_hs.get_extension_id("ut_metadata") else {
-            anyhow::bail!("peer did not advertise ut_metadata");
+            anyhow::bail!(UnusablePeer("peer did not advertise ut_metadata"));
         };
 
         let total = peer_hs.metadata_size.and_then(|v| u32::try_from(v).ok());
@@ -1002,12 +1071,12 @@ async fn wait_for_peer_handshake(conn: &mut PeerConnection) -> anyhow::Result<(u
 }
 
 async fn request_piece(
-    conn: &mut PeerConnection,
+    conn: &mut PeerConn,
     ut_metadata_id: u8,
     piece: u32,
 ) -> anyhow::Result<()> {
     let payload = MetadataMessage::request(piece).encode()?;
-    conn.send(Message::Extended {
+    conn.send_message(&Message::Extended {
         id: ut_metadata_id,
         payload,
     })
@@ -1016,7 +1085,7 @@ async fn request_piece(
 }
 
 async fn recv_metadata_msg(
-    conn: &mut PeerConnection,
+    conn: &mut PeerConn,
     ut_metadata_id: u8,
     timeout_dur: Duration,
 ) -> anyhow::Result<MetadataMessage> {
@@ -1030,7 +1099,7 @@ async fn recv_metadata_msg(
             anyhow::bail!("timed out waiting for ut_metadata message");
         }
         let remaining = deadline - now;
-        let msg = timeout(remaining, conn.receive()).await??;
+        let msg = timeout(remaining, conn.receive_message()).await??;
         let Message::Extended { id, payload } = msg else {
             continue;
         };
@@ -1060,35 +1129,3 @@ fn extract_name_from_info(info_bencode: &[u8]) -> anyhow::Result<String> {
         .context("missing name")?;
     Ok(name.to_string())
 }
-
-async fn announce_seeders(
-    tracker: &TrackerClient,
-    info_hash: &InfoHash,
-    peer_id: &[u8; 20],
-    trackers: &[String],
-) -> Option<i64> {
-    let mut best: Option<i64> = None;
-    for url in trackers {
-        let params = AnnounceParams {
-            url,
-            info_hash,
-            peer_id,
-            port: 6881,
-            uploaded: 0,
-            downloaded: 0,
-            left: 1,
-            event: TrackerEvent::Started,
-        };
-
-        let resp = timeout(Duration::from_secs(6), tracker.announce(params)).await;
-        let Ok(Ok(resp)) = resp else {
-            continue;
-        };
-
-        if let Some(complete) = resp.complete {
-            let complete = complete as i64;
-            best = Some(best.map_or(complete, |b| b.max(complete)));
-        }
-    }
-    best
-}
@@ -0,0 +1,61 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// Live event feed for `/api/stream`.
+//
+// The spider and the enricher publish here as they work; every stream subscriber gets its
+// own receiver. Nothing is buffered for clients that aren't connected, and a subscriber
+// that falls too far behind skips ahead instead of slowing the publishers down.
+
+/// Events held for a slow subscriber before it starts missing some.
+const CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// The spider saw a hash for the first time.
+    Discovered { info_hash: String },
+    /// Enrichment fetched a torrent's metadata.
+    Enriched {
+        info_hash: String,
+        title: Option<String>,
+        size_bytes: u64,
+        file_count: u64,
+        seeders: i64,
+        leechers: i64,
+    },
+}
+
+impl Event {
+    /// SSE event name.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Discovered { .. } => "discovered",
+            Event::Enriched { .. } => "enriched",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Events {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    /// Sends to current subscribers; a no-op when nobody is listening.
+    pub fn publish(&self, event: Event) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.tx.subscribe()
+    }
+}
@@ -0,0 +1,149 @@
+use crate::index::SearchCursor;
+use crate::storage;
+use bytes::Bytes;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::io::Write;
+use tokio::sync::mpsc;
+
+// Full database export: every `TorrentRecord` as one JSON object per line (JSONL),
+// optionally gzip-compressed. Served by `GET /api/export?format=jsonl[.gz]` and written by
+// `serma export`; `serma import --format serma` reads it back (plain or .gz), so a corpus
+// can be moved to another machine or shared.
+//
+// Records include the info dict, so imported torrents don't need to be enriched again.
+//
+// Search results can be downloaded the same way: `GET /api/search/export` streams every
+// match of a query, not just a page, as JSONL or CSV.
+
+/// Bytes buffered before a chunk is handed to the HTTP response.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Writes every stored record as JSONL; returns the number of records written.
+pub fn write_jsonl(db: &crate::kv::Db, out: impl Write, gzip: bool) -> anyhow::Result<usize> {
+    if gzip {
+        let mut encoder = GzEncoder::new(out, Compression::default());
+        let written = write_records(db, &mut encoder)?;
+        encoder.finish()?.flush()?;
+        Ok(written)
+    } else {
+        let mut out = std::io::BufWriter::new(out);
+        let written = write_records(db, &mut out)?;
+        out.flush()?;
+        Ok(written)
+    }
+}
+
+fn write_records(db: &crate::kv::Db, out: &mut impl Write) -> anyhow::Result<usize> {
+    let mut written = 0usize;
+    for record in storage::scan_records(db) {
+        serde_json::to_writer(&mut *out, &record?)?;
+        out.write_all(b"\n")?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Streams the export as an HTTP body. sled is scanned on a blocking thread; a failure
+/// mid-way aborts the response, so clients see a truncated transfer rather than a short file.
+pub fn body(db: crate::kv::Db, gzip: bool) -> axum::body::Body {
+    stream_body("export", move |writer| {
+        let written = write_jsonl(&db, writer, gzip)?;
+        tracing::info!(written, gzip, "export: streamed");
+        Ok(())
+    })
+}
+
+/// Streams every match of a search (`SearchIndex::export`) as JSONL, or as CSV with a
+/// header row. Without `magnets` the magnet column is left empty (read-only visitors).
+pub fn search_body(export: impl FnOnce() -> anyhow::Result<SearchCursor> + Send + 'static, csv: bool, magnets: bool) -> axum::body::Body {
+    stream_body("search export", move |writer| {
+        let cursor = export()?;
+        let matches = cursor.remaining();
+        if csv {
+            let mut out = csv::Writer::from_writer(writer);
+            out.write_record(["info_hash", "title", "seeders", "leechers", "size_bytes", "source", "magnet"])?;
+            for hit in cursor {
+                let hit = hit?;
+                let magnet = hit.magnet.filter(|_| magnets).unwrap_or_default();
+                out.write_record([
+                    hit.info_hash.unwrap_or_default(),
+                    hit.title.unwrap_or_default(),
+                    hit.seeders.to_string(),
+                    hit.leechers.to_string(),
+                    hit.size_bytes.to_string(),
+                    hit.source.unwrap_or_default(),
+                    magnet,
+                ])?;
+            }
+            out.flush()?;
+        } else {
+            let mut out = writer;
+            for hit in cursor {
+                let mut hit = hit?;
+                if !magnets {
+                    hit.magnet = None;
+                }
+                serde_json::to_writer(&mut out, &hit)?;
+                out.write_all(b"\n")?;
+            }
+            out.flush()?;
+        }
+        tracing::debug!(matches, csv, "search export: streamed");
+        Ok(())
+    })
+}
+
+/// Runs `write` on a blocking thread and streams its output as an HTTP body; an error
+/// aborts the response. `what` names the stream in logs.
+pub(crate) fn stream_body(
+    what: &'static str,
+    write: impl FnOnce(ChunkWriter) -> anyhow::Result<()> + Send + 'static,
+) -> axum::body::Body {
+    let (tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(4);
+    tokio::task::spawn_blocking(move || {
+        let writer = ChunkWriter {
+            tx: tx.clone(),
+            buf: Vec::with_capacity(CHUNK_BYTES),
+        };
+        if let Err(err) = write(writer) {
+            tracing::warn!(err = %format!("{err:#}"), "{what}: stream failed");
+            let _ = tx.blocking_send(Err(std::io::Error::other(format!("{what} failed"))));
+        }
+    });
+    axum::body::Body::from_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }))
+}
+
+/// `Write` adapter that sends fixed-size chunks to the response stream.
+pub(crate) struct ChunkWriter {
+    tx: mpsc::Sender<std::io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn send(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_BYTES)));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_BYTES {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send()
+    }
+}
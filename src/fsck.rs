@@ -0,0 +1,122 @@
+use crate::index::{Reconciled, SearchIndex};
+use crate::kv::Db;
+use crate::storage::{self, Integrity};
+
+// Integrity check and repair (`serma fsck`, and SERMA_FSCK_ON_START before serving).
+//
+// Readers mostly step over stale secondary-index entries and ghost search hits when they
+// meet them, so damage from a crash or an older bug can sit unnoticed for a long time.
+// This walks the database (`storage::check_integrity`) and then the search index against it
+// (`SearchIndex::reconcile`), reporting everything it finds and, with `repair`, fixing it.
+
+/// What a check found; with `repaired`, it has also been fixed.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub storage: Integrity,
+    pub index: Reconciled,
+    pub repaired: bool,
+}
+
+impl Report {
+    pub fn problems(&self) -> u64 {
+        self.storage.problems() + self.index.removed + self.index.added
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fixed = |note: &'static str| if self.repaired { note } else { "" };
+        writeln!(f, "{} records checked", self.storage.records)?;
+        if self.storage.corrupt_records > 0 {
+            writeln!(f, "  {} unreadable records{}", self.storage.corrupt_records, fixed(" (removed)"))?;
+        }
+        for (tree, n) in &self.storage.stale_entries {
+            writeln!(f, "  {tree}: {n} stale entries{}", fixed(" (removed)"))?;
+        }
+        for (tree, n) in &self.storage.missing_entries {
+            writeln!(f, "  {tree}: {n} records missing{}", fixed(" (added)"))?;
+        }
+        if self.storage.counters_off {
+            writeln!(f, "  record counters off{}", fixed(" (recounted)"))?;
+        }
+        if self.index.removed > 0 {
+            writeln!(f, "  search index: {} hits without a record{}", self.index.removed, fixed(" (removed)"))?;
+        }
+        if self.index.added > 0 {
+            writeln!(f, "  search index: {} records not indexed{}", self.index.added, fixed(" (added)"))?;
+        }
+        match self.problems() {
+            0 => write!(f, "no problems found"),
+            n if self.repaired => write!(f, "{n} problems repaired"),
+            n => write!(f, "{n} problems found"),
+        }
+    }
+}
+
+/// Checks the database, then the index against it. The database goes first, so the index is
+/// compared with repaired records.
+pub fn run(db: &Db, index: &SearchIndex, repair: bool) -> anyhow::Result<Report> {
+    let storage = storage::check_integrity(db, repair)?;
+    let index = if repair { index.reconcile(db)? } else { index.check_against(db)? };
+    if repair {
+        db.flush()?;
+    }
+    Ok(Report {
+        storage,
+        index,
+        repaired: repair,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_and_repairs_divergence() {
+        let dir = crate::test_dir("fsck");
+        let db = crate::kv::open(crate::kv::Backend::Sled, &dir).unwrap();
+        let index = SearchIndex::open_or_create(dir.join("tantivy"), Default::default()).unwrap();
+        let hash = |n: u32| format!("{n:040x}");
+        let store = |n: u32, title: Option<&str>| {
+            let mut record = crate::storage::TorrentRecord::new(&hash(n), 0);
+            record.title = title.map(str::to_string);
+            storage::merge_imported(&db, record).unwrap().0
+        };
+        index.upsert_record(&store(1, Some("Arch Linux"))).unwrap();
+        store(2, None);
+        store(3, None);
+        // One lookup attempt, waiting for its retry.
+        let policy = storage::RetryPolicy { base_ms: 60_000, max_ms: 60_000, max_attempts: 3 };
+        assert_eq!(storage::take_missing_info(&db, 1, &policy).unwrap().len(), 1);
+        index.commit().unwrap();
+        assert_eq!(run(&db, &index, false).unwrap().problems(), 0);
+
+        db.insert(b"torrent:garbage", b"not a record").unwrap();
+        let mut ghost_seen = 0u64.to_be_bytes().to_vec();
+        ghost_seen.extend_from_slice(hash(9).as_bytes());
+        db.open_tree("idx_last_seen").unwrap().insert(ghost_seen, []).unwrap();
+        db.open_tree("idx_missing_info").unwrap().remove(hash(3)).unwrap();
+        db.open_tree("seeder_history").unwrap().insert(hash(9), b"").unwrap();
+        index.upsert(&hash(9), "Ghost", "", 0).unwrap();
+        store(4, Some("Debian"));
+        index.commit().unwrap();
+
+        let found = run(&db, &index, false).unwrap();
+        assert_eq!(found.storage.corrupt_records, 1);
+        assert_eq!(found.storage.stale_entries.get("idx_last_seen"), Some(&1));
+        assert_eq!(found.storage.stale_entries.get("seeder_history"), Some(&1));
+        // The queue entry that went with the removed missing-info entry.
+        assert_eq!(found.storage.stale_entries.get("idx_enrich_priority"), Some(&1));
+        assert_eq!(found.storage.missing_entries.get("idx_missing_info"), Some(&1));
+        assert_eq!(found.index, Reconciled { removed: 1, added: 1 });
+        assert_eq!(found.problems(), 7);
+
+        // Queued again under the same key, the leftover queue entry counts once more.
+        let repaired = run(&db, &index, true).unwrap();
+        assert!(!repaired.storage.stale_entries.contains_key("idx_enrich_priority"));
+        assert_eq!(repaired.problems(), 6);
+        assert_eq!(run(&db, &index, false).unwrap().problems(), 0);
+        assert_eq!(storage::record_counts(&db).unwrap().total, 4);
+    }
+}
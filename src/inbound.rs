@@ -0,0 +1,90 @@
+use crate::AppState;
+use crate::config::Config;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tokio::time::Duration;
+
+// Incoming peer connections for metadata (BEP-9 the other way round).
+//
+// With SERMA_ENRICH_LISTEN set, enrichment lookups announce the listen port to the closest
+// DHT nodes of each hash, so peers looking for the same torrent connect to us. Many of them
+// sit behind NAT and can't be reached the other way. A peer that offers a hash we still lack
+// metadata for gets our handshake and is asked for ut_metadata as usual; any other
+// connection is dropped. Plaintext only: peers that insist on encryption (MSE) aren't served.
+// Never on behind a SOCKS5 proxy, since announcing would reveal our address.
+
+/// Incoming connections handled at once; more are dropped.
+const MAX_INBOUND: usize = 32;
+/// Budget for one connection, handshakes included.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// The TCP port to announce with enrichment lookups, if we listen for peers.
+pub fn announce_port(config: &Config) -> Option<u16> {
+    if crate::socks5::Socks5Config::from_env().is_some() {
+        return None;
+    }
+    config.enrich_listen.map(|addr| addr.port())
+}
+
+pub async fn run(state: AppState) {
+    let Some(addr) = state.config.enrich_listen else {
+        return;
+    };
+    if announce_port(&state.config).is_none() {
+        tracing::info!("inbound: not listening for peers behind SERMA_SOCKS5_PROXY");
+        return;
+    }
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            tracing::warn!(%err, %addr, "inbound: failed binding SERMA_ENRICH_LISTEN; disabled");
+            return;
+        }
+    };
+    let tracker = match crate::scrape::http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%err, "inbound: failed creating HTTP client; disabled");
+            return;
+        }
+    };
+    tracing::info!(%addr, "inbound: listening for peers");
+
+    let sem = Arc::new(Semaphore::new(MAX_INBOUND));
+    let shutdown = &state.shutdown;
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(v) => v,
+                Err(err) => {
+                    // Usually out of file descriptors; give connections time to close.
+                    tracing::debug!(%err, "inbound: accept failed");
+                    shutdown.sleep(Duration::from_millis(200)).await;
+                    continue;
+                }
+            },
+            _ = shutdown.wait() => break,
+        };
+        let Ok(permit) = sem.clone().try_acquire_owned() else {
+            continue;
+        };
+
+        let state = state.clone();
+        let tracker = tracker.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let fetched = tokio::time::timeout(
+                CONNECTION_TIMEOUT,
+                crate::enrich::enrich_inbound(&state, &tracker, stream, peer),
+            )
+            .await;
+            match fetched {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => tracing::trace!(%err, %peer, "inbound: peer failed"),
+                Err(_) => tracing::trace!(%peer, "inbound: peer timed out"),
+            }
+        });
+    }
+    tracing::info!("inbound: stopped");
+}
@@ -1,15 +1,24 @@
 use anyhow::Context;
 use std::cmp::Ordering as CmpOrdering;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use tantivy::IndexSettings;
 use tantivy::ReloadPolicy;
-use tantivy::collector::TopDocs;
-use tantivy::query::{BooleanQuery, FuzzyTermQuery, Occur, Query, QueryParser, RegexQuery, TermQuery};
-use tantivy::schema::{FAST, Field, IndexRecordOption, STORED, STRING, Schema, TEXT, Value};
+use tantivy::collector::{Count, FacetCollector, TopDocs};
+use tantivy::Order;
+use tantivy::query::{
+    AllQuery, BooleanQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, QueryParser, RangeQuery, RegexQuery, TermQuery,
+};
+use tantivy::schema::{
+    FAST, Facet, FacetOptions, Field, IndexRecordOption, STORED, STRING, Schema, TextFieldIndexing, TextOptions,
+    Value,
+};
+use tantivy::tokenizer::{LowerCaser, NgramTokenizer, TextAnalyzer};
 use tantivy::{Score, Term};
 
 #[derive(Clone)]
@@ -24,9 +33,33 @@ struct SearchIndexInner {
     title: Field,
     magnet: Field,
     seeders: Field,
+    leechers: Field,
+    announces: Field,
+    files: Field,
+    size: Field,
+    info_hash_v2: Field,
+    first_seen: Field,
+    title_prefix: Field,
+    tags: Field,
+    category: Field,
+    source: Field,
+    resolution: Field,
+    codec: Field,
+    group: Field,
+    year: Field,
+    season: Field,
+    episode: Field,
+    tokenizer: TitleTokenizer,
+    options: WriterOptions,
     writer: Mutex<tantivy::IndexWriter>,
     pending_ops: AtomicUsize,
+    /// Upserts and deletes since open, for telling busy from idle.
+    writes: AtomicU64,
     last_commit_at: Mutex<Instant>,
+    /// True if the index was created empty on open (new or schema upgrade).
+    created: bool,
+    /// Set while `reindex` runs, so only one runs at a time.
+    reindexing: AtomicBool,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -35,15 +68,494 @@ pub struct SearchHit {
     pub title: Option<String>,
     pub magnet: Option<String>,
     pub seeders: i64,
+    pub leechers: i64,
+    /// Seeders plus leechers.
+    pub peers: i64,
+    /// Recent DHT announces seen by the spider.
+    pub announces: u64,
+    /// Total size in bytes (0 if unknown).
+    pub size_bytes: u64,
+    /// How the torrent was discovered (`announce`, `sample`, ...), if known.
+    pub source: Option<String>,
+    /// With title grouping, the info hashes of the other matches with the same title.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternates: Vec<String>,
+}
+
+/// One page of search results plus the number of matching documents and how many of
+/// them fall in each `category_of` category.
+#[derive(Debug, Default)]
+pub struct SearchPage {
+    pub hits: Vec<SearchHit>,
+    pub total: usize,
+    /// Matches per category; torrents without one (no metadata yet) aren't counted.
+    pub categories: BTreeMap<String, u64>,
+}
+
+/// Result order. Everything but `Relevance` sorts the whole match set on a fast field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchSort {
+    /// Text relevance with a gentle seeder boost.
+    #[default]
+    Relevance,
+    Seeders,
+    /// Date added (first seen), newest first.
+    Added,
+    Size,
+}
+
+impl SearchSort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "relevance" => Some(Self::Relevance),
+            "seeders" => Some(Self::Seeders),
+            "added" | "first_seen" | "date" => Some(Self::Added),
+            "size" => Some(Self::Size),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Relevance => "relevance",
+            Self::Seeders => "seeders",
+            Self::Added => "added",
+            Self::Size => "size",
+        }
+    }
+}
+
+/// How queries match and rank; instance defaults come from the config, and search requests
+/// can override them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryOptions {
+    /// Edit distance of the typo-tolerant fallback when nothing matches exactly (0 turns it
+    /// off). Words of up to 3 characters always match exactly, up to 5 with at most 1 edit.
+    pub fuzzy_distance: u8,
+    /// Whether every word must match (the default) or any of them.
+    pub match_all: bool,
+    /// Weight of swarm size (seeders, leechers, announces) next to text relevance in
+    /// `Relevance` order: 1 is the default blend, 0 ranks by text alone.
+    pub seeder_boost: f32,
+    /// Collapse matches with the same title (re-uploads) into one hit; see `group_by_title`.
+    pub group_titles: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            fuzzy_distance: 1,
+            match_all: true,
+            seeder_boost: 1.0,
+            group_titles: false,
+        }
+    }
+}
+
+/// Matches an export sorts at a time.
+const EXPORT_PAGE: usize = 10_000;
+
+/// Where a match sorts in an export: its sort value, then its address (lower first), so
+/// every match has its own key and a page can start right after the previous one's last.
+type ExportKey = (i64, std::cmp::Reverse<u32>, std::cmp::Reverse<tantivy::DocId>);
+
+/// Matches of a `SearchIndex::export`. They are sorted `EXPORT_PAGE` at a time, each
+/// page collecting only the matches after the previous one's last, and documents are
+/// loaded one at a time.
+pub struct SearchCursor {
+    index: SearchIndex,
+    searcher: tantivy::Searcher,
+    query: Option<Box<dyn Query>>,
+    sort: SearchSort,
+    page_size: usize,
+    page: std::vec::IntoIter<(ExportKey, tantivy::DocAddress)>,
+    after: Option<ExportKey>,
+    remaining: usize,
+}
+
+impl SearchCursor {
+    /// Matches not yet read.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// The next `page_size` matches after `self.after`, in export order.
+    fn next_page(&self, query: &dyn Query) -> anyhow::Result<Vec<(ExportKey, tantivy::DocAddress)>> {
+        let segments: Vec<tantivy::SegmentId> =
+            self.searcher.segment_readers().iter().map(|r| r.segment_id()).collect();
+        let (sort, after) = (self.sort, self.after);
+        let collector = TopDocs::with_limit(self.page_size).tweak_score(move |reader: &tantivy::SegmentReader| {
+            let segment = segments.iter().position(|id| *id == reader.segment_id()).unwrap_or_default() as u32;
+            let fast = reader.fast_fields();
+            let sizes = fast.u64("size").ok();
+            let values = match sort {
+                SearchSort::Added => fast.i64("first_seen").ok(),
+                SearchSort::Seeders => fast.i64("seeders").ok(),
+                SearchSort::Relevance | SearchSort::Size => None,
+            };
+            move |doc: tantivy::DocId, score: Score| {
+                let value = match sort {
+                    SearchSort::Relevance => i64::from(score_order(score)),
+                    SearchSort::Size => {
+                        let size = sizes.as_ref().and_then(|c| c.first(doc));
+                        size.map_or(i64::MIN, |v| v.min(i64::MAX as u64) as i64)
+                    }
+                    _ => values.as_ref().and_then(|c| c.first(doc)).unwrap_or(i64::MIN),
+                };
+                let key = (value, std::cmp::Reverse(segment), std::cmp::Reverse(doc));
+                // Matches up to the previous page's last sort below every later one.
+                (after.is_none_or(|after| key < after), key)
+            }
+        });
+        Ok(self
+            .searcher
+            .search(query, &collector)?
+            .into_iter()
+            .take_while(|((later, _), _)| *later)
+            .map(|((_, key), addr)| (key, addr))
+            .collect())
+    }
+}
+
+/// Maps a score to an integer with the same order.
+fn score_order(score: Score) -> i32 {
+    let bits = score.to_bits() as i32;
+    bits ^ (((bits >> 31) as u32) >> 1) as i32
+}
+
+impl Iterator for SearchCursor {
+    type Item = anyhow::Result<SearchHit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        if self.page.len() == 0 {
+            let page = match self.next_page(self.query.as_deref()?) {
+                Ok(page) if !page.is_empty() => page,
+                // The snapshot doesn't change, so an empty page means the count was off.
+                Ok(_) => {
+                    self.remaining = 0;
+                    return None;
+                }
+                Err(err) => {
+                    self.remaining = 0;
+                    return Some(Err(err));
+                }
+            };
+            self.page = page.into_iter();
+        }
+        let (key, addr) = self.page.next()?;
+        self.after = Some(key);
+        self.remaining -= 1;
+        Some(
+            self.searcher
+                .doc::<tantivy::schema::TantivyDocument>(addr)
+                .map(|doc| self.index.hit_from_doc(&doc))
+                .map_err(Into::into),
+        )
+    }
+}
+
+/// Restrictions on which matches a search returns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchFilters {
+    /// Smallest total size in bytes, inclusive.
+    pub min_size: Option<u64>,
+    /// Largest total size in bytes, inclusive.
+    pub max_size: Option<u64>,
+}
+
+impl SearchFilters {
+    fn is_empty(&self) -> bool {
+        self.min_size.is_none() && self.max_size.is_none()
+    }
+}
+
+/// With title grouping, matches fetched per result asked for (before grouping), and at
+/// most this many in all, so a page still fills up when many matches share titles.
+const GROUP_CANDIDATES: usize = 4;
+const MAX_GROUP_CANDIDATES: usize = 1000;
+
+/// Most matches a relevance search re-ranks by swarm size; its pages end there.
+pub const MAX_CANDIDATES: usize = 2000;
+/// Deepest offset of a search or browse page in a fast-field order. Collecting a page
+/// keeps every match before it in memory, so the depth has to stop somewhere.
+pub const MAX_OFFSET: usize = 100_000;
+
+/// Deepest `offset` worth asking `search_counted` for in `sort` order: relevance pages
+/// only reach as far as the re-ranked candidates. Deeper offsets are clamped to it.
+pub fn max_offset(sort: SearchSort, limit: usize) -> usize {
+    match sort {
+        SearchSort::Relevance => MAX_CANDIDATES.saturating_sub(limit),
+        _ => MAX_OFFSET,
+    }
+}
+
+/// How title and file-name text is split into searchable terms. Changing it rebuilds the
+/// index, since existing terms were made by the old one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TitleTokenizer {
+    /// Words split at anything not a letter or digit, lowercased (tantivy's `default`).
+    #[default]
+    Simple,
+    /// Like `Simple`, plus English stemming: "running" matches "runs".
+    EnStem,
+    /// Every 3 characters of the text, so words run together ("TheMatrix1999") and scripts
+    /// written without spaces match parts of them. Noisier matches and a larger index.
+    Ngram,
+}
+
+impl TitleTokenizer {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Simple => "simple",
+            Self::EnStem => "en_stem",
+            Self::Ngram => "ngram",
+        }
+    }
+
+    /// The name it is registered (and stored in the schema) under.
+    fn tantivy_name(self) -> &'static str {
+        match self {
+            Self::Simple => "default",
+            Self::EnStem => "en_stem",
+            Self::Ngram => NGRAM_TOKENIZER,
+        }
+    }
+}
+
+impl std::str::FromStr for TitleTokenizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('-', "_").as_str() {
+            "simple" | "default" => Ok(Self::Simple),
+            "en_stem" | "stem" => Ok(Self::EnStem),
+            "ngram" => Ok(Self::Ngram),
+            _ => Err("expected simple, en_stem or ngram".to_string()),
+        }
+    }
+}
+
+/// How the index writer buffers documents and which segments it merges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Tantivy's `LogMergePolicy`: segments of similar size are merged in the background once
+    /// `merge_min_segments` of them pile up.
+    #[default]
+    Log,
+    /// Never merge on its own; segments are only merged by `merge_segments` (compaction).
+    None,
+}
+
+impl MergePolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Log => "log",
+            Self::None => "none",
+        }
+    }
+}
+
+impl std::str::FromStr for MergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "log" | "default" => Ok(Self::Log),
+            "none" | "off" => Ok(Self::None),
+            _ => Err("expected log or none".to_string()),
+        }
+    }
+}
+
+/// Writer tuning: memory, merging and how often pending changes are committed. Every commit
+/// writes a segment, so committing often keeps search fresh at the cost of many small
+/// segments, and committing rarely the other way around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterOptions {
+    /// Indexing memory shared by the writer threads; a full buffer is flushed as a segment.
+    pub heap_bytes: usize,
+    pub merge_policy: MergePolicy,
+    /// Segments of one size class that `MergePolicy::Log` waits for before merging them.
+    pub merge_min_segments: usize,
+    /// Pending upserts that force a commit.
+    pub commit_every_ops: usize,
+    /// Least time between the commits `maybe_commit` makes.
+    pub commit_interval: Duration,
+    /// Documents between commits while rebuilding from storage.
+    pub reindex_batch: usize,
+    /// Documents per second a reindex on a running server is held to (0 is unlimited).
+    pub reindex_max_per_sec: u64,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            heap_bytes: 200 << 20,
+            merge_policy: MergePolicy::Log,
+            merge_min_segments: 8,
+            commit_every_ops: 100,
+            commit_interval: Duration::from_secs(2),
+            reindex_batch: 100_000,
+            reindex_max_per_sec: 0,
+        }
+    }
+}
+
+const NGRAM_TOKENIZER: &str = "serma_ngram3";
+
+/// What `reconcile` repaired.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reconciled {
+    /// Documents of records no longer stored.
+    pub removed: u64,
+    /// Records that were missing from the index.
+    pub added: u64,
+}
+
+/// Whether a record belongs in the index: titled, or with 2+ seeders.
+fn indexable(record: &crate::storage::TorrentRecord) -> bool {
+    record.title.is_some() || record.seeders >= 2
+}
+
+/// Segments with a smaller share of deleted documents aren't worth rewriting by `purge_deletes`.
+const MIN_DELETED_SHARE: f64 = 0.1;
+
+/// What `purge_deletes` rewrote.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Purge {
+    pub segments: usize,
+    pub deleted_docs: u64,
+}
+
+/// Largest edit distance tantivy's fuzzy queries support.
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+
+/// Searchable file-name text per torrent is capped so huge file lists can't bloat the index.
+const MAX_FILES_TEXT: usize = 64 * 1024;
+
+/// Title words are indexed for autocomplete by every prefix up to this many characters.
+const MAX_PREFIX_CHARS: usize = 15;
+
+struct Fields {
+    info_hash: Field,
+    title: Field,
+    magnet: Field,
+    seeders: Field,
+    leechers: Field,
+    announces: Field,
+    files: Field,
+    size: Field,
+    info_hash_v2: Field,
+    first_seen: Field,
+    title_prefix: Field,
+    tags: Field,
+    category: Field,
+    source: Field,
+    resolution: Field,
+    codec: Field,
+    group: Field,
+    year: Field,
+    season: Field,
+    episode: Field,
+}
+
+impl Fields {
+    /// The fields of `schema` if it is the one this binary builds, `tokenizer` included.
+    fn from_schema(schema: &Schema, tokenizer: TitleTokenizer) -> Option<Self> {
+        for name in ["title", "files"] {
+            let field = schema.get_field(name).ok()?;
+            let tokenized_by = match schema.get_field_entry(field).field_type() {
+                tantivy::schema::FieldType::Str(options) => options.get_indexing_options()?.tokenizer(),
+                _ => return None,
+            };
+            if tokenized_by != tokenizer.tantivy_name() {
+                return None;
+            }
+        }
+        // Older indexes kept the category as a plain string.
+        let category = schema.get_field("category").ok()?;
+        if !matches!(schema.get_field_entry(category).field_type(), tantivy::schema::FieldType::Facet(_)) {
+            return None;
+        }
+        Some(Self {
+            info_hash: schema.get_field("info_hash").ok()?,
+            title: schema.get_field("title").ok()?,
+            magnet: schema.get_field("magnet").ok()?,
+            seeders: schema.get_field("seeders").ok()?,
+            leechers: schema.get_field("leechers").ok()?,
+            announces: schema.get_field("announces").ok()?,
+            files: schema.get_field("files").ok()?,
+            size: schema.get_field("size").ok()?,
+            info_hash_v2: schema.get_field("info_hash_v2").ok()?,
+            first_seen: schema.get_field("first_seen").ok()?,
+            title_prefix: schema.get_field("title_prefix").ok()?,
+            tags: schema.get_field("tags").ok()?,
+            category,
+            source: schema.get_field("source").ok()?,
+            resolution: schema.get_field("resolution").ok()?,
+            codec: schema.get_field("codec").ok()?,
+            group: schema.get_field("group").ok()?,
+            year: schema.get_field("year").ok()?,
+            season: schema.get_field("season").ok()?,
+            episode: schema.get_field("episode").ok()?,
+        })
+    }
 }
 
 impl SearchIndex {
-    pub fn open_or_create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+    pub fn open_or_create(path: impl AsRef<Path>, tokenizer: TitleTokenizer) -> anyhow::Result<Self> {
+        Self::open_with(path, tokenizer, WriterOptions::default())
+    }
+
+    /// Like `open_or_create`, with the writer tuned by `options`.
+    pub fn open_with(path: impl AsRef<Path>, tokenizer: TitleTokenizer, options: WriterOptions) -> anyhow::Result<Self> {
+        let tokenized = |stored: bool| {
+            let options = TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(tokenizer.tantivy_name())
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            );
+            if stored { options.set_stored() } else { options }
+        };
         let mut expected_schema_builder = Schema::builder();
         expected_schema_builder.add_text_field("info_hash", STRING | STORED);
-        expected_schema_builder.add_text_field("title", TEXT | STORED);
+        expected_schema_builder.add_text_field("title", tokenized(true));
         expected_schema_builder.add_text_field("magnet", STORED);
         expected_schema_builder.add_i64_field("seeders", FAST | STORED);
+        expected_schema_builder.add_i64_field("leechers", FAST | STORED);
+        expected_schema_builder.add_u64_field("announces", FAST | STORED);
+        expected_schema_builder.add_text_field("files", tokenized(false));
+        expected_schema_builder.add_u64_field("size", FAST | STORED);
+        expected_schema_builder.add_text_field("info_hash_v2", STRING);
+        expected_schema_builder.add_i64_field("first_seen", FAST);
+        // Every prefix of every title word ("ubu", "ubun", ...), for `suggest`. Matching only,
+        // so no positions or frequencies.
+        expected_schema_builder.add_text_field(
+            "title_prefix",
+            TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer("default")
+                    .set_index_option(IndexRecordOption::Basic),
+            ),
+        );
+        // Admin-set tags, one `/<tag>` facet each, for `tag:` filters.
+        expected_schema_builder.add_facet_field("tags", FacetOptions::default());
+        // `category_of` the file list as a `/<category>` facet, for `category:` filters and
+        // per-category counts.
+        expected_schema_builder.add_facet_field("category", FacetOptions::default());
+        // How the torrent was discovered, for `source:` filters.
+        expected_schema_builder.add_text_field("source", STRING | STORED);
+        // The title's `release::parse` fields, for `resolution:`, `codec:`, `group:`, `year:`,
+        // `season:` and `episode:` filters. Resolution, codec and group are lowercase.
+        expected_schema_builder.add_text_field("resolution", STRING);
+        expected_schema_builder.add_text_field("codec", STRING);
+        expected_schema_builder.add_text_field("group", STRING);
+        expected_schema_builder.add_u64_field("year", FAST);
+        expected_schema_builder.add_u64_field("season", FAST);
+        expected_schema_builder.add_u64_field("episode", FAST);
         let expected_schema = expected_schema_builder.build();
 
         std::fs::create_dir_all(path.as_ref()).context("create index directory")?;
@@ -53,18 +565,10 @@ impl SearchIndex {
         // IMPORTANT: When opening an existing Tantivy index, always use the schema
         // stored in that index for field IDs. Mixing field IDs from a newly built
         // schema with an on-disk schema can panic inside Tantivy.
-        let (index, info_hash, title, magnet, seeders) = match tantivy::Index::open(dir.clone()) {
+        let (index, fields, created) = match tantivy::Index::open(dir.clone()) {
             Ok(index) => {
-                let schema = index.schema();
-                let info_hash = schema.get_field("info_hash").ok();
-                let title = schema.get_field("title").ok();
-                let magnet = schema.get_field("magnet").ok();
-                let seeders = schema.get_field("seeders").ok();
-
-                if let (Some(info_hash), Some(title), Some(magnet), Some(seeders)) =
-                    (info_hash, title, magnet, seeders)
-                {
-                    (index, info_hash, title, magnet, seeders)
+                if let Some(fields) = Fields::from_schema(&index.schema(), tokenizer) {
+                    (index, fields, false)
                 } else {
                     tracing::warn!(
                         path = %path.as_ref().display(),
@@ -73,7 +577,8 @@ impl SearchIndex {
                     drop(index);
 
                     // Tantivy does not support in-place schema migrations.
-                    // Recreate the index directory so the schema matches the binary.
+                    // Recreate the index directory so the schema matches the binary;
+                    // the caller rebuilds it from storage (see `was_created`).
                     std::fs::remove_dir_all(path.as_ref()).ok();
                     std::fs::create_dir_all(path.as_ref())
                         .context("recreate index directory")?;
@@ -81,37 +586,21 @@ impl SearchIndex {
                         .context("reopen index directory")?;
                     let index = tantivy::Index::create(dir, expected_schema.clone(), IndexSettings::default())
                         .context("create index")?;
-                    let schema = index.schema();
-                    let info_hash = schema
-                        .get_field("info_hash")
-                        .context("missing info_hash field")?;
-                    let title = schema.get_field("title").context("missing title field")?;
-                    let magnet = schema
-                        .get_field("magnet")
-                        .context("missing magnet field")?;
-                    let seeders = schema
-                        .get_field("seeders")
-                        .context("missing seeders field")?;
-                    (index, info_hash, title, magnet, seeders)
+                    let fields = Fields::from_schema(&index.schema(), tokenizer).context("missing index field")?;
+                    (index, fields, true)
                 }
             }
             Err(_) => {
                 let index = tantivy::Index::create(dir, expected_schema.clone(), IndexSettings::default())
                     .context("create index")?;
-                let schema = index.schema();
-                let info_hash = schema
-                    .get_field("info_hash")
-                    .context("missing info_hash field")?;
-                let title = schema.get_field("title").context("missing title field")?;
-                let magnet = schema
-                    .get_field("magnet")
-                    .context("missing magnet field")?;
-                let seeders = schema
-                    .get_field("seeders")
-                    .context("missing seeders field")?;
-                (index, info_hash, title, magnet, seeders)
+                let fields = Fields::from_schema(&index.schema(), tokenizer).context("missing index field")?;
+                (index, fields, true)
             }
         };
+        let ngram = NgramTokenizer::new(3, 3, false).context("build ngram tokenizer")?;
+        index
+            .tokenizers()
+            .register(NGRAM_TOKENIZER, TextAnalyzer::builder(ngram).filter(LowerCaser).build());
 
         let reader = index
             .reader_builder()
@@ -119,32 +608,209 @@ impl SearchIndex {
             .try_into()
             .context("build index reader")?;
 
-        let writer = index.writer(200_000_000)?;
+        let writer = index.writer(options.heap_bytes).context("create index writer")?;
+        match options.merge_policy {
+            MergePolicy::Log => {
+                let mut policy = tantivy::indexer::LogMergePolicy::default();
+                policy.set_min_num_segments(options.merge_min_segments);
+                writer.set_merge_policy(Box::new(policy));
+            }
+            MergePolicy::None => writer.set_merge_policy(Box::new(tantivy::indexer::NoMergePolicy)),
+        }
 
         Ok(Self {
             inner: Arc::new(SearchIndexInner {
                 index,
                 reader,
-                info_hash,
-                title,
-                magnet,
-                seeders,
+                info_hash: fields.info_hash,
+                title: fields.title,
+                magnet: fields.magnet,
+                seeders: fields.seeders,
+                leechers: fields.leechers,
+                announces: fields.announces,
+                files: fields.files,
+                size: fields.size,
+                info_hash_v2: fields.info_hash_v2,
+                first_seen: fields.first_seen,
+                title_prefix: fields.title_prefix,
+                tags: fields.tags,
+                category: fields.category,
+                source: fields.source,
+                resolution: fields.resolution,
+                codec: fields.codec,
+                group: fields.group,
+                year: fields.year,
+                season: fields.season,
+                episode: fields.episode,
+                tokenizer,
+                options,
                 writer: Mutex::new(writer),
                 pending_ops: AtomicUsize::new(0),
+                writes: AtomicU64::new(0),
                 // Ensure the very first maybe_commit() can commit immediately.
                 // Otherwise, a single ingested hash can remain uncommitted and therefore unsearchable.
                 last_commit_at: Mutex::new(Instant::now() - Duration::from_secs(3600)),
+                created,
+                reindexing: AtomicBool::new(false),
             }),
         })
     }
 
-    pub fn upsert(
-        &self,
-        info_hash_hex: &str,
-        title: &str,
-        magnet: &str,
-        seeders: i64,
-    ) -> anyhow::Result<()> {
+    /// True if the index started out empty (first run, deleted, or recreated for a new schema).
+    pub fn was_created(&self) -> bool {
+        self.inner.created
+    }
+
+    /// Re-adds every record that would normally be indexed (titled, or with 2+ seeders).
+    pub fn rebuild_from(&self, db: &crate::kv::Db) -> anyhow::Result<usize> {
+        self.rebuild(db, 0)
+    }
+
+    /// Commits every `reindex_batch` documents rather than every `commit_every_ops`, so a
+    /// rebuild writes a few large segments, and holds to `max_per_sec` documents a second.
+    fn rebuild(&self, db: &crate::kv::Db, max_per_sec: u64) -> anyhow::Result<usize> {
+        let total = crate::storage::record_counts(db)?.total;
+        let started = Instant::now();
+        let mut scanned = 0;
+        let mut indexed = 0;
+        for record in crate::storage::scan_records(db) {
+            let record = record?;
+            scanned += 1;
+            if scanned % 100_000 == 0 {
+                tracing::info!(scanned, total, indexed, "index: rebuilding from storage");
+            }
+            if !indexable(&record) {
+                continue;
+            }
+            self.add_record(&record, self.inner.options.reindex_batch)?;
+            indexed += 1;
+            if max_per_sec > 0 && indexed % 64 == 0 {
+                let due = Duration::from_secs_f64(indexed as f64 / max_per_sec as f64);
+                if let Some(ahead) = due.checked_sub(started.elapsed()) {
+                    std::thread::sleep(ahead);
+                }
+            }
+        }
+        self.commit()?;
+        Ok(indexed)
+    }
+
+    /// Brings the index back in step with storage after a crash: documents whose record is
+    /// gone are deleted, and records that should be indexed but aren't are added. Records
+    /// whose latest change didn't reach the index are only caught if they were new.
+    pub fn reconcile(&self, db: &crate::kv::Db) -> anyhow::Result<Reconciled> {
+        self.compare(db, true)
+    }
+
+    /// What `reconcile` would repair, without changing anything.
+    pub fn check_against(&self, db: &crate::kv::Db) -> anyhow::Result<Reconciled> {
+        self.compare(db, false)
+    }
+
+    fn compare(&self, db: &crate::kv::Db, repair: bool) -> anyhow::Result<Reconciled> {
+        self.inner.reader.reload()?;
+        let searcher = self.inner.reader.searcher();
+        let mut reconciled = Reconciled::default();
+
+        // A deleted document's hash stays in the term dictionary until its segment is
+        // merged, so only count the ones that still match.
+        let mut ghosts = std::collections::BTreeSet::new();
+        for segment in searcher.segment_readers() {
+            let inverted = segment.inverted_index(self.inner.info_hash)?;
+            let mut terms = inverted.terms().stream()?;
+            while terms.advance() {
+                let hash = String::from_utf8_lossy(terms.key()).into_owned();
+                if !ghosts.contains(&hash) && !crate::storage::contains(db, &hash)? {
+                    ghosts.insert(hash);
+                }
+            }
+        }
+        for hash in ghosts {
+            let query = TermQuery::new(Term::from_field_text(self.inner.info_hash, &hash), IndexRecordOption::Basic);
+            if searcher.search(&query, &Count)? > 0 {
+                if repair {
+                    self.delete(&hash)?;
+                }
+                reconciled.removed += 1;
+            }
+        }
+
+        for record in crate::storage::scan_records(db) {
+            // An unreadable record is `storage::check_integrity`'s to report.
+            let Ok(record) = record else { continue };
+            if indexable(&record)
+                && searcher.doc_freq(&Term::from_field_text(self.inner.info_hash, &record.info_hash_hex))? == 0
+            {
+                if repair {
+                    self.add_record(&record, self.inner.options.reindex_batch)?;
+                }
+                reconciled.added += 1;
+            }
+        }
+        self.commit()?;
+        Ok(reconciled)
+    }
+
+    /// Drops every document and rebuilds the index from storage, for a running server.
+    /// Searches see a partial index until it finishes. Returns `None` if a reindex is
+    /// already running.
+    pub fn reindex(&self, db: &crate::kv::Db) -> anyhow::Result<Option<usize>> {
+        if self.inner.reindexing.swap(true, Ordering::AcqRel) {
+            return Ok(None);
+        }
+        let result = self
+            .clear()
+            .and_then(|()| self.rebuild(db, self.inner.options.reindex_max_per_sec));
+        self.inner.reindexing.store(false, Ordering::Release);
+        let indexed = result?;
+
+        // Drop the segment files the old documents lived in.
+        let writer = self
+            .inner
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("tantivy writer lock poisoned"))?;
+        writer.garbage_collect_files().wait()?;
+        Ok(Some(indexed))
+    }
+
+    pub fn is_reindexing(&self) -> bool {
+        self.inner.reindexing.load(Ordering::Acquire)
+    }
+
+    fn clear(&self) -> anyhow::Result<()> {
+        let mut writer = self
+            .inner
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("tantivy writer lock poisoned"))?;
+        writer.delete_all_documents()?;
+        self.commit_locked(&mut writer)
+    }
+
+    /// Indexes a stored record with its file names and size.
+    /// Untitled records are indexed as "Torrent <hash>".
+    pub fn upsert_record(&self, record: &crate::storage::TorrentRecord) -> anyhow::Result<()> {
+        self.add_record(record, self.inner.options.commit_every_ops)
+    }
+
+    /// `upsert_record`, committing once `commit_after` operations are pending.
+    fn add_record(&self, record: &crate::storage::TorrentRecord, commit_after: usize) -> anyhow::Result<()> {
+        let title = record
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("Torrent {}", &record.info_hash_hex));
+        let mut files = String::new();
+        for file in &record.files {
+            if files.len() + file.path.len() + 1 > MAX_FILES_TEXT {
+                break;
+            }
+            files.push_str(&file.path);
+            files.push('\n');
+        }
+        let info_hash_hex = record.info_hash_hex.as_str();
+        let magnet = record.magnet.as_deref().unwrap_or_default();
+
         let mut writer = self
             .inner
             .writer
@@ -157,22 +823,80 @@ impl SearchIndex {
 
         let mut doc = tantivy::schema::TantivyDocument::default();
         doc.add_text(self.inner.info_hash, info_hash_hex);
-        doc.add_text(self.inner.title, title);
+        doc.add_text(self.inner.title, &title);
         if !magnet.trim().is_empty() {
             doc.add_text(self.inner.magnet, magnet);
         }
-        doc.add_i64(self.inner.seeders, seeders);
+        doc.add_i64(self.inner.seeders, record.seeders);
+        doc.add_i64(self.inner.leechers, record.leechers);
+        doc.add_u64(self.inner.announces, record.announces);
+        doc.add_u64(self.inner.size, record.size_bytes);
+        doc.add_i64(self.inner.first_seen, record.first_seen_unix_ms);
+        if !files.is_empty() {
+            doc.add_text(self.inner.files, &files);
+        }
+        if let Some(v2) = record.info_hash_v2_hex.as_deref() {
+            doc.add_text(self.inner.info_hash_v2, v2);
+        }
+        // Placeholder titles of untitled records aren't worth suggesting.
+        if let Some(title) = record.title.as_deref() {
+            doc.add_text(self.inner.title_prefix, self.title_prefixes(title));
+        }
+        for tag in &record.tags {
+            doc.add_facet(self.inner.tags, Facet::from_path([tag]));
+        }
+        if let Some(category) = category_of(&record.files) {
+            doc.add_facet(self.inner.category, Facet::from_path([category]));
+        }
+        if let Some(via) = record.discovered_via {
+            doc.add_text(self.inner.source, via.as_str());
+        }
+        if let Some(release) = record.release.as_ref() {
+            if let Some(resolution) = release.resolution.as_deref() {
+                doc.add_text(self.inner.resolution, resolution);
+            }
+            if let Some(codec) = release.codec.as_deref() {
+                doc.add_text(self.inner.codec, codec);
+            }
+            if let Some(group) = release.group.as_deref() {
+                doc.add_text(self.inner.group, group.to_lowercase());
+            }
+            if let Some(year) = release.year {
+                doc.add_u64(self.inner.year, year.into());
+            }
+            if let Some(season) = release.season {
+                doc.add_u64(self.inner.season, season.into());
+            }
+            if let Some(episode) = release.episode {
+                doc.add_u64(self.inner.episode, episode.into());
+            }
+        }
 
         writer.add_document(doc)?;
 
+        self.inner.writes.fetch_add(1, Ordering::Relaxed);
         let pending = self.inner.pending_ops.fetch_add(1, Ordering::Relaxed) + 1;
-        if pending >= 100 {
+        if pending >= commit_after {
             self.commit_locked(&mut writer)?;
         }
 
         Ok(())
     }
 
+    pub fn upsert(
+        &self,
+        info_hash_hex: &str,
+        title: &str,
+        magnet: &str,
+        seeders: i64,
+    ) -> anyhow::Result<()> {
+        let mut record = crate::storage::TorrentRecord::new(info_hash_hex, 0);
+        record.title = Some(title.to_string());
+        record.magnet = Some(magnet.to_string());
+        record.seeders = seeders;
+        self.upsert_record(&record)
+    }
+
     pub fn delete(&self, info_hash_hex: &str) -> anyhow::Result<()> {
         let writer = self
             .inner
@@ -183,6 +907,7 @@ impl SearchIndex {
         let term = Term::from_field_text(self.inner.info_hash, info_hash_hex);
         writer.delete_term(term);
 
+        self.inner.writes.fetch_add(1, Ordering::Relaxed);
         self.inner.pending_ops.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
@@ -203,7 +928,7 @@ impl SearchIndex {
             .last_commit_at
             .lock()
             .map_err(|_| anyhow::anyhow!("tantivy commit lock poisoned"))?;
-        if last_commit_at.elapsed() < Duration::from_secs(2) {
+        if last_commit_at.elapsed() < self.inner.options.commit_interval {
             return Ok(());
         }
 
@@ -212,41 +937,357 @@ impl SearchIndex {
         Ok(())
     }
 
+    /// Commits pending operations now, ignoring the `maybe_commit` rate limit.
+    /// Intended for batch jobs (ingest/import) and shutdown.
+    pub fn commit(&self) -> anyhow::Result<()> {
+        let mut writer = self
+            .inner
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("tantivy writer lock poisoned"))?;
+        if self.inner.pending_ops.load(Ordering::Relaxed) == 0 {
+            return Ok(());
+        }
+
+        self.commit_locked(&mut writer)?;
+        if let Ok(mut last_commit_at) = self.inner.last_commit_at.lock() {
+            *last_commit_at = Instant::now();
+        }
+        Ok(())
+    }
+
     fn commit_locked(&self, writer: &mut tantivy::IndexWriter) -> anyhow::Result<()> {
         writer.commit()?;
         self.inner.pending_ops.store(0, Ordering::Relaxed);
         Ok(())
     }
 
-    pub fn search(&self, q: &str, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
-        self.search_page(q, 0, limit)
+    /// Merges every segment into one and removes files the index no longer uses.
+    /// Returns the number of segments before the merge.
+    pub fn merge_segments(&self) -> anyhow::Result<usize> {
+        let mut writer = self
+            .inner
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("tantivy writer lock poisoned"))?;
+        self.commit_locked(&mut writer)?;
+        let segments = self.inner.index.searchable_segment_ids()?;
+        if segments.len() > 1 {
+            writer.merge(&segments).wait()?;
+        }
+        writer.garbage_collect_files().wait()?;
+        Ok(segments.len())
     }
 
-    pub fn search_page(&self, q: &str, offset: usize, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
-        let q = q.trim();
-        if q.is_empty() || limit == 0 {
-            return Ok(Vec::new());
+    /// Upserts and deletes since the index was opened.
+    pub fn write_count(&self) -> u64 {
+        self.inner.writes.load(Ordering::Relaxed)
+    }
+
+    /// Rewrites the segments where deleted documents take up the most room, without them,
+    /// up to `max_docs` documents in all. Unlike `merge_segments` the writer stays free while
+    /// the merge runs.
+    pub async fn purge_deletes(&self, max_docs: u64) -> anyhow::Result<Purge> {
+        self.commit()?;
+        let mut metas: Vec<_> = self
+            .inner
+            .index
+            .searchable_segment_metas()?
+            .into_iter()
+            .filter(|meta| meta.num_deleted_docs() as f64 >= meta.max_doc() as f64 * MIN_DELETED_SHARE)
+            .collect();
+        let share = |meta: &tantivy::index::SegmentMeta| meta.num_deleted_docs() as f64 / meta.max_doc() as f64;
+        metas.sort_by(|a, b| share(b).total_cmp(&share(a)));
+
+        let mut purge = Purge::default();
+        let mut segments = Vec::new();
+        let mut docs = 0;
+        for meta in metas {
+            if docs + u64::from(meta.max_doc()) > max_docs {
+                continue;
+            }
+            docs += u64::from(meta.max_doc());
+            purge.deleted_docs += u64::from(meta.num_deleted_docs());
+            segments.push(meta.id());
         }
+        purge.segments = segments.len();
+        if segments.is_empty() {
+            return Ok(purge);
+        }
+
+        let merged = self
+            .inner
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("tantivy writer lock poisoned"))?
+            .merge(&segments);
+        merged.await?;
+        let collected = self
+            .inner
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("tantivy writer lock poisoned"))?
+            .garbage_collect_files();
+        collected.await?;
+        Ok(purge)
+    }
+
+    /// Commits, then hard-links the committed index files from `dir` (the index directory)
+    /// into `dest`, copying where a link isn't possible. Segment files never change once
+    /// written, so the links stay a consistent snapshot while the index moves on; a merge that
+    /// finishes in between is caught by re-reading the segment list, and the snapshot retried.
+    /// Returns the number of documents in the snapshot.
+    pub fn snapshot_files(&self, dir: &Path, dest: &Path) -> anyhow::Result<u64> {
+        let mut writer = self
+            .inner
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("tantivy writer lock poisoned"))?;
+        self.commit_locked(&mut writer)?;
+        for _ in 0..3 {
+            let metas = self.inner.index.searchable_segment_metas()?;
+            let _ = std::fs::remove_dir_all(dest);
+            std::fs::create_dir_all(dest).context("create index snapshot directory")?;
+            let mut files: Vec<_> = metas.iter().flat_map(|meta| meta.list_files()).collect();
+            files.push("meta.json".into());
+            for file in &files {
+                let (from, to) = (dir.join(file), dest.join(file));
+                match std::fs::hard_link(&from, &to).or_else(|_| std::fs::copy(&from, &to).map(|_| ())) {
+                    Ok(()) => {}
+                    // Segments without deletes have no .del file.
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound && file.as_os_str() != "meta.json" => {}
+                    Err(err) => return Err(err).with_context(|| format!("snapshot {}", from.display())),
+                }
+            }
+            let before: Vec<_> = metas.iter().map(|m| m.id()).collect();
+            if self.inner.index.searchable_segment_ids()? == before {
+                return Ok(metas.iter().map(|m| m.num_docs() as u64).sum());
+            }
+        }
+        anyhow::bail!("index kept changing while taking a snapshot")
+    }
+
+    /// Documents visible to searches (committed).
+    pub fn num_docs(&self) -> u64 {
+        self.inner.reader.searcher().num_docs()
+    }
+
+    pub fn search(&self, q: &str, limit: usize, opts: QueryOptions) -> anyhow::Result<Vec<SearchHit>> {
+        self.search_page(q, 0, limit, opts)
+    }
+
+    pub fn search_page(&self, q: &str, offset: usize, limit: usize, opts: QueryOptions) -> anyhow::Result<Vec<SearchHit>> {
+        let filters = SearchFilters::default();
+        Ok(self.search_counted(q, SearchSort::Relevance, offset, limit, opts, filters)?.hits)
+    }
 
+    /// Like `search_page` in the given order, also counting all matches (of whichever
+    /// query produced hits). `field:value` words in `q` (see `parse_query`) restrict the
+    /// matches; a query of nothing else lists every torrent passing them. With
+    /// `group_titles`, `offset` and `limit` count groups, while `total` still counts torrents.
+    pub fn search_counted(
+        &self,
+        q: &str,
+        sort: SearchSort,
+        offset: usize,
+        limit: usize,
+        opts: QueryOptions,
+        filters: SearchFilters,
+    ) -> anyhow::Result<SearchPage> {
+        let offset = offset.min(max_offset(sort, limit));
+        if !opts.group_titles {
+            return self.search_ungrouped(q, sort, offset, limit, opts, filters);
+        }
+        // Groups are only known once the matches before the page are, so fetch those too.
         let requested = offset.saturating_add(limit);
-        if requested == 0 {
-            return Ok(Vec::new());
+        let candidates = requested.saturating_mul(GROUP_CANDIDATES).min(MAX_GROUP_CANDIDATES).max(requested);
+        let page = self.search_ungrouped(q, sort, 0, candidates, opts, filters)?;
+        Ok(SearchPage {
+            hits: group_by_title(page.hits).into_iter().skip(offset).take(limit).collect(),
+            total: page.total,
+            categories: page.categories,
+        })
+    }
+
+    fn search_ungrouped(
+        &self,
+        q: &str,
+        sort: SearchSort,
+        offset: usize,
+        limit: usize,
+        opts: QueryOptions,
+        filters: SearchFilters,
+    ) -> anyhow::Result<SearchPage> {
+        let (q, fields) = parse_query(q);
+        if (q.is_empty() && fields.is_empty()) || limit == 0 {
+            return Ok(SearchPage::default());
         }
+        let q = q.as_str();
+
+        let requested = offset.saturating_add(limit);
 
         // Ensure we see recent commits.
         self.inner.reader.reload().ok();
         let searcher = self.inner.reader.searcher();
 
-        let strict_query = self.build_query(q, QueryMode::Strict)?;
-        let mut scored_docs = self.search_and_score(&searcher, strict_query.as_ref(), requested)?;
+        let run = |query: &dyn Query| -> anyhow::Result<SearchPage> {
+            let mut page = match sort {
+                SearchSort::Relevance => {
+                    let (hits, total) = self.search_and_score(&searcher, query, requested, opts.seeder_boost)?;
+                    SearchPage {
+                        hits: hits.into_iter().skip(offset).take(limit).collect(),
+                        total,
+                        categories: BTreeMap::new(),
+                    }
+                }
+                _ => self.search_sorted(&searcher, query, sort, offset, limit)?,
+            };
+            if page.total > 0 {
+                page.categories = self.category_counts(&searcher, query)?;
+            }
+            Ok(page)
+        };
+
+        if q.is_empty() {
+            return run(self.filtered(Box::new(AllQuery), filters, &fields).as_ref());
+        }
+
+        let strict_query = self.filtered(self.build_query(q, QueryMode::Strict, opts)?, filters, &fields);
+        let page = run(strict_query.as_ref())?;
 
         // If the strict parse yields nothing, fall back to a typo-tolerant query.
-        if scored_docs.is_empty() {
-            let fuzzy_query = self.build_query(q, QueryMode::FuzzyFallback)?;
-            scored_docs = self.search_and_score(&searcher, fuzzy_query.as_ref(), requested)?;
+        if page.total == 0 && opts.fuzzy_distance > 0 {
+            let fuzzy_query = self.filtered(self.build_query(q, QueryMode::FuzzyFallback, opts)?, filters, &fields);
+            return run(fuzzy_query.as_ref());
+        }
+        Ok(page)
+    }
+
+    /// Every match of `q` (as for `search_counted`, without title grouping) in `sort` order,
+    /// read from one index snapshot as the cursor advances, so the export is consistent
+    /// however long the client takes. `Relevance` orders by text score alone. Matches are
+    /// sorted a page at a time (see `SearchCursor`), so memory doesn't grow with the
+    /// number of matches.
+    pub fn export(&self, q: &str, sort: SearchSort, opts: QueryOptions, filters: SearchFilters) -> anyhow::Result<SearchCursor> {
+        self.inner.reader.reload().ok();
+        let searcher = self.inner.reader.searcher();
+        let (q, fields) = parse_query(q);
+        let (mut query, mut total) = (None, 0);
+        if !q.is_empty() || !fields.is_empty() {
+            let mut matching = if q.is_empty() {
+                self.filtered(Box::new(AllQuery), filters, &fields)
+            } else {
+                self.filtered(self.build_query(&q, QueryMode::Strict, opts)?, filters, &fields)
+            };
+            total = matching.count(&searcher)?;
+            if total == 0 && !q.is_empty() && opts.fuzzy_distance > 0 {
+                matching = self.filtered(self.build_query(&q, QueryMode::FuzzyFallback, opts)?, filters, &fields);
+                total = matching.count(&searcher)?;
+            }
+            query = Some(matching);
+        }
+        Ok(SearchCursor {
+            index: self.clone(),
+            searcher,
+            query,
+            sort,
+            page_size: EXPORT_PAGE,
+            page: Vec::new().into_iter(),
+            after: None,
+            remaining: total,
+        })
+    }
+
+    /// Every indexed torrent in `sort` order, without a query: newest first for `Added`,
+    /// most seeded first for `Seeders`. Sorted on fast fields, so deep pages stay exact
+    /// (down to [`MAX_OFFSET`]).
+    pub fn browse(&self, sort: SearchSort, offset: usize, limit: usize) -> anyhow::Result<SearchPage> {
+        if limit == 0 {
+            return Ok(SearchPage::default());
+        }
+        let offset = offset.min(MAX_OFFSET);
+        self.inner.reader.reload().ok();
+        let searcher = self.inner.reader.searcher();
+        self.search_sorted(&searcher, &AllQuery, sort, offset, limit)
+    }
+
+    /// Titles completing `q` as the user types: every word of `q` must start a title word,
+    /// so the last one can be partial. Most seeded first, without duplicate titles.
+    pub fn suggest(&self, q: &str, limit: usize) -> anyhow::Result<Vec<String>> {
+        let clauses: Vec<(Occur, Box<dyn Query>)> = self
+            .tokenize(q, "default")
+            .into_iter()
+            .map(|token| {
+                let prefix: String = token.chars().take(MAX_PREFIX_CHARS).collect();
+                let term = Term::from_field_text(self.inner.title_prefix, &prefix);
+                (Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>)
+            })
+            .collect();
+        if clauses.is_empty() || limit == 0 {
+            return Ok(Vec::new());
         }
 
-        Ok(scored_docs.into_iter().skip(offset).take(limit).collect())
+        self.inner.reader.reload().ok();
+        let searcher = self.inner.reader.searcher();
+        // Extra candidates, since re-uploads of the same title are common.
+        let top = TopDocs::with_limit(limit.saturating_mul(4)).order_by_fast_field::<i64>("seeders", Order::Desc);
+        let docs = searcher.search(&BooleanQuery::new(clauses), &top)?;
+
+        let mut seen = std::collections::HashSet::new();
+        let mut titles = Vec::with_capacity(limit);
+        for (_, addr) in docs {
+            let retrieved: tantivy::schema::TantivyDocument = searcher.doc(addr)?;
+            let Some(title) = retrieved.get_first(self.inner.title).and_then(|v| v.as_str()) else {
+                continue;
+            };
+            if seen.insert(title.to_lowercase()) {
+                titles.push(title.to_string());
+                if titles.len() == limit {
+                    break;
+                }
+            }
+        }
+        Ok(titles)
+    }
+
+    /// Matches of `query` per category facet.
+    fn category_counts(&self, searcher: &tantivy::Searcher, query: &dyn Query) -> anyhow::Result<BTreeMap<String, u64>> {
+        let mut collector = FacetCollector::for_field("category");
+        collector.add_facet(Facet::root());
+        let counts = searcher.search(query, &collector)?;
+        Ok(counts
+            .get("/")
+            .filter_map(|(facet, count)| Some((facet.to_path().last()?.to_string(), count)))
+            .collect())
+    }
+
+    /// Orders all matches by a fast field (descending), so the page is exact however deep it is.
+    fn search_sorted(
+        &self,
+        searcher: &tantivy::Searcher,
+        query: &dyn Query,
+        sort: SearchSort,
+        offset: usize,
+        limit: usize,
+    ) -> anyhow::Result<SearchPage> {
+        let top = TopDocs::with_limit(limit).and_offset(offset);
+        let (addrs, total): (Vec<tantivy::DocAddress>, usize) = match sort {
+            SearchSort::Size => {
+                let (docs, total) = searcher.search(query, &(top.order_by_fast_field::<u64>("size", Order::Desc), Count))?;
+                (docs.into_iter().map(|(_, addr)| addr).collect(), total)
+            }
+            _ => {
+                let field = if sort == SearchSort::Added { "first_seen" } else { "seeders" };
+                let (docs, total) = searcher.search(query, &(top.order_by_fast_field::<i64>(field, Order::Desc), Count))?;
+                (docs.into_iter().map(|(_, addr)| addr).collect(), total)
+            }
+        };
+
+        let mut hits = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            hits.push(self.hit_from_doc(&searcher.doc(addr)?));
+        }
+        Ok(SearchPage { hits, total, categories: BTreeMap::new() })
     }
 
     fn search_and_score(
@@ -254,58 +1295,65 @@ impl SearchIndex {
         searcher: &tantivy::Searcher,
         query: &dyn Query,
         limit: usize,
-    ) -> anyhow::Result<Vec<SearchHit>> {
+        seeder_boost: f32,
+    ) -> anyhow::Result<(Vec<SearchHit>, usize)> {
         // Pull more candidates than we ultimately return, so we can re-rank
-        // by a combination of textual relevance and seeders.
-        let candidate_limit = (limit.saturating_mul(10)).clamp(limit, 2000);
-        let top_docs = searcher.search(query, &TopDocs::with_limit(candidate_limit))?;
+        // by a combination of textual relevance and swarm size.
+        let candidate_limit = limit.saturating_mul(10).min(MAX_CANDIDATES).max(limit);
+        let (top_docs, total) = searcher.search(query, &(TopDocs::with_limit(candidate_limit), Count))?;
 
         let mut candidates = Vec::with_capacity(top_docs.len());
         for (bm25_score, addr) in top_docs {
             let retrieved: tantivy::schema::TantivyDocument = searcher.doc(addr)?;
-            let seeders = retrieved
-                .get_first(self.inner.seeders)
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0);
-
-            let adjusted = adjust_score(bm25_score, seeders);
-            candidates.push((adjusted, seeders, retrieved));
+            let hit = self.hit_from_doc(&retrieved);
+            let adjusted = adjust_score(bm25_score, &hit, seeder_boost);
+            candidates.push((adjusted, hit));
         }
 
-        candidates.sort_by(|(score_a, seeders_a, _), (score_b, seeders_b, _)| {
+        candidates.sort_by(|(score_a, a), (score_b, b)| {
             score_b
                 .partial_cmp(score_a)
                 .unwrap_or(CmpOrdering::Equal)
-                .then_with(|| seeders_b.cmp(seeders_a))
+                .then_with(|| b.seeders.cmp(&a.seeders))
+                .then_with(|| b.leechers.cmp(&a.leechers))
+                .then_with(|| b.announces.cmp(&a.announces))
         });
 
-        let mut hits = Vec::with_capacity(limit.min(candidates.len()));
-        for (_score, seeders, retrieved) in candidates.into_iter().take(limit) {
-            let info_hash = retrieved
-                .get_first(self.inner.info_hash)
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let title = retrieved
-                .get_first(self.inner.title)
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let magnet = retrieved
-                .get_first(self.inner.magnet)
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
+        let hits = candidates.into_iter().take(limit).map(|(_score, hit)| hit).collect();
+        Ok((hits, total))
+    }
 
-            hits.push(SearchHit {
-                info_hash,
-                title,
-                magnet,
-                seeders,
-            });
+    fn hit_from_doc(&self, retrieved: &tantivy::schema::TantivyDocument) -> SearchHit {
+        let text = |field| {
+            retrieved
+                .get_first(field)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+        let number = |field| retrieved.get_first(field).and_then(|v| v.as_i64()).unwrap_or(0);
+        let seeders = number(self.inner.seeders);
+        let leechers = number(self.inner.leechers);
+        SearchHit {
+            info_hash: text(self.inner.info_hash),
+            title: text(self.inner.title),
+            magnet: text(self.inner.magnet),
+            seeders,
+            leechers,
+            peers: seeders.max(0) + leechers.max(0),
+            announces: retrieved
+                .get_first(self.inner.announces)
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0),
+            size_bytes: retrieved
+                .get_first(self.inner.size)
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0),
+            source: text(self.inner.source),
+            alternates: Vec::new(),
         }
-
-        Ok(hits)
     }
 
-    fn build_query(&self, q: &str, mode: QueryMode) -> anyhow::Result<Box<dyn Query>> {
+    fn build_query(&self, q: &str, mode: QueryMode, opts: QueryOptions) -> anyhow::Result<Box<dyn Query>> {
         let q = q.trim();
 
         // Special-case: if the user pasted a full hash (or a long hex prefix), do the right thing.
@@ -314,31 +1362,126 @@ impl SearchIndex {
                 let term = Term::from_field_text(self.inner.info_hash, &hex);
                 return Ok(Box::new(TermQuery::new(term, IndexRecordOption::Basic)));
             }
-            // A shorter hex string is treated as a prefix match on the hash.
-            if hex.len() >= 8 {
-                let pattern = format!("^{}.*", hex);
-                return Ok(Box::new(
-                    RegexQuery::from_pattern(&pattern, self.inner.info_hash)
-                        .context("build hash prefix query")?,
-                ));
+            if hex.len() == 64 {
+                let term = Term::from_field_text(self.inner.info_hash_v2, &hex);
+                return Ok(Box::new(TermQuery::new(term, IndexRecordOption::Basic)));
             }
+            // A shorter hex string is treated as a prefix match on either hash.
+            return self.hash_prefix_query(&hex);
         }
 
         match mode {
-            QueryMode::Strict => self.build_strict_query(q),
-            QueryMode::FuzzyFallback => self.build_fuzzy_query(q),
+            QueryMode::Strict => self.build_strict_query(q, opts.match_all),
+            QueryMode::FuzzyFallback => self.build_fuzzy_query(q, opts),
+        }
+    }
+
+    /// `query` restricted to `filters` and to the `field:value` filters of the query text.
+    /// Sizes are only known once metadata is fetched, so any size bound leaves out torrents
+    /// without one.
+    fn filtered(&self, query: Box<dyn Query>, filters: SearchFilters, fields: &[FieldFilter]) -> Box<dyn Query> {
+        if filters.is_empty() && fields.is_empty() {
+            return query;
+        }
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, query)];
+        if !filters.is_empty() {
+            let min = filters.min_size.unwrap_or(0).max(1);
+            let max = filters.max_size.map_or(Bound::Unbounded, Bound::Included);
+            let size = RangeQuery::new_u64_bounds("size".to_string(), Bound::Included(min), max);
+            clauses.push((Occur::Must, Box::new(size)));
+        }
+        for filter in fields {
+            let query: Box<dyn Query> = match filter {
+                FieldFilter::Title(words) => {
+                    let terms: Vec<Term> = self
+                        .tokenize_for_title(words)
+                        .iter()
+                        .map(|token| Term::from_field_text(self.inner.title, token))
+                        .collect();
+                    match terms.len() {
+                        0 => continue,
+                        1 => Box::new(TermQuery::new(terms[0].clone(), IndexRecordOption::Basic)),
+                        // N-grams carry no word positions; the best we can do is all of them.
+                        _ if self.inner.tokenizer == TitleTokenizer::Ngram => Box::new(BooleanQuery::new(
+                            terms
+                                .into_iter()
+                                .map(|term| {
+                                    (Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>)
+                                })
+                                .collect(),
+                        )),
+                        _ => Box::new(PhraseQuery::new(terms)),
+                    }
+                }
+                FieldFilter::Tag(tag) => {
+                    let term = Term::from_facet(self.inner.tags, &Facet::from_path([tag]));
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+                }
+                FieldFilter::Category(category) => {
+                    let term = Term::from_facet(self.inner.category, &Facet::from_path([category]));
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+                }
+                FieldFilter::Source(via) => {
+                    let term = Term::from_field_text(self.inner.source, via.as_str());
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+                }
+                FieldFilter::Resolution(resolution) => {
+                    let term = Term::from_field_text(self.inner.resolution, resolution);
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+                }
+                FieldFilter::Codec(codec) => {
+                    let term = Term::from_field_text(self.inner.codec, codec);
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+                }
+                FieldFilter::Group(group) => {
+                    let term = Term::from_field_text(self.inner.group, group);
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic))
+                }
+                FieldFilter::Year(lo, hi) => Box::new(RangeQuery::new_u64_bounds("year".to_string(), *lo, *hi)),
+                FieldFilter::Season(lo, hi) => Box::new(RangeQuery::new_u64_bounds("season".to_string(), *lo, *hi)),
+                FieldFilter::Episode(lo, hi) => Box::new(RangeQuery::new_u64_bounds("episode".to_string(), *lo, *hi)),
+                FieldFilter::Seeders(lo, hi) => Box::new(RangeQuery::new_i64_bounds("seeders".to_string(), *lo, *hi)),
+                FieldFilter::Leechers(lo, hi) => Box::new(RangeQuery::new_i64_bounds("leechers".to_string(), *lo, *hi)),
+                FieldFilter::Size(lo, hi) => {
+                    let lo = match lo {
+                        Bound::Unbounded => Bound::Included(1),
+                        bound => *bound,
+                    };
+                    Box::new(RangeQuery::new_u64_bounds("size".to_string(), lo, *hi))
+                }
+            };
+            clauses.push((Occur::Must, query));
+        }
+        Box::new(BooleanQuery::new(clauses))
+    }
+
+    fn hash_prefix_query(&self, hex: &str) -> anyhow::Result<Box<dyn Query>> {
+        let pattern = format!("^{}.*", hex);
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        // v1 hashes are only 40 characters long.
+        if hex.len() < 40 {
+            let query = RegexQuery::from_pattern(&pattern, self.inner.info_hash)
+                .context("build hash prefix query")?;
+            clauses.push((Occur::Should, Box::new(query)));
         }
+        let query = RegexQuery::from_pattern(&pattern, self.inner.info_hash_v2)
+            .context("build v2 hash prefix query")?;
+        clauses.push((Occur::Should, Box::new(query)));
+        Ok(Box::new(BooleanQuery::new(clauses)))
     }
 
-    fn build_strict_query(&self, q: &str) -> anyhow::Result<Box<dyn Query>> {
+    fn build_strict_query(&self, q: &str, match_all: bool) -> anyhow::Result<Box<dyn Query>> {
         let mut query_parser = QueryParser::for_index(
             &self.inner.index,
-            vec![self.inner.title, self.inner.info_hash],
+            vec![self.inner.title, self.inner.files, self.inner.info_hash],
         );
         // Better default for search UX: space-separated terms behave like AND.
-        query_parser.set_conjunction_by_default();
-        // Prefer title matches to hash matches.
+        if match_all {
+            query_parser.set_conjunction_by_default();
+        }
+        // Prefer title matches to file-name and hash matches.
         query_parser.set_field_boost(self.inner.title, 2.0);
+        query_parser.set_field_boost(self.inner.files, 0.5);
 
         if let Ok(query) = query_parser.parse_query(q) {
             return Ok(query);
@@ -350,12 +1493,12 @@ impl SearchIndex {
             return Ok(query);
         }
 
-        // Last resort: token-based MUST queries on title.
+        // Last resort: token-based queries on title.
         let tokens = self.tokenize_for_title(&sanitized);
         let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
         for token in tokens {
             let term = Term::from_field_text(self.inner.title, &token);
-            clauses.push((Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic))));
+            clauses.push((word_occur(match_all), Box::new(TermQuery::new(term, IndexRecordOption::Basic))));
         }
         if clauses.is_empty() {
             anyhow::bail!("empty query")
@@ -363,7 +1506,7 @@ impl SearchIndex {
         Ok(Box::new(BooleanQuery::new(clauses)))
     }
 
-    fn build_fuzzy_query(&self, q: &str) -> anyhow::Result<Box<dyn Query>> {
+    fn build_fuzzy_query(&self, q: &str, opts: QueryOptions) -> anyhow::Result<Box<dyn Query>> {
         let sanitized = sanitize_query(q);
         let tokens = self.tokenize_for_title(&sanitized);
         let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
@@ -371,22 +1514,28 @@ impl SearchIndex {
         for token in tokens {
             // Also allow searching by hash prefixes when the query contains hex-like chunks.
             if let Some(hex) = normalize_hex_query(&token) {
-                if hex.len() >= 8 {
-                    let pattern = format!("^{}.*", hex);
-                    let query = RegexQuery::from_pattern(&pattern, self.inner.info_hash)
-                        .context("build hash prefix query")?;
-                    clauses.push((Occur::Should, Box::new(query)));
-                }
+                clauses.push((Occur::Should, self.hash_prefix_query(&hex)?));
             }
 
-            // Fuzzy title matching for typos.
+            // Fuzzy title matching for typos; file names only need to match exactly. Short
+            // words get fewer edits, or "cat" would match half the index.
             let term = Term::from_field_text(self.inner.title, &token);
-            if token.len() <= 3 {
-                clauses.push((Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic))));
+            let distance = match token.chars().count() {
+                0..=3 => 0,
+                4..=5 => opts.fuzzy_distance.min(1),
+                _ => opts.fuzzy_distance.min(MAX_FUZZY_DISTANCE),
+            };
+            let title_query: Box<dyn Query> = if distance == 0 {
+                Box::new(TermQuery::new(term, IndexRecordOption::Basic))
             } else {
-                // Distance=1 keeps it reasonably precise while fixing common typos.
-                clauses.push((Occur::Must, Box::new(FuzzyTermQuery::new(term, 1, true))));
-            }
+                Box::new(FuzzyTermQuery::new(term, distance, true))
+            };
+            let files_term = Term::from_field_text(self.inner.files, &token);
+            let either = BooleanQuery::new(vec![
+                (Occur::Should, title_query),
+                (Occur::Should, Box::new(TermQuery::new(files_term, IndexRecordOption::Basic))),
+            ]);
+            clauses.push((word_occur(opts.match_all), Box::new(either)));
         }
 
         if clauses.is_empty() {
@@ -395,8 +1544,27 @@ impl SearchIndex {
         Ok(Box::new(BooleanQuery::new(clauses)))
     }
 
+    /// Title words expanded to all their prefixes, space separated, for the `title_prefix` field.
+    fn title_prefixes(&self, title: &str) -> String {
+        let mut out = String::new();
+        for word in self.tokenize(title, "default") {
+            let mut prefix = String::new();
+            for c in word.chars().take(MAX_PREFIX_CHARS) {
+                prefix.push(c);
+                out.push_str(&prefix);
+                out.push(' ');
+            }
+        }
+        out
+    }
+
+    /// Terms of `text` as the title and files fields index them.
     fn tokenize_for_title(&self, text: &str) -> Vec<String> {
-        let Some(mut tokenizer) = self.inner.index.tokenizers().get("default") else {
+        self.tokenize(text, self.inner.tokenizer.tantivy_name())
+    }
+
+    fn tokenize(&self, text: &str, tokenizer: &str) -> Vec<String> {
+        let Some(mut tokenizer) = self.inner.index.tokenizers().get(tokenizer) else {
             return text
                 .split_whitespace()
                 .map(|s| s.to_ascii_lowercase())
@@ -422,11 +1590,216 @@ enum QueryMode {
     FuzzyFallback,
 }
 
-fn adjust_score(bm25: Score, seeders: i64) -> f32 {
-    // Relevance is primary; seeders is a gentle boost.
-    // Using ln(1+s) avoids huge domination by very large seed counts.
-    let seed_boost = ((seeders.max(0) as f32) + 1.0).ln() / 4.0;
-    bm25 + seed_boost
+/// How each query word's clause combines with the others.
+fn word_occur(match_all: bool) -> Occur {
+    if match_all { Occur::Must } else { Occur::Should }
+}
+
+fn adjust_score(bm25: Score, hit: &SearchHit, seeder_boost: f32) -> f32 {
+    // Relevance is primary; swarm size is a gentle boost, seeders counting more than leechers.
+    // Announces stand in for popularity before any tracker data exists.
+    // Using ln(1+s) avoids huge domination by very large swarms.
+    let boost = |count: f32, weight: f32| (count + 1.0).ln() / weight;
+    bm25 + seeder_boost
+        * (boost(hit.seeders.max(0) as f32, 4.0)
+            + boost(hit.leechers.max(0) as f32, 8.0)
+            + boost(hit.announces as f32, 8.0))
+}
+
+/// Collapses hits whose titles are the same apart from case and punctuation into the first
+/// one's place, keeping the best seeded as the hit and listing the others' hashes as its
+/// `alternates`. Hits without a title stay as they are.
+fn group_by_title(hits: Vec<SearchHit>) -> Vec<SearchHit> {
+    let mut groups: Vec<SearchHit> = Vec::with_capacity(hits.len());
+    let mut by_title: HashMap<String, usize> = HashMap::new();
+    for mut hit in hits {
+        let Some(key) = hit.title.as_deref().map(title_key).filter(|key| !key.is_empty()) else {
+            groups.push(hit);
+            continue;
+        };
+        let Some(&at) = by_title.get(&key) else {
+            by_title.insert(key, groups.len());
+            groups.push(hit);
+            continue;
+        };
+        let group = &mut groups[at];
+        if hit.seeders > group.seeders {
+            std::mem::swap(group, &mut hit);
+            group.alternates = std::mem::take(&mut hit.alternates);
+        }
+        group.alternates.extend(hit.info_hash);
+    }
+    groups
+}
+
+/// A title's words, lowercased: `The.Matrix-1999` and `the matrix 1999` are the same.
+fn title_key(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A `field:value` restriction taken out of the query text by `parse_query`.
+#[derive(Debug, Clone, PartialEq)]
+enum FieldFilter {
+    /// Words that must appear in the title, next to each other and in this order.
+    Title(String),
+    Tag(String),
+    Category(&'static str),
+    Source(crate::storage::Discovery),
+    Resolution(&'static str),
+    Codec(&'static str),
+    /// Lowercase release group.
+    Group(String),
+    Year(Bound<u64>, Bound<u64>),
+    Season(Bound<u64>, Bound<u64>),
+    Episode(Bound<u64>, Bound<u64>),
+    Seeders(Bound<i64>, Bound<i64>),
+    Leechers(Bound<i64>, Bound<i64>),
+    Size(Bound<u64>, Bound<u64>),
+}
+
+/// Splits the field filters off a query: `title:"exact phrase"`, `tag:linux`,
+/// `category:movies`, `source:sample`, `resolution:1080p`, `codec:x265`, `group:ntb`,
+/// `year:>2010`, `season:2`, `episode:5`, `seeders:>10`, `leechers:<=5`, `size:<2GB`,
+/// `size:1GB..4GB`. Numbers take `>`, `>=`, `<`, `<=`, `a..b` (inclusive) or an exact value. Returns the rest of the
+/// query and the filters; words with an unknown field or a value that doesn't parse stay
+/// in the query.
+fn parse_query(q: &str) -> (String, Vec<FieldFilter>) {
+    let mut rest = Vec::new();
+    let mut filters = Vec::new();
+    for word in query_words(q) {
+        let filter = word.split_once(':').and_then(|(field, value)| {
+            let unquoted = value.trim_matches('"');
+            match field.to_ascii_lowercase().as_str() {
+                "title" => (!unquoted.trim().is_empty()).then(|| FieldFilter::Title(unquoted.to_string())),
+                "tag" => crate::storage::normalize_tag(unquoted).map(FieldFilter::Tag),
+                "category" | "cat" => parse_category(unquoted).map(FieldFilter::Category),
+                "source" | "via" => crate::storage::Discovery::parse(unquoted).map(FieldFilter::Source),
+                "resolution" | "res" => crate::release::normalize_resolution(unquoted).map(FieldFilter::Resolution),
+                "codec" => crate::release::normalize_codec(unquoted).map(FieldFilter::Codec),
+                "group" => (!unquoted.trim().is_empty()).then(|| FieldFilter::Group(unquoted.trim().to_lowercase())),
+                "year" => parse_range(value, |v| v.parse().ok()).map(|(lo, hi)| FieldFilter::Year(lo, hi)),
+                "season" => parse_range(value, |v| v.parse().ok()).map(|(lo, hi)| FieldFilter::Season(lo, hi)),
+                "episode" | "ep" => parse_range(value, |v| v.parse().ok()).map(|(lo, hi)| FieldFilter::Episode(lo, hi)),
+                "seeders" => parse_range(value, |v| v.parse().ok()).map(|(lo, hi)| FieldFilter::Seeders(lo, hi)),
+                "leechers" => parse_range(value, |v| v.parse().ok()).map(|(lo, hi)| FieldFilter::Leechers(lo, hi)),
+                "size" => parse_range(value, parse_bytes).map(|(lo, hi)| FieldFilter::Size(lo, hi)),
+                _ => None,
+            }
+        });
+        match filter {
+            Some(filter) if !filters.contains(&filter) => filters.push(filter),
+            Some(_) => {}
+            None => rest.push(word),
+        }
+    }
+    (rest.join(" "), filters)
+}
+
+/// Whitespace-separated words, keeping quoted phrases (`title:"a b"`) in one word.
+fn query_words(q: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in q.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            if let Some(s) = start.take() {
+                words.push(&q[s..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        words.push(&q[s..]);
+    }
+    words
+}
+
+/// `>10`, `>=10`, `<10`, `<=10`, `5..10` or `10` as bounds.
+fn parse_range<T: Copy + PartialOrd>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<(Bound<T>, Bound<T>)> {
+    let value = value.trim();
+    if let Some(v) = value.strip_prefix(">=") {
+        Some((Bound::Included(parse(v)?), Bound::Unbounded))
+    } else if let Some(v) = value.strip_prefix('>') {
+        Some((Bound::Excluded(parse(v)?), Bound::Unbounded))
+    } else if let Some(v) = value.strip_prefix("<=") {
+        Some((Bound::Unbounded, Bound::Included(parse(v)?)))
+    } else if let Some(v) = value.strip_prefix('<') {
+        Some((Bound::Unbounded, Bound::Excluded(parse(v)?)))
+    } else if let Some((lo, hi)) = value.split_once("..") {
+        let (lo, hi) = (parse(lo)?, parse(hi)?);
+        (lo <= hi).then_some((Bound::Included(lo), Bound::Included(hi)))
+    } else {
+        let v = parse(value.strip_prefix('=').unwrap_or(value))?;
+        Some((Bound::Included(v), Bound::Included(v)))
+    }
+}
+
+/// Bytes from `"734003200"`, `"700MiB"`, `"1.5G"` or `"2 TB"`. Units are powers of 1024
+/// whichever spelling is used, matching `web::format_bytes`.
+pub fn parse_bytes(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let unit = unit.trim().to_ascii_lowercase();
+    let unit = unit.trim_end_matches('b').trim_end_matches('i');
+    let power = ["", "k", "m", "g", "t"].iter().position(|u| *u == unit)?;
+    let bytes = number * 1024f64.powi(power as i32);
+    (bytes.is_finite() && bytes < u64::MAX as f64).then_some(bytes as u64)
+}
+
+/// File extensions (lowercase) of each category, checked in this order.
+const CATEGORY_EXTENSIONS: &[(&str, &[&str])] = &[
+    ("video", &["mkv", "mp4", "avi", "m4v", "mov", "wmv", "webm", "ts", "m2ts", "mpg", "mpeg", "flv", "vob"]),
+    ("audio", &["mp3", "flac", "m4a", "aac", "ogg", "opus", "wav", "wv", "ape"]),
+    ("software", &["exe", "msi", "dmg", "pkg", "deb", "rpm", "apk", "iso", "img", "appimage"]),
+    ("books", &["pdf", "epub", "mobi", "azw3", "djvu", "cbz", "cbr", "fb2"]),
+    ("images", &["jpg", "jpeg", "png", "gif", "webp", "tif", "tiff", "heic"]),
+];
+
+/// The category holding most of a torrent's bytes, by file extension; `other` if none of
+/// its files has a known extension and `None` until its file list is known.
+pub fn category_of(files: &[crate::storage::FileEntry]) -> Option<&'static str> {
+    if files.is_empty() {
+        return None;
+    }
+    let mut bytes = [0u64; CATEGORY_EXTENSIONS.len()];
+    for file in files {
+        let Some((_, ext)) = file.path.rsplit_once('.') else {
+            continue;
+        };
+        let ext = ext.to_ascii_lowercase();
+        if let Some(i) = CATEGORY_EXTENSIONS.iter().position(|(_, exts)| exts.contains(&ext.as_str())) {
+            // Count empty files a little, so a list of them still gets a category.
+            bytes[i] += file.length.max(1);
+        }
+    }
+    let (i, most) = bytes.iter().enumerate().max_by_key(|(i, b)| (**b, std::cmp::Reverse(*i)))?;
+    Some(if *most == 0 { "other" } else { CATEGORY_EXTENSIONS[i].0 })
+}
+
+/// A category name, or a common word for one (`movies`, `music`, `ebooks`, ...).
+fn parse_category(name: &str) -> Option<&'static str> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "video" | "videos" | "movie" | "movies" | "film" | "films" | "tv" | "series" => "video",
+        "audio" | "music" | "audiobooks" => "audio",
+        "software" | "apps" | "games" | "pc" | "iso" => "software",
+        "books" | "book" | "ebooks" | "comics" => "books",
+        "images" | "pictures" | "photos" => "images",
+        "other" => "other",
+        _ => return None,
+    })
 }
 
 fn sanitize_query(input: &str) -> String {
@@ -445,7 +1818,7 @@ fn sanitize_query(input: &str) -> String {
 
 fn normalize_hex_query(input: &str) -> Option<String> {
     let s = input.trim();
-    if s.len() < 8 || s.len() > 40 {
+    if s.len() < 8 || s.len() > 64 {
         return None;
     }
     if s.chars().all(|c| c.is_ascii_hexdigit()) {
@@ -458,20 +1831,17 @@ fn normalize_hex_query(input: &str) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::{SystemTime, UNIX_EPOCH};
 
-    fn temp_index_dir() -> std::path::PathBuf {
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos();
-        std::env::temp_dir().join(format!("serma-index-test-{}-{}", std::process::id(), nanos))
+    fn counted(index: &SearchIndex, q: &str, sort: SearchSort, offset: usize, limit: usize) -> SearchPage {
+        index
+            .search_counted(q, sort, offset, limit, QueryOptions::default(), SearchFilters::default())
+            .unwrap()
     }
 
     #[test]
     fn relevance_beats_seeders_sorting() {
-        let dir = temp_index_dir();
-        let index = SearchIndex::open_or_create(&dir).unwrap();
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
 
         // Doc with massive seeders but missing a key term.
         index
@@ -495,16 +1865,34 @@ mod tests {
 
         index.maybe_commit().unwrap();
 
-        let hits = index.search("matrix 1999", 10).unwrap();
+        let hits = index.search("matrix 1999", 10, QueryOptions::default()).unwrap();
         assert!(!hits.is_empty());
         let top_title = hits[0].title.clone().unwrap_or_default().to_ascii_lowercase();
         assert!(top_title.contains("1999"));
     }
 
+    #[test]
+    fn leechers_break_seeder_ties() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for (i, leechers) in [3, 40].into_iter().enumerate() {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 0);
+            record.title = Some("Arch Linux ISO".to_string());
+            record.seeders = 10;
+            record.leechers = leechers;
+            index.upsert_record(&record).unwrap();
+        }
+        index.commit().unwrap();
+
+        let hits = index.search("arch linux", 10, QueryOptions::default()).unwrap();
+        assert_eq!(hits.iter().map(|h| h.leechers).collect::<Vec<_>>(), vec![40, 3]);
+        assert_eq!(hits[0].peers, 50);
+    }
+
     #[test]
     fn fuzzy_fallback_finds_typos() {
-        let dir = temp_index_dir();
-        let index = SearchIndex::open_or_create(&dir).unwrap();
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
         index
             .upsert(
                 "cccccccccccccccccccccccccccccccccccccccc",
@@ -516,9 +1904,462 @@ mod tests {
         index.maybe_commit().unwrap();
 
         // Missing the second 'i'.
-        let hits = index.search("matrx 1999", 10).unwrap();
+        let hits = index.search("matrx 1999", 10, QueryOptions::default()).unwrap();
         assert!(!hits.is_empty());
         let title = hits[0].title.clone().unwrap_or_default().to_ascii_lowercase();
         assert!(title.contains("matrix"));
     }
+
+    #[test]
+    fn search_counted_reports_total_across_pages() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for i in 0..7 {
+            index
+                .upsert(&format!("{i:040x}"), &format!("Ubuntu Release {i}"), "", 10 + i)
+                .unwrap();
+        }
+        index.maybe_commit().unwrap();
+
+        let first = counted(&index, "ubuntu", SearchSort::Relevance, 0, 3);
+        assert_eq!(first.total, 7);
+        assert_eq!(first.hits.len(), 3);
+
+        let last = counted(&index, "ubuntu", SearchSort::Relevance, 6, 3);
+        assert_eq!(last.total, 7);
+        assert_eq!(last.hits.len(), 1);
+        assert!(first.hits.iter().all(|h| h.info_hash != last.hits[0].info_hash));
+    }
+
+    #[test]
+    fn deep_relevance_pages_stop_at_candidate_cap() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for i in 0..5 {
+            index.upsert(&format!("{i:040x}"), &format!("Fedora Spin {i}"), "", i).unwrap();
+        }
+        index.maybe_commit().unwrap();
+
+        // Pages reaching past the re-ranked candidates, or asking for more than all of them.
+        for (offset, limit) in [(1_995, 10), (usize::MAX, 10), (0, MAX_CANDIDATES + 500)] {
+            let page = counted(&index, "fedora", SearchSort::Relevance, offset, limit);
+            assert_eq!(page.total, 5);
+            assert_eq!(page.hits.len(), if offset == 0 { 5 } else { 0 });
+        }
+        assert_eq!(max_offset(SearchSort::Relevance, 10), MAX_CANDIDATES - 10);
+        assert_eq!(max_offset(SearchSort::Relevance, MAX_CANDIDATES + 500), 0);
+    }
+
+    #[test]
+    fn sort_orders_whole_result_set() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for i in 0..30i64 {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 1_000 + i);
+            record.title = Some(format!("Debian Image {i}"));
+            record.seeders = (i * 7) % 30;
+            record.size_bytes = (30 - i) as u64 * 1_000;
+            index.upsert_record(&record).unwrap();
+        }
+        index.commit().unwrap();
+
+        let seeders = counted(&index, "debian", SearchSort::Seeders, 0, 2);
+        assert_eq!(seeders.total, 30);
+        assert_eq!(seeders.hits.iter().map(|h| h.seeders).collect::<Vec<_>>(), vec![29, 28]);
+
+        let added = counted(&index, "debian", SearchSort::Added, 25, 10);
+        assert_eq!(added.hits.len(), 5);
+        assert_eq!(added.hits.last().unwrap().info_hash.as_deref(), Some(format!("{:040x}", 0).as_str()));
+
+        let size = counted(&index, "debian", SearchSort::Size, 0, 1);
+        assert_eq!(size.hits[0].size_bytes, 30_000);
+
+        let filters = SearchFilters {
+            min_size: Some(5_000),
+            max_size: Some(9_000),
+        };
+        let sized = index.search_counted("debian", SearchSort::Size, 0, 10, QueryOptions::default(), filters).unwrap();
+        assert_eq!(sized.total, 5);
+        assert_eq!(sized.hits.iter().map(|h| h.size_bytes).collect::<Vec<_>>(), vec![9_000, 8_000, 7_000, 6_000, 5_000]);
+    }
+
+    #[test]
+    fn sorted_offsets_are_bounded() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for i in 0..3 {
+            index.upsert(&format!("{i:040x}"), &format!("Mint Cinnamon {i}"), "", i).unwrap();
+        }
+        index.maybe_commit().unwrap();
+
+        for sort in [SearchSort::Seeders, SearchSort::Added, SearchSort::Size] {
+            let page = counted(&index, "mint", sort, usize::MAX, 25);
+            assert_eq!((page.total, page.hits.len()), (3, 0));
+        }
+        assert_eq!(max_offset(SearchSort::Seeders, 10), MAX_OFFSET);
+    }
+
+    #[test]
+    fn export_walks_every_match_in_order() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for i in 0..40i64 {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 1_000 + i);
+            record.title = Some(format!("{} Image {i}", if i % 4 == 0 { "Fedora" } else { "Debian" }));
+            record.seeders = i;
+            index.upsert_record(&record).unwrap();
+        }
+        index.commit().unwrap();
+
+        let cursor = index.export("debian", SearchSort::Seeders, QueryOptions::default(), SearchFilters::default()).unwrap();
+        assert_eq!(cursor.remaining(), 30);
+        let seeders: Vec<i64> = cursor.map(|hit| hit.unwrap().seeders).collect();
+        assert_eq!(seeders.len(), 30);
+        assert!(seeders.windows(2).all(|pair| pair[0] > pair[1]));
+
+        // Typos fall back to fuzzy matching, as in search.
+        let fuzzy = index.export("fedorra", SearchSort::Relevance, QueryOptions::default(), SearchFilters::default()).unwrap();
+        assert_eq!(fuzzy.count(), 10);
+        let empty = index.export("", SearchSort::Relevance, QueryOptions::default(), SearchFilters::default()).unwrap();
+        assert_eq!(empty.remaining(), 0);
+    }
+
+    #[test]
+    fn export_pages_through_ties_without_gaps() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        // Two commits make two segments; seeders repeat across both.
+        for i in 0..50i64 {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 1_000 + i);
+            record.title = Some(format!("Debian Image {i}"));
+            record.seeders = i % 4;
+            index.upsert_record(&record).unwrap();
+            if i == 24 {
+                index.commit().unwrap();
+            }
+        }
+        index.commit().unwrap();
+
+        for sort in [SearchSort::Seeders, SearchSort::Relevance, SearchSort::Added, SearchSort::Size] {
+            let mut cursor = index.export("debian", sort, QueryOptions::default(), SearchFilters::default()).unwrap();
+            cursor.page_size = 7;
+            let hits: Vec<SearchHit> = cursor.map(Result::unwrap).collect();
+            let hashes: std::collections::HashSet<_> = hits.iter().map(|hit| hit.info_hash.clone()).collect();
+            assert_eq!((hits.len(), hashes.len()), (50, 50), "{sort:?}");
+            if sort == SearchSort::Seeders {
+                assert!(hits.windows(2).all(|pair| pair[0].seeders >= pair[1].seeders));
+            }
+        }
+    }
+
+    #[test]
+    fn browse_pages_without_a_query() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for i in 0..5i64 {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 1_000 + i);
+            record.title = Some(format!("Torrent {i}"));
+            record.seeders = 10 - i;
+            index.upsert_record(&record).unwrap();
+        }
+        index.commit().unwrap();
+
+        let titles = |page: SearchPage| page.hits.into_iter().map(|h| h.title.unwrap()).collect::<Vec<_>>();
+        let recent = index.browse(SearchSort::Added, 0, 2).unwrap();
+        assert_eq!(recent.total, 5);
+        assert_eq!(titles(recent), ["Torrent 4", "Torrent 3"]);
+        assert_eq!(titles(index.browse(SearchSort::Seeders, 3, 10).unwrap()), ["Torrent 3", "Torrent 4"]);
+
+        let past_the_end = index.browse(SearchSort::Added, usize::MAX, 10).unwrap();
+        assert_eq!((past_the_end.total, past_the_end.hits.len()), (5, 0));
+    }
+
+    #[test]
+    fn tag_filters_restrict_and_list() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        for (i, tags) in [vec!["linux", "iso"], vec!["linux"], vec![]].into_iter().enumerate() {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 0);
+            record.title = Some(format!("Fedora Workstation {i}"));
+            record.tags = tags.into_iter().map(String::from).collect();
+            index.upsert_record(&record).unwrap();
+        }
+        index.commit().unwrap();
+
+        let total = |q: &str| counted(&index, q, SearchSort::Relevance, 0, 10).total;
+        assert_eq!(total("fedora"), 3);
+        assert_eq!(total("fedora tag:linux"), 2);
+        assert_eq!(total("TAG:Linux tag:iso fedora"), 1);
+        assert_eq!(total("tag:linux"), 2);
+        assert_eq!(total("tag:missing"), 0);
+    }
+
+    #[test]
+    fn parse_query_splits_field_filters() {
+        let (rest, filters) = parse_query(r#"ubuntu title:"desktop amd64" seeders:>10 size:1GB..4GB magnet:?xt foo:bar"#);
+        assert_eq!(rest, "ubuntu magnet:?xt foo:bar");
+        assert_eq!(
+            filters,
+            vec![
+                FieldFilter::Title("desktop amd64".to_string()),
+                FieldFilter::Seeders(Bound::Excluded(10), Bound::Unbounded),
+                FieldFilter::Size(Bound::Included(1 << 30), Bound::Included(4 << 30)),
+            ]
+        );
+        let (rest, filters) = parse_query("category:Movies size:<2GB leechers:abc via:Announce");
+        assert_eq!(rest, "leechers:abc");
+        assert_eq!(
+            filters,
+            vec![
+                FieldFilter::Category("video"),
+                FieldFilter::Size(Bound::Unbounded, Bound::Excluded(2 << 30)),
+                FieldFilter::Source(crate::storage::Discovery::Announce),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_filters_restrict_matches() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        let torrents = [
+            ("Big Buck Bunny 1080p", "bunny.mkv", 3 << 30, 50),
+            ("Big Buck Bunny Soundtrack", "theme.flac", 200 << 20, 5),
+            ("Buck and the Big Bunny Poster", "poster.png", 0, 12),
+        ];
+        for (i, (title, file, size, seeders)) in torrents.into_iter().enumerate() {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 0);
+            record.title = Some(title.to_string());
+            record.seeders = seeders;
+            record.size_bytes = size;
+            record.files = vec![crate::storage::FileEntry { path: file.to_string(), length: size }];
+            index.upsert_record(&record).unwrap();
+        }
+        index.commit().unwrap();
+
+        let total = |q: &str| counted(&index, q, SearchSort::Relevance, 0, 10).total;
+        assert_eq!(total("bunny"), 3);
+        assert_eq!(total(r#"title:"big buck""#), 2);
+        assert_eq!(total("bunny seeders:>=12"), 2);
+        assert_eq!(total("bunny seeders:5..12"), 2);
+        assert_eq!(total("size:<1GB"), 1);
+        assert_eq!(total("category:movies"), 1);
+        assert_eq!(total("category:images seeders:12"), 1);
+
+        let page = counted(&index, "bunny", SearchSort::Seeders, 0, 1);
+        let counts: Vec<_> = page.categories.iter().map(|(c, n)| (c.as_str(), *n)).collect();
+        assert_eq!(counts, [("audio", 1), ("images", 1), ("video", 1)]);
+    }
+
+    #[test]
+    fn release_filters_match_parsed_titles() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        let titles = [
+            "The.Matrix.1999.1080p.BluRay.x264-GROUP",
+            "The.Matrix.Resurrections.2021.2160p.WEB.HEVC-Other",
+            "Matrix.Show.S01E02.720p.WEB.h264-GROUP",
+        ];
+        for (i, title) in titles.into_iter().enumerate() {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{i:040x}"), 0);
+            record.title = Some(title.to_string());
+            record.refresh_release();
+            index.upsert_record(&record).unwrap();
+        }
+        index.commit().unwrap();
+
+        let total = |q: &str| counted(&index, q, SearchSort::Relevance, 0, 10).total;
+        assert_eq!(total("matrix resolution:1080p"), 1);
+        assert_eq!(total("res:4k"), 1);
+        assert_eq!(total("codec:x265"), 1);
+        assert_eq!(total("codec:avc group:group"), 2);
+        assert_eq!(total("year:>2000"), 1);
+        assert_eq!(total("season:1 episode:2"), 1);
+        assert_eq!(total("season:2"), 0);
+    }
+
+    #[test]
+    fn query_options_change_matching_and_ranking() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        index.upsert(&format!("{:040x}", 1), "Blender Tutorial", "", 10_000).unwrap();
+        index.upsert(&format!("{:040x}", 2), "Blender Tutorial Blender Basics", "", 0).unwrap();
+        index.upsert(&format!("{:040x}", 3), "Krita Basics", "", 0).unwrap();
+        index.commit().unwrap();
+
+        let defaults = QueryOptions::default();
+        let titles = |q: &str, opts: QueryOptions| {
+            let hits = index.search(q, 10, opts).unwrap();
+            hits.into_iter().map(|h| h.title.unwrap_or_default()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(titles("blender krita", defaults).len(), 0);
+        let any = QueryOptions { match_all: false, ..defaults };
+        assert_eq!(titles("blender krita", any).len(), 3);
+
+        assert_eq!(titles("blender", defaults)[0], "Blender Tutorial");
+        let text_only = QueryOptions { seeder_boost: 0.0, ..defaults };
+        assert_eq!(titles("blender", text_only)[0], "Blender Tutorial Blender Basics");
+
+        assert_eq!(titles("blendre", defaults).len(), 2);
+        let exact = QueryOptions { fuzzy_distance: 0, ..defaults };
+        assert!(titles("blendre", exact).is_empty());
+    }
+
+    #[test]
+    fn suggest_completes_partial_words() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        index.upsert(&format!("{:040x}", 1), "Ubuntu Server 24.04", "", 5).unwrap();
+        index.upsert(&format!("{:040x}", 2), "Ubuntu Desktop 24.04", "", 50).unwrap();
+        index.upsert(&format!("{:040x}", 3), "ubuntu desktop 24.04", "", 1).unwrap();
+        index.upsert(&format!("{:040x}", 4), "Kubuntu 24.04", "", 100).unwrap();
+        index.commit().unwrap();
+
+        assert_eq!(index.suggest("ubu", 10).unwrap(), vec!["Ubuntu Desktop 24.04", "Ubuntu Server 24.04"]);
+        assert_eq!(index.suggest("ubuntu se", 10).unwrap(), vec!["Ubuntu Server 24.04"]);
+        assert!(index.suggest("desktop x", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn groups_copies_of_a_title() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::default()).unwrap();
+        index.upsert(&format!("{:040x}", 1), "Debian 12 netinst", "", 5).unwrap();
+        index.upsert(&format!("{:040x}", 2), "debian.12.netinst", "", 50).unwrap();
+        index.upsert(&format!("{:040x}", 3), "Debian 12 DVD", "", 20).unwrap();
+        index.upsert(&format!("{:040x}", 4), "Debian-12 Netinst", "", 1).unwrap();
+        index.commit().unwrap();
+
+        let grouped = QueryOptions { group_titles: true, ..QueryOptions::default() };
+        let page = index
+            .search_counted("debian", SearchSort::Seeders, 0, 10, grouped, SearchFilters::default())
+            .unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(page.hits.len(), 2);
+        assert_eq!(page.hits[0].info_hash, Some(format!("{:040x}", 2)));
+        assert_eq!(page.hits[0].alternates, vec![format!("{:040x}", 1), format!("{:040x}", 4)]);
+        assert!(page.hits[1].alternates.is_empty());
+
+        let second = index
+            .search_counted("debian", SearchSort::Seeders, 1, 1, grouped, SearchFilters::default())
+            .unwrap();
+        assert_eq!(second.hits[0].title.as_deref(), Some("Debian 12 DVD"));
+    }
+
+    #[test]
+    fn tokenizer_choice_rebuilds_and_matches() {
+        let dir = crate::test_dir("index");
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::EnStem).unwrap();
+        assert!(index.was_created());
+        index.upsert(&format!("{:040x}", 1), "Running Man", "", 5).unwrap();
+        index.commit().unwrap();
+        assert_eq!(index.search("runs", 10, QueryOptions::default()).unwrap().len(), 1);
+        drop(index);
+
+        assert!(!SearchIndex::open_or_create(&dir, TitleTokenizer::EnStem).unwrap().was_created());
+        let index = SearchIndex::open_or_create(&dir, TitleTokenizer::Ngram).unwrap();
+        assert!(index.was_created());
+        index.upsert(&format!("{:040x}", 2), "TheMatrix1999.1080p", "", 5).unwrap();
+        index.commit().unwrap();
+        for q in ["matrix", "title:matrix1999"] {
+            let hits = index.search(q, 10, QueryOptions::default()).unwrap();
+            assert_eq!(hits.len(), 1, "{q}");
+        }
+        let exact = QueryOptions { fuzzy_distance: 0, ..QueryOptions::default() };
+        assert!(index.search("matrox", 10, exact).unwrap().is_empty());
+    }
+
+    #[test]
+    fn writer_options_set_commit_cadence() {
+        let options = WriterOptions {
+            merge_policy: MergePolicy::None,
+            commit_every_ops: 2,
+            commit_interval: Duration::from_secs(3600),
+            ..WriterOptions::default()
+        };
+        let index = SearchIndex::open_with(crate::test_dir("index"), TitleTokenizer::default(), options).unwrap();
+        let searchable = || index.search("ubuntu", 10, QueryOptions::default()).unwrap().len();
+
+        index.upsert(&format!("{:040x}", 1), "Ubuntu 24.04", "", 5).unwrap();
+        // The first maybe_commit is never held back.
+        index.maybe_commit().unwrap();
+        assert_eq!(searchable(), 1);
+        index.upsert(&format!("{:040x}", 2), "Ubuntu 22.04", "", 5).unwrap();
+        index.maybe_commit().unwrap();
+        assert_eq!(searchable(), 1);
+        // The second pending change reaches `commit_every_ops`.
+        index.upsert(&format!("{:040x}", 3), "Ubuntu 20.04", "", 5).unwrap();
+        assert_eq!(searchable(), 3);
+        // Nothing merges them in the background.
+        assert_eq!(index.inner.index.searchable_segment_ids().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn purge_deletes_rewrites_churned_segments() {
+        let options = WriterOptions {
+            merge_policy: MergePolicy::None,
+            ..WriterOptions::default()
+        };
+        let index = SearchIndex::open_with(crate::test_dir("index"), TitleTokenizer::default(), options).unwrap();
+        for segment in 0..3 {
+            for n in 0..10 {
+                index.upsert(&format!("{:040x}", segment * 10 + n), "Debian", "", 5).unwrap();
+            }
+            index.commit().unwrap();
+        }
+        // Half of the first segment and one document of the second are deleted.
+        for n in (0..5).chain([10]) {
+            index.delete(&format!("{n:040x}")).unwrap();
+        }
+
+        // The budget leaves room for one segment: the one with the larger share deleted.
+        assert_eq!(index.purge_deletes(15).await.unwrap(), Purge { segments: 1, deleted_docs: 5 });
+        let metas = index.inner.index.searchable_segment_metas().unwrap();
+        assert_eq!(metas.len(), 3);
+        assert_eq!(metas.iter().map(|m| m.num_deleted_docs()).sum::<u32>(), 1);
+        // A tenth deleted is the least worth rewriting.
+        assert_eq!(index.purge_deletes(100).await.unwrap(), Purge { segments: 1, deleted_docs: 1 });
+        assert_eq!(index.purge_deletes(100).await.unwrap(), Purge::default());
+        assert_eq!(index.search("debian", 100, QueryOptions::default()).unwrap().len(), 24);
+    }
+
+    #[test]
+    fn reconcile_repairs_divergence_from_storage() {
+        let dir = crate::test_dir("index");
+        let db = crate::kv::open(crate::kv::Backend::Sled, &dir.join("db")).unwrap();
+        let index = SearchIndex::open_or_create(dir.join("tantivy"), TitleTokenizer::default()).unwrap();
+        let stored = |n: u32| {
+            let mut record = crate::storage::TorrentRecord::new(&format!("{n:040x}"), 0);
+            record.title = Some(format!("Fedora {n}"));
+            crate::storage::merge_imported(&db, record).unwrap().0
+        };
+        let kept = stored(1);
+        index.upsert_record(&kept).unwrap();
+        // Deleted from storage, but the crash came before the index delete.
+        let ghost = stored(2);
+        index.upsert_record(&ghost).unwrap();
+        crate::storage::delete(&db, &ghost.info_hash_hex).unwrap();
+        // Stored, but the upsert never reached the index.
+        stored(3);
+        // Deleted from both; only its term lingers.
+        let gone = stored(4);
+        index.upsert_record(&gone).unwrap();
+        index.commit().unwrap();
+        crate::storage::delete(&db, &gone.info_hash_hex).unwrap();
+        index.delete(&gone.info_hash_hex).unwrap();
+        index.commit().unwrap();
+
+        assert_eq!(index.check_against(&db).unwrap(), Reconciled { removed: 1, added: 1 });
+        assert_eq!(index.reconcile(&db).unwrap(), Reconciled { removed: 1, added: 1 });
+        let mut found: Vec<_> = index
+            .search("fedora", 10, QueryOptions::default())
+            .unwrap()
+            .into_iter()
+            .filter_map(|hit| hit.title)
+            .collect();
+        found.sort();
+        assert_eq!(found, ["Fedora 1", "Fedora 3"]);
+        assert_eq!(index.reconcile(&db).unwrap(), Reconciled::default());
+    }
 }
@@ -0,0 +1,883 @@
+use crate::storage::Discovery;
+use crate::{AppState, storage};
+use anyhow::Context;
+use base64::Engine as _;
+use rbit::metainfo::{InfoHash, InfoHashV1, InfoHashV2, MagnetLink, Metainfo};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncSeekExt, BufReader};
+
+// Manual hash ingestion: one info hash or magnet link per line, from a file or stdin.
+//
+// Lines may carry extra columns after the hash (e.g. "<hash> <name>"); only the
+// first field is used. Blank lines and `#` comments are ignored.
+//
+// Magnet links keep their display name and trackers, so the record is searchable by
+// name and enrichment can announce to the trackers before metadata is fetched.
+//
+// `.torrent` files already contain the info dict, so they are stored and indexed
+// directly, without waiting for the DHT/enrichment round trip.
+//
+// Large lists are streamed: valid lines are written in batches (one sled batch per
+// tree, one index commit), optionally throttled by SERMA_INGEST_MAX_PER_SEC, and a
+// checkpoint (byte offset + counters) is saved after every batch so a file ingest
+// interrupted by a restart resumes where it stopped instead of starting over.
+
+/// A list to ingest while the server runs (SERMA_INGEST): `stdin` or `file:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IngestSource {
+    Stdin,
+    File(std::path::PathBuf),
+}
+
+impl std::str::FromStr for IngestSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("stdin") {
+            return Ok(Self::Stdin);
+        }
+        match s.strip_prefix("file:").map(str::trim) {
+            Some(path) if !path.is_empty() => Ok(Self::File(path.into())),
+            _ => Err("expected stdin or file:<path>".to_string()),
+        }
+    }
+}
+
+/// Ingests SERMA_INGEST once, next to the other workers, and logs the summary when the
+/// input ends. Shutdown stops it between lines; a file resumes from its checkpoint on
+/// the next start, stdin does not.
+pub async fn run(state: AppState) {
+    let Some(source) = state.config.ingest.clone() else {
+        return;
+    };
+    let path = match &source {
+        IngestSource::Stdin => None,
+        IngestSource::File(path) => Some(path.as_path()),
+    };
+    let started = Instant::now();
+    tokio::select! {
+        result = run_file_or_stdin_ingest(&state, path) => match result {
+            Ok(summary) => tracing::info!(
+                accepted = summary.accepted,
+                existing = summary.existing,
+                invalid = summary.invalid,
+                secs = started.elapsed().as_secs(),
+                "ingest: SERMA_INGEST finished"
+            ),
+            Err(err) => tracing::warn!(err = %format!("{err:#}"), "ingest: SERMA_INGEST failed"),
+        },
+        _ = state.shutdown.wait() => {
+            tracing::info!("ingest: stopped by shutdown before the input ended");
+        }
+    }
+    if let Err(err) = state.index.commit() {
+        tracing::warn!(%err, "ingest: index commit failed");
+    }
+}
+
+/// Valid lines written per batch (also the index commit interval).
+pub const INGEST_BATCH: usize = 1_000;
+
+/// Longer lines are rejected without being buffered in full.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// How many rejected lines are kept (with reasons) in a summary; the rest are only counted.
+const MAX_REJECTS_KEPT: usize = 20;
+
+/// Outcome of one ingest run (file, stdin, upload, watch folder or import).
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct IngestSummary {
+    /// Non-empty, non-comment lines (or rows / files) read.
+    pub lines: usize,
+    /// Hashes that were not in the database before.
+    pub accepted: usize,
+    /// Hashes that already existed (the record is refreshed/merged).
+    pub existing: usize,
+    /// Valid hashes that were refused on purpose (e.g. deleted recently).
+    pub blocked: usize,
+    /// Lines that could not be parsed.
+    pub invalid: usize,
+    /// The first few blocked/invalid lines with the reason they were refused.
+    pub rejects: Vec<Reject>,
+}
+
+/// A torrent refused on purpose (blacklisted, or matching a blocklist rule) rather than
+/// one that couldn't be read. `IngestSummary::reject_error` counts it as blocked.
+#[derive(Debug)]
+pub struct Refused(pub String);
+
+impl std::fmt::Display for Refused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Refused {}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reject {
+    /// 1-based line / row number (or 0 for a whole file, an upload or when unknown).
+    pub line: usize,
+    pub input: String,
+    pub reason: String,
+}
+
+impl IngestSummary {
+    pub fn add(&mut self, other: &IngestSummary) {
+        self.lines += other.lines;
+        self.accepted += other.accepted;
+        self.existing += other.existing;
+        self.blocked += other.blocked;
+        self.invalid += other.invalid;
+        for reject in &other.rejects {
+            if self.rejects.len() < MAX_REJECTS_KEPT {
+                self.rejects.push(reject.clone());
+            }
+        }
+    }
+
+    pub fn reject_invalid(&mut self, line: usize, input: &str, reason: impl Into<String>) {
+        self.invalid += 1;
+        self.keep_reject(line, input, reason.into());
+    }
+
+    pub fn reject_blocked(&mut self, line: usize, input: &str, reason: impl Into<String>) {
+        self.blocked += 1;
+        self.keep_reject(line, input, reason.into());
+    }
+
+    /// Counts a torrent that failed to ingest: as blocked if it was [`Refused`], else invalid.
+    pub fn reject_error(&mut self, line: usize, input: &str, err: &anyhow::Error) {
+        match err.downcast_ref::<Refused>() {
+            Some(refused) => self.reject_blocked(line, input, refused.to_string()),
+            None => self.reject_invalid(line, input, format!("{err:#}")),
+        }
+    }
+
+    fn keep_reject(&mut self, line: usize, input: &str, reason: String) {
+        tracing::debug!(line, input, reason = %reason, "ingest: rejected");
+        if self.rejects.len() < MAX_REJECTS_KEPT {
+            // Keep reports readable even if a "line" is a huge blob.
+            let input: String = input.chars().take(120).collect();
+            self.rejects.push(Reject { line, input, reason });
+        }
+    }
+
+    /// Logs the summary; `what` names the run (e.g. the file path).
+    pub fn log(&self, what: &str) {
+        tracing::info!(
+            source = what,
+            lines = self.lines,
+            accepted = self.accepted,
+            existing = self.existing,
+            blocked = self.blocked,
+            invalid = self.invalid,
+            "ingest: done"
+        );
+        for reject in &self.rejects {
+            tracing::info!(source = what, line = reject.line, input = %reject.input, reason = %reject.reason, "ingest: rejected");
+        }
+        let unlisted = (self.blocked + self.invalid).saturating_sub(self.rejects.len());
+        if unlisted > 0 {
+            tracing::info!(source = what, unlisted, "ingest: more rejected lines not listed");
+        }
+    }
+}
+
+impl std::fmt::Display for IngestSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} entries: {} accepted, {} already known, {} blocked, {} invalid",
+            self.lines, self.accepted, self.existing, self.blocked, self.invalid
+        )?;
+        for reject in &self.rejects {
+            let at = if reject.line > 0 {
+                format!("line {}", reject.line)
+            } else {
+                "file".to_string()
+            };
+            write!(f, "\n  {at}: {} ({})", reject.reason, reject.input)?;
+        }
+        let unlisted = (self.blocked + self.invalid).saturating_sub(self.rejects.len());
+        if unlisted > 0 {
+            write!(f, "\n  ... and {unlisted} more")?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn run_file_or_stdin_ingest(state: &AppState, path: Option<&Path>) -> anyhow::Result<IngestSummary> {
+    let summary = match path {
+        Some(path) if is_torrent_file(path) => ingest_torrent_file(state, path).await?,
+        Some(path) => ingest_list_file(state, path).await?,
+        None => {
+            tracing::info!("ingest: reading stdin");
+            ingest_lines(state, BufReader::new(stdin_reader()), Resume::default(), None).await?
+        }
+    };
+
+    let what = path.map(|p| p.display().to_string()).unwrap_or_else(|| "stdin".to_string());
+    summary.log(&what);
+    Ok(summary)
+}
+
+/// Stdin, read on a thread of its own: tokio's stdin reads on the blocking pool, where a
+/// read waiting for input that never comes would keep the runtime from shutting down.
+fn stdin_reader() -> tokio::io::DuplexStream {
+    use std::io::Read;
+    use tokio::io::AsyncWriteExt;
+
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(4);
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if tx.blocking_send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    tracing::warn!(%err, "ingest: reading stdin failed");
+                    break;
+                }
+            }
+        }
+    });
+    tokio::spawn(async move {
+        while let Some(chunk) = rx.recv().await {
+            if writer.write_all(&chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+    reader
+}
+
+fn is_torrent_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("torrent"))
+}
+
+async fn ingest_torrent_file(state: &AppState, path: &Path) -> anyhow::Result<IngestSummary> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("read torrent file {}", path.display()))?;
+
+    let mut summary = IngestSummary {
+        lines: 1,
+        ..Default::default()
+    };
+    match ingest_torrent_bytes(state, &bytes, Discovery::File) {
+        Ok((_, true)) => summary.accepted += 1,
+        Ok((_, false)) => summary.existing += 1,
+        Err(err) => summary.reject_error(0, &path.display().to_string(), &err),
+    }
+    state.index.commit()?;
+    Ok(summary)
+}
+
+/// Stores a .torrent file's info dict and indexes it under its embedded name. A new
+/// record is counted as discovered `via` this. Blacklisted torrents and ones matching the
+/// blocklist are [`Refused`] before anything is stored.
+///
+/// Returns the stored record and whether it was new. The caller commits the index.
+pub fn ingest_torrent_bytes(
+    state: &AppState,
+    bytes: &[u8],
+    via: Discovery,
+) -> anyhow::Result<(storage::TorrentRecord, bool)> {
+    let metainfo = Metainfo::from_bytes(bytes).context("parse torrent")?;
+    let info_hash_hex = swarm_hash_hex(&metainfo.info_hash);
+    if storage::is_blacklisted(&state.db, &info_hash_hex)? {
+        return Err(Refused(format!("torrent {info_hash_hex} is blacklisted")).into());
+    }
+    let info_b64 = base64::engine::general_purpose::STANDARD.encode(metainfo.raw_info());
+    let mut incoming = storage::TorrentRecord::new(&info_hash_hex, 0);
+    incoming.title = Some(metainfo.info.name.clone());
+    incoming.info_bencode_base64 = Some(info_b64.clone());
+    if let Some(rule) = blocked_by(state, &incoming) {
+        return Err(Refused(rule).into());
+    }
+
+    let existed = storage::get(&state.db, &info_hash_hex)?.is_some();
+    if !existed {
+        storage::upsert_first_seen(&state.db, &info_hash_hex, Some(via))?;
+    }
+    let record = storage::set_metadata(&state.db, &info_hash_hex, Some(&metainfo.info.name), &info_b64)?;
+
+    let magnet = MagnetLink {
+        info_hash: metainfo.info_hash,
+        display_name: Some(metainfo.info.name.clone()),
+        trackers: metainfo.trackers(),
+        web_seeds: metainfo.url_list.clone(),
+        peer_addresses: Vec::new(),
+    };
+    let merged = merge_magnet(record.magnet.as_deref(), &magnet);
+    let record = storage::set_magnet(&state.db, &info_hash_hex, &merged)?;
+
+    // The user has the file, so index it right away instead of waiting for a seeder count.
+    state.index.upsert_record(&record)?;
+
+    Ok((record, !existed))
+}
+
+/// Progress through a hash list file, stored in the `meta` tree after every batch.
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    /// Size and mtime identify the file version; a changed file starts over.
+    size: u64,
+    modified_unix_ms: i64,
+    /// Byte offset just past the last line whose outcome is already counted.
+    offset: u64,
+    line_no: usize,
+    lines: usize,
+    accepted: usize,
+    existing: usize,
+    blocked: usize,
+    invalid: usize,
+}
+
+/// Where a line ingest starts: the byte offset / line number and the counts so far.
+#[derive(Default)]
+struct Resume {
+    offset: u64,
+    line_no: usize,
+    summary: IngestSummary,
+}
+
+/// Identifies the file being ingested, for checkpointing.
+struct CheckpointTarget {
+    key: String,
+    size: u64,
+    modified_unix_ms: i64,
+}
+
+async fn ingest_list_file(state: &AppState, path: &Path) -> anyhow::Result<IngestSummary> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("open ingest file {}", path.display()))?;
+    let meta = file.metadata().await?;
+    let canonical = tokio::fs::canonicalize(path)
+        .await
+        .unwrap_or_else(|_| path.to_path_buf());
+    let target = CheckpointTarget {
+        key: format!("ingest_checkpoint:{}", canonical.display()),
+        size: meta.len(),
+        modified_unix_ms: meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0),
+    };
+
+    let resume = match load_checkpoint(state, &target) {
+        Some(cp) => {
+            tracing::info!(
+                path = %path.display(),
+                offset = cp.offset,
+                line = cp.line_no,
+                "ingest: resuming file from checkpoint"
+            );
+            file.seek(std::io::SeekFrom::Start(cp.offset)).await?;
+            Resume {
+                offset: cp.offset,
+                line_no: cp.line_no,
+                summary: IngestSummary {
+                    lines: cp.lines,
+                    accepted: cp.accepted,
+                    existing: cp.existing,
+                    blocked: cp.blocked,
+                    invalid: cp.invalid,
+                    rejects: Vec::new(),
+                },
+            }
+        }
+        None => {
+            tracing::info!(path = %path.display(), "ingest: reading file");
+            Resume::default()
+        }
+    };
+
+    let summary = ingest_lines(state, BufReader::new(file), resume, Some(&target)).await?;
+    storage::remove_meta(&state.db, &target.key)?;
+    Ok(summary)
+}
+
+fn load_checkpoint(state: &AppState, target: &CheckpointTarget) -> Option<Checkpoint> {
+    let bytes = storage::get_meta(&state.db, &target.key).ok()??;
+    let cp: Checkpoint = serde_json::from_slice(&bytes).ok()?;
+    (cp.size == target.size && cp.modified_unix_ms == target.modified_unix_ms && cp.offset <= cp.size)
+        .then_some(cp)
+}
+
+fn save_checkpoint(
+    state: &AppState,
+    target: &CheckpointTarget,
+    offset: u64,
+    line_no: usize,
+    summary: &IngestSummary,
+) -> anyhow::Result<()> {
+    let cp = Checkpoint {
+        size: target.size,
+        modified_unix_ms: target.modified_unix_ms,
+        offset,
+        line_no,
+        lines: summary.lines,
+        accepted: summary.accepted,
+        existing: summary.existing,
+        blocked: summary.blocked,
+        invalid: summary.invalid,
+    };
+    storage::set_meta(&state.db, &target.key, &serde_json::to_vec(&cp)?)
+}
+
+async fn ingest_lines<R: AsyncBufRead + Unpin>(
+    state: &AppState,
+    mut reader: R,
+    resume: Resume,
+    checkpoint: Option<&CheckpointTarget>,
+) -> anyhow::Result<IngestSummary> {
+    let Resume {
+        mut offset,
+        mut line_no,
+        mut summary,
+    } = resume;
+
+    let mut throttle = Throttle::new(state.config.ingest_max_per_sec);
+    let batch_size = throttle.batch_size();
+
+    let mut batch: Vec<IngestItem> = Vec::with_capacity(batch_size);
+    let mut buf: Vec<u8> = Vec::new();
+
+    loop {
+        let (consumed, overflow) = read_line_capped(&mut reader, &mut buf)
+            .await
+            .context("read ingest line")?;
+        if consumed == 0 {
+            break;
+        }
+        offset += consumed as u64;
+        line_no += 1;
+
+        if overflow {
+            summary.lines += 1;
+            summary.reject_invalid(
+                line_no,
+                &String::from_utf8_lossy(&buf),
+                format!("line is longer than {MAX_LINE_BYTES} bytes"),
+            );
+            continue;
+        }
+        let Ok(line) = std::str::from_utf8(&buf) else {
+            summary.lines += 1;
+            summary.reject_invalid(line_no, &String::from_utf8_lossy(&buf), "line is not valid UTF-8");
+            continue;
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        summary.lines += 1;
+
+        match parse_line(line) {
+            Ok(item) => batch.push(item),
+            Err(reason) => {
+                summary.reject_invalid(line_no, line, reason);
+                continue;
+            }
+        }
+
+        if batch.len() >= batch_size {
+            ingest_items(state, &batch, Discovery::File, &mut summary)?;
+            if let Some(target) = checkpoint {
+                save_checkpoint(state, target, offset, line_no, &summary)?;
+            }
+            tracing::info!(
+                lines = summary.lines,
+                accepted = summary.accepted,
+                per_sec = throttle.rate(),
+                "ingest: progress"
+            );
+            throttle.wait(batch.len()).await;
+            batch.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        ingest_items(state, &batch, Discovery::File, &mut summary)?;
+    }
+    Ok(summary)
+}
+
+/// Caps the write rate of a long ingest (SERMA_INGEST_MAX_PER_SEC; 0 = unlimited).
+pub struct Throttle {
+    max_per_sec: u64,
+    started: Instant,
+    written: u64,
+}
+
+impl Throttle {
+    pub fn new(max_per_sec: u64) -> Self {
+        Self {
+            max_per_sec,
+            started: Instant::now(),
+            written: 0,
+        }
+    }
+
+    /// Batch size that keeps bursts to about a second's worth of hashes.
+    pub fn batch_size(&self) -> usize {
+        if self.max_per_sec > 0 {
+            INGEST_BATCH.min(self.max_per_sec as usize)
+        } else {
+            INGEST_BATCH
+        }
+    }
+
+    /// Items written per second so far.
+    pub fn rate(&self) -> u64 {
+        (self.written as f64 / self.started.elapsed().as_secs_f64().max(0.001)) as u64
+    }
+
+    /// Records `n` written items and sleeps if that puts us ahead of the allowed rate.
+    pub async fn wait(&mut self, n: usize) {
+        self.written += n as u64;
+        if self.max_per_sec == 0 {
+            return;
+        }
+        let due = Duration::from_secs_f64(self.written as f64 / self.max_per_sec as f64);
+        let elapsed = self.started.elapsed();
+        if due > elapsed {
+            tokio::time::sleep(due - elapsed).await;
+        }
+    }
+}
+
+/// Reads one line (including its `\n`) into `buf`, keeping at most MAX_LINE_BYTES of it.
+///
+/// Returns the number of bytes consumed (0 at EOF) and whether the line was cut short.
+async fn read_line_capped<R: AsyncBufRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> std::io::Result<(usize, bool)> {
+    buf.clear();
+    let mut consumed = 0;
+    let mut overflow = false;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok((consumed, overflow));
+        }
+        let (chunk_len, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), false),
+        };
+        let room = MAX_LINE_BYTES.saturating_sub(buf.len());
+        if chunk_len > room {
+            overflow = true;
+        }
+        buf.extend_from_slice(&available[..chunk_len.min(room)]);
+        reader.consume(chunk_len);
+        consumed += chunk_len;
+        if done {
+            return Ok((consumed, overflow));
+        }
+    }
+}
+
+/// The 20-byte hash a torrent is stored under: its v1 hash, or for v2-only torrents the
+/// truncated v2 hash that BEP 52 uses on the DHT and in peer handshakes.
+pub fn swarm_hash_hex(info_hash: &InfoHash) -> String {
+    match (info_hash.v1_hash(), info_hash.v2_hash()) {
+        (Some(v1), _) => v1.to_hex(),
+        (None, Some(v2)) => v2.to_hex()[..40].to_string(),
+        (None, None) => info_hash.to_hex(),
+    }
+}
+
+/// The full info hash of a stored record: v1, hybrid (v1 + v2), or v2-only.
+pub fn record_info_hash(record: &storage::TorrentRecord) -> Option<InfoHash> {
+    let Some(v2) = record.info_hash_v2_hex.as_deref() else {
+        return InfoHash::from_hex(&record.info_hash_hex).ok();
+    };
+    let v2 = InfoHashV2::from_hex(v2).ok()?;
+    if v2.to_hex()[..40] == record.info_hash_hex {
+        return Some(InfoHash::V2(*v2.as_bytes()));
+    }
+    Some(InfoHash::hybrid(InfoHashV1::from_hex(&record.info_hash_hex).ok()?, v2))
+}
+
+/// The magnet for a record with metadata: the stored one, trackers, web seeds and peers
+/// kept, with the torrent's name as `dn`, the v2 hash of a v2 or hybrid torrent, and
+/// `trackers` added. `None` if that is what's stored already.
+pub fn enriched_magnet(record: &storage::TorrentRecord, trackers: &[String]) -> Option<String> {
+    let info_hash = record_info_hash(record)?;
+    let mut magnet = match record.magnet.as_deref().and_then(|m| MagnetLink::parse(m).ok()) {
+        Some(magnet) => magnet,
+        None => MagnetLink {
+            info_hash,
+            display_name: None,
+            trackers: Vec::new(),
+            web_seeds: Vec::new(),
+            peer_addresses: Vec::new(),
+        },
+    };
+    if record.info_hash_v2_hex.is_some() {
+        magnet.info_hash = info_hash;
+    }
+    if let Some(title) = record.title.as_deref().filter(|t| !t.trim().is_empty()) {
+        magnet.display_name = Some(title.to_string());
+    }
+    for tr in trackers {
+        if !magnet.trackers.contains(tr) {
+            magnet.trackers.push(tr.clone());
+        }
+    }
+    let uri = magnet.to_uri();
+    (record.magnet.as_deref() != Some(uri.as_str())).then_some(uri)
+}
+
+/// A .torrent file for a record with metadata: the stored info dict byte for byte (so the
+/// hash matches), with the magnet's trackers as `announce`/`announce-list` (one tier each)
+/// and its web seeds as `url-list`. `None` until the metadata has been fetched.
+pub fn torrent_file(record: &storage::TorrentRecord) -> Option<Vec<u8>> {
+    let info = base64::engine::general_purpose::STANDARD
+        .decode(record.info_bencode_base64.as_deref()?)
+        .ok()?;
+    let magnet = record.magnet.as_deref().and_then(|m| MagnetLink::parse(m).ok());
+    let (trackers, web_seeds) = magnet.map(|m| (m.trackers, m.web_seeds)).unwrap_or_default();
+
+    // Keys in sorted order, as bencoding requires.
+    let mut out = vec![b'd'];
+    if let Some(first) = trackers.first() {
+        put_bencode_bytes(&mut out, b"announce");
+        put_bencode_bytes(&mut out, first.as_bytes());
+        put_bencode_bytes(&mut out, b"announce-list");
+        out.push(b'l');
+        for tracker in &trackers {
+            out.push(b'l');
+            put_bencode_bytes(&mut out, tracker.as_bytes());
+            out.push(b'e');
+        }
+        out.push(b'e');
+    }
+    put_bencode_bytes(&mut out, b"info");
+    out.extend_from_slice(&info);
+    if !web_seeds.is_empty() {
+        put_bencode_bytes(&mut out, b"url-list");
+        out.push(b'l');
+        for url in &web_seeds {
+            put_bencode_bytes(&mut out, url.as_bytes());
+        }
+        out.push(b'e');
+    }
+    out.push(b'e');
+    Some(out)
+}
+
+fn put_bencode_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+pub struct IngestItem {
+    pub info_hash_hex: String,
+    /// Present when the line was a magnet link.
+    pub magnet: Option<MagnetLink>,
+}
+
+/// Stores a batch of items (creating records as needed, discovered `via` this), indexes the
+/// active ones, commits the index and adds the outcome to `summary`.
+pub fn ingest_items(
+    state: &AppState,
+    items: &[IngestItem],
+    via: Discovery,
+    summary: &mut IngestSummary,
+) -> anyhow::Result<()> {
+    let mut allowed = Vec::with_capacity(items.len());
+    for item in items {
+        let mut incoming = storage::TorrentRecord::new(&item.info_hash_hex, 0);
+        incoming.title = item.magnet.as_ref().and_then(|m| m.display_name.clone());
+        if storage::is_blacklisted(&state.db, &item.info_hash_hex)? {
+            summary.reject_blocked(0, &item.info_hash_hex, "blacklisted");
+        } else if let Some(rule) = blocked_by(state, &incoming) {
+            summary.reject_blocked(0, &item.info_hash_hex, rule);
+        } else {
+            allowed.push(item);
+        }
+    }
+    let items = allowed;
+    let hashes: Vec<String> = items.iter().map(|item| item.info_hash_hex.clone()).collect();
+    let stored = storage::upsert_many(&state.db, &hashes, via, |i, record| apply_item(items[i], record))?;
+
+    for (record, created) in stored {
+        if created {
+            summary.accepted += 1;
+        } else {
+            summary.existing += 1;
+        }
+
+        // Same activity threshold as the spider: enrichment indexes the rest once it has seeders.
+        if record.seeders >= 2 {
+            state.index.upsert_record(&record)?;
+        }
+    }
+
+    state.index.commit()?;
+    Ok(())
+}
+
+/// The blocklist rule a torrent coming in from outside matches, as a reject reason. Checked
+/// before it is stored, like enrichment checks a spidered torrent once it learns the name.
+pub fn blocked_by(state: &AppState, incoming: &storage::TorrentRecord) -> Option<String> {
+    let rule = state.blocklist.matches(incoming)?;
+    tracing::info!(hash = %incoming.info_hash_hex, %rule, "ingest: dropped by blocklist");
+    Some(format!("matches blocklist {rule}"))
+}
+
+fn apply_item(item: &IngestItem, record: &mut storage::TorrentRecord) {
+    if record.info_hash_v2_hex.is_none()
+        && let Some(v2) = item.magnet.as_ref().and_then(|m| m.info_hash.v2_hash())
+    {
+        record.info_hash_v2_hex = Some(v2.to_hex());
+    }
+    if let Some(magnet) = item.magnet.as_ref() {
+        record.magnet = Some(merge_magnet(record.magnet.as_deref(), magnet));
+        // The name from the info dict is authoritative; `dn` only fills the gap until then.
+        if record.title.is_none()
+            && let Some(name) = magnet.display_name.as_deref()
+            && !name.trim().is_empty()
+        {
+            record.title = Some(name.trim().to_string());
+            record.refresh_release();
+        }
+    } else if record
+        .magnet
+        .as_deref()
+        .is_none_or(|m| m.trim().is_empty())
+    {
+        record.magnet = Some(format!("magnet:?xt=urn:btih:{}", item.info_hash_hex));
+    }
+}
+
+/// Combines an incoming magnet with the stored one, keeping every tracker seen so far.
+pub fn merge_magnet(existing: Option<&str>, incoming: &MagnetLink) -> String {
+    let mut merged = incoming.clone();
+    if let Some(existing) = existing.and_then(|m| MagnetLink::parse(m).ok()) {
+        if merged.display_name.is_none() {
+            merged.display_name = existing.display_name;
+        }
+        for tr in existing.trackers {
+            if !merged.trackers.contains(&tr) {
+                merged.trackers.push(tr);
+            }
+        }
+    }
+    merged.to_uri()
+}
+
+pub fn parse_line(line: &str) -> Result<IngestItem, String> {
+    let first = line
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .find(|s| !s.is_empty())
+        .unwrap_or_default();
+
+    if first.len() > 8 && first.get(..8).is_some_and(|p| p.eq_ignore_ascii_case("magnet:?")) {
+        // Magnets are URL-encoded, so the link itself never contains whitespace.
+        let token = line.split_whitespace().next().unwrap_or_default();
+        let magnet = MagnetLink::parse(&format!("magnet:?{}", &token[8..]))
+            .map_err(|err| err.to_string())?;
+        return Ok(IngestItem {
+            info_hash_hex: swarm_hash_hex(&magnet.info_hash),
+            magnet: Some(magnet),
+        });
+    }
+
+    if first.len() != 40 && first.len() != 64 {
+        return Err(format!(
+            "expected a 40- or 64-character info hash or magnet link, got {} characters",
+            first.len()
+        ));
+    }
+    if !first.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("info hash contains non-hex characters".to_string());
+    }
+    if first.len() == 64 {
+        // Keep the full v2 hash; a bare magnet lets apply_item record it.
+        let info_hash = InfoHash::from_hex(first).map_err(|err| err.to_string())?;
+        return Ok(IngestItem {
+            info_hash_hex: swarm_hash_hex(&info_hash),
+            magnet: Some(MagnetLink {
+                info_hash,
+                display_name: None,
+                trackers: Vec::new(),
+                web_seeds: Vec::new(),
+                peer_addresses: Vec::new(),
+            }),
+        });
+    }
+    Ok(IngestItem {
+        info_hash_hex: first.to_ascii_lowercase(),
+        magnet: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "c9e15763f722f23e98a29decdfae341b98d53056";
+    const BASE32: &str = "ZHQVOY7XELZD5GFCTXWN7LRUDOMNKMCW";
+    const V2: &str = "caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e";
+
+    fn parsed(line: &str) -> IngestItem {
+        parse_line(line).unwrap_or_else(|err| panic!("{line:?}: {err}"))
+    }
+
+    #[test]
+    fn parses_hashes_and_magnets() {
+        assert_eq!(parsed(&HEX.to_ascii_uppercase()).info_hash_hex, HEX);
+        assert_eq!(parsed(&format!("{HEX}, some title")).info_hash_hex, HEX);
+        assert_eq!(parsed(&format!("magnet:?xt=urn:btih:{HEX}&dn=x")).info_hash_hex, HEX);
+        assert_eq!(parsed(&format!("MAGNET:?xt=urn:btih:{BASE32}")).info_hash_hex, HEX);
+        assert_eq!(parsed(&format!("magnet:?xt=urn:btih:{}", BASE32.to_ascii_lowercase())).info_hash_hex, HEX);
+
+        // v2-only: stored under the truncated hash, the full one kept on the magnet.
+        for line in [format!("magnet:?xt=urn:btmh:1220{V2}"), V2.to_string()] {
+            let item = parsed(&line);
+            assert_eq!(item.info_hash_hex, V2[..40]);
+            assert_eq!(item.magnet.unwrap().info_hash.v2_hash().unwrap().to_hex(), V2);
+        }
+
+        // Hybrid: both `xt`s in one link; the v1 hash names the record.
+        let item = parsed(&format!("magnet:?xt=urn:btih:{HEX}&xt=urn:btmh:1220{V2}&tr=udp%3A%2F%2Ft%3A1"));
+        assert_eq!(item.info_hash_hex, HEX);
+        let magnet = item.magnet.unwrap();
+        assert_eq!(magnet.info_hash.v2_hash().unwrap().to_hex(), V2);
+        assert_eq!(magnet.trackers, ["udp://t:1"]);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for line in [
+            "",
+            "not a hash",
+            &HEX[..39],
+            &format!("{}g", &HEX[..39]),
+            "magnet:?dn=no+hash",
+            "magnet:?xt=urn:btih:tooshort",
+            &format!("magnet:?xt=urn:btih:{}1", &BASE32[..31]),
+            &format!("magnet:?xt=urn:btmh:1220{}", &V2[..60]),
+        ] {
+            assert!(parse_line(line).is_err(), "{line:?} was accepted");
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use crate::{AppState, storage};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+// Bulk operations (`/api/torrents/bulk`) run here, in the background.
+//
+// The web handler validates a request and queues it; `run` works through the queue one
+// job at a time, so two large jobs never fight over the index writer. Job status is kept
+// in memory for the most recent jobs only and is gone after a restart; changes already
+// made stay.
+
+/// Most hashes one job may touch, whether listed or matched by a query.
+pub const MAX_TARGETS: usize = 10_000;
+/// Finished jobs whose status is kept.
+const KEEP_FINISHED: usize = 100;
+/// Storage operations per blocking batch; progress is reported between batches.
+const BATCH: usize = 256;
+/// Concurrent swarm lookups for `re-enrich` on torrents that already have metadata.
+const RECHECK_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BulkOp {
+    Delete,
+    Blacklist,
+    Pin,
+    Unpin,
+    /// Torrents without metadata go back on the enrich queue with their backoff reset;
+    /// the rest get fresh seeder counts.
+    ReEnrich,
+}
+
+#[derive(Debug, Clone)]
+pub enum Target {
+    Hashes(Vec<String>),
+    /// Search results, best first, up to `limit`.
+    Query { q: String, limit: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub id: u64,
+    pub op: BulkOp,
+    /// The search, for jobs that target one.
+    pub query: Option<String>,
+    pub state: JobState,
+    /// Hashes to process; known once a query target has been resolved.
+    pub total: Option<usize>,
+    pub processed: usize,
+    /// Hashes the operation actually changed (e.g. records deleted, newly pinned).
+    pub changed: usize,
+    pub failed: usize,
+    pub error: Option<String>,
+    pub created_unix_ms: i64,
+    pub finished_unix_ms: Option<i64>,
+}
+
+struct Job {
+    id: u64,
+    op: BulkOp,
+    target: Target,
+}
+
+#[derive(Clone)]
+pub struct Jobs {
+    inner: Arc<JobsInner>,
+}
+
+struct JobsInner {
+    next_id: AtomicU64,
+    status: Mutex<BTreeMap<u64, JobStatus>>,
+    tx: mpsc::UnboundedSender<Job>,
+    /// Taken by `run`.
+    rx: Mutex<Option<mpsc::UnboundedReceiver<Job>>>,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            inner: Arc::new(JobsInner {
+                next_id: AtomicU64::new(1),
+                status: Mutex::new(BTreeMap::new()),
+                tx,
+                rx: Mutex::new(Some(rx)),
+            }),
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn now_unix_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+impl Jobs {
+    /// Queues a job and returns its initial status.
+    pub fn submit(&self, op: BulkOp, target: Target) -> JobStatus {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let (total, query) = match &target {
+            Target::Hashes(hashes) => (Some(hashes.len()), None),
+            Target::Query { q, .. } => (None, Some(q.clone())),
+        };
+        let status = JobStatus {
+            id,
+            op,
+            query,
+            state: JobState::Queued,
+            total,
+            processed: 0,
+            changed: 0,
+            failed: 0,
+            error: None,
+            created_unix_ms: now_unix_ms(),
+            finished_unix_ms: None,
+        };
+        lock(&self.inner.status).insert(id, status.clone());
+        let _ = self.inner.tx.send(Job { id, op, target });
+        status
+    }
+
+    pub fn get(&self, id: u64) -> Option<JobStatus> {
+        lock(&self.inner.status).get(&id).cloned()
+    }
+
+    /// Known jobs, newest first.
+    pub fn list(&self) -> Vec<JobStatus> {
+        lock(&self.inner.status).values().rev().cloned().collect()
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut JobStatus)) {
+        if let Some(status) = lock(&self.inner.status).get_mut(&id) {
+            f(status);
+        }
+    }
+
+    fn finish(&self, id: u64, error: Option<String>) {
+        let mut status = lock(&self.inner.status);
+        if let Some(job) = status.get_mut(&id) {
+            job.state = if error.is_some() { JobState::Failed } else { JobState::Done };
+            job.error = error;
+            job.finished_unix_ms = Some(now_unix_ms());
+        }
+        let finished: Vec<u64> = status
+            .values()
+            .filter(|j| j.finished_unix_ms.is_some())
+            .map(|j| j.id)
+            .collect();
+        for id in finished.iter().take(finished.len().saturating_sub(KEEP_FINISHED)) {
+            status.remove(id);
+        }
+    }
+}
+
+pub async fn run(state: AppState) {
+    let Some(mut rx) = lock(&state.jobs.inner.rx).take() else {
+        return;
+    };
+    loop {
+        let job = tokio::select! {
+            job = rx.recv() => match job {
+                Some(job) => job,
+                None => return,
+            },
+            _ = state.shutdown.wait() => return,
+        };
+        state.jobs.update(job.id, |s| s.state = JobState::Running);
+        let result = run_job(&state, &job).await;
+        if let Err(err) = &result {
+            tracing::warn!(id = job.id, op = ?job.op, %err, "jobs: failed");
+        }
+        state.jobs.finish(job.id, result.err().map(|err| format!("{err:#}")));
+        if let Some(status) = state.jobs.get(job.id) {
+            tracing::info!(
+                id = job.id,
+                op = ?job.op,
+                processed = status.processed,
+                changed = status.changed,
+                failed = status.failed,
+                "jobs: finished"
+            );
+        }
+    }
+}
+
+async fn run_job(state: &AppState, job: &Job) -> anyhow::Result<()> {
+    let hashes = match &job.target {
+        Target::Hashes(hashes) => hashes.clone(),
+        Target::Query { q, limit } => {
+            let page = state.index.search_counted(
+                q,
+                crate::index::SearchSort::Relevance,
+                0,
+                *limit,
+                state.config.query_options(),
+                crate::index::SearchFilters::default(),
+            )?;
+            page.hits.into_iter().filter_map(|hit| hit.info_hash).collect()
+        }
+    };
+    state.jobs.update(job.id, |s| s.total = Some(hashes.len()));
+
+    let tracker = match job.op {
+        BulkOp::ReEnrich => Some(crate::scrape::http_client()?),
+        _ => None,
+    };
+    for chunk in hashes.chunks(BATCH) {
+        if state.shutdown.is_triggered() {
+            anyhow::bail!("interrupted by shutdown");
+        }
+        let (changed, failed) = match &tracker {
+            Some(tracker) => re_enrich(state, tracker, chunk).await,
+            None => apply_storage(state, job.op, chunk.to_vec()).await?,
+        };
+        state.jobs.update(job.id, |s| {
+            s.processed += chunk.len();
+            s.changed += changed;
+            s.failed += failed;
+        });
+    }
+    if matches!(job.op, BulkOp::Delete | BulkOp::Blacklist) {
+        state.index.commit()?;
+    } else {
+        state.index.maybe_commit()?;
+    }
+    Ok(())
+}
+
+/// Deletes, blacklists, pins or unpins a batch. Returns `(changed, failed)`.
+async fn apply_storage(state: &AppState, op: BulkOp, hashes: Vec<String>) -> anyhow::Result<(usize, usize)> {
+    let db = state.db.clone();
+    let results = tokio::task::spawn_blocking(move || {
+        hashes
+            .into_iter()
+            .map(|hash| {
+                let hash = storage::resolve_info_hash(&db, &hash)?.ok_or_else(|| anyhow::anyhow!("invalid hash"))?;
+                let changed = match op {
+                    BulkOp::Delete => {
+                        let existed = storage::get(&db, &hash)?.is_some();
+                        if existed {
+                            storage::delete(&db, &hash)?;
+                        }
+                        existed
+                    }
+                    BulkOp::Blacklist => {
+                        storage::blacklist(&db, &hash)?;
+                        true
+                    }
+                    BulkOp::Pin | BulkOp::Unpin => {
+                        let pinned = op == BulkOp::Pin;
+                        let before = storage::get(&db, &hash)?.map(|r| r.pinned);
+                        storage::set_pinned(&db, &hash, pinned)?;
+                        before.is_some_and(|was| was != pinned)
+                    }
+                    BulkOp::ReEnrich => unreachable!("re-enrich runs in re_enrich"),
+                };
+                Ok((hash, changed))
+            })
+            .collect::<Vec<anyhow::Result<(String, bool)>>>()
+    })
+    .await?;
+
+    let (mut changed, mut failed) = (0, 0);
+    for result in results {
+        match result {
+            Ok((hash, true)) => {
+                changed += 1;
+                if matches!(op, BulkOp::Delete | BulkOp::Blacklist) {
+                    state.index.delete(&hash)?;
+                }
+            }
+            Ok((_, false)) => {}
+            Err(err) => {
+                tracing::debug!(%err, ?op, "jobs: item failed");
+                failed += 1;
+            }
+        }
+    }
+    Ok((changed, failed))
+}
+
+/// Requeues torrents without metadata and re-checks the swarm of the rest.
+/// Returns `(changed, failed)`.
+async fn re_enrich(state: &AppState, tracker: &reqwest::Client, hashes: &[String]) -> (usize, usize) {
+    use futures_util::StreamExt as _;
+
+    let results: Vec<anyhow::Result<bool>> = futures_util::stream::iter(hashes.to_vec())
+        .map(|hash| async move {
+            let Some(hash) = storage::resolve_info_hash(&state.db, &hash)? else {
+                anyhow::bail!("invalid hash");
+            };
+            if storage::requeue_missing_info(&state.db, &hash)? {
+                return Ok(true);
+            }
+            let Some(record) = storage::get(&state.db, &hash)? else {
+                return Ok(false);
+            };
+            crate::enrich::recheck_one(state, tracker, &record).await?;
+            Ok(true)
+        })
+        .buffer_unordered(RECHECK_CONCURRENCY)
+        .collect()
+        .await;
+
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    let changed = results.iter().filter(|r| matches!(r, Ok(true))).count();
+    (changed, failed)
+}
@@ -0,0 +1,529 @@
+use anyhow::Context;
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Storage backends.
+//
+// `storage.rs` keeps records and their indexes in named, ordered key-value trees, which is
+// sled's model. `Store` is that model as a trait, implemented by sled and by SQLite
+// (`sqlite.rs`), and picked with SERMA_STORAGE. `Db` and `Tree` are the handles everything
+// else uses; they keep sled's method names so storage code reads the same on both.
+
+/// A key and its value.
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Computes a new value from the old one for `update_and_fetch`; `None` means absent.
+pub type Update<'a> = dyn FnMut(Option<&[u8]>) -> Option<Vec<u8>> + 'a;
+
+/// The tree behind `Db::get`, `Db::insert` etc.; the name sled gives its own default tree.
+pub const DEFAULT_TREE: &[u8] = b"__sled__default";
+
+/// Entries fetched by the first read of an `Iter`; later reads double it up to `MAX_CHUNK`.
+const FIRST_CHUNK: usize = 16;
+const MAX_CHUNK: usize = 1024;
+
+pub trait Store: Send + Sync {
+    fn get(&self, tree: &[u8], key: &[u8]) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Returns the previous value.
+    fn insert(&self, tree: &[u8], key: &[u8], value: &[u8]) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Returns the removed value.
+    fn remove(&self, tree: &[u8], key: &[u8]) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Atomically replaces the value with `f(old)` (`None` removes it); returns the new value.
+    fn update_and_fetch(
+        &self,
+        tree: &[u8],
+        key: &[u8],
+        f: &mut Update<'_>,
+    ) -> anyhow::Result<Option<Vec<u8>>>;
+    /// Up to `limit` entries between the bounds, in key order, or reverse key order.
+    fn range(
+        &self,
+        tree: &[u8],
+        lo: Bound<&[u8]>,
+        hi: Bound<&[u8]>,
+        reverse: bool,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Entry>>;
+    fn len(&self, tree: &[u8]) -> anyhow::Result<usize>;
+    /// Applies every write in the batch, or none of them.
+    fn apply_batch(&self, tree: &[u8], batch: Batch) -> anyhow::Result<()>;
+    /// A unique, increasing id.
+    fn generate_id(&self) -> anyhow::Result<u64>;
+    fn tree_names(&self) -> anyhow::Result<Vec<Vec<u8>>>;
+    /// Makes every write so far durable.
+    fn flush(&self) -> anyhow::Result<()>;
+    fn size_on_disk(&self) -> anyhow::Result<u64>;
+}
+
+/// Which `Store` the data dir uses (SERMA_STORAGE).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Sled,
+    Sqlite,
+}
+
+impl Backend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Backend::Sled => "sled",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+
+    /// Where the database lives in the data dir.
+    pub fn path(self, data_dir: &Path) -> PathBuf {
+        match self {
+            Backend::Sled => data_dir.join("sled"),
+            Backend::Sqlite => data_dir.join("serma.sqlite"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sled" => Ok(Backend::Sled),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err("expected sled or sqlite".to_string()),
+        }
+    }
+}
+
+/// Opens (or creates) the data dir's database.
+pub fn open(backend: Backend, data_dir: &Path) -> anyhow::Result<Db> {
+    let path = backend.path(data_dir);
+    match backend {
+        Backend::Sled => Ok(Db::new(SledStore::open(&path)?)),
+        Backend::Sqlite => {
+            let sled_dir = Backend::Sled.path(data_dir);
+            anyhow::ensure!(
+                path.exists() || !sled_dir.exists(),
+                "SERMA_STORAGE=sqlite but {} holds a sled database; run `serma migrate-sqlite` first",
+                sled_dir.display()
+            );
+            Ok(Db::new(crate::sqlite::SqliteStore::open(&path)?))
+        }
+    }
+}
+
+/// Handle to a database; cheap to clone.
+#[derive(Clone)]
+pub struct Db {
+    storage: Arc<dyn Store>,
+}
+
+impl Db {
+    pub fn new(storage: impl Store + 'static) -> Self {
+        Self {
+            storage: Arc::new(storage),
+        }
+    }
+
+    pub fn open_tree(&self, name: impl AsRef<[u8]>) -> anyhow::Result<Tree> {
+        Ok(Tree {
+            storage: self.storage.clone(),
+            name: name.as_ref().into(),
+        })
+    }
+
+    fn default_tree(&self) -> Tree {
+        Tree {
+            storage: self.storage.clone(),
+            name: DEFAULT_TREE.into(),
+        }
+    }
+
+    pub fn get(&self, key: impl AsRef<[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        self.default_tree().get(key)
+    }
+
+    pub fn insert(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        self.default_tree().insert(key, value)
+    }
+
+    pub fn remove(&self, key: impl AsRef<[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        self.default_tree().remove(key)
+    }
+
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> anyhow::Result<bool> {
+        self.default_tree().contains_key(key)
+    }
+
+    pub fn scan_prefix(&self, prefix: impl AsRef<[u8]>) -> Iter {
+        self.default_tree().scan_prefix(prefix)
+    }
+
+    pub fn apply_batch(&self, batch: Batch) -> anyhow::Result<()> {
+        self.default_tree().apply_batch(batch)
+    }
+
+    pub fn generate_id(&self) -> anyhow::Result<u64> {
+        self.storage.generate_id()
+    }
+
+    pub fn tree_names(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.storage.tree_names()
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.storage.flush()
+    }
+
+    pub fn size_on_disk(&self) -> anyhow::Result<u64> {
+        self.storage.size_on_disk()
+    }
+}
+
+/// One named tree of a `Db`.
+#[derive(Clone)]
+pub struct Tree {
+    storage: Arc<dyn Store>,
+    name: Arc<[u8]>,
+}
+
+impl Tree {
+    pub fn get(&self, key: impl AsRef<[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        self.storage.get(&self.name, key.as_ref())
+    }
+
+    pub fn insert(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        self.storage.insert(&self.name, key.as_ref(), value.as_ref())
+    }
+
+    pub fn remove(&self, key: impl AsRef<[u8]>) -> anyhow::Result<Option<Vec<u8>>> {
+        self.storage.remove(&self.name, key.as_ref())
+    }
+
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> anyhow::Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    pub fn update_and_fetch(
+        &self,
+        key: impl AsRef<[u8]>,
+        mut f: impl FnMut(Option<&[u8]>) -> Option<Vec<u8>>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        self.storage.update_and_fetch(&self.name, key.as_ref(), &mut f)
+    }
+
+    pub fn len(&self) -> anyhow::Result<usize> {
+        self.storage.len(&self.name)
+    }
+
+    pub fn is_empty(&self) -> anyhow::Result<bool> {
+        Ok(self.first()?.is_none())
+    }
+
+    pub fn first(&self) -> anyhow::Result<Option<Entry>> {
+        Ok(self
+            .storage
+            .range(&self.name, Bound::Unbounded, Bound::Unbounded, false, 1)?
+            .pop())
+    }
+
+    pub fn apply_batch(&self, batch: Batch) -> anyhow::Result<()> {
+        self.storage.apply_batch(&self.name, batch)
+    }
+
+    pub fn iter(&self) -> Iter {
+        Iter::new(self, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    pub fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Iter {
+        let owned = |bound: Bound<&K>| match bound {
+            Bound::Included(k) => Bound::Included(k.as_ref().to_vec()),
+            Bound::Excluded(k) => Bound::Excluded(k.as_ref().to_vec()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Iter::new(self, owned(range.start_bound()), owned(range.end_bound()))
+    }
+
+    pub fn scan_prefix(&self, prefix: impl AsRef<[u8]>) -> Iter {
+        let prefix = prefix.as_ref();
+        // The first key past the prefix: bump the last byte that isn't 0xFF.
+        let hi = match prefix.iter().rposition(|&b| b != 0xFF) {
+            Some(i) => {
+                let mut end = prefix[..=i].to_vec();
+                end[i] += 1;
+                Bound::Excluded(end)
+            }
+            None => Bound::Unbounded,
+        };
+        Iter::new(self, Bound::Included(prefix.to_vec()), hi)
+    }
+}
+
+/// Writes applied together by `apply_batch`.
+#[derive(Default)]
+pub struct Batch {
+    pub(crate) ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl Batch {
+    pub fn insert(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        self.ops.push((key.as_ref().to_vec(), Some(value.as_ref().to_vec())));
+    }
+
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) {
+        self.ops.push((key.as_ref().to_vec(), None));
+    }
+}
+
+/// Entries of a tree in key order, read in chunks. Entries written or removed while
+/// iterating may or may not be seen, as with sled.
+pub struct Iter {
+    storage: Arc<dyn Store>,
+    tree: Arc<[u8]>,
+    lo: Bound<Vec<u8>>,
+    hi: Bound<Vec<u8>>,
+    reverse: bool,
+    buf: VecDeque<Entry>,
+    chunk: usize,
+    done: bool,
+}
+
+impl Iter {
+    fn new(tree: &Tree, lo: Bound<Vec<u8>>, hi: Bound<Vec<u8>>) -> Self {
+        Self {
+            storage: tree.storage.clone(),
+            tree: tree.name.clone(),
+            lo,
+            hi,
+            reverse: false,
+            buf: VecDeque::new(),
+            chunk: FIRST_CHUNK,
+            done: false,
+        }
+    }
+
+    /// Iterates from the last entry instead.
+    pub fn rev(mut self) -> Self {
+        self.reverse = !self.reverse;
+        self
+    }
+
+    pub fn keys(self) -> impl Iterator<Item = anyhow::Result<Vec<u8>>> {
+        self.map(|item| item.map(|(k, _)| k))
+    }
+
+    pub fn values(self) -> impl Iterator<Item = anyhow::Result<Vec<u8>>> {
+        self.map(|item| item.map(|(_, v)| v))
+    }
+
+    fn is_empty_range(&self) -> bool {
+        match (&self.lo, &self.hi) {
+            (Bound::Included(lo), Bound::Included(hi)) => lo > hi,
+            (Bound::Included(lo) | Bound::Excluded(lo), Bound::Excluded(hi) | Bound::Included(hi)) => lo >= hi,
+            _ => false,
+        }
+    }
+
+    fn fill(&mut self) -> anyhow::Result<()> {
+        if self.is_empty_range() {
+            self.done = true;
+            return Ok(());
+        }
+        let entries = self.storage.range(
+            &self.tree,
+            self.lo.as_ref().map(Vec::as_slice),
+            self.hi.as_ref().map(Vec::as_slice),
+            self.reverse,
+            self.chunk,
+        )?;
+        if entries.len() < self.chunk {
+            self.done = true;
+        }
+        // Continue past the last entry read.
+        if let Some((last, _)) = entries.last() {
+            if self.reverse {
+                self.hi = Bound::Excluded(last.clone());
+            } else {
+                self.lo = Bound::Excluded(last.clone());
+            }
+        }
+        self.chunk = (self.chunk * 2).min(MAX_CHUNK);
+        self.buf.extend(entries);
+        Ok(())
+    }
+}
+
+impl Iterator for Iter {
+    type Item = anyhow::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty()
+            && !self.done
+            && let Err(err) = self.fill()
+        {
+            self.done = true;
+            return Some(Err(err));
+        }
+        self.buf.pop_front().map(Ok)
+    }
+}
+
+/// sled, the original backend.
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    /// Opens the database at `path`. sled releases its file lock from a background thread
+    /// after the last handle is dropped, so a reopen in the same process waits for that.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut tries = 0;
+        loop {
+            match sled::open(path) {
+                Err(sled::Error::Io(err)) if err.kind() == std::io::ErrorKind::WouldBlock && tries < 50 => {
+                    tries += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                result => {
+                    let db = result.with_context(|| format!("open sled db {}", path.display()))?;
+                    return Ok(Self { db });
+                }
+            }
+        }
+    }
+
+    pub fn from_db(db: sled::Db) -> Self {
+        Self { db }
+    }
+
+    pub fn into_inner(self) -> sled::Db {
+        self.db
+    }
+
+    fn tree(&self, name: &[u8]) -> sled::Result<sled::Tree> {
+        // The default tree is registered under its name, so this also finds it.
+        self.db.open_tree(name)
+    }
+}
+
+impl Store for SledStore {
+    fn get(&self, tree: &[u8], key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.tree(tree)?.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn insert(&self, tree: &[u8], key: &[u8], value: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.tree(tree)?.insert(key, value)?.map(|v| v.to_vec()))
+    }
+
+    fn remove(&self, tree: &[u8], key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.tree(tree)?.remove(key)?.map(|v| v.to_vec()))
+    }
+
+    fn update_and_fetch(
+        &self,
+        tree: &[u8],
+        key: &[u8],
+        f: &mut Update<'_>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.tree(tree)?.update_and_fetch(key, f)?.map(|v| v.to_vec()))
+    }
+
+    fn range(
+        &self,
+        tree: &[u8],
+        lo: Bound<&[u8]>,
+        hi: Bound<&[u8]>,
+        reverse: bool,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let iter = self.tree(tree)?.range::<&[u8], _>((lo, hi));
+        let take = |item: sled::Result<(sled::IVec, sled::IVec)>| -> anyhow::Result<Entry> {
+            let (k, v) = item?;
+            Ok((k.to_vec(), v.to_vec()))
+        };
+        if reverse {
+            iter.rev().take(limit).map(take).collect()
+        } else {
+            iter.take(limit).map(take).collect()
+        }
+    }
+
+    fn len(&self, tree: &[u8]) -> anyhow::Result<usize> {
+        Ok(self.tree(tree)?.len())
+    }
+
+    fn apply_batch(&self, tree: &[u8], batch: Batch) -> anyhow::Result<()> {
+        let mut sled_batch = sled::Batch::default();
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => sled_batch.insert(key, value),
+                None => sled_batch.remove(key),
+            }
+        }
+        self.tree(tree)?.apply_batch(sled_batch)?;
+        Ok(())
+    }
+
+    fn generate_id(&self) -> anyhow::Result<u64> {
+        Ok(self.db.generate_id()?)
+    }
+
+    fn tree_names(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        Ok(self.db.tree_names().into_iter().map(|n| n.to_vec()).collect())
+    }
+
+    fn flush(&self) -> anyhow::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> anyhow::Result<u64> {
+        Ok(self.db.size_on_disk()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(iter: impl Iterator<Item = anyhow::Result<Vec<u8>>>) -> Vec<Vec<u8>> {
+        iter.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn backends_agree_on_order_and_ranges() {
+        let backends = [
+            open(Backend::Sled, &crate::test_dir("kv")).unwrap(),
+            open(Backend::Sqlite, &crate::test_dir("kv")).unwrap(),
+        ];
+        let mut results = Vec::new();
+        for db in &backends {
+            let tree = db.open_tree(b"t").unwrap();
+            let mut batch = Batch::default();
+            // More than one chunk, with keys that differ only past a 0xFF byte.
+            for i in 0..100u8 {
+                batch.insert([i / 10, 0xFF, i], [i]);
+            }
+            batch.remove([0, 0xFF, 3]);
+            tree.apply_batch(batch).unwrap();
+            assert_eq!(tree.insert([1, 0xFF, 10], [7]).unwrap(), Some(vec![10]));
+            assert_eq!(tree.remove([0, 0xFF, 3]).unwrap(), None);
+            db.insert(b"torrent:a", b"x").unwrap();
+
+            results.push((
+                tree.len().unwrap(),
+                keys(tree.iter().keys()),
+                keys(tree.iter().rev().keys().take(5)),
+                keys(tree.range([2u8].as_slice()..[3, 0xFF, 35].as_slice()).keys()),
+                keys(tree.scan_prefix([9u8, 0xFF]).keys()),
+                tree.first().unwrap(),
+                tree.update_and_fetch([5u8], |old| Some(vec![old.map_or(0, |v| v[0]) + 1])).unwrap(),
+                db.get(b"torrent:a").unwrap(),
+                tree.get([1u8, 0xFF, 10]).unwrap(),
+            ));
+        }
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[0].0, 99);
+        assert_eq!(results[0].3.len(), 15);
+    }
+}
@@ -0,0 +1,317 @@
+//! Serma's crawler, metadata fetcher, storage and search index, for embedding in other
+//! programs. The `serma` binary is this plus the command line.
+//!
+//! ```no_run
+//! # async fn demo() -> anyhow::Result<()> {
+//! let config = serma::config::Config::load(None)?;
+//! let state = serma::AppState::open(config)?;
+//! tokio::spawn(serma::Spider::new(&state).run());
+//! tokio::spawn(serma::Enricher::new(&state).run());
+//!
+//! let hits = state.index.search("ubuntu", 10, state.config.query_options())?;
+//! state.shutdown.trigger();
+//! state.close()?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod alerts;
+pub mod backup;
+pub mod blocklist;
+pub mod enrich;
+pub mod cleanup;
+pub mod compact;
+pub mod config;
+pub mod control;
+mod dead_peers;
+pub mod dht;
+pub mod events;
+pub mod export;
+pub mod fsck;
+pub mod import;
+pub mod inbound;
+pub mod index;
+pub mod ingest;
+pub mod jobs;
+pub mod kv;
+mod mse;
+mod node_guard;
+pub mod optimize;
+mod peer_store;
+mod popularity;
+pub mod ratelimit;
+pub mod release;
+mod routing;
+pub mod scrape;
+pub mod settings;
+pub mod spider;
+pub mod sqlite;
+pub mod stats;
+pub mod socks5;
+pub mod sources;
+pub mod storage;
+mod sweep;
+pub mod sync;
+mod throttle;
+mod tls;
+pub mod torznab;
+mod tracker_udp;
+pub mod trackers;
+mod utp;
+pub mod watch;
+pub mod web;
+
+pub use enrich::Enricher;
+pub use index::SearchIndex;
+pub use spider::Spider;
+pub use storage::Storage;
+
+use anyhow::Context;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Everything the background tasks and the web server share; cheap to clone.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: config::Config,
+    pub data_dir: PathBuf,
+    pub db: kv::Db,
+    pub index: index::SearchIndex,
+    pub control: control::Controls,
+    pub settings: settings::Settings,
+    pub blocklist: blocklist::Blocklist,
+    pub dht: dht::Dht,
+    pub events: events::Events,
+    pub jobs: jobs::Jobs,
+    pub stats: stats::Stats,
+    pub limits: ratelimit::Limits,
+    pub trackers: trackers::Trackers,
+    /// Signs the web UI's login sessions (`storage::session_key`).
+    pub session_key: [u8; 32],
+    pub shutdown: Shutdown,
+    /// Held from open until `close`.
+    data_dir_lock: Arc<Mutex<Option<DataDirLock>>>,
+}
+
+impl AppState {
+    /// Opens the database and search index under `config.data_dir`, bringing both up to
+    /// date: secondary indexes an older version didn't keep are built, and a new or
+    /// outdated search index is rebuilt from the database. Nothing runs until spawned.
+    ///
+    /// Fails if another process has the data dir open (see `lock_data_dir`); the lock is
+    /// held until `close`. Until then, the index counts as out of step, and is reconciled
+    /// on the next open.
+    pub fn open(config: config::Config) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&config.data_dir).context("create data dir")?;
+        let lock = lock_data_dir(&config.data_dir)?;
+        let storage = Storage::open(config.storage_backend, &config.data_dir)?;
+        Self::from_storage(config, storage, lock)
+    }
+
+    /// Like `open`, with the data dir already locked and the database already open. A sled
+    /// database can't be reopened right after it was closed (its files stay locked for a
+    /// moment), so reuse the handle.
+    pub fn from_storage(config: config::Config, storage: Storage, lock: DataDirLock) -> anyhow::Result<Self> {
+        let data_dir = config.data_dir.clone();
+        anyhow::ensure!(
+            lock.data_dir == data_dir,
+            "data dir {} is locked, but {} was opened",
+            lock.data_dir.display(),
+            data_dir.display()
+        );
+        let index = SearchIndex::open_with(data_dir.join("tantivy"), config.search_tokenizer, config.index_writer_options())
+            .context("open/create tantivy index")?;
+        if index.was_created() {
+            // New index or schema change: repopulate from the DB, which is the source of truth.
+            let count = index.rebuild_from(storage.db()).context("rebuild search index")?;
+            tracing::info!(count, "index: rebuilt from storage");
+        } else if crate::storage::index_dirty(storage.db())? {
+            // The last process didn't close: a crash can land between a database write and
+            // the index change (or commit) that goes with it.
+            tracing::info!("index: previous run didn't shut down cleanly; reconciling with storage");
+            let reconciled = index.reconcile(storage.db()).context("reconcile search index")?;
+            tracing::info!(removed = reconciled.removed, added = reconciled.added, "index: reconciled with storage");
+        }
+        crate::storage::set_index_dirty(storage.db(), true)?;
+
+        let blocklist = blocklist::Blocklist::load(storage.db()).context("load blocklist")?;
+        let dht = dht::Dht::load(&data_dir.join(dht::FILE_NAME));
+        let session_key = storage::session_key(storage.db()).context("load session key")?;
+        Ok(Self {
+            limits: ratelimit::Limits::new(&config),
+            trackers: trackers::Trackers::new(&config),
+            settings: settings::Settings::new(&config),
+            config,
+            data_dir,
+            db: storage.db().clone(),
+            index,
+            control: control::Controls::default(),
+            blocklist,
+            dht,
+            events: events::Events::default(),
+            jobs: jobs::Jobs::default(),
+            stats: stats::Stats::default(),
+            session_key,
+            shutdown: Shutdown::default(),
+            data_dir_lock: Arc::new(Mutex::new(Some(lock))),
+        })
+    }
+
+    /// Commits the search index and flushes the database, marking the two in step so the
+    /// next open skips reconciling them, then releases the data dir lock. Call it last,
+    /// once nothing writes anymore.
+    pub fn close(&self) -> anyhow::Result<()> {
+        self.index.commit().context("commit search index")?;
+        storage::set_index_dirty(&self.db, false)?;
+        self.db.flush().context("flush storage")?;
+        self.data_dir_lock.lock().unwrap_or_else(PoisonError::into_inner).take();
+        Ok(())
+    }
+
+    /// The database behind `db`, with the record-level API.
+    pub fn storage(&self) -> Storage {
+        Storage::from_db(self.db.clone())
+    }
+}
+
+/// Exclusive lock on a data dir, released when dropped (or by the OS when the process
+/// exits, even on a crash).
+pub struct DataDirLock {
+    data_dir: PathBuf,
+    _file: File,
+}
+
+/// Takes an exclusive lock on `<data_dir>/serma.lock`.
+///
+/// Two processes sharing a data dir corrupt the tantivy index and fight over the database,
+/// so a second instance must refuse to start.
+pub fn lock_data_dir(data_dir: &Path) -> anyhow::Result<DataDirLock> {
+    let path = data_dir.join("serma.lock");
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("open lock file {}", path.display()))?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            let holder = holder.trim();
+            let holder = if holder.is_empty() { "unknown" } else { holder };
+            anyhow::bail!(
+                "data dir {} is already in use by another serma instance (pid {holder}); refusing to start",
+                data_dir.display()
+            );
+        }
+        Err(TryLockError::Error(err)) => {
+            return Err(err).with_context(|| format!("lock {}", path.display()));
+        }
+    }
+
+    // Record our pid so the error above can point at the running instance.
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+    file.flush()?;
+    Ok(DataDirLock {
+        data_dir: data_dir.to_path_buf(),
+        _file: file,
+    })
+}
+
+/// Shutdown coordinator: SIGINT/SIGTERM is broadcast to the background workers, which stop
+/// taking new work and finish what they hold; `main` then commits the index and flushes storage.
+#[derive(Clone)]
+pub struct Shutdown {
+    tx: tokio::sync::broadcast::Sender<()>,
+    triggered: Arc<AtomicBool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            tx: tokio::sync::broadcast::channel(1).0,
+            triggered: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn trigger(&self) {
+        self.triggered.store(true, Ordering::SeqCst);
+        let _ = self.tx.send(());
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.triggered.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once shutdown has been triggered (immediately if it already was).
+    pub async fn wait(&self) {
+        // Subscribe before checking the flag, so a trigger in between isn't missed.
+        let mut rx = self.tx.subscribe();
+        if self.is_triggered() {
+            return;
+        }
+        let _ = rx.recv().await;
+    }
+
+    /// Sleeps for `duration`, returning early on shutdown.
+    pub async fn sleep(&self, duration: Duration) {
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => {}
+            _ = self.wait() => {}
+        }
+    }
+}
+
+/// A new, empty directory for a test's files, named after `name` and unique per call.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str) -> PathBuf {
+    static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("serma-{name}-test-{}-{n}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create test dir");
+    dir
+}
+
+/// An `AppState` on a fresh data dir, configured by `env` (`SERMA_*` names) over the defaults.
+#[cfg(test)]
+pub(crate) fn test_state(env: &[(&str, &str)]) -> AppState {
+    let data_dir = test_dir("state");
+    let config = config::Config::resolve(toml::Table::new(), &|name| {
+        if name == "SERMA_DATA_DIR" {
+            return Some(data_dir.display().to_string());
+        }
+        env.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+    })
+    .expect("test config");
+    AppState::open(config).expect("open test state")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dir_lock_is_exclusive_until_dropped() {
+        let dir = test_dir("lock");
+
+        let lock = lock_data_dir(&dir).unwrap();
+        let err = lock_data_dir(&dir).err().unwrap();
+        assert!(err.to_string().contains(&format!("pid {}", std::process::id())), "{err}");
+        drop(lock);
+        lock_data_dir(&dir).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,80 +1,339 @@
-mod enrich;
-mod cleanup;
-mod config;
-mod index;
-mod spider;
-mod socks5;
-mod storage;
-mod web;
+mod cli;
+mod telemetry;
 
 use anyhow::Context;
-use std::path::PathBuf;
-
-#[derive(Clone)]
-pub struct AppState {
-    pub config: config::Config,
-    pub data_dir: PathBuf,
-    pub db: sled::Db,
-    pub index: index::SearchIndex,
-}
+use clap::Parser;
+use cli::Command;
+use serma::{AppState, Enricher, SearchIndex, Spider, Storage};
+use serma::{backup, cleanup, compact, config, export, fsck, import, index, ingest, jobs, kv, optimize, sources};
+use serma::{alerts, socks5, sqlite, stats, storage, sync, watch, web};
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long shutdown waits for workers to finish in-flight jobs before committing anyway.
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(20);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
-        )
-        .init();
+    let cli = cli::Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve);
 
-    let config = config::Config::load()?;
+    // `serma export` and `serma search` print to stdout, so their logs go to stderr.
+    let logs_to_stderr = matches!(command, Command::Export { .. } | Command::Search { .. });
+    let config = config::Config::load(cli.config.as_deref())?;
+    let telemetry = telemetry::init(&config, logs_to_stderr)?;
 
     let data_dir = config.data_dir.clone();
     std::fs::create_dir_all(&data_dir).context("create data dir")?;
 
-    let db = sled::open(data_dir.join("sled")).context("open sled db")?;
-    // Build secondary indexes (one-time migration) so background tasks can find work without
-    // scanning the full DB each loop.
-    crate::storage::ensure_missing_info_index(&db).context("build missing-info index")?;
-    crate::storage::ensure_cleanup_indexes(&db).context("build cleanup indexes")?;
-    let index = index::SearchIndex::open_or_create(data_dir.join("tantivy"))
-        .context("open/create tantivy index")?;
-
-    let state = AppState {
-        config: config.clone(),
-        data_dir,
-        db,
-        index,
-    };
+    // Taken before anything touches the data dir; `AppState` holds it from there on.
+    let data_dir_lock = serma::lock_data_dir(&data_dir)?;
+
+    let backend = config.storage_backend;
+    if let Command::Restore { path } = &command {
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let (manifest, summary) = backup::stage_restore(&data_dir, file).context("check backup")?;
+        println!(
+            "restore: backup of {} entries and {} indexed documents from serma {} is staged; \
+             it replaces the database and index on the next start",
+            summary.entries, summary.index_docs, manifest.serma_version
+        );
+        return Ok(());
+    }
+    // Before anything opens the database: a restore swaps it.
+    if let Some(restored) = backup::apply_staged(backend, &data_dir).context("apply staged restore")? {
+        tracing::info!(
+            entries = restored.entries,
+            created_at = restored.manifest.created_at,
+            version = %restored.manifest.serma_version,
+            "restore: backup restored; the previous data is in pre-restore/"
+        );
+    }
+    match command {
+        Command::Compact => {
+            // Before anything opens the database: compaction swaps the sled directory.
+            let done = storage::compact(backend, &data_dir).context("compact database")?;
+            println!(
+                "compact: database {} -> {}",
+                web::format_bytes(done.bytes_before),
+                web::format_bytes(done.bytes_after)
+            );
+        }
+        Command::Reindex => {
+            let index_dir = data_dir.join("tantivy");
+            if index_dir.exists() {
+                std::fs::remove_dir_all(&index_dir).context("delete search index")?;
+            }
+            let storage = Storage::open(backend, &data_dir)?;
+            let index = SearchIndex::open_with(index_dir, config.search_tokenizer, config.index_writer_options())
+                .context("create tantivy index")?;
+            let count = index.rebuild_from(storage.db()).context("rebuild search index")?;
+            storage::set_index_dirty(storage.db(), false)?;
+            println!("reindex: {count} records indexed");
+            return Ok(());
+        }
+        Command::MigrateSqlite => {
+            let migrated = sqlite::migrate_from_sled(
+                &kv::Backend::Sled.path(&data_dir),
+                &kv::Backend::Sqlite.path(&data_dir),
+            )
+            .context("migrate to sqlite")?;
+            println!(
+                "migrate-sqlite: copied {} entries in {} trees to {}; set SERMA_STORAGE=sqlite to use it",
+                migrated.entries,
+                migrated.trees,
+                kv::Backend::Sqlite.path(&data_dir).display()
+            );
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let mut storage = Storage::open(backend, &data_dir)?;
+    // Marked by the compact task when most of the file is free space; the copy needs the
+    // database closed, so it happens here rather than while serving.
+    if matches!(command, Command::Serve)
+        && config.compact_on_start
+        && storage::compaction_status(storage.db())?.due_bytes.is_some()
+    {
+        tracing::info!("compact: database marked for compaction; compacting before start");
+        drop(storage);
+        match storage::compact(backend, &data_dir) {
+            Ok(done) => tracing::info!(
+                before = done.bytes_before,
+                after = done.bytes_after,
+                secs = done.duration_ms / 1000,
+                "compact: database compacted"
+            ),
+            Err(err) => tracing::warn!(err = %format!("{err:#}"), "compact: failed; starting with the database as is"),
+        }
+        storage = Storage::open(backend, &data_dir)?;
+    }
+    if let Command::Fsck { repair } = command {
+        // Without AppState, which would reconcile the index on its own after a crash.
+        let index = SearchIndex::open_with(data_dir.join("tantivy"), config.search_tokenizer, config.index_writer_options())
+            .context("open tantivy index")?;
+        let report = fsck::run(storage.db(), &index, repair)?;
+        println!("fsck: {report}");
+        if repair {
+            storage::set_index_dirty(storage.db(), false)?;
+            storage.db().flush()?;
+        } else {
+            anyhow::ensure!(report.problems() == 0, "run `serma fsck --repair` to fix them");
+        }
+        return Ok(());
+    }
+    let state = AppState::from_storage(config.clone(), storage, data_dir_lock)?;
+    state.settings.set_log_reload(telemetry.log_filter_reload());
+    if matches!(command, Command::Serve) && config.fsck_on_start {
+        let report = fsck::run(&state.db, &state.index, true).context("check database and search index")?;
+        match report.problems() {
+            0 => tracing::info!(records = report.storage.records, "fsck: no problems found"),
+            problems => tracing::warn!(problems, "fsck: repaired before start\n{report}"),
+        }
+    }
+
+    match command {
+        Command::Serve => {}
+        // Handled before the database is opened.
+        Command::MigrateSqlite | Command::Restore { .. } | Command::Reindex | Command::Fsck { .. } => unreachable!(),
+        Command::Ingest { paths } => {
+            // One-shot import, no web server or background tasks.
+            let mut summary = ingest::IngestSummary::default();
+            let paths = if paths.is_empty() { vec!["-".to_string()] } else { paths };
+            for path in &paths {
+                let path = Some(Path::new(path)).filter(|p| p.as_os_str() != "-");
+                summary.add(&ingest::run_file_or_stdin_ingest(&state, path).await?);
+            }
+            println!("ingest: {summary}");
+            return state.close();
+        }
+        Command::Import {
+            format,
+            map,
+            delimiter,
+            no_header,
+            include_deleted,
+            source,
+        } => {
+            let defaults = import::ImportOptions::default();
+            let opts = import::ImportOptions {
+                // `--map` wins field by field over SERMA_IMPORT_MAP.
+                fields: map.unwrap_or_default().or(config.import_map.as_ref().unwrap_or(&defaults.fields)),
+                delimiter: delimiter.unwrap_or(defaults.delimiter),
+                has_header: !no_header,
+                include_deleted,
+            };
+            let summary = import::run_import(&state, format, &source, &opts).await?;
+            println!("import: {summary}");
+            return state.close();
+        }
+        Command::Search {
+            query,
+            limit,
+            sort,
+            json,
+        } => {
+            let page = state
+                .index
+                .search_counted(&query, sort, 0, limit, config.query_options(), index::SearchFilters::default())?;
+            for hit in &page.hits {
+                if json {
+                    println!("{}", serde_json::to_string(hit)?);
+                } else {
+                    println!(
+                        "{}  {:>6} seeders  {:>6} leechers  {:>10}  {}",
+                        hit.info_hash.as_deref().unwrap_or("-"),
+                        hit.seeders,
+                        hit.leechers,
+                        web::format_bytes(hit.size_bytes),
+                        hit.title.as_deref().unwrap_or("Unknown Title"),
+                    );
+                }
+            }
+            eprintln!("search: {} of {} results", page.hits.len(), page.total);
+            return state.close();
+        }
+        Command::Export { gzip, path } => {
+            // Every record as JSONL, for `import --format serma`.
+            let written = match path.filter(|p| p != "-") {
+                Some(path) => {
+                    let file = File::create(&path).with_context(|| format!("create {path}"))?;
+                    export::write_jsonl(&state.db, file, gzip)?
+                }
+                None => export::write_jsonl(&state.db, std::io::stdout().lock(), gzip)?,
+            };
+            eprintln!("export: {written} records");
+            return state.close();
+        }
+        Command::Compact => {
+            let segments = state.index.merge_segments().context("merge index segments")?;
+            let index_dir = state.data_dir.join("tantivy");
+            println!(
+                "compact: index merged from {segments} segments, now {}",
+                web::format_bytes(web::dir_size(&index_dir))
+            );
+            return state.close();
+        }
+    }
 
     // Optional SOCKS5 proxy health-check (privacy).
     // This is best-effort and does not change behavior beyond logging.
-    match crate::socks5::Socks5Config::from_env() {
-        Some(Ok(cfg)) => match crate::socks5::Socks5UdpAssociate::connect(&cfg).await {
+    match socks5::Socks5Config::from_env() {
+        Some(Ok(cfg)) => match socks5::Socks5UdpAssociate::connect(&cfg).await {
             Ok(sock) => {
                 tracing::info!(proxy=%cfg.proxy, relay=%sock.relay_addr(), "socks5: udp associate OK");
+                state.stats.set_socks5(stats::Socks5Status::associated(&cfg.proxy, sock.relay_addr()));
             }
             Err(err) => {
-                tracing::warn!(%err, proxy=%cfg.proxy, "socks5: udp associate failed (spider will disable; enrich DHT lookups will fail)");
+                tracing::warn!(%err, proxy=%cfg.proxy, "socks5: udp associate failed (spider and DHT lookups will keep retrying)");
+                state.stats.set_socks5(stats::Socks5Status::failed(&cfg.proxy, format!("{err:#}")));
             }
         },
         Some(Err(err)) => {
-            tracing::warn!(%err, "socks5: invalid SERMA_SOCKS5_PROXY (spider will disable; enrich DHT lookups will fail)");
+            tracing::warn!(%err, "socks5: invalid SERMA_SOCKS5_PROXY (spider and DHT lookups disabled)");
         }
         None => {}
     }
 
-    // Background enrichment: DHT peer lookup -> ut_metadata info dict fetch -> persist full info -> reindex.
-    tokio::spawn(enrich::run(state.clone()));
+    // Background enrichment: DHT peer lookup -> ut_metadata info dict fetch -> persist full
+    // info -> reindex. Also runs the shared DHT node for lookups and the inbound peer listener.
+    let enrich = tokio::spawn(Enricher::new(&state).run());
 
     // Autonomous discovery (DHT spider): harvest new hashes from DHT traffic.
-    tokio::spawn(spider::run(state.clone()));
+    let spider = tokio::spawn(Spider::new(&state).run());
 
     // Periodic cleanup: remove inactive / low-seed torrents so they don't accumulate.
-    tokio::spawn(cleanup::run(state.clone()));
+    let cleanup = tokio::spawn(cleanup::run(state.clone()));
+
+    // Space reclamation: compact the database once deletes have left the file mostly garbage.
+    let compact = tokio::spawn(compact::run(state.clone()));
+
+    // Index upkeep: rewrite segments full of deleted documents while the index is quiet.
+    let optimize = tokio::spawn(optimize::run(state.clone()));
+
+    // Watch folder: ingest .torrent / magnet files dropped into `data/watch/`.
+    let watch = tokio::spawn(watch::run(state.clone()));
+
+    // One-off list from stdin or a file (SERMA_INGEST), fed in while everything else runs.
+    let ingest = tokio::spawn(ingest::run(state.clone()));
+
+    // Remote hash lists / RSS feeds (SERMA_SOURCES).
+    let sources = tokio::spawn(sources::run(state.clone()));
+
+    // Federation: pull records from / push records to other instances (SERMA_SYNC_FROM,
+    // SERMA_SYNC_PUSH_TO).
+    let sync = tokio::spawn(sync::run(state.clone()));
 
-    if let Some(addr) = config.http_addr {
-        web::serve(state, addr).await
+    // Saved searches: webhook notifications for new matches.
+    let alerts = tokio::spawn(alerts::run(state.clone()));
+
+    // Bulk operations queued through the API.
+    let jobs = tokio::spawn(jobs::run(state.clone()));
+
+    let shutdown = state.shutdown.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("shutdown: signal received; draining workers (press Ctrl-C again to force)");
+        shutdown.trigger();
+        shutdown_signal().await;
+        tracing::warn!("shutdown: forced; pending index writes may be lost");
+        std::process::exit(130);
+    });
+
+    // The web server stops accepting connections on shutdown and returns once in-flight
+    // requests are done.
+    let served = if config.http_addrs.is_empty() {
+        web::serve_dual_loopback(state.clone(), config.web_port).await
     } else {
-        web::serve_dual_loopback(state, config.web_port).await
+        for addr in &config.http_addrs {
+            if !addr.ip().is_loopback() && config.api_token.is_none() {
+                tracing::warn!(%addr, "web: listening beyond loopback without SERMA_API_TOKEN; admin and ingest routes are open");
+            }
+        }
+        web::serve(state.clone(), &config.http_addrs).await
+    };
+    state.shutdown.trigger();
+
+    // Every task that writes the database or the index, so none is mid-write at close.
+    let drained = tokio::time::timeout(SHUTDOWN_DRAIN_TIMEOUT, async {
+        let _ = tokio::join!(enrich, spider, cleanup, compact, optimize, watch, ingest, sources, sync, alerts, jobs);
+    })
+    .await;
+    if drained.is_err() {
+        tracing::warn!(timeout_secs = SHUTDOWN_DRAIN_TIMEOUT.as_secs(), "shutdown: workers still busy; committing anyway");
+    }
+    state.close()?;
+    tracing::info!("shutdown: index committed, storage flushed");
+    served
+}
+
+/// Resolves on SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::warn!(%err, "shutdown: failed listening for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::warn!(%err, "shutdown: failed listening for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
     }
 }
@@ -0,0 +1,406 @@
+use anyhow::Context;
+use rand::Rng;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// Message Stream Encryption (MSE/PE), initiator side only.
+//
+// Many clients refuse plaintext peer connections. Enrichment falls back to this handshake:
+// a Diffie-Hellman exchange over the spec's 768-bit prime, keys derived from the shared
+// secret and the info hash, then RC4 (first 1024 bytes of keystream dropped) in each
+// direction. The BitTorrent handshake and messages follow as usual over the cipher.
+
+/// The 768-bit prime from the MSE spec (hex); the generator is 2.
+const P_HEX: &str = "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A63A36210000000000090563";
+const LIMBS: usize = 24;
+const KEY_LEN: usize = LIMBS * 4;
+const MAX_PAD: usize = 512;
+const VC: [u8; 8] = [0; 8];
+const CRYPTO_PLAINTEXT: u32 = 0x01;
+const CRYPTO_RC4: u32 = 0x02;
+
+/// RC4 keystream for one direction.
+pub struct Rc4 {
+    s: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    /// Keyed and with the first 1024 bytes discarded, as MSE requires.
+    fn new(key: &[u8]) -> Self {
+        let mut rc4 = Self::keyed(key);
+        rc4.apply(&mut [0u8; 1024]);
+        rc4
+    }
+
+    /// Plain RC4, keystream from the first byte.
+    fn keyed(key: &[u8]) -> Self {
+        let mut s: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut j = 0u8;
+        for i in 0..256 {
+            j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+            s.swap(i, j as usize);
+        }
+        Self { s, i: 0, j: 0 }
+    }
+
+    /// Encrypts or decrypts in place.
+    pub fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.s[self.i as usize]);
+            self.s.swap(self.i as usize, self.j as usize);
+            let k = self.s[self.s[self.i as usize].wrapping_add(self.s[self.j as usize]) as usize];
+            *byte ^= k;
+        }
+    }
+}
+
+/// What the handshake settled on.
+pub struct Negotiated {
+    /// `(outgoing, incoming)` ciphers, or `None` if the peer chose plaintext.
+    pub cipher: Option<(Rc4, Rc4)>,
+    /// Bytes the peer sent past the handshake, already decrypted.
+    pub leftover: Vec<u8>,
+}
+
+/// Runs the MSE handshake on a fresh connection. The BitTorrent handshake comes next.
+pub async fn initiate<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, info_hash: &[u8; 20]) -> anyhow::Result<Negotiated> {
+    let p = parse_hex_limbs(P_HEX);
+    // 160 random bits, as the spec recommends.
+    let private: [u8; 20] = rand::random();
+    let public = mod_pow(&two(), &private, &p);
+
+    // 1. Ya, PadA
+    let mut out = to_bytes(&public).to_vec();
+    let pad_len = rand::rng().random_range(0..=MAX_PAD);
+    out.extend((0..pad_len).map(|_| rand::random::<u8>()));
+    stream.write_all(&out).await?;
+
+    // 2. Yb, PadB
+    let mut theirs = [0u8; KEY_LEN];
+    stream.read_exact(&mut theirs).await.context("mse: peer closed before its key")?;
+    let secret = to_bytes(&mod_pow(&from_bytes(&theirs), &private, &p));
+
+    let mut enc = Rc4::new(&sha1_of(&[b"keyA", &secret, info_hash]));
+    let mut dec = Rc4::new(&sha1_of(&[b"keyB", &secret, info_hash]));
+
+    // 3. HASH('req1', S), HASH('req2', SKEY) xor HASH('req3', S), ENCRYPT(VC, crypto_provide,
+    //    len(PadC), PadC, len(IA)), no IA: the BitTorrent handshake follows separately.
+    let mut out = sha1_of(&[b"req1", &secret]).to_vec();
+    let req2 = sha1_of(&[b"req2", info_hash]);
+    let req3 = sha1_of(&[b"req3", &secret]);
+    out.extend(req2.iter().zip(req3).map(|(a, b)| a ^ b));
+    let mut body = VC.to_vec();
+    body.extend_from_slice(&(CRYPTO_RC4 | CRYPTO_PLAINTEXT).to_be_bytes());
+    body.extend_from_slice(&0u16.to_be_bytes());
+    body.extend_from_slice(&0u16.to_be_bytes());
+    enc.apply(&mut body);
+    out.extend(body);
+    stream.write_all(&out).await?;
+
+    // 4. PadB ends where the peer's encrypted VC starts; find it.
+    let mut vc = VC;
+    dec.apply(&mut vc);
+    let mut buf = Vec::new();
+    let start = loop {
+        if let Some(pos) = buf.windows(vc.len()).position(|w| w == vc) {
+            break pos + vc.len();
+        }
+        if buf.len() >= MAX_PAD + vc.len() {
+            anyhow::bail!("mse: no verification constant from peer");
+        }
+        let need = buf.len() + 1;
+        read_at_least(stream, &mut buf, need).await?;
+    };
+    let mut rest = buf.split_off(start);
+
+    // crypto_select, len(PadD), PadD
+    read_at_least(stream, &mut rest, 6).await?;
+    let mut header = [0u8; 6];
+    header.copy_from_slice(&rest[..6]);
+    dec.apply(&mut header);
+    let select = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let pad_len = u16::from_be_bytes([header[4], header[5]]) as usize;
+    if pad_len > MAX_PAD {
+        anyhow::bail!("mse: padding too long ({pad_len} bytes)");
+    }
+    read_at_least(stream, &mut rest, 6 + pad_len).await?;
+    dec.apply(&mut rest[6..6 + pad_len]);
+    let mut leftover = rest.split_off(6 + pad_len);
+
+    let cipher = match select {
+        CRYPTO_RC4 => {
+            dec.apply(&mut leftover);
+            Some((enc, dec))
+        }
+        CRYPTO_PLAINTEXT => None,
+        other => anyhow::bail!("mse: peer selected unknown crypto method {other:#x}"),
+    };
+    Ok(Negotiated { cipher, leftover })
+}
+
+async fn read_at_least<S: AsyncRead + Unpin>(stream: &mut S, buf: &mut Vec<u8>, need: usize) -> anyhow::Result<()> {
+    while buf.len() < need {
+        let mut chunk = [0u8; 1024];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("mse: peer closed during handshake");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    Ok(())
+}
+
+fn sha1_of(parts: &[&[u8]]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+// Just enough big-number arithmetic for the key exchange: Montgomery multiplication over
+// little-endian u32 limbs, fixed at the prime's size.
+
+type Num = [u32; LIMBS];
+
+fn parse_hex_limbs(hex: &str) -> Num {
+    let bytes = hex::decode(hex).expect("valid prime");
+    from_bytes(&bytes)
+}
+
+fn from_bytes(bytes: &[u8]) -> Num {
+    let mut out = [0u32; LIMBS];
+    for (i, chunk) in bytes.rchunks(4).take(LIMBS).enumerate() {
+        out[i] = chunk.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32);
+    }
+    out
+}
+
+/// Big-endian, zero-padded to the key length.
+fn to_bytes(n: &Num) -> [u8; KEY_LEN] {
+    let mut out = [0u8; KEY_LEN];
+    for (i, limb) in n.iter().enumerate() {
+        let at = KEY_LEN - 4 * (i + 1);
+        out[at..at + 4].copy_from_slice(&limb.to_be_bytes());
+    }
+    out
+}
+
+fn two() -> Num {
+    let mut n = [0u32; LIMBS];
+    n[0] = 2;
+    n
+}
+
+fn geq(a: &Num, b: &Num) -> bool {
+    for i in (0..LIMBS).rev() {
+        if a[i] != b[i] {
+            return a[i] > b[i];
+        }
+    }
+    true
+}
+
+/// `a -= b`, for `a >= b` (or wrapping, when the caller tracks the missing top bit).
+fn sub(a: &mut Num, b: &Num) {
+    let mut borrow = 0u64;
+    for i in 0..LIMBS {
+        let d = (a[i] as u64).wrapping_sub(b[i] as u64).wrapping_sub(borrow);
+        a[i] = d as u32;
+        borrow = (d >> 63) & 1;
+    }
+}
+
+/// `n = 2n mod p`, for `n < p`.
+fn double_mod(n: &mut Num, p: &Num) {
+    let carry = n[LIMBS - 1] >> 31;
+    for i in (1..LIMBS).rev() {
+        n[i] = (n[i] << 1) | (n[i - 1] >> 31);
+    }
+    n[0] <<= 1;
+    if carry != 0 || geq(n, p) {
+        sub(n, p);
+    }
+}
+
+/// `-p^-1 mod 2^32`.
+fn mont_inv(p: &Num) -> u32 {
+    let mut inv = 1u32;
+    for _ in 0..5 {
+        inv = inv.wrapping_mul(2u32.wrapping_sub(p[0].wrapping_mul(inv)));
+    }
+    inv.wrapping_neg()
+}
+
+/// `a * b / 2^(32*LIMBS) mod p`.
+fn mont_mul(a: &Num, b: &Num, p: &Num, inv: u32) -> Num {
+    let mut t = [0u64; LIMBS + 2];
+    for &bi in b {
+        let mut carry = 0u64;
+        for j in 0..LIMBS {
+            let v = t[j] + a[j] as u64 * bi as u64 + carry;
+            t[j] = v & 0xFFFF_FFFF;
+            carry = v >> 32;
+        }
+        let v = t[LIMBS] + carry;
+        t[LIMBS] = v & 0xFFFF_FFFF;
+        t[LIMBS + 1] = v >> 32;
+
+        let m = (t[0] as u32).wrapping_mul(inv) as u64;
+        let mut carry = (t[0] + m * p[0] as u64) >> 32;
+        for j in 1..LIMBS {
+            let v = t[j] + m * p[j] as u64 + carry;
+            t[j - 1] = v & 0xFFFF_FFFF;
+            carry = v >> 32;
+        }
+        let v = t[LIMBS] + carry;
+        t[LIMBS - 1] = v & 0xFFFF_FFFF;
+        t[LIMBS] = t[LIMBS + 1] + (v >> 32);
+    }
+    let mut out: Num = std::array::from_fn(|i| t[i] as u32);
+    if t[LIMBS] != 0 || geq(&out, p) {
+        sub(&mut out, p);
+    }
+    out
+}
+
+/// `base^exp mod p`, with `exp` big-endian.
+fn mod_pow(base: &Num, exp: &[u8], p: &Num) -> Num {
+    let inv = mont_inv(p);
+    // 2^(32*LIMBS) mod p by doubling, then squared into Montgomery form for the conversion.
+    let mut r = [0u32; LIMBS];
+    r[0] = 1;
+    for _ in 0..32 * LIMBS {
+        double_mod(&mut r, p);
+    }
+    let mut r2 = r;
+    for _ in 0..32 * LIMBS {
+        double_mod(&mut r2, p);
+    }
+
+    let mut base = *base;
+    while geq(&base, p) {
+        sub(&mut base, p);
+    }
+    let base = mont_mul(&base, &r2, p, inv);
+    let mut acc = r;
+    for byte in exp {
+        for bit in (0..8).rev() {
+            acc = mont_mul(&acc, &acc, p, inv);
+            if byte >> bit & 1 == 1 {
+                acc = mont_mul(&acc, &base, p, inv);
+            }
+        }
+    }
+    let mut one = [0u32; LIMBS];
+    one[0] = 1;
+    mont_mul(&acc, &one, p, inv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[test]
+    fn mod_pow_matches_known_answers() {
+        let p = parse_hex_limbs(P_HEX);
+        assert_eq!(from_bytes(&[4, 0]), mod_pow(&two(), &[10], &p));
+        // Fermat: 2^(p-1) = 1 (mod p).
+        let mut p_minus_one = to_bytes(&p);
+        p_minus_one[KEY_LEN - 1] -= 1;
+        assert_eq!(to_bytes(&mod_pow(&two(), &p_minus_one, &p)), to_bytes(&from_bytes(&[1])));
+        // 2^0x0102..14 mod p, computed independently.
+        let exp: Vec<u8> = (1..=20).collect();
+        assert_eq!(
+            hex::encode(to_bytes(&mod_pow(&two(), &exp, &p))),
+            "96e112dab29e8c5272accb9b17b26887ce54a144a4e3b697c7d159b7a817e556b0918db2b4c658e02a87f7e5fb14b18a553e084c\
+bf3dad2d30f16596ccb982d406258c61b30c5c1dae2ddc60bdbd48d79896312aad63238c39e1a633821eb693"
+        );
+    }
+
+    #[test]
+    fn rc4_matches_known_answers() {
+        let mut plain = *b"Plaintext";
+        Rc4::keyed(b"Key").apply(&mut plain);
+        assert_eq!(hex::encode(plain), "bbf316e8d940af0ad3");
+
+        // MSE's keystream starts 1024 bytes in.
+        let mut stream = [0u8; 16];
+        Rc4::new(b"Key").apply(&mut stream);
+        assert_eq!(hex::encode(stream), "ca88075fe00acd8a8119914d76c04993");
+        let mut skipped = Rc4::keyed(b"Key");
+        skipped.apply(&mut [0u8; 1024]);
+        let mut expected = [0u8; 16];
+        skipped.apply(&mut expected);
+        assert_eq!(stream, expected);
+    }
+
+    /// The receiving side of the handshake, as a peer would run it.
+    async fn respond(mut stream: tokio::io::DuplexStream, info_hash: [u8; 20], payload: &[u8]) -> (Rc4, Rc4) {
+        let p = parse_hex_limbs(P_HEX);
+        let mut ya = [0u8; KEY_LEN];
+        stream.read_exact(&mut ya).await.unwrap();
+        let private = [7u8; 20];
+        stream.write_all(&to_bytes(&mod_pow(&two(), &private, &p))).await.unwrap();
+        let secret = to_bytes(&mod_pow(&from_bytes(&ya), &private, &p));
+
+        // Skip PadA up to HASH('req1', S).
+        let req1 = sha1_of(&[b"req1", &secret]);
+        let mut buf = Vec::new();
+        let start = loop {
+            if let Some(pos) = buf.windows(20).position(|w| w == req1) {
+                break pos + 20;
+            }
+            let need = buf.len() + 1;
+            read_at_least(&mut stream, &mut buf, need).await.unwrap();
+        };
+        let mut rest = buf.split_off(start);
+        read_at_least(&mut stream, &mut rest, 20 + 16).await.unwrap();
+        let req2 = sha1_of(&[b"req2", &info_hash]);
+        let req3 = sha1_of(&[b"req3", &secret]);
+        let expected: Vec<u8> = req2.iter().zip(req3).map(|(a, b)| a ^ b).collect();
+        assert_eq!(rest[..20], expected[..]);
+
+        let mut dec = Rc4::new(&sha1_of(&[b"keyA", &secret, &info_hash]));
+        let mut enc = Rc4::new(&sha1_of(&[b"keyB", &secret, &info_hash]));
+        let mut body = rest[20..36].to_vec();
+        dec.apply(&mut body);
+        assert_eq!(body[..8], VC);
+        assert_eq!(u32::from_be_bytes(body[8..12].try_into().unwrap()), CRYPTO_RC4 | CRYPTO_PLAINTEXT);
+        assert_eq!(body[12..16], [0, 0, 0, 0]);
+
+        let mut out = VC.to_vec();
+        out.extend_from_slice(&CRYPTO_RC4.to_be_bytes());
+        out.extend_from_slice(&0u16.to_be_bytes());
+        out.extend_from_slice(payload);
+        enc.apply(&mut out);
+        stream.write_all(&out).await.unwrap();
+        (enc, dec)
+    }
+
+    #[tokio::test]
+    async fn handshake_derives_matching_keys() {
+        let info_hash = [0xAB; 20];
+        let (mut ours, theirs) = duplex(4096);
+        let peer = tokio::spawn(async move { respond(theirs, info_hash, b"handshake").await });
+
+        let negotiated = initiate(&mut ours, &info_hash).await.unwrap();
+        assert_eq!(negotiated.leftover, b"handshake");
+        let (mut enc, mut dec) = negotiated.cipher.expect("peer selected RC4");
+        let (mut peer_enc, mut peer_dec) = peer.await.unwrap();
+
+        // Each side's outgoing stream is the other's incoming one.
+        let mut message = *b"interested";
+        enc.apply(&mut message);
+        peer_dec.apply(&mut message);
+        assert_eq!(&message, b"interested");
+        let mut reply = *b"unchoke";
+        peer_enc.apply(&mut reply);
+        dec.apply(&mut reply);
+        assert_eq!(&reply, b"unchoke");
+    }
+}
@@ -0,0 +1,171 @@
+use crate::ratelimit::bucket_key;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+// Inbound flood protection for the spider.
+//
+// Every datagram takes a token from its sender's bucket (`rate` per second, bursts of
+// `BURST_SECS` seconds' worth). Datagrams over the limit and ones that aren't KRPC count as
+// strikes; strikes drain at one per second, and a sender that collects `BAN_STRIKES` is
+// ignored outright for the ban time. Senders are keyed like the web rate limits: IPv4 by
+// address, IPv6 by /64.
+
+/// Seconds of traffic a sender may burst.
+const BURST_SECS: f64 = 5.0;
+/// Strikes that get a sender banned.
+const BAN_STRIKES: f64 = 20.0;
+/// Senders tracked before idle ones are dropped early.
+const MAX_TRACKED: usize = 100_000;
+
+/// What to do with a datagram.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    /// Over the sender's rate; drop it.
+    Limited,
+    /// The sender is banned, possibly just now; drop it.
+    Banned,
+}
+
+struct Sender {
+    tokens: f64,
+    strikes: f64,
+    at: Instant,
+    banned_until: Option<Instant>,
+}
+
+pub struct NodeGuard {
+    /// Datagrams per second per sender; 0 turns limiting (and banning floods) off.
+    rate: f64,
+    ban: Duration,
+    senders: HashMap<IpAddr, Sender>,
+}
+
+impl NodeGuard {
+    /// `ban` of zero never bans.
+    pub fn new(rate: u32, ban: Duration) -> Self {
+        Self {
+            rate: rate as f64,
+            ban,
+            senders: HashMap::new(),
+        }
+    }
+
+    /// Checks one datagram from `ip`.
+    pub fn admit(&mut self, ip: IpAddr, now: Instant) -> Verdict {
+        if self.rate == 0.0 && self.ban.is_zero() {
+            return Verdict::Accept;
+        }
+        if self.senders.len() >= MAX_TRACKED {
+            self.prune(now);
+        }
+        let limiting = self.rate > 0.0;
+        let sender = self.refill(ip, now);
+        if sender.banned_until.is_some_and(|until| now < until) {
+            return Verdict::Banned;
+        }
+        if !limiting {
+            return Verdict::Accept;
+        }
+        if sender.tokens >= 1.0 {
+            sender.tokens -= 1.0;
+            return Verdict::Accept;
+        }
+        if self.strike(ip, now) {
+            Verdict::Banned
+        } else {
+            Verdict::Limited
+        }
+    }
+
+    /// Counts a datagram from `ip` that wasn't a KRPC message (it is dropped anyway).
+    pub fn malformed(&mut self, ip: IpAddr, now: Instant) {
+        self.refill(ip, now);
+        self.strike(ip, now);
+    }
+
+    /// Senders banned right now.
+    pub fn banned(&self, now: Instant) -> usize {
+        self.senders
+            .values()
+            .filter(|s| s.banned_until.is_some_and(|until| now < until))
+            .count()
+    }
+
+    /// Forgets senders that are back to a full bucket, no strikes and no ban.
+    pub fn prune(&mut self, now: Instant) {
+        let burst = (self.rate * BURST_SECS).max(1.0);
+        let rate = self.rate;
+        self.senders.retain(|_, s| {
+            let idle = now.saturating_duration_since(s.at).as_secs_f64();
+            let full = rate == 0.0 || s.tokens + idle * rate >= burst;
+            let banned = s.banned_until.is_some_and(|until| now < until);
+            !full || s.strikes > idle || banned
+        });
+    }
+
+    fn refill(&mut self, ip: IpAddr, now: Instant) -> &mut Sender {
+        let burst = (self.rate * BURST_SECS).max(1.0);
+        let sender = self.senders.entry(bucket_key(ip)).or_insert(Sender {
+            tokens: burst,
+            strikes: 0.0,
+            at: now,
+            banned_until: None,
+        });
+        let elapsed = now.saturating_duration_since(sender.at).as_secs_f64();
+        sender.tokens = (sender.tokens + elapsed * self.rate).min(burst);
+        sender.strikes = (sender.strikes - elapsed).max(0.0);
+        sender.at = now;
+        sender
+    }
+
+    /// Adds a strike to an already refilled sender; true if that got it banned.
+    fn strike(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let ban = self.ban;
+        let Some(sender) = self.senders.get_mut(&bucket_key(ip)) else {
+            return false;
+        };
+        if sender.banned_until.is_some_and(|until| now < until) {
+            return true;
+        }
+        sender.strikes += 1.0;
+        if ban.is_zero() || sender.strikes < BAN_STRIKES {
+            return false;
+        }
+        sender.strikes = 0.0;
+        sender.banned_until = Some(now + ban);
+        tracing::info!(%ip, ban_secs = ban.as_secs(), "spider: banned node (flooding or malformed messages)");
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floods_are_limited_then_banned() {
+        let mut guard = NodeGuard::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        let flooder: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        for _ in 0..10 {
+            assert_eq!(guard.admit(flooder, start), Verdict::Accept);
+        }
+        for _ in 0..19 {
+            assert_eq!(guard.admit(flooder, start), Verdict::Limited);
+        }
+        assert_eq!(guard.admit(flooder, start), Verdict::Banned);
+        assert_eq!(guard.admit(other, start), Verdict::Accept);
+        assert_eq!(guard.banned(start), 1);
+        assert_eq!(guard.admit(flooder, start + Duration::from_secs(59)), Verdict::Banned);
+        assert_eq!(guard.admit(flooder, start + Duration::from_secs(60)), Verdict::Accept);
+
+        // Garbage bans too, even within the rate.
+        for _ in 0..20 {
+            guard.malformed(other, start);
+        }
+        assert_eq!(guard.admit(other, start), Verdict::Banned);
+    }
+}
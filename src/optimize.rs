@@ -0,0 +1,69 @@
+use crate::AppState;
+use std::time::{Duration, Instant};
+
+// Background purge of deleted documents from the search index.
+//
+// Tantivy only marks a deleted or replaced document as deleted; the segment keeps it until it
+// is merged. Cleanup deletes and every upsert replaces, so under constant churn segments fill
+// with dead documents that still take disk and slow searches down, while the merge policy
+// only looks at segment sizes. Every SERMA_INDEX_OPTIMIZE_EVERY_SECS this task waits for
+// a quiet minute and rewrites the worst segments, at most SERMA_INDEX_OPTIMIZE_MAX_DOCS
+// documents a run (`SearchIndex::purge_deletes`). If the index never goes quiet it runs
+// anyway once another interval has passed.
+
+/// How often index activity is sampled.
+const CHECK_EVERY: Duration = Duration::from_secs(60);
+/// A minute with at most this many index writes counts as idle.
+const IDLE_WRITES_PER_CHECK: u64 = 120;
+
+pub async fn run(state: AppState) {
+    if state.config.index_optimize_every_secs == 0 {
+        tracing::info!("optimize: disabled (SERMA_INDEX_OPTIMIZE_EVERY_SECS=0)");
+        return;
+    }
+    let every = Duration::from_secs(state.config.index_optimize_every_secs);
+    let mut tick = tokio::time::interval_at(tokio::time::Instant::now() + CHECK_EVERY, CHECK_EVERY);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut last_run = Instant::now();
+    let mut last_writes = state.index.write_count();
+
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = state.shutdown.wait() => return,
+        }
+        let writes = state.index.write_count();
+        let recent = writes - last_writes;
+        last_writes = writes;
+
+        let waited = last_run.elapsed();
+        if waited < every || state.index.is_reindexing() {
+            continue;
+        }
+        if recent > IDLE_WRITES_PER_CHECK && waited < every * 2 {
+            continue;
+        }
+        last_run = Instant::now();
+
+        let started = Instant::now();
+        let purged = tokio::select! {
+            purged = state.index.purge_deletes(state.config.index_optimize_max_docs) => purged,
+            // The merge finishes (or is dropped) with the index writer.
+            _ = state.shutdown.wait() => return,
+        };
+        match purged {
+            Ok(purge) if purge.segments == 0 => tracing::debug!("optimize: no segment worth rewriting"),
+            Ok(purge) => {
+                state.stats.record_index_purge(purge);
+                tracing::info!(
+                    segments = purge.segments,
+                    deleted_docs = purge.deleted_docs,
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    busy = recent > IDLE_WRITES_PER_CHECK,
+                    "optimize: purged deleted documents from the search index"
+                );
+            }
+            Err(err) => tracing::warn!(%err, "optimize: merging index segments failed"),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+// Peers announced to the spider (the storage side of BEP-5).
+//
+// A node that sends a valid `announce_peer` is stored under the hash it announced, and
+// `get_peers` for that hash returns it in `values`, as any DHT node would. Everything stays
+// in memory and bounded: a few peers per hash, one entry per IP, peers expire unless they
+// re-announce, and when too many hashes are stored the one stored first goes.
+
+/// Peers kept per hash; a new one replaces the stalest.
+const PEERS_PER_HASH: usize = 100;
+/// Clients re-announce about every 15–30 minutes.
+const PEER_TTL: Duration = Duration::from_secs(30 * 60);
+
+pub struct PeerStore {
+    max_hashes: usize,
+    swarms: HashMap<[u8; 20], Vec<(SocketAddr, Instant)>>,
+    /// Stored hashes, oldest first.
+    order: VecDeque<[u8; 20]>,
+}
+
+impl PeerStore {
+    /// Keeps peers for up to `max_hashes` hashes; 0 stores nothing.
+    pub fn new(max_hashes: usize) -> Self {
+        Self {
+            max_hashes,
+            swarms: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Stores (or refreshes) `peer` for `hash`.
+    pub fn announce(&mut self, hash: [u8; 20], peer: SocketAddr, now: Instant) {
+        if self.max_hashes == 0 {
+            return;
+        }
+        if !self.swarms.contains_key(&hash) {
+            while self.swarms.len() >= self.max_hashes {
+                let Some(oldest) = self.order.pop_front() else {
+                    break;
+                };
+                self.swarms.remove(&oldest);
+            }
+            self.order.push_back(hash);
+        }
+        let peers = self.swarms.entry(hash).or_default();
+        // One entry per IP, so a single host can't fill the list by announcing many ports.
+        if let Some(entry) = peers.iter_mut().find(|(addr, _)| addr.ip() == peer.ip()) {
+            *entry = (peer, now);
+        } else if peers.len() < PEERS_PER_HASH {
+            peers.push((peer, now));
+        } else if let Some(stalest) = peers.iter_mut().min_by_key(|(_, at)| *at) {
+            *stalest = (peer, now);
+        }
+    }
+
+    /// Up to `limit` live peers of `hash` in the same address family as `like`, most
+    /// recently announced first.
+    pub fn peers(&self, hash: &[u8; 20], like: IpAddr, limit: usize, now: Instant) -> Vec<SocketAddr> {
+        let Some(peers) = self.swarms.get(hash) else {
+            return Vec::new();
+        };
+        let mut live: Vec<&(SocketAddr, Instant)> = peers
+            .iter()
+            .filter(|(addr, at)| addr.is_ipv4() == like.is_ipv4() && now.saturating_duration_since(*at) < PEER_TTL)
+            .collect();
+        live.sort_by_key(|(_, at)| std::cmp::Reverse(*at));
+        live.into_iter().take(limit).map(|(addr, _)| *addr).collect()
+    }
+
+    /// Drops expired peers and hashes left without any.
+    pub fn prune(&mut self, now: Instant) {
+        self.swarms.retain(|_, peers| {
+            peers.retain(|(_, at)| now.saturating_duration_since(*at) < PEER_TTL);
+            !peers.is_empty()
+        });
+        let swarms = &self.swarms;
+        self.order.retain(|hash| swarms.contains_key(hash));
+    }
+
+    /// Stored hashes and peers.
+    pub fn counts(&self) -> (usize, usize) {
+        (self.swarms.len(), self.swarms.values().map(Vec::len).sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_bounded_expiring_peers() {
+        let mut store = PeerStore::new(2);
+        let start = Instant::now();
+        let v4: SocketAddr = "192.0.2.1:6881".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:6881".parse().unwrap();
+        store.announce([1; 20], v4, start);
+        store.announce([1; 20], v6, start);
+        // Same IP, new port: replaces the entry.
+        store.announce([1; 20], "192.0.2.1:7000".parse().unwrap(), start + Duration::from_secs(1));
+        assert_eq!(store.peers(&[1; 20], v4.ip(), 10, start), vec!["192.0.2.1:7000".parse().unwrap()]);
+        assert_eq!(store.peers(&[1; 20], v6.ip(), 10, start), vec![v6]);
+
+        // A third hash evicts the first one stored.
+        store.announce([2; 20], v4, start);
+        store.announce([3; 20], v4, start);
+        assert!(store.peers(&[1; 20], v4.ip(), 10, start).is_empty());
+        assert_eq!(store.counts(), (2, 2));
+
+        store.announce([3; 20], v6, start + PEER_TTL);
+        store.prune(start + PEER_TTL);
+        assert_eq!(store.counts(), (1, 1));
+        assert_eq!(store.peers(&[3; 20], v6.ip(), 10, start + PEER_TTL), vec![v6]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Announce-rate popularity.
+//
+// The spider's Bloom filter drops repeat sightings of a hash, which throws away the one
+// signal the DHT gives for free: how often peers announce it. Every announce_peer with a
+// valid token bumps a count-min sketch first; get_peers is left out, since anyone can send
+// those from any address, as often as they like. Counts halve every `DECAY_EVERY`, so they
+// follow the recent rate rather than all-time totals. A hash is queued for storage each
+// time its estimate reaches a power of two, so a hot hash costs a handful of writes, not
+// one per announce.
+//
+// A second, smaller sketch counts only the current `HOT_WINDOW` and is cleared when the
+// window rotates. The hashes with the highest window estimates are tracked as they come in,
+// and on rotation the top ones are handed to the enrich worker ahead of its queue, so a
+// hash that starts trending gets its title within minutes instead of waiting its turn.
+
+/// Counters per row (2^18); four rows of u32 are 4 MiB.
+const WIDTH_POW2: u32 = 18;
+const DEPTH: usize = 4;
+const DECAY_EVERY: Duration = Duration::from_secs(3600);
+/// Smallest estimate worth storing; below this it is mostly noise.
+const MIN_REPORT: u32 = 4;
+/// Window of the trending sketch; its top hashes are flushed when it rotates.
+const HOT_WINDOW: Duration = Duration::from_secs(300);
+/// Counters per row of the window sketch (2^16); four rows are 1 MiB.
+const HOT_WIDTH_POW2: u32 = 16;
+/// Candidates tracked per hash flushed, so late risers can still overtake early ones.
+const HOT_CANDIDATES: usize = 4;
+
+pub struct AnnounceCounter {
+    counters: Vec<u32>,
+    last_decay: Instant,
+    /// Hashes whose estimate crossed a threshold since the last `take_pending`.
+    pending: HashMap<[u8; 20], u32>,
+    /// Hashes flushed per window; 0 turns the trending sketch off.
+    hot_k: usize,
+    window: Vec<u32>,
+    window_started: Instant,
+    /// The highest window estimates so far, at most `hot_k * HOT_CANDIDATES`.
+    hot: HashMap<[u8; 20], u32>,
+}
+
+impl AnnounceCounter {
+    /// Flushes the `hot_k` hottest hashes of every window (see `take_hot`).
+    pub fn new(hot_k: usize) -> Self {
+        Self {
+            counters: vec![0; DEPTH << WIDTH_POW2],
+            last_decay: Instant::now(),
+            pending: HashMap::new(),
+            hot_k,
+            window: if hot_k > 0 { vec![0; DEPTH << HOT_WIDTH_POW2] } else { Vec::new() },
+            window_started: Instant::now(),
+            hot: HashMap::new(),
+        }
+    }
+
+    /// Counts one announce.
+    pub fn record(&mut self, hash: [u8; 20]) {
+        self.maybe_decay();
+        let estimate = bump(&mut self.counters, slots(&hash, WIDTH_POW2));
+        if estimate >= MIN_REPORT && estimate.is_power_of_two() {
+            self.pending.insert(hash, estimate);
+        }
+        if self.hot_k > 0 {
+            let estimate = bump(&mut self.window, slots(&hash, HOT_WIDTH_POW2));
+            self.track_hot(hash, estimate);
+        }
+    }
+
+    /// Hashes to store, with their current estimate.
+    pub fn take_pending(&mut self) -> HashMap<[u8; 20], u32> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Once the window is over: its hottest hashes (at most `hot_k`, hottest first, with
+    /// their window estimate), and a new window starts. Empty before that.
+    pub fn take_hot(&mut self, now: Instant) -> Vec<([u8; 20], u32)> {
+        if self.hot_k == 0 || now.saturating_duration_since(self.window_started) < HOT_WINDOW {
+            return Vec::new();
+        }
+        let mut hot: Vec<([u8; 20], u32)> = self.hot.drain().filter(|&(_, n)| n >= MIN_REPORT).collect();
+        hot.sort_unstable_by_key(|&(_, n)| std::cmp::Reverse(n));
+        hot.truncate(self.hot_k);
+        self.window.fill(0);
+        self.window_started = now;
+        hot
+    }
+
+    fn track_hot(&mut self, hash: [u8; 20], estimate: u32) {
+        if let Some(n) = self.hot.get_mut(&hash) {
+            *n = estimate;
+            return;
+        }
+        if self.hot.len() < self.hot_k * HOT_CANDIDATES {
+            self.hot.insert(hash, estimate);
+            return;
+        }
+        let Some((&coldest, &lowest)) = self.hot.iter().min_by_key(|&(_, n)| *n) else {
+            return;
+        };
+        if estimate > lowest {
+            self.hot.remove(&coldest);
+            self.hot.insert(hash, estimate);
+        }
+    }
+
+    fn maybe_decay(&mut self) {
+        if self.last_decay.elapsed() < DECAY_EVERY {
+            return;
+        }
+        for counter in &mut self.counters {
+            *counter >>= 1;
+        }
+        self.last_decay = Instant::now();
+    }
+}
+
+/// Counts one more in `slots` and returns the new estimate. Conservative update: only the
+/// counters that hold the minimum are raised, which keeps overestimates from hash
+/// collisions small.
+fn bump(counters: &mut [u32], slots: [usize; DEPTH]) -> u32 {
+    let estimate = slots.iter().map(|&i| counters[i]).min().unwrap_or(0).saturating_add(1);
+    for i in slots {
+        counters[i] = counters[i].max(estimate);
+    }
+    estimate
+}
+
+fn slots(hash: &[u8; 20], width_pow2: u32) -> [usize; DEPTH] {
+    let h1 = xxhash_rust::xxh3::xxh3_64(hash);
+    let h2 = xxhash_rust::xxh3::xxh3_64_with_seed(hash, 0xC2B2_AE3D_27D4_EB4F) | 1;
+    let mask = (1u64 << width_pow2) - 1;
+    std::array::from_fn(|row| {
+        let column = h1.wrapping_add((row as u64).wrapping_mul(h2)) & mask;
+        (row << width_pow2) | column as usize
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flushes_hottest_hashes_per_window() {
+        let mut counter = AnnounceCounter::new(2);
+        let start = counter.window_started;
+        for (byte, announces) in [(1u8, 3), (2, 20), (3, 9), (4, 12), (5, 5), (6, 7), (7, 6), (8, 8), (9, 30)] {
+            for _ in 0..announces {
+                counter.record([byte; 20]);
+            }
+        }
+        assert!(counter.take_hot(start).is_empty());
+        assert_eq!(counter.take_hot(start + HOT_WINDOW), vec![([9; 20], 30), ([2; 20], 20)]);
+        // A new window starts from zero.
+        counter.record([2; 20]);
+        assert!(counter.take_hot(start + HOT_WINDOW * 2).is_empty());
+        assert_eq!(counter.take_pending().get(&[9; 20]), Some(&16));
+    }
+}
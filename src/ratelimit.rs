@@ -0,0 +1,158 @@
+use crate::config::Config;
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Per-client rate limits for the web server.
+//
+// Each limited route class keeps a token bucket per client address: it holds up to `burst`
+// requests and refills at the steady rate. IPv6 clients are counted per /64, since one host
+// usually has a whole prefix to pick addresses from.
+
+/// Buckets tracked per class before idle (full again) ones are dropped.
+const MAX_TRACKED: usize = 100_000;
+
+/// Routes sharing one set of buckets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// Searches: `/search`, `/api/search` and Torznab.
+    Search,
+    /// Ingestion: `/api/ingest`, `/api/upload` and `/api/torrents`.
+    Ingest,
+}
+
+impl Class {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Class::Search => "search",
+            Class::Ingest => "ingest",
+        }
+    }
+}
+
+/// The limiters of every class; cheap to clone.
+#[derive(Clone)]
+pub struct Limits {
+    search: Option<Arc<Limiter>>,
+    ingest: Option<Arc<Limiter>>,
+    trust_proxy_headers: bool,
+}
+
+impl Limits {
+    pub fn new(config: &Config) -> Self {
+        let limiter = |per_min: u32, burst: u32| (per_min > 0).then(|| Arc::new(Limiter::new(per_min, burst)));
+        Self {
+            search: limiter(config.rate_limit_search_per_min, config.rate_limit_search_burst),
+            ingest: limiter(config.rate_limit_ingest_per_min, config.rate_limit_ingest_burst),
+            trust_proxy_headers: config.trust_proxy_headers,
+        }
+    }
+
+    /// Takes one request of `class` from the client's bucket. `Err` holds how long until
+    /// the next one is allowed.
+    pub fn check(&self, class: Class, client: IpAddr) -> Result<(), Duration> {
+        let limiter = match class {
+            Class::Search => &self.search,
+            Class::Ingest => &self.ingest,
+        };
+        match limiter {
+            Some(limiter) => limiter.check(client, Instant::now()),
+            None => Ok(()),
+        }
+    }
+
+    /// The client address of a request: the peer, or with SERMA_TRUST_PROXY_HEADERS the
+    /// address the reverse proxy in front reports (last `X-Forwarded-For` entry, else
+    /// `X-Real-IP`).
+    pub fn client_ip(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        if self.trust_proxy_headers {
+            let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+            let forwarded = header("x-forwarded-for")
+                .and_then(|v| v.rsplit(',').next())
+                .or_else(|| header("x-real-ip"))
+                .and_then(|v| v.trim().parse::<IpAddr>().ok());
+            if let Some(ip) = forwarded {
+                return ip;
+            }
+        }
+        peer.ip()
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+}
+
+struct Limiter {
+    per_sec: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl Limiter {
+    fn new(per_min: u32, burst: u32) -> Self {
+        Self {
+            per_sec: per_min as f64 / 60.0,
+            burst: burst.max(1) as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn check(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let Ok(mut buckets) = self.buckets.lock() else {
+            return Ok(());
+        };
+        if buckets.len() >= MAX_TRACKED {
+            let full_after = Duration::from_secs_f64(self.burst / self.per_sec);
+            buckets.retain(|_, b| now.saturating_duration_since(b.at) < full_after);
+        }
+        let bucket = buckets.entry(bucket_key(client)).or_insert(Bucket {
+            tokens: self.burst,
+            at: now,
+        });
+        let refill = now.saturating_duration_since(bucket.at).as_secs_f64() * self.per_sec;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst);
+        bucket.at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_sec))
+        }
+    }
+}
+
+/// IPv4 addresses as they are (also when mapped into IPv6), IPv6 addresses by /64.
+pub fn bucket_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !((1u128 << 64) - 1))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_burst_then_steady_rate() {
+        let limiter = Limiter::new(60, 3);
+        let start = Instant::now();
+        let client: IpAddr = "2001:db8::1".parse().unwrap();
+        let same_prefix: IpAddr = "2001:db8::2".parse().unwrap();
+        for _ in 0..3 {
+            assert!(limiter.check(client, start).is_ok());
+        }
+        let wait = limiter.check(same_prefix, start).unwrap_err();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+        assert!(limiter.check("2001:db8:1::1".parse().unwrap(), start).is_ok());
+        assert!(limiter.check(client, start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check(client, start + Duration::from_secs(1)).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+
+// Scene / "release" naming conventions, e.g. `The.Matrix.1999.1080p.BluRay.x264-GROUP` or
+// `Show.Name.S02E05.720p.WEB.h265-GRP`. Parsing is best effort: a field that isn't
+// recognised is left out, and titles without any of them yield nothing.
+
+/// Structured metadata read from a torrent title by `parse`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    /// `480p`, `576p`, `720p`, `1080p`, `2160p` or `4320p`.
+    #[serde(default)]
+    pub resolution: Option<String>,
+    /// Video codec: `h264`, `h265`, `av1`, `vp9`, `xvid` or `divx`.
+    #[serde(default)]
+    pub codec: Option<String>,
+    #[serde(default)]
+    pub season: Option<u32>,
+    #[serde(default)]
+    pub episode: Option<u32>,
+    #[serde(default)]
+    pub year: Option<u16>,
+    /// Release group, as written in the title.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl Release {
+    /// A TV episode or season pack.
+    pub fn is_episode(&self) -> bool {
+        self.season.is_some()
+    }
+
+    /// Short labels for display (`1080p`, `h265`, `S02E05`, `1999`, `GROUP`), each with the
+    /// search filter that finds more like it.
+    pub fn labels(&self) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+        if let Some(resolution) = &self.resolution {
+            out.push((resolution.clone(), format!("resolution:{resolution}")));
+        }
+        if let Some(codec) = &self.codec {
+            out.push((codec.clone(), format!("codec:{codec}")));
+        }
+        match (self.season, self.episode) {
+            (Some(s), Some(e)) => out.push((format!("S{s:02}E{e:02}"), format!("season:{s} episode:{e}"))),
+            (Some(s), None) => out.push((format!("S{s:02}"), format!("season:{s}"))),
+            _ => {}
+        }
+        if let Some(year) = self.year {
+            out.push((year.to_string(), format!("year:{year}")));
+        }
+        if let Some(group) = &self.group {
+            out.push((group.clone(), format!("group:{}", group.to_lowercase())));
+        }
+        out
+    }
+}
+
+const RESOLUTIONS: [&str; 6] = ["480p", "576p", "720p", "1080p", "2160p", "4320p"];
+/// Longest plausible release group name.
+const MAX_GROUP_CHARS: usize = 24;
+/// Title words that end a scene name after a `-` but aren't groups (`WEB-DL`, `DTS-HD`, ...).
+const NOT_GROUPS: &[&str] = &["dl", "rip", "hd", "ma", "x", "es", "cd", "dvd", "web", "sub", "subs", "dub"];
+
+/// The resolution a title word stands for (`1080p`, `1080i`, `4k`, `UHD`), normalized.
+pub fn normalize_resolution(word: &str) -> Option<&'static str> {
+    let word = word.to_ascii_lowercase();
+    match word.as_str() {
+        "4k" | "uhd" => return Some("2160p"),
+        "8k" => return Some("4320p"),
+        "sd" => return Some("480p"),
+        _ => {}
+    }
+    let digits = word.strip_suffix('p').or_else(|| word.strip_suffix('i')).unwrap_or(&word);
+    RESOLUTIONS.into_iter().find(|r| r.trim_end_matches('p') == digits)
+}
+
+/// The codec a title word stands for (`x264`, `H264`, `AVC`, `HEVC`, ...), normalized.
+pub fn normalize_codec(word: &str) -> Option<&'static str> {
+    Some(match word.to_ascii_lowercase().replace('.', "").as_str() {
+        "x264" | "h264" | "avc" => "h264",
+        "x265" | "h265" | "hevc" => "h265",
+        "av1" => "av1",
+        "vp9" => "vp9",
+        "xvid" => "xvid",
+        "divx" => "divx",
+        _ => return None,
+    })
+}
+
+/// Reads resolution, codec, season/episode, year and group from a release title; `None`
+/// if it has none of them.
+pub fn parse(title: &str) -> Option<Release> {
+    let title = strip_extension(title.trim());
+    let words: Vec<&str> = title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut release = Release::default();
+    for (i, word) in words.iter().enumerate() {
+        let lower = word.to_ascii_lowercase();
+        if release.resolution.is_none()
+            && let Some(resolution) = normalize_resolution(&lower)
+        {
+            release.resolution = Some(resolution.to_string());
+        }
+        if release.codec.is_none() {
+            // `H.264` splits into two words.
+            let codec = normalize_codec(&lower).or_else(|| {
+                let next = words.get(i + 1).filter(|_| lower == "h" || lower == "x")?;
+                normalize_codec(&format!("{lower}{next}"))
+            });
+            release.codec = codec.map(str::to_string);
+        }
+        if release.season.is_none()
+            && let Some((season, episode)) = season_episode(&lower)
+        {
+            release.season = Some(season);
+            release.episode = episode;
+        }
+        if release.season.is_none()
+            && lower == "season"
+            && let Some(season) = words.get(i + 1).and_then(|w| w.parse().ok()).filter(|s| *s < 100)
+        {
+            release.season = Some(season);
+        }
+        // The last year wins: `2001 A Space Odyssey 1968`. A leading number is the title.
+        if i > 0
+            && lower.len() == 4
+            && let Ok(year) = lower.parse::<u16>()
+            && (1900..2100).contains(&year)
+        {
+            release.year = Some(year);
+        }
+    }
+
+    // Only look for a group in names that read like releases, or `Spider-Man` has one.
+    let releaseish = release.resolution.is_some() || release.codec.is_some() || release.season.is_some();
+    if releaseish {
+        release.group = group(title);
+    }
+
+    (release != Release::default()).then_some(release)
+}
+
+/// `s01e02`, `s01`, `1x02` as (season, episode).
+fn season_episode(word: &str) -> Option<(u32, Option<u32>)> {
+    if let Some(rest) = word.strip_prefix('s') {
+        let (season, episode) = match rest.split_once('e') {
+            // Multi-episode `s01e01e02` keeps the first.
+            Some((s, e)) => (s, Some(e.split('e').next().unwrap_or(e))),
+            None => (rest, None),
+        };
+        if season.is_empty() || season.len() > 2 || !season.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let episode = match episode {
+            Some(e) if !e.is_empty() && e.len() <= 3 && e.bytes().all(|b| b.is_ascii_digit()) => Some(e.parse().ok()?),
+            Some(_) => return None,
+            None => None,
+        };
+        return Some((season.parse().ok()?, episode));
+    }
+    let (s, e) = word.split_once('x')?;
+    let digits = |v: &str, max: usize| !v.is_empty() && v.len() <= max && v.bytes().all(|b| b.is_ascii_digit());
+    if !(digits(s, 2) && e.len() >= 2 && digits(e, 3)) {
+        return None;
+    }
+    Some((s.parse().ok()?, Some(e.parse().ok()?)))
+}
+
+/// `[Group] Title - 01` or `Title.1080p.x264-GROUP`.
+fn group(title: &str) -> Option<String> {
+    if let Some(rest) = title.strip_prefix('[')
+        && let Some((name, _)) = rest.split_once(']')
+    {
+        let name = name.trim();
+        if !name.is_empty() && name.chars().count() <= MAX_GROUP_CHARS {
+            return Some(name.to_string());
+        }
+    }
+    // Trailing `[rarbg]`-style tags aren't part of the name.
+    let mut title = title.trim_end();
+    while let Some(rest) = title.strip_suffix(']').and_then(|t| t.rsplit_once('[')).map(|(t, _)| t.trim_end()) {
+        title = rest;
+    }
+    let (_, name) = title.rsplit_once('-')?;
+    let valid = !name.is_empty()
+        && name.chars().count() <= MAX_GROUP_CHARS
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !name.bytes().all(|b| b.is_ascii_digit())
+        && !NOT_GROUPS.contains(&name.to_ascii_lowercase().as_str())
+        && normalize_resolution(name).is_none()
+        && normalize_codec(name).is_none();
+    valid.then(|| name.to_string())
+}
+
+/// Drops a trailing video file extension (`Name-GRP.mkv`).
+fn strip_extension(title: &str) -> &str {
+    match title.rsplit_once('.') {
+        Some((name, ext)) if ["mkv", "mp4", "avi", "m4v", "ts"].contains(&ext.to_ascii_lowercase().as_str()) => name,
+        _ => title,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scene_names() {
+        let movie = parse("The.Matrix.1999.1080p.BluRay.x264-GROUP").unwrap();
+        assert_eq!(movie.resolution.as_deref(), Some("1080p"));
+        assert_eq!(movie.codec.as_deref(), Some("h264"));
+        assert_eq!(movie.year, Some(1999));
+        assert_eq!(movie.group.as_deref(), Some("GROUP"));
+        assert!(!movie.is_episode());
+
+        let episode = parse("Show.Name.S02E05.2160p.WEB-DL.DDP5.1.H.265-NTb.mkv").unwrap();
+        assert_eq!((episode.season, episode.episode), (Some(2), Some(5)));
+        assert_eq!(episode.resolution.as_deref(), Some("2160p"));
+        assert_eq!(episode.codec.as_deref(), Some("h265"));
+        assert_eq!(episode.group.as_deref(), Some("NTb"));
+
+        let anime = parse("[SubsPlease] Some Anime - 3x07 (720p) [ABCD1234]").unwrap();
+        assert_eq!(anime.group.as_deref(), Some("SubsPlease"));
+        assert_eq!((anime.season, anime.episode), (Some(3), Some(7)));
+
+        let pack = parse("Show Name Season 4 Complete 720p WEB-DL").unwrap();
+        assert_eq!((pack.season, pack.episode), (Some(4), None));
+        assert_eq!(pack.group, None);
+
+        assert_eq!(parse("Spider-Man").map(|r| r.group), None);
+        assert_eq!(parse("ubuntu-24.04-desktop-amd64.iso"), None);
+        assert_eq!(parse("2012").map(|r| r.year), None);
+    }
+}
@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// BEP-5 routing table for the spider.
+//
+// Nodes are bucketed by the length of the prefix their id shares with ours (one bucket
+// per bit, 160 total). Each bucket holds up to K "live" nodes that have answered us;
+// every other known node is a replacement candidate. Candidates are still queried for
+// BEP-51 samples, which keeps the spider's reach far wider than the live table, and
+// they fill buckets as live nodes go bad.
+//
+// Liveness follows BEP-5: a node is good if it answered within the last 15 minutes.
+// Every query we send counts as a failure until the node answers; after MAX_FAILS
+// unanswered queries it is dropped. When a bucket is full of nodes that are merely
+// questionable, the stalest one is pinged and replaced only if it stays silent.
+//
+// The table (and our node id) is saved to `dht_routing.dat` in the data dir, so a
+// restart rejoins the DHT from known nodes instead of a full re-bootstrap. Each node is
+// saved with when it last answered, so nodes seen shortly before a restart go straight
+// back into their buckets.
+//
+// The file is binary: `SDHT`, a format version byte and our 20-byte id, then one record
+// per node: its id, the unix second of its last answer (big-endian u64, 0 if never) and
+// its address as a family byte (4 or 6) followed by the compact BEP-5 form.
+
+/// Live nodes per bucket.
+pub const K: usize = 8;
+const MAX_FAILS: u8 = 3;
+const QUESTIONABLE_AFTER: Duration = Duration::from_secs(15 * 60);
+/// Pings to the same node are spaced out so a full bucket doesn't flood it.
+const PING_INTERVAL: Duration = Duration::from_secs(60);
+/// Saved nodes that haven't answered for this long are not loaded.
+const MAX_SAVED_AGE: Duration = Duration::from_secs(7 * 24 * 3600);
+
+pub const FILE_NAME: &str = "dht_routing.dat";
+const FILE_MAGIC: &[u8; 4] = b"SDHT";
+const FILE_VERSION: u8 = 1;
+
+struct Node {
+    id: [u8; 20],
+    live: bool,
+    last_response: Option<Instant>,
+    last_queried: Option<Instant>,
+    last_pinged: Option<Instant>,
+    /// When the node's BEP-51 samples are worth asking for again (its `interval`); `None`
+    /// until it has answered a sample query.
+    sample_after: Option<Instant>,
+    fails: u8,
+}
+
+impl Node {
+    fn new(id: [u8; 20]) -> Self {
+        Node {
+            id,
+            live: false,
+            last_response: None,
+            last_queried: None,
+            last_pinged: None,
+            sample_after: None,
+            fails: 0,
+        }
+    }
+
+    fn is_bad(&self) -> bool {
+        self.fails >= MAX_FAILS
+    }
+
+    fn is_good(&self, now: Instant) -> bool {
+        !self.is_bad()
+            && self
+                .last_response
+                .is_some_and(|t| now.saturating_duration_since(t) < QUESTIONABLE_AFTER)
+    }
+}
+
+pub struct RoutingTable {
+    own_id: [u8; 20],
+    nodes: HashMap<SocketAddr, Node>,
+    /// Live node addresses per bucket, least recently answered first.
+    buckets: Vec<Vec<SocketAddr>>,
+    max_nodes: usize,
+}
+
+struct SavedNode {
+    id: [u8; 20],
+    addr: SocketAddr,
+    /// Unix seconds of the node's last answer.
+    last_seen: Option<u64>,
+}
+
+impl RoutingTable {
+    pub fn new(own_id: [u8; 20], max_nodes: usize) -> Self {
+        RoutingTable {
+            own_id,
+            nodes: HashMap::new(),
+            buckets: vec![Vec::new(); 160],
+            max_nodes: max_nodes.max(K),
+        }
+    }
+
+    /// Loads a saved table. Saved nodes keep the time they last answered, so the ones
+    /// that fit go back into their buckets right away; the dead among them fail out like
+    /// any other node once queried.
+    pub fn load(path: &Path, max_nodes: usize) -> anyhow::Result<Self> {
+        let (own_id, saved) = decode_table(&std::fs::read(path)?)?;
+        let mut table = RoutingTable::new(own_id, max_nodes);
+        let now = Instant::now();
+        let unix_now = unix_secs();
+        for node in saved {
+            let id = node.id;
+            let age = node.last_seen.map(|t| Duration::from_secs(unix_now.saturating_sub(t)));
+            if age.is_some_and(|age| age > MAX_SAVED_AGE) {
+                continue;
+            }
+            table.add_candidate(id, node.addr);
+            if let Some(loaded) = table.nodes.get_mut(&node.addr) {
+                loaded.last_response = age.and_then(|age| now.checked_sub(age));
+            }
+        }
+        table.maintain();
+        Ok(table)
+    }
+
+    /// Saves our id plus live nodes, then candidates that have answered before.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut nodes: Vec<(&SocketAddr, &Node)> = self
+            .nodes
+            .iter()
+            .filter(|(_, n)| !n.is_bad() && n.last_response.is_some())
+            .collect();
+        nodes.sort_by_key(|(_, n)| (!n.live, std::cmp::Reverse(n.last_response)));
+        let now = Instant::now();
+        let unix_now = unix_secs();
+        let saved: Vec<SavedNode> = nodes
+            .into_iter()
+            .map(|(addr, n)| SavedNode {
+                id: n.id,
+                addr: *addr,
+                last_seen: n
+                    .last_response
+                    .map(|t| unix_now.saturating_sub(now.saturating_duration_since(t).as_secs())),
+            })
+            .collect();
+
+        // Write then rename, so a crash mid-write keeps the previous table.
+        let tmp = path.with_extension("dat.tmp");
+        std::fs::write(&tmp, encode_table(&self.own_id, &saved))?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn own_id(&self) -> &[u8; 20] {
+        &self.own_id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn live_len(&self) -> usize {
+        self.buckets.iter().map(Vec::len).sum()
+    }
+
+    fn bucket_index(&self, id: &[u8; 20]) -> Option<usize> {
+        for (i, (a, b)) in self.own_id.iter().zip(id).enumerate() {
+            let x = a ^ b;
+            if x != 0 {
+                return Some(i * 8 + x.leading_zeros() as usize);
+            }
+        }
+        // Our own id (or someone spoofing it).
+        None
+    }
+
+    /// Records a message from a node that told us its id.
+    ///
+    /// `responded` is true for responses to our queries; only those make a node live.
+    /// Returns the address of a questionable live node to ping if this node is waiting
+    /// for a slot in a full bucket.
+    pub fn heard_from(&mut self, id: [u8; 20], addr: SocketAddr, responded: bool) -> Option<SocketAddr> {
+        let b = self.bucket_index(&id)?;
+        if self.nodes.get(&addr).is_some_and(|n| n.id != id) {
+            // The address now belongs to a different node id; start over.
+            self.remove(&addr);
+        }
+        if !self.nodes.contains_key(&addr) {
+            if !responded && self.nodes.len() >= self.max_nodes {
+                return None;
+            }
+            self.nodes.insert(addr, Node::new(id));
+        }
+
+        if !responded {
+            return None;
+        }
+        let node = self.nodes.get_mut(&addr)?;
+        node.last_response = Some(Instant::now());
+        node.fails = 0;
+        if node.live {
+            // Move to the back: most recently answered.
+            let bucket = &mut self.buckets[b];
+            if let Some(pos) = bucket.iter().position(|a| *a == addr) {
+                bucket.remove(pos);
+                bucket.push(addr);
+            }
+            return None;
+        }
+        self.try_promote(addr)
+    }
+
+    /// Adds a node learned from a `nodes` list. It stays a candidate until it answers.
+    pub fn add_candidate(&mut self, id: [u8; 20], addr: SocketAddr) {
+        if self.bucket_index(&id).is_none()
+            || self.nodes.len() >= self.max_nodes
+            || self.nodes.contains_key(&addr)
+        {
+            return;
+        }
+        self.nodes.insert(addr, Node::new(id));
+    }
+
+    fn try_promote(&mut self, addr: SocketAddr) -> Option<SocketAddr> {
+        let now = Instant::now();
+        let id = self.nodes.get(&addr)?.id;
+        let b = self.bucket_index(&id)?;
+
+        if self.buckets[b].len() >= K {
+            // Replace a node that stopped answering, if any.
+            if let Some(pos) = self.buckets[b]
+                .iter()
+                .position(|a| self.nodes.get(a).is_none_or(Node::is_bad))
+            {
+                let bad = self.buckets[b].remove(pos);
+                self.nodes.remove(&bad);
+            } else {
+                // Full of good or questionable nodes: ping the stalest questionable one
+                // (the bucket is ordered by last answer, so that's the first).
+                let stale = self.buckets[b].iter().copied().find(|a| {
+                    self.nodes.get(a).is_some_and(|n| {
+                        !n.is_good(now)
+                            && n.last_pinged.is_none_or(|t| now.saturating_duration_since(t) >= PING_INTERVAL)
+                    })
+                })?;
+                if let Some(node) = self.nodes.get_mut(&stale) {
+                    node.last_pinged = Some(now);
+                }
+                return Some(stale);
+            }
+        }
+
+        self.buckets[b].push(addr);
+        if let Some(node) = self.nodes.get_mut(&addr) {
+            node.live = true;
+        }
+        None
+    }
+
+    fn remove(&mut self, addr: &SocketAddr) {
+        if let Some(node) = self.nodes.remove(addr)
+            && node.live
+            && let Some(b) = self.bucket_index(&node.id)
+        {
+            self.buckets[b].retain(|a| a != addr);
+        }
+    }
+
+    /// Up to `n` nodes closest to `target` by XOR distance, in the same address family
+    /// as `family`. Live nodes are preferred; candidates that have answered before fill
+    /// in while the table is still small.
+    pub fn closest(&self, target: &[u8; 20], n: usize, family: SocketAddr) -> Vec<([u8; 20], SocketAddr)> {
+        let same_family = |addr: &SocketAddr| addr.is_ipv4() == family.is_ipv4();
+        let mut found: Vec<([u8; 20], SocketAddr)> = self
+            .buckets
+            .iter()
+            .flatten()
+            .filter(|addr| same_family(addr))
+            .filter_map(|addr| self.nodes.get(addr).map(|node| (node.id, *addr)))
+            .collect();
+        if found.len() < n {
+            found.extend(
+                self.nodes
+                    .iter()
+                    .filter(|(addr, node)| {
+                        !node.live && !node.is_bad() && node.last_response.is_some() && same_family(addr)
+                    })
+                    .map(|(addr, node)| (node.id, *addr)),
+            );
+        }
+        found.sort_by_key(|(id, _)| xor_distance(id, target));
+        found.truncate(n);
+        found
+    }
+
+    /// Counts a query sent to `addr` as failed until the node answers.
+    pub fn note_queried(&mut self, addr: &SocketAddr) {
+        if let Some(node) = self.nodes.get_mut(addr) {
+            node.last_queried = Some(Instant::now());
+            node.fails = node.fails.saturating_add(1);
+        }
+    }
+
+    /// Picks up to `n` nodes (live or candidate) that were queried least recently,
+    /// and marks them as queried.
+    pub fn next_to_query(&mut self, n: usize) -> Vec<SocketAddr> {
+        let mut order: Vec<(Option<Instant>, SocketAddr)> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !node.is_bad())
+            .map(|(addr, node)| (node.last_queried, *addr))
+            .collect();
+        let n = n.min(order.len());
+        if n == 0 {
+            return Vec::new();
+        }
+        // Never-queried nodes (None) sort first.
+        order.select_nth_unstable_by_key(n - 1, |(t, _)| *t);
+        let picked: Vec<SocketAddr> = order[..n].iter().map(|(_, addr)| *addr).collect();
+        for addr in &picked {
+            self.note_queried(addr);
+        }
+        picked
+    }
+
+    /// Picks up to `n` nodes whose samples are due, ordered by `priority` (lowest first)
+    /// and then by how long ago they were queried, and marks them as queried.
+    pub fn next_to_sample(&mut self, n: usize, priority: impl Fn(&[u8; 20]) -> u64) -> Vec<SocketAddr> {
+        let now = Instant::now();
+        let mut order: Vec<(u64, Option<Instant>, SocketAddr)> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| !node.is_bad() && node.sample_after.is_none_or(|t| now >= t))
+            .map(|(addr, node)| (priority(&node.id), node.last_queried, *addr))
+            .collect();
+        let n = n.min(order.len());
+        if n == 0 {
+            return Vec::new();
+        }
+        order.select_nth_unstable_by_key(n - 1, |(p, t, _)| (*p, *t));
+        let picked: Vec<SocketAddr> = order[..n].iter().map(|(_, _, addr)| *addr).collect();
+        for addr in &picked {
+            self.note_queried(addr);
+        }
+        picked
+    }
+
+    /// Records a sample answer from `addr`: it is not asked again for `wait`.
+    pub fn note_sampled(&mut self, addr: &SocketAddr, wait: Duration) {
+        if let Some(node) = self.nodes.get_mut(addr) {
+            node.sample_after = Some(Instant::now() + wait);
+        }
+    }
+
+    /// Drops nodes that stopped answering, refills buckets from candidates that have
+    /// answered, and trims candidates down to the node cap.
+    pub fn maintain(&mut self) {
+        let bad: Vec<SocketAddr> = self
+            .nodes
+            .iter()
+            .filter(|(_, n)| n.is_bad())
+            .map(|(addr, _)| *addr)
+            .collect();
+        for addr in &bad {
+            self.remove(addr);
+        }
+
+        let mut ready: Vec<(Option<Instant>, SocketAddr)> = self
+            .nodes
+            .iter()
+            .filter(|(_, n)| !n.live && n.last_response.is_some())
+            .map(|(addr, n)| (n.last_response, *addr))
+            .collect();
+        ready.sort_by_key(|(t, _)| std::cmp::Reverse(*t));
+        for (_, addr) in ready {
+            let Some(b) = self.nodes.get(&addr).and_then(|n| self.bucket_index(&n.id)) else {
+                continue;
+            };
+            if self.buckets[b].len() < K {
+                self.buckets[b].push(addr);
+                if let Some(node) = self.nodes.get_mut(&addr) {
+                    node.live = true;
+                }
+            }
+        }
+
+        if self.nodes.len() > self.max_nodes {
+            // Evict candidates that never answered first, then the longest silent.
+            let mut candidates: Vec<(bool, Option<Instant>, SocketAddr)> = self
+                .nodes
+                .iter()
+                .filter(|(_, n)| !n.live)
+                .map(|(addr, n)| (n.last_response.is_some(), n.last_response, *addr))
+                .collect();
+            candidates.sort_by_key(|(answered, t, _)| (*answered, *t));
+            let excess = self.nodes.len() - self.max_nodes;
+            for (_, _, addr) in candidates.into_iter().take(excess) {
+                self.nodes.remove(&addr);
+            }
+        }
+    }
+}
+
+fn xor_distance(a: &[u8; 20], b: &[u8; 20]) -> [u8; 20] {
+    let mut out = [0u8; 20];
+    for (o, (x, y)) in out.iter_mut().zip(a.iter().zip(b)) {
+        *o = x ^ y;
+    }
+    out
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn encode_table(own_id: &[u8; 20], nodes: &[SavedNode]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(25 + nodes.len() * 47);
+    out.extend_from_slice(FILE_MAGIC);
+    out.push(FILE_VERSION);
+    out.extend_from_slice(own_id);
+    for node in nodes {
+        out.extend_from_slice(&node.id);
+        out.extend_from_slice(&node.last_seen.unwrap_or(0).to_be_bytes());
+        match node.addr.ip() {
+            IpAddr::V4(ip) => {
+                out.push(4);
+                out.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                out.push(6);
+                out.extend_from_slice(&ip.octets());
+            }
+        }
+        out.extend_from_slice(&node.addr.port().to_be_bytes());
+    }
+    out
+}
+
+fn decode_table(data: &[u8]) -> anyhow::Result<([u8; 20], Vec<SavedNode>)> {
+    let rest = data
+        .strip_prefix(FILE_MAGIC.as_slice())
+        .ok_or_else(|| anyhow::anyhow!("not a routing table file"))?;
+    let (&version, rest) = rest.split_first().ok_or_else(|| anyhow::anyhow!("truncated routing table"))?;
+    anyhow::ensure!(version == FILE_VERSION, "unsupported routing table version {version}");
+    let (own_id, mut rest) = rest
+        .split_first_chunk::<20>()
+        .ok_or_else(|| anyhow::anyhow!("truncated routing table"))?;
+    let mut nodes = Vec::new();
+    while !rest.is_empty() {
+        let (node, tail) = decode_node(rest).ok_or_else(|| anyhow::anyhow!("truncated routing table"))?;
+        nodes.push(node);
+        rest = tail;
+    }
+    Ok((*own_id, nodes))
+}
+
+fn decode_node(data: &[u8]) -> Option<(SavedNode, &[u8])> {
+    let (id, rest) = data.split_first_chunk::<20>()?;
+    let (last_seen, rest) = rest.split_first_chunk::<8>()?;
+    let (&family, rest) = rest.split_first()?;
+    let (ip, rest) = match family {
+        4 => {
+            let (ip, rest) = rest.split_first_chunk::<4>()?;
+            (IpAddr::V4(Ipv4Addr::from(*ip)), rest)
+        }
+        6 => {
+            let (ip, rest) = rest.split_first_chunk::<16>()?;
+            (IpAddr::V6(Ipv6Addr::from(*ip)), rest)
+        }
+        _ => return None,
+    };
+    let (port, rest) = rest.split_first_chunk::<2>()?;
+    let last_seen = u64::from_be_bytes(*last_seen);
+    let node = SavedNode {
+        id: *id,
+        addr: SocketAddr::new(ip, u16::from_be_bytes(*port)),
+        last_seen: (last_seen != 0).then_some(last_seen),
+    };
+    Some((node, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_table_round_trips() {
+        let mut table = RoutingTable::new([7; 20], 100);
+        let v4: SocketAddr = "1.2.3.4:6881".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:51413".parse().unwrap();
+        let silent: SocketAddr = "5.6.7.8:6881".parse().unwrap();
+        table.heard_from([1; 20], v4, true);
+        table.heard_from([2; 20], v6, true);
+        table.add_candidate([3; 20], silent);
+
+        let path = crate::test_dir("routing").join(FILE_NAME);
+        table.save(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        assert!(data.starts_with(b"SDHT\x01"));
+        let loaded = RoutingTable::load(&path, 100).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.own_id(), &[7; 20]);
+        // Only nodes that have answered are saved, and they come back live.
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.live_len(), 2);
+        let closest = loaded.closest(&[1; 20], 8, v4);
+        assert_eq!(closest, vec![([1; 20], v4)]);
+        let closest = loaded.closest(&[2; 20], 8, v6);
+        assert_eq!(closest, vec![([2; 20], v6)]);
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let node = SavedNode {
+            id: [1; 20],
+            addr: "1.2.3.4:6881".parse().unwrap(),
+            last_seen: Some(1_700_000_000),
+        };
+        let data = encode_table(&[7; 20], &[node]);
+        let (own_id, nodes) = decode_table(&data).unwrap();
+        assert_eq!(own_id, [7; 20]);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].last_seen, Some(1_700_000_000));
+
+        assert!(decode_table(&data[..data.len() - 1]).is_err());
+        assert!(decode_table(b"{\"node_id\":\"\"}").is_err());
+        let mut future = data.clone();
+        future[4] = FILE_VERSION + 1;
+        assert!(decode_table(&future).is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use crate::config::Config;
+use crate::trackers::Trackers;
+use crate::tracker_udp::{MAX_SCRAPE_HASHES, SwarmStats, UdpTracker};
+use crate::{AppState, control::Subsystem, storage};
+use anyhow::Context;
+use futures_util::StreamExt as _;
+use rbit::bencode;
+use rbit::metainfo::MagnetLink;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::time::Instant;
+use tokio::time::{Duration, timeout};
+
+// Tracker scrapes (BEP-15 for udp://, BEP-48 for http(s)://): seeder and leecher counts
+// without announcing, so we never join swarms we don't take part in.
+//
+// New torrents are scraped once by the enrich worker; this loop then periodically
+// re-scrapes every stored torrent whose magnet lists trackers, grouping hashes by tracker
+// and sending up to MAX_SCRAPE_HASHES per request. The best count across a torrent's
+// trackers wins. A tracker reporting all zeros usually doesn't know the torrent, so such
+// results are ignored rather than dragging the count down. Every request goes through
+// `Trackers`, which paces requests per tracker and skips the ones that keep failing.
+//
+// Most hashes found on the DHT come as bare magnets without trackers, leaving only the DHT
+// estimate (BEP-33 filters saturate around a few thousand, and peer lists stop at 50). With
+// SERMA_SCRAPE_OPEN_TRACKERS, such torrents are scraped at the open trackers in
+// SERMA_OPEN_TRACKERS instead, which see most public swarms.
+
+/// Trackers scraped concurrently.
+const TRACKER_CONCURRENCY: usize = 8;
+/// Time budget for all requests to one tracker in a round.
+const TRACKER_TIMEOUT: Duration = Duration::from_secs(120);
+/// Time budget for a single-torrent scrape across its trackers.
+const SINGLE_TIMEOUT: Duration = Duration::from_secs(6);
+/// A single-torrent scrape skips trackers it would have to wait longer than this for.
+const SINGLE_MAX_WAIT: Duration = Duration::from_secs(2);
+/// Upper bound on hashes scraped per round, to bound memory on very large databases.
+const MAX_HASHES_PER_ROUND: usize = 200_000;
+
+/// Default SERMA_OPEN_TRACKERS: long-running open trackers that answer scrapes.
+pub const OPEN_TRACKERS: &[&str] = &[
+    "udp://tracker.opentrackr.org:1337/announce",
+    "udp://open.stealth.si:80/announce",
+    "udp://tracker.torrent.eu.org:451/announce",
+    "udp://exodus.desync.com:6969/announce",
+    "udp://open.demonii.com:1337/announce",
+];
+
+pub async fn run(state: AppState) {
+    if state.config.enrich_scrape_every_secs == 0 {
+        tracing::info!("scrape: disabled (SERMA_ENRICH_SCRAPE_EVERY_SECS=0)");
+        return;
+    }
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%err, "scrape: failed creating HTTP client; disabled");
+            return;
+        }
+    };
+
+    // The enrich worker scrapes new torrents itself, so the first round can wait.
+    let every = Duration::from_secs(state.config.enrich_scrape_every_secs);
+    let mut tick = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = state.shutdown.wait() => break,
+        }
+        if state.control.is_paused(Subsystem::Enrich) {
+            continue;
+        }
+        match scrape_all(&state, &client).await {
+            Ok((torrents, updated)) => tracing::info!(torrents, updated, "scrape: round done"),
+            Err(err) => tracing::warn!(err = %format!("{err:#}"), "scrape: round failed"),
+        }
+    }
+}
+
+pub fn http_client() -> anyhow::Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!("serma/", env!("CARGO_PKG_VERSION")))
+        .timeout(SINGLE_TIMEOUT)
+        .build()?)
+}
+
+/// What a torrent's trackers reported.
+pub struct Scraped {
+    /// The best counts among them.
+    pub stats: SwarmStats,
+    /// The trackers that knew the torrent.
+    pub trackers: Vec<String>,
+}
+
+/// Scrapes one torrent's trackers; returns the best counts, if any tracker knew it.
+pub async fn scrape_one(
+    client: &reqwest::Client,
+    health: &Trackers,
+    info_hash: [u8; 20],
+    trackers: &[String],
+) -> Option<Scraped> {
+    let scrapes = trackers.iter().map(|url| async move {
+        let request = async {
+            timeout(SINGLE_TIMEOUT, scrape_tracker(client, url, &[info_hash]))
+                .await
+                .context("tracker timed out")?
+        };
+        match paced(health, url, SINGLE_MAX_WAIT, request).await {
+            Some(Ok(stats)) => stats.into_iter().next().filter(is_known).map(|stats| (url, stats)),
+            Some(Err(err)) => {
+                tracing::debug!(%url, err = %format!("{err:#}"), "scrape: tracker failed");
+                None
+            }
+            None => {
+                tracing::trace!(%url, "scrape: tracker backed off or busy; skipped");
+                None
+            }
+        }
+    });
+    let known: Vec<_> = futures_util::future::join_all(scrapes).await.into_iter().flatten().collect();
+    Some(Scraped {
+        stats: known.iter().map(|(_, stats)| *stats).reduce(best)?,
+        trackers: known.into_iter().map(|(url, _)| url.clone()).collect(),
+    })
+}
+
+/// The trackers to scrape for a torrent: those its magnet lists, or with
+/// SERMA_SCRAPE_OPEN_TRACKERS the open trackers if it lists none.
+pub fn trackers_for(config: &Config, magnet: Option<&str>) -> Vec<String> {
+    let listed = magnet
+        .and_then(|m| MagnetLink::parse(m).ok())
+        .map(|m| m.trackers)
+        .unwrap_or_default();
+    if listed.is_empty() && config.scrape_open_trackers {
+        config.open_trackers.clone()
+    } else {
+        listed
+    }
+}
+
+async fn scrape_all(state: &AppState, client: &reqwest::Client) -> anyhow::Result<(usize, usize)> {
+    // tracker url -> hashes
+    let mut by_tracker: HashMap<String, Vec<[u8; 20]>> = HashMap::new();
+    let mut torrents = 0usize;
+    for record in storage::scan_records(&state.db) {
+        let record = record?;
+        let Some(hash) = hash_bytes(&record.info_hash_hex) else {
+            continue;
+        };
+        let trackers = trackers_for(&state.config, record.magnet.as_deref());
+        if trackers.is_empty() {
+            continue;
+        }
+        for tracker in trackers {
+            if is_scrapable(&tracker) {
+                by_tracker.entry(tracker).or_default().push(hash);
+            }
+        }
+        torrents += 1;
+        if torrents >= MAX_HASHES_PER_ROUND {
+            break;
+        }
+    }
+
+    let results = futures_util::stream::iter(by_tracker)
+        .map(|(url, hashes)| async move {
+            let mut out = Vec::with_capacity(hashes.len());
+            let scraped = timeout(TRACKER_TIMEOUT, async {
+                // One UDP session per tracker, so the connection id is reused across batches.
+                let mut udp: Option<UdpTracker> = None;
+                for chunk in hashes.chunks(MAX_SCRAPE_HASHES) {
+                    let request = async {
+                        if url.starts_with("udp://") {
+                            let tracker = match &mut udp {
+                                Some(tracker) => tracker,
+                                None => udp.insert(UdpTracker::open(&url).await?),
+                            };
+                            tracker.scrape(chunk).await
+                        } else {
+                            http_scrape(client, &url, chunk).await
+                        }
+                    };
+                    let Some(stats) = paced(&state.trackers, &url, TRACKER_TIMEOUT, request).await else {
+                        tracing::debug!(%url, "scrape: tracker backed off; skipped this round");
+                        break;
+                    };
+                    out.extend(chunk.iter().copied().zip(stats?));
+                }
+                anyhow::Ok(())
+            })
+            .await;
+            match scraped {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => tracing::debug!(%url, err = %format!("{err:#}"), "scrape: tracker failed"),
+                Err(_) => tracing::debug!(%url, "scrape: tracker timed out"),
+            }
+            out
+        })
+        .buffer_unordered(TRACKER_CONCURRENCY)
+        .collect();
+    // Nothing is written until every tracker has answered, so a round cut short is simply dropped.
+    let results: Vec<Vec<([u8; 20], SwarmStats)>> = tokio::select! {
+        results = results => results,
+        _ = state.shutdown.wait() => return Ok((torrents, 0)),
+    };
+
+    let mut best_by_hash: HashMap<[u8; 20], SwarmStats> = HashMap::new();
+    for (hash, stats) in results.into_iter().flatten().filter(|(_, s)| is_known(s)) {
+        best_by_hash
+            .entry(hash)
+            .and_modify(|b| *b = best(*b, stats))
+            .or_insert(stats);
+    }
+
+    let mut updated = 0usize;
+    for (hash, stats) in best_by_hash {
+        if apply(state, &hex::encode(hash), stats)? {
+            updated += 1;
+        }
+    }
+    state.index.commit()?;
+    Ok((torrents, updated))
+}
+
+/// Stores scrape counts and keeps the search index in step (same seeder threshold as
+/// enrichment). Returns false if the record is gone or unchanged.
+pub fn apply(state: &AppState, info_hash_hex: &str, stats: SwarmStats) -> anyhow::Result<bool> {
+    let seeders = i64::from(stats.seeders);
+    let leechers = i64::from(stats.leechers);
+    let history = state.config.seeder_history();
+    if let Some(current) = storage::get(&state.db, info_hash_hex)?
+        && current.seeders == seeders
+        && current.leechers == leechers
+    {
+        // Unchanged, but a steady swarm still needs its samples.
+        storage::record_seeders(&state.db, &current, &history)?;
+        return Ok(false);
+    }
+    let Some(record) = storage::set_swarm_counts(&state.db, info_hash_hex, seeders, leechers, &history)? else {
+        return Ok(false);
+    };
+    if record.title.is_some() {
+        if record.seeders >= 2 {
+            state.index.upsert_record(&record)?;
+        } else {
+            state.index.delete(&record.info_hash_hex)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Sends one request to `url` once `trackers` lets it (waiting at most `max_wait` for its
+/// turn) and records how it went. None if the tracker is backed off or too busy.
+async fn paced<T>(
+    trackers: &Trackers,
+    url: &str,
+    max_wait: Duration,
+    request: impl Future<Output = anyhow::Result<T>>,
+) -> Option<anyhow::Result<T>> {
+    let wait = trackers.reserve(url, max_wait, Instant::now())?;
+    tokio::time::sleep(wait).await;
+    let started = Instant::now();
+    let result = request.await;
+    match &result {
+        Ok(_) => trackers.succeeded(url, started.elapsed()),
+        Err(_) => trackers.failed(url, Instant::now()),
+    }
+    Some(result)
+}
+
+async fn scrape_tracker(client: &reqwest::Client, url: &str, hashes: &[[u8; 20]]) -> anyhow::Result<Vec<SwarmStats>> {
+    if url.starts_with("udp://") {
+        let mut tracker = UdpTracker::open(url).await?;
+        tracker.scrape(hashes).await
+    } else {
+        http_scrape(client, url, hashes).await
+    }
+}
+
+/// BEP-48: GET the scrape URL with one `info_hash` per torrent; the response maps each
+/// raw hash to its counts. Hashes the tracker doesn't know are reported as zeros.
+async fn http_scrape(client: &reqwest::Client, url: &str, hashes: &[[u8; 20]]) -> anyhow::Result<Vec<SwarmStats>> {
+    let mut scrape_url = scrape_url(url).with_context(|| format!("tracker has no scrape url: {url}"))?;
+    for (i, hash) in hashes.iter().enumerate() {
+        scrape_url.push(if i == 0 && !scrape_url.contains('?') { '?' } else { '&' });
+        scrape_url.push_str("info_hash=");
+        for b in hash {
+            let _ = write!(scrape_url, "%{b:02X}");
+        }
+    }
+
+    let body = client.get(&scrape_url).send().await?.error_for_status()?.bytes().await?;
+    let value = bencode::decode(&body).context("decode scrape response")?;
+    if let Some(reason) = value.get(b"failure reason").and_then(|v| v.as_str()) {
+        anyhow::bail!("tracker failure: {reason}");
+    }
+    let files = value
+        .get(b"files")
+        .and_then(|v| v.as_dict())
+        .context("scrape response has no files")?;
+
+    let count = |stats: &bencode::Value, key: &[u8]| -> u32 {
+        stats
+            .get(key)
+            .and_then(|v| v.as_integer())
+            .and_then(|n| u32::try_from(n).ok())
+            .unwrap_or(0)
+    };
+    Ok(hashes
+        .iter()
+        .map(|hash| match files.get(hash.as_slice()) {
+            Some(stats) => SwarmStats {
+                seeders: count(stats, b"complete"),
+                leechers: count(stats, b"incomplete"),
+                completed: count(stats, b"downloaded"),
+            },
+            None => SwarmStats::default(),
+        })
+        .collect())
+}
+
+/// `.../announce[...]` -> `.../scrape[...]`; trackers whose last path segment doesn't
+/// start with "announce" don't support scraping (BEP-48).
+fn scrape_url(announce: &str) -> Option<String> {
+    let (path, query) = match announce.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (announce, None),
+    };
+    let slash = path.rfind('/')?;
+    let last = &path[slash + 1..];
+    let rest = last.strip_prefix("announce")?;
+    let mut out = format!("{}/scrape{rest}", &path[..slash]);
+    if let Some(query) = query {
+        out.push('?');
+        out.push_str(query);
+    }
+    Some(out)
+}
+
+pub fn is_scrapable(url: &str) -> bool {
+    url.starts_with("udp://") || ((url.starts_with("http://") || url.starts_with("https://")) && scrape_url(url).is_some())
+}
+
+fn is_known(stats: &SwarmStats) -> bool {
+    stats.seeders > 0 || stats.leechers > 0 || stats.completed > 0
+}
+
+fn best(a: SwarmStats, b: SwarmStats) -> SwarmStats {
+    if (b.seeders, b.leechers) > (a.seeders, a.leechers) { b } else { a }
+}
+
+fn hash_bytes(hex_hash: &str) -> Option<[u8; 20]> {
+    hex::decode(hex_hash).ok()?.try_into().ok()
+}
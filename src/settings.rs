@@ -0,0 +1,192 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::watch;
+
+// Settings that can change on a running server (`PATCH /api/admin/config`).
+//
+// They start out as configured; workers hold a `watch::Receiver` and pick up a change at
+// their next batch or sweep, so tuning doesn't cost a restart (and with it the DHT routing
+// state built up since start). Changes last until the process exits: the config file and
+// environment are still what the next start reads.
+
+/// Largest `enrich_max_concurrent` accepted at runtime.
+pub const MAX_ENRICH_CONCURRENT: usize = 4096;
+
+/// The current values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Runtime {
+    /// Metadata lookups in flight at once (SERMA_ENRICH_MAX_CONCURRENT).
+    pub enrich_max_concurrent: usize,
+    /// Records not seen for this long are removed by cleanup (SERMA_TORRENT_TTL_SECS).
+    pub torrent_ttl_secs: u64,
+    /// Torrents with fewer seeders are removed after the grace period (SERMA_CLEANUP_MIN_SEEDERS).
+    pub cleanup_min_seeders: u32,
+    /// `RUST_LOG`-style filter for log output and exported spans.
+    pub log_filter: String,
+}
+
+/// A partial update: fields left out keep their value.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Patch {
+    pub enrich_max_concurrent: Option<usize>,
+    pub torrent_ttl_secs: Option<u64>,
+    pub cleanup_min_seeders: Option<u32>,
+    pub log_filter: Option<String>,
+}
+
+/// Swaps the process's log filter; installed by whoever set up the subscriber.
+type LogReload = Box<dyn Fn(&str) -> anyhow::Result<()> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Settings {
+    tx: Arc<watch::Sender<Runtime>>,
+    log_reload: Arc<OnceLock<LogReload>>,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Self {
+        let log_filter = std::env::var("RUST_LOG")
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "info".to_string());
+        Self::with(Runtime {
+            enrich_max_concurrent: config.enrich_max_concurrent,
+            torrent_ttl_secs: config.torrent_ttl_secs,
+            cleanup_min_seeders: config.cleanup_min_seeders,
+            log_filter,
+        })
+    }
+
+    fn with(runtime: Runtime) -> Self {
+        Self {
+            tx: Arc::new(watch::channel(runtime).0),
+            log_reload: Arc::default(),
+        }
+    }
+
+    pub fn get(&self) -> Runtime {
+        self.tx.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Runtime> {
+        self.tx.subscribe()
+    }
+
+    /// Makes `log_filter` changeable; without it, patching the filter is refused.
+    pub fn set_log_reload(&self, reload: impl Fn(&str) -> anyhow::Result<()> + Send + Sync + 'static) {
+        let _ = self.log_reload.set(Box::new(reload));
+    }
+
+    /// Checks every field, then applies them all; on any error nothing changes.
+    pub fn apply(&self, patch: Patch) -> Result<Runtime, Vec<String>> {
+        let mut errors = Vec::new();
+        if let Some(n) = patch.enrich_max_concurrent
+            && !(1..=MAX_ENRICH_CONCURRENT).contains(&n)
+        {
+            errors.push(format!("enrich_max_concurrent={n}: must be between 1 and {MAX_ENRICH_CONCURRENT}"));
+        }
+        if let Some(n) = patch.cleanup_min_seeders
+            && n > 2
+        {
+            errors.push(format!("cleanup_min_seeders={n}: must be between 0 and 2"));
+        }
+        let log_filter = patch.log_filter.as_deref().map(str::trim);
+        if let Some(filter) = log_filter {
+            match self.log_reload.get() {
+                None => errors.push("log_filter: the log filter can't be changed in this process".to_string()),
+                Some(_) if filter.is_empty() => errors.push("log_filter: must not be empty".to_string()),
+                Some(_) => {}
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        // Last check, since it already takes effect: the filter syntax.
+        if let Some(filter) = log_filter
+            && let Some(reload) = self.log_reload.get()
+            && let Err(err) = reload(filter)
+        {
+            return Err(vec![format!("log_filter={filter:?}: {err}")]);
+        }
+
+        self.tx.send_if_modified(|runtime| {
+            let before = runtime.clone();
+            if let Some(n) = patch.enrich_max_concurrent {
+                runtime.enrich_max_concurrent = n;
+            }
+            if let Some(secs) = patch.torrent_ttl_secs {
+                runtime.torrent_ttl_secs = secs;
+            }
+            if let Some(n) = patch.cleanup_min_seeders {
+                runtime.cleanup_min_seeders = n;
+            }
+            if let Some(filter) = log_filter {
+                runtime.log_filter = filter.to_string();
+            }
+            let changed = *runtime != before;
+            if changed {
+                tracing::info!(?runtime, "settings: changed at runtime");
+            }
+            changed
+        });
+        Ok(self.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patches_apply_whole_or_not_at_all() {
+        let settings = Settings::with(Runtime {
+            enrich_max_concurrent: 64,
+            torrent_ttl_secs: 86_400,
+            cleanup_min_seeders: 2,
+            log_filter: "info".to_string(),
+        });
+        let mut rx = settings.subscribe();
+
+        let bad = Patch {
+            enrich_max_concurrent: Some(8),
+            cleanup_min_seeders: Some(3),
+            ..Patch::default()
+        };
+        assert_eq!(settings.apply(bad).unwrap_err().len(), 1);
+        // No subscriber to reload: the filter can't change.
+        let filter = Patch {
+            log_filter: Some("debug".to_string()),
+            ..Patch::default()
+        };
+        assert!(settings.apply(filter).is_err());
+        assert!(!rx.has_changed().unwrap());
+
+        let good = Patch {
+            enrich_max_concurrent: Some(8),
+            torrent_ttl_secs: Some(3600),
+            ..Patch::default()
+        };
+        let runtime = settings.apply(good).unwrap();
+        assert_eq!((runtime.enrich_max_concurrent, runtime.torrent_ttl_secs, runtime.cleanup_min_seeders), (8, 3600, 2));
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(rx.borrow_and_update().enrich_max_concurrent, 8);
+
+        settings.set_log_reload(|filter| {
+            anyhow::ensure!(filter != "bad", "invalid filter");
+            Ok(())
+        });
+        let bad_filter = Patch {
+            log_filter: Some("bad".to_string()),
+            ..Patch::default()
+        };
+        assert!(settings.apply(bad_filter).is_err());
+        let filter = Patch {
+            log_filter: Some(" serma=debug ".to_string()),
+            ..Patch::default()
+        };
+        assert_eq!(settings.apply(filter).unwrap().log_filter, "serma=debug");
+    }
+}
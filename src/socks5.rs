@@ -1,7 +1,12 @@
 use anyhow::Context;
+use socket2::{SockRef, TcpKeepalive};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 
 #[derive(Debug, Clone)]
 pub struct Socks5Config {
@@ -35,137 +40,375 @@ impl Socks5Config {
         }))
     }
 
-    async fn resolve_proxy_addr(&self) -> anyhow::Result<SocketAddr> {
+    async fn resolve_proxy_addrs(&self) -> anyhow::Result<Vec<SocketAddr>> {
         // Accept raw SocketAddr too (fast path).
         if let Ok(sa) = self.proxy.parse::<SocketAddr>() {
-            return Ok(sa);
+            return Ok(vec![sa]);
         }
-        let mut iter = tokio::net::lookup_host(&self.proxy)
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host(&self.proxy)
             .await
-            .with_context(|| format!("resolve SOCKS5 proxy host: {}", self.proxy))?;
-        iter.next()
-            .with_context(|| format!("no addresses for SOCKS5 proxy: {}", self.proxy))
+            .with_context(|| format!("resolve SOCKS5 proxy host: {}", self.proxy))?
+            .collect();
+        anyhow::ensure!(!addrs.is_empty(), "no addresses for SOCKS5 proxy: {}", self.proxy);
+        Ok(addrs)
+    }
+
+    /// Connects to the proxy, trying each of its addresses in turn (a host with an IPv6
+    /// address this machine can't reach may still have a working IPv4 one).
+    async fn connect_proxy(&self) -> anyhow::Result<(TcpStream, SocketAddr)> {
+        let mut last_err = None;
+        for addr in self.resolve_proxy_addrs().await? {
+            match TcpStream::connect(addr).await {
+                Ok(tcp) => return Ok((tcp, addr)),
+                Err(err) => last_err = Some(anyhow::Error::new(err).context(format!("connect to SOCKS5 proxy: {addr}"))),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("no addresses for SOCKS5 proxy: {}", self.proxy)))
     }
 }
 
-/// A SOCKS5 UDP ASSOCIATE mapping.
+/// Shortest and longest wait between attempts to re-establish a dropped UDP association.
+const REASSOCIATE_MIN: Duration = Duration::from_secs(1);
+const REASSOCIATE_MAX: Duration = Duration::from_secs(60);
+/// Longest wait between attempts at the first association (`connect_with_backoff`).
+const CONNECT_RETRY_MAX: Duration = Duration::from_secs(300);
+/// Upper bound for one re-association attempt, so a blackholed proxy can't stall callers.
+const REASSOCIATE_TIMEOUT: Duration = Duration::from_secs(10);
+/// TCP keepalive on the control connection: probe after 30s idle, every 10s, give up after
+/// 3 misses. A proxy host that vanishes without closing the connection is noticed in ~1 min.
+const CONTROL_KEEPALIVE_IDLE: Duration = Duration::from_secs(30);
+const CONTROL_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+const CONTROL_KEEPALIVE_RETRIES: u32 = 3;
+
+/// A SOCKS5 UDP ASSOCIATE mapping that survives proxy restarts.
 ///
-/// Keeps the TCP control connection open so the proxy maintains the UDP mapping.
+/// The proxy keeps the UDP mapping only while the TCP control connection is open, so a
+/// background task watches that connection (with TCP keepalive) and marks the mapping dead
+/// when it closes. The next `send_to`/`recv_from` then re-associates transparently, backing
+/// off between failed attempts; while the proxy is down, sends fail and receives wait.
 #[derive(Debug)]
 pub struct Socks5UdpAssociate {
+    cfg: Socks5Config,
+    current: Mutex<Arc<Association>>,
+    /// Serializes re-association so concurrent callers don't each open a mapping.
+    reassociate: tokio::sync::Mutex<Backoff>,
+}
+
+#[derive(Debug)]
+struct Association {
     udp: UdpSocket,
     relay: SocketAddr,
-    _tcp: TcpStream,
+    dead: Arc<watch::Sender<bool>>,
+    /// Owns the control connection; aborting it closes the mapping.
+    monitor: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct Backoff {
+    delay: Duration,
+    next_attempt: Instant,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            delay: REASSOCIATE_MIN,
+            next_attempt: Instant::now(),
+        }
+    }
 }
 
 impl Socks5UdpAssociate {
     pub async fn connect(cfg: &Socks5Config) -> anyhow::Result<Self> {
-        let proxy_addr = cfg.resolve_proxy_addr().await?;
+        let association = Association::open(cfg).await?;
+        Ok(Self {
+            cfg: cfg.clone(),
+            current: Mutex::new(Arc::new(association)),
+            reassociate: tokio::sync::Mutex::new(Backoff::default()),
+        })
+    }
 
-        let mut tcp = TcpStream::connect(proxy_addr)
-            .await
-            .with_context(|| format!("connect to SOCKS5 proxy: {proxy_addr}"))?;
-
-        // 1) Greeting
-        let want_userpass = cfg.username.is_some() || cfg.password.is_some();
-        if want_userpass {
-            tcp.write_all(&[0x05, 0x02, 0x00, 0x02]).await?;
-        } else {
-            tcp.write_all(&[0x05, 0x01, 0x00]).await?;
+    /// Like `connect`, retrying with exponential backoff until the proxy accepts the
+    /// association. Returns `None` on shutdown. `component` names the caller in the logs.
+    pub async fn connect_with_backoff(cfg: &Socks5Config, shutdown: &crate::Shutdown, component: &str) -> Option<Self> {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            match Self::connect(cfg).await {
+                Ok(sock) => return Some(sock),
+                Err(err) => tracing::warn!(
+                    err = %format!("{err:#}"),
+                    proxy = %cfg.proxy,
+                    retry_secs = delay.as_secs(),
+                    "{component}: SOCKS5 UDP associate failed; retrying"
+                ),
+            }
+            shutdown.sleep(delay).await;
+            if shutdown.is_triggered() {
+                return None;
+            }
+            delay = (delay * 2).min(CONNECT_RETRY_MAX);
         }
+    }
+
+    pub fn relay_addr(&self) -> SocketAddr {
+        self.current().relay
+    }
+
+    /// Whether the current mapping is usable (its control connection is still open).
+    pub fn is_alive(&self) -> bool {
+        !self.current().is_dead()
+    }
 
-        let mut choice = [0u8; 2];
-        tcp.read_exact(&mut choice).await?;
-        if choice[0] != 0x05 {
-            anyhow::bail!("SOCKS5: invalid version in method select: {}", choice[0]);
+    /// Drops the current mapping; the next send or receive re-associates. For callers that
+    /// detect a dead relay on their own (e.g. no replies for a long time).
+    pub fn mark_dead(&self) {
+        self.current().mark_dead();
+    }
+
+    pub async fn send_to(&self, payload: &[u8], target: SocketAddr) -> std::io::Result<usize> {
+        let association = self.live().await?;
+        let pkt = encode_udp_packet(target, payload);
+        // Return the payload size to make callers treat this like a normal UDP socket.
+        if let Err(err) = association.udp.send_to(&pkt, association.relay).await {
+            association.mark_dead();
+            return Err(err);
         }
-        match choice[1] {
-            0x00 => {}
-            0x02 => {
-                let u = cfg.username.clone().unwrap_or_default();
-                let p = cfg.password.clone().unwrap_or_default();
-                if u.len() > 255 || p.len() > 255 {
-                    anyhow::bail!("SOCKS5: username/password too long");
-                }
-                let mut auth = Vec::with_capacity(3 + u.len() + p.len());
-                auth.push(0x01);
-                auth.push(u.len() as u8);
-                auth.extend_from_slice(u.as_bytes());
-                auth.push(p.len() as u8);
-                auth.extend_from_slice(p.as_bytes());
-                tcp.write_all(&auth).await?;
-
-                let mut resp = [0u8; 2];
-                tcp.read_exact(&mut resp).await?;
-                if resp[0] != 0x01 || resp[1] != 0x00 {
-                    anyhow::bail!("SOCKS5: auth failed");
+        Ok(payload.len())
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        let n = loop {
+            let association = match self.live().await {
+                Ok(association) => association,
+                Err(_) => {
+                    let next_attempt = self.reassociate.lock().await.next_attempt;
+                    tokio::time::sleep_until(next_attempt.into()).await;
+                    continue;
                 }
+            };
+            let mut dead = association.dead.subscribe();
+            tokio::select! {
+                r = association.udp.recv_from(buf) => match r {
+                    Ok((n, _from_relay)) => break n,
+                    Err(err) => {
+                        association.mark_dead();
+                        return Err(err);
+                    }
+                },
+                _ = dead.wait_for(|dead| *dead) => continue,
             }
-            0xFF => anyhow::bail!("SOCKS5: no acceptable auth methods"),
-            other => anyhow::bail!("SOCKS5: unsupported auth method: {other}"),
+        };
+
+        let (src, payload_pos) = decode_udp_header(&buf[..n])
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        if payload_pos > n {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "SOCKS5 UDP: invalid payload offset",
+            ));
         }
 
-        // 2) UDP ASSOCIATE
-        // Send an "unspecified" address of our IP family; proxy returns relay address.
-        let mut req = Vec::with_capacity(32);
-        req.extend_from_slice(&[0x05, 0x03, 0x00]);
-        match proxy_addr.ip() {
-            IpAddr::V4(_) => {
-                req.push(0x01);
-                req.extend_from_slice(&Ipv4Addr::UNSPECIFIED.octets());
-                req.extend_from_slice(&0u16.to_be_bytes());
+        // Shift payload down so callers can treat this like a normal UDP socket.
+        buf.copy_within(payload_pos..n, 0);
+        Ok((n - payload_pos, src))
+    }
+
+    fn current(&self) -> Arc<Association> {
+        self.current
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns a usable mapping, re-associating if the current one is dead. Fails without
+    /// touching the proxy while the backoff from an earlier failed attempt is running.
+    async fn live(&self) -> std::io::Result<Arc<Association>> {
+        let current = self.current();
+        if !current.is_dead() {
+            return Ok(current);
+        }
+
+        let mut backoff = self.reassociate.lock().await;
+        // Another caller may have re-associated while we waited for the lock.
+        let current = self.current();
+        if !current.is_dead() {
+            return Ok(current);
+        }
+        if Instant::now() < backoff.next_attempt {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "SOCKS5 UDP association is down",
+            ));
+        }
+
+        let attempt = tokio::time::timeout(REASSOCIATE_TIMEOUT, Association::open(&self.cfg))
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("timed out")));
+        match attempt {
+            Ok(fresh) => {
+                tracing::info!(proxy = %self.cfg.proxy, relay = %fresh.relay, "socks5: UDP association re-established");
+                *backoff = Backoff::default();
+                let fresh = Arc::new(fresh);
+                *self.current.lock().unwrap_or_else(std::sync::PoisonError::into_inner) = fresh.clone();
+                Ok(fresh)
             }
-            IpAddr::V6(_) => {
-                req.push(0x04);
-                req.extend_from_slice(&Ipv6Addr::UNSPECIFIED.octets());
-                req.extend_from_slice(&0u16.to_be_bytes());
+            Err(err) => {
+                tracing::warn!(
+                    err = %format!("{err:#}"),
+                    proxy = %self.cfg.proxy,
+                    retry_secs = backoff.delay.as_secs(),
+                    "socks5: UDP re-associate failed; retrying"
+                );
+                backoff.next_attempt = Instant::now() + backoff.delay;
+                backoff.delay = (backoff.delay * 2).min(REASSOCIATE_MAX);
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::NotConnected,
+                    format!("SOCKS5 UDP association is down: {err:#}"),
+                ))
             }
         }
+    }
+}
+
+impl Association {
+    async fn open(cfg: &Socks5Config) -> anyhow::Result<Self> {
+        let (mut tcp, proxy_addr) = cfg.connect_proxy().await?;
+        let keepalive = TcpKeepalive::new()
+            .with_time(CONTROL_KEEPALIVE_IDLE)
+            .with_interval(CONTROL_KEEPALIVE_INTERVAL)
+            .with_retries(CONTROL_KEEPALIVE_RETRIES);
+        if let Err(err) = SockRef::from(&tcp).set_tcp_keepalive(&keepalive) {
+            tracing::debug!(%err, "socks5: failed enabling TCP keepalive on control connection");
+        }
+        negotiate(&mut tcp, cfg).await?;
+
+        // UDP ASSOCIATE
+        // Send an "unspecified" address of our IP family; proxy returns relay address.
+        let mut req = Vec::with_capacity(32);
+        req.extend_from_slice(&[0x05, 0x03, 0x00]);
+        let unspecified: IpAddr = match proxy_addr.ip() {
+            IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        encode_addr(&mut req, SocketAddr::new(unspecified, 0));
         tcp.write_all(&req).await?;
 
-        let relay = read_socks5_reply_addr(&mut tcp)
+        let mut relay = read_socks5_reply_addr(&mut tcp)
             .await
             .context("SOCKS5: udp associate failed")?;
+        // Many proxies answer with an unspecified address, meaning "the address you
+        // connected to"; sending there would go to this machine instead.
+        if relay.ip().is_unspecified() {
+            relay.set_ip(proxy_addr.ip());
+        }
 
         let udp_bind = if relay.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
         let udp = UdpSocket::bind(udp_bind)
             .await
             .with_context(|| format!("bind UDP socket for SOCKS5 relay: {udp_bind}"))?;
 
+        let dead = Arc::new(watch::Sender::new(false));
+        let monitor = tokio::spawn(watch_control(tcp, relay, dead.clone()));
+
         Ok(Self {
             udp,
             relay,
-            _tcp: tcp,
+            dead,
+            monitor,
         })
     }
 
-    pub fn relay_addr(&self) -> SocketAddr {
-        self.relay
+    fn is_dead(&self) -> bool {
+        *self.dead.borrow()
     }
 
-    pub async fn send_to(&self, payload: &[u8], target: SocketAddr) -> std::io::Result<usize> {
-        let pkt = encode_udp_packet(target, payload);
-        // Return the payload size to make callers treat this like a normal UDP socket.
-        let _ = self.udp.send_to(&pkt, self.relay).await?;
-        Ok(payload.len())
+    fn mark_dead(&self) {
+        self.dead.send_replace(true);
     }
+}
 
-    pub async fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
-        let (n, _from_relay) = self.udp.recv_from(buf).await?;
-        let (src, payload_pos) = decode_udp_header(&buf[..n])
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+impl Drop for Association {
+    fn drop(&mut self) {
+        self.monitor.abort();
+    }
+}
 
-        if payload_pos > n {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "SOCKS5 UDP: invalid payload offset",
-            ));
+/// Waits for the proxy to close (or keepalive to break) the control connection, then marks
+/// the mapping dead. The proxy never sends anything after the ASSOCIATE reply.
+async fn watch_control(mut tcp: TcpStream, relay: SocketAddr, dead: Arc<watch::Sender<bool>>) {
+    let mut buf = [0u8; 64];
+    let reason = loop {
+        match tcp.read(&mut buf).await {
+            Ok(0) => break "closed by proxy".to_string(),
+            Ok(_) => continue,
+            Err(err) => break err.to_string(),
         }
+    };
+    if !dead.send_replace(true) {
+        tracing::warn!(%relay, %reason, "socks5: UDP association control connection lost");
+    }
+}
 
-        // Shift payload down so callers can treat this like a normal UDP socket.
-        buf.copy_within(payload_pos..n, 0);
-        Ok((n - payload_pos, src))
+/// Opens a TCP connection to `target` through the proxy (SOCKS5 CONNECT). IPv6 targets
+/// work over an IPv4 proxy connection too, if the proxy has IPv6 connectivity.
+///
+/// Once the proxy has connected, the returned stream carries the peer's bytes as-is.
+pub async fn connect_tcp(cfg: &Socks5Config, target: SocketAddr) -> anyhow::Result<TcpStream> {
+    let (mut tcp, _) = cfg.connect_proxy().await?;
+    negotiate(&mut tcp, cfg).await?;
+
+    let mut req = Vec::with_capacity(32);
+    req.extend_from_slice(&[0x05, 0x01, 0x00]);
+    encode_addr(&mut req, target);
+    tcp.write_all(&req).await?;
+
+    read_socks5_reply_addr(&mut tcp)
+        .await
+        .with_context(|| format!("SOCKS5: connect to {target} failed"))?;
+    Ok(tcp)
+}
+
+/// Greeting and (optional) username/password authentication.
+async fn negotiate(tcp: &mut TcpStream, cfg: &Socks5Config) -> anyhow::Result<()> {
+    let want_userpass = cfg.username.is_some() || cfg.password.is_some();
+    if want_userpass {
+        tcp.write_all(&[0x05, 0x02, 0x00, 0x02]).await?;
+    } else {
+        tcp.write_all(&[0x05, 0x01, 0x00]).await?;
+    }
+
+    let mut choice = [0u8; 2];
+    tcp.read_exact(&mut choice).await?;
+    if choice[0] != 0x05 {
+        anyhow::bail!("SOCKS5: invalid version in method select: {}", choice[0]);
+    }
+    match choice[1] {
+        0x00 => {}
+        0x02 => {
+            let u = cfg.username.clone().unwrap_or_default();
+            let p = cfg.password.clone().unwrap_or_default();
+            if u.len() > 255 || p.len() > 255 {
+                anyhow::bail!("SOCKS5: username/password too long");
+            }
+            let mut auth = Vec::with_capacity(3 + u.len() + p.len());
+            auth.push(0x01);
+            auth.push(u.len() as u8);
+            auth.extend_from_slice(u.as_bytes());
+            auth.push(p.len() as u8);
+            auth.extend_from_slice(p.as_bytes());
+            tcp.write_all(&auth).await?;
+
+            let mut resp = [0u8; 2];
+            tcp.read_exact(&mut resp).await?;
+            if resp[0] != 0x01 || resp[1] != 0x00 {
+                anyhow::bail!("SOCKS5: auth failed");
+            }
+        }
+        0xFF => anyhow::bail!("SOCKS5: no acceptable auth methods"),
+        other => anyhow::bail!("SOCKS5: unsupported auth method: {other}"),
     }
+    Ok(())
 }
 
 fn parse_proxy_string(input: &str) -> anyhow::Result<(String, Option<String>, Option<String>)> {
@@ -240,6 +483,9 @@ async fn read_socks5_reply_addr(stream: &mut TcpStream) -> anyhow::Result<Socket
         anyhow::bail!("SOCKS5: invalid reply version: {}", head[0]);
     }
     let rep = head[1];
+    if rep == 0x08 {
+        anyhow::bail!("SOCKS5: proxy does not support this address type (REP=0x8), e.g. IPv6");
+    }
     if rep != 0x00 {
         anyhow::bail!("SOCKS5: request failed (REP={rep:#x})");
     }
@@ -285,8 +531,14 @@ fn encode_udp_packet(target: SocketAddr, payload: &[u8]) -> Vec<u8> {
     // RSV(2) FRAG(1) ATYP(1) DST.ADDR DST.PORT DATA
     let mut out = Vec::with_capacity(64 + payload.len());
     out.extend_from_slice(&[0x00, 0x00, 0x00]);
+    encode_addr(&mut out, target);
+    out.extend_from_slice(payload);
+    out
+}
 
-    match target.ip() {
+/// ATYP DST.ADDR DST.PORT
+fn encode_addr(out: &mut Vec<u8>, addr: SocketAddr) {
+    match addr.ip() {
         IpAddr::V4(v4) => {
             out.push(0x01);
             out.extend_from_slice(&v4.octets());
@@ -296,9 +548,7 @@ fn encode_udp_packet(target: SocketAddr, payload: &[u8]) -> Vec<u8> {
             out.extend_from_slice(&v6.octets());
         }
     }
-    out.extend_from_slice(&target.port().to_be_bytes());
-    out.extend_from_slice(payload);
-    out
+    out.extend_from_slice(&addr.port().to_be_bytes());
 }
 
 fn decode_udp_header(pkt: &[u8]) -> Result<(SocketAddr, usize), &'static str> {
@@ -0,0 +1,412 @@
+use crate::ingest::{self, IngestItem, IngestSummary, Throttle};
+use crate::storage::{self, Discovery};
+use crate::AppState;
+use anyhow::Context;
+use quick_xml::events::{BytesStart, Event};
+use rbit::metainfo::{InfoHash, MagnetLink};
+use std::collections::HashSet;
+use std::time::Duration;
+
+// Remote ingestion sources: URLs listed in SERMA_SOURCES are fetched every
+// SERMA_SOURCES_EVERY_SECS and their hashes ingested like `serma ingest`.
+//
+// A source is either a plain magnet/hash list (one per line) or an RSS/Atom torrent
+// feed; the format is detected from the body. Feed items may carry a magnet link,
+// an info hash element (e.g. `torrent:infoHash`) or a `.torrent` link/enclosure,
+// which is downloaded and stored like an uploaded torrent file.
+//
+// Each source keeps its own "seen" set so items are only ingested the first time
+// they appear; conditional GETs (ETag / Last-Modified) skip unchanged feeds entirely.
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+/// Larger responses are rejected instead of being buffered.
+const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+const MAX_TORRENT_BYTES: usize = 10 * 1024 * 1024;
+/// `.torrent` downloads per source per fetch; the rest wait for the next round.
+const MAX_TORRENT_DOWNLOADS: usize = 50;
+/// Seen items are forgotten this long after they were first fetched.
+const SEEN_TTL_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+
+pub async fn run(state: AppState) {
+    if state.config.sources.is_empty() {
+        tracing::info!("sources: none configured (SERMA_SOURCES)");
+        return;
+    }
+
+    let client = match reqwest::Client::builder()
+        .user_agent(concat!("serma/", env!("CARGO_PKG_VERSION")))
+        .timeout(FETCH_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%err, "sources: failed creating HTTP client; disabled");
+            return;
+        }
+    };
+
+    tracing::info!(
+        count = state.config.sources.len(),
+        every_secs = state.config.sources_every_secs,
+        "sources: started"
+    );
+
+    let mut tick = tokio::time::interval(Duration::from_secs(state.config.sources_every_secs));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = state.shutdown.wait() => return,
+        }
+        for url in &state.config.sources {
+            if state.shutdown.is_triggered() {
+                return;
+            }
+            match fetch_source(&state, &client, url).await {
+                Ok(Some(summary)) => summary.log(url),
+                Ok(None) => tracing::debug!(source = %url, "sources: not modified"),
+                Err(err) => tracing::warn!(source = %url, err = %format!("{err:#}"), "sources: fetch failed"),
+            }
+        }
+    }
+}
+
+/// Fetches and ingests one source. Returns `None` if it hasn't changed since the last fetch.
+async fn fetch_source(state: &AppState, client: &reqwest::Client, url: &str) -> anyhow::Result<Option<IngestSummary>> {
+    let etag_key = format!("source_etag:{url}");
+    let modified_key = format!("source_last_modified:{url}");
+
+    let mut request = client.get(url);
+    if let Some(etag) = storage::get_meta(&state.db, &etag_key)? {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(modified) = storage::get_meta(&state.db, &modified_key)? {
+        request = request.header(reqwest::header::IF_MODIFIED_SINCE, modified);
+    }
+
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    let response = response.error_for_status()?;
+    let etag = response.headers().get(reqwest::header::ETAG).cloned();
+    let modified = response.headers().get(reqwest::header::LAST_MODIFIED).cloned();
+    let body = read_body(response, MAX_BODY_BYTES).await?;
+
+    let mut summary = IngestSummary::default();
+    let (items, torrent_urls) = if looks_like_xml(&body) {
+        parse_feed_items(&body, &mut summary)?
+    } else {
+        (parse_list_items(&body, &mut summary), Vec::new())
+    };
+
+    ingest_new_items(state, url, items, &mut summary).await?;
+    download_new_torrents(state, client, url, torrent_urls, &mut summary).await?;
+
+    // Remember validators only once the body was processed, so a failed run is retried in full.
+    match etag {
+        Some(v) => storage::set_meta(&state.db, &etag_key, v.as_bytes())?,
+        None => storage::remove_meta(&state.db, &etag_key)?,
+    }
+    match modified {
+        Some(v) => storage::set_meta(&state.db, &modified_key, v.as_bytes())?,
+        None => storage::remove_meta(&state.db, &modified_key)?,
+    }
+
+    let pruned = storage::prune_source_seen(&state.db, url, now_unix_ms() - SEEN_TTL_MS)?;
+    if pruned > 0 {
+        tracing::debug!(source = %url, pruned, "sources: forgot old items");
+    }
+    Ok(Some(summary))
+}
+
+async fn read_body(mut response: reqwest::Response, limit: usize) -> anyhow::Result<Vec<u8>> {
+    if response.content_length().is_some_and(|len| len > limit as u64) {
+        anyhow::bail!("response is larger than {limit} bytes");
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            anyhow::bail!("response is larger than {limit} bytes");
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+fn looks_like_xml(body: &[u8]) -> bool {
+    let body = body.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(body);
+    body.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'<')
+}
+
+fn parse_list_items(body: &[u8], summary: &mut IngestSummary) -> Vec<IngestItem> {
+    let mut items = Vec::new();
+    for (i, line) in String::from_utf8_lossy(body).lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        summary.lines += 1;
+        match ingest::parse_line(line) {
+            Ok(item) => items.push(item),
+            Err(reason) => summary.reject_invalid(i + 1, line, reason),
+        }
+    }
+    items
+}
+
+/// Splits feed items into hash items and `.torrent` URLs to download.
+fn parse_feed_items(body: &[u8], summary: &mut IngestSummary) -> anyhow::Result<(Vec<IngestItem>, Vec<String>)> {
+    let mut items = Vec::new();
+    let mut torrent_urls = Vec::new();
+
+    for (i, entry) in parse_feed(body)?.into_iter().enumerate() {
+        let line = i + 1;
+        let what = entry.title.as_deref().unwrap_or("(untitled)");
+
+        if let Some(magnet) = entry.magnet.as_deref() {
+            summary.lines += 1;
+            match ingest::parse_line(magnet) {
+                Ok(mut item) => {
+                    if let Some(m) = item.magnet.as_mut()
+                        && m.display_name.is_none()
+                    {
+                        m.display_name = entry.title.clone();
+                    }
+                    items.push(item);
+                }
+                Err(reason) => summary.reject_invalid(line, what, reason),
+            }
+        } else if let Some(hash) = entry.info_hash.as_deref() {
+            summary.lines += 1;
+            match InfoHash::from_hex(hash).ok() {
+                Some(info_hash) => items.push(IngestItem {
+                    info_hash_hex: ingest::swarm_hash_hex(&info_hash),
+                    magnet: Some(MagnetLink {
+                        info_hash,
+                        display_name: entry.title.clone(),
+                        trackers: Vec::new(),
+                        web_seeds: Vec::new(),
+                        peer_addresses: Vec::new(),
+                    }),
+                }),
+                None => summary.reject_invalid(line, what, format!("invalid info hash {hash:?}")),
+            }
+        } else if let Some(url) = entry.torrent_url {
+            // Counted when downloaded.
+            torrent_urls.push(url);
+        } else {
+            summary.lines += 1;
+            summary.reject_invalid(line, what, "feed item has no magnet link, info hash or .torrent link");
+        }
+    }
+    Ok((items, torrent_urls))
+}
+
+async fn ingest_new_items(
+    state: &AppState,
+    source: &str,
+    items: Vec<IngestItem>,
+    summary: &mut IngestSummary,
+) -> anyhow::Result<()> {
+    let keys: Vec<String> = items.iter().map(|item| item.info_hash_hex.clone()).collect();
+    let seen = storage::source_seen(&state.db, source, &keys)?;
+
+    let mut unique = HashSet::new();
+    let mut fresh = Vec::new();
+    for (item, seen) in items.into_iter().zip(seen) {
+        if seen || !unique.insert(item.info_hash_hex.clone()) {
+            summary.existing += 1;
+        } else {
+            fresh.push(item);
+        }
+    }
+
+    let mut throttle = Throttle::new(state.config.ingest_max_per_sec);
+    let batch_size = throttle.batch_size();
+    for batch in fresh.chunks(batch_size) {
+        ingest::ingest_items(state, batch, Discovery::Feed, summary)?;
+        let keys: Vec<String> = batch.iter().map(|item| item.info_hash_hex.clone()).collect();
+        storage::mark_source_seen(&state.db, source, &keys)?;
+        throttle.wait(batch.len()).await;
+    }
+    Ok(())
+}
+
+async fn download_new_torrents(
+    state: &AppState,
+    client: &reqwest::Client,
+    source: &str,
+    urls: Vec<String>,
+    summary: &mut IngestSummary,
+) -> anyhow::Result<()> {
+    let seen = storage::source_seen(&state.db, source, &urls)?;
+    let mut downloaded = 0;
+    for (url, seen) in urls.into_iter().zip(seen) {
+        if downloaded >= MAX_TORRENT_DOWNLOADS && !seen {
+            // Not marked as seen, so it's picked up by a later fetch.
+            continue;
+        }
+        summary.lines += 1;
+        if seen {
+            summary.existing += 1;
+            continue;
+        }
+        downloaded += 1;
+
+        let result = async {
+            let response = client.get(&url).send().await?.error_for_status()?;
+            let bytes = read_body(response, MAX_TORRENT_BYTES).await?;
+            ingest::ingest_torrent_bytes(state, &bytes, Discovery::Feed)
+        }
+        .await;
+        match result {
+            Ok((_, true)) => summary.accepted += 1,
+            Ok((_, false)) => summary.existing += 1,
+            // Refused torrents stay refused; no point downloading them again.
+            Err(err) if err.is::<ingest::Refused>() => summary.reject_error(0, &url, &err),
+            Err(err) => {
+                // Left unmarked: transient download errors are retried next round.
+                summary.reject_invalid(0, &url, format!("{err:#}"));
+                continue;
+            }
+        }
+        storage::mark_source_seen(&state.db, source, std::slice::from_ref(&url))?;
+    }
+    if downloaded > 0 {
+        state.index.commit()?;
+    }
+    Ok(())
+}
+
+/// What a feed item points to. Only the first value of each kind is kept.
+#[derive(Debug, Default)]
+struct FeedEntry {
+    title: Option<String>,
+    magnet: Option<String>,
+    info_hash: Option<String>,
+    torrent_url: Option<String>,
+}
+
+impl FeedEntry {
+    fn text(&mut self, element: &str, text: &str) {
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        if is_magnet(text) {
+            self.magnet.get_or_insert_with(|| text.to_string());
+        } else if element == "title" {
+            self.title.get_or_insert_with(|| text.to_string());
+        } else if element.contains("infohash") {
+            self.info_hash.get_or_insert_with(|| text.to_string());
+        } else if matches!(element, "link" | "guid") && is_torrent_url(text) {
+            self.torrent_url.get_or_insert_with(|| text.to_string());
+        }
+    }
+
+    fn attributes(&mut self, element: &str, e: &BytesStart<'_>) {
+        let mut url = None;
+        let mut is_torrent_type = false;
+        for attr in e.attributes().flatten() {
+            let name = local_name(attr.key.local_name().as_ref());
+            let Ok(value) = attr.unescape_value() else {
+                continue;
+            };
+            match name.as_str() {
+                "url" | "href" => url = Some(value.trim().to_string()),
+                "type" => is_torrent_type = value.trim().eq_ignore_ascii_case("application/x-bittorrent"),
+                _ => {}
+            }
+        }
+
+        let Some(url) = url else {
+            return;
+        };
+        if is_magnet(&url) {
+            self.magnet.get_or_insert(url);
+        } else if matches!(element, "enclosure" | "link") && (is_torrent_type || is_torrent_url(&url)) {
+            self.torrent_url.get_or_insert(url);
+        }
+    }
+}
+
+/// Parses RSS `<item>`s or Atom `<entry>`s, ignoring namespaces.
+fn parse_feed(body: &[u8]) -> anyhow::Result<Vec<FeedEntry>> {
+    let mut reader = quick_xml::Reader::from_reader(body);
+    reader.config_mut().trim_text(true);
+
+    let mut entries = Vec::new();
+    let mut current: Option<FeedEntry> = None;
+    // Innermost open element inside the current item.
+    let mut element = String::new();
+    let mut buf = Vec::new();
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .with_context(|| format!("parse feed at byte {}", reader.buffer_position()))?;
+        match event {
+            Event::Start(e) => {
+                let name = local_name(e.local_name().as_ref());
+                if name == "item" || name == "entry" {
+                    current = Some(FeedEntry::default());
+                } else if let Some(entry) = current.as_mut() {
+                    entry.attributes(&name, &e);
+                }
+                element = name;
+            }
+            Event::Empty(e) => {
+                if let Some(entry) = current.as_mut() {
+                    entry.attributes(&local_name(e.local_name().as_ref()), &e);
+                }
+            }
+            Event::Text(t) => {
+                if let Some(entry) = current.as_mut()
+                    && let Ok(text) = t.unescape()
+                {
+                    entry.text(&element, &text);
+                }
+            }
+            Event::CData(c) => {
+                if let Some(entry) = current.as_mut() {
+                    entry.text(&element, &String::from_utf8_lossy(&c));
+                }
+            }
+            Event::End(e) => {
+                let name = local_name(e.local_name().as_ref());
+                if (name == "item" || name == "entry")
+                    && let Some(entry) = current.take()
+                {
+                    entries.push(entry);
+                }
+                element.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(entries)
+}
+
+fn local_name(name: &[u8]) -> String {
+    String::from_utf8_lossy(name).to_ascii_lowercase()
+}
+
+fn is_magnet(s: &str) -> bool {
+    s.get(..8).is_some_and(|p| p.eq_ignore_ascii_case("magnet:?")) && s.len() > 8
+}
+
+fn is_torrent_url(s: &str) -> bool {
+    (s.starts_with("http://") || s.starts_with("https://"))
+        && s.split(['?', '#'])
+            .next()
+            .is_some_and(|path| path.to_ascii_lowercase().ends_with(".torrent"))
+}
+
+fn now_unix_ms() -> i64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_millis() as i64
+}
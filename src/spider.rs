@@ -1,17 +1,66 @@
-use crate::{config::Config, storage, AppState};
-use std::collections::{HashSet, VecDeque};
+use crate::events::Event;
+use crate::storage::Discovery;
+use crate::node_guard::{NodeGuard, Verdict};
+use crate::peer_store::PeerStore;
+use crate::popularity::AnnounceCounter;
+use crate::routing::{self, RoutingTable};
+use crate::sweep::KeyspaceSweep;
+use crate::throttle::{self, Throttle};
+use crate::{config::Config, control::Subsystem, storage, AppState};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use std::time::Instant;
+use tracing::Instrument;
 use tokio::net::UdpSocket;
 use tokio::time::{Duration, interval};
 
 use crate::socks5::{Socks5Config, Socks5UdpAssociate};
+use crate::stats::{DiscoveryThrottle, Socks5Status};
 
 // Minimal BEP-5 DHT “spider”:
 // - Joins the DHT via bootstrap nodes (find_node)
-// - Responds to incoming queries so other nodes keep us in their routing tables
-// - Harvests info_hash from announce_peer / get_peers queries
-// - Learns more nodes from responses (“nodes” compact format)
+// - Responds to incoming queries so other nodes keep us in their routing tables, and
+//   stores the peers announced to us so our get_peers answers include them (see
+//   peer_store.rs)
+// - Rate limits inbound traffic per sender and bans nodes that flood us or send garbage for
+//   a while (see node_guard.rs), so one abusive node can't saturate the receive loop
+// - Harvests info_hash from announce_peer / get_peers queries, and counts token-validated
+//   announces as a popularity signal (see popularity.rs); the hashes announced most every
+//   few minutes jump the enrich queue
+// - Samples hashes with BEP-51 sample_infohashes, sweeping the keyspace region by region
+//   and asking each node again only once its `interval` has passed (see sweep.rs)
+// - Learns more nodes from responses (“nodes” compact format) into a k-bucket routing
+//   table (see routing.rs), saved under the data dir across restarts
+// - SERMA_SPIDER_NODES runs several node identities, each on its own UDP port and in its
+//   own region of the keyspace, so more of the network's announce traffic reaches us
+// - With SERMA_SOCKS5_PROXY, all DHT traffic goes through a SOCKS5 UDP association. The
+//   spider never falls back to direct traffic: it retries the proxy with backoff, the
+//   association re-establishes itself after proxy restarts, and the spider forces a new
+//   one when the relay goes silent (mapping expired without the proxy saying so)
+// - Slows its own discovery when the enrich queue or the database grows faster than they
+//   are worked off (see throttle.rs)
+
+/// Longest wait between attempts to set up the SOCKS5 UDP association.
+/// With the spider querying nodes every few seconds, this long without a single datagram
+/// means the SOCKS5 relay mapping is gone.
+const SOCKS_SILENCE_RECONNECT: Duration = Duration::from_secs(120);
+
+/// The spider as a value, for embedders: discovered hashes are stored and queued for
+/// metadata, which an `Enricher` on the same state then fetches.
+pub struct Spider {
+    state: AppState,
+}
+
+impl Spider {
+    pub fn new(state: &AppState) -> Self {
+        Self { state: state.clone() }
+    }
+
+    /// Runs until `state.shutdown` is triggered; returns at once if SERMA_SPIDER is off.
+    pub async fn run(self) {
+        run(self.state).await
+    }
+}
 
 pub async fn run(state: AppState) {
     // Allow disabling the spider entirely.
@@ -20,30 +69,96 @@ pub async fn run(state: AppState) {
         return;
     }
 
-    let sockets = match Socks5Config::from_env() {
+    let socks = match Socks5Config::from_env() {
         Some(Ok(cfg)) => {
-            if std::env::var("SERMA_SPIDER_BIND").ok().is_some_and(|v| !v.trim().is_empty()) {
+            if state.config.spider_bind != "0.0.0.0:0" {
                 tracing::info!("spider: SERMA_SPIDER_BIND ignored when SERMA_SOCKS5_PROXY is set");
             }
-
-            match Socks5UdpAssociate::connect(&cfg).await {
-                Ok(sock) => {
-                    tracing::info!(proxy=%cfg.proxy, relay=%sock.relay_addr(), "spider: using SOCKS5 UDP proxy");
-                    DhtSockets::Socks { sock }
-                }
-                Err(err) => {
-                    // Fail closed: if the user asked for proxying, don't silently fall back to direct.
-                    tracing::warn!(%err, proxy=%cfg.proxy, "spider: failed to set up SOCKS5 proxy; spider disabled");
-                    return;
-                }
-            }
+            Some(cfg)
         }
         Some(Err(err)) => {
             tracing::warn!(%err, "spider: invalid SERMA_SOCKS5_PROXY; spider disabled");
             return;
         }
+        None => None,
+    };
+
+    // One filter for all nodes, so a hash announced to several of them is ingested once.
+    let seen_hashes = Arc::new(Mutex::new(RollingBloom::new(
+        state.config.spider_seen_bits_pow2,
+        state.config.spider_seen_k,
+        Duration::from_secs(state.config.spider_seen_rotate_every_secs),
+    )));
+    let announces = Arc::new(Mutex::new(AnnounceCounter::new(state.config.spider_hot_hashes)));
+    // Also shared: a peer announced to one node is returned by all of them.
+    let peers = Arc::new(Mutex::new(PeerStore::new(state.config.spider_peer_store_hashes)));
+    let guard = Arc::new(Mutex::new(NodeGuard::new(
+        state.config.spider_ip_rate,
+        Duration::from_secs(state.config.spider_ban_secs),
+    )));
+
+    // One discovery rate for all nodes, adjusted by `tune`.
+    let throttle = Arc::new(Mutex::new(Throttle::new(
+        state.config.spider_queue_target,
+        state.config.spider_max_disk_growth_mb << 20,
+    )));
+    if state.config.spider_queue_target > 0 || state.config.spider_max_disk_growth_mb > 0 {
+        tokio::spawn(tune(state.clone(), throttle.clone()));
+    }
+
+    let nodes = state.config.spider_nodes;
+    if nodes > 1 {
+        tracing::info!(nodes, "spider: running virtual node identities");
+    }
+    let shared = Shared {
+        seen_hashes,
+        announces,
+        peers,
+        guard,
+        throttle,
+    };
+    let tasks = (0..nodes).map(|index| {
+        let node = run_node(state.clone(), index, socks.clone(), shared.clone());
+        if nodes > 1 {
+            tokio::spawn(node.instrument(tracing::info_span!("node", index)))
+        } else {
+            tokio::spawn(node)
+        }
+    });
+    futures_util::future::join_all(tasks).await;
+}
+
+/// What the node identities share.
+#[derive(Clone)]
+struct Shared {
+    seen_hashes: Arc<Mutex<RollingBloom>>,
+    announces: Arc<Mutex<AnnounceCounter>>,
+    peers: Arc<Mutex<PeerStore>>,
+    guard: Arc<Mutex<NodeGuard>>,
+    throttle: Arc<Mutex<Throttle>>,
+}
+
+/// One DHT node identity: its own socket, node id and routing table.
+async fn run_node(state: AppState, index: usize, socks: Option<Socks5Config>, shared: Shared) {
+    let Shared {
+        seen_hashes,
+        announces,
+        peers,
+        guard,
+        throttle,
+    } = shared;
+    let nodes = state.config.spider_nodes;
+    let sockets = match socks {
+        Some(cfg) => {
+            // Fail closed: if the user asked for proxying, never fall back to direct.
+            let Some(sock) = Socks5UdpAssociate::connect_with_backoff(&cfg, &state.shutdown, "spider").await else {
+                return;
+            };
+            tracing::info!(proxy=%cfg.proxy, relay=%sock.relay_addr(), "spider: using SOCKS5 UDP proxy");
+            DhtSockets::Socks { sock, cfg }
+        }
         None => {
-            let bind = state.config.spider_bind.clone();
+            let bind = node_bind(&state.config.spider_bind, index);
 
             // Use separate IPv4 + (optional) IPv6 UDP sockets so we can talk to both
             // families regardless of OS IPv6 dual-stack settings.
@@ -97,166 +212,361 @@ pub async fn run(state: AppState) {
         }
     };
 
-    // DHT node id: 20 random-ish bytes. We reuse rbit’s peer-id generator (also 20 bytes).
-    let node_id = *rbit::peer::PeerId::generate().as_bytes();
+    // Reuse the saved table (and node id) if there is one, so restarts skip most of the
+    // bootstrap and other nodes still find us under the same id.
+    let table_path = state.data_dir.join(table_file_name(index));
+    let region = keyspace_region(index, nodes);
+    let mut table = match RoutingTable::load(&table_path, state.config.spider_max_known_nodes) {
+        Ok(table) if region.contains(&(table.own_id()[0] as usize)) => {
+            tracing::info!(nodes = table.len(), "spider: loaded routing table");
+            table
+        }
+        loaded => {
+            match loaded {
+                Ok(_) => tracing::info!("spider: saved node id is outside this node's keyspace region; starting fresh"),
+                Err(err) if table_path.exists() => {
+                    tracing::warn!(%err, "spider: failed loading routing table; starting fresh")
+                }
+                Err(_) => {}
+            }
+            RoutingTable::new(node_id_in_region(index, nodes), state.config.spider_max_known_nodes)
+        }
+    };
+    let node_id = *table.own_id();
 
     match &sockets {
         DhtSockets::Direct { socket_v4, socket_v6 } => {
-            if let Some(sock) = socket_v4.as_ref() {
-                if let Ok(a) = sock.local_addr() {
-                    tracing::info!(bind=%a, "spider: listening (ipv4)");
-                }
+            if let Some(sock) = socket_v4.as_ref()
+                && let Ok(a) = sock.local_addr()
+            {
+                tracing::info!(bind=%a, "spider: listening (ipv4)");
             }
-            if let Some(sock) = socket_v6.as_ref() {
-                if let Ok(a) = sock.local_addr() {
-                    tracing::info!(bind=%a, "spider: listening (ipv6)");
-                }
+            if let Some(sock) = socket_v6.as_ref()
+                && let Ok(a) = sock.local_addr()
+            {
+                tracing::info!(bind=%a, "spider: listening (ipv6)");
             }
         }
-        DhtSockets::Socks { sock } => {
+        DhtSockets::Socks { sock, .. } => {
             tracing::info!(relay=%sock.relay_addr(), "spider: listening (via socks5 relay)");
         }
     }
 
-    let mut known_nodes: VecDeque<SocketAddr> = VecDeque::new();
-    let mut known_set: HashSet<SocketAddr> = HashSet::new();
-
-    let mut seen_hashes = RollingBloom::new(
-        state.config.spider_seen_bits_pow2,
-        state.config.spider_seen_k,
-        Duration::from_secs(state.config.spider_seen_rotate_every_secs),
-    );
-
     // Bootstrap right away.
-    for addr in resolve_bootstrap(&state.config).await {
-        push_node(
-            addr,
-            &mut known_nodes,
-            &mut known_set,
-            state.config.spider_max_known_nodes,
-        );
-    }
-    bootstrap_tick(&sockets, &node_id, &mut known_nodes).await;
+    bootstrap_tick(&sockets, &node_id, &mut table, &state.config).await;
 
     // Actively sample info-hashes from the network (BEP-51) so we still discover
     // content even when we're behind NAT and not receiving unsolicited queries.
-    sample_tick(&sockets, &node_id, &mut known_nodes, state.config.spider_sample_per_tick).await;
+    let mut sweep = KeyspaceSweep::default();
+    sample_tick(&sockets, &node_id, &mut table, &mut sweep, state.config.spider_sample_per_tick).await;
 
     let mut boot_int = interval(Duration::from_secs(state.config.spider_bootstrap_every_secs.max(1)));
     let mut gc_int = interval(Duration::from_secs(state.config.spider_gc_every_secs.max(1)));
     let mut sample_int = interval(Duration::from_secs(state.config.spider_sample_every_secs.max(1)));
+    let mut last_save = Instant::now();
+    let mut last_recv = Instant::now();
+    let mut tokens = AnnounceTokens::new();
 
     let mut buf4 = vec![0u8; 4096];
     let mut buf6 = vec![0u8; 4096];
     loop {
         tokio::select! {
+            _ = state.shutdown.wait() => {
+                match table.save(&table_path) {
+                    Ok(()) => tracing::info!(nodes = table.len(), "spider: stopped; saved routing table"),
+                    Err(err) => tracing::warn!(%err, "spider: stopped; failed saving routing table"),
+                }
+                return;
+            }
             _ = boot_int.tick() => {
-                bootstrap_tick(&sockets, &node_id, &mut known_nodes).await;
+                bootstrap_tick(&sockets, &node_id, &mut table, &state.config).await;
             }
             _ = sample_int.tick() => {
-                sample_tick(&sockets, &node_id, &mut known_nodes, state.config.spider_sample_per_tick).await;
+                if state.control.is_paused(Subsystem::Spider) {
+                    continue;
+                }
+                let samples = lock(&throttle).samples(state.config.spider_sample_per_tick);
+                sample_tick(&sockets, &node_id, &mut table, &mut sweep, samples).await;
             }
             _ = gc_int.tick() => {
                 // Keep the rolling Bloom filter fresh.
-                seen_hashes.maybe_rotate();
-                if known_nodes.len() > state.config.spider_max_known_nodes {
-                    while known_nodes.len() > state.config.spider_max_known_nodes {
-                        if let Some(old) = known_nodes.pop_front() {
-                            known_set.remove(&old);
-                        } else {
-                            break;
-                        }
+                lock(&seen_hashes).maybe_rotate();
+                table.maintain();
+                if last_save.elapsed() >= TABLE_SAVE_EVERY {
+                    last_save = Instant::now();
+                    match table.save(&table_path) {
+                        Ok(()) => tracing::debug!(nodes = table.len(), live = table.live_len(), "spider: saved routing table"),
+                        Err(err) => tracing::warn!(%err, "spider: failed saving routing table"),
                     }
                 }
+                // The first node stores announce counts, hands trending hashes to enrich,
+                // expires announced peers and forgets calm senders for all of them.
+                if index == 0 {
+                    store_announces(&state, &announces);
+                    prioritize_hot(&state, &announces);
+                    let mut peers = lock(&peers);
+                    peers.prune(Instant::now());
+                    let (hashes, stored) = peers.counts();
+                    let mut guard = lock(&guard);
+                    guard.prune(Instant::now());
+                    let banned = guard.banned(Instant::now());
+                    tracing::debug!(hashes, peers = stored, banned, "spider: announced peers and banned nodes");
+                }
+                // The first node reports the proxy state for the admin dashboard.
+                if let (0, DhtSockets::Socks { sock, cfg }) = (index, &sockets) {
+                    state.stats.set_socks5(if sock.is_alive() {
+                        Socks5Status::associated(&cfg.proxy, sock.relay_addr())
+                    } else {
+                        Socks5Status::failed(&cfg.proxy, "UDP association is down; re-associating".to_string())
+                    });
+                }
+                // The association re-establishes itself when the proxy drops the control
+                // connection; this catches relays that stop forwarding without closing it.
+                if let DhtSockets::Socks { sock, .. } = &sockets
+                    && last_recv.elapsed() >= SOCKS_SILENCE_RECONNECT
+                {
+                    tracing::warn!(
+                        relay = %sock.relay_addr(),
+                        silent_secs = last_recv.elapsed().as_secs(),
+                        "spider: SOCKS5 relay went silent; re-associating"
+                    );
+                    sock.mark_dead();
+                    last_recv = Instant::now();
+                    bootstrap_tick(&sockets, &node_id, &mut table, &state.config).await;
+                }
             }
             recv = recv_from_any(&sockets, &mut buf4, &mut buf6) => {
                 let Some((n, from, fam)) = recv else {
                     continue;
                 };
+                last_recv = Instant::now();
                 if n == 0 {
                     continue;
                 }
+                // Dropped before any parsing, so floods stay cheap.
+                if lock(&guard).admit(from.ip(), last_recv) != Verdict::Accept {
+                    continue;
+                }
+
+                // While paused we keep answering queries (so other nodes keep us in their
+                // routing tables) but stop harvesting hashes.
+                let harvesting = !state.control.is_paused(Subsystem::Spider);
 
                 let raw = if fam == 4 { &buf4[..n] } else { &buf6[..n] };
-                if let Some(msg) = KrpcMessage::decode(raw) {
+                let msg = KrpcMessage::decode(raw).filter(KrpcMessage::is_well_formed);
+                if msg.is_none() {
+                    lock(&guard).malformed(from.ip(), last_recv);
+                }
+                if let Some(msg) = msg {
+                    // Any node that tells us its id is a routing table entry; only answers
+                    // to our queries make it live.
+                    if let Some(id) = msg.sender_id()
+                        && is_usable_node(from)
+                        && msg.is_response()
+                    {
+                        // Peer lookups start from the same nodes.
+                        state.dht.add_node(id, from);
+                    }
+                    if let Some(id) = msg.sender_id()
+                        && is_usable_node(from)
+                        && let Some(stale) = table.heard_from(id, from, msg.is_response())
+                    {
+                        // A full bucket: check whether its stalest node is still there.
+                        table.note_queried(&stale);
+                        send_to_family(&sockets, &make_ping(next_txid(), &node_id), stale).await;
+                    }
+
                     // Learn nodes from responses.
                     if let Some(nodes) = msg.compact_nodes() {
-                        for addr in parse_compact_nodes(nodes) {
-                            push_node(
-                                addr,
-                                &mut known_nodes,
-                                &mut known_set,
-                                state.config.spider_max_known_nodes,
-                            );
+                        for (id, addr) in parse_compact_nodes(nodes) {
+                            if is_usable_node(addr) {
+                                table.add_candidate(id, addr);
+                            }
                         }
                     }
                     if let Some(nodes6) = msg.compact_nodes_v6() {
-                        for addr in parse_compact_nodes_v6(nodes6) {
-                            push_node(
-                                addr,
-                                &mut known_nodes,
-                                &mut known_set,
-                                state.config.spider_max_known_nodes,
-                            );
+                        for (id, addr) in parse_compact_nodes_v6(nodes6) {
+                            if is_usable_node(addr) {
+                                table.add_candidate(id, addr);
+                            }
                         }
                     }
 
                     // Active discovery: harvest info_hash from BEP-51 sample_infohashes responses.
+                    // The node says how long until its samples change (`interval`) and how
+                    // many hashes it has (`num`); one with none is left alone for longer.
                     if let Some(samples) = msg.samples_from_response() {
+                        let (interval, num) = msg.sample_schedule();
+                        let wait = match (interval, num) {
+                            (_, Some(0)) => SAMPLE_EMPTY_WAIT,
+                            (interval, _) => interval.unwrap_or(SAMPLE_DEFAULT_WAIT),
+                        };
+                        table.note_sampled(&from, wait.min(SAMPLE_MAX_WAIT));
+                        if let Some(id) = msg.sender_id() {
+                            sweep.record(&id, samples.len() / 20);
+                        }
+                    }
+                    if harvesting && let Some(samples) = msg.samples_from_response() {
                         for chunk in samples
                             .chunks_exact(20)
                             .take(state.config.spider_max_samples_per_msg)
                         {
                             let mut info_hash = [0u8; 20];
                             info_hash.copy_from_slice(chunk);
-                            if should_accept_hash(&mut seen_hashes, info_hash) {
+                            if should_accept_hash(&mut lock(&seen_hashes), info_hash) {
                                 let info_hex = hex::encode(info_hash);
-                                if let Err(err) = ingest_spidered_hash(&state, &info_hex) {
-                                    tracing::debug!(%err, hash=%info_hex, "spider: ingest failed");
-                                } else {
-                                    tracing::info!(hash=%info_hex, "spider: sampled");
+                                match ingest_spidered_hash(&state, &info_hex, Discovery::Sample) {
+                                    Ok(true) => {
+                                        state.stats.record_discovery();
+                                        state.events.publish(Event::Discovered { info_hash: info_hex.clone() });
+                                        tracing::info!(hash=%info_hex, "spider: sampled");
+                                    }
+                                    Ok(false) => {}
+                                    Err(err) => tracing::debug!(%err, hash=%info_hex, "spider: ingest failed"),
                                 }
                             }
                         }
                     }
 
-                    // Harvest info_hash from incoming queries.
-                    if let Some(info_hash) = msg.info_hash_from_query() {
-                        if should_accept_hash(&mut seen_hashes, info_hash) {
-                            let info_hex = hex::encode(info_hash);
-
-                            // Store + index.
-                            if let Err(err) = ingest_spidered_hash(&state, &info_hex) {
-                                tracing::debug!(%err, hash=%info_hex, "spider: ingest failed");
-                            } else {
+                    // Harvest info_hash from incoming queries; only the first in a while is
+                    // ingested, and only as many as the throttle lets through. Popularity
+                    // counts only announces carrying a token we handed to that address, so
+                    // one node can't inflate a hash with a stream of (spoofable) get_peers.
+                    if harvesting && let Some(info_hash) = msg.announced_hash(&mut tokens, from) {
+                        lock(&announces).record(info_hash);
+                    }
+                    if harvesting
+                        && let Some(info_hash) = msg.info_hash_from_query()
+                        && lock(&throttle).admit()
+                        && should_accept_hash(&mut lock(&seen_hashes), info_hash)
+                    {
+                        let info_hex = hex::encode(info_hash);
+
+                        // Store + index.
+                        match ingest_spidered_hash(&state, &info_hex, Discovery::Announce) {
+                            Ok(true) => {
+                                state.stats.record_discovery();
+                                state.events.publish(Event::Discovered { info_hash: info_hex.clone() });
                                 tracing::info!(hash=%info_hex, "spider: discovered");
                             }
+                            Ok(false) => {}
+                            Err(err) => tracing::debug!(%err, hash=%info_hex, "spider: ingest failed"),
                         }
                     }
 
-                    // Respond to queries so we remain a "good" node.
+                    // Answer queries properly (closest nodes, announce tokens) so other nodes
+                    // keep us in their routing tables and keep sending us traffic.
                     if msg.is_query() {
-                        if let Some(resp) = msg.make_minimal_response(&node_id) {
-                            send_to_family(&sockets, &resp, from)
-                                .await;
+                        let resp = msg.make_query_response(&table, &mut tokens, &mut lock(&peers), from);
+                        if let Some(resp) = resp {
+                            send_to_family(&sockets, &resp, from).await;
                         }
                     }
+                }
+            }
+        }
+    }
+}
 
-                    // If we get a query from this node, keep it as known.
-                    if msg.is_query() {
-                        push_node(
-                            from,
-                            &mut known_nodes,
-                            &mut known_set,
-                            state.config.spider_max_known_nodes,
-                        );
-                    }
+/// Reads the enrich queue length and database size every `throttle::CHECK_EVERY` and
+/// adjusts the discovery rate to them.
+async fn tune(state: AppState, throttle: Arc<Mutex<Throttle>>) {
+    while !state.shutdown.is_triggered() {
+        let db = state.db.clone();
+        let reading = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+            Ok((storage::enrich_queue_len(&db)? as u64, db.size_on_disk()?))
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+        match reading {
+            Ok((queued, disk_bytes)) => {
+                let (before, rate, growth) = {
+                    let mut throttle = lock(&throttle);
+                    let before = throttle.rate();
+                    let growth = throttle.update(queued, disk_bytes, Instant::now());
+                    (before, throttle.rate(), growth)
+                };
+                if rate < 1.0 && before >= 1.0 {
+                    tracing::info!(queued, growth_per_min = growth, rate, "spider: enrichment is falling behind; slowing discovery");
+                } else if rate >= 1.0 && before < 1.0 {
+                    tracing::info!(queued, "spider: caught up; discovering at full rate");
+                } else {
+                    tracing::debug!(queued, growth_per_min = growth, rate, "spider: discovery rate");
                 }
+                state.stats.set_discovery_throttle(DiscoveryThrottle::new(rate, queued, growth));
             }
+            Err(err) => tracing::warn!(%err, "spider: failed reading enrich queue and database size"),
         }
+        state.shutdown.sleep(throttle::CHECK_EVERY).await;
     }
 }
 
+/// How often the routing table is written to disk.
+const TABLE_SAVE_EVERY: Duration = Duration::from_secs(5 * 60);
+/// Nodes returned in find_node / get_peers responses (BEP-5's K).
+const RESPONSE_NODES: usize = 8;
+/// Peers returned in a get_peers response; with 8 IPv6 nodes that still fits in a
+/// 1280-byte datagram.
+const RESPONSE_PEERS: usize = 32;
+/// Wait before sampling a node again when it doesn't send an `interval`.
+const SAMPLE_DEFAULT_WAIT: Duration = Duration::from_secs(5 * 60);
+/// Nodes holding no hashes at all are asked again this much later.
+const SAMPLE_EMPTY_WAIT: Duration = Duration::from_secs(3600);
+/// BEP-51 caps `interval` at six hours.
+const SAMPLE_MAX_WAIT: Duration = Duration::from_secs(6 * 3600);
+/// Announce token secrets rotate this often; tokens from the previous secret stay valid.
+const TOKEN_ROTATE_EVERY: Duration = Duration::from_secs(5 * 60);
+
+/// get_peers tokens (BEP-5): a hash of the querier's IP with a rotating secret, so only
+/// nodes that recently asked us can announce, without storing per-node state.
+struct AnnounceTokens {
+    current: u64,
+    previous: u64,
+    rotated_at: Instant,
+}
+
+impl AnnounceTokens {
+    fn new() -> Self {
+        Self {
+            current: random_secret(),
+            previous: random_secret(),
+            rotated_at: Instant::now(),
+        }
+    }
+
+    fn maybe_rotate(&mut self) {
+        if self.rotated_at.elapsed() >= TOKEN_ROTATE_EVERY {
+            self.previous = std::mem::replace(&mut self.current, random_secret());
+            self.rotated_at = Instant::now();
+        }
+    }
+
+    fn token_for(&mut self, ip: IpAddr) -> [u8; 8] {
+        self.maybe_rotate();
+        token_with_secret(ip, self.current)
+    }
+
+    fn is_valid(&mut self, ip: IpAddr, token: &[u8]) -> bool {
+        self.maybe_rotate();
+        token == token_with_secret(ip, self.current) || token == token_with_secret(ip, self.previous)
+    }
+}
+
+fn token_with_secret(ip: IpAddr, secret: u64) -> [u8; 8] {
+    let hash = match ip {
+        IpAddr::V4(v4) => xxhash_rust::xxh3::xxh3_64_with_seed(&v4.octets(), secret),
+        IpAddr::V6(v6) => xxhash_rust::xxh3::xxh3_64_with_seed(&v6.octets(), secret),
+    };
+    hash.to_be_bytes()
+}
+
+fn random_secret() -> u64 {
+    // rbit's peer-id generator is our source of randomness (see node id).
+    let bytes = rbit::peer::PeerId::generate();
+    xxhash_rust::xxh3::xxh3_64(bytes.as_bytes())
+}
+
 enum DhtSockets {
     Direct {
         socket_v4: Option<UdpSocket>,
@@ -264,12 +574,103 @@ enum DhtSockets {
     },
     Socks {
         sock: Socks5UdpAssociate,
+        cfg: Socks5Config,
     },
 }
 
-fn ingest_spidered_hash(state: &AppState, info_hash_hex: &str) -> anyhow::Result<()> {
+/// Node `index` binds `port + index` when a fixed port is configured; ephemeral ports
+/// stay ephemeral.
+fn node_bind(bind: &str, index: usize) -> String {
+    match bind.parse::<SocketAddr>() {
+        Ok(mut addr) if index > 0 && addr.port() != 0 => {
+            addr.set_port(addr.port().saturating_add(index as u16));
+            addr.to_string()
+        }
+        _ => bind.to_string(),
+    }
+}
+
+/// The first node keeps the original file name, so single-node setups are unaffected.
+fn table_file_name(index: usize) -> String {
+    if index == 0 {
+        routing::FILE_NAME.to_string()
+    } else {
+        routing::FILE_NAME.replace(".dat", &format!("_{index}.dat"))
+    }
+}
+
+/// Range of first id bytes owned by node `index` of `nodes`. Spreading the nodes over the
+/// keyspace puts each in different routing tables, so they hear different announces.
+fn keyspace_region(index: usize, nodes: usize) -> std::ops::Range<usize> {
+    (index * 256 / nodes)..((index + 1) * 256 / nodes)
+}
+
+fn node_id_in_region(index: usize, nodes: usize) -> [u8; 20] {
+    let mut id = *rbit::NodeId::generate().as_bytes();
+    let region = keyspace_region(index, nodes);
+    id[0] = (region.start + id[0] as usize % region.len()) as u8;
+    id
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Writes announce counts that crossed a threshold to their records and re-indexes the
+/// ones that are searchable.
+fn store_announces(state: &AppState, announces: &Mutex<AnnounceCounter>) {
+    let pending = lock(announces).take_pending();
+    if pending.is_empty() {
+        return;
+    }
+    let count = pending.len();
+    for (hash, estimate) in pending {
+        let info_hex = hex::encode(hash);
+        match storage::set_announces(&state.db, &info_hex, u64::from(estimate)) {
+            Ok(Some(record)) if record.title.is_some() && record.seeders >= 2 => {
+                if let Err(err) = state.index.upsert_record(&record) {
+                    tracing::debug!(%err, hash = %info_hex, "spider: failed indexing announce count");
+                }
+            }
+            Ok(_) => {}
+            Err(err) => tracing::debug!(%err, hash = %info_hex, "spider: failed storing announce count"),
+        }
+    }
+    state.index.maybe_commit().ok();
+    tracing::debug!(count, "spider: stored announce counts");
+}
+
+/// Moves the hashes announced most in the window that just ended to the front of the
+/// enrich queue.
+fn prioritize_hot(state: &AppState, announces: &Mutex<AnnounceCounter>) {
+    let hot = lock(announces).take_hot(Instant::now());
+    if hot.is_empty() {
+        return;
+    }
+    let hot: Vec<(String, u32)> = hot.into_iter().map(|(hash, n)| (hex::encode(hash), n)).collect();
+    match storage::prioritize_enrich(&state.db, &hot) {
+        Ok(queued) => tracing::debug!(hot = hot.len(), queued, "spider: trending hashes moved up the enrich queue"),
+        Err(err) => tracing::debug!(%err, "spider: failed prioritizing trending hashes"),
+    }
+}
+
+/// Returns `false` if the hash is blacklisted (dropped without a trace) or an already-known
+/// torrent matches the blocklist (deleted).
+fn ingest_spidered_hash(state: &AppState, info_hash_hex: &str, via: Discovery) -> anyhow::Result<bool> {
+    if storage::is_blacklisted(&state.db, info_hash_hex)? {
+        return Ok(false);
+    }
+
     // Ensure record exists.
-    let mut record = storage::upsert_first_seen(&state.db, info_hash_hex)?;
+    let mut record = storage::upsert_first_seen(&state.db, info_hash_hex, Some(via))?;
+
+    // Known torrents have a title to check; new ones are checked once enrichment finds it.
+    if let Some(rule) = state.blocklist.matches(&record) {
+        tracing::debug!(hash = %info_hash_hex, %rule, "spider: dropped by blocklist");
+        storage::delete(&state.db, info_hash_hex)?;
+        state.index.delete(info_hash_hex)?;
+        return Ok(false);
+    }
 
     // Give it a usable magnet if missing.
     if record
@@ -281,21 +682,13 @@ fn ingest_spidered_hash(state: &AppState, info_hash_hex: &str) -> anyhow::Result
         record = storage::set_magnet(&state.db, info_hash_hex, &magnet)?;
     }
 
-    let title = record
-        .title
-        .clone()
-        .unwrap_or_else(|| format!("Torrent {}", &record.info_hash_hex));
-    let magnet = record.magnet.clone().unwrap_or_default();
-
     // Only index "active" torrents to conserve memory.
     // The enrichment worker will update seeders and reindex once >= 2.
     if record.seeders >= 2 {
-        state
-            .index
-            .upsert(&record.info_hash_hex, &title, &magnet, record.seeders)?;
+        state.index.upsert_record(&record)?;
         state.index.maybe_commit().ok();
     }
-    Ok(())
+    Ok(true)
 }
 
 fn should_accept_hash(seen: &mut RollingBloom, hash: [u8; 20]) -> bool {
@@ -361,7 +754,7 @@ impl BloomFilter {
         let m_bits: usize = 1usize
             .checked_shl(bits_pow2)
             .expect("bits_pow2 too large");
-        let words = (m_bits + 63) / 64;
+        let words = m_bits.div_ceil(64);
         Self {
             bits: vec![0u64; words],
             bits_pow2,
@@ -410,27 +803,8 @@ fn bloom_hashes(item: &[u8; 20]) -> (u64, u64) {
     (h1, h2)
 }
 
-fn push_node(
-    addr: SocketAddr,
-    q: &mut VecDeque<SocketAddr>,
-    set: &mut HashSet<SocketAddr>,
-    cap: usize,
-) {
-    if addr.port() == 0 {
-        return;
-    }
-    if !is_publicly_routable_ip(addr.ip()) {
-        return;
-    }
-
-    if set.insert(addr) {
-        q.push_back(addr);
-        if q.len() > cap {
-            if let Some(old) = q.pop_front() {
-                set.remove(&old);
-            }
-        }
-    }
+fn is_usable_node(addr: SocketAddr) -> bool {
+    addr.port() != 0 && is_publicly_routable_ip(addr.ip())
 }
 
 fn is_publicly_routable_ip(ip: IpAddr) -> bool {
@@ -477,8 +851,8 @@ fn is_publicly_routable_ip(ip: IpAddr) -> bool {
 
 async fn resolve_bootstrap(cfg: &Config) -> Vec<SocketAddr> {
     let mut out = Vec::new();
-    for host in cfg.spider_bootstrap.iter().cloned() {
-        match tokio::net::lookup_host(&host).await {
+    for host in cfg.spider_bootstrap.iter() {
+        match tokio::net::lookup_host(host).await {
             Ok(iter) => {
                 for addr in iter {
                     out.push(addr);
@@ -496,15 +870,20 @@ async fn resolve_bootstrap(cfg: &Config) -> Vec<SocketAddr> {
 async fn bootstrap_tick(
     sockets: &DhtSockets,
     node_id: &[u8; 20],
-    known: &mut VecDeque<SocketAddr>,
+    table: &mut RoutingTable,
+    cfg: &Config,
 ) {
-    // Probe a handful of known nodes each tick.
-    for _ in 0..16 {
-        let Some(addr) = known.pop_front() else {
-            break;
-        };
-        known.push_back(addr);
+    // Look ourselves up via the bootstrap nodes until enough of the table is live; they
+    // join the table like any other node once they answer.
+    if table.live_len() < routing::K {
+        for addr in resolve_bootstrap(cfg).await {
+            let msg = make_find_node(next_txid(), node_id, node_id);
+            send_to_family(sockets, &msg, addr).await;
+        }
+    }
 
+    // Probe a handful of known nodes each tick.
+    for addr in table.next_to_query(16) {
         let target = *rbit::peer::PeerId::generate().as_bytes();
         let tx = next_txid();
         let msg = make_find_node(tx, node_id, &target);
@@ -546,6 +925,30 @@ fn make_find_node(tx: [u8; 2], id: &[u8; 20], target: &[u8; 20]) -> Vec<u8> {
     out
 }
 
+fn make_ping(tx: [u8; 2], id: &[u8; 20]) -> Vec<u8> {
+    // d1:ad2:id20:<id>e1:q4:ping1:t2:<tx>1:y1:qe
+    let mut out = Vec::with_capacity(60);
+    out.push(b'd');
+
+    benc_key(&mut out, b"a");
+    out.push(b'd');
+    benc_key(&mut out, b"id");
+    benc_bytes(&mut out, id);
+    out.push(b'e');
+
+    benc_key(&mut out, b"q");
+    benc_bytes(&mut out, b"ping");
+
+    benc_key(&mut out, b"t");
+    benc_bytes(&mut out, &tx);
+
+    benc_key(&mut out, b"y");
+    benc_bytes(&mut out, b"q");
+
+    out.push(b'e');
+    out
+}
+
 fn make_sample_infohashes(tx: [u8; 2], id: &[u8; 20], target: &[u8; 20]) -> Vec<u8> {
     // d1:ad2:id20:<id>6:target20:<target>e1:q17:sample_infohashes1:t2:<tx>1:y1:qe
     let mut out = Vec::with_capacity(140);
@@ -572,15 +975,57 @@ fn make_sample_infohashes(tx: [u8; 2], id: &[u8; 20], target: &[u8; 20]) -> Vec<
     out
 }
 
-fn make_response(tx: &[u8], id: &[u8; 20]) -> Vec<u8> {
-    // d1:rd2:id20:<id>e1:t<tx>1:y1:re
-    let mut out = Vec::with_capacity(80);
+/// Builds a response: `r` holds our id plus `nodes`/`nodes6`, `token` and `values` when
+/// given.
+fn make_response(
+    tx: &[u8],
+    id: &[u8; 20],
+    nodes: &[([u8; 20], SocketAddr)],
+    token: Option<&[u8]>,
+    values: &[SocketAddr],
+) -> Vec<u8> {
+    // d1:rd2:id20:<id>5:nodes<compact>5:token<token>6:valuesl<peer>...ee1:t<tx>1:y1:re
+    let mut out = Vec::with_capacity(80 + nodes.len() * 38 + values.len() * 21);
     out.push(b'd');
 
     benc_key(&mut out, b"r");
     out.push(b'd');
     benc_key(&mut out, b"id");
     benc_bytes(&mut out, id);
+    // Keys must be sorted; nodes are all one family (the querier's), so only one of
+    // `nodes` / `nodes6` is present.
+    if let Some((_, first)) = nodes.first() {
+        let mut compact = Vec::with_capacity(nodes.len() * 38);
+        for (node_id, addr) in nodes {
+            compact.extend_from_slice(node_id);
+            match addr.ip() {
+                IpAddr::V4(v4) => compact.extend_from_slice(&v4.octets()),
+                IpAddr::V6(v6) => compact.extend_from_slice(&v6.octets()),
+            }
+            compact.extend_from_slice(&addr.port().to_be_bytes());
+        }
+        benc_key(&mut out, if first.is_ipv4() { b"nodes".as_slice() } else { b"nodes6".as_slice() });
+        benc_bytes(&mut out, &compact);
+    }
+    if let Some(token) = token {
+        benc_key(&mut out, b"token");
+        benc_bytes(&mut out, token);
+    }
+    // Compact peer info: IP + port, one string each.
+    if !values.is_empty() {
+        benc_key(&mut out, b"values");
+        out.push(b'l');
+        for peer in values {
+            let mut compact = Vec::with_capacity(18);
+            match peer.ip() {
+                IpAddr::V4(v4) => compact.extend_from_slice(&v4.octets()),
+                IpAddr::V6(v6) => compact.extend_from_slice(&v6.octets()),
+            }
+            compact.extend_from_slice(&peer.port().to_be_bytes());
+            benc_bytes(&mut out, &compact);
+        }
+        out.push(b'e');
+    }
     out.push(b'e');
 
     benc_key(&mut out, b"t");
@@ -593,6 +1038,29 @@ fn make_response(tx: &[u8], id: &[u8; 20]) -> Vec<u8> {
     out
 }
 
+fn make_error(tx: &[u8], code: u16, message: &str) -> Vec<u8> {
+    // d1:eli<code>e<len>:<message>e1:t<tx>1:y1:ee
+    let mut out = Vec::with_capacity(40 + message.len());
+    out.push(b'd');
+
+    benc_key(&mut out, b"e");
+    out.push(b'l');
+    out.push(b'i');
+    itoa_len(&mut out, code as usize);
+    out.push(b'e');
+    benc_bytes(&mut out, message.as_bytes());
+    out.push(b'e');
+
+    benc_key(&mut out, b"t");
+    benc_bytes(&mut out, tx);
+
+    benc_key(&mut out, b"y");
+    benc_bytes(&mut out, b"e");
+
+    out.push(b'e');
+    out
+}
+
 fn benc_key(out: &mut Vec<u8>, key: &[u8]) {
     // Keys must be bytestrings.
     benc_bytes(out, key);
@@ -621,24 +1089,28 @@ fn itoa_len(out: &mut Vec<u8>, n: usize) {
     out.extend_from_slice(&buf[i..]);
 }
 
-fn parse_compact_nodes(nodes: &[u8]) -> Vec<SocketAddr> {
+fn parse_compact_nodes(nodes: &[u8]) -> Vec<([u8; 20], SocketAddr)> {
     // Compact node info: 26 bytes per node: 20-byte node id + 4-byte IPv4 + 2-byte port.
     let mut out = Vec::new();
     let mut i = 0;
     while i + 26 <= nodes.len() {
+        let mut id = [0u8; 20];
+        id.copy_from_slice(&nodes[i..i + 20]);
         let ip = Ipv4Addr::new(nodes[i + 20], nodes[i + 21], nodes[i + 22], nodes[i + 23]);
         let port = u16::from_be_bytes([nodes[i + 24], nodes[i + 25]]);
-        out.push(SocketAddr::new(IpAddr::V4(ip), port));
+        out.push((id, SocketAddr::new(IpAddr::V4(ip), port)));
         i += 26;
     }
     out
 }
 
-fn parse_compact_nodes_v6(nodes: &[u8]) -> Vec<SocketAddr> {
+fn parse_compact_nodes_v6(nodes: &[u8]) -> Vec<([u8; 20], SocketAddr)> {
     // nodes6: 38 bytes per node: 20-byte node id + 16-byte IPv6 + 2-byte port.
     let mut out = Vec::new();
     let mut i = 0;
     while i + 38 <= nodes.len() {
+        let mut id = [0u8; 20];
+        id.copy_from_slice(&nodes[i..i + 20]);
         let ip = Ipv6Addr::from([
             nodes[i + 20],
             nodes[i + 21],
@@ -658,7 +1130,7 @@ fn parse_compact_nodes_v6(nodes: &[u8]) -> Vec<SocketAddr> {
             nodes[i + 35],
         ]);
         let port = u16::from_be_bytes([nodes[i + 36], nodes[i + 37]]);
-        out.push(SocketAddr::new(IpAddr::V6(ip), port));
+        out.push((id, SocketAddr::new(IpAddr::V6(ip), port)));
         i += 38;
     }
     out
@@ -678,6 +1150,12 @@ impl<'a> KrpcMessage<'a> {
         Some(Self { raw })
     }
 
+    /// A KRPC message: a transaction id and a known type.
+    fn is_well_formed(&self) -> bool {
+        benc_get_bytes(self.raw, b"t").is_some()
+            && benc_get_bytes(self.raw, b"y").is_some_and(|y| matches!(y, b"q" | b"r" | b"e"))
+    }
+
     fn is_query(&self) -> bool {
         benc_get_bytes(self.raw, b"y").is_some_and(|v| v == b"q")
     }
@@ -686,6 +1164,18 @@ impl<'a> KrpcMessage<'a> {
         benc_get_bytes(self.raw, b"y").is_some_and(|v| v == b"r")
     }
 
+    /// The sender's node id: `r.id` in responses, `a.id` in queries.
+    fn sender_id(&self) -> Option<[u8; 20]> {
+        let body = if self.is_response() {
+            benc_get_dict(self.raw, b"r")?
+        } else if self.is_query() {
+            benc_get_dict(self.raw, b"a")?
+        } else {
+            return None;
+        };
+        benc_get_bytes(body, b"id")?.try_into().ok()
+    }
+
     fn compact_nodes(&self) -> Option<&'a [u8]> {
         // Look for r:nodes in responses.
         let r = benc_get_dict(self.raw, b"r")?;
@@ -706,6 +1196,16 @@ impl<'a> KrpcMessage<'a> {
         benc_get_bytes(r, b"samples")
     }
 
+    /// `interval` and `num` of a sample_infohashes response.
+    fn sample_schedule(&self) -> (Option<Duration>, Option<u64>) {
+        let Some(r) = benc_get_dict(self.raw, b"r") else {
+            return (None, None);
+        };
+        let interval = benc_get_int(r, b"interval").and_then(|s| u64::try_from(s).ok());
+        let num = benc_get_int(r, b"num").and_then(|n| u64::try_from(n).ok());
+        (interval.map(Duration::from_secs), num)
+    }
+
     fn info_hash_from_query(&self) -> Option<[u8; 20]> {
         if !self.is_query() {
             return None;
@@ -724,29 +1224,97 @@ impl<'a> KrpcMessage<'a> {
         Some(out)
     }
 
-    fn make_minimal_response(&self, node_id: &[u8; 20]) -> Option<Vec<u8>> {
+    /// The hash of an `announce_peer` whose token is valid for its sender.
+    fn announced_hash(&self, tokens: &mut AnnounceTokens, from: SocketAddr) -> Option<[u8; 20]> {
+        if !self.is_query() || benc_get_bytes(self.raw, b"q")? != b"announce_peer" {
+            return None;
+        }
+        let a = benc_get_dict(self.raw, b"a")?;
+        if !tokens.is_valid(from.ip(), benc_get_bytes(a, b"token")?) {
+            return None;
+        }
+        benc_get_bytes(a, b"info_hash")?.try_into().ok()
+    }
+
+    /// Answers a query: `ping` and `announce_peer` with our id, `find_node` and
+    /// `get_peers` (and `sample_infohashes`, which we don't serve samples for) with the
+    /// closest nodes we know; `get_peers` also gets an announce token, and the peers
+    /// announced to us for the hash as `values`. A token-validated `announce_peer` stores
+    /// its sender in `peers`.
+    fn make_query_response(
+        &self,
+        table: &RoutingTable,
+        tokens: &mut AnnounceTokens,
+        peers: &mut PeerStore,
+        from: SocketAddr,
+    ) -> Option<Vec<u8>> {
         if !self.is_query() {
             return None;
         }
         let tx = benc_get_bytes(self.raw, b"t")?;
-        Some(make_response(tx, node_id))
+        let node_id = table.own_id();
+        let q = benc_get_bytes(self.raw, b"q")?;
+        let Some(a) = benc_get_dict(self.raw, b"a") else {
+            return Some(make_error(tx, 203, "missing arguments"));
+        };
+        let target = |key: &[u8]| -> Option<[u8; 20]> { benc_get_bytes(a, key)?.try_into().ok() };
+
+        match q {
+            b"ping" => Some(make_response(tx, node_id, &[], None, &[])),
+            b"find_node" | b"sample_infohashes" => {
+                let Some(target) = target(b"target") else {
+                    return Some(make_error(tx, 203, "invalid target"));
+                };
+                let nodes = table.closest(&target, RESPONSE_NODES, from);
+                Some(make_response(tx, node_id, &nodes, None, &[]))
+            }
+            b"get_peers" => {
+                let Some(info_hash) = target(b"info_hash") else {
+                    return Some(make_error(tx, 203, "invalid info_hash"));
+                };
+                let nodes = table.closest(&info_hash, RESPONSE_NODES, from);
+                let token = tokens.token_for(from.ip());
+                let values = peers.peers(&info_hash, from.ip(), RESPONSE_PEERS, Instant::now());
+                Some(make_response(tx, node_id, &nodes, Some(&token), &values))
+            }
+            b"announce_peer" => {
+                let valid = benc_get_bytes(a, b"token").is_some_and(|t| tokens.is_valid(from.ip(), t));
+                if !valid {
+                    return Some(make_error(tx, 203, "bad token"));
+                }
+                let Some(info_hash) = target(b"info_hash") else {
+                    return Some(make_error(tx, 203, "invalid info_hash"));
+                };
+                // With `implied_port` the peer listens where the query came from (it is
+                // behind a NAT that maps the port); otherwise on `port`.
+                let port = if benc_get_int(a, b"implied_port").is_some_and(|v| v != 0) {
+                    Some(from.port())
+                } else {
+                    benc_get_int(a, b"port").and_then(|p| u16::try_from(p).ok())
+                };
+                let peer = port.map(|port| SocketAddr::new(from.ip(), port));
+                if let Some(peer) = peer.filter(|p| is_usable_node(*p)) {
+                    peers.announce(info_hash, peer, Instant::now());
+                }
+                Some(make_response(tx, node_id, &[], None, &[]))
+            }
+            _ => Some(make_error(tx, 204, "method unknown")),
+        }
     }
 }
 
 async fn sample_tick(
     sockets: &DhtSockets,
     node_id: &[u8; 20],
-    known: &mut VecDeque<SocketAddr>,
+    table: &mut RoutingTable,
+    sweep: &mut KeyspaceSweep,
     per_tick: usize,
 ) {
-    // Query a handful of known nodes for hash samples (BEP-51).
-    for _ in 0..per_tick {
-        let Some(addr) = known.pop_front() else {
-            break;
-        };
-        known.push_back(addr);
-
-        let target = *rbit::peer::PeerId::generate().as_bytes();
+    // Query a handful of nodes whose samples are due for them (BEP-51), those in the
+    // least explored regions first, and aim the queries at the least explored region so
+    // the answers bring new nodes from there.
+    for addr in table.next_to_sample(per_tick, |id| sweep.coverage(id)) {
+        let target = sweep.next_target();
         let tx = next_txid();
         let msg = make_sample_infohashes(tx, node_id, &target);
         send_to_family(sockets, &msg, addr).await;
@@ -771,7 +1339,7 @@ async fn send_to_family(
                 }
             }
         },
-        DhtSockets::Socks { sock } => {
+        DhtSockets::Socks { sock, .. } => {
             let _ = sock.send_to(msg, addr).await;
         }
     }
@@ -801,7 +1369,7 @@ async fn recv_from_any(
                 } => r.ok().map(|(n, from)| (n, from, 6u8)),
             }
         }
-        DhtSockets::Socks { sock } => sock
+        DhtSockets::Socks { sock, .. } => sock
             .recv_from(buf4)
             .await
             .ok()
@@ -823,6 +1391,11 @@ fn benc_get_dict<'a>(raw: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
     dict.get_dict_slice(key)
 }
 
+fn benc_get_int(raw: &[u8], key: &[u8]) -> Option<i64> {
+    let dict = BencParser::new(raw).parse_dict()?;
+    dict.get_int(key)
+}
+
 struct BencDict<'a> {
     // Slice containing the dict payload (starts at 'd', ends at matching 'e').
     raw: &'a [u8],
@@ -860,6 +1433,24 @@ impl<'a> BencDict<'a> {
         }
     }
 
+    fn get_int(&self, key: &[u8]) -> Option<i64> {
+        let mut p = BencParser::new(self.raw);
+        p.expect_byte(b'd')?;
+        loop {
+            if p.peek()? == b'e' {
+                return None;
+            }
+            let k = p.parse_bytes()?;
+            if k == key && p.peek()? == b'i' {
+                let start = p.pos + 1;
+                p.skip_value()?;
+                let digits = std::str::from_utf8(self.raw.get(start..p.pos - 1)?).ok()?;
+                return digits.parse().ok();
+            }
+            p.skip_value()?;
+        }
+    }
+
     fn get_dict_slice(&self, key: &[u8]) -> Option<&'a [u8]> {
         let mut p = BencParser::new(self.raw);
         p.expect_byte(b'd')?;
@@ -998,3 +1589,43 @@ impl<'a> BencParser<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(q: &str, info_hash: [u8; 20], token: &[u8]) -> Vec<u8> {
+        let mut raw = b"d1:ad2:id20:".to_vec();
+        raw.extend_from_slice(&[7; 20]);
+        raw.extend_from_slice(b"9:info_hash20:");
+        raw.extend_from_slice(&info_hash);
+        raw.extend_from_slice(b"4:porti6881e5:token");
+        raw.extend_from_slice(format!("{}:", token.len()).as_bytes());
+        raw.extend_from_slice(token);
+        raw.extend_from_slice(format!("e1:q{}:{q}1:t2:aa1:y1:qe", q.len()).as_bytes());
+        raw
+    }
+
+    #[test]
+    fn only_token_validated_announces_count() {
+        let mut tokens = AnnounceTokens::new();
+        let from: SocketAddr = "203.0.113.5:6881".parse().unwrap();
+        let other: SocketAddr = "198.51.100.9:6881".parse().unwrap();
+        let token = tokens.token_for(from.ip());
+        let hash = [1; 20];
+
+        let announce = query("announce_peer", hash, &token);
+        let msg = KrpcMessage::decode(&announce).unwrap();
+        assert_eq!(msg.announced_hash(&mut tokens, from), Some(hash));
+        // Someone else's token, or none we issued.
+        assert_eq!(msg.announced_hash(&mut tokens, other), None);
+        let forged = query("announce_peer", hash, b"12345678");
+        assert_eq!(KrpcMessage::decode(&forged).unwrap().announced_hash(&mut tokens, from), None);
+
+        // get_peers still names a hash to harvest, but never counts.
+        let get_peers = query("get_peers", hash, &token);
+        let msg = KrpcMessage::decode(&get_peers).unwrap();
+        assert_eq!(msg.info_hash_from_query(), Some(hash));
+        assert_eq!(msg.announced_hash(&mut tokens, from), None);
+    }
+}
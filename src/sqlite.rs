@@ -0,0 +1,270 @@
+use crate::kv::{Batch, Entry, Store, Update};
+use anyhow::Context;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+// SQLite backend (SERMA_STORAGE=sqlite).
+//
+// Every tree lives in one table keyed by (tree, key). SQLite compares blobs with memcmp,
+// so keys sort exactly as in sled and the range scans `storage.rs` relies on work
+// unchanged. One connection serves the whole process behind a mutex; in WAL mode each
+// write is its own small transaction and readers never wait on fsync.
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS kv (
+        tree BLOB NOT NULL,
+        key BLOB NOT NULL,
+        value BLOB NOT NULL,
+        PRIMARY KEY (tree, key)
+    ) WITHOUT ROWID;
+    CREATE TABLE IF NOT EXISTS ids (next INTEGER NOT NULL);
+";
+
+/// Rows copied per transaction by `migrate_from_sled`.
+const MIGRATE_BATCH: usize = 10_000;
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("open sqlite db {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA).context("create sqlite schema")?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn conn(&self) -> anyhow::Result<MutexGuard<'_, Connection>> {
+        self.conn.lock().map_err(|_| anyhow::anyhow!("sqlite connection lock poisoned"))
+    }
+
+    /// Rewrites the file without its free pages.
+    pub fn vacuum(&self) -> anyhow::Result<()> {
+        let conn = self.conn()?;
+        conn.execute_batch("VACUUM")?;
+        // VACUUM goes through the WAL; fold it back so the file shrinks now.
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    pub fn set_next_id(&self, next: u64) -> anyhow::Result<()> {
+        let conn = self.conn()?;
+        conn.execute("DELETE FROM ids", [])?;
+        conn.execute("INSERT INTO ids (next) VALUES (?1)", [next as i64])?;
+        Ok(())
+    }
+}
+
+fn get(conn: &Connection, tree: &[u8], key: &[u8]) -> rusqlite::Result<Option<Vec<u8>>> {
+    conn.prepare_cached("SELECT value FROM kv WHERE tree = ?1 AND key = ?2")?
+        .query_row(params![tree, key], |row| row.get(0))
+        .optional()
+}
+
+fn put(conn: &Connection, tree: &[u8], key: &[u8], value: Option<&[u8]>) -> rusqlite::Result<()> {
+    match value {
+        Some(value) => conn
+            .prepare_cached("INSERT OR REPLACE INTO kv (tree, key, value) VALUES (?1, ?2, ?3)")?
+            .execute(params![tree, key, value])?,
+        None => conn
+            .prepare_cached("DELETE FROM kv WHERE tree = ?1 AND key = ?2")?
+            .execute(params![tree, key])?,
+    };
+    Ok(())
+}
+
+impl Store for SqliteStore {
+    fn get(&self, tree: &[u8], key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(get(&*self.conn()?, tree, key)?)
+    }
+
+    fn insert(&self, tree: &[u8], key: &[u8], value: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let old = get(&tx, tree, key)?;
+        put(&tx, tree, key, Some(value))?;
+        tx.commit()?;
+        Ok(old)
+    }
+
+    fn remove(&self, tree: &[u8], key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let old = get(&tx, tree, key)?;
+        if old.is_some() {
+            put(&tx, tree, key, None)?;
+        }
+        tx.commit()?;
+        Ok(old)
+    }
+
+    fn update_and_fetch(
+        &self,
+        tree: &[u8],
+        key: &[u8],
+        f: &mut Update<'_>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let new = f(get(&tx, tree, key)?.as_deref());
+        put(&tx, tree, key, new.as_deref())?;
+        tx.commit()?;
+        Ok(new)
+    }
+
+    fn range(
+        &self,
+        tree: &[u8],
+        lo: Bound<&[u8]>,
+        hi: Bound<&[u8]>,
+        reverse: bool,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let mut sql = "SELECT key, value FROM kv WHERE tree = ?".to_string();
+        let mut keys: Vec<&[u8]> = Vec::with_capacity(2);
+        for (bound, included, excluded) in [(lo, " AND key >= ?", " AND key > ?"), (hi, " AND key <= ?", " AND key < ?")] {
+            match bound {
+                Bound::Included(k) => (keys.push(k), sql.push_str(included)),
+                Bound::Excluded(k) => (keys.push(k), sql.push_str(excluded)),
+                Bound::Unbounded => continue,
+            };
+        }
+        sql.push_str(if reverse { " ORDER BY key DESC LIMIT ?" } else { " ORDER BY key LIMIT ?" });
+        let limit = limit.min(i64::MAX as usize) as i64;
+        let mut args: Vec<&dyn rusqlite::ToSql> = vec![&tree];
+        args.extend(keys.iter().map(|k| k as &dyn rusqlite::ToSql));
+        args.push(&limit);
+
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare_cached(&sql)?;
+        let mut rows = stmt.query(args.as_slice())?;
+        let mut out = Vec::new();
+        while let Some(row) = rows.next()? {
+            out.push((row.get(0)?, row.get(1)?));
+        }
+        Ok(out)
+    }
+
+    fn len(&self, tree: &[u8]) -> anyhow::Result<usize> {
+        let conn = self.conn()?;
+        let count: i64 = conn
+            .prepare_cached("SELECT COUNT(*) FROM kv WHERE tree = ?1")?
+            .query_row([tree], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn apply_batch(&self, tree: &[u8], batch: Batch) -> anyhow::Result<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        for (key, value) in &batch.ops {
+            put(&tx, tree, key, value.as_deref())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn generate_id(&self) -> anyhow::Result<u64> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let next: i64 = tx
+            .query_row("SELECT next FROM ids", [], |row| row.get(0))
+            .optional()?
+            .unwrap_or(0);
+        tx.execute("DELETE FROM ids", [])?;
+        tx.execute("INSERT INTO ids (next) VALUES (?1)", [next + 1])?;
+        tx.commit()?;
+        Ok(next as u64)
+    }
+
+    fn tree_names(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT DISTINCT tree FROM kv ORDER BY tree")?;
+        let names = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+        Ok(names)
+    }
+
+    fn flush(&self) -> anyhow::Result<()> {
+        // Commits are already in the WAL; move them into the main file.
+        self.conn()?.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    fn size_on_disk(&self) -> anyhow::Result<u64> {
+        let mut total = 0;
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.path.clone().into_os_string();
+            path.push(suffix);
+            total += std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        }
+        Ok(total)
+    }
+}
+
+/// What `migrate_from_sled` copied.
+pub struct Migration {
+    pub trees: usize,
+    pub entries: u64,
+}
+
+/// Copies every tree of the sled database at `sled_dir` into a new SQLite database at
+/// `path`. The copy is built next to it and only moved into place once every tree's entry
+/// count matches, so an interrupted run leaves nothing behind. sled is left as it was.
+pub fn migrate_from_sled(sled_dir: &Path, path: &Path) -> anyhow::Result<Migration> {
+    anyhow::ensure!(sled_dir.exists(), "no sled database at {}", sled_dir.display());
+    anyhow::ensure!(!path.exists(), "{} already exists; remove it to migrate again", path.display());
+
+    let partial = path.with_extension("sqlite.partial");
+    for suffix in ["", "-wal", "-shm"] {
+        let mut p = partial.clone().into_os_string();
+        p.push(suffix);
+        let _ = std::fs::remove_file(p);
+    }
+
+    let sled = crate::kv::SledStore::open(sled_dir)?;
+    let sqlite = SqliteStore::open(&partial)?;
+    let mut migration = Migration { trees: 0, entries: 0 };
+    for name in sled.tree_names()? {
+        let mut copied = 0usize;
+        let mut after: Option<Vec<u8>> = None;
+        loop {
+            let lo = after.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+            let entries = sled.range(&name, lo, Bound::Unbounded, false, MIGRATE_BATCH)?;
+            let Some((last, _)) = entries.last() else {
+                break;
+            };
+            after = Some(last.clone());
+            copied += entries.len();
+            let mut batch = Batch::default();
+            for (key, value) in entries {
+                batch.insert(key, value);
+            }
+            sqlite.apply_batch(&name, batch)?;
+        }
+        let expected = sled.len(&name)?;
+        anyhow::ensure!(
+            sqlite.len(&name)? == expected,
+            "tree {} has {expected} entries in sled but {copied} were copied; left the copy at {}",
+            String::from_utf8_lossy(&name),
+            partial.display()
+        );
+        if copied > 0 {
+            migration.trees += 1;
+            migration.entries += copied as u64;
+        }
+        tracing::info!(tree = %String::from_utf8_lossy(&name), entries = copied, "migrate: copied tree");
+    }
+    // Keep ids (block rules) unique across the move.
+    sqlite.set_next_id(sled.generate_id()?)?;
+    sqlite.vacuum()?;
+    drop(sqlite);
+    std::fs::rename(&partial, path).context("move migrated database in place")?;
+    Ok(migration)
+}
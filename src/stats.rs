@@ -0,0 +1,344 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Live counters for the admin dashboard.
+//
+// Background tasks bump these as they work; `/admin` and `/api/admin/stats` read a
+// snapshot. Everything is in memory and starts from zero on restart; totals that must
+// survive restarts (record counts, index size) are read from storage instead.
+
+/// Minutes of discovery history kept for the dashboard graph.
+pub const HISTORY_MINUTES: usize = 60;
+
+#[derive(Clone)]
+pub struct Stats {
+    inner: Arc<StatsInner>,
+}
+
+struct StatsInner {
+    started: Instant,
+    discovered: AtomicU64,
+    /// (minute since start, hashes discovered in that minute), oldest first.
+    discovery_minutes: Mutex<VecDeque<(u64, u64)>>,
+    enrich_in_flight: AtomicU64,
+    enrich_fetched: AtomicU64,
+    enrich_missed: AtomicU64,
+    enrich_lookups: StageCounters,
+    enrich_fetches: StageCounters,
+    cleanup_sweeps: AtomicU64,
+    cleanup_deleted: AtomicU64,
+    cleanup_last: Mutex<Option<CleanupSweep>>,
+    socks5: Mutex<Socks5Status>,
+    discovery_throttle: Mutex<DiscoveryThrottle>,
+    index_purges: Mutex<IndexPurges>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(StatsInner {
+                started: Instant::now(),
+                discovered: AtomicU64::new(0),
+                discovery_minutes: Mutex::new(VecDeque::with_capacity(HISTORY_MINUTES)),
+                enrich_in_flight: AtomicU64::new(0),
+                enrich_fetched: AtomicU64::new(0),
+                enrich_missed: AtomicU64::new(0),
+                enrich_lookups: StageCounters::default(),
+                enrich_fetches: StageCounters::default(),
+                cleanup_sweeps: AtomicU64::new(0),
+                cleanup_deleted: AtomicU64::new(0),
+                cleanup_last: Mutex::new(None),
+                socks5: Mutex::new(Socks5Status::default()),
+                discovery_throttle: Mutex::new(DiscoveryThrottle::default()),
+                index_purges: Mutex::new(IndexPurges::default()),
+            }),
+        }
+    }
+}
+
+/// A step of a metadata lookup with its own concurrency limit (see `enrich.rs`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnrichStage {
+    /// DHT peer lookup (SERMA_ENRICH_MAX_LOOKUPS).
+    Lookup,
+    /// Metadata download from the peers found (SERMA_ENRICH_MAX_FETCHES).
+    Fetch,
+}
+
+#[derive(Default)]
+struct StageCounters {
+    waiting: AtomicU64,
+    running: AtomicU64,
+}
+
+/// Lookups waiting for a slot in one enrich stage, and holding one.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StageDepth {
+    pub waiting: u64,
+    pub running: u64,
+}
+
+impl StageCounters {
+    fn depth(&self) -> StageDepth {
+        StageDepth {
+            waiting: self.waiting.load(Ordering::Relaxed),
+            running: self.running.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CleanupSweep {
+    pub at_unix_ms: i64,
+    /// With `SERMA_CLEANUP_DRY_RUN`, candidates are counted but nothing is removed.
+    pub dry_run: bool,
+    pub scanned: usize,
+    /// Records that qualified for removal, by reason.
+    pub candidates: CleanupReasons,
+    pub deleted: usize,
+    pub tombstones_pruned: usize,
+    /// Low-seed records kept because their seeder history was steady (see `cleanup.rs`).
+    pub spared_by_trend: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CleanupReasons {
+    /// Not seen for `SERMA_TORRENT_TTL_SECS`.
+    pub ttl: usize,
+    /// Fewer than 2 seeders after `SERMA_LOW_SEED_GRACE_SECS`.
+    pub low_seed: usize,
+    /// Oldest records over `SERMA_MAX_TORRENTS`.
+    pub max_records: usize,
+}
+
+impl CleanupReasons {
+    pub fn total(&self) -> usize {
+        self.ttl + self.low_seed + self.max_records
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Socks5Status {
+    pub configured: bool,
+    pub proxy: Option<String>,
+    /// Whether the UDP association is currently up.
+    pub associated: bool,
+    pub relay: Option<String>,
+    pub error: Option<String>,
+    pub checked_unix_ms: i64,
+}
+
+impl Socks5Status {
+    pub fn associated(proxy: &str, relay: SocketAddr) -> Self {
+        Self {
+            configured: true,
+            proxy: Some(proxy.to_string()),
+            associated: true,
+            relay: Some(relay.to_string()),
+            error: None,
+            checked_unix_ms: now_unix_ms(),
+        }
+    }
+
+    pub fn failed(proxy: &str, error: String) -> Self {
+        Self {
+            configured: true,
+            proxy: Some(proxy.to_string()),
+            associated: false,
+            relay: None,
+            error: Some(error),
+            checked_unix_ms: now_unix_ms(),
+        }
+    }
+}
+
+/// The spider's discovery auto-tuning, as of its last check (see `throttle.rs`).
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveryThrottle {
+    /// Share of sample queries and new announced hashes let through, from 0.02 to 1.
+    pub rate: f64,
+    pub enrich_queue: u64,
+    pub disk_growth_bytes_per_min: u64,
+    pub checked_unix_ms: i64,
+}
+
+impl Default for DiscoveryThrottle {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            enrich_queue: 0,
+            disk_growth_bytes_per_min: 0,
+            checked_unix_ms: 0,
+        }
+    }
+}
+
+impl DiscoveryThrottle {
+    pub fn new(rate: f64, enrich_queue: u64, disk_growth_bytes_per_min: u64) -> Self {
+        Self {
+            rate,
+            enrich_queue,
+            disk_growth_bytes_per_min,
+            checked_unix_ms: now_unix_ms(),
+        }
+    }
+}
+
+/// Background index optimization since start (see `optimize.rs`).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexPurges {
+    pub runs: u64,
+    pub segments_rewritten: u64,
+    pub deleted_docs_purged: u64,
+    pub last_unix_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSnapshot {
+    pub uptime_secs: u64,
+    pub discovered: u64,
+    /// Hashes discovered per minute over the last `HISTORY_MINUTES` minutes, oldest first.
+    pub discovered_per_minute: Vec<u64>,
+    pub enrich_in_flight: u64,
+    pub enrich_fetched: u64,
+    pub enrich_missed: u64,
+    pub enrich_lookups: StageDepth,
+    pub enrich_fetches: StageDepth,
+    pub cleanup_sweeps: u64,
+    pub cleanup_deleted: u64,
+    pub cleanup_last: Option<CleanupSweep>,
+    pub socks5: Socks5Status,
+    pub discovery_throttle: DiscoveryThrottle,
+    pub index_purges: IndexPurges,
+}
+
+impl Stats {
+    fn minute(&self) -> u64 {
+        self.inner.started.elapsed().as_secs() / 60
+    }
+
+    /// A new hash entered the ingest path from the DHT.
+    pub fn record_discovery(&self) {
+        self.inner.discovered.fetch_add(1, Ordering::Relaxed);
+        let minute = self.minute();
+        let mut minutes = lock(&self.inner.discovery_minutes);
+        match minutes.back_mut() {
+            Some((m, count)) if *m == minute => *count += 1,
+            _ => {
+                minutes.push_back((minute, 1));
+                while minutes.len() > HISTORY_MINUTES {
+                    minutes.pop_front();
+                }
+            }
+        }
+    }
+
+    pub fn uptime_secs(&self) -> u64 {
+        self.inner.started.elapsed().as_secs()
+    }
+
+    pub fn enrich_started(&self) {
+        self.inner.enrich_in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn enrich_finished(&self, fetched: bool) {
+        self.inner.enrich_in_flight.fetch_sub(1, Ordering::Relaxed);
+        let counter = if fetched {
+            &self.inner.enrich_fetched
+        } else {
+            &self.inner.enrich_missed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stage(&self, stage: EnrichStage) -> &StageCounters {
+        match stage {
+            EnrichStage::Lookup => &self.inner.enrich_lookups,
+            EnrichStage::Fetch => &self.inner.enrich_fetches,
+        }
+    }
+
+    /// A lookup started waiting for a slot in `stage`.
+    pub fn enrich_stage_waiting(&self, stage: EnrichStage) {
+        self.stage(stage).waiting.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The lookup got its slot.
+    pub fn enrich_stage_entered(&self, stage: EnrichStage) {
+        let counters = self.stage(stage);
+        counters.waiting.fetch_sub(1, Ordering::Relaxed);
+        counters.running.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The lookup gave its slot back.
+    pub fn enrich_stage_left(&self, stage: EnrichStage) {
+        self.stage(stage).running.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn record_cleanup(&self, sweep: CleanupSweep) {
+        self.inner.cleanup_sweeps.fetch_add(1, Ordering::Relaxed);
+        self.inner.cleanup_deleted.fetch_add(sweep.deleted as u64, Ordering::Relaxed);
+        *lock(&self.inner.cleanup_last) = Some(sweep);
+    }
+
+    pub fn set_socks5(&self, status: Socks5Status) {
+        *lock(&self.inner.socks5) = status;
+    }
+
+    pub fn set_discovery_throttle(&self, throttle: DiscoveryThrottle) {
+        *lock(&self.inner.discovery_throttle) = throttle;
+    }
+
+    pub fn record_index_purge(&self, purge: crate::index::Purge) {
+        let mut purges = lock(&self.inner.index_purges);
+        purges.runs += 1;
+        purges.segments_rewritten += purge.segments as u64;
+        purges.deleted_docs_purged += purge.deleted_docs;
+        purges.last_unix_ms = Some(now_unix_ms());
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let now = self.minute();
+        let mut discovered_per_minute = vec![0; HISTORY_MINUTES];
+        for (minute, count) in lock(&self.inner.discovery_minutes).iter() {
+            let age = (now - minute) as usize;
+            if age < HISTORY_MINUTES {
+                discovered_per_minute[HISTORY_MINUTES - 1 - age] = *count;
+            }
+        }
+
+        StatsSnapshot {
+            uptime_secs: self.uptime_secs(),
+            discovered: self.inner.discovered.load(Ordering::Relaxed),
+            discovered_per_minute,
+            enrich_in_flight: self.inner.enrich_in_flight.load(Ordering::Relaxed),
+            enrich_fetched: self.inner.enrich_fetched.load(Ordering::Relaxed),
+            enrich_missed: self.inner.enrich_missed.load(Ordering::Relaxed),
+            enrich_lookups: self.inner.enrich_lookups.depth(),
+            enrich_fetches: self.inner.enrich_fetches.depth(),
+            cleanup_sweeps: self.inner.cleanup_sweeps.load(Ordering::Relaxed),
+            cleanup_deleted: self.inner.cleanup_deleted.load(Ordering::Relaxed),
+            cleanup_last: lock(&self.inner.cleanup_last).clone(),
+            socks5: lock(&self.inner.socks5).clone(),
+            discovery_throttle: lock(&self.inner.discovery_throttle).clone(),
+            index_purges: lock(&self.inner.index_purges).clone(),
+        }
+    }
+}
+
+fn now_unix_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -1,14 +1,64 @@
+use anyhow::Context;
+use base64::Engine as _;
 use bincode::Options;
+use rbit::bencode;
 use serde::{Deserialize, Serialize};
+use crate::kv::{Backend, Batch, Db, Tree};
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-const TORRENT_RECORD_MAGIC: [u8; 4] = *b"SRM1";
+const TORRENT_RECORD_MAGIC: [u8; 4] = *b"SRM9";
+/// Version of the record layout written now (the digit in the magic); backups carry it.
+pub const RECORD_FORMAT: u32 = (TORRENT_RECORD_MAGIC[3] - b'0') as u32;
+/// Previous binary layouts (SRM1: no size / file list, SRM2: no v2 hash, SRM3: no
+/// leechers, SRM4: no announce count, SRM5: no pinned flag, SRM6: no tags / note, SRM7: no
+/// discovery source, SRM8: no release info); decoded and rewritten on read.
+const TORRENT_RECORD_MAGIC_V1: [u8; 4] = *b"SRM1";
+const TORRENT_RECORD_MAGIC_V2: [u8; 4] = *b"SRM2";
+const TORRENT_RECORD_MAGIC_V3: [u8; 4] = *b"SRM3";
+const TORRENT_RECORD_MAGIC_V4: [u8; 4] = *b"SRM4";
+const TORRENT_RECORD_MAGIC_V5: [u8; 4] = *b"SRM5";
+const TORRENT_RECORD_MAGIC_V6: [u8; 4] = *b"SRM6";
+const TORRENT_RECORD_MAGIC_V7: [u8; 4] = *b"SRM7";
+const TORRENT_RECORD_MAGIC_V8: [u8; 4] = *b"SRM8";
 const MISSING_INFO_TREE: &[u8] = b"idx_missing_info";
+const ENRICH_PRIORITY_TREE: &[u8] = b"idx_enrich_priority";
+const ENRICH_HOT_TREE: &[u8] = b"idx_enrich_hot";
 const LAST_SEEN_TREE: &[u8] = b"idx_last_seen";
 const LOW_SEED_TREE: &[u8] = b"idx_low_seed";
+const TOMBSTONE_TREE: &[u8] = b"tombstones";
+const TOMBSTONE_TIME_TREE: &[u8] = b"idx_tombstone_time";
+const BLACKLIST_TREE: &[u8] = b"blacklist";
+const ARCHIVE_TREE: &[u8] = b"archive";
+const SOURCE_SEEN_TREE: &[u8] = b"source_seen";
+const INFO_HASH_V2_TREE: &[u8] = b"idx_info_hash_v2";
+const RECHECK_TREE: &[u8] = b"idx_next_recheck";
+const ENRICH_ATTEMPT_TREE: &[u8] = b"enrich_attempts";
+const ENRICH_RETRY_TREE: &[u8] = b"idx_enrich_retry";
+const CLEANUP_HISTORY_TREE: &[u8] = b"cleanup_history";
+const SAVED_SEARCH_TREE: &[u8] = b"saved_searches";
+const SAVED_SEARCH_HIT_TREE: &[u8] = b"saved_search_hits";
+const BLOCK_RULE_TREE: &[u8] = b"block_rules";
+const SEEDER_HISTORY_TREE: &[u8] = b"seeder_history";
 const META_TREE: &[u8] = b"meta";
 const META_MISSING_INFO_BUILT_V1: &[u8] = b"missing_info_index_built_v1";
 const META_CLEANUP_INDEXES_BUILT_V1: &[u8] = b"cleanup_indexes_built_v1";
+const META_INFO_FIELDS_BUILT_V2: &[u8] = b"info_fields_built_v2";
+const META_RECHECK_INDEX_BUILT_V1: &[u8] = b"recheck_index_built_v1";
+const META_ENRICH_PRIORITY_BUILT_V1: &[u8] = b"enrich_priority_index_built_v1";
+const META_RECORD_COUNTERS_BUILT_V1: &[u8] = b"record_counters_built_v1";
+const META_RECORD_COUNT: &[u8] = b"record_count";
+const META_NO_INFO_COUNT: &[u8] = b"no_info_count";
+const META_COMPACTION: &[u8] = b"compaction";
+const META_INDEX_DIRTY: &[u8] = b"index_dirty";
+const META_SESSION_KEY: &[u8] = b"session_key";
+
+/// Most files kept per record; `file_count` and `size_bytes` still cover all of them.
+const MAX_STORED_FILES: usize = 1000;
+/// Limits on the admin-set tags and note of a record.
+const MAX_TAGS: usize = 32;
+const MAX_TAG_CHARS: usize = 48;
+pub const MAX_NOTE_CHARS: usize = 4000;
 
 fn bincode_opts() -> impl bincode::Options {
     // Varint encoding reduces disk usage for small integers.
@@ -26,25 +76,65 @@ fn encode_torrent_record(record: &TorrentRecord) -> anyhow::Result<Vec<u8>> {
     Ok(out)
 }
 
+/// Decodes a stored record. The flag is true if it used an older encoding and should be rewritten.
 fn decode_torrent_record(bytes: &[u8]) -> anyhow::Result<(TorrentRecord, bool)> {
     if bytes.starts_with(&TORRENT_RECORD_MAGIC) {
         let payload = &bytes[TORRENT_RECORD_MAGIC.len()..];
         let record: TorrentRecord = bincode_opts().deserialize(payload)?;
-        Ok((record, false))
+        return Ok((record, false));
+    }
+    // Layouts from before release parsing get it from their title on the way up.
+    let mut record = decode_old_torrent_record(bytes)?;
+    record.refresh_release();
+    Ok((record, true))
+}
+
+fn decode_old_torrent_record(bytes: &[u8]) -> anyhow::Result<TorrentRecord> {
+    if bytes.starts_with(&TORRENT_RECORD_MAGIC_V8) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V8.len()..];
+        let record: TorrentRecordV8 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
+    } else if bytes.starts_with(&TORRENT_RECORD_MAGIC_V7) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V7.len()..];
+        let record: TorrentRecordV7 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
+    } else if bytes.starts_with(&TORRENT_RECORD_MAGIC_V6) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V6.len()..];
+        let record: TorrentRecordV6 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
+    } else if bytes.starts_with(&TORRENT_RECORD_MAGIC_V5) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V5.len()..];
+        let record: TorrentRecordV5 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
+    } else if bytes.starts_with(&TORRENT_RECORD_MAGIC_V4) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V4.len()..];
+        let record: TorrentRecordV4 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
+    } else if bytes.starts_with(&TORRENT_RECORD_MAGIC_V3) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V3.len()..];
+        let record: TorrentRecordV3 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
+    } else if bytes.starts_with(&TORRENT_RECORD_MAGIC_V2) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V2.len()..];
+        let record: TorrentRecordV2 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
+    } else if bytes.starts_with(&TORRENT_RECORD_MAGIC_V1) {
+        let payload = &bytes[TORRENT_RECORD_MAGIC_V1.len()..];
+        let record: TorrentRecordV1 = bincode_opts().deserialize(payload)?;
+        Ok(record.into())
     } else {
         // Backward-compat: legacy JSON values.
-        let record: TorrentRecord = serde_json::from_slice(bytes)?;
-        Ok((record, true))
+        Ok(serde_json::from_slice(bytes)?)
     }
 }
 
 pub fn decode_torrent_record_maybe_migrate(
-    db: &sled::Db,
+    db: &Db,
     key: &[u8],
     bytes: &[u8],
 ) -> anyhow::Result<TorrentRecord> {
-    let (record, was_json) = decode_torrent_record(bytes)?;
-    if was_json {
+    let (record, outdated) = decode_torrent_record(bytes)?;
+    if outdated {
         match encode_torrent_record(&record) {
             Ok(new_bytes) => {
                 if let Err(e) = db.insert(key, new_bytes) {
@@ -59,6 +149,8 @@ pub fn decode_torrent_record_maybe_migrate(
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TorrentRecord {
+    /// The v1 info hash, or the truncated v2 hash for v2-only torrents (BEP 52);
+    /// either way the 20-byte hash peers and the DHT use.
     pub info_hash_hex: String,
     pub title: Option<String>,
     pub magnet: Option<String>,
@@ -67,6 +159,599 @@ pub struct TorrentRecord {
     pub info_bencode_base64: Option<String>,
     pub first_seen_unix_ms: i64,
     pub last_seen_unix_ms: i64,
+    /// Total size in bytes from the info dict (0 until metadata is known).
+    #[serde(default)]
+    pub size_bytes: u64,
+    /// Number of files in the torrent (0 until metadata is known).
+    #[serde(default)]
+    pub file_count: u64,
+    /// File paths (relative to the torrent name) and sizes, at most MAX_STORED_FILES.
+    #[serde(default)]
+    pub files: Vec<FileEntry>,
+    /// Full SHA-256 info hash of v2 and hybrid torrents, once known.
+    #[serde(default)]
+    pub info_hash_v2_hex: Option<String>,
+    /// Leechers from tracker scrapes or BEP-33 DHT estimates (0 if unknown).
+    #[serde(default)]
+    pub leechers: i64,
+    /// Recent DHT announces / peer lookups the spider saw for this hash (decaying estimate).
+    #[serde(default)]
+    pub announces: u64,
+    /// Kept through cleanup and max-record eviction, whatever its age or seeders.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Free-form labels set by an admin, normalized by `normalize_tag`; searchable as `tag:`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Free-form admin note shown on the torrent page.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// How the hash first reached us (`first_seen_unix_ms` says when); set when the record
+    /// is created, `None` for records older than the field.
+    #[serde(default)]
+    pub discovered_via: Option<Discovery>,
+    /// Resolution, codec, season/episode, year and group parsed from the title (see
+    /// `release::parse`); searchable as `resolution:`, `codec:`, `season:` and so on.
+    #[serde(default)]
+    pub release: Option<crate::release::Release>,
+}
+
+/// Where a hash came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Discovery {
+    /// A DHT `get_peers` / `announce_peer` query to the spider.
+    Announce,
+    /// A BEP-51 `sample_infohashes` answer.
+    Sample,
+    /// `serma ingest`, a file in the watch folder or stdin.
+    File,
+    /// `/api/ingest`, `/api/upload` or `POST /api/torrents`.
+    Api,
+    /// A hash list or RSS feed (SERMA_SOURCES).
+    Feed,
+    /// `serma import` of a magnetico / bitmagnet catalog.
+    Import,
+    /// Pulled from another instance (SERMA_SYNC_FROM).
+    Sync,
+}
+
+impl Discovery {
+    pub const ALL: [Discovery; 7] = [
+        Discovery::Announce,
+        Discovery::Sample,
+        Discovery::File,
+        Discovery::Api,
+        Discovery::Feed,
+        Discovery::Import,
+        Discovery::Sync,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Discovery::Announce => "announce",
+            Discovery::Sample => "sample",
+            Discovery::File => "file",
+            Discovery::Api => "api",
+            Discovery::Feed => "feed",
+            Discovery::Import => "import",
+            Discovery::Sync => "sync",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|d| d.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// For the torrent page.
+    pub fn label(self) -> &'static str {
+        match self {
+            Discovery::Announce => "DHT announce",
+            Discovery::Sample => "DHT sample (BEP-51)",
+            Discovery::File => "Ingested file",
+            Discovery::Api => "HTTP API",
+            Discovery::Feed => "Feed",
+            Discovery::Import => "Catalog import",
+            Discovery::Sync => "Sync",
+        }
+    }
+}
+
+impl std::str::FromStr for Discovery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s).ok_or_else(|| {
+            let names: Vec<&str> = Self::ALL.iter().map(|d| d.as_str()).collect();
+            format!("expected one of {}", names.join(", "))
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: String,
+    pub length: u64,
+}
+
+/// Record layout stored under the `SRM1` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV1 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+}
+
+impl From<TorrentRecordV1> for TorrentRecord {
+    fn from(v1: TorrentRecordV1) -> Self {
+        let mut record = TorrentRecord {
+            info_hash_hex: v1.info_hash_hex,
+            title: v1.title,
+            magnet: v1.magnet,
+            seeders: v1.seeders,
+            info_bencode_base64: v1.info_bencode_base64,
+            first_seen_unix_ms: v1.first_seen_unix_ms,
+            last_seen_unix_ms: v1.last_seen_unix_ms,
+            size_bytes: 0,
+            file_count: 0,
+            files: Vec::new(),
+            info_hash_v2_hex: None,
+            leechers: 0,
+            announces: 0,
+            pinned: false,
+            tags: Vec::new(),
+            note: None,
+            discovered_via: None,
+            release: None,
+        };
+        fill_from_info(&mut record);
+        record
+    }
+}
+
+/// Record layout stored under the `SRM2` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV2 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+    size_bytes: u64,
+    file_count: u64,
+    files: Vec<FileEntry>,
+}
+
+impl From<TorrentRecordV2> for TorrentRecord {
+    fn from(v2: TorrentRecordV2) -> Self {
+        let mut record = TorrentRecord {
+            info_hash_hex: v2.info_hash_hex,
+            title: v2.title,
+            magnet: v2.magnet,
+            seeders: v2.seeders,
+            info_bencode_base64: v2.info_bencode_base64,
+            first_seen_unix_ms: v2.first_seen_unix_ms,
+            last_seen_unix_ms: v2.last_seen_unix_ms,
+            size_bytes: v2.size_bytes,
+            file_count: v2.file_count,
+            files: v2.files,
+            info_hash_v2_hex: None,
+            leechers: 0,
+            announces: 0,
+            pinned: false,
+            tags: Vec::new(),
+            note: None,
+            discovered_via: None,
+            release: None,
+        };
+        fill_from_info(&mut record);
+        record
+    }
+}
+
+/// Record layout stored under the `SRM3` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV3 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+    size_bytes: u64,
+    file_count: u64,
+    files: Vec<FileEntry>,
+    info_hash_v2_hex: Option<String>,
+}
+
+impl From<TorrentRecordV3> for TorrentRecord {
+    fn from(v3: TorrentRecordV3) -> Self {
+        TorrentRecord {
+            info_hash_hex: v3.info_hash_hex,
+            title: v3.title,
+            magnet: v3.magnet,
+            seeders: v3.seeders,
+            info_bencode_base64: v3.info_bencode_base64,
+            first_seen_unix_ms: v3.first_seen_unix_ms,
+            last_seen_unix_ms: v3.last_seen_unix_ms,
+            size_bytes: v3.size_bytes,
+            file_count: v3.file_count,
+            files: v3.files,
+            info_hash_v2_hex: v3.info_hash_v2_hex,
+            leechers: 0,
+            announces: 0,
+            pinned: false,
+            tags: Vec::new(),
+            note: None,
+            discovered_via: None,
+            release: None,
+        }
+    }
+}
+
+/// Record layout stored under the `SRM4` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV4 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+    size_bytes: u64,
+    file_count: u64,
+    files: Vec<FileEntry>,
+    info_hash_v2_hex: Option<String>,
+    leechers: i64,
+}
+
+impl From<TorrentRecordV4> for TorrentRecord {
+    fn from(v4: TorrentRecordV4) -> Self {
+        TorrentRecord {
+            info_hash_hex: v4.info_hash_hex,
+            title: v4.title,
+            magnet: v4.magnet,
+            seeders: v4.seeders,
+            info_bencode_base64: v4.info_bencode_base64,
+            first_seen_unix_ms: v4.first_seen_unix_ms,
+            last_seen_unix_ms: v4.last_seen_unix_ms,
+            size_bytes: v4.size_bytes,
+            file_count: v4.file_count,
+            files: v4.files,
+            info_hash_v2_hex: v4.info_hash_v2_hex,
+            leechers: v4.leechers,
+            announces: 0,
+            pinned: false,
+            tags: Vec::new(),
+            note: None,
+            discovered_via: None,
+            release: None,
+        }
+    }
+}
+
+/// Record layout stored under the `SRM5` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV5 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+    size_bytes: u64,
+    file_count: u64,
+    files: Vec<FileEntry>,
+    info_hash_v2_hex: Option<String>,
+    leechers: i64,
+    announces: u64,
+}
+
+impl From<TorrentRecordV5> for TorrentRecord {
+    fn from(v5: TorrentRecordV5) -> Self {
+        TorrentRecord {
+            info_hash_hex: v5.info_hash_hex,
+            title: v5.title,
+            magnet: v5.magnet,
+            seeders: v5.seeders,
+            info_bencode_base64: v5.info_bencode_base64,
+            first_seen_unix_ms: v5.first_seen_unix_ms,
+            last_seen_unix_ms: v5.last_seen_unix_ms,
+            size_bytes: v5.size_bytes,
+            file_count: v5.file_count,
+            files: v5.files,
+            info_hash_v2_hex: v5.info_hash_v2_hex,
+            leechers: v5.leechers,
+            announces: v5.announces,
+            pinned: false,
+            tags: Vec::new(),
+            note: None,
+            discovered_via: None,
+            release: None,
+        }
+    }
+}
+
+/// Record layout stored under the `SRM6` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV6 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+    size_bytes: u64,
+    file_count: u64,
+    files: Vec<FileEntry>,
+    info_hash_v2_hex: Option<String>,
+    leechers: i64,
+    announces: u64,
+    pinned: bool,
+}
+
+impl From<TorrentRecordV6> for TorrentRecord {
+    fn from(v6: TorrentRecordV6) -> Self {
+        TorrentRecord {
+            info_hash_hex: v6.info_hash_hex,
+            title: v6.title,
+            magnet: v6.magnet,
+            seeders: v6.seeders,
+            info_bencode_base64: v6.info_bencode_base64,
+            first_seen_unix_ms: v6.first_seen_unix_ms,
+            last_seen_unix_ms: v6.last_seen_unix_ms,
+            size_bytes: v6.size_bytes,
+            file_count: v6.file_count,
+            files: v6.files,
+            info_hash_v2_hex: v6.info_hash_v2_hex,
+            leechers: v6.leechers,
+            announces: v6.announces,
+            pinned: v6.pinned,
+            tags: Vec::new(),
+            note: None,
+            discovered_via: None,
+            release: None,
+        }
+    }
+}
+
+/// Record layout stored under the `SRM7` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV7 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+    size_bytes: u64,
+    file_count: u64,
+    files: Vec<FileEntry>,
+    info_hash_v2_hex: Option<String>,
+    leechers: i64,
+    announces: u64,
+    pinned: bool,
+    tags: Vec<String>,
+    note: Option<String>,
+}
+
+impl From<TorrentRecordV7> for TorrentRecord {
+    fn from(v7: TorrentRecordV7) -> Self {
+        TorrentRecord {
+            info_hash_hex: v7.info_hash_hex,
+            title: v7.title,
+            magnet: v7.magnet,
+            seeders: v7.seeders,
+            info_bencode_base64: v7.info_bencode_base64,
+            first_seen_unix_ms: v7.first_seen_unix_ms,
+            last_seen_unix_ms: v7.last_seen_unix_ms,
+            size_bytes: v7.size_bytes,
+            file_count: v7.file_count,
+            files: v7.files,
+            info_hash_v2_hex: v7.info_hash_v2_hex,
+            leechers: v7.leechers,
+            announces: v7.announces,
+            pinned: v7.pinned,
+            tags: v7.tags,
+            note: v7.note,
+            discovered_via: None,
+            release: None,
+        }
+    }
+}
+
+/// Record layout stored under the `SRM8` magic.
+#[derive(Deserialize)]
+struct TorrentRecordV8 {
+    info_hash_hex: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    info_bencode_base64: Option<String>,
+    first_seen_unix_ms: i64,
+    last_seen_unix_ms: i64,
+    size_bytes: u64,
+    file_count: u64,
+    files: Vec<FileEntry>,
+    info_hash_v2_hex: Option<String>,
+    leechers: i64,
+    announces: u64,
+    pinned: bool,
+    tags: Vec<String>,
+    note: Option<String>,
+    discovered_via: Option<Discovery>,
+}
+
+impl From<TorrentRecordV8> for TorrentRecord {
+    fn from(v8: TorrentRecordV8) -> Self {
+        TorrentRecord {
+            info_hash_hex: v8.info_hash_hex,
+            title: v8.title,
+            magnet: v8.magnet,
+            seeders: v8.seeders,
+            info_bencode_base64: v8.info_bencode_base64,
+            first_seen_unix_ms: v8.first_seen_unix_ms,
+            last_seen_unix_ms: v8.last_seen_unix_ms,
+            size_bytes: v8.size_bytes,
+            file_count: v8.file_count,
+            files: v8.files,
+            info_hash_v2_hex: v8.info_hash_v2_hex,
+            leechers: v8.leechers,
+            announces: v8.announces,
+            pinned: v8.pinned,
+            tags: v8.tags,
+            note: v8.note,
+            discovered_via: v8.discovered_via,
+            release: None,
+        }
+    }
+}
+
+impl TorrentRecord {
+    /// A record with only the hash set, first/last seen at `now_unix_ms`.
+    pub fn new(info_hash_hex: &str, now_unix_ms: i64) -> Self {
+        TorrentRecord {
+            info_hash_hex: info_hash_hex.to_string(),
+            title: None,
+            magnet: None,
+            seeders: 0,
+            info_bencode_base64: None,
+            first_seen_unix_ms: now_unix_ms,
+            last_seen_unix_ms: now_unix_ms,
+            size_bytes: 0,
+            file_count: 0,
+            files: Vec::new(),
+            info_hash_v2_hex: None,
+            leechers: 0,
+            announces: 0,
+            pinned: false,
+            tags: Vec::new(),
+            note: None,
+            discovered_via: None,
+            release: None,
+        }
+    }
+
+    /// Re-parses `release` from the current title.
+    pub fn refresh_release(&mut self) {
+        self.release = self.title.as_deref().and_then(crate::release::parse);
+    }
+
+    /// Swarm size: seeders plus leechers.
+    pub fn peers(&self) -> i64 {
+        self.seeders.max(0) + self.leechers.max(0)
+    }
+}
+
+/// Total size and file list of a bencoded info dict (v1, hybrid or v2).
+pub fn files_from_info(info_bencode: &[u8]) -> anyhow::Result<(u64, Vec<FileEntry>)> {
+    let info = bencode::decode(info_bencode)?;
+    let mut files = Vec::new();
+
+    if let Some(length) = info.get(b"length").and_then(|v| v.as_integer()) {
+        let name = info
+            .get(b"name.utf-8")
+            .and_then(|v| v.as_str())
+            .or_else(|| info.get(b"name").and_then(|v| v.as_str()))
+            .unwrap_or_default();
+        files.push(FileEntry {
+            path: name.to_string(),
+            length: length.max(0) as u64,
+        });
+    } else if let Some(list) = info.get(b"files").and_then(|v| v.as_list()) {
+        for file in list {
+            // BEP 47 padding files aren't real content.
+            if file
+                .get(b"attr")
+                .and_then(|v| v.as_str())
+                .is_some_and(|attr| attr.contains('p'))
+            {
+                continue;
+            }
+            let length = file.get(b"length").and_then(|v| v.as_integer()).unwrap_or(0);
+            let path = file
+                .get(b"path.utf-8")
+                .or_else(|| file.get(b"path"))
+                .and_then(|v| v.as_list())
+                .map(|parts| parts.iter().map(lossy_string).collect::<Vec<_>>().join("/"))
+                .unwrap_or_default();
+            files.push(FileEntry {
+                path,
+                length: length.max(0) as u64,
+            });
+        }
+    } else if let Some(tree) = info.get(b"file tree") {
+        // v2-only: nested directories, each file a dict under the empty key.
+        collect_file_tree(tree, "", &mut files);
+    }
+
+    let size = files.iter().map(|f| f.length).sum();
+    Ok((size, files))
+}
+
+fn collect_file_tree(node: &bencode::Value, prefix: &str, files: &mut Vec<FileEntry>) {
+    let Some(entries) = node.as_dict() else {
+        return;
+    };
+    for (name, child) in entries {
+        if name.is_empty() {
+            let length = child.get(b"length").and_then(|v| v.as_integer()).unwrap_or(0);
+            files.push(FileEntry {
+                path: prefix.to_string(),
+                length: length.max(0) as u64,
+            });
+            continue;
+        }
+        let name = String::from_utf8_lossy(name);
+        let path = if prefix.is_empty() { name.into_owned() } else { format!("{prefix}/{name}") };
+        collect_file_tree(child, &path, files);
+    }
+}
+
+/// Whether a bencoded info dict is v2 or hybrid (`meta version` 2).
+pub fn is_v2_info(info_bencode: &[u8]) -> bool {
+    bencode::decode(info_bencode)
+        .ok()
+        .and_then(|info| info.get(b"meta version").and_then(|v| v.as_integer()))
+        == Some(2)
+}
+
+fn lossy_string(value: &bencode::Value) -> String {
+    value
+        .as_bytes()
+        .map(|b| String::from_utf8_lossy(b).into_owned())
+        .unwrap_or_default()
+}
+
+/// Sets size / file count / file list and the v2 hash from the stored info dict, if any.
+pub fn fill_from_info(record: &mut TorrentRecord) {
+    let Some(info) = record
+        .info_bencode_base64
+        .as_deref()
+        .and_then(|b64| base64::engine::general_purpose::STANDARD.decode(b64).ok())
+    else {
+        return;
+    };
+    if let Ok((size, mut files)) = files_from_info(&info) {
+        record.size_bytes = size;
+        record.file_count = files.len() as u64;
+        files.truncate(MAX_STORED_FILES);
+        record.files = files;
+    }
+    if record.info_hash_v2_hex.is_none() && is_v2_info(&info) {
+        record.info_hash_v2_hex = Some(rbit::metainfo::InfoHashV2::from_info_bytes(&info).to_hex());
+    }
 }
 
 fn now_unix_ms() -> i64 {
@@ -106,55 +791,216 @@ fn parse_ts_key(key: &[u8]) -> Option<(i64, String)> {
     Some((ts, hash))
 }
 
-fn has_info(record: &TorrentRecord) -> bool {
+pub fn has_info(record: &TorrentRecord) -> bool {
     record
         .info_bencode_base64
         .as_deref()
         .is_some_and(|s| !s.trim().is_empty())
 }
 
-fn missing_info_tree(db: &sled::Db) -> sled::Result<sled::Tree> {
+fn missing_info_tree(db: &Db) -> anyhow::Result<Tree> {
     db.open_tree(MISSING_INFO_TREE)
 }
 
-fn last_seen_tree(db: &sled::Db) -> sled::Result<sled::Tree> {
+fn enrich_priority_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(ENRICH_PRIORITY_TREE)
+}
+
+fn enrich_hot_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(ENRICH_HOT_TREE)
+}
+
+fn last_seen_tree(db: &Db) -> anyhow::Result<Tree> {
     db.open_tree(LAST_SEEN_TREE)
 }
 
-fn low_seed_tree(db: &sled::Db) -> sled::Result<sled::Tree> {
+fn low_seed_tree(db: &Db) -> anyhow::Result<Tree> {
     db.open_tree(LOW_SEED_TREE)
 }
 
-fn meta_tree(db: &sled::Db) -> sled::Result<sled::Tree> {
+fn tombstone_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(TOMBSTONE_TREE)
+}
+
+fn tombstone_time_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(TOMBSTONE_TIME_TREE)
+}
+
+fn blacklist_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(BLACKLIST_TREE)
+}
+
+fn archive_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(ARCHIVE_TREE)
+}
+
+fn source_seen_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(SOURCE_SEEN_TREE)
+}
+
+fn meta_tree(db: &Db) -> anyhow::Result<Tree> {
     db.open_tree(META_TREE)
 }
 
-fn sync_missing_info_index(db: &sled::Db, record: &TorrentRecord) -> anyhow::Result<()> {
-    let tree = missing_info_tree(db)?;
-    let key = record.info_hash_hex.as_bytes();
+fn info_hash_v2_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(INFO_HASH_V2_TREE)
+}
+
+fn block_rule_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(BLOCK_RULE_TREE)
+}
+
+fn saved_search_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(SAVED_SEARCH_TREE)
+}
+
+fn saved_search_hit_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(SAVED_SEARCH_HIT_TREE)
+}
+
+fn recheck_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(RECHECK_TREE)
+}
+
+fn enrich_attempt_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(ENRICH_ATTEMPT_TREE)
+}
+
+fn enrich_retry_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(ENRICH_RETRY_TREE)
+}
+
+fn cleanup_history_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(CLEANUP_HISTORY_TREE)
+}
+
+fn seeder_history_tree(db: &Db) -> anyhow::Result<Tree> {
+    db.open_tree(SEEDER_HISTORY_TREE)
+}
+
+fn sync_info_hash_v2_index(db: &Db, record: &TorrentRecord) -> anyhow::Result<()> {
+    if let Some(v2) = record.info_hash_v2_hex.as_deref() {
+        info_hash_v2_tree(db)?.insert(v2.as_bytes(), record.info_hash_hex.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn sync_missing_info_index(db: &Db, record: &TorrentRecord) -> anyhow::Result<()> {
     if has_info(record) {
-        let _ = tree.remove(key)?;
-    } else {
-        // Value is unused; presence of key indicates "needs enrich".
-        tree.insert(key, &[])?;
+        dequeue_missing_info(db, &record.info_hash_hex)?;
+        clear_enrich_attempts(db, &record.info_hash_hex)?;
+    } else if !enrich_attempt_tree(db)?.contains_key(record.info_hash_hex.as_bytes())? {
+        // Hashes already tried wait for their retry (`release_due_enrich_retries`) instead.
+        enqueue_missing_info(db, record)?;
+    }
+    Ok(())
+}
+
+/// Enrich queue order (`idx_enrich_priority`): most recently seen first, with each doubling
+/// of the announce count worth an hour of recency, so popular hashes get titles first.
+fn enrich_priority_key(record: &TorrentRecord) -> Vec<u8> {
+    let boost = record.announces.saturating_add(1).ilog2() as i64 * 60 * 60 * 1000;
+    let priority = record.last_seen_unix_ms.max(0).saturating_add(boost) as u64;
+    let mut out = Vec::with_capacity(8 + record.info_hash_hex.len());
+    out.extend_from_slice(&u64_be(u64::MAX - priority));
+    out.extend_from_slice(record.info_hash_hex.as_bytes());
+    out
+}
+
+/// Queues a record for enrichment, or moves it to its new place in the queue. The
+/// missing-info entry holds the record's current priority key.
+fn enqueue_missing_info(db: &Db, record: &TorrentRecord) -> anyhow::Result<()> {
+    let key = enrich_priority_key(record);
+    let old = missing_info_tree(db)?.insert(record.info_hash_hex.as_bytes(), key.as_slice())?;
+    if old.as_deref() == Some(key.as_slice()) {
+        return Ok(());
+    }
+    let priority = enrich_priority_tree(db)?;
+    if let Some(old) = old.filter(|old| !old.is_empty()) {
+        let _ = priority.remove(old)?;
+    }
+    priority.insert(key, [])?;
+    Ok(())
+}
+
+fn dequeue_missing_info(db: &Db, info_hash_hex: &str) -> anyhow::Result<()> {
+    if let Some(old) = missing_info_tree(db)?.remove(info_hash_hex.as_bytes())?
+        && !old.is_empty()
+    {
+        let _ = enrich_priority_tree(db)?.remove(old)?;
+    }
+    Ok(())
+}
+
+// Record totals are kept as counters in `meta` and adjusted on every write, so stats
+// don't have to walk the records. `ensure_record_counters` seeds them once.
+
+fn adjust_counter(db: &Db, key: &[u8], delta: i64) -> anyhow::Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+    meta_tree(db)?.update_and_fetch(key, |old| {
+        let old = old.and_then(|v| <[u8; 8]>::try_from(v).ok()).map_or(0, u64::from_be_bytes);
+        Some(u64_be(old.saturating_add_signed(delta)).to_vec())
+    })?;
+    Ok(())
+}
+
+fn read_counter(db: &Db, key: &[u8]) -> anyhow::Result<u64> {
+    Ok(meta_tree(db)?
+        .get(key)?
+        .and_then(|v| <[u8; 8]>::try_from(v.as_ref()).ok())
+        .map_or(0, u64::from_be_bytes))
+}
+
+/// Moves the counters for one record going from `before` to `after` (`None`: absent).
+fn sync_record_counters(db: &Db, before: Option<&TorrentRecord>, after: Option<&TorrentRecord>) -> anyhow::Result<()> {
+    let no_info = |r: Option<&TorrentRecord>| r.is_some_and(|r| !has_info(r)) as i64;
+    adjust_counter(db, META_RECORD_COUNT, after.is_some() as i64 - before.is_some() as i64)?;
+    adjust_counter(db, META_NO_INFO_COUNT, no_info(after) - no_info(before))?;
+    Ok(())
+}
+
+/// Stores a record and brings the counters and secondary indexes in line with it. The
+/// previous value comes from the write itself, so two writers racing on one hash each
+/// account for exactly the record they replaced. Returns that previous record.
+fn put_record(db: &Db, record: &TorrentRecord) -> anyhow::Result<Option<TorrentRecord>> {
+    let before = db
+        .insert(key_for_hash(&record.info_hash_hex), encode_torrent_record(record)?)?
+        .and_then(|b| decode_torrent_record(&b).ok())
+        .map(|(r, _)| r);
+    sync_record_counters(db, before.as_ref(), Some(record))?;
+    sync_missing_info_index(db, record)?;
+    sync_last_seen_index(db, before.as_ref(), record)?;
+    sync_low_seed_index(db, before.as_ref(), record)?;
+    sync_info_hash_v2_index(db, record)?;
+    sync_recheck_index(db, before.as_ref(), record)?;
+    Ok(before)
+}
+
+/// Queues a torrent for periodic seeder re-checks once its metadata arrives. Keys are
+/// `ts_key(last refresh, hash)`; the re-check worker takes the oldest.
+fn sync_recheck_index(db: &Db, before: Option<&TorrentRecord>, after: &TorrentRecord) -> anyhow::Result<()> {
+    if has_info(after) && !before.is_some_and(has_info) {
+        recheck_tree(db)?.insert(ts_key(now_unix_ms(), &after.info_hash_hex), [])?;
     }
     Ok(())
 }
 
-fn sync_last_seen_index(db: &sled::Db, before: Option<&TorrentRecord>, after: &TorrentRecord) -> anyhow::Result<()> {
+fn sync_last_seen_index(db: &Db, before: Option<&TorrentRecord>, after: &TorrentRecord) -> anyhow::Result<()> {
     let tree = last_seen_tree(db)?;
 
-    if let Some(before) = before {
-        if before.last_seen_unix_ms != after.last_seen_unix_ms {
-            let _ = tree.remove(ts_key(before.last_seen_unix_ms, &before.info_hash_hex))?;
-        }
+    if let Some(before) = before
+        && before.last_seen_unix_ms != after.last_seen_unix_ms
+    {
+        let _ = tree.remove(ts_key(before.last_seen_unix_ms, &before.info_hash_hex))?;
     }
 
-    tree.insert(ts_key(after.last_seen_unix_ms, &after.info_hash_hex), &[])?;
+    tree.insert(ts_key(after.last_seen_unix_ms, &after.info_hash_hex), [])?;
     Ok(())
 }
 
-fn sync_low_seed_index(db: &sled::Db, before: Option<&TorrentRecord>, after: &TorrentRecord) -> anyhow::Result<()> {
+fn sync_low_seed_index(db: &Db, before: Option<&TorrentRecord>, after: &TorrentRecord) -> anyhow::Result<()> {
     let tree = low_seed_tree(db)?;
     let key = ts_key(after.first_seen_unix_ms, &after.info_hash_hex);
 
@@ -166,7 +1012,7 @@ fn sync_low_seed_index(db: &sled::Db, before: Option<&TorrentRecord>, after: &To
             let _ = tree.remove(key)?;
         }
         (false, true) => {
-            tree.insert(key, &[])?;
+            tree.insert(key, [])?;
         }
         (true, true) => {
             // First-seen is immutable; no-op.
@@ -176,12 +1022,12 @@ fn sync_low_seed_index(db: &sled::Db, before: Option<&TorrentRecord>, after: &To
     Ok(())
 }
 
-pub fn cleanup_last_seen_tree(db: &sled::Db) -> anyhow::Result<sled::Tree> {
-    Ok(last_seen_tree(db)?)
+pub fn cleanup_last_seen_tree(db: &Db) -> anyhow::Result<Tree> {
+    last_seen_tree(db)
 }
 
-pub fn cleanup_low_seed_tree(db: &sled::Db) -> anyhow::Result<sled::Tree> {
-    Ok(low_seed_tree(db)?)
+pub fn cleanup_low_seed_tree(db: &Db) -> anyhow::Result<Tree> {
+    low_seed_tree(db)
 }
 
 pub fn end_key_for_ts(ts_unix_ms: i64) -> Vec<u8> {
@@ -198,20 +1044,20 @@ pub fn parse_cleanup_index_key(key: &[u8]) -> Option<(i64, String)> {
 }
 
 pub fn fix_last_seen_index_entry(
-    db: &sled::Db,
+    db: &Db,
     indexed_last_seen_unix_ms: i64,
     record: &TorrentRecord,
 ) -> anyhow::Result<()> {
     let tree = last_seen_tree(db)?;
     if indexed_last_seen_unix_ms != record.last_seen_unix_ms {
         let _ = tree.remove(ts_key(indexed_last_seen_unix_ms, &record.info_hash_hex))?;
-        tree.insert(ts_key(record.last_seen_unix_ms, &record.info_hash_hex), &[])?;
+        tree.insert(ts_key(record.last_seen_unix_ms, &record.info_hash_hex), [])?;
     }
     Ok(())
 }
 
 pub fn fix_low_seed_index_entry(
-    db: &sled::Db,
+    db: &Db,
     indexed_first_seen_unix_ms: i64,
     record: &TorrentRecord,
 ) -> anyhow::Result<()> {
@@ -223,16 +1069,16 @@ pub fn fix_low_seed_index_entry(
 
     if indexed_first_seen_unix_ms != record.first_seen_unix_ms {
         let _ = tree.remove(ts_key(indexed_first_seen_unix_ms, &record.info_hash_hex))?;
-        tree.insert(ts_key(record.first_seen_unix_ms, &record.info_hash_hex), &[])?;
+        tree.insert(ts_key(record.first_seen_unix_ms, &record.info_hash_hex), [])?;
     }
     Ok(())
 }
 
 /// Ensures the missing-info index exists and is populated.
 ///
-/// This replaces the previous runtime O(n) scan in `list_missing_info` with an indexed lookup.
+/// This replaces the previous runtime O(n) scan in `take_missing_info` with an indexed lookup.
 /// Rebuilding can still be O(n) once, but happens only on first startup after upgrade.
-pub fn ensure_missing_info_index(db: &sled::Db) -> anyhow::Result<()> {
+pub fn ensure_missing_info_index(db: &Db) -> anyhow::Result<()> {
     let meta = meta_tree(db)?;
     if meta.get(META_MISSING_INFO_BUILT_V1)?.is_some() {
         return Ok(());
@@ -248,7 +1094,7 @@ pub fn ensure_missing_info_index(db: &sled::Db) -> anyhow::Result<()> {
         if has_info(&record) {
             let _ = tree.remove(record.info_hash_hex.as_bytes())?;
         } else {
-            tree.insert(record.info_hash_hex.as_bytes(), &[])?;
+            tree.insert(record.info_hash_hex.as_bytes(), [])?;
             missing_count += 1;
         }
     }
@@ -258,13 +1104,44 @@ pub fn ensure_missing_info_index(db: &sled::Db) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// One-time migration: orders the existing enrich queue by priority (`enrich_priority_key`).
+pub fn ensure_enrich_priority_index(db: &Db) -> anyhow::Result<()> {
+    let meta = meta_tree(db)?;
+    if meta.get(META_ENRICH_PRIORITY_BUILT_V1)?.is_some() {
+        return Ok(());
+    }
+
+    let mut queued: usize = 0;
+    for item in missing_info_tree(db)?.iter().keys() {
+        let hash_bytes = item?;
+        let record = match std::str::from_utf8(&hash_bytes) {
+            Ok(hash) => get(db, hash)?,
+            Err(_) => None,
+        };
+        match record {
+            Some(record) if !has_info(&record) => {
+                enqueue_missing_info(db, &record)?;
+                queued += 1;
+            }
+            // Deleted, already enriched or a corrupt key.
+            _ => {
+                let _ = missing_info_tree(db)?.remove(hash_bytes)?;
+            }
+        }
+    }
+
+    meta.insert(META_ENRICH_PRIORITY_BUILT_V1, b"1")?;
+    tracing::info!(queued, "storage: built enrich priority queue");
+    Ok(())
+}
+
 /// Ensures cleanup indexes exist and are populated.
 ///
 /// - `idx_last_seen`: ordered by `last_seen_unix_ms` for TTL pruning / eviction
 /// - `idx_low_seed`: ordered by `first_seen_unix_ms` for pruning low-seed stale entries
 ///
 /// This avoids periodic O(n) scans in the cleanup worker.
-pub fn ensure_cleanup_indexes(db: &sled::Db) -> anyhow::Result<()> {
+pub fn ensure_cleanup_indexes(db: &Db) -> anyhow::Result<()> {
     let meta = meta_tree(db)?;
     if meta.get(META_CLEANUP_INDEXES_BUILT_V1)?.is_some() {
         return Ok(());
@@ -279,9 +1156,9 @@ pub fn ensure_cleanup_indexes(db: &sled::Db) -> anyhow::Result<()> {
         let (k, v) = item?;
         total += 1;
         let record = decode_torrent_record_maybe_migrate(db, &k, &v)?;
-        last_seen.insert(ts_key(record.last_seen_unix_ms, &record.info_hash_hex), &[])?;
+        last_seen.insert(ts_key(record.last_seen_unix_ms, &record.info_hash_hex), [])?;
         if record.seeders < 2 {
-            low_seed.insert(ts_key(record.first_seen_unix_ms, &record.info_hash_hex), &[])?;
+            low_seed.insert(ts_key(record.first_seen_unix_ms, &record.info_hash_hex), [])?;
             low_seed_count += 1;
         }
     }
@@ -291,78 +1168,467 @@ pub fn ensure_cleanup_indexes(db: &sled::Db) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn upsert_first_seen(db: &sled::Db, info_hash_hex: &str) -> anyhow::Result<TorrentRecord> {
-    let key = key_for_hash(info_hash_hex);
-    let now = now_unix_ms();
-
-    let existing = db.get(&key)?;
-
-    let before = existing
-        .as_ref()
-        .and_then(|b| decode_torrent_record(b).ok())
-        .map(|(r, _)| r);
+/// Fills size, file lists and v2 hashes for records that got their info dict before
+/// these were stored, and indexes v2 hashes.
+///
+/// Older records are also upgraded lazily on read; this one-time pass makes sure the
+/// search index can be rebuilt with file names right after upgrading, and that v2
+/// hashes of existing records resolve.
+pub fn ensure_info_fields(db: &Db) -> anyhow::Result<()> {
+    let meta = meta_tree(db)?;
+    if meta.get(META_INFO_FIELDS_BUILT_V2)?.is_some() {
+        return Ok(());
+    }
 
-    let record = if let Some(bytes) = existing.as_ref() {
-        let mut record: TorrentRecord = decode_torrent_record(bytes)?.0;
-        record.last_seen_unix_ms = now;
-        record
-    } else {
-        TorrentRecord {
-            info_hash_hex: info_hash_hex.to_string(),
-            title: None,
-            magnet: None,
-            seeders: 0,
-            info_bencode_base64: None,
-            first_seen_unix_ms: now,
-            last_seen_unix_ms: now,
+    let mut total: usize = 0;
+    let mut filled: usize = 0;
+    for item in db.scan_prefix(b"torrent:") {
+        let (k, v) = item?;
+        total += 1;
+        let (mut record, outdated) = decode_torrent_record(&v)?;
+        let before = (record.files.len(), record.info_hash_v2_hex.clone());
+        if record.files.is_empty() || record.info_hash_v2_hex.is_none() {
+            fill_from_info(&mut record);
         }
-    };
+        if outdated || (record.files.len(), record.info_hash_v2_hex.clone()) != before {
+            db.insert(k, encode_torrent_record(&record)?)?;
+        }
+        sync_info_hash_v2_index(db, &record)?;
+        if !record.files.is_empty() {
+            filled += 1;
+        }
+    }
 
-    // Keep indexes consistent.
-    db.insert(key, encode_torrent_record(&record)?)?;
-    let _ = sync_missing_info_index(db, &record);
-    let _ = sync_last_seen_index(db, before.as_ref(), &record);
-    let _ = sync_low_seed_index(db, before.as_ref(), &record);
-    Ok(record)
+    meta.insert(META_INFO_FIELDS_BUILT_V2, b"1")?;
+    tracing::info!(total, filled, "storage: built file lists and v2 hash index");
+    Ok(())
 }
 
-pub fn list_missing_info(db: &sled::Db, limit: usize) -> anyhow::Result<Vec<TorrentRecord>> {
-    let tree = missing_info_tree(db)?;
-
-    // If the index hasn't been built yet (e.g. user upgraded but restarted without
-    // calling `ensure_missing_info_index`), we can still return quickly.
-    // Startup should call `ensure_missing_info_index`; this is just a safe fallback.
-    if tree.is_empty() {
-        return Ok(Vec::new());
+/// Queues every torrent with metadata for seeder re-checks, oldest last-seen first.
+pub fn ensure_recheck_index(db: &Db) -> anyhow::Result<()> {
+    let meta = meta_tree(db)?;
+    if meta.get(META_RECHECK_INDEX_BUILT_V1)?.is_some() {
+        return Ok(());
     }
 
-    let mut out = Vec::new();
-    for item in tree.iter() {
-        let (hash_bytes, _) = item?;
-        let hash_hex = std::str::from_utf8(&hash_bytes)
-            .ok()
-            .map(str::to_string);
-        let Some(hash_hex) = hash_hex else {
-            // Corrupt key; drop it.
-            let _ = tree.remove(hash_bytes)?;
+    let tree = recheck_tree(db)?;
+    let mut queued: usize = 0;
+    for item in db.scan_prefix(b"torrent:") {
+        let (k, v) = item?;
+        let record = decode_torrent_record_maybe_migrate(db, &k, &v)?;
+        if has_info(&record) {
+            tree.insert(ts_key(record.last_seen_unix_ms, &record.info_hash_hex), [])?;
+            queued += 1;
+        }
+    }
+
+    meta.insert(META_RECHECK_INDEX_BUILT_V1, b"1")?;
+    tracing::info!(queued, "storage: built re-check queue");
+    Ok(())
+}
+
+/// Seeds the record counters (`sync_record_counters`) for databases created before they
+/// were kept.
+pub fn ensure_record_counters(db: &Db) -> anyhow::Result<()> {
+    let meta = meta_tree(db)?;
+    if meta.get(META_RECORD_COUNTERS_BUILT_V1)?.is_some() {
+        return Ok(());
+    }
+
+    let mut total: u64 = 0;
+    let mut no_info: u64 = 0;
+    for item in db.scan_prefix(b"torrent:") {
+        let (k, v) = item?;
+        total += 1;
+        if !has_info(&decode_torrent_record_maybe_migrate(db, &k, &v)?) {
+            no_info += 1;
+        }
+    }
+
+    meta.insert(META_RECORD_COUNT, u64_be(total))?;
+    meta.insert(META_NO_INFO_COUNT, u64_be(no_info))?;
+    meta.insert(META_RECORD_COUNTERS_BUILT_V1, b"1")?;
+    tracing::info!(total, no_info, "storage: counted records");
+    Ok(())
+}
+
+/// The record database with its secondary indexes in place, for embedders; the functions
+/// in this module are the full API and take `db()`.
+#[derive(Clone)]
+pub struct Storage {
+    db: Db,
+}
+
+impl Storage {
+    /// Opens (or creates) the database under `data_dir`, building any secondary index an
+    /// older version didn't keep, so background tasks find work without full scans.
+    pub fn open(backend: Backend, data_dir: &Path) -> anyhow::Result<Self> {
+        let db = crate::kv::open(backend, data_dir)?;
+        ensure_missing_info_index(&db).context("build missing-info index")?;
+        ensure_enrich_priority_index(&db).context("build enrich priority queue")?;
+        ensure_cleanup_indexes(&db).context("build cleanup indexes")?;
+        ensure_info_fields(&db).context("build file lists and v2 hash index")?;
+        ensure_recheck_index(&db).context("build re-check queue")?;
+        ensure_record_counters(&db).context("count records")?;
+        Ok(Self { db })
+    }
+
+    /// Wraps a database that `open` has already prepared.
+    pub fn from_db(db: Db) -> Self {
+        Self { db }
+    }
+
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    pub fn get(&self, info_hash_hex: &str) -> anyhow::Result<Option<TorrentRecord>> {
+        get(&self.db, info_hash_hex)
+    }
+
+    /// Adds a hash (queued for metadata), or bumps its last-seen time if it is known.
+    pub fn add(&self, info_hash_hex: &str, via: Discovery) -> anyhow::Result<TorrentRecord> {
+        upsert_first_seen(&self.db, info_hash_hex, Some(via))
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = anyhow::Result<TorrentRecord>> + '_ {
+        scan_records(&self.db)
+    }
+
+    pub fn totals(&self) -> anyhow::Result<RecordTotals> {
+        record_totals(&self.db)
+    }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.db.flush()
+    }
+}
+
+/// Every stored record, in hash order.
+pub fn scan_records(db: &Db) -> impl Iterator<Item = anyhow::Result<TorrentRecord>> + '_ {
+    db.scan_prefix(b"torrent:").map(move |item| {
+        let (k, v) = item?;
+        decode_torrent_record_maybe_migrate(db, &k, &v)
+    })
+}
+
+/// Record totals from the maintained counters; no scanning.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RecordTotals {
+    pub records: u64,
+    /// Records without metadata, whether queued, backing off or parked.
+    pub missing_metadata: u64,
+}
+
+pub fn record_totals(db: &Db) -> anyhow::Result<RecordTotals> {
+    Ok(RecordTotals {
+        records: read_counter(db, META_RECORD_COUNT)?,
+        missing_metadata: read_counter(db, META_NO_INFO_COUNT)?,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct RecordCounts {
+    pub total: usize,
+    /// Records still waiting for metadata (the enrich queue).
+    pub missing_info: usize,
+    /// Records whose last lookup failed, waiting for their retry.
+    pub enrich_backoff: usize,
+    /// Records that ran out of attempts and are no longer looked up.
+    pub enrich_parked: usize,
+    pub tombstones: usize,
+    /// Records cleanup moved to the archive (`SERMA_CLEANUP_ARCHIVE`).
+    pub archived: usize,
+}
+
+/// Hashes waiting on the enrich queue (not backing off or parked). Walks the queue's keys.
+pub fn enrich_queue_len(db: &Db) -> anyhow::Result<usize> {
+    missing_info_tree(db)?.len()
+}
+
+/// Counts records by walking the queue trees' keys (no decoding); cheap enough for the
+/// admin page.
+pub fn record_counts(db: &Db) -> anyhow::Result<RecordCounts> {
+    let mut enrich_parked = 0;
+    for item in enrich_attempt_tree(db)?.iter().values() {
+        if decode_enrich_attempt(&item?).is_some_and(|(_, next)| next == ENRICH_PARKED) {
+            enrich_parked += 1;
+        }
+    }
+    Ok(RecordCounts {
+        total: read_counter(db, META_RECORD_COUNT)? as usize,
+        missing_info: missing_info_tree(db)?.len()?,
+        enrich_backoff: enrich_retry_tree(db)?.len()?,
+        enrich_parked,
+        tombstones: tombstone_tree(db)?.len()?,
+        archived: archive_tree(db)?.len()?,
+    })
+}
+
+/// Creates the record for a hash, or bumps its last-seen time. `via` is recorded as its
+/// discovery source if this creates it.
+pub fn upsert_first_seen(db: &Db, info_hash_hex: &str, via: Option<Discovery>) -> anyhow::Result<TorrentRecord> {
+    let now = now_unix_ms();
+    let record = match db.get(key_for_hash(info_hash_hex))? {
+        Some(bytes) => {
+            let mut record: TorrentRecord = decode_torrent_record(&bytes)?.0;
+            record.last_seen_unix_ms = now;
+            record
+        }
+        None => TorrentRecord {
+            discovered_via: via,
+            ..TorrentRecord::new(info_hash_hex, now)
+        },
+    };
+    put_record(db, &record)?;
+    Ok(record)
+}
+
+/// Upserts many records with one batched write per tree (records + secondary indexes).
+///
+/// `update(i, record)` is applied to the record for `hashes[i]` after last_seen is bumped;
+/// new records start out empty, discovered `via`. A hash repeated within the batch sees the
+/// earlier update. Returns one `(record, created)` pair per input hash.
+pub fn upsert_many(
+    db: &Db,
+    hashes: &[String],
+    via: Discovery,
+    mut update: impl FnMut(usize, &mut TorrentRecord),
+) -> anyhow::Result<Vec<(TorrentRecord, bool)>> {
+    let now = now_unix_ms();
+    // hash -> (state before this batch, current record)
+    let mut pending: std::collections::HashMap<&str, (Option<TorrentRecord>, TorrentRecord)> =
+        std::collections::HashMap::with_capacity(hashes.len());
+    let mut out = Vec::with_capacity(hashes.len());
+
+    for (i, hash) in hashes.iter().enumerate() {
+        let created = match pending.get(hash.as_str()) {
+            // Repeated within the batch: only the first occurrence counts as created.
+            Some(_) => false,
+            None => {
+                let before = match db.get(key_for_hash(hash))? {
+                    Some(bytes) => Some(decode_torrent_record(&bytes)?.0),
+                    None => None,
+                };
+                let current = before.clone().unwrap_or_else(|| TorrentRecord {
+                    discovered_via: Some(via),
+                    ..TorrentRecord::new(hash, now)
+                });
+                let created = before.is_none();
+                pending.insert(hash.as_str(), (before, current));
+                created
+            }
+        };
+
+        let (_, record) = pending.get_mut(hash.as_str()).expect("inserted above");
+        record.last_seen_unix_ms = now;
+        update(i, record);
+        out.push((record.clone(), created));
+    }
+
+    let mut records = Batch::default();
+    let mut last_seen = Batch::default();
+    let mut low_seed = Batch::default();
+    let mut info_hash_v2 = Batch::default();
+    let mut recheck = Batch::default();
+    for (before, after) in pending.values() {
+        records.insert(key_for_hash(&after.info_hash_hex), encode_torrent_record(after)?);
+        if let Some(v2) = after.info_hash_v2_hex.as_deref() {
+            info_hash_v2.insert(v2.as_bytes(), after.info_hash_hex.as_bytes());
+        }
+
+        if has_info(after) && !before.as_ref().is_some_and(has_info) {
+            recheck.insert(ts_key(now, &after.info_hash_hex), []);
+        }
+
+        if let Some(before) = before
+            && before.last_seen_unix_ms != after.last_seen_unix_ms
+        {
+            last_seen.remove(ts_key(before.last_seen_unix_ms, &before.info_hash_hex));
+        }
+        last_seen.insert(ts_key(after.last_seen_unix_ms, &after.info_hash_hex), []);
+
+        let before_low = before.as_ref().is_some_and(|r| r.seeders < 2);
+        let key = ts_key(after.first_seen_unix_ms, &after.info_hash_hex);
+        match (before_low, after.seeders < 2) {
+            (true, false) => low_seed.remove(key),
+            (false, true) => low_seed.insert(key, []),
+            _ => {}
+        }
+    }
+
+    db.apply_batch(records)?;
+    for (before, after) in pending.values() {
+        sync_record_counters(db, before.as_ref(), Some(after))?;
+        sync_missing_info_index(db, after)?;
+    }
+    last_seen_tree(db)?.apply_batch(last_seen)?;
+    low_seed_tree(db)?.apply_batch(low_seed)?;
+    info_hash_v2_tree(db)?.apply_batch(info_hash_v2)?;
+    recheck_tree(db)?.apply_batch(recheck)?;
+    Ok(out)
+}
+
+/// Enrichment retry schedule: after attempt n a hash waits `base_ms * 2^(n-1)`, capped at
+/// `max_ms`; after `max_attempts` it is parked.
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub base_ms: i64,
+    pub max_ms: i64,
+    pub max_attempts: u32,
+}
+
+/// Next-attempt time of a parked hash.
+const ENRICH_PARKED: i64 = i64::MAX;
+
+/// `enrich_attempts` values: attempt count, then the next attempt time (`ENRICH_PARKED`).
+fn encode_enrich_attempt(attempts: u32, next_unix_ms: i64) -> [u8; 12] {
+    let mut out = [0u8; 12];
+    out[..4].copy_from_slice(&attempts.to_be_bytes());
+    out[4..].copy_from_slice(&next_unix_ms.to_be_bytes());
+    out
+}
+
+fn decode_enrich_attempt(bytes: &[u8]) -> Option<(u32, i64)> {
+    let attempts = u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?);
+    let next = i64::from_be_bytes(bytes.get(4..12)?.try_into().ok()?);
+    Some((attempts, next))
+}
+
+/// Counts an attempt and schedules the next one in `idx_enrich_retry`, or parks the hash
+/// when this was its last.
+fn schedule_enrich_retry(db: &Db, info_hash_hex: &str, policy: &RetryPolicy) -> anyhow::Result<()> {
+    let attempts_tree = enrich_attempt_tree(db)?;
+    let attempts = attempts_tree
+        .get(info_hash_hex.as_bytes())?
+        .and_then(|v| decode_enrich_attempt(&v))
+        .map_or(0, |(n, _)| n)
+        + 1;
+    let next = if attempts < policy.max_attempts {
+        let delay = policy.base_ms.saturating_mul(1 << (attempts - 1).min(32)).min(policy.max_ms);
+        let next = now_unix_ms() + delay;
+        enrich_retry_tree(db)?.insert(ts_key(next, info_hash_hex), [])?;
+        next
+    } else {
+        ENRICH_PARKED
+    };
+    attempts_tree.insert(info_hash_hex.as_bytes(), encode_enrich_attempt(attempts, next))?;
+    Ok(())
+}
+
+/// Forgets a hash's attempts (metadata arrived, or the record was deleted).
+fn clear_enrich_attempts(db: &Db, info_hash_hex: &str) -> anyhow::Result<()> {
+    let Some(old) = enrich_attempt_tree(db)?.remove(info_hash_hex.as_bytes())? else {
+        return Ok(());
+    };
+    if let Some((_, next)) = decode_enrich_attempt(&old)
+        && next != ENRICH_PARKED
+    {
+        let _ = enrich_retry_tree(db)?.remove(ts_key(next, info_hash_hex))?;
+    }
+    Ok(())
+}
+
+/// Forgets a record's failed lookups and puts it back on the enrich queue right away.
+/// Returns false if the record is gone or already has its metadata.
+pub fn requeue_missing_info(db: &Db, info_hash_hex: &str) -> anyhow::Result<bool> {
+    let Some(record) = get(db, info_hash_hex)? else {
+        return Ok(false);
+    };
+    if has_info(&record) {
+        return Ok(false);
+    }
+    clear_enrich_attempts(db, info_hash_hex)?;
+    enqueue_missing_info(db, &record)?;
+    Ok(true)
+}
+
+/// Puts hashes whose backoff has run out back on the enrich queue.
+pub fn release_due_enrich_retries(db: &Db, limit: usize) -> anyhow::Result<usize> {
+    let tree = enrich_retry_tree(db)?;
+    let mut released = 0;
+    for item in tree.range(..end_key_for_ts(now_unix_ms())).keys() {
+        if released >= limit {
+            break;
+        }
+        let key = item?;
+        let _ = tree.remove(&key)?;
+        let Some((_, hash)) = parse_ts_key(&key) else {
             continue;
         };
+        if let Some(record) = get(db, &hash)?
+            && !has_info(&record)
+        {
+            enqueue_missing_info(db, &record)?;
+            released += 1;
+        }
+    }
+    Ok(released)
+}
 
-        let key = key_for_hash(&hash_hex);
-        let Some(bytes) = db.get(&key)? else {
-            // Record was deleted; drop index entry.
-            let _ = tree.remove(hash_bytes)?;
+/// Moves trending hashes (`idx_enrich_hot`, hottest first) ahead of the whole enrich queue.
+/// Only hashes waiting in the queue are taken; ones without a record, with metadata, or
+/// backing off after a failed lookup are skipped. Returns how many were queued.
+pub fn prioritize_enrich(db: &Db, hot: &[(String, u32)]) -> anyhow::Result<usize> {
+    let tree = enrich_hot_tree(db)?;
+    let missing = missing_info_tree(db)?;
+    let mut queued = 0;
+    for (hash_hex, estimate) in hot {
+        if !missing.contains_key(hash_hex.as_bytes())? {
+            continue;
+        }
+        let mut key = u64_be(u64::MAX - u64::from(*estimate)).to_vec();
+        key.extend_from_slice(hash_hex.as_bytes());
+        tree.insert(key, [])?;
+        queued += 1;
+    }
+    Ok(queued)
+}
+
+/// Takes up to `limit` records waiting for metadata off the enrich queue, trending ones
+/// (`prioritize_enrich`) first. Each one taken counts as an attempt and comes back after its
+/// backoff unless the metadata arrives first, so a lookup that dies with the process is
+/// retried too.
+pub fn take_missing_info(db: &Db, limit: usize, policy: &RetryPolicy) -> anyhow::Result<Vec<TorrentRecord>> {
+    let tree = enrich_priority_tree(db)?;
+    let hot = enrich_hot_tree(db)?;
+    let missing = missing_info_tree(db)?;
+
+    let mut out = Vec::new();
+    for item in hot.iter().keys() {
+        if out.len() >= limit {
+            return Ok(out);
+        }
+        let hot_key = item?;
+        let _ = hot.remove(&hot_key)?;
+        let Some(hash_hex) = hot_key.get(8..).and_then(|h| std::str::from_utf8(h).ok()) else {
             continue;
         };
+        // Taken through the main queue since, or never queued.
+        if !missing.contains_key(hash_hex.as_bytes())? {
+            continue;
+        }
+        if let Some(record) = take_if_missing(db, hash_hex, policy)? {
+            out.push(record);
+        }
+    }
 
-        let record = decode_torrent_record_maybe_migrate(db, &key, &bytes)?;
-        if has_info(&record) {
-            // Index is stale; fix it.
-            let _ = tree.remove(hash_bytes)?;
+    for item in tree.iter().keys() {
+        let priority_key = item?;
+        let Some(hash_hex) = priority_key.get(8..).and_then(|h| std::str::from_utf8(h).ok()) else {
+            // Corrupt key; drop it.
+            let _ = tree.remove(priority_key)?;
+            continue;
+        };
+        if missing.get(hash_hex.as_bytes())?.as_deref() != Some(&priority_key[..]) {
+            // Moved or dequeued since; the entry under its current key (if any) counts.
+            let _ = tree.remove(&priority_key)?;
             continue;
         }
 
-        out.push(record);
+        if let Some(record) = take_if_missing(db, hash_hex, policy)? {
+            out.push(record);
+        }
         if out.len() >= limit {
             break;
         }
@@ -371,99 +1637,1305 @@ pub fn list_missing_info(db: &sled::Db, limit: usize) -> anyhow::Result<Vec<Torr
     Ok(out)
 }
 
+/// Takes one queued hash off the enrich queue: its record if it still needs metadata.
+fn take_if_missing(db: &Db, hash_hex: &str, policy: &RetryPolicy) -> anyhow::Result<Option<TorrentRecord>> {
+    let key = key_for_hash(hash_hex);
+    let record = match db.get(&key)? {
+        Some(bytes) => decode_torrent_record_maybe_migrate(db, &key, &bytes)?,
+        None => {
+            // Record was deleted; drop index entry.
+            dequeue_missing_info(db, hash_hex)?;
+            return Ok(None);
+        }
+    };
+    if has_info(&record) {
+        // Index is stale; fix it.
+        dequeue_missing_info(db, hash_hex)?;
+        return Ok(None);
+    }
+
+    schedule_enrich_retry(db, hash_hex, policy)?;
+    dequeue_missing_info(db, hash_hex)?;
+    Ok(Some(record))
+}
+
 pub fn set_metadata(
-    db: &sled::Db,
+    db: &Db,
     info_hash_hex: &str,
     title: Option<&str>,
     info_bencode_base64: &str,
 ) -> anyhow::Result<TorrentRecord> {
-    let mut record = upsert_first_seen(db, info_hash_hex)?;
-    if let Some(title) = title {
-        if !title.trim().is_empty() {
-            record.title = Some(title.to_string());
-        }
+    let mut record = upsert_first_seen(db, info_hash_hex, None)?;
+    if let Some(title) = title
+        && !title.trim().is_empty()
+    {
+        record.title = Some(title.to_string());
+        record.refresh_release();
     }
     record.info_bencode_base64 = Some(info_bencode_base64.to_string());
-    let key = key_for_hash(info_hash_hex);
-    let before = db
-        .get(&key)?
-        .and_then(|b| decode_torrent_record(&b).ok())
-        .map(|(r, _)| r);
-    db.insert(&key, encode_torrent_record(&record)?)?;
-    let _ = sync_missing_info_index(db, &record);
-    let _ = sync_last_seen_index(db, before.as_ref(), &record);
-    let _ = sync_low_seed_index(db, before.as_ref(), &record);
+    fill_from_info(&mut record);
+    put_record(db, &record)?;
     Ok(record)
 }
 
-pub fn set_seeders(
-    db: &sled::Db,
+/// Takes up to `limit` torrents last refreshed more than `max_age_ms` ago off the
+/// re-check queue. Callers put them back with `mark_rechecked`; deleted torrents just
+/// drop out.
+pub fn take_due_rechecks(db: &Db, max_age_ms: i64, limit: usize) -> anyhow::Result<Vec<TorrentRecord>> {
+    let tree = recheck_tree(db)?;
+    let end = end_key_for_ts(now_unix_ms() - max_age_ms);
+    let mut out = Vec::new();
+    for item in tree.range(..end) {
+        if out.len() >= limit {
+            break;
+        }
+        let (key, _) = item?;
+        let _ = tree.remove(&key)?;
+        let Some((_, hash)) = parse_ts_key(&key) else {
+            continue;
+        };
+        if let Some(record) = get(db, &hash)? {
+            out.push(record);
+        }
+    }
+    Ok(out)
+}
+
+pub fn mark_rechecked(db: &Db, info_hash_hex: &str) -> anyhow::Result<()> {
+    recheck_tree(db)?.insert(ts_key(now_unix_ms(), info_hash_hex), [])?;
+    Ok(())
+}
+
+/// Stores swarm counts from a DHT lookup and bumps last_seen, since peers answering means
+/// the torrent is alive.
+pub fn set_dht_swarm_counts(
+    db: &Db,
     info_hash_hex: &str,
     seeders: i64,
+    leechers: i64,
+    history: &HistoryPolicy,
 ) -> anyhow::Result<TorrentRecord> {
-    let mut record = upsert_first_seen(db, info_hash_hex)?;
+    let mut record = upsert_first_seen(db, info_hash_hex, None)?;
     record.seeders = seeders;
-    let key = key_for_hash(info_hash_hex);
-    let before = db
-        .get(&key)?
-        .and_then(|b| decode_torrent_record(&b).ok())
-        .map(|(r, _)| r);
-    db.insert(&key, encode_torrent_record(&record)?)?;
-    let _ = sync_missing_info_index(db, &record);
-    let _ = sync_last_seen_index(db, before.as_ref(), &record);
-    let _ = sync_low_seed_index(db, before.as_ref(), &record);
+    record.leechers = leechers;
+    put_record(db, &record)?;
+    record_seeders(db, &record, history)?;
     Ok(record)
 }
 
-pub fn set_magnet(
-    db: &sled::Db,
+/// Stores tracker scrape counts; seeders may go down as well as up. Returns `None` if the
+/// record no longer exists.
+pub fn set_swarm_counts(
+    db: &Db,
     info_hash_hex: &str,
-    magnet: &str,
-) -> anyhow::Result<TorrentRecord> {
-    let mut record = upsert_first_seen(db, info_hash_hex)?;
-    if !magnet.trim().is_empty() {
-        record.magnet = Some(magnet.to_string());
-    }
+    seeders: i64,
+    leechers: i64,
+    history: &HistoryPolicy,
+) -> anyhow::Result<Option<TorrentRecord>> {
     let key = key_for_hash(info_hash_hex);
-    let before = db
-        .get(&key)?
-        .and_then(|b| decode_torrent_record(&b).ok())
-        .map(|(r, _)| r);
+    let Some(before) = db.get(&key)?.and_then(|b| decode_torrent_record(&b).ok()).map(|(r, _)| r) else {
+        // Deleted since the scrape started; don't resurrect it.
+        return Ok(None);
+    };
+    let mut record = before.clone();
+    record.seeders = seeders;
+    record.leechers = leechers;
     db.insert(&key, encode_torrent_record(&record)?)?;
-    let _ = sync_missing_info_index(db, &record);
-    let _ = sync_last_seen_index(db, before.as_ref(), &record);
-    let _ = sync_low_seed_index(db, before.as_ref(), &record);
-    Ok(record)
+    sync_low_seed_index(db, Some(&before), &record)?;
+    record_seeders(db, &record, history)?;
+    Ok(Some(record))
+}
+
+// Seeder history: `seeder_history` maps hash -> samples of (unix seconds, seeders), 8 bytes
+// each, oldest first. Swarm count updates add a sample at most every `every_ms`; updates
+// in between overwrite the newest one, so it always holds the current count. Torrents that
+// never had a seeder get no history.
+
+/// How often seeder counts are sampled and how many samples are kept per torrent.
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryPolicy {
+    pub every_ms: i64,
+    /// 0 turns history off.
+    pub keep: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SeederSample {
+    pub at_unix_ms: i64,
+    pub seeders: u32,
+}
+
+/// Direction of a torrent's swarm over its seeder history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeederTrend {
+    /// Too few samples to tell.
+    Unknown,
+    Declining,
+    Stable,
+    Rising,
 }
 
-pub fn get(db: &sled::Db, info_hash_hex: &str) -> anyhow::Result<Option<TorrentRecord>> {
+/// Samples needed before a trend is reported.
+const TREND_MIN_SAMPLES: usize = 4;
+
+impl SeederTrend {
+    /// Compares the average of the newer half of the samples with the older half: below
+    /// half of it is declining, over one and a half times is rising.
+    pub fn of(samples: &[SeederSample]) -> Self {
+        if samples.len() < TREND_MIN_SAMPLES {
+            return Self::Unknown;
+        }
+        let (older, newer) = samples.split_at(samples.len() / 2);
+        let older = mean_seeders(older);
+        let newer = mean_seeders(newer);
+        if newer < older * 0.5 {
+            Self::Declining
+        } else if newer > older * 1.5 {
+            Self::Rising
+        } else {
+            Self::Stable
+        }
+    }
+}
+
+/// Average seeders over `samples`.
+pub fn mean_seeders(samples: &[SeederSample]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.iter().map(|s| f64::from(s.seeders)).sum::<f64>() / samples.len() as f64
+}
+
+fn decode_seeder_history(bytes: &[u8]) -> Vec<SeederSample> {
+    bytes
+        .chunks_exact(8)
+        .map(|c| SeederSample {
+            at_unix_ms: i64::from(u32::from_be_bytes([c[0], c[1], c[2], c[3]])) * 1000,
+            seeders: u32::from_be_bytes([c[4], c[5], c[6], c[7]]),
+        })
+        .collect()
+}
+
+fn encode_seeder_history(samples: &[SeederSample]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(samples.len() * 8);
+    for sample in samples {
+        out.extend_from_slice(&((sample.at_unix_ms / 1000).clamp(0, u32::MAX as i64) as u32).to_be_bytes());
+        out.extend_from_slice(&sample.seeders.to_be_bytes());
+    }
+    out
+}
+
+/// Oldest first; empty if none were recorded.
+pub fn seeder_history(db: &Db, info_hash_hex: &str) -> anyhow::Result<Vec<SeederSample>> {
+    Ok(seeder_history_tree(db)?
+        .get(info_hash_hex.as_bytes())?
+        .map(|bytes| decode_seeder_history(&bytes))
+        .unwrap_or_default())
+}
+
+/// Adds `record`'s seeder count to its history (see above).
+pub fn record_seeders(db: &Db, record: &TorrentRecord, policy: &HistoryPolicy) -> anyhow::Result<()> {
+    if policy.keep == 0 {
+        return Ok(());
+    }
+    let now = now_unix_ms();
+    let seeders = record.seeders.clamp(0, u32::MAX as i64) as u32;
+    let tree = seeder_history_tree(db)?;
+    let key = record.info_hash_hex.as_bytes();
+    let mut samples = tree.get(key)?.map(|bytes| decode_seeder_history(&bytes)).unwrap_or_default();
+    let added = match samples.last_mut() {
+        None if seeders == 0 => return Ok(()),
+        Some(last) if now - last.at_unix_ms < policy.every_ms => {
+            if last.seeders == seeders {
+                return Ok(());
+            }
+            last.seeders = seeders;
+            false
+        }
+        _ => {
+            samples.push(SeederSample { at_unix_ms: now, seeders });
+            let excess = samples.len().saturating_sub(policy.keep);
+            samples.drain(..excess);
+            true
+        }
+    };
+    tree.insert(key, encode_seeder_history(&samples))?;
+    // Cleanup drops low-seed entries whose swarm looked healthy; a new sample lets it look
+    // again.
+    if added && record.seeders < 2 && !record.pinned {
+        low_seed_tree(db)?.insert(ts_key(record.first_seen_unix_ms, &record.info_hash_hex), [])?;
+    }
+    Ok(())
+}
+
+/// Stores the spider's announce estimate. Returns `None` if the record doesn't exist.
+pub fn set_announces(db: &Db, info_hash_hex: &str, announces: u64) -> anyhow::Result<Option<TorrentRecord>> {
     let key = key_for_hash(info_hash_hex);
-    let Some(bytes) = db.get(&key)? else {
+    let Some(mut record) = db.get(&key)?.and_then(|b| decode_torrent_record(&b).ok()).map(|(r, _)| r) else {
         return Ok(None);
     };
+    record.announces = announces;
+    db.insert(&key, encode_torrent_record(&record)?)?;
+    // Announces move it up the enrich queue.
+    sync_missing_info_index(db, &record)?;
+    Ok(Some(record))
+}
 
-    Ok(Some(decode_torrent_record_maybe_migrate(db, &key, &bytes)?))
+pub fn set_magnet(
+    db: &Db,
+    info_hash_hex: &str,
+    magnet: &str,
+) -> anyhow::Result<TorrentRecord> {
+    let mut record = upsert_first_seen(db, info_hash_hex, None)?;
+    if !magnet.trim().is_empty() {
+        record.magnet = Some(magnet.to_string());
+    }
+    put_record(db, &record)?;
+    Ok(record)
 }
 
-pub fn delete(db: &sled::Db, info_hash_hex: &str) -> anyhow::Result<()> {
-    let key = key_for_hash(info_hash_hex);
+/// Merges a record imported from another catalog into the existing one (if any).
+///
+/// Keeps the earliest first-seen time and the latest last-seen time; title, magnet and
+/// info dict only fill fields that are still empty. Swarm counts stay this instance's own:
+/// only a new record takes the imported ones. Returns the stored record and whether it
+/// was new.
+pub fn merge_imported(db: &Db, imported: TorrentRecord) -> anyhow::Result<(TorrentRecord, bool)> {
+    let key = key_for_hash(&imported.info_hash_hex);
     let before = db
         .get(&key)?
         .and_then(|b| decode_torrent_record(&b).ok())
         .map(|(r, _)| r);
 
-    let _ = db.remove(&key)?;
-    if let Ok(tree) = missing_info_tree(db) {
-        let _ = tree.remove(info_hash_hex.as_bytes());
-    }
-
-    if let Some(before) = before.as_ref() {
-        if let Ok(tree) = last_seen_tree(db) {
-            let _ = tree.remove(ts_key(before.last_seen_unix_ms, &before.info_hash_hex));
-        }
-        if let Ok(tree) = low_seed_tree(db) {
-            let _ = tree.remove(ts_key(before.first_seen_unix_ms, &before.info_hash_hex));
+    let created = before.is_none();
+    let mut record = match before.clone() {
+        None => imported,
+        Some(mut record) => {
+            record.first_seen_unix_ms = record.first_seen_unix_ms.min(imported.first_seen_unix_ms);
+            record.last_seen_unix_ms = record.last_seen_unix_ms.max(imported.last_seen_unix_ms);
+            if record.title.is_none() {
+                record.title = imported.title;
+            }
+            if record.magnet.is_none() {
+                record.magnet = imported.magnet;
+            }
+            if record.info_bencode_base64.is_none() {
+                record.info_bencode_base64 = imported.info_bencode_base64;
+            }
+            if record.size_bytes == 0 {
+                record.size_bytes = imported.size_bytes;
+            }
+            if record.files.is_empty() && !imported.files.is_empty() {
+                record.file_count = imported.file_count;
+                record.files = imported.files;
+            }
+            if record.info_hash_v2_hex.is_none() {
+                record.info_hash_v2_hex = imported.info_hash_v2_hex;
+            }
+            record
+        }
+    };
+    if record.files.is_empty() || record.info_hash_v2_hex.is_none() {
+        fill_from_info(&mut record);
+    }
+    if record.release.is_none() {
+        record.refresh_release();
+    }
+
+    put_record(db, &record)?;
+    Ok((record, created))
+}
+
+/// Records ordered by last-seen time, starting at `since_unix_ms`.
+///
+/// `after` is the hash of the last record of the previous page; records with the same
+/// timestamp up to and including it are skipped, so pages never split or repeat ties.
+pub fn list_seen_since(
+    db: &Db,
+    since_unix_ms: i64,
+    after: Option<&str>,
+    limit: usize,
+) -> anyhow::Result<Vec<TorrentRecord>> {
+    let tree = last_seen_tree(db)?;
+    let start = ts_key(since_unix_ms, after.unwrap_or_default());
+
+    let mut out = Vec::new();
+    for item in tree.range(start.clone()..) {
+        if out.len() >= limit {
+            break;
+        }
+        let (idx_key, _) = item?;
+        if after.is_some() && idx_key == start {
+            continue;
+        }
+        let Some((ts, hash_hex)) = parse_ts_key(&idx_key) else {
+            continue;
+        };
+        // Skip stale index entries (the record was touched again or deleted).
+        if let Some(record) = get(db, &hash_hex)?
+            && record.last_seen_unix_ms.max(0) == ts
+        {
+            out.push(record);
+        }
+    }
+    Ok(out)
+}
+
+/// Most recently seen records that have a title, newest first.
+///
+/// Scans at most `scan_limit` index entries, so databases full of untitled hashes
+/// return a short page instead of walking everything.
+pub fn list_recent_titled(
+    db: &Db,
+    offset: usize,
+    limit: usize,
+    scan_limit: usize,
+) -> anyhow::Result<Vec<TorrentRecord>> {
+    let tree = last_seen_tree(db)?;
+    let mut out = Vec::new();
+    let mut skipped = 0;
+    for item in tree.iter().rev().take(scan_limit) {
+        if out.len() >= limit {
+            break;
+        }
+        let (idx_key, _) = item?;
+        let Some((ts, hash_hex)) = parse_ts_key(&idx_key) else {
+            continue;
+        };
+        let Some(record) = get(db, &hash_hex)? else {
+            continue;
+        };
+        if record.last_seen_unix_ms.max(0) != ts || record.title.is_none() {
+            continue;
+        }
+        if skipped < offset {
+            skipped += 1;
+            continue;
+        }
+        out.push(record);
+    }
+    Ok(out)
+}
+
+/// Small opaque values in the `meta` tree (e.g. ingest checkpoints).
+pub fn get_meta(db: &Db, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    Ok(meta_tree(db)?.get(key.as_bytes())?.map(|v| v.to_vec()))
+}
+
+pub fn set_meta(db: &Db, key: &str, value: &[u8]) -> anyhow::Result<()> {
+    meta_tree(db)?.insert(key.as_bytes(), value)?;
+    Ok(())
+}
+
+pub fn remove_meta(db: &Db, key: &str) -> anyhow::Result<()> {
+    let _ = meta_tree(db)?.remove(key.as_bytes())?;
+    Ok(())
+}
+
+pub fn get(db: &Db, info_hash_hex: &str) -> anyhow::Result<Option<TorrentRecord>> {
+    let key = key_for_hash(info_hash_hex);
+    let Some(bytes) = db.get(&key)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(decode_torrent_record_maybe_migrate(db, &key, &bytes)?))
+}
+
+/// Reclaims the space taken by overwritten and deleted data. sled is copied into a fresh
+/// database that is then swapped in; SQLite is vacuumed. Nothing else may have it open.
+pub fn compact(backend: Backend, data_dir: &Path) -> anyhow::Result<Compaction> {
+    let started = std::time::Instant::now();
+    let path = backend.path(data_dir);
+    match backend {
+        Backend::Sled => compact_sled(&path, started),
+        Backend::Sqlite => {
+            use crate::kv::Store as _;
+            let sqlite = crate::sqlite::SqliteStore::open(&path)?;
+            let bytes_before = sqlite.size_on_disk()?;
+            sqlite.vacuum()?;
+            let bytes_after = sqlite.size_on_disk()?;
+            record_compaction(&Db::new(sqlite), started, bytes_before, bytes_after)
+        }
+    }
+}
+
+fn compact_sled(path: &Path, started: std::time::Instant) -> anyhow::Result<Compaction> {
+    let fresh_path = path.with_extension("compact");
+    let old_path = path.with_extension("old");
+    let _ = std::fs::remove_dir_all(&fresh_path);
+    let compaction = {
+        let db = crate::kv::SledStore::open(path)?.into_inner();
+        let fresh = sled::open(&fresh_path).context("create compacted sled db")?;
+        fresh.import(db.export());
+        fresh.flush()?;
+        anyhow::ensure!(
+            db.checksum()? == fresh.checksum()?,
+            "compacted copy does not match the original; left it at {}",
+            fresh_path.display()
+        );
+        let (bytes_before, bytes_after) = (db.size_on_disk()?, fresh.size_on_disk()?);
+        record_compaction(
+            &Db::new(crate::kv::SledStore::from_db(fresh)),
+            started,
+            bytes_before,
+            bytes_after,
+        )?
+    };
+    std::fs::rename(path, &old_path).context("move old database aside")?;
+    std::fs::rename(&fresh_path, path).context("move compacted database in place")?;
+    std::fs::remove_dir_all(&old_path).context("remove old database")?;
+    Ok(compaction)
+}
+
+/// Stores the outcome of a compaction in the compacted database.
+fn record_compaction(
+    db: &Db,
+    started: std::time::Instant,
+    bytes_before: u64,
+    bytes_after: u64,
+) -> anyhow::Result<Compaction> {
+    let compaction = Compaction {
+        finished_unix_ms: now_unix_ms(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        bytes_before,
+        bytes_after,
+        reclaimed_bytes: bytes_before.saturating_sub(bytes_after),
+    };
+    let mut status = compaction_status(db)?;
+    status.due_bytes = None;
+    status.reclaimed_bytes_total += compaction.reclaimed_bytes;
+    status.last = Some(compaction.clone());
+    set_compaction_status(db, &status)?;
+    db.flush()?;
+    Ok(compaction)
+}
+
+/// Maps a 40-character (v1 or truncated v2) or 64-character (v2) hex hash to the hash
+/// its record is stored under. Returns `None` for anything else.
+pub fn resolve_info_hash(db: &Db, hash_hex: &str) -> anyhow::Result<Option<String>> {
+    let hash = hash_hex.trim().to_ascii_lowercase();
+    if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    match hash.len() {
+        40 => Ok(Some(hash)),
+        64 => match info_hash_v2_tree(db)?.get(hash.as_bytes())? {
+            Some(key) => Ok(Some(String::from_utf8_lossy(&key).into_owned())),
+            // v2-only torrents are stored under the truncated hash.
+            None => Ok(Some(hash[..40].to_string())),
+        },
+        _ => Ok(None),
+    }
+}
+
+pub fn delete(db: &Db, info_hash_hex: &str) -> anyhow::Result<()> {
+    // As with `put_record`, the counters follow what this remove actually took out.
+    let before = db
+        .remove(key_for_hash(info_hash_hex))?
+        .and_then(|b| decode_torrent_record(&b).ok())
+        .map(|(r, _)| r);
+    sync_record_counters(db, before.as_ref(), None)?;
+    let _ = dequeue_missing_info(db, info_hash_hex);
+    let _ = clear_enrich_attempts(db, info_hash_hex);
+    if let Ok(tree) = seeder_history_tree(db) {
+        let _ = tree.remove(info_hash_hex.as_bytes());
+    }
+
+    if let Some(before) = before.as_ref() {
+        if let Ok(tree) = last_seen_tree(db) {
+            let _ = tree.remove(ts_key(before.last_seen_unix_ms, &before.info_hash_hex));
+        }
+        if let Ok(tree) = low_seed_tree(db) {
+            let _ = tree.remove(ts_key(before.first_seen_unix_ms, &before.info_hash_hex));
+        }
+        if let Some(v2) = before.info_hash_v2_hex.as_deref()
+            && let Ok(tree) = info_hash_v2_tree(db)
+        {
+            let _ = tree.remove(v2.as_bytes());
+        }
+        let _ = add_tombstone(db, info_hash_hex);
+    }
+    Ok(())
+}
+
+// Tombstones remember recently deleted hashes so bulk imports of old dumps don't
+// resurrect torrents that cleanup already dropped. `tombstones` maps hash -> deleted_at,
+// `idx_tombstone_time` orders them by time for pruning.
+
+fn add_tombstone(db: &Db, info_hash_hex: &str) -> anyhow::Result<()> {
+    let now = now_unix_ms();
+    let tree = tombstone_tree(db)?;
+    let time_tree = tombstone_time_tree(db)?;
+    if let Some(prev) = tree.insert(info_hash_hex.as_bytes(), u64_be(now.max(0) as u64))?
+        && let Ok(prev) = <[u8; 8]>::try_from(prev.as_ref())
+    {
+        let _ = time_tree.remove(ts_key(u64::from_be_bytes(prev) as i64, info_hash_hex))?;
+    }
+    time_tree.insert(ts_key(now, info_hash_hex), [])?;
+    Ok(())
+}
+
+pub fn is_tombstoned(db: &Db, info_hash_hex: &str) -> anyhow::Result<bool> {
+    tombstone_tree(db)?.contains_key(info_hash_hex.as_bytes())
+}
+
+/// Drops up to `limit` tombstones older than `cutoff_unix_ms`. Returns how many were removed.
+pub fn prune_tombstones(db: &Db, cutoff_unix_ms: i64, limit: usize) -> anyhow::Result<usize> {
+    let tree = tombstone_tree(db)?;
+    let time_tree = tombstone_time_tree(db)?;
+    let end_key = end_key_for_ts(cutoff_unix_ms);
+
+    let mut removed = 0;
+    for item in time_tree
+        .range((std::ops::Bound::Unbounded, std::ops::Bound::Included(end_key)))
+        .take(limit)
+    {
+        let (idx_key, _) = item?;
+        if let Some((ts, hash_hex)) = parse_ts_key(&idx_key) {
+            // Only drop the tombstone if it wasn't refreshed by a later delete.
+            let current = tree
+                .get(hash_hex.as_bytes())?
+                .and_then(|v| <[u8; 8]>::try_from(v.as_ref()).ok())
+                .map(|v| u64::from_be_bytes(v) as i64);
+            if current == Some(ts) {
+                let _ = tree.remove(hash_hex.as_bytes())?;
+            }
+        }
+        let _ = time_tree.remove(idx_key)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+// Space reclamation. sled reuses log segments once everything in them is dead but never
+// shrinks a file it has open, and SQLite keeps freed pages, so a database that lost most
+// of its records stays large until it is copied into a fresh one or vacuumed (`compact`).
+// `compact.rs` periodically measures how much of the file is live data and marks the
+// database for compaction; that happens on the next start or with `serma compact`. The
+// outcome is kept in `meta`.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Compaction {
+    pub finished_unix_ms: i64,
+    pub duration_ms: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub reclaimed_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompactionStatus {
+    pub checked_unix_ms: Option<i64>,
+    /// Key and value bytes found by the last check.
+    pub live_bytes: Option<u64>,
+    /// Set when the last check found the file mostly garbage: roughly what compacting frees.
+    pub due_bytes: Option<u64>,
+    pub last: Option<Compaction>,
+    /// Freed by every compaction so far.
+    pub reclaimed_bytes_total: u64,
+}
+
+pub fn compaction_status(db: &Db) -> anyhow::Result<CompactionStatus> {
+    Ok(meta_tree(db)?
+        .get(META_COMPACTION)?
+        .and_then(|v| serde_json::from_slice(&v).ok())
+        .unwrap_or_default())
+}
+
+pub fn set_compaction_status(db: &Db, status: &CompactionStatus) -> anyhow::Result<()> {
+    meta_tree(db)?.insert(META_COMPACTION, serde_json::to_vec(status)?)?;
+    Ok(())
+}
+
+/// Whether the search index may have fallen out of step with the database: set while a
+/// process has both open, cleared once it committed the index and closed (`AppState::close`).
+pub fn index_dirty(db: &Db) -> anyhow::Result<bool> {
+    Ok(meta_tree(db)?.get(META_INDEX_DIRTY)?.is_some())
+}
+
+pub fn set_index_dirty(db: &Db, dirty: bool) -> anyhow::Result<()> {
+    let meta = meta_tree(db)?;
+    if dirty {
+        meta.insert(META_INDEX_DIRTY, b"1")?;
+        // On disk before anything else is written, or a crash could hide it.
+        db.flush()?;
+    } else {
+        meta.remove(META_INDEX_DIRTY)?;
+    }
+    Ok(())
+}
+
+/// This instance's random key for signing login sessions, made on first use. It stays
+/// with the database, so sessions survive a restart.
+pub fn session_key(db: &Db) -> anyhow::Result<[u8; 32]> {
+    let meta = meta_tree(db)?;
+    if let Some(key) = meta.get(META_SESSION_KEY)?
+        && let Ok(key) = <[u8; 32]>::try_from(key.as_slice())
+    {
+        return Ok(key);
+    }
+    let key: [u8; 32] = rand::random();
+    meta.insert(META_SESSION_KEY, key)?;
+    Ok(key)
+}
+
+/// Whether a record is stored for the hash.
+pub fn contains(db: &Db, info_hash_hex: &str) -> anyhow::Result<bool> {
+    db.contains_key(key_for_hash(info_hash_hex))
+}
+
+// Integrity check (`serma fsck`). The writers keep the secondary indexes in step with the
+// records, but a crash between two tree writes, or a bug in an older version, can leave an
+// entry behind for a record that is gone or has changed, or leave a record out of an index.
+// Most readers skip such entries when they meet them; this finds them all at once.
+
+/// What `check_integrity` found. With `repair`, every count here was also fixed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Integrity {
+    pub records: u64,
+    /// `torrent:` entries that don't decode, or hold another hash than their key; removed.
+    pub corrupt_records: u64,
+    /// Index entries for a record that is gone or has changed since, by tree; removed.
+    pub stale_entries: std::collections::BTreeMap<&'static str, u64>,
+    /// Records left out of an index they belong in, by tree; added.
+    pub missing_entries: std::collections::BTreeMap<&'static str, u64>,
+    /// The record counters in `meta` were off; recounted.
+    pub counters_off: bool,
+}
+
+impl Integrity {
+    pub fn problems(&self) -> u64 {
+        self.corrupt_records
+            + self.stale_entries.values().sum::<u64>()
+            + self.missing_entries.values().sum::<u64>()
+            + u64::from(self.counters_off)
+    }
+}
+
+/// Checks every record and the per-record secondary indexes against each other, and fixes
+/// what it finds if `repair` is set. Reads the whole database; meant for a stopped server.
+pub fn check_integrity(db: &Db, repair: bool) -> anyhow::Result<Integrity> {
+    let mut report = Integrity::default();
+    let last_seen = last_seen_tree(db)?;
+    let low_seed = low_seed_tree(db)?;
+    let missing_info = missing_info_tree(db)?;
+    let attempts = enrich_attempt_tree(db)?;
+    let retry = enrich_retry_tree(db)?;
+    let v2_tree = info_hash_v2_tree(db)?;
+
+    // Records, and the entries each one should have. `fix` adds a missing one.
+    let mut missing = |tree: &'static str, fix: &mut dyn FnMut() -> anyhow::Result<()>| {
+        *report.missing_entries.entry(tree).or_default() += 1;
+        if repair { fix() } else { Ok(()) }
+    };
+    let mut corrupt = Vec::new();
+    let (mut total, mut no_info) = (0u64, 0u64);
+    for item in db.scan_prefix(b"torrent:") {
+        let (key, value) = item?;
+        let record = match decode_torrent_record(&value) {
+            Ok((record, _)) if key_for_hash(&record.info_hash_hex) == key => record,
+            _ => {
+                corrupt.push(key);
+                continue;
+            }
+        };
+        total += 1;
+        let hash = record.info_hash_hex.as_str();
+        let seen_key = ts_key(record.last_seen_unix_ms, hash);
+        if !last_seen.contains_key(&seen_key)? {
+            missing("idx_last_seen", &mut || last_seen.insert(&seen_key, []).map(drop))?;
+        }
+        let low_key = ts_key(record.first_seen_unix_ms, hash);
+        if record.seeders < 2 && !low_seed.contains_key(&low_key)? {
+            missing("idx_low_seed", &mut || low_seed.insert(&low_key, []).map(drop))?;
+        }
+        if !has_info(&record) {
+            no_info += 1;
+            // Waiting in the queue, for a retry, or parked after its last attempt.
+            let attempt = attempts.get(hash.as_bytes())?.and_then(|v| decode_enrich_attempt(&v));
+            match attempt {
+                _ if missing_info.contains_key(hash.as_bytes())? => {}
+                None => missing("idx_missing_info", &mut || enqueue_missing_info(db, &record))?,
+                Some((_, next)) if next != ENRICH_PARKED && !retry.contains_key(ts_key(next, hash))? => {
+                    missing("idx_enrich_retry", &mut || retry.insert(ts_key(next, hash), []).map(drop))?
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(v2) = record.info_hash_v2_hex.as_deref()
+            && v2_tree.get(v2.as_bytes())?.as_deref() != Some(hash.as_bytes())
+        {
+            missing("idx_info_hash_v2", &mut || v2_tree.insert(v2.as_bytes(), hash.as_bytes()).map(drop))?;
+        }
+    }
+    report.records = total;
+    report.corrupt_records = corrupt.len() as u64;
+    if repair {
+        for key in corrupt {
+            let _ = db.remove(key)?;
+        }
+    }
+
+    // Entries, each against the record it names.
+    let record = |hash: &[u8]| -> anyhow::Result<Option<TorrentRecord>> {
+        let Ok(hash) = std::str::from_utf8(hash) else {
+            return Ok(None);
+        };
+        Ok(db.get(key_for_hash(hash))?.and_then(|b| decode_torrent_record(&b).ok()).map(|(r, _)| r))
+    };
+    let ts_hash = |key: &[u8]| parse_ts_key(key).map(|(ts, hash)| (ts, hash.into_bytes()));
+    let has_info_or_gone = |hash: &[u8]| Ok(record(hash)?.is_none_or(|r| has_info(&r)));
+    type Stale<'a> = &'a dyn Fn(&[u8], &[u8]) -> anyhow::Result<bool>;
+    let checks: [(&'static str, Tree, Stale); 10] = [
+        ("idx_last_seen", last_seen, &|key, _| {
+            let Some((ts, hash)) = ts_hash(key) else { return Ok(true) };
+            Ok(record(&hash)?.is_none_or(|r| r.last_seen_unix_ms.max(0) != ts))
+        }),
+        ("idx_low_seed", low_seed, &|key, _| {
+            let Some((ts, hash)) = ts_hash(key) else { return Ok(true) };
+            Ok(record(&hash)?.is_none_or(|r| r.seeders >= 2 || r.first_seen_unix_ms.max(0) != ts))
+        }),
+        ("idx_missing_info", missing_info.clone(), &|hash, _| has_info_or_gone(hash)),
+        // Queue entries count only under the key their missing-info entry holds.
+        ("idx_enrich_priority", enrich_priority_tree(db)?, &|key, _| {
+            Ok(key.len() < 8 || missing_info.get(&key[8..])?.as_deref() != Some(key))
+        }),
+        ("idx_enrich_hot", enrich_hot_tree(db)?, &|key, _| {
+            Ok(key.len() < 8 || !missing_info.contains_key(&key[8..])?)
+        }),
+        ("enrich_attempts", attempts.clone(), &|hash, _| has_info_or_gone(hash)),
+        ("idx_enrich_retry", retry, &|key, _| match ts_hash(key) {
+            Some((_, hash)) => has_info_or_gone(&hash),
+            None => Ok(true),
+        }),
+        ("idx_next_recheck", recheck_tree(db)?, &|key, _| match ts_hash(key) {
+            Some((_, hash)) => Ok(record(&hash)?.is_none_or(|r| !has_info(&r))),
+            None => Ok(true),
+        }),
+        ("idx_info_hash_v2", v2_tree, &|v2, hash| {
+            Ok(record(hash)?.is_none_or(|r| r.info_hash_v2_hex.as_deref().map(str::as_bytes) != Some(v2)))
+        }),
+        ("seeder_history", seeder_history_tree(db)?, &|hash, _| Ok(record(hash)?.is_none())),
+    ];
+    for (name, tree, is_stale) in checks {
+        let mut stale = Vec::new();
+        for item in tree.iter() {
+            let (key, value) = item?;
+            if is_stale(&key, &value)? {
+                stale.push(key);
+            }
+        }
+        if !stale.is_empty() {
+            report.stale_entries.insert(name, stale.len() as u64);
+        }
+        if repair {
+            for key in stale {
+                let _ = tree.remove(key)?;
+            }
+        }
+    }
+
+    if read_counter(db, META_RECORD_COUNT)? != total || read_counter(db, META_NO_INFO_COUNT)? != no_info {
+        report.counters_off = true;
+        if repair {
+            let meta = meta_tree(db)?;
+            meta.insert(META_RECORD_COUNT, u64_be(total))?;
+            meta.insert(META_NO_INFO_COUNT, u64_be(no_info))?;
+        }
+    }
+    Ok(report)
+}
+
+/// Progress of one `measure_tree` call.
+pub struct TreeSlice {
+    /// Key and value bytes seen.
+    pub bytes: u64,
+    /// Last key seen, to resume after; `None` once the tree is done.
+    pub next: Option<Vec<u8>>,
+}
+
+/// Adds up a tree's key and value sizes, starting after `after`, until `deadline`.
+pub fn measure_tree(
+    db: &Db,
+    name: &[u8],
+    after: Option<&[u8]>,
+    deadline: std::time::Instant,
+) -> anyhow::Result<TreeSlice> {
+    let tree = db.open_tree(name)?;
+    let range = match after {
+        Some(key) => tree.range::<&[u8], _>((std::ops::Bound::Excluded(key), std::ops::Bound::Unbounded)),
+        None => tree.iter(),
+    };
+    let mut slice = TreeSlice { bytes: 0, next: None };
+    for (seen, item) in range.enumerate() {
+        let (key, value) = item?;
+        slice.bytes += (key.len() + value.len()) as u64;
+        if (seen + 1).is_multiple_of(1024) && std::time::Instant::now() >= deadline {
+            slice.next = Some(key.to_vec());
+            break;
+        }
+    }
+    Ok(slice)
+}
+
+// Cleanup sweep reports, keyed by sweep time, so operators can see what a TTL or grace
+// setting removes (or would remove, in dry-run mode) across restarts. Only the newest
+// `keep` entries are retained.
+
+/// Stores a sweep report and drops the oldest ones beyond `keep`.
+pub fn push_cleanup_sweep<T: Serialize>(db: &Db, at_unix_ms: i64, sweep: &T, keep: usize) -> anyhow::Result<()> {
+    let tree = cleanup_history_tree(db)?;
+    tree.insert(u64_be(at_unix_ms.max(0) as u64), serde_json::to_vec(sweep)?)?;
+    while tree.len()? > keep {
+        let Some((key, _)) = tree.first()? else {
+            break;
+        };
+        tree.remove(key)?;
+    }
+    Ok(())
+}
+
+/// Up to `limit` sweep reports, newest first. Entries that no longer decode are skipped.
+pub fn cleanup_history<T: serde::de::DeserializeOwned>(db: &Db, limit: usize) -> anyhow::Result<Vec<T>> {
+    let mut out = Vec::new();
+    for item in cleanup_history_tree(db)?.iter().rev().take(limit) {
+        let (_, value) = item?;
+        if let Ok(sweep) = serde_json::from_slice(&value) {
+            out.push(sweep);
+        }
+    }
+    Ok(out)
+}
+
+// With `SERMA_CLEANUP_ARCHIVE`, cleanup moves expired records here instead of only
+// deleting them. `archive` maps hash -> archived_at (8 bytes) + the record in its stored
+// encoding, deflated. Nothing reads it on the hot paths; searching it walks every entry.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedTorrent {
+    pub info_hash: String,
+    pub title: Option<String>,
+    pub size_bytes: u64,
+    pub seeders: i64,
+    pub has_metadata: bool,
+    pub first_seen_unix_ms: i64,
+    pub last_seen_unix_ms: i64,
+    pub archived_at_unix_ms: i64,
+}
+
+impl ArchivedTorrent {
+    fn new(record: &TorrentRecord, archived_at_unix_ms: i64) -> Self {
+        Self {
+            info_hash: record.info_hash_hex.clone(),
+            title: record.title.clone(),
+            size_bytes: record.size_bytes,
+            seeders: record.seeders,
+            has_metadata: has_info(record),
+            first_seen_unix_ms: record.first_seen_unix_ms,
+            last_seen_unix_ms: record.last_seen_unix_ms,
+            archived_at_unix_ms,
+        }
+    }
+}
+
+fn decode_archived(value: &[u8]) -> anyhow::Result<(TorrentRecord, i64)> {
+    use std::io::Read as _;
+    let at = <[u8; 8]>::try_from(value.get(..8).context("archive entry too short")?)?;
+    let mut bytes = Vec::new();
+    flate2::read::DeflateDecoder::new(&value[8..]).read_to_end(&mut bytes)?;
+    Ok((decode_torrent_record(&bytes)?.0, u64::from_be_bytes(at) as i64))
+}
+
+/// Moves a record into the archive and deletes it (tombstone included).
+pub fn archive(db: &Db, record: &TorrentRecord) -> anyhow::Result<()> {
+    use std::io::Write as _;
+    let mut encoder = flate2::write::DeflateEncoder::new(
+        u64_be(now_unix_ms().max(0) as u64).to_vec(),
+        flate2::Compression::default(),
+    );
+    encoder.write_all(&encode_torrent_record(record)?)?;
+    archive_tree(db)?.insert(record.info_hash_hex.as_bytes(), encoder.finish()?)?;
+    delete(db, &record.info_hash_hex)
+}
+
+/// Archived torrents whose title contains every word of `q` (case-insensitive), or the one
+/// with that hash. An empty query lists the archive. At most `limit`, in hash order.
+pub fn search_archive(db: &Db, q: &str, limit: usize) -> anyhow::Result<Vec<ArchivedTorrent>> {
+    let tree = archive_tree(db)?;
+    let q = q.trim().to_lowercase();
+    if q.len() == 40 && q.chars().all(|c| c.is_ascii_hexdigit()) {
+        return match tree.get(q.as_bytes())? {
+            Some(value) => {
+                let (record, at) = decode_archived(&value)?;
+                Ok(vec![ArchivedTorrent::new(&record, at)])
+            }
+            None => Ok(Vec::new()),
+        };
+    }
+
+    let words: Vec<&str> = q.split_whitespace().collect();
+    let mut out = Vec::new();
+    for item in tree.iter() {
+        if out.len() >= limit {
+            break;
+        }
+        let (_, value) = item?;
+        let Ok((record, at)) = decode_archived(&value) else {
+            continue;
+        };
+        let title = record.title.as_deref().unwrap_or_default().to_lowercase();
+        if words.iter().all(|w| title.contains(w)) {
+            out.push(ArchivedTorrent::new(&record, at));
+        }
+    }
+    Ok(out)
+}
+
+/// Takes a torrent out of the archive and stores it again, as if just seen, so cleanup
+/// doesn't expire it again straight away. Returns `None` if it isn't archived.
+pub fn restore_archived(db: &Db, info_hash_hex: &str) -> anyhow::Result<Option<TorrentRecord>> {
+    let tree = archive_tree(db)?;
+    let Some(value) = tree.get(info_hash_hex.as_bytes())? else {
+        return Ok(None);
+    };
+    let (mut record, _) = decode_archived(&value)?;
+    record.last_seen_unix_ms = now_unix_ms();
+    // The time index entry goes stale and is dropped by `prune_tombstones`.
+    let _ = tombstone_tree(db)?.remove(info_hash_hex.as_bytes())?;
+    let (record, _) = merge_imported(db, record)?;
+    let _ = tree.remove(info_hash_hex.as_bytes())?;
+    Ok(Some(record))
+}
+
+// The blacklist is permanent, unlike tombstones: blacklisted hashes are deleted and every
+// ingest path (spider, ingest, import, sync, uploads) drops them for good.
+// `blacklist` maps hash -> blacklisted_at.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlacklistEntry {
+    pub info_hash: String,
+    pub blacklisted_at_unix_ms: i64,
+}
+
+/// Blacklists a (stored, v1 or truncated v2) hash and deletes its record.
+/// Returns whether a record was deleted.
+pub fn blacklist(db: &Db, info_hash_hex: &str) -> anyhow::Result<bool> {
+    blacklist_tree(db)?.insert(info_hash_hex.as_bytes(), u64_be(now_unix_ms().max(0) as u64))?;
+    let existed = contains(db, info_hash_hex)?;
+    if existed {
+        delete(db, info_hash_hex)?;
+    }
+    Ok(existed)
+}
+
+/// Returns whether the hash was blacklisted.
+pub fn unblacklist(db: &Db, info_hash_hex: &str) -> anyhow::Result<bool> {
+    Ok(blacklist_tree(db)?.remove(info_hash_hex.as_bytes())?.is_some())
+}
+
+pub fn is_blacklisted(db: &Db, info_hash_hex: &str) -> anyhow::Result<bool> {
+    blacklist_tree(db)?.contains_key(info_hash_hex.as_bytes())
+}
+
+pub fn list_blacklist(db: &Db) -> anyhow::Result<Vec<BlacklistEntry>> {
+    let mut out = Vec::new();
+    for item in blacklist_tree(db)?.iter() {
+        let (hash, at) = item?;
+        out.push(BlacklistEntry {
+            info_hash: String::from_utf8_lossy(&hash).into_owned(),
+            blacklisted_at_unix_ms: <[u8; 8]>::try_from(at.as_ref())
+                .map(|b| u64::from_be_bytes(b) as i64)
+                .unwrap_or(0),
+        });
+    }
+    Ok(out)
+}
+
+/// Sets or clears a record's `pinned` flag. Returns the updated record, or `None` if
+/// there is no such record. Cleanup drops the index entries of pinned records as it
+/// passes them, so unpinning puts them back.
+pub fn set_pinned(db: &Db, info_hash_hex: &str, pinned: bool) -> anyhow::Result<Option<TorrentRecord>> {
+    let key = key_for_hash(info_hash_hex);
+    let Some(mut record) = db.get(&key)?.and_then(|b| decode_torrent_record(&b).ok()).map(|(r, _)| r) else {
+        return Ok(None);
+    };
+    if record.pinned == pinned {
+        return Ok(Some(record));
+    }
+    record.pinned = pinned;
+    db.insert(&key, encode_torrent_record(&record)?)?;
+    if !pinned {
+        last_seen_tree(db)?.insert(ts_key(record.last_seen_unix_ms, info_hash_hex), [])?;
+        if record.seeders < 2 {
+            low_seed_tree(db)?.insert(ts_key(record.first_seen_unix_ms, info_hash_hex), [])?;
+        }
+    }
+    Ok(Some(record))
+}
+
+/// Turns user input into a tag: lowercase, with runs of whitespace as one `-`. `None` if
+/// nothing is left, it's longer than MAX_TAG_CHARS or it contains a `:`.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join("-").to_lowercase();
+    (!tag.is_empty() && tag.chars().count() <= MAX_TAG_CHARS && !tag.contains(':')).then_some(tag)
+}
+
+/// Normalizes a record's new tags, dropping duplicates but keeping their order.
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = normalize_tag(tag).ok_or_else(|| format!("invalid tag {tag:?}"))?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(format!("at most {MAX_TAGS} tags per torrent"));
+    }
+    Ok(normalized)
+}
+
+/// Replaces a record's tags and/or note (already normalized); `None` leaves that part as
+/// it is, and an empty note clears it. Returns the updated record, or `None` if there is none.
+pub fn set_annotations(
+    db: &Db,
+    info_hash_hex: &str,
+    tags: Option<Vec<String>>,
+    note: Option<String>,
+) -> anyhow::Result<Option<TorrentRecord>> {
+    let key = key_for_hash(info_hash_hex);
+    let Some(mut record) = db.get(&key)?.and_then(|b| decode_torrent_record(&b).ok()).map(|(r, _)| r) else {
+        return Ok(None);
+    };
+    if let Some(tags) = tags {
+        record.tags = tags;
+    }
+    if let Some(note) = note {
+        record.note = (!note.is_empty()).then_some(note);
+    }
+    db.insert(&key, encode_torrent_record(&record)?)?;
+    Ok(Some(record))
+}
+
+fn source_seen_key(source: &str, item: &str) -> Vec<u8> {
+    // Key: xxh3(source URL) (8 bytes) + item id, so one source's items share a prefix.
+    let mut key = Vec::with_capacity(8 + item.len());
+    key.extend_from_slice(&xxhash_rust::xxh3::xxh3_64(source.as_bytes()).to_be_bytes());
+    key.extend_from_slice(item.as_bytes());
+    key
+}
+
+/// Returns, per item (info hash or feed item id), whether it was already fetched from `source`.
+pub fn source_seen(db: &Db, source: &str, items: &[String]) -> anyhow::Result<Vec<bool>> {
+    let tree = source_seen_tree(db)?;
+    items
+        .iter()
+        .map(|item| tree.contains_key(source_seen_key(source, item)))
+        .collect()
+}
+
+/// Records items fetched from `source` so later fetches skip them.
+pub fn mark_source_seen(db: &Db, source: &str, items: &[String]) -> anyhow::Result<()> {
+    let tree = source_seen_tree(db)?;
+    let now = u64_be(now_unix_ms().max(0) as u64);
+    let mut batch = Batch::default();
+    for item in items {
+        batch.insert(source_seen_key(source, item), now);
+    }
+    tree.apply_batch(batch)?;
+    Ok(())
+}
+
+/// Forgets a source's items first recorded before `cutoff_unix_ms`. Returns how many were removed.
+pub fn prune_source_seen(db: &Db, source: &str, cutoff_unix_ms: i64) -> anyhow::Result<usize> {
+    let tree = source_seen_tree(db)?;
+    let prefix = xxhash_rust::xxh3::xxh3_64(source.as_bytes()).to_be_bytes();
+    let mut removed = 0;
+    for item in tree.scan_prefix(prefix) {
+        let (key, value) = item?;
+        let ts = <[u8; 8]>::try_from(value.as_ref())
+            .map(|v| u64::from_be_bytes(v) as i64)
+            .unwrap_or(0);
+        if ts < cutoff_unix_ms {
+            let _ = tree.remove(key)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// Saved searches: `saved_searches` maps name -> JSON `SavedSearch`; `saved_search_hits`
+// holds `name \0 hash` for every torrent a search has already matched, so each match
+// is announced once.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    pub query: String,
+    pub webhook_url: String,
+    /// `json` (default), `discord` or `slack`.
+    #[serde(default)]
+    pub webhook_format: WebhookFormat,
+    #[serde(default)]
+    pub created_unix_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Json,
+    Discord,
+    Slack,
+}
+
+pub fn list_saved_searches(db: &Db) -> anyhow::Result<Vec<SavedSearch>> {
+    saved_search_tree(db)?
+        .iter()
+        .map(|item| {
+            let (_, value) = item?;
+            Ok(serde_json::from_slice(&value)?)
+        })
+        .collect()
+}
+
+/// Creates or replaces a saved search. Replacing keeps its match history unless the
+/// query changed.
+pub fn put_saved_search(db: &Db, mut search: SavedSearch) -> anyhow::Result<SavedSearch> {
+    let tree = saved_search_tree(db)?;
+    let before: Option<SavedSearch> = tree
+        .get(search.name.as_bytes())?
+        .and_then(|v| serde_json::from_slice(&v).ok());
+    search.created_unix_ms = before.as_ref().map(|b| b.created_unix_ms).unwrap_or_else(now_unix_ms);
+    if before.is_some_and(|b| b.query != search.query) {
+        clear_saved_search_hits(db, &search.name)?;
+    }
+    tree.insert(search.name.as_bytes(), serde_json::to_vec(&search)?)?;
+    Ok(search)
+}
+
+/// Returns false if no search had that name.
+pub fn delete_saved_search(db: &Db, name: &str) -> anyhow::Result<bool> {
+    let removed = saved_search_tree(db)?.remove(name.as_bytes())?.is_some();
+    clear_saved_search_hits(db, name)?;
+    Ok(removed)
+}
+
+fn saved_search_hit_prefix(name: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(name.len() + 1);
+    key.extend_from_slice(name.as_bytes());
+    key.push(0);
+    key
+}
+
+fn clear_saved_search_hits(db: &Db, name: &str) -> anyhow::Result<()> {
+    let tree = saved_search_hit_tree(db)?;
+    for item in tree.scan_prefix(saved_search_hit_prefix(name)) {
+        let (key, _) = item?;
+        let _ = tree.remove(key)?;
+    }
+    Ok(())
+}
+
+fn saved_search_hit_key(name: &str, info_hash_hex: &str) -> Vec<u8> {
+    let mut key = saved_search_hit_prefix(name);
+    key.extend_from_slice(info_hash_hex.as_bytes());
+    key
+}
+
+pub fn saved_search_hit_known(db: &Db, name: &str, info_hash_hex: &str) -> anyhow::Result<bool> {
+    saved_search_hit_tree(db)?.contains_key(saved_search_hit_key(name, info_hash_hex))
+}
+
+/// True if the saved search had never matched `info_hash_hex` before; records it either way.
+pub fn mark_saved_search_hit(db: &Db, name: &str, info_hash_hex: &str) -> anyhow::Result<bool> {
+    let key = saved_search_hit_key(name, info_hash_hex);
+    let previous = saved_search_hit_tree(db)?.insert(key, u64_be(now_unix_ms().max(0) as u64))?;
+    Ok(previous.is_none())
+}
+
+/// False the first time a saved search is evaluated (its existing matches are recorded
+/// without being announced), true afterwards.
+pub fn saved_search_primed(db: &Db, name: &str) -> anyhow::Result<bool> {
+    // The marker is a hit with an empty hash.
+    Ok(!mark_saved_search_hit(db, name, "")?)
+}
+
+// Blocklist rules: `block_rules` maps id (u64 BE) -> JSON `BlockRule`. See `blocklist.rs`
+// for how they are matched.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockRule {
+    pub id: u64,
+    pub kind: BlockRuleKind,
+    pub pattern: String,
+    #[serde(default)]
+    pub created_unix_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockRuleKind {
+    /// Case-insensitive substring of the title.
+    Keyword,
+    /// Case-insensitive regex over the title.
+    Regex,
+    /// File extension (without the dot) of any file in the torrent.
+    Extension,
+}
+
+pub fn list_block_rules(db: &Db) -> anyhow::Result<Vec<BlockRule>> {
+    block_rule_tree(db)?
+        .iter()
+        .map(|item| {
+            let (_, value) = item?;
+            Ok(serde_json::from_slice(&value)?)
+        })
+        .collect()
+}
+
+/// Stores a new rule, assigning its id and creation time.
+pub fn add_block_rule(db: &Db, kind: BlockRuleKind, pattern: &str) -> anyhow::Result<BlockRule> {
+    let rule = BlockRule {
+        id: db.generate_id()?,
+        kind,
+        pattern: pattern.to_string(),
+        created_unix_ms: now_unix_ms(),
+    };
+    block_rule_tree(db)?.insert(u64_be(rule.id), serde_json::to_vec(&rule)?)?;
+    Ok(rule)
+}
+
+/// Returns false if no rule had that id.
+pub fn delete_block_rule(db: &Db, id: u64) -> anyhow::Result<bool> {
+    Ok(block_rule_tree(db)?.remove(u64_be(id))?.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn racing_writers_keep_the_record_count() {
+        for backend in [crate::kv::Backend::Sled, crate::kv::Backend::Sqlite] {
+            let db = crate::kv::open(backend, &crate::test_dir("storage")).unwrap();
+            let hashes: Vec<String> = (0..50u8).map(|i| hex::encode([i; 20])).collect();
+            std::thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| {
+                        for hash in &hashes {
+                            upsert_first_seen(&db, hash, None).unwrap();
+                        }
+                    });
+                }
+            });
+            assert_eq!(record_counts(&db).unwrap().total, hashes.len());
+
+            delete(&db, &hashes[0]).unwrap();
+            delete(&db, &hashes[0]).unwrap();
+            assert_eq!(record_counts(&db).unwrap().total, hashes.len() - 1);
         }
     }
-    Ok(())
 }
@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+// Keyspace sweep for BEP-51 sampling.
+//
+// A `sample_infohashes` answer carries the answering node's own samples plus the nodes it
+// knows closest to our target, so the target decides which part of the keyspace we learn
+// new nodes in. Instead of random targets, the sweep splits the keyspace into 256 regions
+// by first id byte and counts the samples harvested from nodes in each; the next target
+// lies in the region with the fewest (ties go round-robin), and nodes in thin regions are
+// sampled first. Counts halve every `DECAY_EVERY`, so the sweep follows the network as it
+// changes rather than the whole run's history.
+
+const REGIONS: usize = 256;
+const DECAY_EVERY: Duration = Duration::from_secs(3600);
+
+pub struct KeyspaceSweep {
+    /// Samples harvested per region.
+    harvested: [u64; REGIONS],
+    /// Next region to look at when several are equally thin.
+    cursor: usize,
+    last_decay: Instant,
+}
+
+impl Default for KeyspaceSweep {
+    fn default() -> Self {
+        Self {
+            harvested: [0; REGIONS],
+            cursor: 0,
+            last_decay: Instant::now(),
+        }
+    }
+}
+
+impl KeyspaceSweep {
+    /// A target id in the least explored region, the rest of it random.
+    pub fn next_target(&mut self) -> [u8; 20] {
+        self.maybe_decay();
+        let region = (0..REGIONS)
+            .map(|i| (self.cursor + i) % REGIONS)
+            .min_by_key(|&r| self.harvested[r])
+            .unwrap_or(0);
+        self.cursor = (region + 1) % REGIONS;
+        let mut target = *rbit::peer::PeerId::generate().as_bytes();
+        target[0] = region as u8;
+        target
+    }
+
+    /// Counts `samples` harvested from the node with id `node_id`.
+    pub fn record(&mut self, node_id: &[u8; 20], samples: usize) {
+        let region = &mut self.harvested[node_id[0] as usize];
+        *region = region.saturating_add(samples as u64);
+    }
+
+    /// How well explored the region of `node_id` is; lower is thinner.
+    pub fn coverage(&self, node_id: &[u8; 20]) -> u64 {
+        self.harvested[node_id[0] as usize]
+    }
+
+    fn maybe_decay(&mut self) {
+        if self.last_decay.elapsed() < DECAY_EVERY {
+            return;
+        }
+        for count in &mut self.harvested {
+            *count /= 2;
+        }
+        self.last_decay = Instant::now();
+    }
+}
@@ -0,0 +1,345 @@
+use crate::ingest::IngestSummary;
+use crate::web::ExportPage;
+use crate::{AppState, storage};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Instance-to-instance sync (federation). Each instance can:
+//
+// - pull: periodically fetch the records that other instances saw since the last pull
+//   (their `/api/export` feed, SERMA_SYNC_FROM) and merge them locally;
+// - push: send the records it saw since the last push to other instances (their
+//   `POST /api/sync`, SERMA_SYNC_PUSH_TO), for nodes behind NAT that nobody can pull from.
+//
+// Both sides authenticate with the receiving / exporting instance's SERMA_EXPORT_TOKEN.
+// The cursor (last-seen time + hash of the last record) is stored per peer and direction
+// in the `meta` tree, so each round only transfers what changed. Merging never overwrites:
+// the earliest first-seen and latest last-seen win, and title, magnet and info dict only
+// fill empty fields. A peer's info dict is kept only if it hashes to the record's info
+// hash, and its swarm counts are ignored: seeders come from this instance's own scrapes,
+// so no peer can push a torrent up the rankings. Deletions are not synced. Records that
+// came in by sync are not pushed on, so peers pushing to each other don't echo records back.
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+const PAGE_SIZE: usize = 1000;
+/// Records per `POST /api/sync`; they carry info dicts, so keep requests small.
+pub const PUSH_PAGE_SIZE: usize = 200;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cursor {
+    since: i64,
+    after: Option<String>,
+}
+
+/// Body of `POST /api/sync`.
+#[derive(Serialize, Deserialize)]
+pub struct SyncPush {
+    pub records: Vec<storage::TorrentRecord>,
+}
+
+pub async fn run(state: AppState) {
+    let pull_from = state.config.sync_from.clone();
+    let push_to = state.config.sync_push_to.clone();
+    if pull_from.is_empty() && push_to.is_empty() {
+        tracing::info!("sync: disabled (SERMA_SYNC_FROM and SERMA_SYNC_PUSH_TO not set)");
+        return;
+    }
+
+    let client = match reqwest::Client::builder()
+        .user_agent(concat!("serma/", env!("CARGO_PKG_VERSION")))
+        .timeout(FETCH_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::warn!(%err, "sync: failed creating HTTP client; disabled");
+            return;
+        }
+    };
+
+    tracing::info!(
+        pull_from = ?pull_from,
+        push_to = ?push_to,
+        every_secs = state.config.sync_every_secs,
+        "sync: started"
+    );
+
+    let mut tick = tokio::time::interval(Duration::from_secs(state.config.sync_every_secs));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = state.shutdown.wait() => return,
+        }
+        // Peers are independent: one that is down doesn't hold up the others.
+        let (state, client) = (&state, &client);
+        let pulls = pull_from.iter().map(|remote| async move {
+            match pull(state, client, remote).await {
+                Ok(summary) if summary.lines > 0 => summary.log(remote),
+                Ok(_) => tracing::debug!(%remote, "sync: up to date"),
+                Err(err) => tracing::warn!(%remote, err = %format!("{err:#}"), "sync: pull failed"),
+            }
+        });
+        let pushes = push_to.iter().map(|remote| async move {
+            match push(state, client, remote).await {
+                Ok(0) => tracing::debug!(%remote, "sync: nothing to push"),
+                Ok(pushed) => tracing::info!(%remote, pushed, "sync: pushed records"),
+                Err(err) => tracing::warn!(%remote, err = %format!("{err:#}"), "sync: push failed"),
+            }
+        });
+        futures_util::future::join(
+            futures_util::future::join_all(pulls),
+            futures_util::future::join_all(pushes),
+        )
+        .await;
+    }
+}
+
+fn load_cursor(state: &AppState, key: &str) -> anyhow::Result<Cursor> {
+    Ok(storage::get_meta(&state.db, key)?
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default())
+}
+
+/// Pulls pages until the remote has nothing newer, saving the cursor after each page.
+async fn pull(state: &AppState, client: &reqwest::Client, remote: &str) -> anyhow::Result<IngestSummary> {
+    let cursor_key = format!("sync_cursor:{remote}");
+    let mut cursor = {
+        let (state, cursor_key) = (state.clone(), cursor_key.clone());
+        tokio::task::spawn_blocking(move || load_cursor(&state, &cursor_key)).await??
+    };
+
+    let mut summary = IngestSummary::default();
+    // Stops between pages on shutdown; the cursor picks up from there next time.
+    while !state.shutdown.is_triggered() {
+        let mut request = client.get(format!("{remote}/api/export")).query(&[
+            ("since", cursor.since.to_string()),
+            ("limit", PAGE_SIZE.to_string()),
+        ]);
+        if let Some(after) = cursor.after.as_deref() {
+            request = request.query(&[("after", after)]);
+        }
+        if let Some(token) = state.config.sync_token.as_deref() {
+            request = request.bearer_auth(token);
+        }
+        let page: ExportPage = request
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("decode export page")?;
+
+        cursor = Cursor {
+            since: page.next_since,
+            after: page.next_after,
+        };
+        // Merging writes the database and the index; keep it off the async workers.
+        summary = {
+            let (state, cursor_key) = (state.clone(), cursor_key.clone());
+            let cursor = serde_json::to_vec(&cursor)?;
+            tokio::task::spawn_blocking(move || {
+                merge_page(&state, page.records, &mut summary)?;
+                storage::set_meta(&state.db, &cursor_key, &cursor)?;
+                anyhow::Ok(summary)
+            })
+            .await??
+        };
+
+        if !page.more {
+            break;
+        }
+    }
+    Ok(summary)
+}
+
+/// Sends records seen since the last push, page by page, saving the cursor after each
+/// page the remote accepted. Returns how many records were sent.
+async fn push(state: &AppState, client: &reqwest::Client, remote: &str) -> anyhow::Result<usize> {
+    let cursor_key = format!("sync_push_cursor:{remote}");
+    let mut cursor = {
+        let (state, cursor_key) = (state.clone(), cursor_key.clone());
+        tokio::task::spawn_blocking(move || load_cursor(&state, &cursor_key)).await??
+    };
+
+    let mut pushed = 0;
+    while !state.shutdown.is_triggered() {
+        let page = {
+            let (db, since, after) = (state.db.clone(), cursor.since, cursor.after.clone());
+            tokio::task::spawn_blocking(move || storage::list_seen_since(&db, since, after.as_deref(), PUSH_PAGE_SIZE))
+                .await??
+        };
+        let Some(last) = page.last() else {
+            return Ok(pushed);
+        };
+        let next = Cursor {
+            since: last.last_seen_unix_ms.max(0),
+            after: Some(last.info_hash_hex.clone()),
+        };
+        let full = page.len() == PUSH_PAGE_SIZE;
+        let records: Vec<_> = page
+            .into_iter()
+            .filter(|record| record.discovered_via != Some(storage::Discovery::Sync))
+            .collect();
+
+        if !records.is_empty() {
+            let count = records.len();
+            let mut request = client.post(format!("{remote}/api/sync")).json(&SyncPush { records });
+            if let Some(token) = state.config.sync_token.as_deref() {
+                request = request.bearer_auth(token);
+            }
+            request.send().await?.error_for_status()?;
+            pushed += count;
+        }
+
+        let (db, cursor_key, saved) = (state.db.clone(), cursor_key.clone(), serde_json::to_vec(&next)?);
+        tokio::task::spawn_blocking(move || storage::set_meta(&db, &cursor_key, &saved)).await??;
+        cursor = next;
+        if !full {
+            break;
+        }
+    }
+    Ok(pushed)
+}
+
+/// Merges one page of records from another instance and commits the index. Blocks on the
+/// database and the index, so async callers run it with `spawn_blocking`.
+pub fn merge_page(
+    state: &AppState,
+    records: Vec<storage::TorrentRecord>,
+    summary: &mut IngestSummary,
+) -> anyhow::Result<()> {
+    for record in records {
+        summary.lines += 1;
+        merge_record(state, record, storage::Discovery::Sync, false, summary)?;
+    }
+    state.index.commit()
+}
+
+/// Drops a peer's info dict unless it hashes to the record's info hash, along with the
+/// files, size and v2 hash that can only come from it.
+fn keep_verified_info(record: &mut storage::TorrentRecord) {
+    use base64::Engine as _;
+    let verified = record.info_bencode_base64.as_deref().is_some_and(|b64| {
+        let info = base64::engine::general_purpose::STANDARD.decode(b64).unwrap_or_default();
+        let hash = hex::decode(&record.info_hash_hex).ok().and_then(|h| <[u8; 20]>::try_from(h).ok());
+        hash.is_some_and(|hash| crate::enrich::verify_metadata(&hash, &info).is_ok())
+    });
+    if !verified {
+        if record.info_bencode_base64.is_some() {
+            tracing::debug!(info_hash = %record.info_hash_hex, "sync: info dict doesn't match its hash; dropped");
+        }
+        record.info_bencode_base64 = None;
+        record.info_hash_v2_hex = None;
+        record.size_bytes = 0;
+        record.file_count = 0;
+        record.files = Vec::new();
+    }
+}
+
+/// Merges a record from another instance (sync or `import --format serma`) and reindexes it.
+/// A new record is counted as discovered `via` this, whatever the other instance says.
+pub fn merge_record(
+    state: &AppState,
+    record: storage::TorrentRecord,
+    via: storage::Discovery,
+    include_deleted: bool,
+    summary: &mut IngestSummary,
+) -> anyhow::Result<()> {
+    let hash = record.info_hash_hex.to_ascii_lowercase();
+    if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        summary.reject_invalid(summary.lines, &record.info_hash_hex, "not a v1 info hash");
+        return Ok(());
+    }
+    if storage::is_blacklisted(&state.db, &hash)? {
+        summary.reject_blocked(summary.lines, &hash, "blacklisted on this instance");
+        return Ok(());
+    }
+    // Same rule as imports: don't resurrect torrents removed here recently.
+    if !include_deleted && storage::is_tombstoned(&state.db, &hash)? && storage::get(&state.db, &hash)?.is_none() {
+        summary.reject_blocked(summary.lines, &hash, "deleted recently on this instance");
+        return Ok(());
+    }
+    if let Some(rule) = crate::ingest::blocked_by(state, &record) {
+        summary.reject_blocked(summary.lines, &hash, rule);
+        return Ok(());
+    }
+
+    let mut record = storage::TorrentRecord {
+        info_hash_hex: hash,
+        // Pins, tags and notes are local choices, and swarm counts are our own scrapes'.
+        pinned: false,
+        tags: Vec::new(),
+        note: None,
+        discovered_via: Some(via),
+        seeders: 0,
+        leechers: 0,
+        announces: 0,
+        ..record
+    };
+    keep_verified_info(&mut record);
+    let (merged, created) = storage::merge_imported(&state.db, record)?;
+    if created {
+        summary.accepted += 1;
+    } else {
+        summary.existing += 1;
+    }
+
+    if merged.title.is_some() {
+        state.index.upsert_record(&merged)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine as _;
+
+    fn info_dict(name: &str) -> Vec<u8> {
+        let mut info = format!("d6:lengthi5e4:name{}:{name}12:piece lengthi16384e6:pieces20:", name.len()).into_bytes();
+        info.extend_from_slice(&[0; 20]);
+        info.push(b'e');
+        info
+    }
+
+    fn remote(hash: &str, info: &[u8], seeders: i64) -> storage::TorrentRecord {
+        let mut record = storage::TorrentRecord::new(hash, 1_000);
+        record.title = Some("remote".to_string());
+        record.info_bencode_base64 = Some(base64::engine::general_purpose::STANDARD.encode(info));
+        record.seeders = seeders;
+        record.leechers = seeders;
+        record
+    }
+
+    #[test]
+    fn merge_checks_info_dicts_and_ignores_remote_counts() {
+        let state = crate::test_state(&[]);
+        let mut summary = IngestSummary::default();
+        let via = storage::Discovery::Sync;
+
+        let info = info_dict("real");
+        let hash = rbit::metainfo::InfoHashV1::from_info_bytes(&info).to_hex();
+        merge_record(&state, remote(&hash, &info, 500), via, false, &mut summary).unwrap();
+        let stored = storage::get(&state.db, &hash).unwrap().unwrap();
+        assert!(stored.info_bencode_base64.is_some());
+        assert_eq!(stored.files.len(), 1);
+        assert_eq!((stored.seeders, stored.leechers), (0, 0));
+
+        // Someone else's dict under this hash is dropped with everything read from it.
+        let forged = "ab".repeat(20);
+        merge_record(&state, remote(&forged, &info_dict("fake"), 500), via, false, &mut summary).unwrap();
+        let stored = storage::get(&state.db, &forged).unwrap().unwrap();
+        assert!(stored.info_bencode_base64.is_none());
+        assert!(stored.files.is_empty());
+        assert_eq!(stored.size_bytes, 0);
+
+        // Local counts survive a peer reporting more.
+        storage::set_swarm_counts(&state.db, &hash, 3, 4, &state.config.seeder_history()).unwrap();
+        merge_record(&state, remote(&hash, &info, 9_999), via, false, &mut summary).unwrap();
+        let stored = storage::get(&state.db, &hash).unwrap().unwrap();
+        assert_eq!((stored.seeders, stored.leechers), (3, 4));
+        assert_eq!((summary.accepted, summary.existing), (2, 1));
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::Write;
+
+use anyhow::Context;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+use serma::config::Config;
+
+// Logs always go to the terminal. With `SERMA_OTLP_ENDPOINT` set, spans (enrichment
+// attempts, DHT lookups, web requests, each with the info hash where there is one) are
+// also exported over OTLP/HTTP, for Jaeger, Tempo or any OpenTelemetry collector.
+
+/// Flushes exported spans when dropped; keep it alive for the life of the process.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl Telemetry {
+    /// Replaces the `RUST_LOG` filter, for `PATCH /api/admin/config`.
+    pub fn log_filter_reload(&self) -> impl Fn(&str) -> anyhow::Result<()> + Send + Sync + 'static {
+        let handle = self.filter.clone();
+        move |filter| {
+            let filter = EnvFilter::try_new(filter)?;
+            handle.reload(filter).context("reload log filter")
+        }
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("otlp shutdown: {e}");
+        }
+    }
+}
+
+/// Installs the global subscriber: `RUST_LOG` filtering (default `info`), log lines on
+/// stdout (stderr when stdout carries command output), and the OTLP exporter if configured.
+pub fn init(config: &Config, logs_to_stderr: bool) -> anyhow::Result<Telemetry> {
+    let provider = config
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| tracer_provider(config, endpoint))
+        .transpose()?;
+    let otel = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("serma")));
+
+    let (filter, filter_handle) =
+        reload::Layer::new(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_writer(move || -> Box<dyn Write> {
+            if logs_to_stderr {
+                Box::new(std::io::stderr())
+            } else {
+                Box::new(std::io::stdout())
+            }
+        }))
+        .with(otel)
+        .init();
+
+    if let Some(endpoint) = &config.otlp_endpoint {
+        tracing::info!(
+            endpoint = %endpoint,
+            sample_ratio = config.otlp_sample_ratio,
+            "exporting trace spans over otlp"
+        );
+    }
+    Ok(Telemetry {
+        provider,
+        filter: filter_handle,
+    })
+}
+
+fn tracer_provider(config: &Config, endpoint: &str) -> anyhow::Result<SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{endpoint}/v1/traces"))
+        .build()
+        .context("build otlp exporter")?;
+    // Follow the caller's decision for child spans so traces are never cut in half.
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+        config.otlp_sample_ratio as f64,
+    )));
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(sampler)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.otlp_service_name.clone())
+                .build(),
+        )
+        .build())
+}
@@ -0,0 +1,127 @@
+use std::time::{Duration, Instant};
+
+// Discovery auto-tuning.
+//
+// The spider can find hashes much faster than enrichment fetches their metadata, and every
+// hash it keeps is a record on disk. Left alone, the enrich queue grows without end and the
+// database with it. Every `CHECK_EVERY` the controller compares the enrich queue with
+// SERMA_SPIDER_QUEUE_TARGET and the database's growth with SERMA_SPIDER_MAX_DISK_GROWTH_MB.
+// When either is over its limit, the admission rate is divided by how far over it is; once
+// both are under, it climbs back by `RECOVER_STEP` per check. The rate scales the BEP-51
+// sample queries per tick and the share of newly announced hashes that are ingested.
+// Dropped announces still count towards popularity and aren't marked as seen, so a torrent
+// announced again later gets another chance.
+
+pub const CHECK_EVERY: Duration = Duration::from_secs(30);
+/// Discovery never stops entirely, so the queue keeps fresh hashes.
+const MIN_RATE: f64 = 0.02;
+const RECOVER_STEP: f64 = 0.1;
+
+pub struct Throttle {
+    queue_target: u64,
+    /// Bytes per minute; 0 is unlimited.
+    max_growth: u64,
+    rate: f64,
+    /// Admissions owed to `admit`, so the rate is spread evenly over arrivals.
+    credit: f64,
+    last_size: Option<(Instant, u64)>,
+}
+
+impl Throttle {
+    /// A limit of 0 leaves that signal out.
+    pub fn new(queue_target: u64, max_growth_per_min: u64) -> Self {
+        Self {
+            queue_target,
+            max_growth: max_growth_per_min,
+            rate: 1.0,
+            credit: 0.0,
+            last_size: None,
+        }
+    }
+
+    /// Takes one reading and adjusts the rate; returns the database growth in bytes per
+    /// minute since the previous reading (0 on the first).
+    pub fn update(&mut self, queued: u64, disk_bytes: u64, now: Instant) -> u64 {
+        let growth = match self.last_size.replace((now, disk_bytes)) {
+            Some((then, before)) => {
+                let minutes = now.duration_since(then).as_secs_f64() / 60.0;
+                if minutes > 0.0 {
+                    (disk_bytes.saturating_sub(before) as f64 / minutes) as u64
+                } else {
+                    0
+                }
+            }
+            None => 0,
+        };
+        let over = |value: u64, limit: u64| if limit == 0 { 0.0 } else { value as f64 / limit as f64 };
+        let pressure = over(queued, self.queue_target).max(over(growth, self.max_growth));
+        self.rate = if pressure > 1.0 {
+            (self.rate / pressure).max(MIN_RATE)
+        } else {
+            (self.rate + RECOVER_STEP).min(1.0)
+        };
+        growth
+    }
+
+    /// The share of discovery currently let through, from `MIN_RATE` to 1.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Whether to ingest the next announced hash.
+    pub fn admit(&mut self) -> bool {
+        if self.rate >= 1.0 {
+            return true;
+        }
+        self.credit += self.rate;
+        if self.credit >= 1.0 {
+            self.credit -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sample queries to send this tick, out of `per_tick` at full rate; at least one.
+    pub fn samples(&self, per_tick: usize) -> usize {
+        ((per_tick as f64 * self.rate).ceil() as usize).clamp(per_tick.min(1), per_tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_under_pressure_and_recovers() {
+        let mut throttle = Throttle::new(1_000, 10 << 20);
+        let start = Instant::now();
+        throttle.update(500, 0, start);
+        assert_eq!(throttle.rate(), 1.0);
+        assert_eq!(throttle.samples(12), 12);
+
+        // Queue twice the target: half the rate, and half the announces get through.
+        throttle.update(2_000, 0, start + CHECK_EVERY);
+        assert_eq!(throttle.rate(), 0.5);
+        assert_eq!(throttle.samples(12), 6);
+        assert_eq!((0..100).filter(|_| throttle.admit()).count(), 50);
+
+        // Disk growing at 40 MiB a minute against a 10 MiB limit.
+        let growth = throttle.update(100, 20 << 20, start + CHECK_EVERY * 2);
+        assert_eq!(growth, 40 << 20);
+        assert_eq!(throttle.rate(), 0.125);
+
+        // A sustained flood bottoms out rather than stopping discovery.
+        for i in 3..10 {
+            throttle.update(1_000_000, 20 << 20, start + CHECK_EVERY * i);
+        }
+        assert_eq!(throttle.rate(), MIN_RATE);
+        assert_eq!(throttle.samples(12), 1);
+
+        for i in 10..30 {
+            throttle.update(0, 20 << 20, start + CHECK_EVERY * i);
+        }
+        assert_eq!(throttle.rate(), 1.0);
+        assert!(throttle.admit());
+    }
+}
@@ -0,0 +1,429 @@
+use crate::AppState;
+use crate::index::{SearchSort, max_offset};
+use crate::storage::TorrentRecord;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::fmt::Write as _;
+
+// Torznab API (the XML dialect Prowlarr/Jackett/Sonarr/Radarr speak), so Serma can be
+// added as a "Generic Torznab" indexer at `http://<host>/torznab`.
+//
+// Titles that parse as releases (see `release::parse`) are reported as Movies or TV, with
+// an SD/HD/UHD subcategory when the resolution is known. Anything else is reported in the
+// first category the client asked for (or "Other"); otherwise *arr apps would drop it.
+// Results link to magnets only. Peers are seeders plus leechers, which are only known
+// once a tracker or the DHT has reported them; sizes are 0 until metadata is known.
+
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 100;
+const CATEGORY_OTHER: u32 = 8000;
+/// Index entries scanned for the "latest releases" feed (search without a query).
+const RECENT_SCAN_LIMIT: usize = 20_000;
+
+#[derive(Deserialize)]
+pub struct TorznabParams {
+    t: Option<String>,
+    q: Option<String>,
+    apikey: Option<String>,
+    cat: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    season: Option<String>,
+    ep: Option<String>,
+    year: Option<String>,
+}
+
+pub async fn api(State(state): State<AppState>, headers: HeaderMap, Query(params): Query<TorznabParams>) -> Response {
+    let t = params.t.as_deref().unwrap_or_default();
+    if t == "caps" {
+        return xml(StatusCode::OK, caps_xml());
+    }
+
+    let apikey = params.apikey.as_deref().unwrap_or_default().as_bytes();
+    if let Some(expected) = state.config.torznab_apikey.as_deref() {
+        if !crate::web::constant_time_eq(apikey, expected.as_bytes()) {
+            return error_xml(100, "Incorrect user credentials");
+        }
+    } else if !state.config.public_search {
+        // A private index without a Torznab key takes the API token, as `apikey` (all *arr
+        // apps can send) or like the search pages do.
+        let authorized = state.config.api_token.as_deref().is_some_and(|token| {
+            crate::web::constant_time_eq(apikey, token.as_bytes()) || crate::web::is_authorized(&state, token, &headers)
+        });
+        if !authorized {
+            return error_xml(100, "Incorrect user credentials");
+        }
+    }
+    // Results are magnet links, which a read-only instance only gives out with a key.
+    if state.config.read_only && state.config.torznab_apikey.is_none() {
+        return error_xml(100, "Torznab needs SERMA_TORZNAB_APIKEY on a read-only instance");
+    }
+
+    let mut query = params.q.clone().unwrap_or_default().trim().to_string();
+    match t {
+        "search" => {}
+        "tvsearch" | "tv-search" => {
+            if let Some(suffix) = episode_suffix(params.season.as_deref(), params.ep.as_deref()) {
+                query = format!("{query} {suffix}").trim().to_string();
+            }
+        }
+        "movie" | "movie-search" => {
+            if let Some(year) = params.year.as_deref().map(str::trim).filter(|y| !y.is_empty()) {
+                // A number is matched against the parsed release year, anything else as text.
+                let year = match year.parse::<u16>() {
+                    Ok(year) => format!("year:{year}"),
+                    Err(_) => year.to_string(),
+                };
+                query = format!("{query} {year}").trim().to_string();
+            }
+        }
+        "" => return error_xml(200, "Missing parameter (t)"),
+        _ => return error_xml(202, "No such function"),
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).min(max_offset(SearchSort::Relevance, limit));
+    let category = params
+        .cat
+        .as_deref()
+        .and_then(|c| c.split(',').find_map(|id| id.trim().parse::<u32>().ok()))
+        .unwrap_or(CATEGORY_OTHER);
+
+    let records = if query.is_empty() {
+        // An empty search is the indexer's RSS feed: latest releases.
+        crate::storage::list_recent_titled(&state.db, offset, limit, RECENT_SCAN_LIMIT).unwrap_or_default()
+    } else {
+        let hits = state
+            .index
+            .search_page(&query, offset, limit, state.config.query_options())
+            .unwrap_or_default();
+        hits.into_iter()
+            .filter_map(|hit| {
+                let info_hash = hit.info_hash?;
+                let record = crate::storage::get(&state.db, &info_hash).ok().flatten();
+                // Title/magnet/swarm counts come from the index, first-seen time from storage.
+                Some(TorrentRecord {
+                    title: hit.title.or_else(|| record.as_ref().and_then(|r| r.title.clone())),
+                    magnet: hit.magnet.or_else(|| record.as_ref().and_then(|r| r.magnet.clone())),
+                    seeders: hit.seeders,
+                    info_bencode_base64: None,
+                    first_seen_unix_ms: record.as_ref().map(|r| r.first_seen_unix_ms).unwrap_or(0),
+                    last_seen_unix_ms: record.as_ref().map(|r| r.last_seen_unix_ms).unwrap_or(0),
+                    size_bytes: hit.size_bytes,
+                    file_count: record.as_ref().map(|r| r.file_count).unwrap_or(0),
+                    files: Vec::new(),
+                    info_hash_v2_hex: record.as_ref().and_then(|r| r.info_hash_v2_hex.clone()),
+                    leechers: hit.leechers,
+                    announces: hit.announces,
+                    pinned: record.as_ref().is_some_and(|r| r.pinned),
+                    tags: record.as_ref().map(|r| r.tags.clone()).unwrap_or_default(),
+                    note: None,
+                    discovered_via: record.as_ref().and_then(|r| r.discovered_via),
+                    release: record.as_ref().and_then(|r| r.release.clone()),
+                    info_hash_hex: info_hash,
+                })
+            })
+            .collect()
+    };
+
+    let base = base_url(&state.config, &headers);
+    xml(StatusCode::OK, results_xml(&base, &records, offset, category))
+}
+
+/// `season:1 episode:2` / `season:1` filters on the parsed release for numeric season/episode;
+/// daily shows ("2024", "03/15") are appended as-is.
+fn episode_suffix(season: Option<&str>, ep: Option<&str>) -> Option<String> {
+    let season = season.map(str::trim).filter(|s| !s.is_empty())?;
+    let ep = ep.map(str::trim).filter(|e| !e.is_empty());
+    match (season.parse::<u32>(), ep.map(str::parse::<u32>)) {
+        (Ok(s), Some(Ok(e))) if s < 1000 => Some(format!("season:{s} episode:{e}")),
+        (Ok(s), None) if s < 1000 => Some(format!("season:{s}")),
+        _ => Some(match ep {
+            Some(ep) => format!("{season} {ep}"),
+            None => season.to_string(),
+        }),
+    }
+}
+
+fn caps_xml() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<caps>
+  <server version="{version}" title="Serma"/>
+  <limits max="{MAX_LIMIT}" default="{DEFAULT_LIMIT}"/>
+  <searching>
+    <search available="yes" supportedParams="q"/>
+    <tv-search available="yes" supportedParams="q,season,ep"/>
+    <movie-search available="yes" supportedParams="q,year"/>
+  </searching>
+  <categories>
+    <category id="2000" name="Movies">
+      <subcat id="2030" name="Movies/SD"/>
+      <subcat id="2040" name="Movies/HD"/>
+      <subcat id="2045" name="Movies/UHD"/>
+    </category>
+    <category id="3000" name="Audio"/>
+    <category id="4000" name="PC"/>
+    <category id="5000" name="TV">
+      <subcat id="5030" name="TV/SD"/>
+      <subcat id="5040" name="TV/HD"/>
+      <subcat id="5045" name="TV/UHD"/>
+    </category>
+    <category id="7000" name="Books"/>
+    <category id="8000" name="Other"/>
+  </categories>
+</caps>
+"#,
+        version = env!("CARGO_PKG_VERSION"),
+    )
+}
+
+/// Where links in results point: SERMA_PUBLIC_URL, else the Host the client used, with the
+/// scheme this server speaks. A Host that isn't a plain `host[:port]` gives relative links.
+fn base_url(config: &crate::config::Config, headers: &HeaderMap) -> String {
+    if let Some(url) = &config.public_url {
+        return url.clone();
+    }
+    let scheme = if config.tls_cert.is_some() { "https" } else { "http" };
+    headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .filter(|host| is_host(host))
+        .map(|host| format!("{scheme}://{host}"))
+        .unwrap_or_default()
+}
+
+/// A hostname, IPv4 or bracketed IPv6 address, with an optional port.
+fn is_host(value: &str) -> bool {
+    let (host, port) = match value.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(':') && (!host.starts_with('[') || host.ends_with(']')) => (host, Some(port)),
+        _ => (value, None),
+    };
+    let host_ok = match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(v6) => v6.parse::<std::net::Ipv6Addr>().is_ok(),
+        None => {
+            !host.is_empty()
+                && host.len() <= 253
+                && host.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.')
+        }
+    };
+    host_ok && port.is_none_or(|p| p.parse::<u16>().is_ok())
+}
+
+fn results_xml(base: &str, records: &[TorrentRecord], offset: usize, category: u32) -> String {
+    let mut out = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:torznab="http://torznab.com/schemas/2015/feed">
+<channel>
+<title>Serma</title>
+<description>Serma DHT search</description>
+"#,
+    );
+    let _ = writeln!(out, "<link>{}/</link>", xml_escape(base));
+    let _ = writeln!(out, r#"<torznab:response offset="{offset}"/>"#);
+
+    for record in records {
+        let hash = &record.info_hash_hex;
+        let title = record.title.as_deref().unwrap_or(hash);
+        let magnet = record
+            .magnet
+            .clone()
+            .unwrap_or_else(|| format!("magnet:?xt=urn:btih:{hash}"));
+        let magnet = xml_escape(&magnet);
+        let seeders = record.seeders.max(0);
+        let peers = record.peers();
+        let size = record.size_bytes;
+        let file_count = record.file_count;
+        let categories = record
+            .release
+            .as_ref()
+            .map(release_categories)
+            .filter(|c| !c.is_empty())
+            .unwrap_or_else(|| vec![category]);
+        let category_tags: String = categories.iter().map(|c| format!("<category>{c}</category>\n")).collect();
+        let category_attrs: String = categories
+            .iter()
+            .map(|c| format!("<torznab:attr name=\"category\" value=\"{c}\"/>\n"))
+            .collect();
+
+        let _ = write!(
+            out,
+            r#"<item>
+<title>{title}</title>
+<guid isPermaLink="false">{hash}</guid>
+<link>{magnet}</link>
+<comments>{base}/t/{hash}</comments>
+<pubDate>{date}</pubDate>
+<size>{size}</size>
+{category_tags}<enclosure url="{magnet}" length="{size}" type="application/x-bittorrent"/>
+{category_attrs}<torznab:attr name="seeders" value="{seeders}"/>
+<torznab:attr name="peers" value="{peers}"/>
+<torznab:attr name="infohash" value="{hash}"/>
+<torznab:attr name="files" value="{file_count}"/>
+<torznab:attr name="magneturl" value="{magnet}"/>
+</item>
+"#,
+            title = xml_escape(title),
+            base = xml_escape(base),
+            date = rfc2822(record.first_seen_unix_ms),
+        );
+    }
+
+    out.push_str("</channel>\n</rss>\n");
+    out
+}
+
+/// Movies (2000) or TV (5000) for a parsed release, followed by its SD/HD/UHD subcategory
+/// when the resolution is known; empty if it reads as neither. Movies need a year and a
+/// resolution or codec, so a plain "Title 1999" stays unclassified.
+fn release_categories(release: &crate::release::Release) -> Vec<u32> {
+    let parent = if release.is_episode() {
+        5000
+    } else if release.year.is_some() && (release.resolution.is_some() || release.codec.is_some()) {
+        2000
+    } else {
+        return Vec::new();
+    };
+    let sub = match release.resolution.as_deref() {
+        Some("480p" | "576p") => Some(parent + 30),
+        Some("720p" | "1080p") => Some(parent + 40),
+        Some("2160p" | "4320p") => Some(parent + 45),
+        _ => None,
+    };
+    std::iter::once(parent).chain(sub).collect()
+}
+
+fn error_xml(code: u32, description: &str) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<error code=\"{code}\" description=\"{}\"/>\n",
+        xml_escape(description)
+    );
+    // Torznab clients read the error element; the status stays 200 like other indexers.
+    xml(StatusCode::OK, body)
+}
+
+fn xml(status: StatusCode, body: String) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/xml; charset=utf-8")], body).into_response()
+}
+
+/// Escapes text for XML, dropping control characters XML 1.0 can't represent.
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c < ' ' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Formats unix ms as an RFC 2822 date (`Thu, 01 Jan 1970 00:00:00 +0000`).
+fn rfc2822(unix_ms: i64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = unix_ms.max(0) / 1000;
+    let days = secs / 86_400;
+    let rem = secs % 86_400;
+    let (y, m, d) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} +0000",
+        DAYS[(days % 7) as usize],
+        d,
+        MONTHS[(m - 1) as usize],
+        y,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Days since 1970-01-01 to (year, month, day); inverse of `import::days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(state: &AppState, query: &str, headers: HeaderMap) -> String {
+        let params = Query::try_from_uri(&format!("/torznab/api?{query}").parse().unwrap()).unwrap();
+        let response = api(State(state.clone()), headers, params).await;
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, format!("Bearer {token}").parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn private_index_needs_a_key() {
+        const DENIED: &str = "<error code=\"100\"";
+
+        let open = crate::test_state(&[]);
+        assert!(get(&open, "t=search&q=x", HeaderMap::new()).await.contains("<rss"));
+
+        let private = crate::test_state(&[("SERMA_PUBLIC_SEARCH", "0"), ("SERMA_API_TOKEN", "secret")]);
+        assert!(get(&private, "t=search&q=x", HeaderMap::new()).await.contains(DENIED));
+        assert!(get(&private, "t=search&q=x&apikey=wrong", HeaderMap::new()).await.contains(DENIED));
+        assert!(get(&private, "t=search&q=x&apikey=secret", HeaderMap::new()).await.contains("<rss"));
+        assert!(get(&private, "t=search&q=x", bearer("secret")).await.contains("<rss"));
+        // Capabilities stay open, so clients can be set up before the key is entered.
+        assert!(get(&private, "t=caps", HeaderMap::new()).await.contains("<caps"));
+
+        // Without any token there is nothing to check, so a private index doesn't answer.
+        let no_token = crate::test_state(&[("SERMA_PUBLIC_SEARCH", "0")]);
+        assert!(get(&no_token, "t=search&q=x", HeaderMap::new()).await.contains(DENIED));
+
+        let keyed = crate::test_state(&[
+            ("SERMA_PUBLIC_SEARCH", "0"),
+            ("SERMA_API_TOKEN", "secret"),
+            ("SERMA_TORZNAB_APIKEY", "key"),
+        ]);
+        assert!(get(&keyed, "t=search&q=x&apikey=secret", HeaderMap::new()).await.contains(DENIED));
+        assert!(get(&keyed, "t=search&q=x&apikey=key", HeaderMap::new()).await.contains("<rss"));
+    }
+
+    #[test]
+    fn links_use_the_public_url_or_a_checked_host() {
+        let host = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::HOST, value.parse().unwrap());
+            headers
+        };
+
+        let plain = crate::test_state(&[]);
+        assert_eq!(base_url(&plain.config, &host("example.org:8080")), "http://example.org:8080");
+        assert_eq!(base_url(&plain.config, &host("[::1]:3000")), "http://[::1]:3000");
+        assert_eq!(base_url(&plain.config, &host("evil.org/phish?")), "");
+        assert_eq!(base_url(&plain.config, &host("example.org:99999")), "");
+        assert_eq!(base_url(&plain.config, &HeaderMap::new()), "");
+
+        let public = crate::test_state(&[("SERMA_PUBLIC_URL", "https://search.example.org/serma/")]);
+        assert_eq!(base_url(&public.config, &host("10.0.0.5:3000")), "https://search.example.org/serma");
+    }
+}
@@ -0,0 +1,285 @@
+use crate::socks5::{Socks5Config, Socks5UdpAssociate};
+use anyhow::Context;
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+use tokio::time::{Duration, Instant, timeout};
+
+// UDP tracker client (BEP-15): connect and scrape.
+//
+// rbit's UDP tracker only accepts literal IP addresses in `udp://` URLs, which rules out
+// nearly every tracker found in magnets, and it has no scrape. This client resolves host
+// names, speaks IPv4 and IPv6, goes through the SOCKS5 proxy when one is configured (like
+// the enrich DHT lookups), and keeps retries short so one dead tracker can't stall a task.
+
+const PROTOCOL_ID: u64 = 0x0417_2710_1980;
+const ACTION_CONNECT: u32 = 0;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+
+/// BEP-15 caps a scrape at about 74 info hashes so the packet fits in one datagram.
+pub const MAX_SCRAPE_HASHES: usize = 74;
+/// Connection ids are valid for a minute; renew a little early.
+const CONNECTION_TTL: Duration = Duration::from_secs(50);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_ATTEMPTS: u32 = 2;
+
+/// Swarm counts reported by a tracker.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SwarmStats {
+    pub seeders: u32,
+    pub leechers: u32,
+    pub completed: u32,
+}
+
+enum Transport {
+    Direct(UdpSocket),
+    Socks(Socks5UdpAssociate),
+}
+
+pub struct UdpTracker {
+    transport: Transport,
+    addr: SocketAddr,
+    connection: Option<(u64, Instant)>,
+}
+
+impl UdpTracker {
+    /// Resolves a `udp://host:port[/announce]` URL. The connect handshake happens lazily
+    /// on the first request.
+    pub async fn open(url: &str) -> anyhow::Result<Self> {
+        let host_port = parse_udp_url(url).with_context(|| format!("not a udp tracker url: {url}"))?;
+        let addr = tokio::net::lookup_host(host_port)
+            .await
+            .with_context(|| format!("resolve tracker {host_port}"))?
+            .next()
+            .with_context(|| format!("no addresses for tracker {host_port}"))?;
+
+        let transport = match Socks5Config::from_env() {
+            Some(Ok(cfg)) => Transport::Socks(
+                Socks5UdpAssociate::connect(&cfg)
+                    .await
+                    .with_context(|| format!("connect SOCKS5 proxy {}", cfg.proxy))?,
+            ),
+            Some(Err(err)) => anyhow::bail!("invalid SERMA_SOCKS5_PROXY: {err}"),
+            None => {
+                let bind = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                let socket = UdpSocket::bind(bind).await?;
+                socket.connect(addr).await?;
+                Transport::Direct(socket)
+            }
+        };
+
+        Ok(Self {
+            transport,
+            addr,
+            connection: None,
+        })
+    }
+
+    /// Scrapes up to [`MAX_SCRAPE_HASHES`] info hashes; results are in request order.
+    pub async fn scrape(&mut self, info_hashes: &[[u8; 20]]) -> anyhow::Result<Vec<SwarmStats>> {
+        anyhow::ensure!(
+            info_hashes.len() <= MAX_SCRAPE_HASHES,
+            "too many info hashes for one scrape ({})",
+            info_hashes.len()
+        );
+        if info_hashes.is_empty() {
+            return Ok(Vec::new());
+        }
+        let connection_id = self.connection_id().await?;
+        let tx = transaction_id();
+        let request = scrape_request(connection_id, tx, info_hashes);
+        let response = self.request(&request, ACTION_SCRAPE, tx, 8).await?;
+        let stats = parse_scrape(&response);
+        anyhow::ensure!(
+            stats.len() == info_hashes.len(),
+            "tracker {} scraped {} of {} hashes",
+            self.addr,
+            stats.len(),
+            info_hashes.len()
+        );
+        Ok(stats)
+    }
+
+    async fn connection_id(&mut self) -> anyhow::Result<u64> {
+        if let Some((id, at)) = self.connection
+            && at.elapsed() < CONNECTION_TTL
+        {
+            return Ok(id);
+        }
+        let tx = transaction_id();
+        let mut request = Vec::with_capacity(16);
+        request.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+        request.extend_from_slice(&ACTION_CONNECT.to_be_bytes());
+        request.extend_from_slice(&tx.to_be_bytes());
+
+        let response = self.request(&request, ACTION_CONNECT, tx, 16).await?;
+        let id = u64::from_be_bytes(response[8..16].try_into().expect("checked length"));
+        self.connection = Some((id, Instant::now()));
+        Ok(id)
+    }
+
+    /// Sends `request` and waits for the matching response, retrying on timeout.
+    /// Tracker errors (action 3) are returned as errors; the connection id is dropped so
+    /// the next request reconnects.
+    async fn request(&mut self, request: &[u8], action: u32, tx: u32, min_len: usize) -> anyhow::Result<Vec<u8>> {
+        let mut buf = vec![0u8; 2048];
+        for _ in 0..MAX_ATTEMPTS {
+            self.send(request).await?;
+            let deadline = Instant::now() + REQUEST_TIMEOUT;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let Ok(received) = timeout(remaining, self.recv(&mut buf)).await else {
+                    break;
+                };
+                let response = &buf[..received?];
+                match classify(response, action, tx, min_len) {
+                    // Stray or late datagram from an earlier attempt.
+                    Reply::Stray => continue,
+                    Reply::Matched => return Ok(response.to_vec()),
+                    Reply::TrackerError(message) => {
+                        self.connection = None;
+                        anyhow::bail!("tracker {} error: {message}", self.addr);
+                    }
+                    Reply::Unexpected(got) => anyhow::bail!(
+                        "tracker {} sent unexpected response (action {got}, {} bytes)",
+                        self.addr,
+                        response.len()
+                    ),
+                }
+            }
+        }
+        anyhow::bail!("tracker {} timed out", self.addr)
+    }
+
+    async fn send(&self, payload: &[u8]) -> std::io::Result<usize> {
+        match &self.transport {
+            Transport::Direct(socket) => socket.send(payload).await,
+            Transport::Socks(sock) => sock.send_to(payload, self.addr).await,
+        }
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &self.transport {
+            Transport::Direct(socket) => socket.recv(buf).await,
+            Transport::Socks(sock) => loop {
+                let (n, from) = sock.recv_from(buf).await?;
+                if from == self.addr {
+                    return Ok(n);
+                }
+            },
+        }
+    }
+}
+
+/// `udp://host:port/announce` -> `host:port` (IPv6 hosts stay bracketed).
+fn parse_udp_url(url: &str) -> Option<&str> {
+    let rest = url.strip_prefix("udp://")?;
+    let host_port = rest.split(['/', '?']).next()?;
+    (!host_port.is_empty()).then_some(host_port)
+}
+
+fn transaction_id() -> u32 {
+    rand::random()
+}
+
+fn scrape_request(connection_id: u64, tx: u32, info_hashes: &[[u8; 20]]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(16 + info_hashes.len() * 20);
+    request.extend_from_slice(&connection_id.to_be_bytes());
+    request.extend_from_slice(&ACTION_SCRAPE.to_be_bytes());
+    request.extend_from_slice(&tx.to_be_bytes());
+    for hash in info_hashes {
+        request.extend_from_slice(hash);
+    }
+    request
+}
+
+/// Per-hash counts from a scrape response; a truncated trailing entry is dropped.
+fn parse_scrape(response: &[u8]) -> Vec<SwarmStats> {
+    response
+        .get(8..)
+        .unwrap_or_default()
+        .chunks_exact(12)
+        .map(|c| SwarmStats {
+            seeders: be_u32(c),
+            completed: be_u32(&c[4..]),
+            leechers: be_u32(&c[8..]),
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    /// Too short to carry a header, or for another transaction.
+    Stray,
+    Matched,
+    TrackerError(String),
+    Unexpected(u32),
+}
+
+fn classify(response: &[u8], action: u32, tx: u32, min_len: usize) -> Reply {
+    if response.len() < 8 || be_u32(&response[4..]) != tx {
+        return Reply::Stray;
+    }
+    match be_u32(response) {
+        ACTION_ERROR => Reply::TrackerError(String::from_utf8_lossy(&response[8..]).trim().to_string()),
+        got if got == action && response.len() >= min_len => Reply::Matched,
+        got => Reply::Unexpected(got),
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(action: u32, tx: u32) -> Vec<u8> {
+        let mut out = action.to_be_bytes().to_vec();
+        out.extend_from_slice(&tx.to_be_bytes());
+        out
+    }
+
+    #[test]
+    fn builds_scrape_request_and_parses_response() {
+        let request = scrape_request(0x1122_3344_5566_7788, 7, &[[1; 20], [2; 20]]);
+        assert_eq!(request.len(), 16 + 40);
+        assert_eq!(&request[..8], &0x1122_3344_5566_7788u64.to_be_bytes());
+        assert_eq!(be_u32(&request[8..]), ACTION_SCRAPE);
+        assert_eq!(be_u32(&request[12..]), 7);
+        assert_eq!(&request[16..36], &[1; 20]);
+        assert_eq!(&request[36..], &[2; 20]);
+
+        let mut response = header(ACTION_SCRAPE, 7);
+        for n in [10u32, 20, 5, 1, 0, 3] {
+            response.extend_from_slice(&n.to_be_bytes());
+        }
+        assert_eq!(classify(&response, ACTION_SCRAPE, 7, 8), Reply::Matched);
+        let stats = parse_scrape(&response);
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].seeders, stats[0].completed, stats[0].leechers), (10, 20, 5));
+        assert_eq!((stats[1].seeders, stats[1].completed, stats[1].leechers), (1, 0, 3));
+
+        // A truncated trailing entry is not reported as a zeroed swarm.
+        response.truncate(response.len() - 4);
+        assert_eq!(parse_scrape(&response).len(), 1);
+    }
+
+    #[test]
+    fn classifies_errors_and_short_packets() {
+        let mut error = header(ACTION_ERROR, 9);
+        error.extend_from_slice(b"unregistered torrent\n");
+        assert_eq!(
+            classify(&error, ACTION_SCRAPE, 9, 8),
+            Reply::TrackerError("unregistered torrent".to_string())
+        );
+
+        // Too short for a header, or another transaction: ignored and waited past.
+        assert_eq!(classify(&[0, 0, 0, 2, 0], ACTION_SCRAPE, 9, 8), Reply::Stray);
+        assert_eq!(classify(&header(ACTION_SCRAPE, 8), ACTION_SCRAPE, 9, 8), Reply::Stray);
+        // A connect reply missing its connection id.
+        assert_eq!(classify(&header(ACTION_CONNECT, 9), ACTION_CONNECT, 9, 16), Reply::Unexpected(ACTION_CONNECT));
+        assert!(parse_scrape(&[0, 0, 0]).is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use crate::config::Config;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+// Per-tracker health and request pacing for scrapes.
+//
+// The magnets of thousands of torrents name the same few trackers, and many of those have
+// been dead for years: without memory, every enrichment and re-check waits out the same
+// timeout again. After `FAILURES_BEFORE_BACKOFF` failures in a row a tracker is skipped for
+// SERMA_TRACKER_BACKOFF_SECS, doubling with each further failure up to `MAX_BACKOFF`; then
+// a single request probes it, and one success clears its record. Requests to one tracker
+// are also spaced out to SERMA_TRACKER_MAX_PER_SEC, so the popular trackers found in most
+// magnets aren't flooded when a batch of them is enriched at once.
+
+/// Failures in a row before a tracker is skipped.
+const FAILURES_BEFORE_BACKOFF: u32 = 3;
+const MAX_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+/// How long other requests wait on a probe of a backed-off tracker.
+const PROBE_WINDOW: Duration = Duration::from_secs(30);
+/// Trackers remembered; the least recently used go first.
+const MAX_TRACKED: usize = 10_000;
+
+/// Health of every tracker scraped so far; cheap to clone.
+#[derive(Clone)]
+pub struct Trackers {
+    inner: Arc<Mutex<HashMap<String, Health>>>,
+    /// Minimum spacing of requests to one tracker; zero is unlimited.
+    interval: Duration,
+    backoff: Duration,
+}
+
+struct Health {
+    requests: u64,
+    failures: u64,
+    failures_in_row: u32,
+    /// Moving average of successful response times.
+    avg_ms: Option<f64>,
+    next_slot: Instant,
+    backoff_until: Option<Instant>,
+    last_used: Instant,
+}
+
+/// One tracker in `/api/admin/trackers`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackerHealth {
+    pub url: String,
+    pub requests: u64,
+    pub failures: u64,
+    pub failures_in_row: u32,
+    pub avg_response_ms: u64,
+    /// Seconds until the tracker is tried again; 0 if it isn't backed off.
+    pub backoff_secs: u64,
+}
+
+impl Trackers {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(HashMap::new())),
+            interval: match config.tracker_max_per_sec {
+                0 => Duration::ZERO,
+                n => Duration::from_secs(1) / n,
+            },
+            backoff: Duration::from_secs(config.tracker_backoff_secs),
+        }
+    }
+
+    /// Books the next request to `url`, if it can go out within `max_wait`: returns how long
+    /// to wait before sending it. None while the tracker is backed off, or too busy.
+    pub fn reserve(&self, url: &str, max_wait: Duration, now: Instant) -> Option<Duration> {
+        let mut trackers = self.lock();
+        if !trackers.contains_key(url) && trackers.len() >= MAX_TRACKED {
+            let oldest = trackers.iter().min_by_key(|(_, h)| h.last_used).map(|(url, _)| url.clone());
+            if let Some(oldest) = oldest {
+                trackers.remove(&oldest);
+            }
+        }
+        let health = trackers.entry(url.to_string()).or_insert(Health {
+            requests: 0,
+            failures: 0,
+            failures_in_row: 0,
+            avg_ms: None,
+            next_slot: now,
+            backoff_until: None,
+            last_used: now,
+        });
+        health.last_used = now;
+        if let Some(until) = health.backoff_until {
+            if now < until {
+                return None;
+            }
+            // This request is the probe; the rest keep skipping until it's answered.
+            health.backoff_until = Some(now + PROBE_WINDOW);
+        }
+        let slot = health.next_slot.max(now);
+        let wait = slot - now;
+        if wait > max_wait {
+            return None;
+        }
+        health.next_slot = slot + self.interval;
+        health.requests += 1;
+        Some(wait)
+    }
+
+    pub fn succeeded(&self, url: &str, took: Duration) {
+        let mut trackers = self.lock();
+        let Some(health) = trackers.get_mut(url) else {
+            return;
+        };
+        let ms = took.as_secs_f64() * 1000.0;
+        health.avg_ms = Some(health.avg_ms.map_or(ms, |avg| avg * 0.8 + ms * 0.2));
+        health.failures_in_row = 0;
+        health.backoff_until = None;
+    }
+
+    /// Counts a failed or timed out request, backing the tracker off once it keeps failing.
+    pub fn failed(&self, url: &str, now: Instant) {
+        let mut trackers = self.lock();
+        let Some(health) = trackers.get_mut(url) else {
+            return;
+        };
+        health.failures += 1;
+        health.failures_in_row += 1;
+        if !self.backoff.is_zero()
+            && let Some(over) = health.failures_in_row.checked_sub(FAILURES_BEFORE_BACKOFF)
+        {
+            let backoff = self.backoff.saturating_mul(1 << over.min(16)).min(MAX_BACKOFF);
+            health.backoff_until = Some(now + backoff);
+            if over == 0 {
+                tracing::debug!(%url, backoff_secs = backoff.as_secs(), "trackers: backing off");
+            }
+        }
+    }
+
+    /// Every tracker remembered, most requested first.
+    pub fn health(&self) -> Vec<TrackerHealth> {
+        let now = Instant::now();
+        let mut list: Vec<TrackerHealth> = self
+            .lock()
+            .iter()
+            .map(|(url, h)| TrackerHealth {
+                url: url.clone(),
+                requests: h.requests,
+                failures: h.failures,
+                failures_in_row: h.failures_in_row,
+                avg_response_ms: h.avg_ms.unwrap_or_default() as u64,
+                backoff_secs: h.backoff_until.map_or(0, |until| until.saturating_duration_since(now).as_secs()),
+            })
+            .collect();
+        list.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.url.cmp(&b.url)));
+        list
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Health>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trackers(per_sec: u32) -> Trackers {
+        Trackers {
+            inner: Default::default(),
+            interval: Duration::from_secs(1) / per_sec,
+            backoff: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn paces_requests_and_backs_off_dead_trackers() {
+        let trackers = trackers(2);
+        let start = Instant::now();
+        let url = "udp://tracker.example:1337/announce";
+        let second = Duration::from_secs(1);
+        assert_eq!(trackers.reserve(url, second, start), Some(Duration::ZERO));
+        assert_eq!(trackers.reserve(url, second, start), Some(Duration::from_millis(500)));
+        assert_eq!(trackers.reserve(url, second, start), Some(second));
+        assert_eq!(trackers.reserve(url, second, start), None);
+        assert_eq!(trackers.reserve("udp://other.example:80", second, start), Some(Duration::ZERO));
+
+        let later = start + Duration::from_secs(10);
+        for _ in 0..3 {
+            trackers.failed(url, later);
+        }
+        assert_eq!(trackers.reserve(url, second, later + Duration::from_secs(59)), None);
+        // One probe after the backoff; it fails, and the next backoff is twice as long.
+        let probe = later + Duration::from_secs(60);
+        assert!(trackers.reserve(url, second, probe).is_some());
+        assert_eq!(trackers.reserve(url, second, probe + Duration::from_secs(1)), None);
+        trackers.failed(url, probe);
+        assert_eq!(trackers.reserve(url, second, probe + Duration::from_secs(119)), None);
+        let probe = probe + Duration::from_secs(120);
+        assert!(trackers.reserve(url, second, probe).is_some());
+        trackers.succeeded(url, Duration::from_millis(80));
+        assert!(trackers.reserve(url, second, probe + second).is_some());
+
+        let health = trackers.health();
+        assert_eq!(health[0].url, url);
+        assert_eq!((health[0].requests, health[0].failures, health[0].failures_in_row), (6, 4, 0));
+        assert_eq!((health[0].avg_response_ms, health[0].backoff_secs), (80, 0));
+    }
+}
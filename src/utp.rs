@@ -0,0 +1,392 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tokio::net::UdpSocket;
+use tokio::time::{Duration, Instant, interval, timeout};
+
+// Minimal uTP (BEP-29) client, for peers that only accept uTP.
+//
+// Enough for a short metadata exchange, not a bulk transfer: one UDP socket per
+// connection, a fixed send window instead of LEDBAT congestion control, and no selective
+// acks. `connect` does the SYN handshake, then a driver task runs the protocol and the
+// caller gets the other end of an in-memory pipe, usable like a TcpStream.
+
+const ST_DATA: u8 = 0;
+const ST_FIN: u8 = 1;
+const ST_STATE: u8 = 2;
+const ST_RESET: u8 = 3;
+const ST_SYN: u8 = 4;
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 20;
+
+/// Payload per packet; keeps datagrams under common path MTUs.
+const MAX_PAYLOAD: usize = 1200;
+/// Bytes in flight, whatever window the peer advertises.
+const MAX_WINDOW: usize = 64 * 1024;
+/// Receive window we advertise.
+const RECV_WINDOW: u32 = 1024 * 1024;
+/// Out-of-order packets held while waiting for a gap to fill.
+const MAX_REORDER: usize = 256;
+const SYN_TRIES: u32 = 3;
+const INITIAL_RTO: Duration = Duration::from_millis(1000);
+const MAX_RESENDS: u32 = 5;
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const PIPE_BUFFER: usize = 256 * 1024;
+
+struct Header {
+    kind: u8,
+    conn_id: u16,
+    ts: u32,
+    ts_diff: u32,
+    wnd: u32,
+    seq: u16,
+    ack: u16,
+}
+
+impl Header {
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+        out.push(self.kind << 4 | VERSION);
+        out.push(0); // no extensions
+        out.extend_from_slice(&self.conn_id.to_be_bytes());
+        out.extend_from_slice(&self.ts.to_be_bytes());
+        out.extend_from_slice(&self.ts_diff.to_be_bytes());
+        out.extend_from_slice(&self.wnd.to_be_bytes());
+        out.extend_from_slice(&self.seq.to_be_bytes());
+        out.extend_from_slice(&self.ack.to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// Header and payload; extensions (e.g. selective acks) are skipped.
+    fn decode(buf: &[u8]) -> Option<(Self, &[u8])> {
+        if buf.len() < HEADER_LEN || buf[0] & 0x0F != VERSION {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+        let u32_at = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let header = Self {
+            kind: buf[0] >> 4,
+            conn_id: u16_at(2),
+            ts: u32_at(4),
+            ts_diff: u32_at(8),
+            wnd: u32_at(12),
+            seq: u16_at(16),
+            ack: u16_at(18),
+        };
+        let mut ext = buf[1];
+        let mut at = HEADER_LEN;
+        while ext != 0 {
+            let len = *buf.get(at + 1)? as usize;
+            ext = buf[at];
+            at += 2 + len;
+        }
+        Some((header, buf.get(at..)?))
+    }
+}
+
+/// `a <= b` in wrapping sequence-number space.
+fn seq_le(a: u16, b: u16) -> bool {
+    b.wrapping_sub(a) < 0x8000
+}
+
+/// Opens a uTP connection to `addr`. Errors if the peer doesn't answer the SYN.
+pub async fn connect(addr: SocketAddr) -> anyhow::Result<DuplexStream> {
+    let bind: SocketAddr = if addr.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(addr).await?;
+
+    let recv_id: u16 = rand::random();
+    let mut conn = Conn {
+        socket,
+        epoch: Instant::now(),
+        recv_id,
+        send_id: recv_id.wrapping_add(1),
+        seq_nr: 1,
+        ack_nr: 0,
+        peer_wnd: MAX_WINDOW as u32,
+        their_ts: 0,
+        unacked: VecDeque::new(),
+        reorder: HashMap::new(),
+        fin_seq: None,
+        last_heard: Instant::now(),
+    };
+
+    // SYN carries the receive id; everything after uses the send id.
+    let syn = conn.header(ST_SYN, recv_id, conn.seq_nr).encode(&[]);
+    conn.seq_nr = conn.seq_nr.wrapping_add(1);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut wait = INITIAL_RTO;
+    let mut answered = false;
+    for _ in 0..SYN_TRIES {
+        conn.socket.send(&syn).await?;
+        let deadline = Instant::now() + wait;
+        while let Ok(recv) = timeout(deadline.saturating_duration_since(Instant::now()), conn.socket.recv(&mut buf)).await {
+            let n = recv?;
+            let Some((header, _)) = Header::decode(&buf[..n]) else {
+                continue;
+            };
+            if header.conn_id != recv_id {
+                continue;
+            }
+            match header.kind {
+                ST_STATE => {
+                    // Their first data packet carries this sequence number.
+                    conn.ack_nr = header.seq.wrapping_sub(1);
+                    conn.peer_wnd = header.wnd;
+                    conn.their_ts = header.ts;
+                    answered = true;
+                    break;
+                }
+                ST_RESET => anyhow::bail!("utp: connection refused"),
+                _ => {}
+            }
+        }
+        if answered {
+            break;
+        }
+        wait *= 2;
+    }
+    if !answered {
+        anyhow::bail!("utp: no answer to SYN");
+    }
+
+    let (user, driver) = tokio::io::duplex(PIPE_BUFFER);
+    tokio::spawn(async move {
+        let (from_user, to_user) = tokio::io::split(driver);
+        if let Err(err) = conn.run(from_user, to_user).await {
+            tracing::trace!(%err, %addr, "utp: connection ended");
+        }
+    });
+    Ok(user)
+}
+
+struct Sent {
+    seq: u16,
+    payload: Vec<u8>,
+    sent_at: Instant,
+    resends: u32,
+}
+
+struct Conn {
+    socket: UdpSocket,
+    epoch: Instant,
+    recv_id: u16,
+    send_id: u16,
+    /// Next sequence number we send.
+    seq_nr: u16,
+    /// Last in-order sequence number received.
+    ack_nr: u16,
+    peer_wnd: u32,
+    /// Timestamp of the peer's last packet, echoed back as a delay sample.
+    their_ts: u32,
+    unacked: VecDeque<Sent>,
+    reorder: HashMap<u16, Vec<u8>>,
+    fin_seq: Option<u16>,
+    last_heard: Instant,
+}
+
+impl Conn {
+    fn header(&self, kind: u8, conn_id: u16, seq: u16) -> Header {
+        let ts = self.epoch.elapsed().as_micros() as u32;
+        Header {
+            kind,
+            conn_id,
+            ts,
+            ts_diff: ts.wrapping_sub(self.their_ts),
+            wnd: RECV_WINDOW,
+            seq,
+            ack: self.ack_nr,
+        }
+    }
+
+    async fn send_packet(&self, kind: u8, seq: u16, payload: &[u8]) -> std::io::Result<()> {
+        let packet = self.header(kind, self.send_id, seq).encode(payload);
+        self.socket.send(&packet).await.map(|_| ())
+    }
+
+    fn in_flight(&self) -> usize {
+        self.unacked.iter().map(|s| s.payload.len()).sum()
+    }
+
+    /// Room for another packet: the smaller of both windows, but always one packet.
+    fn can_send(&self) -> bool {
+        let window = (self.peer_wnd as usize).clamp(MAX_PAYLOAD, MAX_WINDOW);
+        self.in_flight() + MAX_PAYLOAD <= window
+    }
+
+    async fn run(
+        &mut self,
+        mut from_user: ReadHalf<DuplexStream>,
+        mut to_user: WriteHalf<DuplexStream>,
+    ) -> anyhow::Result<()> {
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut out = vec![0u8; MAX_PAYLOAD];
+        let mut tick = interval(Duration::from_millis(100));
+        loop {
+            tokio::select! {
+                recv = self.socket.recv(&mut buf) => {
+                    let n = recv?;
+                    let Some((header, payload)) = Header::decode(&buf[..n]) else {
+                        continue;
+                    };
+                    if header.conn_id != self.recv_id {
+                        continue;
+                    }
+                    if self.handle(&header, payload, &mut to_user).await? {
+                        return Ok(());
+                    }
+                }
+                read = from_user.read(&mut out), if self.can_send() => {
+                    let n = read?;
+                    if n == 0 {
+                        // Caller dropped its end: say goodbye and stop.
+                        let _ = self.send_packet(ST_FIN, self.seq_nr, &[]).await;
+                        return Ok(());
+                    }
+                    let seq = self.seq_nr;
+                    self.seq_nr = seq.wrapping_add(1);
+                    self.send_packet(ST_DATA, seq, &out[..n]).await?;
+                    self.unacked.push_back(Sent { seq, payload: out[..n].to_vec(), sent_at: Instant::now(), resends: 0 });
+                }
+                _ = tick.tick() => {
+                    if self.last_heard.elapsed() > IDLE_TIMEOUT {
+                        anyhow::bail!("utp: peer went silent");
+                    }
+                    if let Some(oldest) = self.unacked.front()
+                        && oldest.sent_at.elapsed() >= INITIAL_RTO * 2u32.pow(oldest.resends)
+                    {
+                        if oldest.resends >= MAX_RESENDS {
+                            anyhow::bail!("utp: too many retransmissions");
+                        }
+                        let (seq, payload) = (oldest.seq, oldest.payload.clone());
+                        self.send_packet(ST_DATA, seq, &payload).await?;
+                        let oldest = self.unacked.front_mut().expect("checked above");
+                        oldest.sent_at = Instant::now();
+                        oldest.resends += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Processes one packet from the peer. Returns true once the connection is done.
+    async fn handle(&mut self, header: &Header, payload: &[u8], to_user: &mut WriteHalf<DuplexStream>) -> anyhow::Result<bool> {
+        self.last_heard = Instant::now();
+        self.their_ts = header.ts;
+        self.peer_wnd = header.wnd;
+        while self.unacked.front().is_some_and(|s| seq_le(s.seq, header.ack)) {
+            self.unacked.pop_front();
+        }
+
+        match header.kind {
+            ST_RESET => anyhow::bail!("utp: reset by peer"),
+            ST_DATA | ST_FIN => {
+                if header.kind == ST_FIN {
+                    self.fin_seq = Some(header.seq);
+                }
+                let next = self.ack_nr.wrapping_add(1);
+                if header.seq == next {
+                    if header.kind == ST_DATA {
+                        to_user.write_all(payload).await?;
+                        self.ack_nr = next;
+                    }
+                } else if seq_le(next, header.seq) && self.reorder.len() < MAX_REORDER && header.kind == ST_DATA {
+                    self.reorder.insert(header.seq, payload.to_vec());
+                }
+                // Deliver whatever the gap was holding back.
+                while let Some(data) = self.reorder.remove(&self.ack_nr.wrapping_add(1)) {
+                    to_user.write_all(&data).await?;
+                    self.ack_nr = self.ack_nr.wrapping_add(1);
+                }
+                let finished = self.fin_seq == Some(self.ack_nr.wrapping_add(1));
+                if finished {
+                    self.ack_nr = self.ack_nr.wrapping_add(1);
+                }
+                self.send_packet(ST_STATE, self.seq_nr, &[]).await?;
+                if finished {
+                    to_user.shutdown().await?;
+                    return Ok(true);
+                }
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(kind: u8, conn_id: u16, seq: u16) -> Header {
+        Header { kind, conn_id, ts: 0x0102_0304, ts_diff: 5, wnd: RECV_WINDOW, seq, ack: 0xFFFE }
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let packet = header(ST_DATA, 0xBEEF, 42).encode(b"payload");
+        assert_eq!(packet.len(), HEADER_LEN + 7);
+        let (decoded, payload) = Header::decode(&packet).unwrap();
+        assert_eq!(payload, b"payload");
+        assert_eq!((decoded.kind, decoded.conn_id, decoded.seq, decoded.ack), (ST_DATA, 0xBEEF, 42, 0xFFFE));
+        assert_eq!((decoded.ts, decoded.ts_diff, decoded.wnd), (0x0102_0304, 5, RECV_WINDOW));
+
+        // A selective-ack extension (type 1, 4 bytes) is skipped.
+        let mut with_ext = header(ST_STATE, 1, 2).encode(&[]);
+        with_ext[1] = 1;
+        with_ext.extend_from_slice(&[0, 4, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(Header::decode(&with_ext).unwrap().1, b"");
+
+        assert!(Header::decode(&packet[..HEADER_LEN - 1]).is_none());
+        let mut wrong_version = packet.clone();
+        wrong_version[0] = ST_DATA << 4 | 2;
+        assert!(Header::decode(&wrong_version).is_none());
+        // An extension running past the end of the packet.
+        let mut truncated = header(ST_STATE, 1, 2).encode(&[]);
+        truncated[1] = 1;
+        truncated.push(0);
+        assert!(Header::decode(&truncated).is_none());
+    }
+
+    #[test]
+    fn seq_le_wraps_at_u16() {
+        assert!(seq_le(5, 5));
+        assert!(seq_le(5, 6));
+        assert!(!seq_le(6, 5));
+        assert!(seq_le(u16::MAX, 0));
+        assert!(seq_le(u16::MAX - 10, 20));
+        assert!(!seq_le(0, u16::MAX));
+    }
+
+    #[tokio::test]
+    async fn delivers_out_of_order_packets_in_order() {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = peer.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut stream = connect(addr).await.unwrap();
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).await.unwrap();
+            received
+        });
+
+        let mut buf = [0u8; 2048];
+        let (n, from) = peer.recv_from(&mut buf).await.unwrap();
+        let (syn, _) = Header::decode(&buf[..n]).unwrap();
+        assert_eq!(syn.kind, ST_SYN);
+        // Our first data packet is the last sequence number before the wrap.
+        let first = u16::MAX;
+        peer.send_to(&header(ST_STATE, syn.conn_id, first).encode(&[]), from).await.unwrap();
+        for (kind, seq, payload) in [
+            (ST_DATA, first.wrapping_add(2), &b"c"[..]),
+            (ST_DATA, first, b"a"),
+            (ST_FIN, first.wrapping_add(3), b""),
+            (ST_DATA, first.wrapping_add(1), b"b"),
+        ] {
+            peer.send_to(&header(kind, syn.conn_id, seq).encode(payload), from).await.unwrap();
+        }
+
+        let received = timeout(Duration::from_secs(5), client).await.unwrap().unwrap();
+        assert_eq!(received, b"abc");
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{AppState, ingest};
+use notify::Watcher as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+// Watch folder ("blackhole" directory): any .torrent file or magnet/hash text file
+// dropped into it is ingested and then moved to `done/`.
+//
+// Filesystem events only trigger a rescan of the directory; a periodic rescan covers
+// filesystems where notify events are unreliable (network mounts, some containers).
+
+const RESCAN_EVERY: Duration = Duration::from_secs(30);
+/// Files modified more recently than this are assumed to still be written.
+const SETTLE: Duration = Duration::from_secs(2);
+
+pub async fn run(state: AppState) {
+    if !state.config.watch_enabled {
+        tracing::info!("watch: disabled via SERMA_WATCH");
+        return;
+    }
+
+    let dir = state.config.watch_dir.clone();
+    let done_dir = dir.join("done");
+    if let Err(err) = tokio::fs::create_dir_all(&done_dir).await {
+        tracing::warn!(%err, dir = %dir.display(), "watch: failed creating watch dir; disabled");
+        return;
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+    let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if res.is_ok() {
+            let _ = tx.send(());
+        }
+    })
+    .and_then(|mut w| w.watch(&dir, notify::RecursiveMode::NonRecursive).map(|_| w));
+    // Keep the watcher alive for the lifetime of the task.
+    let _watcher = match watcher {
+        Ok(w) => Some(w),
+        Err(err) => {
+            tracing::warn!(%err, dir = %dir.display(), "watch: notify unavailable; falling back to polling");
+            None
+        }
+    };
+    tracing::info!(dir = %dir.display(), "watch: watching for .torrent and magnet/hash files");
+
+    while !state.shutdown.is_triggered() {
+        let pending = scan_once(&state, &dir, &done_dir).await;
+
+        // Wait for the next event (or rescan), then let writers finish before scanning.
+        let wait = if pending { SETTLE } else { RESCAN_EVERY };
+        tokio::select! {
+            _ = tokio::time::timeout(wait, rx.recv()) => {}
+            _ = state.shutdown.wait() => break,
+        }
+        state.shutdown.sleep(SETTLE).await;
+        while rx.try_recv().is_ok() {}
+    }
+    tracing::info!("watch: stopped");
+}
+
+/// Ingests every settled file in `dir`. Returns true if some files were skipped
+/// because they are still being written.
+async fn scan_once(state: &AppState, dir: &Path, done_dir: &Path) -> bool {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(e) => e,
+        Err(err) => {
+            tracing::warn!(%err, dir = %dir.display(), "watch: failed reading watch dir");
+            return false;
+        }
+    };
+
+    let mut pending = false;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        // Files not started yet are left for the next run.
+        if state.shutdown.is_triggered() {
+            break;
+        }
+        let path = entry.path();
+        let Ok(meta) = entry.metadata().await else {
+            continue;
+        };
+        if !meta.is_file() || is_ignored(&path) {
+            continue;
+        }
+
+        let age = meta
+            .modified()
+            .ok()
+            .and_then(|m| m.elapsed().ok())
+            .unwrap_or_default();
+        if age < SETTLE {
+            pending = true;
+            continue;
+        }
+
+        // The summary (including rejected lines) is logged by the ingest itself.
+        if let Err(err) = ingest::run_file_or_stdin_ingest(state, Some(&path)).await {
+            tracing::warn!(%err, file = %path.display(), "watch: ingest failed");
+        }
+
+        // Move it out of the way either way so a bad file isn't retried forever.
+        let target = done_path(done_dir, &path);
+        if let Err(err) = tokio::fs::rename(&path, &target).await {
+            tracing::warn!(%err, file = %path.display(), "watch: failed moving file to done/");
+        }
+    }
+    pending
+}
+
+/// Hidden files and partial downloads from browsers / download clients.
+fn is_ignored(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return true;
+    };
+    let lower = name.to_ascii_lowercase();
+    name.starts_with('.')
+        || [".part", ".tmp", ".crdownload", ".partial", ".download"]
+            .iter()
+            .any(|ext| lower.ends_with(ext))
+}
+
+fn done_path(done_dir: &Path, path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    let target = done_dir.join(name);
+    if !target.exists() {
+        return target;
+    }
+    // Same name dropped twice: keep both.
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    done_dir.join(format!("{}-{}", ts, name.to_string_lossy()))
+}
@@ -1,11 +1,20 @@
 use crate::AppState;
+use crate::control::Subsystem;
+use crate::ratelimit::Class;
+use crate::storage::Discovery;
+use crate::index::{MAX_FUZZY_DISTANCE, MAX_OFFSET, QueryOptions, SearchFilters, SearchPage, SearchSort, max_offset, parse_bytes};
 use axum::{
-    extract::{Path, Query, State},
-    response::{Html, IntoResponse},
-    routing::get,
-    Json, Router,
+    extract::{ConnectInfo, DefaultBodyLimit, Multipart, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Redirect},
+    routing::{delete, get, post, put},
+    Form, Json, Router,
 };
-use serde::Deserialize;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::Instrument;
 
 const APP_TITLE: &str = "Serma";
 
@@ -370,7 +379,9 @@ fn page(title: &str, body: String) -> Html<String> {
             </a>
             <nav class="flex gap-4">
                 <a href="/" class="nav-link">Home</a>
-                <a href="/search" class="nav-link">Browse</a>
+                <a href="/browse/recent" class="nav-link">Recent</a>
+                <a href="/browse/top" class="nav-link">Top</a>
+                <a href="/admin" class="nav-link">Admin</a>
             </nav>
         </div>
     </header>
@@ -386,6 +397,7 @@ fn page(title: &str, body: String) -> Html<String> {
     </footer>
 
     <div id="toast" class="toast">Notification</div>
+    <datalist id="suggestions"></datalist>
 
     <script>
         const toastEl = document.getElementById('toast');
@@ -419,6 +431,33 @@ fn page(title: &str, body: String) -> Html<String> {
                 showToast('Copied');
             }}
         }});
+
+        // Title completions for the search boxes, fetched once typing pauses.
+        const suggestionsEl = document.getElementById('suggestions');
+        let suggestTimeout;
+        let suggestFor = '';
+        document.addEventListener('input', (e) => {{
+            if (e.target.getAttribute('list') !== 'suggestions') return;
+            const q = e.target.value.trim();
+            clearTimeout(suggestTimeout);
+            if (q.length < 2) {{
+                suggestionsEl.replaceChildren();
+                return;
+            }}
+            suggestTimeout = setTimeout(async () => {{
+                suggestFor = q;
+                try {{
+                    const res = await fetch('/api/suggest?q=' + encodeURIComponent(q));
+                    if (!res.ok || suggestFor !== q) return;
+                    const {{ suggestions }} = await res.json();
+                    suggestionsEl.replaceChildren(...suggestions.map((title) => {{
+                        const opt = document.createElement('option');
+                        opt.value = title;
+                        return opt;
+                    }}));
+                }} catch (err) {{}}
+            }}, 200);
+        }});
     </script>
 </body>
 </html>"##,
@@ -429,62 +468,403 @@ fn page(title: &str, body: String) -> Html<String> {
     ))
 }
 
-pub async fn serve(state: AppState, addr: std::net::SocketAddr) -> anyhow::Result<()> {
-    let app = Router::new()
+fn router(state: AppState) -> Router {
+    let limit = |class| middleware::from_fn_with_state((state.clone(), class), rate_limit);
+    let search = Router::new()
         .route("/", get(home))
-        .route("/search", get(search_html))
-        .route("/search/", get(search_html))
-        .route("/api/search", get(search_api))
-        .route("/api/search/", get(search_api))
+        .route("/search", get(search_html).route_layer(limit(Class::Search)))
+        .route("/search/", get(search_html).route_layer(limit(Class::Search)))
+        .route("/api/search", get(search_api).route_layer(limit(Class::Search)))
+        .route("/api/search/", get(search_api).route_layer(limit(Class::Search)))
+        .route("/api/search/export", get(search_export_api).route_layer(limit(Class::Search)))
+        .route("/api/browse/:order", get(browse_api).route_layer(limit(Class::Search)))
+        .route("/api/suggest", get(suggest_api))
+        .route("/api/stream", get(stream_api))
+        .route("/api/stats", get(stats_api))
         .route("/t/:info_hash", get(torrent_page))
-        .with_state(state);
-    tracing::info!(%addr, "listening");
+        .route("/api/torrents/:info_hash/history", get(seeder_history_api));
+    // Left out of a read-only instance's public pages, like magnet links.
+    let browse = Router::new()
+        .route("/browse/:order", get(browse_html).route_layer(limit(Class::Search)))
+        .route("/t/:info_hash/download.torrent", get(torrent_download));
+    let gate = |router: Router<AppState>, public: bool| {
+        if public {
+            router
+        } else {
+            router.route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        }
+    };
+    let search = gate(search, state.config.public_search);
+    let browse = gate(browse, state.config.public_search && !state.config.read_only);
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
-    Ok(())
+    // Everything that changes state or exposes internals needs the API token (if set).
+    let protected = Router::new()
+        .route("/admin", get(admin_page))
+        .route("/admin/control/:subsystem/:action", post(admin_control_form))
+        .route("/admin/reindex", post(admin_reindex_form))
+        .route("/api/admin/stats", get(admin_stats_api))
+        .route("/api/admin/reindex", post(reindex_api))
+        .route("/api/admin/control", get(control_api))
+        .route("/api/admin/control/:subsystem/:action", post(control_set_api))
+        .route("/api/admin/config", get(settings_api).patch(settings_patch_api))
+        .route("/api/admin/cleanup/history", get(cleanup_history_api))
+        .route("/api/admin/trackers", get(trackers_api))
+        .route("/api/admin/backup", post(backup_api))
+        // Archives can be large; the upload is streamed to a file, and each entry is
+        // size-checked as it is unpacked (see `backup::stage_restore`).
+        .route(
+            "/api/admin/restore",
+            post(restore_api).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/api/upload",
+            post(upload_api)
+                .layer(DefaultBodyLimit::max(UPLOAD_MAX_BYTES))
+                .route_layer(limit(Class::Ingest)),
+        )
+        .route(
+            "/api/ingest",
+            post(ingest_api)
+                .layer(DefaultBodyLimit::max(INGEST_MAX_BYTES))
+                .route_layer(limit(Class::Ingest)),
+        )
+        .route(
+            "/api/torrents",
+            post(torrent_raw_api)
+                .layer(DefaultBodyLimit::max(UPLOAD_MAX_BYTES))
+                .route_layer(limit(Class::Ingest)),
+        )
+        .route("/api/torrents/:info_hash", delete(torrent_delete_api).patch(torrent_patch_api))
+        .route("/api/torrents/bulk", post(bulk_api))
+        .route("/api/jobs", get(jobs_api))
+        .route("/api/jobs/:id", get(job_api))
+        .route("/t/:info_hash/delete", post(torrent_delete_form))
+        .route("/api/torrents/:info_hash/pin", put(pin_api).delete(unpin_api))
+        .route("/t/:info_hash/pin", post(pin_form))
+        .route("/api/blacklist", get(blacklist_api))
+        .route("/api/archive", get(archive_api))
+        .route("/api/archive/:info_hash/restore", post(archive_restore_api))
+        .route("/api/blacklist/:info_hash", delete(unblacklist_api))
+        .route("/api/blocklist", get(block_rules_api).post(block_rule_add_api))
+        .route("/api/blocklist/:id", delete(block_rule_delete_api))
+        .route("/api/saved-searches", get(saved_searches_api).post(saved_search_put_api))
+        .route("/api/saved-searches/:name", delete(saved_search_delete_api))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token));
+
+    // Export and Torznab check their own keys.
+    Router::new()
+        .route("/login", get(login_page).post(login_submit))
+        .route("/logout", post(logout))
+        .route("/api/export", get(export_api))
+        .route("/api/sync", post(sync_push_api).layer(DefaultBodyLimit::max(SYNC_MAX_BYTES)))
+        .route("/torznab/api", get(crate::torznab::api).route_layer(limit(Class::Search)))
+        .merge(search)
+        .merge(browse)
+        .merge(protected)
+        .layer(middleware::from_fn(trace_request))
+        .with_state(state)
 }
 
-pub async fn serve_dual_loopback(state: AppState, port: u16) -> anyhow::Result<()> {
-    let app = Router::new()
-        .route("/", get(home))
-        .route("/search", get(search_html))
-        .route("/search/", get(search_html))
-        .route("/api/search", get(search_api))
-        .route("/api/search/", get(search_api))
-        .route("/t/:info_hash", get(torrent_page))
-        .with_state(state);
-
-    let addr_v4: std::net::SocketAddr = format!("127.0.0.1:{}", port).parse()?;
-    tracing::info!(%addr_v4, "listening");
-    let listener_v4 = tokio::net::TcpListener::bind(addr_v4).await?;
-    let server_v4 = axum::serve(listener_v4, app.clone());
-
-    let addr_v6: std::net::SocketAddr = format!("[::1]:{}", port).parse()?;
-    let listener_v6 = match tokio::net::TcpListener::bind(addr_v6).await {
-        Ok(l) => {
-            tracing::info!(%addr_v6, "listening");
-            Some(l)
-        }
-        Err(err) => {
-            tracing::debug!(%err, %addr_v6, "ipv6 bind failed; continuing with ipv4 only");
-            None
-        }
+/// One span per request, named by route (`/t/:info_hash`) so traces group by endpoint.
+async fn trace_request(request: Request, next: Next) -> axum::response::Response {
+    let route = request
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let span = tracing::info_span!(
+        "http_request",
+        method = %request.method(),
+        route = %route,
+        status = tracing::field::Empty,
+    );
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+    response
+}
+
+/// Browser session cookie set by `/login`.
+const SESSION_COOKIE: &str = "serma_session";
+const SESSION_MAX_AGE_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Cookie value for a session ending at `expires` (Unix seconds): the expiry and an
+/// HMAC-SHA256 of it and the token under the instance's session key, so the token itself
+/// never sits in the browser and a cookie can't be forged or extended. Changing
+/// `SERMA_API_TOKEN` invalidates every session.
+fn session_value(key: &[u8; 32], token: &str, expires: u64) -> String {
+    format!("{expires}.{}", hex::encode(session_mac(key, token, expires).finalize().into_bytes()))
+}
+
+fn session_mac(key: &[u8; 32], token: &str, expires: u64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(format!("serma-session:{expires}:{token}").as_bytes());
+    mac
+}
+
+fn session_valid(key: &[u8; 32], token: &str, cookie: &str) -> bool {
+    let Some((expires, tag)) = cookie.split_once('.') else {
+        return false;
+    };
+    let (Ok(expires), Ok(tag)) = (expires.parse::<u64>(), hex::decode(tag)) else {
+        return false;
     };
+    expires > unix_now_secs() && session_mac(key, token, expires).verify_slice(&tag).is_ok()
+}
 
-    if let Some(listener_v6) = listener_v6 {
-        let server_v6 = axum::serve(listener_v6, app);
-        tokio::select! {
-            r = server_v4 => r?,
-            r = server_v6 => r?,
-        }
+fn unix_now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(axum::http::header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| pair.trim().strip_prefix(SESSION_COOKIE)?.strip_prefix('='))
+}
+
+pub(crate) fn is_authorized(state: &AppState, token: &str, headers: &HeaderMap) -> bool {
+    bearer_token(headers).is_some_and(|t| constant_time_eq(t.as_bytes(), token.as_bytes()))
+        || session_cookie(headers).is_some_and(|c| session_valid(&state.session_key, token, c))
+}
+
+/// Whether a request gets magnet links, `.torrent` files and the admin buttons: always,
+/// except on a read-only instance (`SERMA_READ_ONLY`) without the API token.
+fn full_access(state: &AppState, headers: &HeaderMap) -> bool {
+    !state.config.read_only || state.config.api_token.as_deref().is_some_and(|token| is_authorized(state, token, headers))
+}
+
+fn hide_magnets(hits: &mut [crate::index::SearchHit]) {
+    for hit in hits {
+        hit.magnet = None;
+    }
+}
+
+/// Requires `SERMA_API_TOKEN` as a bearer token or a `/login` session. API clients get
+/// 401; browsers are sent to the login page. A no-op when no token is configured.
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> axum::response::Response {
+    let Some(token) = state.config.api_token.as_deref() else {
+        return next.run(request).await;
+    };
+    if is_authorized(&state, token, request.headers()) {
+        return next.run(request).await;
+    }
+
+    let path = request.uri().path();
+    if path.starts_with("/api/") {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(axum::http::header::WWW_AUTHENTICATE, "Bearer")],
+            Json(serde_json::json!({ "error": "missing or invalid bearer token" })),
+        )
+            .into_response();
+    }
+    // Form posts can't be replayed after login; go back to the page they came from.
+    let next_path = if request.method() == axum::http::Method::GET {
+        request.uri().path_and_query().map(|pq| pq.as_str()).unwrap_or("/")
+    } else {
+        "/admin"
+    };
+    Redirect::to(&format!("/login?next={}", url_encode(next_path))).into_response()
+}
+
+/// Answers requests over the client's `class` rate limit with 429 and a `Retry-After`.
+/// Requests with the API token aren't limited.
+async fn rate_limit(
+    State((state, class)): State<(AppState, Class)>,
+    request: Request,
+    next: Next,
+) -> axum::response::Response {
+    if let Some(token) = state.config.api_token.as_deref()
+        && is_authorized(&state, token, request.headers())
+    {
+        return next.run(request).await;
+    }
+    let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<std::net::SocketAddr>>().copied() else {
+        return next.run(request).await;
+    };
+    let client = state.limits.client_ip(peer, request.headers());
+    let Err(wait) = state.limits.check(class, client) else {
+        return next.run(request).await;
+    };
+
+    tracing::debug!(%client, class = class.as_str(), "web: rate limited");
+    let retry_after = [(axum::http::header::RETRY_AFTER, (wait.as_secs_f64().ceil() as u64).max(1).to_string())];
+    if request.uri().path().starts_with("/api/") {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            retry_after,
+            Json(serde_json::json!({ "error": "too many requests; slow down" })),
+        )
+            .into_response()
     } else {
-        server_v4.await?;
+        (StatusCode::TOO_MANY_REQUESTS, retry_after, "Too many requests; slow down.").into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginParams {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LoginForm {
+    token: String,
+    next: Option<String>,
+}
+
+/// Only local paths, so the login form can't be used as an open redirect.
+fn login_next(next: Option<&str>) -> &str {
+    next.filter(|n| n.starts_with('/') && !n.starts_with("//")).unwrap_or("/admin")
+}
+
+fn login_form(next: &str, error: Option<&str>) -> Html<String> {
+    let error = error
+        .map(|e| format!(r##"<p class="muted">{}</p>"##, html_escape(e)))
+        .unwrap_or_default();
+    page(
+        "Log in",
+        format!(
+            r##"
+            <main class="detail-card">
+                <div class="detail-header">
+                    <h1 class="detail-title">Log in</h1>
+                    <p class="muted">Enter the API token (<code>SERMA_API_TOKEN</code>).</p>
+                </div>
+                {}
+                <form method="post" action="/login" class="flex gap-2">
+                    <input type="hidden" name="next" value="{}" />
+                    <input type="password" name="token" placeholder="API token" autocomplete="current-password" autofocus />
+                    <button type="submit" class="btn btn-primary">Log in</button>
+                </form>
+            </main>
+            "##,
+            error,
+            html_escape(next)
+        ),
+    )
+}
+
+async fn login_page(State(state): State<AppState>, Query(params): Query<LoginParams>) -> impl IntoResponse {
+    let next = login_next(params.next.as_deref());
+    if state.config.api_token.is_none() {
+        return Redirect::to(next).into_response();
+    }
+    login_form(next, None).into_response()
+}
+
+async fn login_submit(State(state): State<AppState>, Form(form): Form<LoginForm>) -> impl IntoResponse {
+    let next = login_next(form.next.as_deref());
+    let Some(token) = state.config.api_token.as_deref() else {
+        return Redirect::to(next).into_response();
+    };
+    if !constant_time_eq(form.token.trim().as_bytes(), token.as_bytes()) {
+        return (StatusCode::UNAUTHORIZED, login_form(next, Some("Wrong token."))).into_response();
+    }
+    let cookie = format!(
+        "{SESSION_COOKIE}={}; Path=/; Max-Age={SESSION_MAX_AGE_SECS}; HttpOnly; SameSite=Strict",
+        session_value(&state.session_key, token, unix_now_secs() + SESSION_MAX_AGE_SECS)
+    );
+    ([(axum::http::header::SET_COOKIE, cookie)], Redirect::to(next)).into_response()
+}
+
+async fn logout() -> impl IntoResponse {
+    let cookie = format!("{SESSION_COOKIE}=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict");
+    ([(axum::http::header::SET_COOKIE, cookie)], Redirect::to("/"))
+}
+
+/// Serves `app` on `listener` until shutdown, over HTTPS when `tls` is set.
+async fn listen(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    tls: Option<tokio_rustls::TlsAcceptor>,
+    shutdown: crate::Shutdown,
+) -> anyhow::Result<()> {
+    match tls {
+        Some(acceptor) => crate::tls::serve(listener, app, acceptor, shutdown).await,
+        None => {
+            axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+                .with_graceful_shutdown(async move { shutdown.wait().await })
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+/// The TLS acceptor for SERMA_TLS_CERT / SERMA_TLS_KEY, if set.
+fn tls_acceptor(state: &AppState) -> anyhow::Result<Option<tokio_rustls::TlsAcceptor>> {
+    match (&state.config.tls_cert, &state.config.tls_key) {
+        (Some(cert), Some(key)) => Ok(Some(crate::tls::acceptor(cert, key)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Binds `addr`. A wildcard IPv6 address normally takes IPv4 connections on its port too;
+/// with `v6_only` it leaves them to a separate IPv4 listener.
+fn bind(addr: std::net::SocketAddr, v6_only: bool) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = socket2::Socket::new(socket2::Domain::for_address(addr), socket2::Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
     }
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
+/// Serves on each address with its own listener until shutdown. Addresses marked optional
+/// are skipped if they can't be bound; any other bind failure is an error.
+async fn serve_on(state: AppState, addrs: &[(std::net::SocketAddr, bool)]) -> anyhow::Result<()> {
+    let shutdown = state.shutdown.clone();
+    let tls = tls_acceptor(&state)?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let app = router(state);
 
+    let mut servers = Vec::with_capacity(addrs.len());
+    for &(addr, optional) in addrs {
+        let v6_only = addrs
+            .iter()
+            .any(|(other, _)| other.is_ipv4() && other.port() == addr.port());
+        let listener = match bind(addr, v6_only) {
+            Ok(listener) => listener,
+            Err(err) if optional => {
+                tracing::debug!(%err, %addr, "bind failed; continuing without it");
+                continue;
+            }
+            Err(err) => return Err(anyhow::Error::from(err).context(format!("bind web server to {addr}"))),
+        };
+        tracing::info!(%addr, scheme, "listening");
+        servers.push(listen(listener, app.clone(), tls.clone(), shutdown.clone()));
+    }
+    futures_util::future::try_join_all(servers).await?;
     Ok(())
 }
 
+/// Serves on every address of SERMA_ADDR.
+pub async fn serve(state: AppState, addrs: &[std::net::SocketAddr]) -> anyhow::Result<()> {
+    let addrs: Vec<_> = addrs.iter().map(|&addr| (addr, false)).collect();
+    serve_on(state, &addrs).await
+}
+
+/// Serves on `127.0.0.1` and, where the host has IPv6, `::1`.
+pub async fn serve_dual_loopback(state: AppState, port: u16) -> anyhow::Result<()> {
+    let addr_v4 = std::net::SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, port));
+    let addr_v6 = std::net::SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, port));
+    serve_on(state, &[(addr_v4, false), (addr_v6, true)]).await
+}
+
 async fn home() -> impl IntoResponse {
     page(
         "Home",
@@ -495,7 +875,7 @@ async fn home() -> impl IntoResponse {
                 <p>Serma continuously discovers hashes, enriches metadata, and cleans inactive torrents</p>
                 <form action="/search" method="get" class="hero-search">
                     <div class="search-wrapper">
-                        <input type="text" name="q" placeholder="Search by title..." autocomplete="off" autofocus />
+                        <input type="text" name="q" placeholder="Search by title..." autocomplete="off" list="suggestions" autofocus />
                     </div>
                     <div style="margin-top: 20px; display: flex; gap: 8px; justify-content: center;">
                          <button type="submit" class="btn btn-primary">{} Search</button>
@@ -511,105 +891,237 @@ async fn home() -> impl IntoResponse {
 #[derive(Deserialize)]
 struct SearchParams {
     q: Option<String>,
+    page: Option<usize>,
     limit: Option<usize>,
+    sort: Option<String>,
+}
+
+/// Optional tuning and filter parameters shared by `/search` and `/api/search`, read as a
+/// second `Query` so the handlers' own params stay plain. Results-page links keep them.
+#[derive(Deserialize)]
+struct SearchExtras {
+    /// Fuzzy fallback edit distance, 0 to 2.
+    fuzzy: Option<String>,
+    /// Whether `all` query words must match, or `any`.
+    #[serde(rename = "match")]
+    match_words: Option<String>,
+    /// Weight of swarm size in relevance order.
+    boost: Option<String>,
+    /// Size bounds, in bytes or with a unit (`700MiB`, `1.5G`).
+    min_size: Option<String>,
+    max_size: Option<String>,
+    /// `title` collapses matches with the same title into one result.
+    group: Option<String>,
+}
+
+impl SearchExtras {
+    fn pairs(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("fuzzy", &self.fuzzy),
+            ("match", &self.match_words),
+            ("boost", &self.boost),
+            ("min_size", &self.min_size),
+            ("max_size", &self.max_size),
+            ("group", &self.group),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref().map(str::trim).filter(|v| !v.is_empty())?)))
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.pairs().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// The configured query options with the request's overrides, or what was invalid.
+    fn query_options(&self, mut opts: QueryOptions) -> Result<QueryOptions, &'static str> {
+        if let Some(v) = self.get("fuzzy") {
+            opts.fuzzy_distance = v
+                .parse()
+                .ok()
+                .filter(|d| *d <= MAX_FUZZY_DISTANCE)
+                .ok_or("invalid fuzzy; expected 0, 1 or 2")?;
+        }
+        if let Some(v) = self.get("match") {
+            opts.match_all = match v.to_ascii_lowercase().as_str() {
+                "all" => true,
+                "any" => false,
+                _ => return Err("invalid match; expected all or any"),
+            };
+        }
+        if let Some(v) = self.get("boost") {
+            opts.seeder_boost = v
+                .parse()
+                .ok()
+                .filter(|b| (0.0..=100.0).contains(b))
+                .ok_or("invalid boost; expected a number between 0 and 100")?;
+        }
+        if let Some(v) = self.get("group") {
+            opts.group_titles = match v.to_ascii_lowercase().as_str() {
+                "title" => true,
+                "none" => false,
+                _ => return Err("invalid group; expected title or none"),
+            };
+        }
+        Ok(opts)
+    }
+
+    fn filters(&self) -> Result<SearchFilters, &'static str> {
+        let size = |name: &str, err: &'static str| self.get(name).map(|v| parse_bytes(v).ok_or(err)).transpose();
+        Ok(SearchFilters {
+            min_size: size("min_size", "invalid min_size; expected bytes or a size like 700MiB")?,
+            max_size: size("max_size", "invalid max_size; expected bytes or a size like 4GiB")?,
+        })
+    }
+
+    /// `&name=value` for each parameter given, so links keep them.
+    fn query_suffix(&self) -> String {
+        self.query_suffix_without("")
+    }
+
+    /// `query_suffix` leaving out the parameter `skip`, for links that set it themselves.
+    fn query_suffix_without(&self, skip: &str) -> String {
+        self.pairs()
+            .filter(|(name, _)| *name != skip)
+            .map(|(name, value)| format!("&{name}={}", url_encode(value)))
+            .collect()
+    }
+
+    /// Hidden inputs for each parameter given, so a new search from the results page keeps them.
+    fn hidden_inputs(&self) -> String {
+        self.pairs()
+            .map(|(name, value)| format!(r#"<input type="hidden" name="{name}" value="{}" />"#, html_escape(value)))
+            .collect()
+    }
 }
 
 const SEARCH_PAGE_SIZE: usize = 25;
 const SEARCH_MAX_LIMIT: usize = 200;
+const SUGGEST_DEFAULT_LIMIT: usize = 8;
+const SUGGEST_MAX_LIMIT: usize = 20;
 
 async fn search_html(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<SearchParams>,
+    Query(extras): Query<SearchExtras>,
 ) -> impl IntoResponse {
     let q = params.q.unwrap_or_default();
     let limit = params
         .limit
         .unwrap_or(SEARCH_PAGE_SIZE)
         .clamp(1, SEARCH_MAX_LIMIT);
+    let sort = params
+        .sort
+        .as_deref()
+        .and_then(SearchSort::parse)
+        .unwrap_or_default();
+    // Pages past what the sort can reach would repeat the last one.
+    let last_page = max_offset(sort, limit) / limit + 1;
+    let page_no = params.page.unwrap_or(1).clamp(1, last_page);
+    let offset = (page_no - 1).saturating_mul(limit);
+    let defaults = state.config.query_options();
+    let opts = extras.query_options(defaults).unwrap_or(defaults);
+    let filters = extras.filters().unwrap_or_default();
 
-    let (hits, has_more) = if q.trim().is_empty() {
-        (Vec::new(), false)
+    let SearchPage { mut hits, total, .. } = if q.trim().is_empty() {
+        SearchPage::default()
     } else {
-        // Fetch one extra so we can decide whether to show a "Load more" control.
-        let hits = state
-            .index
-            .search(&q, limit.saturating_add(1))
-            .unwrap_or_default();
-        let has_more = hits.len() > limit;
-        let hits = hits.into_iter().take(limit).collect();
-        (hits, has_more)
+        state.index.search_counted(&q, sort, offset, limit, opts, filters).unwrap_or_default()
     };
-
-    let mut items = String::new();
-    for hit in hits {
-        let info_hash = hit.info_hash.unwrap_or_default();
-        let title = hit.title.unwrap_or_else(|| "(untitled)".to_string());
-        let magnet = hit.magnet.unwrap_or_default();
-        let short_hash = if info_hash.len() > 12 {
-            &info_hash[0..12]
-        } else {
-            &info_hash
-        };
-
-        let actions = if !magnet.is_empty() {
-            format!(
-                r##"<a href="{}" class="btn btn-icon" title="Magnet">{}</a>
-                   <button class="btn btn-icon" data-copy="{}" title="Copy Link">{}</button>"##,
-                html_escape(&magnet),
-                ICON_MAGNET,
-                html_escape(&magnet),
-                ICON_COPY
-            )
-        } else {
-            String::new()
-        };
-
-        items.push_str(&format!(
-            r##"
-            <li class="list-item">
-                <div class="item-header">
-                    <div>
-                        <a href="/t/{}" class="item-title">{}</a>
-                        <div class="item-meta">
-                            <span class="badge">S: {}</span>
-                            <span class="mono">#{}</span>
-                        </div>
-                    </div>
-                    <div class="flex gap-2">
-                        {}
-                        <a href="/t/{}" class="btn btn-icon">{}</a>
-                    </div>
-                </div>
-            </li>
-            "##,
-            html_escape(&info_hash),
-            html_escape(&title),
-            hit.seeders,
-            html_escape(short_hash),
-            actions,
-            html_escape(&info_hash),
-            ICON_ARROW_RIGHT
-        ));
+    if !full_access(&state, &headers) {
+        hide_magnets(&mut hits);
     }
 
-    let load_more_html = if has_more {
-        let next_limit = (limit.saturating_add(SEARCH_PAGE_SIZE)).min(SEARCH_MAX_LIMIT);
+    let items: String = hits.into_iter().map(result_item_html).collect();
+
+    let pages = total.div_ceil(limit).clamp(1, last_page);
+    let page_link = |n: usize, label: &str| {
         format!(
-            r##"<div style="margin-top: 18px; display:flex; justify-content:center;">
-                    <a class="btn btn-ghost" href="/search?q={}&limit={}">Load more</a>
-                </div>"##,
+            r##"<a class="btn btn-ghost" href="/search?q={}&page={}&limit={}&sort={}{}">{}</a>"##,
             url_encode(&q),
-            next_limit
+            n,
+            limit,
+            sort.as_str(),
+            extras.query_suffix(),
+            label
+        )
+    };
+    let pager_html = if total > limit {
+        format!(
+            r##"<div class="flex gap-4" style="margin-top: 18px; justify-content:center; align-items:center;">
+                    {}
+                    <span class="muted">Page {} of {} &middot; {} results</span>
+                    {}
+                </div>"##,
+            if page_no > 1 { page_link(page_no - 1, "&larr; Prev") } else { String::new() },
+            page_no,
+            pages,
+            total,
+            if page_no < pages { page_link(page_no + 1, "Next &rarr;") } else { String::new() }
         )
     } else {
         String::new()
     };
 
+    let sort_html = [
+        (SearchSort::Relevance, "Relevance"),
+        (SearchSort::Seeders, "Seeders"),
+        (SearchSort::Added, "Newest"),
+        (SearchSort::Size, "Size"),
+    ]
+    .iter()
+    .map(|&(option, label)| {
+        let class = if option == sort { "btn btn-primary" } else { "btn btn-ghost" };
+        format!(
+            r##"<a class="{}" href="/search?q={}&limit={}&sort={}{}">{}</a>"##,
+            class,
+            url_encode(&q),
+            limit,
+            option.as_str(),
+            extras.query_suffix(),
+            label
+        )
+    })
+    .collect::<String>();
+    let (group_value, group_label) = if opts.group_titles {
+        ("none", "Show copies")
+    } else {
+        ("title", "Group copies")
+    };
+    let group_html = format!(
+        r##"<a class="btn btn-ghost" href="/search?q={}&limit={}&sort={}{}&group={}">{}</a>"##,
+        url_encode(&q),
+        limit,
+        sort.as_str(),
+        extras.query_suffix_without("group"),
+        group_value,
+        group_label
+    );
+
+    // All matches, not just this page.
+    let export_html = ["csv", "jsonl"]
+        .iter()
+        .map(|format| {
+            format!(
+                r##"<a class="btn btn-ghost" href="/api/search/export?q={}&sort={}&format={}{}" title="Download every result">Export {}</a>"##,
+                url_encode(&q),
+                sort.as_str(),
+                format,
+                extras.query_suffix_without("group"),
+                format.to_uppercase()
+            )
+        })
+        .collect::<String>();
+
     let results_html = if items.is_empty() {
         r##"<div style="text-align:center; padding: 40px; color: var(--text-muted);">No results found in the nest.</div>"##
             .to_string()
     } else {
-        format!("<ul class=\"results-list\">{}</ul>{}", items, load_more_html)
+        format!(
+            r##"<div class="flex gap-2" style="margin-bottom: 12px; align-items:center;"><span class="muted">Sort:</span>{}{}{}</div>
+                <ul class="results-list">{}</ul>{}"##,
+            sort_html, group_html, export_html, items, pager_html
+        )
     };
 
     page(
@@ -618,110 +1130,1633 @@ async fn search_html(
             r##"
             <div style="margin-top: 40px;">
                 <form action="/search" method="get" class="search-wrapper">
-                    <input type="text" name="q" value="{}" placeholder="Search..." autocomplete="off" />
+                    <input type="text" name="q" value="{}" placeholder="Search..." autocomplete="off" list="suggestions" />
                     <input type="hidden" name="limit" value="{}" />
+                    <input type="hidden" name="sort" value="{}" />
+                    {}
                 </form>
                 {}
             </div>
             "##,
             html_escape(&q),
             limit,
+            sort.as_str(),
+            extras.hidden_inputs(),
             results_html
         ),
     )
 }
 
+/// A search or browse result as a list item.
+fn result_item_html(hit: crate::index::SearchHit) -> String {
+    let info_hash = hit.info_hash.unwrap_or_default();
+    let title = hit.title.unwrap_or_else(|| "(untitled)".to_string());
+    let magnet = hit.magnet.unwrap_or_default();
+    // Unknown (0) until the metadata has been fetched.
+    let size = if hit.size_bytes > 0 {
+        format!(r#"<span class="badge">{}</span>"#, format_bytes(hit.size_bytes))
+    } else {
+        String::new()
+    };
+    let copies = if hit.alternates.is_empty() {
+        String::new()
+    } else {
+        format!(r#"<span class="badge" title="Other torrents with this title">+{} copies</span>"#, hit.alternates.len())
+    };
+    let short_hash = if info_hash.len() > 12 {
+        &info_hash[0..12]
+    } else {
+        &info_hash
+    };
+
+    let actions = if !magnet.is_empty() {
+        format!(
+            r##"<a href="{}" class="btn btn-icon" title="Magnet">{}</a>
+               <button class="btn btn-icon" data-copy="{}" title="Copy Link">{}</button>"##,
+            html_escape(&magnet),
+            ICON_MAGNET,
+            html_escape(&magnet),
+            ICON_COPY
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        r##"
+        <li class="list-item">
+            <div class="item-header">
+                <div>
+                    <a href="/t/{}" class="item-title">{}</a>
+                    <div class="item-meta">
+                        <span class="badge">S: {} &middot; L: {}</span>
+                        {}
+                        {}
+                        <span class="mono">#{}</span>
+                    </div>
+                </div>
+                <div class="flex gap-2">
+                    {}
+                    <a href="/t/{}" class="btn btn-icon">{}</a>
+                </div>
+            </div>
+        </li>
+        "##,
+        html_escape(&info_hash),
+        html_escape(&title),
+        hit.seeders,
+        hit.leechers,
+        size,
+        copies,
+        html_escape(short_hash),
+        actions,
+        html_escape(&info_hash),
+        ICON_ARROW_RIGHT
+    )
+}
+
 #[derive(Deserialize)]
 struct SearchApiParams {
     q: Option<String>,
     offset: Option<usize>,
+    /// 1-based page number; an alternative to `offset`.
+    page: Option<usize>,
     limit: Option<usize>,
+    /// relevance (default), seeders, added or size.
+    sort: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SearchApiResponse {
+    results: Vec<crate::index::SearchHit>,
+    total: usize,
+    limit: usize,
+    offset: usize,
+    sort: &'static str,
+    facets: SearchFacets,
+    /// Time spent searching, in milliseconds.
+    took_ms: u64,
+}
+
+#[derive(Serialize)]
+struct SearchFacets {
+    /// Matching torrents per category (see `index::category_of`).
+    category: std::collections::BTreeMap<String, u64>,
 }
 
 async fn search_api(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(params): Query<SearchApiParams>,
+    Query(extras): Query<SearchExtras>,
 ) -> impl IntoResponse {
     let q = params.q.unwrap_or_default();
-    let offset = params.offset.unwrap_or(0);
     let limit = params
         .limit
         .unwrap_or(SEARCH_PAGE_SIZE)
         .clamp(1, SEARCH_MAX_LIMIT);
+    let offset = match (params.offset, params.page) {
+        (Some(offset), _) => offset,
+        (None, Some(page)) => page.max(1).saturating_sub(1).saturating_mul(limit),
+        (None, None) => 0,
+    };
+    let Some(sort) = SearchSort::parse(params.sort.as_deref().unwrap_or_default()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "invalid sort; expected relevance, seeders, added or size" })),
+        )
+            .into_response();
+    };
+    let offset = offset.min(max_offset(sort, limit));
+    let parsed = extras
+        .query_options(state.config.query_options())
+        .and_then(|opts| Ok((opts, extras.filters()?)));
+    let (opts, filters) = match parsed {
+        Ok(v) => v,
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": err }))).into_response(),
+    };
 
-    let hits = if q.trim().is_empty() {
-        Vec::new()
+    let started = std::time::Instant::now();
+    let SearchPage { mut hits, total, categories } = if q.trim().is_empty() {
+        SearchPage::default()
     } else {
-        state.index.search_page(&q, offset, limit).unwrap_or_default()
+        state.index.search_counted(&q, sort, offset, limit, opts, filters).unwrap_or_default()
     };
-    Json(hits)
+    if !full_access(&state, &headers) {
+        hide_magnets(&mut hits);
+    }
+    Json(SearchApiResponse {
+        results: hits,
+        total,
+        limit,
+        offset,
+        sort: sort.as_str(),
+        facets: SearchFacets { category: categories },
+        took_ms: started.elapsed().as_millis() as u64,
+    })
+    .into_response()
 }
 
-async fn torrent_page(
-    State(state): State<AppState>,
-    Path(info_hash): Path<String>,
-) -> impl IntoResponse {
-    let record = crate::storage::get(&state.db, &info_hash).ok().flatten();
-
-    let title = record
-        .as_ref()
-        .and_then(|r| r.title.clone())
-        .unwrap_or_else(|| "Unknown Title".to_string());
-
-    let magnet = record
-        .as_ref()
-        .and_then(|r| r.magnet.clone())
-        .unwrap_or_default();
+#[derive(Deserialize)]
+struct SearchExportParams {
+    q: Option<String>,
+    /// relevance (default), seeders, added or size.
+    sort: Option<String>,
+    /// jsonl (default) or csv.
+    format: Option<String>,
+}
 
-    let seeders = record.as_ref().map(|r| r.seeders).unwrap_or(0);
-    
-    let magnet_section = if magnet.is_empty() {
-        String::new()
-    } else {
-        format!(
-            r##"
-            <div class="magnet-box">
-                <div class="flex" style="padding: 0 12px; color: var(--snake-orange);">{}</div>
-                <input type="text" value="{}" readonly onclick="this.select()" />
-                <button class="btn btn-ghost" data-copy="{}">Copy</button>
-                <a href="{}" class="btn btn-primary">Open</a>
-            </div>
-            "##,
-            ICON_MAGNET,
-            html_escape(&magnet),
-            html_escape(&magnet),
-            html_escape(&magnet)
+/// Streams every match of a search as a download, read through one index snapshot.
+/// Takes the same query, sort and filters as `/api/search`; titles are never grouped.
+async fn search_export_api(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<SearchExportParams>,
+    Query(extras): Query<SearchExtras>,
+) -> axum::response::Response {
+    let csv = match params.format.as_deref().unwrap_or("jsonl") {
+        "jsonl" => false,
+        "csv" => true,
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": "invalid format; expected jsonl or csv" })),
+            )
+                .into_response();
+        }
+    };
+    let Some(sort) = SearchSort::parse(params.sort.as_deref().unwrap_or_default()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "invalid sort; expected relevance, seeders, added or size" })),
         )
+            .into_response();
+    };
+    let parsed = extras
+        .query_options(state.config.query_options())
+        .and_then(|opts| Ok((opts, extras.filters()?)));
+    let (opts, filters) = match parsed {
+        Ok(v) => v,
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": err }))).into_response(),
     };
 
-    page(
-        &title,
-        format!(
-            r##"
-            <main class="detail-card">
+    let q = params.q.unwrap_or_default();
+    let (content_type, extension) = if csv { ("text/csv", "csv") } else { ("application/x-ndjson", "jsonl") };
+    let disposition = format!("attachment; filename=\"serma-search.{extension}\"");
+    let index = state.index.clone();
+    let body = crate::export::search_body(
+        move || index.export(&q, sort, opts, filters),
+        csv,
+        full_access(&state, &headers),
+    );
+    (
+        [
+            (axum::http::header::CONTENT_TYPE, content_type.to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// Orders of the `/browse/<order>` pages: `recent` (newest first) and `top` (most seeded).
+fn browse_order(order: &str) -> Option<(SearchSort, &'static str)> {
+    match order {
+        "recent" => Some((SearchSort::Added, "Recently Discovered")),
+        "top" => Some((SearchSort::Seeders, "Top Seeded")),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+struct BrowseParams {
+    offset: Option<usize>,
+    /// 1-based page number; an alternative to `offset`.
+    page: Option<usize>,
+    limit: Option<usize>,
+}
+
+impl BrowseParams {
+    fn limit(&self) -> usize {
+        self.limit.unwrap_or(SEARCH_PAGE_SIZE).clamp(1, SEARCH_MAX_LIMIT)
+    }
+
+    fn offset(&self) -> usize {
+        let offset = match (self.offset, self.page) {
+            (Some(offset), _) => offset,
+            (None, Some(page)) => page.max(1).saturating_sub(1).saturating_mul(self.limit()),
+            (None, None) => 0,
+        };
+        offset.min(MAX_OFFSET)
+    }
+}
+
+/// Pages through every indexed torrent, newest or most seeded first, without a query.
+async fn browse_html(
+    State(state): State<AppState>,
+    Path(order): Path<String>,
+    Query(params): Query<BrowseParams>,
+) -> axum::response::Response {
+    let Some((sort, heading)) = browse_order(&order) else {
+        return (StatusCode::NOT_FOUND, "Not found").into_response();
+    };
+    let limit = params.limit();
+    let last_page = MAX_OFFSET / limit + 1;
+    let page_no = params.page.unwrap_or(1).clamp(1, last_page);
+    let offset = (page_no - 1).saturating_mul(limit);
+    let SearchPage { hits, total, .. } = state.index.browse(sort, offset, limit).unwrap_or_default();
+
+    let items: String = hits.into_iter().map(result_item_html).collect();
+    let pages = total.div_ceil(limit).clamp(1, last_page);
+    let page_link = |n: usize, label: &str| {
+        format!(r##"<a class="btn btn-ghost" href="/browse/{order}?page={n}&limit={limit}">{label}</a>"##)
+    };
+    let pager_html = if total > limit {
+        format!(
+            r##"<div class="flex gap-4" style="margin-top: 18px; justify-content:center; align-items:center;">
+                    {}
+                    <span class="muted">Page {} of {} &middot; {} torrents</span>
+                    {}
+                </div>"##,
+            if page_no > 1 { page_link(page_no - 1, "&larr; Prev") } else { String::new() },
+            page_no,
+            pages,
+            total,
+            if page_no < pages { page_link(page_no + 1, "Next &rarr;") } else { String::new() }
+        )
+    } else {
+        String::new()
+    };
+    let results_html = if items.is_empty() {
+        r##"<div style="text-align:center; padding: 40px; color: var(--text-muted);">Nothing in the nest yet.</div>"##
+            .to_string()
+    } else {
+        format!(r##"<ul class="results-list">{items}</ul>{pager_html}"##)
+    };
+    let tabs: String = [("recent", "Recent"), ("top", "Top Seeded")]
+        .iter()
+        .map(|&(name, label)| {
+            let class = if name == order { "btn btn-primary" } else { "btn btn-ghost" };
+            format!(r##"<a class="{class}" href="/browse/{name}?limit={limit}">{label}</a>"##)
+        })
+        .collect();
+
+    page(
+        heading,
+        format!(
+            r##"
+            <div style="margin-top: 40px;">
+                <div class="flex gap-2" style="margin-bottom: 12px; align-items:center;">{tabs}</div>
+                {results_html}
+            </div>
+            "##
+        ),
+    )
+    .into_response()
+}
+
+#[derive(Serialize)]
+struct BrowseApiResponse {
+    results: Vec<crate::index::SearchHit>,
+    total: usize,
+    limit: usize,
+    offset: usize,
+    order: String,
+}
+
+/// JSON of `/browse/<order>`.
+async fn browse_api(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(order): Path<String>,
+    Query(params): Query<BrowseParams>,
+) -> axum::response::Response {
+    let Some((sort, _)) = browse_order(&order) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "unknown order; expected recent or top" })))
+            .into_response();
+    };
+    let (limit, offset) = (params.limit(), params.offset());
+    let SearchPage { mut hits, total, .. } = state.index.browse(sort, offset, limit).unwrap_or_default();
+    if !full_access(&state, &headers) {
+        hide_magnets(&mut hits);
+    }
+    Json(BrowseApiResponse { results: hits, total, limit, offset, order }).into_response()
+}
+
+#[derive(Deserialize)]
+struct SuggestParams {
+    q: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Title completions for a partly typed query, most seeded first.
+async fn suggest_api(State(state): State<AppState>, Query(params): Query<SuggestParams>) -> impl IntoResponse {
+    let q = params.q.unwrap_or_default();
+    let limit = params
+        .limit
+        .unwrap_or(SUGGEST_DEFAULT_LIMIT)
+        .clamp(1, SUGGEST_MAX_LIMIT);
+    let suggestions = state.index.suggest(&q, limit).unwrap_or_default();
+    Json(serde_json::json!({ "query": q, "suggestions": suggestions }))
+}
+
+/// Keep-alive comment interval, so proxies don't close an idle stream.
+const STREAM_KEEP_ALIVE: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Server-sent events as the spider discovers hashes (`discovered`) and enrichment fetches
+/// metadata (`enriched`); each event's data is the JSON of `events::Event`. A client that
+/// falls behind gets a `lagged` event with the number it missed. Streams end on shutdown.
+async fn stream_api(State(state): State<AppState>) -> impl IntoResponse {
+    use axum::response::sse::{Event, KeepAlive, Sse};
+    use tokio::sync::broadcast::error::RecvError;
+
+    let rx = state.events.subscribe();
+    let shutdown = state.shutdown.clone();
+    let stream = futures_util::stream::unfold((rx, shutdown), |(mut rx, shutdown)| async move {
+        let received = tokio::select! {
+            received = rx.recv() => received,
+            _ = shutdown.wait() => return None,
+        };
+        let event = match received {
+            Ok(event) => Event::default().event(event.kind()).json_data(&event).ok()?,
+            Err(RecvError::Lagged(missed)) => Event::default()
+                .event("lagged")
+                .data(serde_json::json!({ "missed": missed }).to_string()),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok::<_, std::convert::Infallible>(event), (rx, shutdown)))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::new().interval(STREAM_KEEP_ALIVE))
+}
+
+async fn torrent_page(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(info_hash): Path<String>,
+) -> impl IntoResponse {
+    // Accepts v2 (64-character) hashes too.
+    let record = crate::storage::resolve_info_hash(&state.db, &info_hash)
+        .ok()
+        .flatten()
+        .and_then(|hash| crate::storage::get(&state.db, &hash).ok().flatten());
+    let info_hash = record.as_ref().map(|r| r.info_hash_hex.clone()).unwrap_or(info_hash);
+    let info_hash_v2 = record
+        .as_ref()
+        .and_then(|r| r.info_hash_v2_hex.as_deref())
+        .map(|v2| format!(r#"<span class="mono muted">v2: {}</span>"#, html_escape(v2)))
+        .unwrap_or_default();
+
+    let title = record
+        .as_ref()
+        .and_then(|r| r.title.clone())
+        .unwrap_or_else(|| "Unknown Title".to_string());
+
+    let full_access = full_access(&state, &headers);
+    let magnet = record
+        .as_ref()
+        .filter(|_| full_access)
+        .and_then(|r| r.magnet.clone())
+        .unwrap_or_default();
+
+    let seeders = record.as_ref().map(|r| r.seeders).unwrap_or(0);
+    let leechers = record.as_ref().map(|r| r.leechers).unwrap_or(0);
+    let size = record
+        .as_ref()
+        .filter(|r| r.size_bytes > 0)
+        .map(|r| {
+            let files = if r.file_count == 1 { "1 file".to_string() } else { format!("{} files", r.file_count) };
+            format!(r#"<span class="badge">Size: {} &middot; {}</span>"#, format_bytes(r.size_bytes), files)
+        })
+        .unwrap_or_default();
+
+    let download_button = if full_access && record.as_ref().is_some_and(|r| r.info_bencode_base64.is_some()) {
+        format!(
+            r#"<a href="/t/{}/download.torrent" class="btn btn-ghost">Download .torrent</a>"#,
+            html_escape(&info_hash)
+        )
+    } else {
+        String::new()
+    };
+
+    let history = record
+        .as_ref()
+        .map(|r| seeder_sparkline(&crate::storage::seeder_history(&state.db, &r.info_hash_hex).unwrap_or_default()))
+        .filter(|graph| !graph.is_empty())
+        .map(|graph| {
+            format!(
+                r##"
+                <div style="margin-top: 24px;">
+                    <h3 style="font-size: 15px; font-weight: 600; margin-bottom: 12px; color: var(--text-main);">Seeder History</h3>
+                    {graph}
+                </div>
+                "##
+            )
+        })
+        .unwrap_or_default();
+
+    let pinned = record.as_ref().is_some_and(|r| r.pinned);
+    let pinned_badge = if pinned { r#"<span class="badge">Pinned</span>"# } else { "" };
+    let source_badge = record
+        .as_ref()
+        .and_then(|r| r.discovered_via)
+        .map(|via| {
+            format!(
+                r#"<a href="/search?q={}" class="badge">Found via {}</a>"#,
+                url_encode(&format!("source:{}", via.as_str())),
+                html_escape(via.label())
+            )
+        })
+        .unwrap_or_default();
+    let release_badges = record
+        .as_ref()
+        .and_then(|r| r.release.as_ref())
+        .map(|release| release.labels())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(label, filter)| {
+            format!(r#"<a href="/search?q={}" class="badge">{}</a>"#, url_encode(&filter), html_escape(&label))
+        });
+    let tag_badges: String = record
+        .as_ref()
+        .map(|r| r.tags.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|tag| {
+            format!(
+                r#"<a href="/search?q={}" class="badge">#{}</a>"#,
+                url_encode(&format!("tag:{tag}")),
+                html_escape(tag)
+            )
+        })
+        .chain(release_badges)
+        .collect();
+    let note_section = record
+        .as_ref()
+        .and_then(|r| r.note.as_deref())
+        .map(|note| {
+            format!(
+                r##"
+                <div style="margin-top: 24px;">
+                    <h3 style="font-size: 15px; font-weight: 600; margin-bottom: 12px; color: var(--text-main);">Note</h3>
+                    <p class="muted" style="white-space: pre-wrap;">{}</p>
+                </div>
+                "##,
+                html_escape(note)
+            )
+        })
+        .unwrap_or_default();
+
+    let delete_section = if full_access && record.is_some() {
+        let pin_form = if pinned {
+            format!(
+                r#"<form method="post" action="/t/{}/pin?pinned=0"><button type="submit" class="btn btn-ghost">Unpin</button></form>"#,
+                html_escape(&info_hash)
+            )
+        } else {
+            format!(
+                r#"<form method="post" action="/t/{}/pin"><button type="submit" class="btn btn-ghost" title="Keep through cleanup">Pin</button></form>"#,
+                html_escape(&info_hash)
+            )
+        };
+        format!(
+            r##"
+            {pin_form}
+            <form method="post" action="/t/{hash}/delete" onsubmit="return confirm('Delete this torrent?')">
+                <button type="submit" class="btn btn-ghost">Delete</button>
+            </form>
+            <form method="post" action="/t/{hash}/delete?blacklist=1" onsubmit="return confirm('Delete and never store this torrent again?')">
+                <button type="submit" class="btn btn-ghost">Delete &amp; blacklist</button>
+            </form>
+            "##,
+            hash = html_escape(&info_hash)
+        )
+    } else {
+        String::new()
+    };
+
+    let magnet_section = if !full_access && record.is_some() {
+        format!(
+            r#"<p class="muted"><a href="/login?next={}">Log in</a> to get the magnet link.</p>"#,
+            url_encode(&format!("/t/{info_hash}"))
+        )
+    } else if magnet.is_empty() {
+        String::new()
+    } else {
+        format!(
+            r##"
+            <div class="magnet-box">
+                <div class="flex" style="padding: 0 12px; color: var(--snake-orange);">{}</div>
+                <input type="text" value="{}" readonly onclick="this.select()" />
+                <button class="btn btn-ghost" data-copy="{}">Copy</button>
+                <a href="{}" class="btn btn-primary">Open</a>
+            </div>
+            "##,
+            ICON_MAGNET,
+            html_escape(&magnet),
+            html_escape(&magnet),
+            html_escape(&magnet)
+        )
+    };
+
+    page(
+        &title,
+        format!(
+            r##"
+            <main class="detail-card">
                 <div class="detail-header">
                     <div style="color: var(--snake-green); font-size: 13px; font-weight: 700; text-transform: uppercase; letter-spacing: 0.05em; margin-bottom: 8px;">Torrent Detail</div>
                     <h1 class="detail-title">{}</h1>
                     <div class="flex gap-4">
                         <span class="badge">Seeders: {}</span>
+                        <span class="badge">Leechers: {}</span>
+                        {}
+                        {}
+                        {}
+                        {}
                         <span class="mono muted">{}</span>
+                        {}
+                    </div>
+                </div>
+                {}
+                {}
+                <div style="margin-top: 24px;">
+                    <h3 style="font-size: 15px; font-weight: 600; margin-bottom: 12px; color: var(--text-main);">Magnet Link</h3>
+                    {}
+                </div>
+
+                <div class="flex gap-2" style="margin-top: 40px;">
+                    <a href="/search" class="btn btn-ghost" style="display:inline-flex;">&larr; Back to Search</a>
+                    {}
+                    {}
+                </div>
+            </main>
+            "##,
+            html_escape(&title),
+            seeders,
+            leechers,
+            size,
+            pinned_badge,
+            source_badge,
+            tag_badges,
+            html_escape(&info_hash),
+            info_hash_v2,
+            note_section,
+            history,
+            magnet_section,
+            download_button,
+            delete_section
+        ),
+    )
+}
+
+/// Seeder samples of one torrent, oldest first, with the trend cleanup sees.
+async fn seeder_history_api(State(state): State<AppState>, Path(info_hash): Path<String>) -> impl IntoResponse {
+    let result = crate::storage::resolve_info_hash(&state.db, &info_hash).and_then(|hash| match hash {
+        Some(hash) if crate::storage::get(&state.db, &hash)?.is_some() => {
+            crate::storage::seeder_history(&state.db, &hash).map(|samples| Some((hash, samples)))
+        }
+        _ => Ok(None),
+    });
+    match result {
+        Ok(Some((hash, samples))) => Json(serde_json::json!({
+            "info_hash": hash,
+            "trend": crate::storage::SeederTrend::of(&samples),
+            "samples": samples,
+        }))
+        .into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "no such torrent" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+/// The stored metadata as a .torrent file (see `ingest::torrent_file`). 404 until
+/// enrichment has fetched the info dict.
+async fn torrent_download(
+    State(state): State<AppState>,
+    Path(info_hash): Path<String>,
+) -> impl IntoResponse {
+    let record = crate::storage::resolve_info_hash(&state.db, &info_hash)
+        .ok()
+        .flatten()
+        .and_then(|hash| crate::storage::get(&state.db, &hash).ok().flatten());
+    let Some((record, file)) = record.and_then(|r| crate::ingest::torrent_file(&r).map(|f| (r, f))) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "no metadata stored for this torrent" })),
+        )
+            .into_response();
+    };
+
+    // Plain ASCII fallback name for old clients, the real title via RFC 5987.
+    let name = record.title.clone().unwrap_or_else(|| record.info_hash_hex.clone());
+    let ascii: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || " .-_()[]".contains(c) { c } else { '_' })
+        .collect();
+    let disposition = format!(
+        "attachment; filename=\"{ascii}.torrent\"; filename*=UTF-8''{}.torrent",
+        url_encode(&name)
+    );
+    (
+        [
+            (axum::http::header::CONTENT_TYPE, "application/x-bittorrent".to_string()),
+            (axum::http::header::CONTENT_DISPOSITION, disposition),
+        ],
+        file,
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+struct DeleteTorrentParams {
+    /// `1`/`true`: also blacklist the hash so it is never stored again.
+    blacklist: Option<String>,
+}
+
+impl DeleteTorrentParams {
+    fn blacklist(&self) -> bool {
+        matches!(self.blacklist.as_deref(), Some("1" | "true" | "yes"))
+    }
+}
+
+#[derive(Serialize)]
+struct DeletedTorrent {
+    info_hash: String,
+    /// Whether a stored record was removed.
+    deleted: bool,
+    blacklisted: bool,
+}
+
+/// Removes a torrent from sled and the index, optionally blacklisting it.
+async fn delete_torrent(state: &AppState, info_hash: &str, blacklist: bool) -> anyhow::Result<Option<DeletedTorrent>> {
+    let Some(hash) = crate::storage::resolve_info_hash(&state.db, info_hash)? else {
+        return Ok(None);
+    };
+    let db = state.db.clone();
+    let stored = hash.clone();
+    let deleted = tokio::task::spawn_blocking(move || {
+        if blacklist {
+            crate::storage::blacklist(&db, &stored)
+        } else {
+            let existed = crate::storage::get(&db, &stored)?.is_some();
+            if existed {
+                crate::storage::delete(&db, &stored)?;
+            }
+            Ok(existed)
+        }
+    })
+    .await??;
+    if !deleted && !blacklist {
+        return Ok(None);
+    }
+
+    state.index.delete(&hash)?;
+    state.index.commit()?;
+    tracing::info!(info_hash = %hash, deleted, blacklist, "web: deleted torrent");
+    Ok(Some(DeletedTorrent {
+        info_hash: hash,
+        deleted,
+        blacklisted: blacklist,
+    }))
+}
+
+async fn torrent_delete_api(
+    State(state): State<AppState>,
+    Path(info_hash): Path<String>,
+    Query(params): Query<DeleteTorrentParams>,
+) -> impl IntoResponse {
+    match delete_torrent(&state, &info_hash, params.blacklist()).await {
+        Ok(Some(deleted)) => Json(deleted).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "no such torrent" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn torrent_delete_form(
+    State(state): State<AppState>,
+    Path(info_hash): Path<String>,
+    Query(params): Query<DeleteTorrentParams>,
+) -> impl IntoResponse {
+    if let Err(err) = delete_torrent(&state, &info_hash, params.blacklist()).await {
+        tracing::warn!(%err, %info_hash, "web: delete failed");
+    }
+    Redirect::to("/search")
+}
+
+/// Body of `PATCH /api/torrents/:info_hash`; fields left out stay as they are.
+#[derive(Deserialize)]
+struct TorrentPatch {
+    tags: Option<Vec<String>>,
+    /// An empty note removes it.
+    note: Option<String>,
+}
+
+async fn torrent_patch_api(
+    State(state): State<AppState>,
+    Path(info_hash): Path<String>,
+    Json(patch): Json<TorrentPatch>,
+) -> impl IntoResponse {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": error }))).into_response();
+    let tags = match patch.tags.as_deref().map(crate::storage::normalize_tags).transpose() {
+        Ok(tags) => tags,
+        Err(error) => return bad_request(error),
+    };
+    let note = patch.note.map(|note| note.trim().to_string());
+    if note.as_ref().is_some_and(|note| note.chars().count() > crate::storage::MAX_NOTE_CHARS) {
+        return bad_request(format!("note is longer than {} characters", crate::storage::MAX_NOTE_CHARS));
+    }
+
+    let result = match crate::storage::resolve_info_hash(&state.db, &info_hash) {
+        Ok(Some(hash)) => {
+            let db = state.db.clone();
+            tokio::task::spawn_blocking(move || crate::storage::set_annotations(&db, &hash, tags, note))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|r| r)
+        }
+        Ok(None) => Ok(None),
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(Some(record)) => {
+            // Tags are matched in the index, so refresh it now.
+            if record.title.is_some() || record.seeders >= 2 {
+                let _ = state.index.upsert_record(&record);
+                let _ = state.index.commit();
+            }
+            tracing::info!(info_hash = %record.info_hash_hex, tags = record.tags.len(), "web: torrent annotated");
+            Json(serde_json::json!({
+                "info_hash": record.info_hash_hex,
+                "tags": record.tags,
+                "note": record.note,
+            }))
+            .into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "no such torrent" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+async fn set_pinned(state: &AppState, info_hash: &str, pinned: bool) -> anyhow::Result<Option<String>> {
+    let Some(hash) = crate::storage::resolve_info_hash(&state.db, info_hash)? else {
+        return Ok(None);
+    };
+    let db = state.db.clone();
+    let stored = hash.clone();
+    let record = tokio::task::spawn_blocking(move || crate::storage::set_pinned(&db, &stored, pinned)).await??;
+    if record.is_some() {
+        tracing::info!(info_hash = %hash, pinned, "web: pin changed");
+    }
+    Ok(record.map(|_| hash))
+}
+
+async fn pin_response(state: &AppState, info_hash: &str, pinned: bool) -> axum::response::Response {
+    match set_pinned(state, info_hash, pinned).await {
+        Ok(Some(hash)) => Json(serde_json::json!({ "info_hash": hash, "pinned": pinned })).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "no such torrent" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+async fn pin_api(State(state): State<AppState>, Path(info_hash): Path<String>) -> impl IntoResponse {
+    pin_response(&state, &info_hash, true).await
+}
+
+async fn unpin_api(State(state): State<AppState>, Path(info_hash): Path<String>) -> impl IntoResponse {
+    pin_response(&state, &info_hash, false).await
+}
+
+#[derive(Deserialize)]
+struct PinParams {
+    /// `0`/`false`: unpin instead.
+    pinned: Option<String>,
+}
+
+async fn pin_form(
+    State(state): State<AppState>,
+    Path(info_hash): Path<String>,
+    Query(params): Query<PinParams>,
+) -> impl IntoResponse {
+    let pinned = !matches!(params.pinned.as_deref(), Some("0" | "false" | "no"));
+    if let Err(err) = set_pinned(&state, &info_hash, pinned).await {
+        tracing::warn!(%err, %info_hash, "web: pin failed");
+    }
+    Redirect::to(&format!("/t/{}", url_encode(&info_hash)))
+}
+
+async fn blacklist_api(State(state): State<AppState>) -> impl IntoResponse {
+    match crate::storage::list_blacklist(&state.db) {
+        Ok(entries) => Json(entries).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+/// Archived results per page unless the request asks for fewer or more.
+const ARCHIVE_DEFAULT_LIMIT: usize = 50;
+const ARCHIVE_MAX_LIMIT: usize = 500;
+
+#[derive(Deserialize)]
+struct ArchiveParams {
+    #[serde(default)]
+    q: String,
+    limit: Option<usize>,
+}
+
+async fn archive_api(State(state): State<AppState>, Query(params): Query<ArchiveParams>) -> impl IntoResponse {
+    let limit = params.limit.unwrap_or(ARCHIVE_DEFAULT_LIMIT).clamp(1, ARCHIVE_MAX_LIMIT);
+    let db = state.db.clone();
+    // Walks the whole archive for text queries.
+    let result = tokio::task::spawn_blocking(move || crate::storage::search_archive(&db, &params.q, limit))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+    match result {
+        Ok(torrents) => Json(torrents).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct RestoreParams {
+    /// `1`/`true`: pin the restored torrent so cleanup keeps it.
+    pin: Option<String>,
+}
+
+async fn archive_restore_api(
+    State(state): State<AppState>,
+    Path(info_hash): Path<String>,
+    Query(params): Query<RestoreParams>,
+) -> impl IntoResponse {
+    let hash = info_hash.trim().to_ascii_lowercase();
+    let pin = matches!(params.pin.as_deref(), Some("1" | "true" | "yes"));
+    if crate::storage::is_blacklisted(&state.db, &hash).unwrap_or(false) {
+        return (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({ "error": "hash is blacklisted" })),
+        )
+            .into_response();
+    }
+    let result = crate::storage::restore_archived(&state.db, &hash).and_then(|record| match record {
+        Some(_) if pin => crate::storage::set_pinned(&state.db, &hash, true),
+        other => Ok(other),
+    });
+    match result {
+        Ok(Some(record)) => {
+            if record.title.is_some() {
+                let _ = state.index.upsert_record(&record);
+                let _ = state.index.commit();
+            }
+            tracing::info!(info_hash = %hash, pin, "web: restored archived torrent");
+            Json(serde_json::json!({ "info_hash": hash, "restored": true, "pinned": record.pinned })).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "not in the archive" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+/// Lifts a blacklist entry. The torrent itself stays deleted until something rediscovers it.
+async fn unblacklist_api(State(state): State<AppState>, Path(info_hash): Path<String>) -> impl IntoResponse {
+    let result = crate::storage::resolve_info_hash(&state.db, &info_hash).and_then(|hash| match hash {
+        Some(hash) => crate::storage::unblacklist(&state.db, &hash),
+        None => Ok(false),
+    });
+    match result {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "hash is not blacklisted" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+fn parse_control_action(subsystem: &str, action: &str) -> Option<(Subsystem, bool)> {
+    let subsystem = Subsystem::parse(subsystem)?;
+    let paused = match action {
+        "pause" => true,
+        "resume" => false,
+        _ => return None,
+    };
+    Some((subsystem, paused))
+}
+
+async fn control_api(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.control.snapshot(&state.config))
+}
+
+async fn trackers_api(State(state): State<AppState>) -> impl IntoResponse {
+    Json(serde_json::json!({ "trackers": state.trackers.health() }))
+}
+
+#[derive(Deserialize)]
+struct CleanupHistoryParams {
+    limit: Option<usize>,
+}
+
+async fn cleanup_history_api(
+    State(state): State<AppState>,
+    Query(params): Query<CleanupHistoryParams>,
+) -> impl IntoResponse {
+    let limit = params.limit.unwrap_or(100).clamp(1, crate::cleanup::HISTORY_KEEP);
+    match crate::storage::cleanup_history::<crate::stats::CleanupSweep>(&state.db, limit) {
+        Ok(sweeps) => Json(serde_json::json!({
+            "dry_run": state.config.cleanup_dry_run,
+            "sweeps": sweeps,
+        }))
+        .into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+async fn control_set_api(
+    State(state): State<AppState>,
+    Path((subsystem, action)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let Some((subsystem, paused)) = parse_control_action(&subsystem, &action) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "unknown subsystem or action" })),
+        )
+            .into_response();
+    };
+    state.control.set_paused(subsystem, paused);
+    Json(state.control.status(&state.config, subsystem)).into_response()
+}
+
+async fn settings_api(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.settings.get())
+}
+
+async fn settings_patch_api(
+    State(state): State<AppState>,
+    Json(patch): Json<crate::settings::Patch>,
+) -> impl IntoResponse {
+    match state.settings.apply(patch) {
+        Ok(runtime) => Json(runtime).into_response(),
+        Err(errors) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "errors": errors }))).into_response(),
+    }
+}
+
+/// Starts an index rebuild in the background. Returns false if one is already running.
+fn start_reindex(state: &AppState) -> bool {
+    if state.index.is_reindexing() {
+        return false;
+    }
+    let index = state.index.clone();
+    let db = state.db.clone();
+    tokio::task::spawn_blocking(move || {
+        tracing::info!("index: reindex started");
+        let started = std::time::Instant::now();
+        match index.reindex(&db) {
+            Ok(Some(indexed)) => {
+                tracing::info!(indexed, secs = started.elapsed().as_secs(), "index: reindex finished")
+            }
+            Ok(None) => {}
+            Err(err) => tracing::warn!(err = %format!("{err:#}"), "index: reindex failed"),
+        }
+    });
+    true
+}
+
+async fn reindex_api(State(state): State<AppState>) -> impl IntoResponse {
+    if start_reindex(&state) {
+        (StatusCode::ACCEPTED, Json(serde_json::json!({ "started": true }))).into_response()
+    } else {
+        (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({ "error": "a reindex is already running" })),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct BackupParams {
+    /// `1`/`true`: stream the archive back instead of writing it to `SERMA_BACKUP_DIR`.
+    download: Option<String>,
+}
+
+/// Snapshots the database and search index into one .tar.zst (see `backup.rs`).
+async fn backup_api(State(state): State<AppState>, Query(params): Query<BackupParams>) -> impl IntoResponse {
+    let Some(running) = crate::backup::try_start() else {
+        return (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({ "error": "a backup is already running" })),
+        )
+            .into_response();
+    };
+
+    if matches!(params.download.as_deref(), Some("1" | "true" | "yes")) {
+        let disposition = format!("attachment; filename=\"{}\"", crate::backup::file_name());
+        let body = crate::export::stream_body("backup", move |writer| {
+            let _running = running;
+            let done = crate::backup::write(&state, writer)?;
+            tracing::info!(entries = done.entries, index_docs = done.index_docs, "backup: streamed");
+            Ok(())
+        });
+        return (
+            [
+                (axum::http::header::CONTENT_TYPE, "application/zstd".to_string()),
+                (axum::http::header::CONTENT_DISPOSITION, disposition),
+            ],
+            body,
+        )
+            .into_response();
+    }
+
+    let dir = state.config.backup_dir.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let _running = running;
+        crate::backup::write_file(&state, &dir)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r);
+    match result {
+        Ok((path, bytes, done)) => {
+            tracing::info!(path = %path.display(), bytes, entries = done.entries, "backup: written");
+            Json(serde_json::json!({
+                "path": path.display().to_string(),
+                "bytes": bytes,
+                "entries": done.entries,
+                "index_docs": done.index_docs,
+            }))
+            .into_response()
+        }
+        Err(err) => {
+            tracing::warn!(err = %format!("{err:#}"), "backup: failed");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": format!("{err:#}") })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Takes a backup archive as the request body, checks it and stages it; the next start
+/// replaces the database and index with it.
+async fn restore_api(State(state): State<AppState>, body: axum::body::Body) -> impl IntoResponse {
+    use futures_util::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let upload = state.data_dir.join("restore.upload");
+    let received = async {
+        let mut file = tokio::fs::File::create(&upload).await?;
+        let mut stream = body.into_data_stream();
+        while let Some(chunk) = stream.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(err) = received {
+        let _ = std::fs::remove_file(&upload);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": format!("upload failed: {err:#}") })),
+        )
+            .into_response();
+    }
+
+    let data_dir = state.data_dir.clone();
+    let staged = tokio::task::spawn_blocking(move || {
+        let result = std::fs::File::open(&upload)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| crate::backup::stage_restore(&data_dir, file));
+        let _ = std::fs::remove_file(&upload);
+        result
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r);
+    match staged {
+        Ok((manifest, summary)) => {
+            tracing::info!(entries = summary.entries, version = %manifest.serma_version, "restore: backup staged for the next start");
+            Json(serde_json::json!({
+                "staged": true,
+                "created_at": manifest.created_at,
+                "serma_version": manifest.serma_version,
+                "entries": summary.entries,
+                "index_docs": summary.index_docs,
+            }))
+            .into_response()
+        }
+        Err(err) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+async fn admin_reindex_form(State(state): State<AppState>) -> impl IntoResponse {
+    start_reindex(&state);
+    Redirect::to("/admin")
+}
+
+async fn admin_control_form(
+    State(state): State<AppState>,
+    Path((subsystem, action)): Path<(String, String)>,
+) -> impl IntoResponse {
+    if let Some((subsystem, paused)) = parse_control_action(&subsystem, &action) {
+        state.control.set_paused(subsystem, paused);
+    }
+    Redirect::to("/admin")
+}
+
+/// Dashboard numbers: storage totals read on demand plus the live task counters.
+#[derive(Serialize)]
+struct AdminStats {
+    records: crate::storage::RecordCounts,
+    indexed_docs: u64,
+    index_bytes: u64,
+    /// `SERMA_SEARCH_TOKENIZER` the index was built with.
+    tokenizer: &'static str,
+    /// `sled` or `sqlite`.
+    storage: &'static str,
+    sled_bytes: u64,
+    compaction: crate::storage::CompactionStatus,
+    /// An index rebuild (`/api/admin/reindex`) is running.
+    reindexing: bool,
+    /// Records waiting for metadata plus lookups in flight.
+    enrich_queue: u64,
+    /// Trackers scraped since start, and those of them skipped for failing.
+    trackers: usize,
+    trackers_backed_off: usize,
+    #[serde(flatten)]
+    live: crate::stats::StatsSnapshot,
+}
+
+async fn admin_stats(state: &AppState) -> anyhow::Result<AdminStats> {
+    let db = state.db.clone();
+    let index_dir = state.data_dir.join("tantivy");
+    // Counting walks every record key; keep it off the async workers.
+    let (records, index_bytes, sled_bytes, compaction) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        Ok((
+            crate::storage::record_counts(&db)?,
+            dir_size(&index_dir),
+            db.size_on_disk()?,
+            crate::storage::compaction_status(&db)?,
+        ))
+    })
+    .await??;
+    let live = state.stats.snapshot();
+    let trackers = state.trackers.health();
+    Ok(AdminStats {
+        records,
+        indexed_docs: state.index.num_docs(),
+        index_bytes,
+        tokenizer: state.config.search_tokenizer.as_str(),
+        storage: state.config.storage_backend.as_str(),
+        sled_bytes,
+        compaction,
+        reindexing: state.index.is_reindexing(),
+        enrich_queue: records.missing_info as u64 + live.enrich_in_flight,
+        trackers_backed_off: trackers.iter().filter(|t| t.backoff_secs > 0).count(),
+        trackers: trackers.len(),
+        live,
+    })
+}
+
+pub fn dir_size(dir: &std::path::Path) -> u64 {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok()?.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Instance totals for `/api/stats`. Everything here is a counter or a directory listing,
+/// so it is cheap enough to poll.
+#[derive(Serialize)]
+struct PublicStats {
+    records: u64,
+    missing_metadata: u64,
+    indexed_docs: u64,
+    sled_bytes: u64,
+    index_bytes: u64,
+    uptime_secs: u64,
+}
+
+async fn stats_api(State(state): State<AppState>) -> impl IntoResponse {
+    let db = state.db.clone();
+    let index_dir = state.data_dir.join("tantivy");
+    let sizes = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        Ok((crate::storage::record_totals(&db)?, db.size_on_disk()?, dir_size(&index_dir)))
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r);
+    match sizes {
+        Ok((totals, sled_bytes, index_bytes)) => Json(PublicStats {
+            records: totals.records,
+            missing_metadata: totals.missing_metadata,
+            indexed_docs: state.index.num_docs(),
+            sled_bytes,
+            index_bytes,
+            uptime_secs: state.stats.uptime_secs(),
+        })
+        .into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+async fn admin_stats_api(State(state): State<AppState>) -> impl IntoResponse {
+    match admin_stats(&state).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Bar chart of hashes discovered per minute, oldest on the left.
+fn discovery_graph(per_minute: &[u64]) -> String {
+    const WIDTH: usize = 600;
+    const HEIGHT: u64 = 80;
+    let max = per_minute.iter().copied().max().unwrap_or(0).max(1);
+    let bar = WIDTH / per_minute.len().max(1);
+    let mut bars = String::new();
+    for (i, count) in per_minute.iter().enumerate() {
+        let h = (count * HEIGHT).div_ceil(max);
+        bars.push_str(&format!(
+            r##"<rect x="{}" y="{}" width="{}" height="{}" fill="var(--snake-green)"><title>{} min ago: {}</title></rect>"##,
+            i * bar,
+            HEIGHT - h,
+            bar.saturating_sub(1).max(1),
+            h,
+            per_minute.len() - 1 - i,
+            count
+        ));
+    }
+    format!(
+        r##"<svg viewBox="0 0 {WIDTH} {HEIGHT}" width="100%" height="{HEIGHT}" preserveAspectRatio="none" role="img" aria-label="Discoveries per minute">{bars}</svg>
+        <p class="muted">Discovered per minute, last {} minutes (peak {})</p>"##,
+        per_minute.len(),
+        max
+    )
+}
+
+/// Seeder history as a sparkline, oldest on the left, with the trend under it. Empty
+/// until there are two samples.
+fn seeder_sparkline(samples: &[crate::storage::SeederSample]) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 60.0;
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return String::new();
+    };
+    if samples.len() < 2 {
+        return String::new();
+    }
+    let span = (last.at_unix_ms - first.at_unix_ms).max(1) as f64;
+    let max = samples.iter().map(|s| s.seeders).max().unwrap_or(0).max(1);
+    let min = samples.iter().map(|s| s.seeders).min().unwrap_or(0);
+    let points: Vec<String> = samples
+        .iter()
+        .map(|s| {
+            let x = (s.at_unix_ms - first.at_unix_ms) as f64 / span * WIDTH;
+            let y = HEIGHT - 2.0 - f64::from(s.seeders) / f64::from(max) * (HEIGHT - 4.0);
+            format!("{x:.1},{y:.1}")
+        })
+        .collect();
+    let trend = match crate::storage::SeederTrend::of(samples) {
+        crate::storage::SeederTrend::Unknown => "too early to tell",
+        crate::storage::SeederTrend::Declining => "declining",
+        crate::storage::SeederTrend::Stable => "stable",
+        crate::storage::SeederTrend::Rising => "rising",
+    };
+    let hours = span / 3_600_000.0;
+    let over = if hours >= 48.0 { format!("{:.0} days", hours / 24.0) } else { format!("{hours:.0} hours") };
+    format!(
+        r##"<svg viewBox="0 0 {WIDTH} {HEIGHT}" width="100%" height="{HEIGHT}" preserveAspectRatio="none" role="img" aria-label="Seeders over time"><polyline points="{}" fill="none" stroke="var(--snake-green)" stroke-width="2" vector-effect="non-scaling-stroke"/></svg>
+        <p class="muted">{} samples over {over}, {min} to {max} seeders &middot; {trend}</p>"##,
+        points.join(" "),
+        samples.len()
+    )
+}
+
+fn stat_row(title: &str, meta: &str) -> String {
+    format!(
+        r##"
+            <li class="list-item">
+                <div class="item-title">{}</div>
+                <div class="item-meta">{}</div>
+            </li>
+            "##,
+        html_escape(title),
+        meta
+    )
+}
+
+fn admin_stats_html(stats: &AdminStats) -> String {
+    let mut rows = String::new();
+    rows.push_str(&stat_row(
+        "Records",
+        &format!(
+            r##"<span class="badge">{} total</span> {} searchable &middot; {} tombstones &middot; {} archived"##,
+            stats.records.total, stats.indexed_docs, stats.records.tombstones, stats.records.archived
+        ),
+    ));
+    let rebuild = if stats.reindexing {
+        r##"<span class="badge">rebuilding</span>"##
+    } else {
+        r##"<form method="post" action="/admin/reindex" style="display:inline">
+                <button type="submit" class="btn btn-ghost">Rebuild from database</button>
+            </form>"##
+    };
+    let purges = &stats.live.index_purges;
+    let purged = if purges.runs > 0 {
+        format!(
+            " &middot; {} deleted documents purged from {} segments",
+            purges.deleted_docs_purged, purges.segments_rewritten
+        )
+    } else {
+        String::new()
+    };
+    rows.push_str(&stat_row(
+        "Index size",
+        &format!("{}{} {}", format_bytes(stats.index_bytes), purged, rebuild),
+    ));
+    let mut compaction = match stats.compaction.due_bytes {
+        Some(due) => format!(
+            r##"<span class="badge">compaction due</span> about {} to reclaim on next start"##,
+            format_bytes(due)
+        ),
+        None => match stats.compaction.live_bytes {
+            Some(live) => format!("{} live data", format_bytes(live)),
+            None => "not checked yet".to_string(),
+        },
+    };
+    if let Some(last) = &stats.compaction.last {
+        compaction.push_str(&format!(
+            " &middot; last compaction freed {} ({} in total)",
+            format_bytes(last.reclaimed_bytes),
+            format_bytes(stats.compaction.reclaimed_bytes_total)
+        ));
+    }
+    rows.push_str(&stat_row(
+        "Database size",
+        &format!("{} ({}) &middot; {}", format_bytes(stats.sled_bytes), stats.storage, compaction),
+    ));
+    let throttle = &stats.live.discovery_throttle;
+    let throttled = if throttle.rate < 1.0 {
+        format!(
+            r##" &middot; <span class="badge">throttled to {:.0}%</span> ({} queued, database +{}/min)"##,
+            throttle.rate * 100.0,
+            throttle.enrich_queue,
+            format_bytes(throttle.disk_growth_bytes_per_min)
+        )
+    } else {
+        String::new()
+    };
+    rows.push_str(&stat_row(
+        "Discovery",
+        &format!(
+            "{} hashes since start ({} min uptime){}{}",
+            stats.live.discovered,
+            stats.live.uptime_secs / 60,
+            throttled,
+            discovery_graph(&stats.live.discovered_per_minute)
+        ),
+    ));
+    rows.push_str(&stat_row(
+        "Enrich",
+        &format!(
+            r##"<span class="badge">{} queued</span> {} in flight ({} DHT lookups, {} waiting; {} metadata fetches, {} waiting) &middot; {} fetched &middot; {} without metadata &middot; {} waiting to retry &middot; {} parked"##,
+            stats.enrich_queue,
+            stats.live.enrich_in_flight,
+            stats.live.enrich_lookups.running,
+            stats.live.enrich_lookups.waiting,
+            stats.live.enrich_fetches.running,
+            stats.live.enrich_fetches.waiting,
+            stats.live.enrich_fetched,
+            stats.live.enrich_missed,
+            stats.records.enrich_backoff,
+            stats.records.enrich_parked
+        ),
+    ));
+    rows.push_str(&stat_row(
+        "Trackers",
+        &format!(
+            r##"{} scraped since start &middot; {} backed off after failing (<a href="/api/admin/trackers">details</a>)"##,
+            stats.trackers, stats.trackers_backed_off
+        ),
+    ));
+    let last_sweep = match &stats.live.cleanup_last {
+        Some(sweep) if sweep.dry_run => format!(
+            "dry run: last sweep scanned {}, would remove {} (TTL {}, low seed {}, over cap {})",
+            sweep.scanned,
+            sweep.candidates.total(),
+            sweep.candidates.ttl,
+            sweep.candidates.low_seed,
+            sweep.candidates.max_records
+        ),
+        Some(sweep) => format!(
+            "last sweep scanned {}, deleted {}, kept {} with steady seeders, pruned {} tombstones",
+            sweep.scanned, sweep.deleted, sweep.spared_by_trend, sweep.tombstones_pruned
+        ),
+        None => "no sweep yet".to_string(),
+    };
+    rows.push_str(&stat_row(
+        "Cleanup",
+        &format!(
+            "{} sweeps, {} records deleted &middot; {}",
+            stats.live.cleanup_sweeps, stats.live.cleanup_deleted, last_sweep
+        ),
+    ));
+    let socks5 = &stats.live.socks5;
+    let socks5_meta = if !socks5.configured {
+        "not configured".to_string()
+    } else if socks5.associated {
+        format!(
+            r##"<span class="badge">associated</span> {} via relay {}"##,
+            html_escape(socks5.proxy.as_deref().unwrap_or_default()),
+            html_escape(socks5.relay.as_deref().unwrap_or_default())
+        )
+    } else {
+        format!(
+            "down: {} ({})",
+            html_escape(socks5.proxy.as_deref().unwrap_or_default()),
+            html_escape(socks5.error.as_deref().unwrap_or_default())
+        )
+    };
+    rows.push_str(&stat_row("SOCKS5", &socks5_meta));
+    rows
+}
+
+async fn admin_page(State(state): State<AppState>) -> impl IntoResponse {
+    let stats_rows = match admin_stats(&state).await {
+        Ok(stats) => admin_stats_html(&stats),
+        Err(err) => stat_row("Stats unavailable", &html_escape(&format!("{err:#}"))),
+    };
+
+    let logout = if state.config.api_token.is_some() {
+        r##"<form method="post" action="/logout"><button type="submit" class="btn btn-ghost">Log out</button></form>"##
+    } else {
+        ""
+    };
+
+    let mut rows = String::new();
+    for status in state.control.snapshot(&state.config) {
+        let (label, action, button) = if !status.enabled {
+            ("Disabled", "", String::new())
+        } else if status.paused {
+            ("Paused", "resume", "Resume".to_string())
+        } else {
+            ("Running", "pause", "Pause".to_string())
+        };
+
+        let control = if action.is_empty() {
+            r##"<span class="muted">set at startup</span>"##.to_string()
+        } else {
+            format!(
+                r##"<form method="post" action="/admin/control/{}/{}">
+                        <button type="submit" class="btn btn-ghost">{}</button>
+                    </form>"##,
+                status.name, action, button
+            )
+        };
+
+        rows.push_str(&format!(
+            r##"
+            <li class="list-item">
+                <div class="item-header">
+                    <div>
+                        <div class="item-title">{}</div>
+                        <div class="item-meta"><span class="badge">{}</span></div>
                     </div>
+                    <div class="flex gap-2">{}</div>
                 </div>
-                
-                <div style="margin-top: 24px;">
-                    <h3 style="font-size: 15px; font-weight: 600; margin-bottom: 12px; color: var(--text-main);">Magnet Link</h3>
-                    {}
-                </div>
+            </li>
+            "##,
+            html_escape(status.name),
+            label,
+            control
+        ));
+    }
 
-                <div style="margin-top: 40px;">
-                    <a href="/search" class="btn btn-ghost" style="display:inline-flex;">&larr; Back to Search</a>
+    page(
+        "Admin",
+        format!(
+            r##"
+            <main class="detail-card">
+                <div class="detail-header">
+                    <h1 class="detail-title">Status</h1>
+                    <p class="muted">Refreshes every 10 seconds. Also available as JSON at <code>/api/admin/stats</code>.</p>
+                    {}
                 </div>
+                <ul class="results-list">{}</ul>
             </main>
+            <section class="detail-card">
+                <div class="detail-header">
+                    <h1 class="detail-title">Subsystems</h1>
+                    <p class="muted">Pause or resume background tasks without restarting Serma.</p>
+                </div>
+                <ul class="results-list">{}</ul>
+            </section>
+            <script>setTimeout(() => location.reload(), 10000);</script>
             "##,
-            html_escape(&title),
-            seeders,
-            html_escape(&info_hash),
-            magnet_section
+            logout, stats_rows, rows
         ),
     )
 }
@@ -750,4 +2785,555 @@ fn url_encode(s: &str) -> String {
         }
     }
     out
-}
\ No newline at end of file
+}
+// Whole multipart request; large multi-file torrents can be several MB each.
+const UPLOAD_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Serialize)]
+struct UploadedTorrent {
+    file: Option<String>,
+    info_hash: String,
+    title: Option<String>,
+    magnet: Option<String>,
+    seeders: i64,
+    /// False if the torrent was already known (its metadata is refreshed).
+    created: bool,
+}
+
+#[derive(Serialize)]
+struct UploadResponse {
+    records: Vec<UploadedTorrent>,
+    summary: crate::ingest::IngestSummary,
+}
+
+async fn upload_api(State(state): State<AppState>, mut multipart: Multipart) -> impl IntoResponse {
+    let mut response = UploadResponse {
+        records: Vec::new(),
+        summary: crate::ingest::IngestSummary::default(),
+    };
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({ "error": format!("invalid multipart body: {err}") })),
+                )
+                    .into_response();
+            }
+        };
+
+        let file = field.file_name().map(str::to_string);
+        let name = file.clone().unwrap_or_default();
+        response.summary.lines += 1;
+        let bytes = match field.bytes().await {
+            Ok(b) => b,
+            Err(err) => {
+                response.summary.reject_invalid(0, &name, format!("read failed: {err}"));
+                continue;
+            }
+        };
+
+        match crate::ingest::ingest_torrent_bytes(&state, &bytes, Discovery::Api) {
+            Ok((record, created)) => {
+                if created {
+                    response.summary.accepted += 1;
+                } else {
+                    response.summary.existing += 1;
+                }
+                response.records.push(UploadedTorrent {
+                    file,
+                    info_hash: record.info_hash_hex,
+                    title: record.title,
+                    magnet: record.magnet,
+                    seeders: record.seeders,
+                    created,
+                });
+            }
+            Err(err) => response.summary.reject_error(0, &name, &err),
+        }
+    }
+    response.summary.log("upload");
+
+    if !response.records.is_empty()
+        && let Err(err) = state.index.commit()
+    {
+        tracing::warn!(%err, "upload: index commit failed");
+    }
+
+    let status = if response.records.is_empty() {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(response)).into_response()
+}
+
+/// Raw-body variant of `/api/upload` for scripts: the request body is one .torrent file.
+async fn torrent_raw_api(State(state): State<AppState>, body: bytes::Bytes) -> impl IntoResponse {
+    if body.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "empty body; send the .torrent file as the request body" })),
+        )
+            .into_response();
+    }
+
+    let result = crate::ingest::ingest_torrent_bytes(&state, &body, Discovery::Api);
+    if result.is_ok()
+        && let Err(err) = state.index.commit()
+    {
+        tracing::warn!(%err, "upload: index commit failed");
+    }
+    match result {
+        Ok((record, created)) => {
+            tracing::info!(info_hash = %record.info_hash_hex, created, "upload: stored torrent");
+            let status = if created { StatusCode::CREATED } else { StatusCode::OK };
+            (
+                status,
+                Json(UploadedTorrent {
+                    file: None,
+                    info_hash: record.info_hash_hex,
+                    title: record.title,
+                    magnet: record.magnet,
+                    seeders: record.seeders,
+                    created,
+                }),
+            )
+                .into_response()
+        }
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+// Roughly 200k magnet links or 400k bare hashes per request.
+const INGEST_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+/// `POST /api/ingest` body: a JSON array of hashes/magnets, or an object with both lists.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IngestRequest {
+    List(Vec<String>),
+    Lists {
+        #[serde(default)]
+        hashes: Vec<String>,
+        #[serde(default)]
+        magnets: Vec<String>,
+    },
+}
+
+/// Pushes info hashes / magnet links into a running instance, same as `serma ingest`.
+/// JSON bodies are an array or `{"hashes": [...], "magnets": [...]}`; any other content
+/// type is read as one hash or magnet per line.
+async fn ingest_api(State(state): State<AppState>, headers: HeaderMap, body: bytes::Bytes) -> impl IntoResponse {
+    let is_json = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/json"));
+
+    let entries: Vec<String> = if is_json {
+        match serde_json::from_slice::<IngestRequest>(&body) {
+            Ok(IngestRequest::List(list)) => list,
+            Ok(IngestRequest::Lists { hashes, magnets }) => hashes.into_iter().chain(magnets).collect(),
+            Err(err) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(serde_json::json!({ "error": format!("invalid JSON body: {err}") })),
+                )
+                    .into_response();
+            }
+        }
+    } else {
+        String::from_utf8_lossy(&body)
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    };
+    if entries.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "no hashes or magnet links in request" })),
+        )
+            .into_response();
+    }
+
+    let mut summary = crate::ingest::IngestSummary::default();
+    let mut items = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.iter().enumerate() {
+        summary.lines += 1;
+        match crate::ingest::parse_line(entry.trim()) {
+            Ok(item) => items.push(item),
+            Err(reason) => summary.reject_invalid(i + 1, entry, reason),
+        }
+    }
+
+    for batch in items.chunks(crate::ingest::INGEST_BATCH) {
+        if let Err(err) = crate::ingest::ingest_items(&state, batch, Discovery::Api, &mut summary) {
+            tracing::warn!(%err, "ingest: http batch failed");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "storing hashes failed", "summary": summary })),
+            )
+                .into_response();
+        }
+    }
+    summary.log("http");
+    Json(summary).into_response()
+}
+
+const EXPORT_PAGE_SIZE: usize = 500;
+const EXPORT_MAX_LIMIT: usize = 5000;
+
+#[derive(Deserialize)]
+struct ExportParams {
+    /// Unix ms; records last seen at or after this time are returned.
+    since: Option<i64>,
+    /// Hash of the last record of the previous page (from `next_after`).
+    after: Option<String>,
+    limit: Option<usize>,
+    /// `jsonl` or `jsonl.gz`: stream the whole database instead of a page.
+    format: Option<String>,
+}
+
+/// Export and sync requests from other instances authenticate with SERMA_EXPORT_TOKEN;
+/// without it both are disabled. Returns the error response for a refused request.
+fn export_token_denied(state: &AppState, headers: &HeaderMap) -> Option<axum::response::Response> {
+    let Some(token) = state.config.export_token.as_deref() else {
+        return Some((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "error": "export and sync are disabled (SERMA_EXPORT_TOKEN is not set)" })),
+        )
+            .into_response());
+    };
+    let presented = bearer_token(headers).unwrap_or_default();
+    if !constant_time_eq(presented.as_bytes(), token.as_bytes()) {
+        return Some((
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({ "error": "missing or invalid bearer token" })),
+        )
+            .into_response());
+    }
+    None
+}
+
+/// Largest `POST /api/sync` body: a page of records with their info dicts.
+const SYNC_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Records pushed by another instance (its SERMA_SYNC_PUSH_TO), merged like pulled ones.
+async fn sync_push_api(State(state): State<AppState>, headers: HeaderMap, body: axum::body::Bytes) -> impl IntoResponse {
+    if let Some(denied) = export_token_denied(&state, &headers) {
+        return denied;
+    }
+    let push: crate::sync::SyncPush = match serde_json::from_slice(&body) {
+        Ok(push) => push,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": format!("invalid JSON body: {err}") })),
+            )
+                .into_response();
+        }
+    };
+    let merged = tokio::task::spawn_blocking(move || {
+        let mut summary = crate::ingest::IngestSummary::default();
+        crate::sync::merge_page(&state, push.records, &mut summary)?;
+        anyhow::Ok(summary)
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|r| r);
+    match merged {
+        Ok(summary) => {
+            if summary.accepted > 0 {
+                summary.log("sync push");
+            }
+            Json(summary).into_response()
+        }
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+/// One page of the export feed. Pass `next_since` / `next_after` back to continue.
+#[derive(Serialize, Deserialize)]
+pub struct ExportPage {
+    pub records: Vec<crate::storage::TorrentRecord>,
+    pub next_since: i64,
+    pub next_after: Option<String>,
+    /// True if the page was full, i.e. there may be more records right away.
+    pub more: bool,
+}
+
+/// Records seen since a cursor, for instance-to-instance sync (`SERMA_SYNC_FROM`), or with
+/// `format=jsonl[.gz]` the whole database as a download (see `export`).
+/// Disabled unless SERMA_EXPORT_TOKEN is set; requests must send it as a bearer token.
+async fn export_api(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<ExportParams>,
+) -> impl IntoResponse {
+    if let Some(denied) = export_token_denied(&state, &headers) {
+        return denied;
+    }
+
+    match params.format.as_deref() {
+        None => {}
+        Some(format @ ("jsonl" | "jsonl.gz")) => {
+            let gzip = format == "jsonl.gz";
+            let content_type = if gzip { "application/gzip" } else { "application/x-ndjson" };
+            let disposition = format!("attachment; filename=\"serma-export.{format}\"");
+            return (
+                [
+                    (axum::http::header::CONTENT_TYPE, content_type.to_string()),
+                    (axum::http::header::CONTENT_DISPOSITION, disposition),
+                ],
+                crate::export::body(state.db.clone(), gzip),
+            )
+                .into_response();
+        }
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({ "error": "invalid format; expected jsonl or jsonl.gz" })),
+            )
+                .into_response();
+        }
+    }
+
+    let since = params.since.unwrap_or(0).max(0);
+    let limit = params
+        .limit
+        .unwrap_or(EXPORT_PAGE_SIZE)
+        .clamp(1, EXPORT_MAX_LIMIT);
+    let records = match crate::storage::list_seen_since(&state.db, since, params.after.as_deref(), limit) {
+        Ok(records) => records,
+        Err(err) => {
+            tracing::warn!(%err, "export: listing records failed");
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": "listing records failed" })),
+            )
+                .into_response();
+        }
+    };
+
+    let (next_since, next_after) = match records.last() {
+        Some(last) => (last.last_seen_unix_ms, Some(last.info_hash_hex.clone())),
+        None => (since, params.after),
+    };
+    let more = records.len() >= limit;
+    Json(ExportPage {
+        records,
+        next_since,
+        next_after,
+        more,
+    })
+    .into_response()
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Deserialize)]
+struct SavedSearchRequest {
+    name: String,
+    query: String,
+    webhook_url: String,
+    #[serde(default)]
+    webhook_format: crate::storage::WebhookFormat,
+}
+
+async fn saved_searches_api(State(state): State<AppState>) -> impl IntoResponse {
+    match crate::storage::list_saved_searches(&state.db) {
+        Ok(searches) => Json(searches).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+/// Creates or replaces a saved search (matched by name).
+async fn saved_search_put_api(State(state): State<AppState>, Json(req): Json<SavedSearchRequest>) -> impl IntoResponse {
+    let name = req.name.trim().to_string();
+    let query = req.query.trim().to_string();
+    let webhook_url = req.webhook_url.trim().to_string();
+    let invalid = if name.is_empty() || name.contains('\0') {
+        Some("name must be non-empty")
+    } else if query.is_empty() {
+        Some("query must be non-empty")
+    } else if !(webhook_url.starts_with("http://") || webhook_url.starts_with("https://")) {
+        Some("webhook_url must be an http(s) URL")
+    } else {
+        None
+    };
+    if let Some(error) = invalid {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": error }))).into_response();
+    }
+
+    let search = crate::storage::SavedSearch {
+        name,
+        query,
+        webhook_url,
+        webhook_format: req.webhook_format,
+        created_unix_ms: 0,
+    };
+    match crate::storage::put_saved_search(&state.db, search) {
+        Ok(search) => Json(search).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn saved_search_delete_api(State(state): State<AppState>, Path(name): Path<String>) -> impl IntoResponse {
+    match crate::storage::delete_saved_search(&state.db, &name) {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "no such saved search" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+/// Search results a query-targeted bulk job covers unless it asks for more.
+const BULK_DEFAULT_QUERY_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+struct BulkRequest {
+    op: crate::jobs::BulkOp,
+    /// Either these hashes (v1 or v2)...
+    #[serde(default)]
+    hashes: Vec<String>,
+    /// ...or the torrents this search finds, best first, up to `limit`.
+    query: Option<String>,
+    limit: Option<usize>,
+}
+
+impl BulkRequest {
+    fn target(self) -> Result<crate::jobs::Target, String> {
+        use crate::jobs::{MAX_TARGETS, Target};
+        let query = self.query.map(|q| q.trim().to_string()).filter(|q| !q.is_empty());
+        match (self.hashes.is_empty(), query) {
+            (false, Some(_)) => Err("send either hashes or query, not both".into()),
+            (true, None) => Err("send hashes or a query".into()),
+            (true, Some(q)) => {
+                let limit = self.limit.unwrap_or(BULK_DEFAULT_QUERY_LIMIT);
+                if limit == 0 || limit > MAX_TARGETS {
+                    return Err(format!("limit must be between 1 and {MAX_TARGETS}"));
+                }
+                Ok(Target::Query { q, limit })
+            }
+            (false, None) => {
+                if self.hashes.len() > MAX_TARGETS {
+                    return Err(format!("at most {MAX_TARGETS} hashes per job"));
+                }
+                let mut hashes = Vec::with_capacity(self.hashes.len());
+                for hash in self.hashes {
+                    let hash = hash.trim().to_ascii_lowercase();
+                    if !matches!(hash.len(), 40 | 64) || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(format!("invalid info hash: {hash}"));
+                    }
+                    hashes.push(hash);
+                }
+                Ok(Target::Hashes(hashes))
+            }
+        }
+    }
+}
+
+/// Queues a bulk operation; poll `/api/jobs/<id>` for progress.
+async fn bulk_api(State(state): State<AppState>, Json(req): Json<BulkRequest>) -> impl IntoResponse {
+    let op = req.op;
+    match req.target() {
+        Ok(target) => {
+            let status = state.jobs.submit(op, target);
+            tracing::info!(id = status.id, ?op, total = ?status.total, query = ?status.query, "jobs: queued");
+            (StatusCode::ACCEPTED, Json(status)).into_response()
+        }
+        Err(err) => (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": err }))).into_response(),
+    }
+}
+
+async fn jobs_api(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.jobs.list())
+}
+
+async fn job_api(State(state): State<AppState>, Path(id): Path<u64>) -> impl IntoResponse {
+    match state.jobs.get(id) {
+        Some(status) => Json(status).into_response(),
+        None => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "no such job" }))).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct BlockRuleRequest {
+    kind: crate::storage::BlockRuleKind,
+    pattern: String,
+}
+
+async fn block_rules_api(State(state): State<AppState>) -> impl IntoResponse {
+    match crate::storage::list_block_rules(&state.db) {
+        Ok(rules) => Json(rules).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn block_rule_add_api(State(state): State<AppState>, Json(req): Json<BlockRuleRequest>) -> impl IntoResponse {
+    let pattern = req.pattern.trim();
+    if let Err(err) = crate::blocklist::validate(req.kind, pattern) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": format!("{err:#}") })),
+        )
+            .into_response();
+    }
+    let result = crate::storage::add_block_rule(&state.db, req.kind, pattern)
+        .and_then(|rule| state.blocklist.reload(&state.db).map(|()| rule));
+    match result {
+        Ok(rule) => {
+            tracing::info!(id = rule.id, kind = ?rule.kind, pattern = %rule.pattern, "blocklist: rule added");
+            (StatusCode::CREATED, Json(rule)).into_response()
+        }
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn block_rule_delete_api(State(state): State<AppState>, Path(id): Path<u64>) -> impl IntoResponse {
+    let result = crate::storage::delete_block_rule(&state.db, id)
+        .and_then(|removed| state.blocklist.reload(&state.db).map(|()| removed));
+    match result {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, Json(serde_json::json!({ "error": "no such rule" }))).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}